@@ -0,0 +1,187 @@
+use serde_derive::Serialize;
+use std::time::Instant;
+use tokio::sync::mpsc;
+use tracing::{debug, error, info};
+
+use crate::app::ErrorPopup;
+use crate::heart_rate::HeartRateStatus;
+use crate::settings::{AlertSettings, AlertSink};
+
+mod rules;
+mod sinks;
+
+pub use rules::RuleWatcher;
+pub use sinks::toast;
+
+/// What kind of event raised an alert, used to look up which sinks it should be routed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    Error,
+    Warning,
+    Notification,
+    Connected,
+    Disconnected,
+    // Heart rate zone changed, never shown as a popup
+    Zone,
+    // From `[alerts.rules]`, also never shown as popups
+    HighHeartRate,
+    LowHeartRate,
+    LowBattery,
+}
+
+impl From<&ErrorPopup> for AlertKind {
+    fn from(popup: &ErrorPopup) -> Self {
+        match popup {
+            ErrorPopup::Fatal(_) | ErrorPopup::FatalDetailed(_, _) => AlertKind::Error,
+            ErrorPopup::Intermittent(_) => AlertKind::Warning,
+            ErrorPopup::UserMustDismiss(_) => AlertKind::Notification,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub kind: AlertKind,
+    pub message: String,
+}
+
+impl From<&ErrorPopup> for Alert {
+    fn from(popup: &ErrorPopup) -> Self {
+        let message = match popup {
+            ErrorPopup::FatalDetailed(msg, details) => format!("{msg} {details}"),
+            ErrorPopup::Fatal(msg)
+            | ErrorPopup::Intermittent(msg)
+            | ErrorPopup::UserMustDismiss(msg) => msg.to_owned(),
+        };
+        Self {
+            kind: popup.into(),
+            message,
+        }
+    }
+}
+
+impl AlertKind {
+    fn title(&self) -> &'static str {
+        match self {
+            AlertKind::Error => "Error",
+            AlertKind::Warning => "Warning",
+            AlertKind::Notification => "Notification",
+            AlertKind::Connected => "Connected",
+            AlertKind::Disconnected => "Disconnected",
+            AlertKind::Zone => "Heart Rate Zone",
+            AlertKind::HighHeartRate => "High Heart Rate",
+            AlertKind::LowHeartRate => "Low Heart Rate",
+            AlertKind::LowBattery => "Low Battery",
+        }
+    }
+}
+
+struct AlertBackend {
+    alert_rx: mpsc::Receiver<Alert>,
+    settings: AlertSettings,
+    client: reqwest::Client,
+}
+
+impl AlertBackend {
+    async fn handle_alert(&self, alert: Alert) {
+        for sink in self.settings.routes.sinks_for(alert.kind) {
+            let result = match sink {
+                // Handled by the App itself
+                AlertSink::Popup => Ok(()),
+                AlertSink::Toast => sinks::toast(&alert).await,
+                AlertSink::Xsoverlay => {
+                    sinks::xsoverlay(&alert, self.settings.xsoverlay_port).await
+                }
+                AlertSink::Webhook => {
+                    sinks::webhook(&alert, &self.client, &self.settings.webhook_url).await
+                }
+                // Rung by the TUI between draws, so it doesn't land in the middle of one
+                AlertSink::Sound => Ok(()),
+            };
+            // Just putting these in the .log, an alert failing to alert shouldn't stop the app
+            if let Err(e) = result {
+                error!("Failed to send {:?} alert to {:?}: {e}", alert.kind, sink);
+            }
+        }
+    }
+}
+
+async fn alert_backend_loop(mut actor: AlertBackend) {
+    while let Some(alert) = actor.alert_rx.recv().await {
+        debug!("Alert: {alert:?}");
+        actor.handle_alert(alert).await;
+    }
+    info!("Alert channel closed");
+}
+
+/// Routes alerts raised by the App to the sinks configured in `[alerts.routes]`.
+#[derive(Debug)]
+pub struct AlertHandle {
+    alert_tx: Option<mpsc::Sender<Alert>>,
+    settings: AlertSettings,
+    rules: RuleWatcher,
+    // Waiting for the TUI to ring the terminal bell
+    bell: bool,
+}
+
+impl AlertHandle {
+    pub fn new(settings: AlertSettings) -> Self {
+        let alert_tx = if settings.enabled {
+            let (alert_tx, alert_rx) = mpsc::channel(16);
+            let actor = AlertBackend {
+                alert_rx,
+                settings: settings.clone(),
+                client: reqwest::Client::new(),
+            };
+            tokio::spawn(alert_backend_loop(actor));
+            Some(alert_tx)
+        } else {
+            None
+        };
+        Self {
+            alert_tx,
+            rules: RuleWatcher::new(settings.rules.clone()),
+            settings,
+            bell: false,
+        }
+    }
+    /// Hands the alert off to the backend for any non-popup sinks
+    pub fn raise(&mut self, alert: Alert) {
+        if let Some(alert_tx) = self.alert_tx.as_ref() {
+            if self
+                .settings
+                .routes
+                .sinks_for(alert.kind)
+                .contains(&AlertSink::Sound)
+            {
+                self.bell = true;
+            }
+            if let Err(e) = alert_tx.try_send(alert) {
+                error!("Couldn't queue alert: {e}");
+            }
+        }
+    }
+    /// Raises any `[alerts.rules]` this reading sets off
+    pub fn check_rules(&mut self, hr_status: &HeartRateStatus) {
+        if self.alert_tx.is_none() {
+            return;
+        }
+        for alert in self.rules.update(hr_status, Instant::now()) {
+            self.raise(alert);
+        }
+    }
+    /// If any alerts since the last call wanted the terminal bell rung.
+    pub fn take_bell(&mut self) -> bool {
+        std::mem::take(&mut self.bell)
+    }
+    /// If alerting is disabled, everything gets shown in the TUI like usual.
+    pub fn shows_popup(&self, kind: AlertKind) -> bool {
+        !self.settings.enabled
+            || self
+                .settings
+                .routes
+                .sinks_for(kind)
+                .contains(&AlertSink::Popup)
+    }
+}
@@ -0,0 +1,120 @@
+use std::time::{Duration, Instant};
+
+use super::{Alert, AlertKind};
+use crate::heart_rate::HeartRateStatus;
+use crate::settings::AlertRules;
+
+/// A heart rate limit that alerts once it's been past it for long enough.
+#[derive(Debug, Default)]
+struct Sustained {
+    since: Option<Instant>,
+    raised: bool,
+}
+
+impl Sustained {
+    /// True the moment it's been past the limit for `hold`, then not again until it's back within it.
+    fn update(&mut self, past: bool, hold: Duration, now: Instant) -> bool {
+        if !past {
+            *self = Self::default();
+            return false;
+        }
+        let since = *self.since.get_or_insert(now);
+        if self.raised || now.duration_since(since) < hold {
+            return false;
+        }
+        self.raised = true;
+        true
+    }
+}
+
+/// Checks each reading against `[alerts.rules]`, raising alerts for them.
+#[derive(Debug)]
+pub struct RuleWatcher {
+    rules: AlertRules,
+    hold: Duration,
+    high: Sustained,
+    low: Sustained,
+}
+
+impl RuleWatcher {
+    pub fn new(rules: AlertRules) -> Self {
+        Self {
+            hold: Duration::from_secs_f32(rules.sustain_sec.max(0.0)),
+            rules,
+            high: Sustained::default(),
+            low: Sustained::default(),
+        }
+    }
+    pub fn update(&mut self, hr_status: &HeartRateStatus, now: Instant) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        let bpm = hr_status.heart_rate_bpm;
+        // No reading, so neither limit's been crossed (the disconnect has its own alert)
+        let reading = bpm > 0;
+        let rules = &self.rules;
+        let high = reading && rules.high_bpm > 0 && bpm >= rules.high_bpm;
+        if self.high.update(high, self.hold, now) {
+            alerts.push(Alert {
+                kind: AlertKind::HighHeartRate,
+                message: format!(
+                    "Heart rate has been at or above {} BPM for {}s, now {bpm} BPM",
+                    rules.high_bpm, rules.sustain_sec
+                ),
+            });
+        }
+        let low = reading && rules.low_bpm > 0 && bpm <= rules.low_bpm;
+        if self.low.update(low, self.hold, now) {
+            alerts.push(Alert {
+                kind: AlertKind::LowHeartRate,
+                message: format!(
+                    "Heart rate has been at or below {} BPM for {}s, now {bpm} BPM",
+                    rules.low_bpm, rules.sustain_sec
+                ),
+            });
+        }
+        alerts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heart_rate::BatteryLevel;
+
+    fn status(bpm: u16, battery: BatteryLevel) -> HeartRateStatus {
+        HeartRateStatus {
+            heart_rate_bpm: bpm,
+            battery_level: battery,
+            ..Default::default()
+        }
+    }
+
+    fn kinds(alerts: Vec<Alert>) -> Vec<AlertKind> {
+        alerts.into_iter().map(|alert| alert.kind).collect()
+    }
+
+    #[test]
+    fn high_heart_rate_must_be_sustained() {
+        let mut watcher = RuleWatcher::new(AlertRules {
+            high_bpm: 150,
+            low_bpm: 50,
+            sustain_sec: 10.0,
+        });
+        let start = Instant::now();
+        let at = |sec| start + Duration::from_secs(sec);
+        let unknown = BatteryLevel::Unknown;
+        assert!(watcher.update(&status(155, unknown), at(0)).is_empty());
+        // Dipping under resets the timer
+        assert!(watcher.update(&status(140, unknown), at(5)).is_empty());
+        assert!(watcher.update(&status(160, unknown), at(6)).is_empty());
+        assert!(watcher.update(&status(160, unknown), at(15)).is_empty());
+        assert_eq!(
+            kinds(watcher.update(&status(160, unknown), at(16))),
+            [AlertKind::HighHeartRate]
+        );
+        // Only once
+        assert!(watcher.update(&status(170, unknown), at(30)).is_empty());
+        // Disconnecting doesn't count as low
+        assert!(watcher.update(&status(0, unknown), at(31)).is_empty());
+        assert!(watcher.update(&status(0, unknown), at(60)).is_empty());
+    }
+}
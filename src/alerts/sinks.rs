@@ -0,0 +1,71 @@
+use notify_rust::Notification;
+use serde_derive::Serialize;
+use tokio::net::UdpSocket;
+
+use super::Alert;
+use crate::errors::AppError;
+
+const APP_NAME: &str = "Iron Heart";
+
+pub async fn toast(alert: &Alert) -> Result<(), AppError> {
+    let summary = format!("{APP_NAME} - {}", alert.kind.title());
+    let body = alert.message.clone();
+    // Some platforms block while the toast is being shown
+    tokio::task::spawn_blocking(move || {
+        Notification::new()
+            .appname(APP_NAME)
+            .summary(&summary)
+            .body(&body)
+            .show()
+            .map(|_| ())
+    })
+    .await??;
+    Ok(())
+}
+
+/// <https://xsoverlay.vercel.app/Developer/API/sockets/notifications>
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct XSOverlayMessage<'a> {
+    message_type: u8,
+    title: &'a str,
+    content: &'a str,
+    height: f32,
+    source_app: &'a str,
+    timeout: f32,
+    volume: f32,
+    audio_path: &'a str,
+    opacity: f32,
+}
+
+pub async fn xsoverlay(alert: &Alert, port: u16) -> Result<(), AppError> {
+    let message = XSOverlayMessage {
+        message_type: 1,
+        title: alert.kind.title(),
+        content: &alert.message,
+        height: 110.0,
+        source_app: APP_NAME,
+        timeout: 5.0,
+        volume: 0.5,
+        audio_path: "default",
+        opacity: 1.0,
+    };
+    let payload = serde_json::to_vec(&message)?;
+    let socket = UdpSocket::bind("127.0.0.1:0").await?;
+    socket.send_to(&payload, ("127.0.0.1", port)).await?;
+    Ok(())
+}
+
+pub async fn webhook(alert: &Alert, client: &reqwest::Client, url: &str) -> Result<(), AppError> {
+    if url.is_empty() {
+        return Err(AppError::MissingWebhookUrl);
+    }
+    client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(serde_json::to_string(alert)?)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
@@ -1,13 +1,15 @@
 use chrono::{DateTime, Local};
 use ratatui::widgets::TableState;
+use regex::Regex;
 use std::collections::VecDeque;
+use std::ops::Bound;
 use std::path::PathBuf;
 use std::{
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tokio::sync::{
     broadcast::{self, Receiver as BReceiver, Sender as BSender},
@@ -16,37 +18,78 @@ use tokio::sync::{
 use tokio::task::JoinHandle;
 use tokio::time::timeout;
 use tokio_util::sync::CancellationToken;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 use crate::activities::Activities;
-use crate::args::{SubCommands, TopLevelCmd};
+use crate::alerts::{Alert, AlertHandle, AlertKind};
+use crate::args::{DummyCmd, SubCommands, TopLevelCmd};
+use crate::audio::audio_thread;
 use crate::broadcast;
 use crate::errors::AppError;
+use crate::fan::fan_thread;
+use crate::format::NumberFormat;
+use crate::gatt_server::gatt_server_thread;
+use crate::haptics::haptics_thread;
 use crate::heart_rate::ble::HEART_RATE_SERVICE_UUID;
-use crate::heart_rate::dummy::dummy_thread;
-use crate::heart_rate::websocket::websocket_thread;
-use crate::logging::prometheus_logging_thread;
+use crate::heart_rate::device_information::DeviceInformation;
+use crate::heart_rate::dummy::DummySource;
+use crate::heart_rate::ftms::FITNESS_MACHINE_SERVICE_UUID;
+use crate::heart_rate::low_battery::LowBattery;
+use crate::heart_rate::plx::PULSE_OXIMETER_SERVICE_UUID;
+use crate::heart_rate::twitcher::{TwitchSample, TwitchThreshold};
+use crate::heart_rate::websocket::WebSocketSource;
+use crate::hooks::{HookEvent, Hooks};
+use crate::journal::{self, Severity, Subsystem};
+use crate::logging::sessions::SessionStore;
+use crate::logging::syslog::{self, syslog_thread};
+use crate::logging::{
+    hrtovrchat_thread, influx_logging_thread, ipc_thread, mqtt_output_thread, obs_thread,
+    overlay_thread, prometheus_exporter_thread, prometheus_logging_thread, raw_capture,
+    streamerbot_thread, udp_output_thread, webhook_thread,
+};
+use crate::pairing::{PairingPrompt, PairingRequest};
+use crate::preflight::{self, PreflightIssue};
+use crate::sleep_inhibit::SleepInhibit;
+use crate::sources::{HeartRateSource, HrSource, SourceContext, SourceManager};
+use crate::twitch::twitch_thread;
 use crate::ui::table_state_scroll;
+use crate::ui_state::{Screen, UiState};
 use crate::updates::{UpdateHandle, UpdateReply};
 use crate::vrcx::VrcxStartup;
-use crate::widgets::prompts::SavePromptChoice;
+use crate::widgets::heart_rate::refresh::ChartRefresh;
+use crate::widgets::prompts::{PreflightPromptChoice, SavePromptChoice};
+use crate::widgets::session_compare::SessionCompare;
+use crate::widgets::twitch_tuning::TwitchTuning;
+use crate::wled::wled_thread;
+use crate::zones::ZoneEngine;
 use crate::{
-    heart_rate::ble::start_notification_thread,
+    heart_rate::advertisement::start_advertisement_thread,
+    heart_rate::aggregate::{aggregator_thread, AggregateInput, PRIMARY_SOURCE, SECONDARY_SOURCE},
+    heart_rate::ble::{start_notification_thread, BleReconnect, ConnectStage},
+    heart_rate::failover::{failover_thread, FailoverInput},
     heart_rate::HeartRateStatus,
     logging::file_logging_thread,
-    osc::osc_thread,
+    osc::{new_tap_log, osc_thread, OscTapLog},
     scan::{bluetooth_event_thread, get_characteristics},
-    settings::Settings,
+    settings::{BeatStrength, Settings},
     structs::{Characteristic, DeviceInfo},
     widgets::heart_rate_display::{
         CHART_BPM_MAX_ELEMENTS, CHART_BPM_VERT_MARGIN, CHART_RR_MAX_ELEMENTS, CHART_RR_VERT_MARGIN,
     },
 };
 
+const SENSITIVE_REVEAL_DURATION: Duration = Duration::from_secs(10);
+// About 5 minutes at one update a second
+const TWITCH_REPLAY_SAMPLES: usize = 300;
+
 pub enum AppRx {
     DeviceUpdate(DeviceUpdate),
     AppUpdate(AppUpdate),
     UpdateReply(UpdateReply),
+    // A device wants a PIN/passkey to finish pairing
+    PairingRequest(PairingRequest),
+    // Stopped waiting for a higher priority saved device
+    SavedDeviceWaitOver,
 }
 
 #[derive(Debug)]
@@ -63,6 +106,32 @@ pub enum AppUpdate {
     HeartRateStatus(HeartRateStatus),
     ActivitySelected(u8),
     WebsocketReady(std::net::SocketAddr),
+    // How many websocket clients are connected right now
+    WebsocketClients(usize),
+    // mDNS instance name the websocket server is advertised under
+    WebsocketAdvertised(String),
+    // OSC target is unreachable, beat params are being held back
+    OscDegraded(bool),
+    // User temporarily stopped OSC output, HR source stays connected
+    OscPaused(bool),
+    // Float HR param is being swept for calibration, live data is held back
+    OscCalibrating(bool),
+    // Switched OSC address preset, None being the ones in [osc.addresses]
+    OscPreset(Option<String>),
+    // Session CSV being written to, None if not logging
+    SessionLogging(Option<String>),
+    // Failover manager switched sources, None if none of them have a reading
+    FailoverActive(Option<HrSource>),
+    // Heart rate zone changed (after hysteresis), see `zones`
+    ZoneChanged(u8),
+    // Results from re-running the pre-flight checks
+    Preflight(Vec<PreflightIssue>),
+    // BLE connection was lost and is being retried
+    BleReconnect(BleReconnect),
+    // Progress connecting to the main device
+    ConnectStage(ConnectStage),
+    // Read from the connected device's Device Information Service
+    DeviceInformation(DeviceInformation),
     Error(ErrorPopup),
 }
 
@@ -88,6 +157,10 @@ impl From<std::net::SocketAddr> for AppUpdate {
 pub enum AppView {
     BleDeviceSelection,
     WaitingForWebsocket,
+    WaitingForUdp,
+    WaitingForAnt,
+    WaitingForSerial,
+    WaitingForRemote,
     HeartRateView,
 }
 
@@ -108,6 +181,12 @@ pub enum SubState {
     UpdateDownloading,
     #[cfg(windows)]
     LaunchUpdatePrompt,
+    PreflightPrompt,
+    TwitchTuning,
+    SessionCompare,
+    DeviceInformation,
+    SourceSelection,
+    PairingPrompt,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -144,10 +223,15 @@ pub struct App {
     pub table_state: TableState,
     pub prompt_state: TableState,
     pub should_save_ble_device: bool,
+    // Best lower priority saved device found so far, and when to give up waiting for a better one
+    pending_saved_device: Option<(usize, DeviceInfo)>,
+    saved_device_deadline: Option<tokio::time::Instant>,
     pub allow_modifying_config: bool,
     // devices with the heart rate service
     // UI references this using table_state as the index
     pub discovered_devices: Vec<DeviceInfo>,
+    // From `ble.scan_name_filter`
+    scan_name_filter: Option<Regex>,
     pub quick_connect_ui: bool,
     pub characteristic_scroll: usize,
     pub selected_characteristics: Vec<Characteristic>,
@@ -158,17 +242,60 @@ pub struct App {
     pub cancel_actors: CancellationToken,
     pub ble_thread_handle: Option<JoinHandle<()>>,
     pub hr_thread_handle: Option<JoinHandle<()>>,
+    // Only used with `ble.secondary_device`
+    pub secondary_hr_thread_handle: Option<JoinHandle<()>>,
+    pub aggregator_handle: Option<JoinHandle<()>>,
+    // Main source's way into the failover manager, if it's on
+    failover_input: Option<FailoverInput>,
+    pub failover_handle: Option<JoinHandle<()>>,
+    pub fallback_handles: Vec<JoinHandle<()>>,
+    // Which source is driving outputs, while failing over
+    pub failover_active: Option<HrSource>,
+    aggregate_tx: Option<Sender<HeartRateStatus>>,
+    secondary_device_id: Option<String>,
     pub osc_thread_handle: Option<JoinHandle<()>>,
     pub file_logging_handle: Option<JoinHandle<()>>,
     pub prometheus_handle: Option<JoinHandle<()>>,
-    pub dummy_thread_handle: Option<JoinHandle<()>>,
-    pub websocket_thread_handle: Option<JoinHandle<()>>,
+    pub influx_handle: Option<JoinHandle<()>>,
+    pub webhook_handle: Option<JoinHandle<()>>,
+    pub udp_output_handle: Option<JoinHandle<()>>,
+    pub prometheus_exporter_handle: Option<JoinHandle<()>>,
+    pub mqtt_output_handle: Option<JoinHandle<()>>,
+    pub obs_handle: Option<JoinHandle<()>>,
+    pub streamerbot_handle: Option<JoinHandle<()>>,
+    pub hrtovrchat_handle: Option<JoinHandle<()>>,
+    pub overlay_handle: Option<JoinHandle<()>>,
+    pub ipc_handle: Option<JoinHandle<()>>,
+    pub fan_handle: Option<JoinHandle<()>>,
+    pub twitch_handle: Option<JoinHandle<()>>,
+    pub haptics_handle: Option<JoinHandle<()>>,
+    pub wled_handle: Option<JoinHandle<()>>,
+    pub audio_handle: Option<JoinHandle<()>>,
+    pub gatt_server_handle: Option<JoinHandle<()>>,
+    pub syslog_handle: Option<JoinHandle<()>>,
+    // Any source but Bluetooth, see `HeartRateSource`
+    pub source_thread_handle: Option<JoinHandle<()>>,
+    // Launched with `--stdin`, overrides the config's source
+    read_stdin: bool,
     // Raw histories
     pub heart_rate_history: VecDeque<f64>,
     pub rr_history: VecDeque<f64>,
     // Used for the graphs in the heart rate view
     pub bpm_dataset: Vec<(f64, f64)>,
     pub rr_dataset: Vec<(f64, f64)>,
+    pub chart_refresh: ChartRefresh,
+    pub number_format: NumberFormat,
+    sleep_inhibit: SleepInhibit,
+    // Shared with the running sources, so a tuned threshold applies right away
+    rr_twitch_threshold: TwitchThreshold,
+    // Recent updates for the twitch tuning screen to replay
+    pub twitch_samples: VecDeque<TwitchSample>,
+    pub twitch_tuning: Option<TwitchTuning>,
+    pub session_compare: Option<SessionCompare>,
+    // BLE/WebSocket/Dummy, swappable while running
+    pub sources: SourceManager,
+    // Only from BLE devices that have the Device Information Service
+    pub device_information: Option<DeviceInformation>,
     pub session_high_bpm: (f64, DateTime<Local>),
     pub session_low_bpm: (f64, DateTime<Local>),
     // Usually same as session but can have a margin applied
@@ -180,10 +307,47 @@ pub struct App {
     pub chart_low_rr: f64,
     ignore_margins_for_vhs: bool,
     pub websocket_url: Option<String>,
+    pub websocket_clients: Option<usize>,
+    // Name of the session CSV being written, see `log_sessions_to_csv`
+    pub session_log: Option<String>,
+    pub websocket_mdns_name: Option<String>,
+    pub osc_degraded: bool,
+    pub osc_paused: bool,
+    pub osc_calibrating: bool,
+    // Name of the `osc.presets` entry in use
+    pub osc_preset: Option<String>,
+    // Set while the HR monitor connection is being retried
+    pub ble_reconnect: Option<BleReconnect>,
+    // Shown in the connecting popup
+    pub connect_stage: Option<ConnectStage>,
+    // Lets a connection attempt be cancelled without stopping every other actor
+    hr_cancel: Option<CancellationToken>,
+    // Not auto-connected to again after the user cancels connecting to it
+    cancelled_device_id: Option<String>,
+    // When in stream-safe mode, sensitive info is shown until this passes
+    reveal_sensitive_until: Option<Instant>,
+    // Only present if `osc.debug_tap` or `osc.dry_run` is on
+    pub osc_tap_log: Option<OscTapLog>,
+    // Chart toggles and such that get remembered across restarts
+    pub ui_state: UiState,
+    // Last session's open panel, reopened once there's a heart rate to show
+    restore_screen: Option<Screen>,
     pub config_path: PathBuf,
     vrcx: VrcxStartup,
     pub activities: Activities,
     pub updates: UpdateHandle,
+    pub pairing: PairingPrompt,
+    alerts: AlertHandle,
+    // Feeds both the low_battery alert and the on_low_battery hook
+    low_battery: LowBattery,
+    hooks: Hooks,
+    // Only present if `zones.enabled`
+    zones: Option<ZoneEngine>,
+    // Set when running with the TUI, so startup prompts can actually be answered
+    pub interactive: bool,
+    // Shown in the pre-flight prompt, threads aren't started until it's dismissed
+    pub preflight_issues: Vec<PreflightIssue>,
+    pub preflight_running: bool,
     pub update_download_percentage: f64,
     pub update_newer_version: Option<String>,
 }
@@ -228,6 +392,35 @@ impl App {
                 Settings::default()
             }
         };
+        let alerts = AlertHandle::new(settings.alerts.clone());
+        let zones = settings
+            .zones
+            .enabled
+            .then(|| ZoneEngine::new(&settings.zones));
+        let scan_name_filter = match settings.ble.scan_name_filter.as_str() {
+            "" => None,
+            pattern => match Regex::new(pattern) {
+                Ok(regex) => Some(regex),
+                Err(e) => {
+                    error!("Invalid scan name filter: {e}");
+                    error_message.get_or_insert(ErrorPopup::detailed(
+                        "Invalid ble.scan_name_filter!",
+                        e.into(),
+                    ));
+                    None
+                }
+            },
+        };
+        let ui_state = UiState::load(&settings.tui).unwrap_or_else(|e| {
+            warn!("Failed to load UI state, using config defaults: {e}");
+            UiState::from_settings(&settings.tui)
+        });
+        let chart_refresh = ChartRefresh::new(&settings.tui);
+        let number_format = NumberFormat::new(&settings.format);
+        let sleep_inhibit = SleepInhibit::new(settings.misc.inhibit_sleep);
+        let low_battery = LowBattery::new(settings.misc.low_battery_percent);
+        let hooks = Hooks::new(settings.ble.hooks.clone());
+        let rr_twitch_threshold = TwitchThreshold::from_ms(settings.osc.twitch_rr_threshold_ms);
         Self {
             ble_tx,
             ble_rx,
@@ -240,8 +433,11 @@ impl App {
             table_state,
             prompt_state,
             should_save_ble_device: false,
+            pending_saved_device: None,
+            saved_device_deadline: None,
             allow_modifying_config,
             discovered_devices: Vec::new(),
+            scan_name_filter,
             quick_connect_ui: false,
             characteristic_scroll: 0,
             selected_characteristics: Vec::new(),
@@ -253,15 +449,49 @@ impl App {
             rr_history: VecDeque::with_capacity(CHART_RR_MAX_ELEMENTS),
             bpm_dataset: Vec::with_capacity(CHART_BPM_MAX_ELEMENTS),
             rr_dataset: Vec::with_capacity(CHART_RR_MAX_ELEMENTS),
+            chart_refresh,
+            number_format,
+            sleep_inhibit,
+            rr_twitch_threshold,
+            twitch_samples: VecDeque::with_capacity(TWITCH_REPLAY_SAMPLES),
+            twitch_tuning: None,
+            session_compare: None,
+            sources: SourceManager::new(cancel_actors.clone()),
+            device_information: None,
             cancel_app,
             cancel_actors,
             ble_thread_handle: None,
             hr_thread_handle: None,
+            secondary_hr_thread_handle: None,
+            aggregator_handle: None,
+            failover_input: None,
+            failover_handle: None,
+            fallback_handles: Vec::new(),
+            failover_active: None,
+            aggregate_tx: None,
+            secondary_device_id: None,
             osc_thread_handle: None,
             file_logging_handle: None,
             prometheus_handle: None,
-            dummy_thread_handle: None,
-            websocket_thread_handle: None,
+            influx_handle: None,
+            webhook_handle: None,
+            udp_output_handle: None,
+            prometheus_exporter_handle: None,
+            mqtt_output_handle: None,
+            obs_handle: None,
+            streamerbot_handle: None,
+            hrtovrchat_handle: None,
+            overlay_handle: None,
+            ipc_handle: None,
+            fan_handle: None,
+            twitch_handle: None,
+            haptics_handle: None,
+            wled_handle: None,
+            audio_handle: None,
+            gatt_server_handle: None,
+            syslog_handle: None,
+            source_thread_handle: None,
+            read_stdin: false,
             session_high_bpm: (0.0, Local::now()),
             session_low_bpm: (0.0, Local::now()),
             chart_high_bpm: 0.0,
@@ -272,10 +502,33 @@ impl App {
             chart_mid_rr: 0.0,
             ignore_margins_for_vhs: false,
             websocket_url: None,
+            websocket_clients: None,
+            session_log: None,
+            websocket_mdns_name: None,
+            osc_degraded: false,
+            osc_paused: false,
+            osc_calibrating: false,
+            osc_preset: None,
+            ble_reconnect: None,
+            connect_stage: None,
+            hr_cancel: None,
+            cancelled_device_id: None,
+            reveal_sensitive_until: None,
+            osc_tap_log: None,
+            restore_screen: Some(ui_state.screen),
+            ui_state,
             config_path,
             vrcx: VrcxStartup::new(),
             activities: Activities::new(),
             updates: UpdateHandle::new(),
+            pairing: PairingPrompt::new(),
+            alerts,
+            low_battery,
+            hooks,
+            zones,
+            interactive: false,
+            preflight_issues: Vec::new(),
+            preflight_running: false,
             update_download_percentage: 0.0,
             update_newer_version: None,
         }
@@ -291,37 +544,153 @@ impl App {
         if !self.try_save_settings() {
             return;
         }
+        if self.settings.syslog.enabled {
+            self.start_syslog_thread();
+        }
+        if self.settings.misc.journal_enabled {
+            let journal_path = PathBuf::from(&self.settings.misc.journal_path);
+            if let Err(e) = journal::init(&journal_path) {
+                self.handle_error_update(ErrorPopup::detailed("Failed to open journal!", e));
+                return;
+            }
+            journal::record(
+                Severity::Info,
+                Subsystem::App,
+                &format!("Started v{}", env!("CARGO_PKG_VERSION")),
+            );
+        }
         let Some(activity) = self.try_load_activities().await else {
             return;
         };
         // self.handle_error_update(ErrorPopup::Fatal(format!("{:?}", self.activities)));
         // return;
+        // Subcommands and skip_prompts are usually from scripts/shortcuts, where nobody's around to answer
+        if self.interactive
+            && self.settings.misc.preflight_checks
+            && arg_config.subcommands.is_none()
+            && !arg_config.skip_prompts
+            && !arg_config.dummy
+            && !arg_config.stdin
+        {
+            let issues = preflight::run(&self.settings).await;
+            if !issues.is_empty() {
+                self.preflight_issues = issues;
+                self.prompt_state.select(Some(0));
+                self.sub_state = SubState::PreflightPrompt;
+                return;
+            }
+        }
+        // Same as the dummy subcommand, without its options
+        let dummy = SubCommands::Dummy(DummyCmd {
+            speed: None,
+            vhs: false,
+        });
+        let subcommands = match &arg_config.subcommands {
+            None if arg_config.dummy => Some(&dummy),
+            subcommands => subcommands.as_ref(),
+        };
+        self.read_stdin = arg_config.stdin;
+        self.start_threads(activity, subcommands);
+    }
+
+    fn start_threads(&mut self, activity: Option<u8>, subcommands: Option<&SubCommands>) {
         if self.settings.osc.enabled {
             self.start_osc_thread(activity);
         }
         self.start_logging_threads(activity.unwrap_or(0));
+        if self.settings.fan.enabled {
+            self.start_fan_thread();
+        }
+        if self.settings.twitch.enabled {
+            self.start_twitch_thread();
+        }
+        if self.settings.haptics.enabled {
+            self.start_haptics_thread();
+        }
+        if self.settings.wled.enabled {
+            self.start_wled_thread();
+        }
+        if self.settings.audio.enabled {
+            self.start_audio_thread();
+        }
+        if self.settings.gatt_server.enabled {
+            self.start_gatt_server_thread();
+        }
         // HR source selection
-        if let Some(subcommands) = arg_config.subcommands.as_ref() {
+        if let Some(subcommands) = subcommands {
             match subcommands {
                 SubCommands::Ble(_) => self.start_bluetooth_event_thread(),
                 SubCommands::Dummy(dummy) => {
                     self.ignore_margins_for_vhs = dummy.vhs;
                     self.start_dummy_thread(dummy.speed, dummy.vhs);
                 }
-                SubCommands::WebSocket(ws) => self.start_websocket_thread(ws.port),
+                SubCommands::WebSocket(ws) => self.start_source(Box::new(WebSocketSource {
+                    port_override: ws.port,
+                })),
+                // Don't start the app at all, handled before getting here
+                SubCommands::Listen(_) | SubCommands::Export(_) => {}
             }
             return;
         }
 
-        if self.settings.dummy.enabled {
-            self.start_dummy_thread(None, false);
-        } else if self.settings.websocket.enabled {
-            self.start_websocket_thread(None);
+        let source = if self.read_stdin {
+            HrSource::Stdin
         } else {
-            self.start_bluetooth_event_thread();
+            self.settings.hr_source()
+        };
+        self.start_hr_source(source);
+    }
+
+    fn start_hr_source(&mut self, source: HrSource) {
+        match source.driver() {
+            None => self.start_bluetooth_event_thread(),
+            Some(_) if source == HrSource::Dummy => self.start_dummy_thread(None, false),
+            Some(driver) => self.start_source(driver),
+        }
+    }
+
+    fn preflight_enter_pressed(&mut self) {
+        let chosen_option = self.prompt_state.selected().unwrap_or(0);
+        match PreflightPromptChoice::from(chosen_option as u8) {
+            PreflightPromptChoice::Retry => {
+                if self.preflight_running {
+                    return;
+                }
+                self.preflight_running = true;
+                let settings = self.settings.clone();
+                let broadcast_tx = self.broadcast_tx.clone();
+                tokio::spawn(async move {
+                    let issues = preflight::run(&settings).await;
+                    broadcast!(broadcast_tx, AppUpdate::Preflight(issues));
+                });
+            }
+            PreflightPromptChoice::Continue => self.skip_preflight(),
+            PreflightPromptChoice::Quit => self.cancel_app.cancel(),
         }
     }
 
+    /// If an alert routed to the "sound" sink came in since the last draw.
+    pub fn take_bell(&mut self) -> bool {
+        self.alerts.take_bell()
+    }
+
+    /// Starts up as usual if we were holding off for the pre-flight prompt.
+    pub fn skip_preflight(&mut self) {
+        if self.sub_state != SubState::PreflightPrompt {
+            return;
+        }
+        for issue in self.preflight_issues.drain(..) {
+            warn!("Continuing despite pre-flight issue: {}", issue.message());
+        }
+        self.sub_state = SubState::None;
+        let activity = self
+            .settings
+            .activities
+            .enabled
+            .then_some(self.activities.current_activity);
+        self.start_threads(activity, None);
+    }
+
     /// Returns None if activities didn't load properly (error handling is handled in here)
     ///
     /// Returns Some(None) if activities were disabled.
@@ -349,6 +718,7 @@ impl App {
     // Had to break this apart into two functions, since the parent
     // tokio::select could cancel any concurrent handling if a terminal event came in
     pub async fn app_receivers(&mut self) -> AppRx {
+        let saved_device_deadline = self.saved_device_deadline;
         tokio::select! {
             // Check for updates from BLE Thread
             Some(new_device_info) = self.ble_rx.recv() => {
@@ -365,26 +735,44 @@ impl App {
                 // debug!("update: {data:?}");
                 AppRx::UpdateReply(data)
             }
+            // PIN prompts from a device being paired
+            Some(request) = self.pairing.request_rx.recv() => {
+                AppRx::PairingRequest(request)
+            }
+            _ = tokio::time::sleep_until(saved_device_deadline.unwrap_or_else(tokio::time::Instant::now)), if saved_device_deadline.is_some() => {
+                AppRx::SavedDeviceWaitOver
+            }
         }
     }
 
     pub async fn app_handlers(&mut self, data: AppRx) {
         match data {
             AppRx::DeviceUpdate(new_device_info) => self.device_info_callback(new_device_info),
+            AppRx::SavedDeviceWaitOver => self.saved_device_wait_over(),
+            AppRx::PairingRequest(request) => self.pairing_prompt_open(request),
             AppRx::AppUpdate(hr_data) => {
                 match hr_data {
                     AppUpdate::HeartRateStatus(data) => {
+                        self.sleep_inhibit
+                            .hr_update(data.heart_rate_bpm, Instant::now());
+                        self.alerts.check_rules(&data);
+                        self.check_low_battery(&data);
                         if data.heart_rate_bpm > 0 || !data.rr_intervals.is_empty() {
                             // Assume we have proper data now
                             self.view = AppView::HeartRateView;
                             if self.sub_state == SubState::ConnectingForHeartRate {
                                 self.sub_state = SubState::None;
                             }
+                            if let Some(screen) = self.restore_screen.take() {
+                                self.open_screen(screen);
+                            }
                             // Dismiss intermittent errors if we just got a notification packet
                             if let Some(ErrorPopup::Intermittent(_)) = self.error_message {
                                 self.error_message = None;
                             }
                             self.append_to_history(&data);
+                            self.update_zone(data.heart_rate_bpm);
+                            self.record_twitch_sample(&data);
                         }
                         self.heart_rate_status = data;
                     }
@@ -392,6 +780,35 @@ impl App {
                     AppUpdate::WebsocketReady(local_addr) => {
                         self.websocket_url = Some(local_addr.to_string());
                     }
+                    AppUpdate::WebsocketClients(clients) => {
+                        self.websocket_clients = Some(clients);
+                    }
+                    AppUpdate::WebsocketAdvertised(name) => {
+                        self.websocket_mdns_name = Some(name);
+                    }
+                    AppUpdate::OscDegraded(degraded) => {
+                        self.osc_degraded = degraded;
+                    }
+                    AppUpdate::OscPaused(_) | AppUpdate::OscPreset(_) => {}
+                    AppUpdate::ZoneChanged(_) => {}
+                    AppUpdate::Preflight(issues) => {
+                        self.preflight_running = false;
+                        self.preflight_issues = issues;
+                        if self.preflight_issues.is_empty() {
+                            self.skip_preflight();
+                        }
+                    }
+                    AppUpdate::FailoverActive(active) => self.failover_active = active,
+                    AppUpdate::SessionLogging(file_name) => self.session_log = file_name,
+                    AppUpdate::OscCalibrating(calibrating) => {
+                        self.osc_calibrating = calibrating;
+                    }
+                    AppUpdate::BleReconnect(status) => self.ble_reconnect_callback(status),
+                    AppUpdate::ConnectStage(stage) => self.connect_stage = Some(stage),
+                    AppUpdate::DeviceInformation(device_information) => {
+                        self.device_information =
+                            (!device_information.is_empty()).then_some(device_information);
+                    }
                     AppUpdate::ActivitySelected(_) => {
                         if let Err(err) = self.activities.save().await {
                             self.handle_error_update(ErrorPopup::detailed(
@@ -508,9 +925,13 @@ impl App {
     pub fn start_bluetooth_event_thread(&mut self) {
         let pause_signal_clone = Arc::clone(&self.ble_scan_paused);
         let app_tx_clone = self.ble_tx.clone();
-        let shutdown_requested_clone = self.cancel_actors.clone();
+        let shutdown_requested_clone = self.sources.start(HrSource::Ble);
         let (restart_tx, restart_rx) = mpsc::channel(1);
         self.ble_restart_tx = Some(restart_tx);
+        // Fallbacks get going while the device is still being found
+        if self.settings.failover.enabled {
+            self.start_failover();
+        }
         debug!("Spawning Bluetooth CentralEvent thread");
         self.ble_thread_handle = Some(tokio::spawn(async move {
             bluetooth_event_thread(
@@ -573,38 +994,327 @@ impl App {
         debug!("(HR) Pausing BLE scan");
         self.ble_scan_paused.store(true, Ordering::SeqCst);
         self.sub_state = SubState::ConnectingForHeartRate;
+        self.connect_stage = Some(ConnectStage::Connecting);
 
         let device = selected_device.clone();
-        let hr_tx_clone = self.broadcast_tx.clone();
+        // Picked on purpose this time
+        self.cancelled_device_id = None;
+        let hr_tx_clone = self.source_broadcast_tx();
         let restart_tx_clone = self.ble_restart_tx.clone().expect("BLE Restart TX missing");
-        let shutdown_requested_clone = self.cancel_actors.clone();
-        // Not leaving as Duration as it's being used to check an abs difference
-        let rr_twitch_threshold =
-            Duration::from_millis(self.settings.osc.twitch_rr_threshold_ms as u64).as_secs_f32();
-        let rr_ignore_after_empty = self.settings.ble.rr_ignore_after_empty as usize;
+        let hr_cancel = self.sources.token().child_token();
+        self.hr_cancel = Some(hr_cancel.clone());
+        let shutdown_requested_clone = hr_cancel;
+        let rr_twitch_threshold = self.rr_twitch_threshold.clone();
+        if self.settings.ble.advertisement_only {
+            debug!("Spawning advertisement thread, AppView: {:?}", self.view);
+            self.hr_thread_handle = Some(tokio::spawn(async move {
+                start_advertisement_thread(
+                    hr_tx_clone,
+                    device,
+                    rr_twitch_threshold,
+                    shutdown_requested_clone,
+                )
+                .await
+            }));
+            return;
+        }
+        let ble_settings = self.settings.ble.clone();
+        let stress_index_window = self.settings.hrv.stress_index_window as usize;
+        let aggregate = self.aggregate_input(PRIMARY_SOURCE);
+        let pairing_tx = self.pairing.request_tx();
         debug!("Spawning notification thread, AppView: {:?}", self.view);
         self.hr_thread_handle = Some(tokio::spawn(async move {
             start_notification_thread(
                 hr_tx_clone,
                 restart_tx_clone,
                 device,
-                rr_ignore_after_empty,
+                ble_settings,
                 rr_twitch_threshold,
+                stress_index_window,
+                aggregate,
+                pairing_tx,
                 shutdown_requested_clone,
             )
             .await
         }));
     }
 
-    fn is_device_saved(&self, given_device: Option<&DeviceInfo>) -> bool {
-        if self.settings.ble.saved_name.is_empty() && self.settings.ble.saved_address.is_empty() {
-            return false;
+    /// Connects to `ble.secondary_device` alongside the main device.
+    fn connect_to_secondary(&mut self, device: DeviceInfo) {
+        if self.secondary_hr_thread_handle.is_some() {
+            return;
+        }
+        info!("Found secondary device {}, connecting", device.name);
+        journal::record(
+            Severity::Info,
+            Subsystem::Ble,
+            &format!("Connecting to secondary device {}", device.name),
+        );
+        self.secondary_device_id = Some(device.id.clone());
+        let hr_tx_clone = self.source_broadcast_tx();
+        let restart_tx_clone = self.ble_restart_tx.clone().expect("BLE Restart TX missing");
+        let shutdown_requested_clone = self.sources.token();
+        let rr_twitch_threshold = self.rr_twitch_threshold.clone();
+        let ble_settings = self.settings.ble.clone();
+        let stress_index_window = self.settings.hrv.stress_index_window as usize;
+        let aggregate = self.aggregate_input(SECONDARY_SOURCE);
+        let pairing_tx = self.pairing.request_tx();
+        debug!("Spawning secondary notification thread");
+        self.secondary_hr_thread_handle = Some(tokio::spawn(async move {
+            start_notification_thread(
+                hr_tx_clone,
+                restart_tx_clone,
+                device,
+                ble_settings,
+                rr_twitch_threshold,
+                stress_index_window,
+                aggregate,
+                pairing_tx,
+                shutdown_requested_clone,
+            )
+            .await
+        }));
+    }
+
+    /// Starts the aggregator the first time it's needed, if there's a secondary device to combine with.
+    fn aggregate_input(&mut self, source: u8) -> Option<AggregateInput> {
+        if !self.uses_secondary_device() {
+            return None;
+        }
+        if self.aggregate_tx.is_none() {
+            let (aggregate_tx, aggregate_rx) = mpsc::channel(20);
+            let broadcast_tx = self.source_broadcast_tx();
+            let policy = self.settings.ble.aggregate_policy;
+            let gap = Duration::from_secs(self.settings.ble.aggregate_gap_sec.max(1) as u64);
+            let shutdown_requested_clone = self.sources.token();
+            debug!("Spawning HR aggregator thread");
+            self.aggregator_handle = Some(tokio::spawn(async move {
+                aggregator_thread(
+                    broadcast_tx,
+                    aggregate_rx,
+                    policy,
+                    gap,
+                    shutdown_requested_clone,
+                )
+                .await
+            }));
+            self.aggregate_tx = Some(aggregate_tx);
+        }
+        self.aggregate_tx
+            .clone()
+            .map(|tx| AggregateInput::new(source, tx))
+    }
+
+    /// Where the running source should send updates, through the failover manager if it's on.
+    fn source_broadcast_tx(&mut self) -> BSender<AppUpdate> {
+        if !self.settings.failover.enabled || self.sources.current().is_none() {
+            return self.broadcast_tx.clone();
+        }
+        if self.failover_input.is_none() {
+            self.start_failover();
+        }
+        self.failover_input
+            .clone()
+            .expect("Failover input missing")
+            .relay(self.broadcast_tx.clone())
+    }
+
+    /// Starts the failover manager and fallback sources under the main source's token,
+    /// so they're swapped out along with it.
+    fn start_failover(&mut self) {
+        let main = self
+            .sources
+            .current()
+            .expect("Failover started without a source");
+        let mut fallbacks = Vec::new();
+        for &source in &self.settings.failover.fallbacks {
+            match source {
+                // Only one Bluetooth connection at a time, and stdin's only there with `--stdin`
+                HrSource::Ble | HrSource::Stdin => {
+                    warn!("{} can't be a fallback source, skipping", source.name());
+                }
+                _ if source == main || fallbacks.contains(&source) => {}
+                _ => fallbacks.push(source),
+            }
+        }
+        let (failover_tx, failover_rx) = mpsc::channel(20);
+        let cancel_token = self.sources.token();
+        for (index, &source) in fallbacks.iter().enumerate() {
+            let broadcast_tx =
+                FailoverInput::new(index + 1, failover_tx.clone()).relay(self.broadcast_tx.clone());
+            if let Some(handle) = self.spawn_fallback(source, broadcast_tx, cancel_token.clone()) {
+                self.fallback_handles.push(handle);
+            }
+        }
+        let sources = std::iter::once(main).chain(fallbacks).collect();
+        let broadcast_tx = self.broadcast_tx.clone();
+        let stale_after = Duration::from_secs(self.settings.failover.stale_after_sec.max(1) as u64);
+        debug!("Spawning failover manager thread");
+        self.failover_handle = Some(tokio::spawn(async move {
+            failover_thread(
+                broadcast_tx,
+                failover_rx,
+                sources,
+                stale_after,
+                cancel_token,
+            )
+            .await
+        }));
+        self.failover_input = Some(FailoverInput::new(0, failover_tx));
+    }
+
+    fn spawn_fallback(
+        &self,
+        source: HrSource,
+        broadcast_tx: BSender<AppUpdate>,
+        cancel_token: CancellationToken,
+    ) -> Option<JoinHandle<()>> {
+        let driver = source.driver()?;
+        let context = SourceContext {
+            settings: self.settings.clone(),
+            broadcast_tx,
+            rr_twitch_threshold: self.rr_twitch_threshold.clone(),
+            stress_index_window: self.settings.hrv.stress_index_window as usize,
+            cancel_token,
+        };
+        debug!("Spawning {} as a fallback source", source.name());
+        Some(driver.spawn(context))
+    }
+
+    // Advertisement-only mode never connects, so it's main device only
+    fn uses_secondary_device(&self) -> bool {
+        self.settings.ble.has_secondary_device() && !self.settings.ble.advertisement_only
+    }
+
+    // Scan keeps going after connecting to the main device until the secondary shows up
+    fn waiting_for_secondary(&self) -> bool {
+        self.uses_secondary_device() && self.secondary_hr_thread_handle.is_none()
+    }
+
+    /// Lets listeners know the main device dropped out,
+    /// through the aggregator if there is one so the secondary device can take over.
+    fn send_disconnected_hr(&self, err_msg: &str) {
+        match self.aggregate_tx.as_ref() {
+            Some(aggregate_tx) => {
+                if aggregate_tx.try_send(HeartRateStatus::default()).is_err() {
+                    warn!("{err_msg}");
+                }
+            }
+            None => match self.failover_input.as_ref() {
+                Some(failover_input) => failover_input.try_send(HeartRateStatus::default()),
+                None => {
+                    broadcast!(self.broadcast_tx, HeartRateStatus::default(), err_msg);
+                }
+            },
+        }
+    }
+
+    fn ble_reconnect_callback(&mut self, status: BleReconnect) {
+        match status {
+            BleReconnect::Retrying { .. } => self.ble_reconnect = Some(status),
+            BleReconnect::Connected => self.ble_reconnect = None,
+            BleReconnect::GaveUp => {
+                self.ble_reconnect = None;
+                // Thread has already exited, go back to scanning so the device
+                // gets picked back up if it's saved
+                self.hr_thread_handle = None;
+                self.hr_cancel = None;
+                self.view = AppView::BleDeviceSelection;
+                self.sub_state = SubState::None;
+                info!("Gave up reconnecting, resuming BLE scan");
+                self.ble_scan_paused.store(false, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// Stops a connection attempt that hasn't started streaming yet,
+    /// going back to the device list so another one can be picked.
+    fn cancel_connect(&mut self) {
+        let Some(hr_cancel) = self.hr_cancel.take() else {
+            return;
+        };
+        hr_cancel.cancel();
+        // Exits on its own once it notices
+        self.hr_thread_handle = None;
+        if let Some(device) = self.get_selected_device() {
+            info!("Cancelled connecting to {}", device.name);
+            journal::record(
+                Severity::Info,
+                Subsystem::Ble,
+                &format!("Cancelled connecting to {}", device.name),
+            );
+            // Otherwise a saved device would get connected to again as soon as it's seen
+            self.cancelled_device_id = Some(device.id.clone());
         }
+        self.connect_stage = None;
+        self.ble_reconnect = None;
+        self.quick_connect_ui = false;
+        self.view = AppView::BleDeviceSelection;
+        self.sub_state = SubState::None;
+        self.ble_scan_paused.store(false, Ordering::SeqCst);
+    }
 
+    fn is_device_saved(&self, given_device: Option<&DeviceInfo>) -> bool {
         let device = given_device.unwrap_or_else(|| self.get_selected_device().unwrap());
+        self.saved_priority(device).is_some()
+    }
+
+    fn saved_priority(&self, device: &DeviceInfo) -> Option<usize> {
+        self.settings
+            .ble
+            .saved_priority(&device.name, &device.get_id())
+    }
+
+    /// Connects right away to the highest priority saved device,
+    /// otherwise holds onto the best one found for `saved_device_wait_sec`.
+    fn found_saved_device(&mut self, priority: usize, device: DeviceInfo) {
+        if priority == 0 {
+            self.connect_to_saved_device(device);
+            return;
+        }
+        if matches!(&self.pending_saved_device, Some((best, _)) if *best <= priority) {
+            return;
+        }
+        debug!("Found saved device with priority {priority}, waiting for better ones");
+        self.pending_saved_device = Some((priority, device));
+        self.saved_device_deadline.get_or_insert_with(|| {
+            let wait = Duration::from_secs(self.settings.ble.saved_device_wait_sec as u64);
+            tokio::time::Instant::now() + wait
+        });
+    }
+
+    fn saved_device_wait_over(&mut self) {
+        self.saved_device_deadline = None;
+        if let Some((priority, device)) = self.pending_saved_device.take() {
+            if self.is_idle_on_ble_selection() {
+                info!("Falling back to saved device with priority {priority}");
+                self.connect_to_saved_device(device);
+            }
+        }
+    }
 
-        device.name == self.settings.ble.saved_name
-            || device.address == self.settings.ble.saved_address
+    fn connect_to_saved_device(&mut self, device: DeviceInfo) {
+        self.pending_saved_device = None;
+        self.saved_device_deadline = None;
+        self.quick_connect_ui = true;
+        // I'm going to assume that if we find a set saved device,
+        // they're always going to want to update the value in case Name/MAC changes,
+        // even if they're weird and have set `never_ask_to_save` to true
+        self.should_save_ble_device = true;
+        // Adding device to UI list so other parts of the app that check the selected device
+        // get the expected result
+        if !self.discovered_devices.iter().any(|d| d.id == device.id) {
+            self.discovered_devices.push(device.clone());
+        }
+        self.table_state.select(
+            self.discovered_devices
+                .iter()
+                .position(|d| d.id == device.id),
+        );
+        self.try_save_device(Some(&device));
+        debug!("Connecting to saved device, AppView: {:?}", self.view);
+        // app_state changed by method
+        self.connect_for_hr(Some(&device));
     }
 
     pub fn start_osc_thread(&mut self, initial_activity: Option<u8>) {
@@ -612,6 +1322,10 @@ impl App {
         let broadcast_rx = self.broadcast_tx.subscribe();
         let broadcast_tx = self.broadcast_tx.clone();
         let shutdown_requested_clone = self.cancel_actors.clone();
+        if osc_settings.debug_tap || osc_settings.dry_run {
+            self.osc_tap_log = Some(new_tap_log());
+        }
+        let tap_log = self.osc_tap_log.clone();
 
         debug!("Spawning OSC thread");
         self.osc_thread_handle = Some(tokio::spawn(async move {
@@ -620,6 +1334,7 @@ impl App {
                 broadcast_tx,
                 initial_activity,
                 osc_settings,
+                tap_log,
                 shutdown_requested_clone,
             )
             .await
@@ -632,9 +1347,21 @@ impl App {
             || self.settings.misc.write_rr_to_file;
         if file_logging_enabled {
             let misc_settings_clone = self.settings.misc.clone();
+            let number_format = self.number_format.clone();
             let shutdown_requested_clone = self.cancel_actors.clone();
             let broadcast_rx = self.broadcast_tx.subscribe();
             let broadcast_tx = self.broadcast_tx.clone();
+            let raw_rx = match (
+                self.settings.misc.raw_capture,
+                self.settings.misc.log_sessions_to_csv,
+            ) {
+                (true, true) => raw_capture::init(),
+                (true, false) => {
+                    warn!("Raw capture enabled without session logging, not capturing!");
+                    None
+                }
+                _ => None,
+            };
 
             debug!("Spawning Data Logging thread");
             self.file_logging_handle = Some(tokio::spawn(async move {
@@ -643,6 +1370,8 @@ impl App {
                     broadcast_tx,
                     initial_activity,
                     misc_settings_clone,
+                    number_format,
+                    raw_rx,
                     shutdown_requested_clone,
                 )
                 .await
@@ -667,116 +1396,530 @@ impl App {
                 .await
             }));
         }
-    }
-
-    pub fn start_dummy_thread(&mut self, seconds_override: Option<f32>, vhs_prefill: bool) {
-        let broadcast_tx = self.broadcast_tx.clone();
-        let shutdown_requested_clone = self.cancel_actors.clone();
-        let dummy_settings_clone = self.settings.dummy.clone();
-        debug!("Spawning Dummy thread");
-        self.view = AppView::HeartRateView;
-        self.chart_high_rr = self.settings.tui.chart_rr_max;
-        self.dummy_thread_handle = Some(tokio::spawn(async move {
-            dummy_thread(
-                broadcast_tx,
-                dummy_settings_clone,
-                seconds_override,
-                vhs_prefill,
-                shutdown_requested_clone,
-            )
-            .await
-        }));
-    }
-
-    pub fn start_websocket_thread(&mut self, port_override: Option<u16>) {
-        let broadcast_tx = self.broadcast_tx.clone();
-        let shutdown_requested_clone = self.cancel_actors.clone();
-        let websocket_settings_clone = self.settings.websocket.clone();
-        // Not leaving as Duration as it's being used to check an abs difference
-        let rr_twitch_threshold =
-            Duration::from_millis(self.settings.osc.twitch_rr_threshold_ms as u64).as_secs_f32();
-        debug!("Spawning Websocket thread");
-        self.view = AppView::WaitingForWebsocket;
-        self.websocket_thread_handle = Some(tokio::spawn(async move {
-            websocket_thread(
-                broadcast_tx,
-                websocket_settings_clone,
-                port_override,
-                rr_twitch_threshold,
-                shutdown_requested_clone,
-            )
-            .await
-        }));
-    }
 
-    pub async fn join_threads(&mut self) {
-        let duration = Duration::from_secs(3);
-        info!("Sending shutdown signal to threads!");
-        self.cancel_app.cancel();
+        if self.settings.influxdb.enabled {
+            let influx_settings_clone = self.settings.influxdb.clone();
+            let shutdown_requested_clone = self.cancel_actors.clone();
+            let broadcast_rx = self.broadcast_tx.subscribe();
+            let broadcast_tx = self.broadcast_tx.clone();
 
-        if let Some(handle) = self.ble_thread_handle.take() {
-            debug!("Joining BLE thread");
-            if let Err(err) = timeout(duration, handle).await {
-                error!("Failed to join BLE thread: {:?}", err);
-            }
+            debug!("Spawning InfluxDB thread");
+            self.influx_handle = Some(tokio::spawn(async move {
+                influx_logging_thread(
+                    broadcast_rx,
+                    broadcast_tx,
+                    influx_settings_clone,
+                    shutdown_requested_clone,
+                )
+                .await
+            }));
         }
 
-        if let Some(handle) = self.hr_thread_handle.take() {
-            debug!("Joining HR thread");
-            if let Err(err) = timeout(duration, handle).await {
-                error!("Failed to join HR thread: {:?}", err);
-            }
-        }
+        if self.settings.webhook.enabled {
+            let webhook_settings_clone = self.settings.webhook.clone();
+            let shutdown_requested_clone = self.cancel_actors.clone();
+            let broadcast_rx = self.broadcast_tx.subscribe();
+            let broadcast_tx = self.broadcast_tx.clone();
 
-        if let Some(handle) = self.websocket_thread_handle.take() {
-            debug!("Joining Websocket thread");
-            if let Err(err) = timeout(duration, handle).await {
-                error!("Failed to join Websocket thread: {:?}", err);
-            }
+            debug!("Spawning Webhook thread");
+            self.webhook_handle = Some(tokio::spawn(async move {
+                webhook_thread(
+                    broadcast_rx,
+                    broadcast_tx,
+                    webhook_settings_clone,
+                    shutdown_requested_clone,
+                )
+                .await
+            }));
         }
 
-        if let Some(handle) = self.osc_thread_handle.take() {
-            debug!("Joining OSC thread");
-            if let Err(err) = timeout(duration, handle).await {
-                error!("Failed to join OSC thread: {:?}", err);
-            }
-        }
+        if self.settings.udp_output.enabled {
+            let udp_output_settings_clone = self.settings.udp_output.clone();
+            let shutdown_requested_clone = self.cancel_actors.clone();
+            let broadcast_rx = self.broadcast_tx.subscribe();
+            let broadcast_tx = self.broadcast_tx.clone();
 
-        if let Some(handle) = self.file_logging_handle.take() {
-            debug!("Joining File Logging thread");
-            if let Err(err) = timeout(duration, handle).await {
-                error!("Failed to join File Logging thread: {:?}", err);
-            }
+            debug!("Spawning UDP output thread");
+            self.udp_output_handle = Some(tokio::spawn(async move {
+                udp_output_thread(
+                    broadcast_rx,
+                    broadcast_tx,
+                    udp_output_settings_clone,
+                    shutdown_requested_clone,
+                )
+                .await
+            }));
         }
 
-        if let Some(handle) = self.prometheus_handle.take() {
-            debug!("Joining Prometheus thread");
-            if let Err(err) = timeout(duration, handle).await {
-                error!("Failed to join Prometheus thread: {:?}", err);
-            }
-        }
+        if self.settings.prometheus.exporter.enabled {
+            let exporter_settings_clone = self.settings.prometheus.exporter.clone();
+            let shutdown_requested_clone = self.cancel_actors.clone();
+            let broadcast_tx = self.broadcast_tx.clone();
 
-        if let Some(handle) = self.dummy_thread_handle.take() {
-            debug!("Joining Dummy thread");
-            if let Err(err) = timeout(duration, handle).await {
-                error!("Failed to join Dummy thread: {:?}", err);
-            }
+            debug!("Spawning Prometheus exporter thread");
+            self.prometheus_exporter_handle = Some(tokio::spawn(async move {
+                prometheus_exporter_thread(
+                    broadcast_tx,
+                    exporter_settings_clone,
+                    shutdown_requested_clone,
+                )
+                .await
+            }));
         }
-    }
 
-    /// Wrapper for save_settings that handles errors and returns just a success bool
-    pub fn try_save_settings(&mut self) -> bool {
-        if let Err(e) = self.save_settings() {
-            error!("Couldn't save settings! {}", e);
-            self.handle_error_update(ErrorPopup::detailed("Couldn't save settings!", e));
+        if self.settings.mqtt_output.enabled {
+            let mqtt_output_settings_clone = self.settings.mqtt_output.clone();
+            let shutdown_requested_clone = self.cancel_actors.clone();
+            let broadcast_rx = self.broadcast_tx.subscribe();
+            let broadcast_tx = self.broadcast_tx.clone();
 
-            false
-        } else {
-            true
+            debug!("Spawning MQTT output thread");
+            self.mqtt_output_handle = Some(tokio::spawn(async move {
+                mqtt_output_thread(
+                    broadcast_rx,
+                    broadcast_tx,
+                    mqtt_output_settings_clone,
+                    shutdown_requested_clone,
+                )
+                .await
+            }));
         }
-    }
 
-    fn save_settings(&mut self) -> Result<(), AppError> {
+        if self.settings.hrtovrchat.enabled {
+            let hrtovrchat_settings_clone = self.settings.hrtovrchat.clone();
+            let shutdown_requested_clone = self.cancel_actors.clone();
+            let broadcast_tx = self.broadcast_tx.clone();
+
+            debug!("Spawning HRtoVRChat websocket thread");
+            self.hrtovrchat_handle = Some(tokio::spawn(async move {
+                hrtovrchat_thread(
+                    broadcast_tx,
+                    hrtovrchat_settings_clone,
+                    shutdown_requested_clone,
+                )
+                .await
+            }));
+        }
+
+        if self.settings.obs.enabled {
+            let obs_settings_clone = self.settings.obs.clone();
+            let shutdown_requested_clone = self.cancel_actors.clone();
+            let broadcast_rx = self.broadcast_tx.subscribe();
+            let broadcast_tx = self.broadcast_tx.clone();
+
+            debug!("Spawning OBS thread");
+            self.obs_handle = Some(tokio::spawn(async move {
+                obs_thread(
+                    broadcast_rx,
+                    broadcast_tx,
+                    obs_settings_clone,
+                    shutdown_requested_clone,
+                )
+                .await
+            }));
+        }
+
+        if self.settings.streamerbot.enabled {
+            let streamerbot_settings_clone = self.settings.streamerbot.clone();
+            let shutdown_requested_clone = self.cancel_actors.clone();
+            let broadcast_rx = self.broadcast_tx.subscribe();
+            let broadcast_tx = self.broadcast_tx.clone();
+
+            debug!("Spawning Streamer.bot thread");
+            self.streamerbot_handle = Some(tokio::spawn(async move {
+                streamerbot_thread(
+                    broadcast_rx,
+                    broadcast_tx,
+                    streamerbot_settings_clone,
+                    shutdown_requested_clone,
+                )
+                .await
+            }));
+        }
+
+        if self.settings.overlay.enabled {
+            let overlay_settings_clone = self.settings.overlay.clone();
+            let number_format = self.number_format.clone();
+            let shutdown_requested_clone = self.cancel_actors.clone();
+            let broadcast_tx = self.broadcast_tx.clone();
+
+            debug!("Spawning overlay websocket thread");
+            self.overlay_handle = Some(tokio::spawn(async move {
+                overlay_thread(
+                    broadcast_tx,
+                    overlay_settings_clone,
+                    number_format,
+                    shutdown_requested_clone,
+                )
+                .await
+            }));
+        }
+
+        if self.settings.ipc.enabled {
+            let ipc_settings_clone = self.settings.ipc.clone();
+            let shutdown_requested_clone = self.cancel_actors.clone();
+            let broadcast_tx = self.broadcast_tx.clone();
+
+            debug!("Spawning IPC thread");
+            self.ipc_handle = Some(tokio::spawn(async move {
+                ipc_thread(broadcast_tx, ipc_settings_clone, shutdown_requested_clone).await
+            }));
+        }
+    }
+
+    fn start_fan_thread(&mut self) {
+        if self.zones.is_none() {
+            warn!("Fan control needs [zones] enabled, not starting it");
+            return;
+        }
+        let fan_settings_clone = self.settings.fan.clone();
+        let broadcast_rx = self.broadcast_tx.subscribe();
+        let shutdown_requested_clone = self.cancel_actors.clone();
+        debug!("Spawning Fan thread");
+        self.fan_handle = Some(tokio::spawn(async move {
+            fan_thread(broadcast_rx, fan_settings_clone, shutdown_requested_clone).await
+        }));
+    }
+
+    fn start_twitch_thread(&mut self) {
+        let twitch_settings_clone = self.settings.twitch.clone();
+        let broadcast_rx = self.broadcast_tx.subscribe();
+        let broadcast_tx_clone = self.broadcast_tx.clone();
+        let shutdown_requested_clone = self.cancel_actors.clone();
+        debug!("Spawning Twitch thread");
+        self.twitch_handle = Some(tokio::spawn(async move {
+            twitch_thread(
+                broadcast_rx,
+                broadcast_tx_clone,
+                twitch_settings_clone,
+                shutdown_requested_clone,
+            )
+            .await
+        }));
+    }
+
+    fn start_haptics_thread(&mut self) {
+        if self.settings.haptics.intensity_from == BeatStrength::Zone && self.zones.is_none() {
+            warn!("Haptics with intensity_from = \"zone\" needs [zones] enabled, not starting it");
+            return;
+        }
+        let haptics_settings_clone = self.settings.haptics.clone();
+        let broadcast_rx = self.broadcast_tx.subscribe();
+        let broadcast_tx_clone = self.broadcast_tx.clone();
+        let shutdown_requested_clone = self.cancel_actors.clone();
+        debug!("Spawning Haptics thread");
+        self.haptics_handle = Some(tokio::spawn(async move {
+            haptics_thread(
+                broadcast_rx,
+                broadcast_tx_clone,
+                haptics_settings_clone,
+                shutdown_requested_clone,
+            )
+            .await
+        }));
+    }
+
+    fn start_wled_thread(&mut self) {
+        let wled_settings_clone = self.settings.wled.clone();
+        let broadcast_rx = self.broadcast_tx.subscribe();
+        let broadcast_tx_clone = self.broadcast_tx.clone();
+        let shutdown_requested_clone = self.cancel_actors.clone();
+        debug!("Spawning WLED thread");
+        self.wled_handle = Some(tokio::spawn(async move {
+            wled_thread(
+                broadcast_rx,
+                broadcast_tx_clone,
+                wled_settings_clone,
+                shutdown_requested_clone,
+            )
+            .await
+        }));
+    }
+
+    fn start_audio_thread(&mut self) {
+        if self.settings.audio.volume_from == BeatStrength::Zone && self.zones.is_none() {
+            warn!("Audio with volume_from = \"zone\" needs [zones] enabled, not starting it");
+            return;
+        }
+        let audio_settings_clone = self.settings.audio.clone();
+        let broadcast_rx = self.broadcast_tx.subscribe();
+        let broadcast_tx_clone = self.broadcast_tx.clone();
+        let shutdown_requested_clone = self.cancel_actors.clone();
+        debug!("Spawning Audio thread");
+        self.audio_handle = Some(tokio::spawn(async move {
+            audio_thread(
+                broadcast_rx,
+                broadcast_tx_clone,
+                audio_settings_clone,
+                shutdown_requested_clone,
+            )
+            .await
+        }));
+    }
+
+    fn start_gatt_server_thread(&mut self) {
+        let gatt_settings_clone = self.settings.gatt_server.clone();
+        let broadcast_rx = self.broadcast_tx.subscribe();
+        let broadcast_tx_clone = self.broadcast_tx.clone();
+        let shutdown_requested_clone = self.cancel_actors.clone();
+        debug!("Spawning GATT server thread");
+        self.gatt_server_handle = Some(tokio::spawn(async move {
+            gatt_server_thread(
+                broadcast_rx,
+                broadcast_tx_clone,
+                gatt_settings_clone,
+                shutdown_requested_clone,
+            )
+            .await
+        }));
+    }
+
+    fn start_syslog_thread(&mut self) {
+        let Some(syslog_rx) = syslog::init(&self.settings.syslog) else {
+            return;
+        };
+        let syslog_settings_clone = self.settings.syslog.clone();
+        let shutdown_requested_clone = self.cancel_actors.clone();
+        debug!("Spawning Syslog thread");
+        self.syslog_handle = Some(tokio::spawn(async move {
+            syslog_thread(syslog_rx, syslog_settings_clone, shutdown_requested_clone).await
+        }));
+    }
+
+    pub fn start_dummy_thread(&mut self, speed_override: Option<f32>, vhs_mode: bool) {
+        self.chart_high_rr = self.settings.tui.chart_rr_max;
+        self.start_source(Box::new(DummySource {
+            speed_override,
+            vhs_mode,
+        }));
+    }
+
+    /// Starts any source but Bluetooth, swapping out whatever was running.
+    pub fn start_source(&mut self, driver: Box<dyn HeartRateSource>) {
+        let source = driver.source();
+        let cancel_token = self.sources.start(source);
+        let context = SourceContext {
+            settings: self.settings.clone(),
+            broadcast_tx: self.source_broadcast_tx(),
+            rr_twitch_threshold: self.rr_twitch_threshold.clone(),
+            stress_index_window: self.settings.hrv.stress_index_window as usize,
+            cancel_token,
+        };
+        debug!("Spawning {} thread", source.name());
+        self.view = driver.waiting_view();
+        self.source_thread_handle = Some(driver.spawn(context));
+    }
+
+    pub async fn join_threads(&mut self) {
+        let duration = Duration::from_secs(3);
+        info!("Sending shutdown signal to threads!");
+        journal::record(Severity::Info, Subsystem::App, "Shutting down");
+        self.cancel_app.cancel();
+
+        if let Some(handle) = self.ble_thread_handle.take() {
+            debug!("Joining BLE thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join BLE thread: {:?}", err);
+            }
+        }
+
+        if let Some(handle) = self.hr_thread_handle.take() {
+            debug!("Joining HR thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join HR thread: {:?}", err);
+            }
+        }
+
+        if let Some(handle) = self.secondary_hr_thread_handle.take() {
+            debug!("Joining secondary HR thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join secondary HR thread: {:?}", err);
+            }
+        }
+
+        if let Some(handle) = self.failover_handle.take() {
+            debug!("Joining failover manager thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join failover manager thread: {:?}", err);
+            }
+        }
+
+        for handle in self.fallback_handles.drain(..) {
+            debug!("Joining fallback source thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join fallback source thread: {:?}", err);
+            }
+        }
+
+        if let Some(handle) = self.aggregator_handle.take() {
+            debug!("Joining HR aggregator thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join HR aggregator thread: {:?}", err);
+            }
+        }
+
+        if let Some(handle) = self.source_thread_handle.take() {
+            debug!("Joining HR source thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join HR source thread: {:?}", err);
+            }
+        }
+
+        if let Some(handle) = self.osc_thread_handle.take() {
+            debug!("Joining OSC thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join OSC thread: {:?}", err);
+            }
+        }
+
+        if let Some(handle) = self.file_logging_handle.take() {
+            debug!("Joining File Logging thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join File Logging thread: {:?}", err);
+            }
+        }
+
+        if let Some(handle) = self.prometheus_handle.take() {
+            debug!("Joining Prometheus thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join Prometheus thread: {:?}", err);
+            }
+        }
+
+        if let Some(handle) = self.influx_handle.take() {
+            debug!("Joining InfluxDB thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join InfluxDB thread: {:?}", err);
+            }
+        }
+
+        if let Some(handle) = self.webhook_handle.take() {
+            debug!("Joining Webhook thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join Webhook thread: {:?}", err);
+            }
+        }
+
+        if let Some(handle) = self.udp_output_handle.take() {
+            debug!("Joining UDP output thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join UDP output thread: {:?}", err);
+            }
+        }
+
+        if let Some(handle) = self.prometheus_exporter_handle.take() {
+            debug!("Joining Prometheus exporter thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join Prometheus exporter thread: {:?}", err);
+            }
+        }
+
+        if let Some(handle) = self.mqtt_output_handle.take() {
+            debug!("Joining MQTT output thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join MQTT output thread: {:?}", err);
+            }
+        }
+
+        if let Some(handle) = self.hrtovrchat_handle.take() {
+            debug!("Joining HRtoVRChat websocket thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join HRtoVRChat websocket thread: {:?}", err);
+            }
+        }
+
+        if let Some(handle) = self.obs_handle.take() {
+            debug!("Joining OBS thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join OBS thread: {:?}", err);
+            }
+        }
+
+        if let Some(handle) = self.streamerbot_handle.take() {
+            debug!("Joining Streamer.bot thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join Streamer.bot thread: {:?}", err);
+            }
+        }
+
+        if let Some(handle) = self.overlay_handle.take() {
+            debug!("Joining overlay websocket thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join overlay websocket thread: {:?}", err);
+            }
+        }
+
+        if let Some(handle) = self.ipc_handle.take() {
+            debug!("Joining IPC thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join IPC thread: {:?}", err);
+            }
+        }
+
+        if let Some(handle) = self.fan_handle.take() {
+            debug!("Joining Fan thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join Fan thread: {:?}", err);
+            }
+        }
+
+        if let Some(handle) = self.twitch_handle.take() {
+            debug!("Joining Twitch thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join Twitch thread: {:?}", err);
+            }
+        }
+
+        if let Some(handle) = self.haptics_handle.take() {
+            debug!("Joining Haptics thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join Haptics thread: {:?}", err);
+            }
+        }
+
+        if let Some(handle) = self.wled_handle.take() {
+            debug!("Joining WLED thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join WLED thread: {:?}", err);
+            }
+        }
+
+        if let Some(handle) = self.audio_handle.take() {
+            debug!("Joining Audio thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join Audio thread: {:?}", err);
+            }
+        }
+
+        if let Some(handle) = self.gatt_server_handle.take() {
+            debug!("Joining GATT server thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join GATT server thread: {:?}", err);
+            }
+        }
+
+        // Last, so it can send everything above
+        if let Some(handle) = self.syslog_handle.take() {
+            debug!("Joining Syslog thread");
+            if let Err(err) = timeout(duration, handle).await {
+                error!("Failed to join Syslog thread: {:?}", err);
+            }
+        }
+    }
+
+    /// Wrapper for save_settings that handles errors and returns just a success bool
+    pub fn try_save_settings(&mut self) -> bool {
+        if let Err(e) = self.save_settings() {
+            error!("Couldn't save settings! {}", e);
+            self.handle_error_update(ErrorPopup::detailed("Couldn't save settings!", e));
+
+            false
+        } else {
+            true
+        }
+    }
+
+    fn save_settings(&mut self) -> Result<(), AppError> {
         if self.allow_modifying_config
         // && !self.cancel_actors.is_cancelled()
         {
@@ -799,23 +1942,72 @@ impl App {
                 return;
             }
 
-            let mut damaged = false;
-            if self.settings.ble.saved_address != new_id || self.settings.ble.saved_name != new_name
-            {
-                damaged = true;
-            }
-            // TODO See if I can find a way to get "Unknown" programatically,
-            // not a fan of hardcoding it (and it's "" in the ::default())
-            // Maybe do a .new() and supply a None?
-            if damaged && new_name != "Unknown" {
-                self.settings.ble.saved_address.clone_from(&new_id);
-                self.settings.ble.saved_name.clone_from(&new_name);
+            // Not saving the placeholder name (it's "" in the ::default(), caught above)
+            if !device.is_unnamed() && self.settings.ble.remember_device(&new_name, &new_id) {
                 info!("Updating saved device! Name: {} MAC: {}", new_name, new_id);
                 self.try_save_settings();
             }
         }
     }
 
+    /// Whether a newly found device should be listed, see `ble.scan_*`.
+    fn passes_scan_filters(&self, device: &DeviceInfo) -> bool {
+        if device.is_unnamed() && !self.ui_state.show_unnamed_devices {
+            return false;
+        }
+        if let Some(regex) = self.scan_name_filter.as_ref() {
+            if !regex.is_match(&device.name) {
+                return false;
+            }
+        }
+        !self.settings.ble.scan_hr_service_only
+            || device.services.iter().any(|service| {
+                *service == HEART_RATE_SERVICE_UUID
+                    || *service == PULSE_OXIMETER_SERVICE_UUID
+                    || *service == FITNESS_MACHINE_SERVICE_UUID
+            })
+    }
+
+    pub fn toggle_unnamed_devices(&mut self) {
+        self.ui_state.show_unnamed_devices = !self.ui_state.show_unnamed_devices;
+        self.ui_state.persist(&self.settings.tui);
+        if self.ui_state.show_unnamed_devices {
+            // They'll show back up as they keep advertising
+            return;
+        }
+        let selected_id = self.get_selected_device().map(|device| device.id.clone());
+        self.discovered_devices
+            .retain(|device| !device.is_unnamed());
+        let selected_index = selected_id
+            .and_then(|id| self.discovered_devices.iter().position(|d| d.id == id))
+            .or_else(|| (!self.discovered_devices.is_empty()).then_some(0));
+        self.table_state.select(selected_index);
+    }
+
+    fn check_low_battery(&mut self, hr_status: &HeartRateStatus) {
+        let Some(level) = self.low_battery.update(hr_status.battery_level) else {
+            return;
+        };
+        warn!("Sensor battery is at {level}%");
+        self.alerts.raise(Alert {
+            kind: AlertKind::LowBattery,
+            message: format!("Sensor battery is at {level}%"),
+        });
+        // Only BLE devices have a name/address to hand over
+        let (name, address) = match self.get_selected_device() {
+            Some(device) if self.ble_thread_handle.is_some() => {
+                (device.name.clone(), device.get_id())
+            }
+            _ => Default::default(),
+        };
+        self.hooks.run(
+            HookEvent::LowBattery,
+            &name,
+            &address,
+            hr_status.battery_level,
+        );
+    }
+
     pub fn get_selected_device(&self) -> Option<&DeviceInfo> {
         if let Some(selected_index) = self.table_state.selected() {
             self.discovered_devices.get(selected_index)
@@ -830,6 +2022,269 @@ impl App {
             && self.sub_state == SubState::None
     }
 
+    /// If MACs, IPs, etc. should be replaced with a placeholder in the TUI
+    pub fn hide_sensitive(&self) -> bool {
+        self.settings.tui.stream_safe
+            && !matches!(self.reveal_sensitive_until, Some(until) if Instant::now() < until)
+    }
+
+    pub fn reveal_sensitive(&mut self) {
+        if self.settings.tui.stream_safe {
+            debug!("Temporarily revealing sensitive info");
+            self.reveal_sensitive_until = Some(Instant::now() + SENSITIVE_REVEAL_DURATION);
+        }
+    }
+
+    pub fn toggle_osc_tap(&mut self) {
+        if self.osc_tap_log.is_some() {
+            self.ui_state.show_osc_tap = !self.ui_state.show_osc_tap;
+            self.ui_state.persist(&self.settings.tui);
+        }
+    }
+
+    fn record_twitch_sample(&mut self, hr_data: &HeartRateStatus) {
+        if self.twitch_samples.len() >= TWITCH_REPLAY_SAMPLES {
+            self.twitch_samples.pop_front();
+        }
+        self.twitch_samples.push_back(TwitchSample {
+            bpm: hr_data.heart_rate_bpm,
+            rr_intervals: hr_data.rr_intervals.clone(),
+        });
+    }
+
+    pub fn twitch_tuning_open(&mut self) {
+        if self.view != AppView::HeartRateView || self.sub_state != SubState::None {
+            return;
+        }
+        let samples = self.twitch_samples.iter().cloned().collect();
+        self.twitch_tuning = Some(TwitchTuning::new(
+            self.settings.osc.twitch_rr_threshold_ms,
+            samples,
+        ));
+        self.sub_state = SubState::TwitchTuning;
+    }
+
+    fn twitch_tuning_close(&mut self) {
+        self.twitch_tuning = None;
+        self.sub_state = SubState::None;
+    }
+
+    fn twitch_tuning_save(&mut self) {
+        if let Some(tuning) = self.twitch_tuning.as_ref() {
+            info!("Twitch threshold set to {}ms", tuning.threshold_ms);
+            self.settings.osc.twitch_rr_threshold_ms = tuning.threshold_ms;
+            self.rr_twitch_threshold.set_ms(tuning.threshold_ms);
+            self.try_save_settings();
+        }
+        self.twitch_tuning_close();
+    }
+
+    pub fn session_compare_open(&mut self) {
+        if self.sub_state != SubState::None {
+            return;
+        }
+        let store = SessionStore::new(&self.settings.misc);
+        self.session_compare = Some(SessionCompare::new(store, self.session_log.clone()));
+        self.sub_state = SubState::SessionCompare;
+        self.set_screen(Screen::SessionCompare);
+    }
+
+    fn session_compare_esc_pressed(&mut self) {
+        match self.session_compare.as_mut() {
+            Some(compare) if compare.showing_comparison() => compare.back(),
+            _ => {
+                self.session_compare = None;
+                self.sub_state = SubState::None;
+                self.set_screen(Screen::HeartRate);
+            }
+        }
+    }
+
+    fn open_screen(&mut self, screen: Screen) {
+        match screen {
+            Screen::HeartRate => {}
+            Screen::DeviceInformation => self.device_information_open(),
+            Screen::SessionCompare => self.session_compare_open(),
+        }
+    }
+
+    fn set_screen(&mut self, screen: Screen) {
+        if self.ui_state.screen != screen {
+            self.ui_state.screen = screen;
+            self.ui_state.persist(&self.settings.tui);
+        }
+    }
+
+    pub fn source_selection_open(&mut self) {
+        // Not offered until a source has started (i.e. still in the pre-flight prompt)
+        if self.sub_state != SubState::None || self.sources.current().is_none() {
+            return;
+        }
+        self.sources.select_current();
+        self.sub_state = SubState::SourceSelection;
+    }
+
+    fn source_selection_enter_pressed(&mut self) {
+        self.sub_state = SubState::None;
+        match self.sources.selected() {
+            Some(source) if Some(source) != self.sources.current() => self.switch_source(source),
+            _ => {}
+        }
+    }
+
+    /// Stops the running HR source and starts `source` in its place,
+    /// leaving OSC, logging, etc. running throughout.
+    fn switch_source(&mut self, source: HrSource) {
+        info!("Switching HR source to {}", source.name());
+        journal::record(
+            Severity::Info,
+            Subsystem::App,
+            &format!("Switching HR source to {}", source.name()),
+        );
+        // Handles are dropped rather than joined, they wind down on their own once
+        // `sources.start` cancels their token below
+        self.ble_thread_handle = None;
+        self.hr_thread_handle = None;
+        self.secondary_hr_thread_handle = None;
+        self.aggregator_handle = None;
+        self.failover_input = None;
+        self.failover_handle = None;
+        // Aborted outright, so a fallback stuck mid-connect can't keep relaying into the new source
+        for handle in self.fallback_handles.drain(..) {
+            handle.abort();
+        }
+        self.failover_active = None;
+        self.source_thread_handle = None;
+        self.ble_restart_tx = None;
+        self.aggregate_tx = None;
+        self.secondary_device_id = None;
+        self.hr_cancel = None;
+        self.pending_saved_device = None;
+        self.saved_device_deadline = None;
+        self.quick_connect_ui = false;
+        self.ble_reconnect = None;
+        self.connect_stage = None;
+        self.device_information = None;
+        self.websocket_url = None;
+        self.websocket_clients = None;
+        self.websocket_mdns_name = None;
+        self.discovered_devices.clear();
+        self.table_state.select(Some(0));
+        self.ble_scan_paused.store(false, Ordering::SeqCst);
+        // So listeners don't hold onto the old source's last reading
+        broadcast!(
+            self.broadcast_tx,
+            HeartRateStatus::default(),
+            "Failed to send 0BPM when switching sources"
+        );
+        if source == HrSource::Ble {
+            self.view = AppView::BleDeviceSelection;
+        }
+        self.start_hr_source(source);
+    }
+
+    pub fn device_information_open(&mut self) {
+        if self.view == AppView::HeartRateView
+            && self.sub_state == SubState::None
+            && self.device_information.is_some()
+        {
+            self.sub_state = SubState::DeviceInformation;
+            self.set_screen(Screen::DeviceInformation);
+        }
+    }
+
+    pub fn toggle_osc_paused(&mut self) {
+        if self.osc_thread_handle.is_none() {
+            return;
+        }
+        self.osc_paused = !self.osc_paused;
+        info!("OSC output paused: {}", self.osc_paused);
+        broadcast!(self.broadcast_tx, AppUpdate::OscPaused(self.osc_paused));
+    }
+
+    /// Moves to the next of `osc.presets`, wrapping back around to the default addresses.
+    pub fn cycle_osc_preset(&mut self) {
+        let presets = &self.settings.osc.presets;
+        let next = match self.osc_preset.as_ref() {
+            None => presets.keys().next(),
+            Some(current) => presets
+                .range::<String, _>((Bound::Excluded(current), Bound::Unbounded))
+                .next()
+                .map(|(name, _)| name),
+        };
+        self.set_osc_preset(next.cloned());
+    }
+
+    /// Switches to the preset bound to this key, returns false if there isn't one.
+    pub fn osc_preset_key(&mut self, key: char) -> bool {
+        let Some(name) = self
+            .settings
+            .osc
+            .presets
+            .iter()
+            .find(|(_, preset)| preset.bound_to(key))
+            .map(|(name, _)| name.clone())
+        else {
+            return false;
+        };
+        self.set_osc_preset(Some(name));
+        true
+    }
+
+    fn set_osc_preset(&mut self, preset: Option<String>) {
+        if self.osc_thread_handle.is_none() || self.osc_preset == preset {
+            return;
+        }
+        info!("OSC preset: {}", preset.as_deref().unwrap_or("default"));
+        self.osc_preset = preset.clone();
+        broadcast!(self.broadcast_tx, AppUpdate::OscPreset(preset));
+    }
+
+    /// Starts (or cancels) a sweep of the float HR param, see `osc.calibration_sweep_sec`.
+    pub fn toggle_osc_calibration(&mut self) {
+        if self.osc_thread_handle.is_none() || self.osc_paused {
+            return;
+        }
+        broadcast!(
+            self.broadcast_tx,
+            AppUpdate::OscCalibrating(!self.osc_calibrating)
+        );
+    }
+
+    pub fn toggle_bpm_chart(&mut self) {
+        self.ui_state.chart_bpm_enabled = !self.ui_state.chart_bpm_enabled;
+        self.chart_toggled();
+    }
+
+    pub fn toggle_rr_chart(&mut self) {
+        self.ui_state.chart_rr_enabled = !self.ui_state.chart_rr_enabled;
+        self.chart_toggled();
+    }
+
+    pub fn toggle_charts_combined(&mut self) {
+        self.ui_state.charts_combine = !self.ui_state.charts_combine;
+        self.chart_toggled();
+    }
+
+    pub fn cycle_layout(&mut self) {
+        self.ui_state.layout = self.ui_state.layout.next();
+        debug!("Layout: {:?}", self.ui_state.layout);
+        self.ui_state.persist(&self.settings.tui);
+    }
+
+    pub fn cycle_theme(&mut self) {
+        self.ui_state.theme = self.ui_state.theme.next();
+        debug!("Theme: {:?}", self.ui_state.theme);
+        self.ui_state.persist(&self.settings.tui);
+    }
+
+    fn chart_toggled(&mut self) {
+        // Datasets are only kept up to date for the enabled charts,
+        // and the RR one is scaled differently when combined
+        self.update_chart_data();
+        self.ui_state.persist(&self.settings.tui);
+    }
+
     fn datasets_empty(&self) -> bool {
         self.heart_rate_history.is_empty() && self.rr_history.is_empty()
     }
@@ -885,9 +2340,9 @@ impl App {
     }
 
     fn update_chart_data(&mut self) {
-        let bpm_enabled = self.settings.tui.chart_bpm_enabled;
-        let rr_enabled = self.settings.tui.chart_rr_enabled;
-        let combine = self.settings.tui.charts_combine;
+        let bpm_enabled = self.ui_state.chart_bpm_enabled;
+        let rr_enabled = self.ui_state.chart_rr_enabled;
+        let combine = self.ui_state.charts_combine;
         if rr_enabled {
             self.rr_dataset = self
                 .rr_history
@@ -906,16 +2361,44 @@ impl App {
                     }
                 })
                 .collect();
+            self.rr_dataset = self
+                .chart_refresh
+                .decimate(std::mem::take(&mut self.rr_dataset));
         }
 
         if bpm_enabled {
-            self.bpm_dataset = self
+            let dataset = self
                 .heart_rate_history
                 .iter()
                 .rev()
                 .enumerate()
                 .map(|(i, &x)| (i as f64, x))
                 .collect();
+            self.bpm_dataset = self.chart_refresh.decimate(dataset);
+        }
+    }
+
+    pub fn current_zone(&self) -> Option<u8> {
+        self.zones.as_ref().and_then(ZoneEngine::current)
+    }
+
+    // The engine lives here so the OSC param, TUI colors, and alerts all agree on the zone
+    fn update_zone(&mut self, bpm: u16) {
+        let Some(zones) = self.zones.as_mut() else {
+            return;
+        };
+        let previous = zones.current();
+        let Some(zone) = zones.update(bpm, Instant::now()) else {
+            return;
+        };
+        debug!("Heart rate zone: {previous:?} -> {zone}");
+        broadcast!(self.broadcast_tx, AppUpdate::ZoneChanged(zone));
+        // Not worth alerting about the first reading
+        if previous.is_some() {
+            self.alerts.raise(Alert {
+                kind: AlertKind::Zone,
+                message: format!("Entered heart rate zone {zone}"),
+            });
         }
     }
 
@@ -939,7 +2422,9 @@ impl App {
                 }
             }
 
-            self.update_chart_data();
+            if self.chart_refresh.should_update(Instant::now()) {
+                self.update_chart_data();
+            }
         }
     }
 
@@ -949,6 +2434,10 @@ impl App {
             Some(ErrorPopup::Fatal(_)) | Some(ErrorPopup::FatalDetailed(_, _)) => return,
             _ => {}
         }
+        let kind = AlertKind::from(&error);
+        let alert = Alert::from(&error);
+        journal::record(Severity::from(&error), Subsystem::App, &alert.message);
+        self.alerts.raise(alert);
         match error {
             ErrorPopup::Fatal(_) | ErrorPopup::FatalDetailed(_, _) => {
                 self.error_message = Some(error);
@@ -957,6 +2446,8 @@ impl App {
                 // Just for the UI, "stop" the scan
                 self.ble_scan_paused.store(true, Ordering::SeqCst);
             }
+            // Fatal errors always get shown, but the rest can be routed elsewhere
+            _ if !self.alerts.shows_popup(kind) => {}
             // Don't let an intermittent error override a "UserMustDismiss" error
             ErrorPopup::Intermittent(_) => match self.error_message {
                 Some(ErrorPopup::UserMustDismiss(_)) => {}
@@ -969,6 +2460,12 @@ impl App {
     /// Terminal interval tick
     pub fn term_tick(&mut self) {
         (self.frame_count, _) = self.frame_count.overflowing_add(1);
+        let now = Instant::now();
+        if self.chart_refresh.pending_due(now) {
+            self.update_chart_data();
+        }
+        self.sleep_inhibit.tick(now);
+        self.pairing_tick();
     }
 
     pub fn scroll_up(&mut self) {
@@ -976,7 +2473,7 @@ impl App {
             SubState::CharacteristicView => {
                 self.characteristic_scroll = self.characteristic_scroll.saturating_sub(1);
             }
-            SubState::SaveDevicePrompt => {
+            SubState::SaveDevicePrompt | SubState::PreflightPrompt => {
                 table_state_scroll(true, &mut self.prompt_state, 3);
             }
             #[cfg(windows)]
@@ -993,6 +2490,19 @@ impl App {
             SubState::UpdateFoundPrompt | SubState::UpdateAllowCheckPrompt => {
                 self.updates_scroll(true)
             }
+            SubState::TwitchTuning => {
+                if let Some(tuning) = self.twitch_tuning.as_mut() {
+                    tuning.raise();
+                }
+            }
+            SubState::SessionCompare => {
+                if let Some(compare) = self.session_compare.as_mut() {
+                    compare.scroll(true);
+                }
+            }
+            SubState::SourceSelection => {
+                table_state_scroll(true, &mut self.sources.table_state, HrSource::ALL.len());
+            }
             #[cfg(windows)]
             SubState::LaunchUpdatePrompt => self.updates_scroll(true),
             _ => {}
@@ -1009,7 +2519,7 @@ impl App {
             SubState::CharacteristicView => {
                 self.characteristic_scroll = self.characteristic_scroll.wrapping_add(1);
             }
-            SubState::SaveDevicePrompt => {
+            SubState::SaveDevicePrompt | SubState::PreflightPrompt => {
                 table_state_scroll(false, &mut self.prompt_state, 3);
             }
             #[cfg(windows)]
@@ -1026,6 +2536,19 @@ impl App {
             SubState::UpdateFoundPrompt | SubState::UpdateAllowCheckPrompt => {
                 self.updates_scroll(false)
             }
+            SubState::TwitchTuning => {
+                if let Some(tuning) = self.twitch_tuning.as_mut() {
+                    tuning.lower();
+                }
+            }
+            SubState::SessionCompare => {
+                if let Some(compare) = self.session_compare.as_mut() {
+                    compare.scroll(false);
+                }
+            }
+            SubState::SourceSelection => {
+                table_state_scroll(false, &mut self.sources.table_state, HrSource::ALL.len());
+            }
             #[cfg(windows)]
             SubState::LaunchUpdatePrompt => self.updates_scroll(false),
             _ => {}
@@ -1042,6 +2565,15 @@ impl App {
             SubState::ActivitySelection | SubState::ActivityCreation => {
                 self.activities_esc_pressed();
             }
+            SubState::TwitchTuning => self.twitch_tuning_close(),
+            SubState::SessionCompare => self.session_compare_esc_pressed(),
+            SubState::DeviceInformation => {
+                self.sub_state = SubState::None;
+                self.set_screen(Screen::HeartRate);
+            }
+            SubState::SourceSelection => self.sub_state = SubState::None,
+            SubState::ConnectingForHeartRate => self.cancel_connect(),
+            SubState::PairingPrompt => self.pairing_esc_pressed(),
             _ => {}
         }
     }
@@ -1135,6 +2667,32 @@ impl App {
                 self.updates_enter_pressed();
                 return;
             }
+            SubState::PreflightPrompt => {
+                self.preflight_enter_pressed();
+                return;
+            }
+            SubState::PairingPrompt => {
+                self.pairing_enter_pressed();
+                return;
+            }
+            SubState::TwitchTuning => {
+                self.twitch_tuning_save();
+                return;
+            }
+            SubState::SessionCompare => {
+                if let Some(compare) = self.session_compare.as_mut() {
+                    compare.toggle_selected();
+                }
+                return;
+            }
+            SubState::DeviceInformation => {
+                self.sub_state = SubState::None;
+                return;
+            }
+            SubState::SourceSelection => {
+                self.source_selection_enter_pressed();
+                return;
+            }
             #[cfg(windows)]
             SubState::LaunchUpdatePrompt => {
                 self.updates_enter_pressed();
@@ -1156,6 +2714,8 @@ impl App {
     /// Callback to handle new/updated devices found by the BLE scan thread
     pub fn device_info_callback(&mut self, new_device_info: DeviceUpdate) {
         match new_device_info {
+            // Stragglers from a scan that was stopped by switching sources
+            _ if self.sources.current() != Some(HrSource::Ble) => {}
             DeviceUpdate::DeviceInfo(device) => {
                 // If the device is already in the list, update it
                 if let Some(existing_device) = self
@@ -1167,15 +2727,11 @@ impl App {
                     //self.discovered_devices[existing_device_index] = device.clone();
                 } else {
                     // If the device is not in the list, add it
-                    // but only if it has the heart rate service
+                    // but only if it passes the scan filters
                     // (We don't use the ScanFilter from btleplug to allow quicker connection to saved devices,
                     // and since it reports only "Unknown" names for some reason)
                     // TODO: Raise issue about it
-                    if device
-                        .services
-                        .iter()
-                        .any(|service| *service == HEART_RATE_SERVICE_UUID)
-                    {
+                    if self.passes_scan_filters(&device) {
                         self.discovered_devices.push(device.clone());
                     }
                     // This filter used to be in scan.rs, but doing it here
@@ -1183,28 +2739,23 @@ impl App {
                 }
 
                 // If the device is saved, connect to it
-                if self.is_device_saved(Some(&device)) && self.is_idle_on_ble_selection() {
-                    self.quick_connect_ui = true;
-                    // I'm going to assume that if we find a set saved device,
-                    // they're always going to want to update the value in case Name/MAC changes,
-                    // even if they're weird and have set `never_ask_to_save` to true
-                    self.should_save_ble_device = true;
-                    // Adding device to UI list so other parts of the app that check the selected device
-                    // get the expected result
-                    if !self.discovered_devices.iter().any(|d| d.id == device.id) {
-                        self.discovered_devices.push(device.clone());
+                // (or wait a bit for a higher priority one to show up)
+                match self.saved_priority(&device) {
+                    _ if self.uses_secondary_device()
+                        && self
+                            .settings
+                            .ble
+                            .is_secondary_device(&device.name, &device.get_id()) =>
+                    {
+                        self.connect_to_secondary(device);
                     }
-                    self.table_state.select(
-                        self.discovered_devices
-                            .iter()
-                            .position(|d| d.id == device.id),
-                    );
-                    self.try_save_device(Some(&device));
-                    debug!("Connecting to saved device, AppView: {:?}", self.view);
-                    // app_state changed by method
-                    self.connect_for_hr(Some(&device));
-                } else {
-                    self.try_save_device(None);
+                    Some(priority)
+                        if self.is_idle_on_ble_selection()
+                            && self.cancelled_device_id.as_ref() != Some(&device.id) =>
+                    {
+                        self.found_saved_device(priority, device);
+                    }
+                    _ => self.try_save_device(None),
                 }
             }
             DeviceUpdate::Characteristics(characteristics) => {
@@ -1213,9 +2764,13 @@ impl App {
             }
             DeviceUpdate::Error(error) => {
                 error!("BLE Thread Error: {:?}", error.clone());
+                let kind = AlertKind::from(&error);
+                let alert = Alert::from(&error);
+                journal::record(Severity::from(&error), Subsystem::Ble, &alert.message);
+                self.alerts.raise(alert);
                 if self.view == AppView::HeartRateView && self.datasets_empty() {
                     // Ignoring the intermittent ones when we're in the inbetween state
-                } else {
+                } else if self.alerts.shows_popup(kind) {
                     // Don't override a fatal error
                     if !matches!(self.error_message, Some(ErrorPopup::Fatal(_))) {
                         self.error_message = Some(error);
@@ -1224,14 +2779,21 @@ impl App {
                 if self.view == AppView::HeartRateView
                     || self.sub_state == SubState::ConnectingForHeartRate
                 {
-                    broadcast!(
-                        self.broadcast_tx,
-                        HeartRateStatus::default(),
-                        "Failed to send 0BPM on BLE Error"
-                    );
+                    self.send_disconnected_hr("Failed to send 0BPM on BLE Error");
                 }
                 //self.is_loading_characteristics = false;
             }
+            DeviceUpdate::ConnectedEvent(id) if self.secondary_device_id.as_ref() == Some(&id) => {
+                info!("Connected to secondary device {:?}", id);
+                journal::record(
+                    Severity::Info,
+                    Subsystem::Ble,
+                    "Connected to secondary device",
+                );
+                if self.view == AppView::HeartRateView {
+                    self.ble_scan_paused.store(true, Ordering::SeqCst);
+                }
+            }
             DeviceUpdate::ConnectedEvent(id) => {
                 if self.sub_state == SubState::ConnectingForCharacteristics {
                     self.sub_state = SubState::CharacteristicView;
@@ -1241,29 +2803,58 @@ impl App {
                 }
 
                 if self.view == AppView::HeartRateView {
-                    if id == self.get_selected_device().unwrap().id {
-                        info!("Connected to device {:?}, stopping BLE scan", id);
-                        self.ble_scan_paused.store(true, Ordering::SeqCst);
+                    // Late events after switching away from BLE may have nothing selected
+                    if self.get_selected_device().is_some_and(|d| d.id == id) {
+                        journal::record(Severity::Info, Subsystem::Ble, "Connected to device");
+                        self.alerts.raise(Alert {
+                            kind: AlertKind::Connected,
+                            message: "Connected to device!".to_string(),
+                        });
+                        if self.waiting_for_secondary() {
+                            info!(
+                                "Connected to device {:?}, scanning for secondary device",
+                                id
+                            );
+                            self.ble_scan_paused.store(false, Ordering::SeqCst);
+                        } else {
+                            info!("Connected to device {:?}, stopping BLE scan", id);
+                            self.ble_scan_paused.store(true, Ordering::SeqCst);
+                        }
                     }
                     self.try_save_device(None);
                 }
             }
+            // Its thread handles reconnecting, and the main device keeps the popups
+            DeviceUpdate::DisconnectedEvent(disconnected_id)
+                if self.secondary_device_id.as_ref() == Some(&disconnected_id) =>
+            {
+                info!("Disconnected from secondary device {:?}", disconnected_id);
+                journal::record(
+                    Severity::Warning,
+                    Subsystem::Ble,
+                    "Disconnected from secondary device",
+                );
+            }
             DeviceUpdate::DisconnectedEvent(disconnected_id) => {
-                self.error_message = Some(ErrorPopup::Intermittent(
-                    "Disconnected from device!".to_string(),
-                ));
+                let message = "Disconnected from device!".to_string();
+                journal::record(Severity::Warning, Subsystem::Ble, &message);
+                if self.alerts.shows_popup(AlertKind::Disconnected) {
+                    self.error_message = Some(ErrorPopup::Intermittent(message.clone()));
+                }
+                self.alerts.raise(Alert {
+                    kind: AlertKind::Disconnected,
+                    message,
+                });
                 if (self.view == AppView::HeartRateView || self.is_idle_on_ble_selection())
-                    && disconnected_id == self.get_selected_device().unwrap().id
+                    && self
+                        .get_selected_device()
+                        .is_some_and(|d| d.id == disconnected_id)
                 {
                     info!(
                         "Disconnected from device {:?}, resuming BLE scan",
                         disconnected_id
                     );
-                    broadcast!(
-                        self.broadcast_tx,
-                        HeartRateStatus::default(),
-                        "Failed to send 0BPM on BLE DC"
-                    );
+                    self.send_disconnected_hr("Failed to send 0BPM on BLE DC");
                     self.ble_scan_paused.store(false, Ordering::SeqCst);
                 }
             }
@@ -16,6 +16,12 @@ pub struct TopLevelCmd {
     /// skip vrcx and auto-update prompts
     #[argh(switch)]
     pub skip_prompts: bool,
+    /// use the dummy source from the config, no HR device needed
+    #[argh(switch)]
+    pub dummy: bool,
+    /// read heart rate from stdin, one BPM or JSON reading per line
+    #[argh(switch)]
+    pub stdin: bool,
     #[argh(subcommand)]
     pub subcommands: Option<SubCommands>,
 }
@@ -26,6 +32,8 @@ pub enum SubCommands {
     Ble(BleCmd),
     WebSocket(WebSocketCmd),
     Dummy(DummyCmd),
+    Listen(ListenCmd),
+    Export(ExportCmd),
 }
 
 /// connect to a BLE device with the HR Measure characteristic
@@ -53,3 +61,30 @@ pub struct DummyCmd {
     #[argh(switch)]
     pub vhs: bool,
 }
+
+/// print any OSC messages arriving on a port, to check what's reaching VRChat
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "listen")]
+pub struct ListenCmd {
+    /// port to listen on, defaults to VRChat's (9000)
+    #[argh(option, short = 'p', default = "9000")]
+    pub port: u16,
+    /// interface to listen on, defaults to 127.0.0.1
+    #[argh(option, default = "String::from(\"127.0.0.1\")")]
+    pub host: String,
+}
+
+/// convert a logged session CSV to TCX, for uploading to training sites
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "export")]
+pub struct ExportCmd {
+    /// session CSV to convert
+    #[argh(positional)]
+    pub session: PathBuf,
+    /// where to write it, defaults to the CSV's path with .tcx
+    #[argh(option, short = 'o')]
+    pub output: Option<PathBuf>,
+    /// split into laps of this many minutes, defaults to one lap for the whole session
+    #[argh(option, default = "0")]
+    pub lap_minutes: u32,
+}
@@ -0,0 +1,263 @@
+// Plays a heartbeat sound on each beat, louder as the BPM (or zone) climbs
+// Only in builds with the "audio" feature, rodio needs ALSA on Linux
+#![cfg_attr(not(feature = "audio"), allow(dead_code))]
+
+use std::f32::consts::TAU;
+#[cfg(feature = "audio")]
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::{Receiver as BReceiver, Sender as BSender};
+#[cfg(feature = "audio")]
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::error;
+#[cfg(feature = "audio")]
+use tracing::{info, warn};
+
+use crate::app::{AppUpdate, ErrorPopup};
+use crate::broadcast;
+#[cfg(feature = "audio")]
+use crate::errors::AppError;
+use crate::heart_rate::beat_timer::BeatTimer;
+use crate::settings::AudioSettings;
+
+const SAMPLE_RATE: u32 = 44_100;
+
+/// A "lub-dub", two low thumps that fade out, for when no `sound_path` is set.
+fn heartbeat_samples(sample_rate: u32) -> Vec<f32> {
+    // Start, pitch (Hz), loudness, how quickly it fades
+    const THUMPS: [(f32, f32, f32, f32); 2] = [(0.0, 50.0, 0.9, 0.035), (0.18, 65.0, 0.6, 0.025)];
+    const LENGTH_SEC: f32 = 0.35;
+    // Fading in over a few ms, so it doesn't click
+    const FADE_IN_SEC: f32 = 0.004;
+    let len = (LENGTH_SEC * sample_rate as f32) as usize;
+    (0..len)
+        .map(|i| {
+            let t = i as f32 / sample_rate as f32;
+            THUMPS
+                .iter()
+                .filter(|(start, ..)| t >= *start)
+                .map(|&(start, pitch, loudness, decay)| {
+                    let t = t - start;
+                    let fade_in = (t / FADE_IN_SEC).min(1.0);
+                    (TAU * pitch * t).sin() * loudness * fade_in * (-t / decay).exp()
+                })
+                .sum::<f32>()
+        })
+        .collect()
+}
+
+struct AudioOutput {
+    settings: AudioSettings,
+    zone: Option<u8>,
+    beats: BeatTimer,
+}
+
+impl AudioOutput {
+    fn new(settings: AudioSettings) -> Self {
+        Self {
+            settings,
+            zone: None,
+            beats: BeatTimer::default(),
+        }
+    }
+    fn volume(&self) -> f32 {
+        let settings = &self.settings;
+        settings.volume_from.scale(
+            self.beats.bpm(),
+            self.zone,
+            (settings.min_bpm, settings.max_bpm),
+            (settings.min_volume, settings.max_volume),
+            &settings.zone_volumes,
+        )
+    }
+}
+
+#[cfg(feature = "audio")]
+mod playback {
+    use std::fs::File;
+    use std::io::BufReader;
+    use std::path::Path;
+    use std::sync::mpsc;
+    use std::sync::Arc;
+
+    use rodio::buffer::SamplesBuffer;
+    use rodio::{Decoder, OutputStream, OutputStreamHandle, Source};
+
+    use super::{heartbeat_samples, SAMPLE_RATE};
+    use crate::errors::AppError;
+
+    pub(super) struct Player {
+        handle: OutputStreamHandle,
+        // The stream isn't Send, so it's kept on its own thread until this is dropped
+        _keep_alive: mpsc::Sender<()>,
+        channels: u16,
+        sample_rate: u32,
+        samples: Arc<[f32]>,
+    }
+
+    impl Player {
+        pub(super) fn open(sound_path: &str) -> Result<Self, AppError> {
+            let (channels, sample_rate, samples) = if sound_path.is_empty() {
+                (1, SAMPLE_RATE, heartbeat_samples(SAMPLE_RATE))
+            } else {
+                let path = Path::new(sound_path);
+                let file = File::open(path).map_err(|e| AppError::ReadFile {
+                    path: path.to_owned(),
+                    source: e,
+                })?;
+                let decoder = Decoder::new_wav(BufReader::new(file))
+                    .map_err(|e| AppError::Audio(format!("Couldn't read {sound_path}: {e}")))?;
+                let channels = decoder.channels();
+                let sample_rate = decoder.sample_rate();
+                (channels, sample_rate, decoder.convert_samples().collect())
+            };
+
+            let (handle_tx, handle_rx) = mpsc::channel();
+            let (keep_alive, stop_rx) = mpsc::channel::<()>();
+            std::thread::spawn(move || match OutputStream::try_default() {
+                Ok((_stream, handle)) => {
+                    let _ = handle_tx.send(Ok(handle));
+                    // Returns once the Player's dropped
+                    let _ = stop_rx.recv();
+                }
+                Err(e) => {
+                    let _ = handle_tx.send(Err(AppError::Audio(e.to_string())));
+                }
+            });
+            let handle = handle_rx
+                .recv()
+                .map_err(|_| AppError::Audio("Audio thread stopped".into()))??;
+            Ok(Self {
+                handle,
+                _keep_alive: keep_alive,
+                channels,
+                sample_rate,
+                samples: samples.into(),
+            })
+        }
+        pub(super) fn play(&self, volume: f32) -> Result<(), AppError> {
+            let sound = SamplesBuffer::new(self.channels, self.sample_rate, self.samples.to_vec());
+            self.handle
+                .play_raw(sound.amplify(volume))
+                .map_err(|e| AppError::Audio(e.to_string()))
+        }
+    }
+}
+
+#[cfg(feature = "audio")]
+async fn rx_loop(
+    output: &mut AudioOutput,
+    broadcast_rx: &mut BReceiver<AppUpdate>,
+    cancel_token: CancellationToken,
+) -> Result<(), AppError> {
+    let player = playback::Player::open(&output.settings.sound_path)?;
+    info!("Audio: Playing heartbeats");
+    let mut play_failing = false;
+    loop {
+        let next_beat = output.beats.next_beat();
+        tokio::select! {
+            update = broadcast_rx.recv() => match update {
+                Ok(AppUpdate::HeartRateStatus(hr_status)) => {
+                    output.beats.update(&hr_status, Instant::now());
+                }
+                Ok(AppUpdate::ZoneChanged(zone)) => output.zone = Some(zone),
+                Ok(_) => {}
+                Err(RecvError::Lagged(count)) => warn!("Audio: Lagged! Missed {count} messages"),
+                Err(RecvError::Closed) => {
+                    error!("Audio: Channel closed");
+                    return Ok(());
+                }
+            },
+            _ = tokio::time::sleep_until(next_beat.unwrap_or_else(Instant::now)), if next_beat.is_some() => {
+                if !output.beats.beat(Instant::now()) {
+                    continue;
+                }
+                // The output device may come back (i.e. headphones plugged back in), so just noting it
+                match player.play(output.volume()) {
+                    Ok(()) => play_failing = false,
+                    Err(e) if !play_failing => {
+                        warn!("Audio: Couldn't play beat: {e}");
+                        play_failing = true;
+                    }
+                    Err(_) => {}
+                }
+            }
+            _ = cancel_token.cancelled() => {
+                info!("Shutting down Audio thread!");
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Plays a heartbeat sound with each beat.
+#[cfg(feature = "audio")]
+pub async fn audio_thread(
+    mut broadcast_rx: BReceiver<AppUpdate>,
+    broadcast_tx: BSender<AppUpdate>,
+    audio_settings: AudioSettings,
+    cancel_token: CancellationToken,
+) {
+    let mut output = AudioOutput::new(audio_settings);
+    if let Err(e) = rx_loop(&mut output, &mut broadcast_rx, cancel_token).await {
+        error!("Audio error: {e}");
+        broadcast!(broadcast_tx, ErrorPopup::detailed("Audio error", e));
+    }
+}
+
+#[cfg(not(feature = "audio"))]
+pub async fn audio_thread(
+    _broadcast_rx: BReceiver<AppUpdate>,
+    broadcast_tx: BSender<AppUpdate>,
+    _audio_settings: AudioSettings,
+    _cancel_token: CancellationToken,
+) {
+    let message =
+        "This build doesn't include heartbeat audio, it needs to be built with the \"audio\" feature.";
+    error!("{message}");
+    broadcast!(broadcast_tx, ErrorPopup::UserMustDismiss(message.into()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heart_rate::HeartRateStatus;
+    use crate::settings::BeatStrength;
+    use tokio::time::Instant;
+
+    #[test]
+    fn built_in_sound() {
+        let samples = heartbeat_samples(SAMPLE_RATE);
+        assert_eq!(samples.len(), (0.35 * SAMPLE_RATE as f32) as usize);
+        assert!(samples.iter().all(|sample| sample.abs() <= 1.0));
+        // Starts silent rather than with a click, and fades out by the end
+        assert_eq!(samples[0], 0.0);
+        assert!(samples[samples.len() - 1].abs() < 0.01);
+        // The "dub" is there
+        let dub = (0.18 * SAMPLE_RATE as f32) as usize;
+        assert!(samples[dub..dub + 1000]
+            .iter()
+            .any(|sample| sample.abs() > 0.3));
+    }
+    #[test]
+    fn volume_follows_bpm_or_zone() {
+        let mut audio = AudioOutput::new(AudioSettings {
+            volume_from: BeatStrength::Bpm,
+            min_bpm: 60,
+            max_bpm: 160,
+            min_volume: 0.5,
+            max_volume: 1.0,
+            zone_volumes: vec![0.2, 0.8],
+            ..Default::default()
+        });
+        let status = HeartRateStatus {
+            heart_rate_bpm: 110,
+            ..Default::default()
+        };
+        audio.beats.update(&status, Instant::now());
+        assert!((audio.volume() - 0.75).abs() < 0.001);
+        audio.settings.volume_from = BeatStrength::Zone;
+        audio.zone = Some(3);
+        assert_eq!(audio.volume(), 0.8);
+    }
+}
@@ -0,0 +1,96 @@
+use std::time::{Duration, Instant};
+
+/// Exponential backoff between attempts (BLE reconnects, OSC probes, client reconnects),
+/// giving up once `window` has passed without one succeeding.
+#[derive(Debug)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    // None retries forever
+    window: Option<Duration>,
+    attempt: u32,
+    failing_since: Option<Instant>,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration, window: Option<Duration>) -> Self {
+        Self {
+            initial,
+            max,
+            window,
+            attempt: 0,
+            failing_since: None,
+        }
+    }
+    pub fn window(&self) -> Option<Duration> {
+        self.window
+    }
+    /// Attempts so far since the last reset.
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+    fn delay_for(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt).unwrap_or(u32::MAX);
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+    /// How long to wait before the next attempt, or None if we've been failing for too long.
+    pub fn next_delay(&mut self, now: Instant) -> Option<Duration> {
+        let failing_since = *self.failing_since.get_or_insert(now);
+        let delay = self.delay_for(self.attempt);
+        if let Some(window) = self.window {
+            if now + delay > failing_since + window {
+                return None;
+            }
+        }
+        self.attempt = self.attempt.saturating_add(1);
+        Some(delay)
+    }
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+        self.failing_since = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backoff(window: Option<u64>) -> Backoff {
+        Backoff::new(
+            Duration::from_secs(2),
+            Duration::from_secs(30),
+            window.map(Duration::from_secs),
+        )
+    }
+
+    #[test]
+    fn doubles_up_to_max() {
+        let mut backoff = backoff(None);
+        let now = Instant::now();
+        let delays: Vec<u64> = (0..6)
+            .map(|_| backoff.next_delay(now).unwrap().as_secs())
+            .collect();
+        assert_eq!(delays, vec![2, 4, 8, 16, 30, 30]);
+        // Shouldn't overflow after a long time
+        assert_eq!(backoff.delay_for(u32::MAX), Duration::from_secs(30));
+    }
+    #[test]
+    fn gives_up_after_window() {
+        let mut backoff = backoff(Some(10));
+        let start = Instant::now();
+        assert!(backoff.next_delay(start).is_some());
+        assert!(backoff.next_delay(start + Duration::from_secs(2)).is_some());
+        // 6 + 8 would land past the 10 second window
+        assert!(backoff.next_delay(start + Duration::from_secs(6)).is_none());
+    }
+    #[test]
+    fn reset_after_connecting() {
+        let mut backoff = backoff(Some(10));
+        let start = Instant::now();
+        backoff.next_delay(start);
+        backoff.next_delay(start);
+        backoff.reset();
+        let later = start + Duration::from_secs(60);
+        assert_eq!(backoff.next_delay(later), Some(Duration::from_secs(2)));
+    }
+}
@@ -13,11 +13,21 @@ pub enum AppError {
         path: PathBuf,
         source: std::io::Error,
     },
+    #[error("Failed to read file \"{path}\": {source}")]
+    ReadFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
     #[error("Failed to write to file \"{path}\": {source}")]
     WriteFile {
         path: PathBuf,
         source: std::io::Error,
     },
+    #[error("Failed to bind OSC socket to \"{addr}\": {source}")]
+    OscBind {
+        addr: std::net::SocketAddr,
+        source: std::io::Error,
+    },
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
     #[error("Error parsing IP Address: {0}")]
@@ -28,6 +38,8 @@ pub enum AppError {
     Osc(#[from] rosc::OscError),
     #[error("Websocket Error: {0}")]
     Ws(#[from] tokio_websockets::Error),
+    #[error("TLS Error: {0}")]
+    Tls(#[from] tokio_native_tls::native_tls::Error),
     #[error("Bluetooth Error: {0}")]
     Bt(#[from] btleplug::Error),
     #[error("TOML Write Error: {0}")]
@@ -36,6 +48,8 @@ pub enum AppError {
     TomlDe(#[from] toml::de::Error),
     #[error("CSV Error: {0}")]
     Csv(#[from] csv_async::Error),
+    #[error("CSV Read Error: {0}")]
+    CsvRead(#[from] csv::Error),
     #[error("Parse Int Error: {0}")]
     ParseIntError(#[from] std::num::ParseIntError),
     #[error("Updater Error: {0}")]
@@ -46,10 +60,33 @@ pub enum AppError {
     Reqwest(#[from] reqwest::Error),
     #[error("Prometheus Error: {0}")]
     Prometheus(#[from] prometheus::Error),
+    #[error("JSON Error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[cfg(feature = "ant")]
+    #[error("USB Error: {0}")]
+    Usb(#[from] rusb::Error),
+    #[cfg(feature = "serial")]
+    #[error("Serial Error: {0}")]
+    Serial(#[from] serialport::Error),
+    #[cfg(feature = "sqlite")]
+    #[error("Session Database Error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[cfg(all(
+        any(feature = "gatt_server", feature = "pairing_agent"),
+        target_os = "linux"
+    ))]
+    #[error("D-Bus Error: {0}")]
+    DBus(#[from] dbus::Error),
+    #[error("Notification Error: {0}")]
+    Notify(#[from] notify_rust::error::Error),
+    #[error("mDNS Error: {0}")]
+    Mdns(#[from] mdns_sd::Error),
     #[error("ASCII characters (32-127) only")]
     InvalidHeaderValue(#[from] http::header::InvalidHeaderValue),
     #[error("ASCII characters (32-127) only")]
     InvalidHeaderName(#[from] http::header::InvalidHeaderName),
+    #[error("Invalid URI: {0}")]
+    InvalidUri(#[from] http::uri::InvalidUri),
     // My errors
     #[error("Failed to get working directory")]
     WorkDir,
@@ -57,8 +94,16 @@ pub enum AppError {
     OscPrefix(String),
     #[error("Invalid OSC Address: \"{0}\" - \"{1}\"")]
     OscAddress(String, String),
+    #[error("Couldn't resolve OSC address \"{0}\": {1}")]
+    OscResolve(String, String),
+    #[error("Invalid scan name filter: {0}")]
+    ScanFilter(#[from] regex::Error),
+    #[error("Invalid custom OSC param \"{name}\": {reason}")]
+    OscExpression { name: String, reason: String },
     #[error("Failed to get event")]
     NoEvent,
+    #[error("No terminal attached")]
+    NoTerminal,
     #[error("Bad HTTP Status: \"{0}\"")]
     HttpStatus(u16),
     #[error("Update checksum missing")]
@@ -73,6 +118,80 @@ pub enum AppError {
     MissingDelimiter,
     #[error("Empty IP Address")]
     MissingIpAddr,
+    #[error("Websocket TLS needs both a certificate and a key")]
+    WebsocketTlsIncomplete,
+    #[error("No ANT USB stick found")]
+    AntStickMissing,
+    #[error("No serial port set")]
+    SerialPortMissing,
+    #[error("No URL set to poll")]
+    HttpPollUrlMissing,
+    #[error("No MQTT topic set")]
+    MqttTopicMissing,
+    #[error("MQTT broker refused connection: {0}")]
+    MqttRefused(&'static str),
+    #[error("MQTT broker refused subscription to \"{0}\"")]
+    MqttSubscribe(String),
+    #[error("No Pulsoid token set")]
+    PulsoidTokenMissing,
+    #[error("Pulsoid rejected the token, it may have expired or be missing the data:heart_rate:read scope")]
+    PulsoidUnauthorized,
+    #[error("No HypeRate API key set")]
+    HypeRateKeyMissing,
+    #[error("No HypeRate session ID set")]
+    HypeRateSessionMissing,
+    #[error("HypeRate rejected the API key")]
+    HypeRateUnauthorized,
+    #[error("No Stromno widget ID set")]
+    StromnoWidgetMissing,
+    #[error("Stromno didn't recognize the widget ID")]
+    StromnoUnauthorized,
+    #[error("OBS asked for a password, but none is set")]
+    ObsPasswordMissing,
+    #[error("OBS rejected the password")]
+    ObsUnauthorized,
+    #[error("No Streamer.bot action set")]
+    StreamerbotActionMissing,
+    #[error("Streamer.bot asked for a password, but none is set")]
+    StreamerbotPasswordMissing,
+    #[error("Streamer.bot rejected the password")]
+    StreamerbotUnauthorized,
+    #[error("Twitch needs a username, OAuth token and channel set")]
+    TwitchSettingsMissing,
+    #[error("Twitch rejected the OAuth token, it may have expired or be missing the chat:read and chat:edit scopes")]
+    TwitchUnauthorized,
+    #[error("Intiface refused the connection: {0}")]
+    IntifaceRejected(String),
+    #[error("No WLED address set")]
+    WledAddressMissing,
+    #[error("Invalid WLED palette color \"{0}\", expected hex like \"ff0000\"")]
+    WledColor(String),
+    #[error("Audio Error: {0}")]
+    Audio(String),
+    #[error("No Fitbit client ID set")]
+    FitbitClientIdMissing,
+    #[error("Fitbit sign in failed: {0}")]
+    FitbitAuth(String),
+    #[error("Fitbit rejected the access token")]
+    FitbitUnauthorized,
+    #[error("No readings in \"{0}\"")]
+    EmptySession(PathBuf),
+    #[error("This build doesn't include the SQLite session store, it needs to be built with the \"sqlite\" feature")]
+    NoSqlite,
+    #[error("The session database was made by a newer version (schema {0})")]
+    SessionDbTooNew(u32),
+    #[error("InfluxDB needs an org and bucket set")]
+    InfluxSettingsMissing,
+    #[error("Invalid InfluxDB URL: {0}")]
+    InfluxUrl(String),
+    #[error("InfluxDB rejected the token, it needs write access to the bucket")]
+    InfluxUnauthorized,
+    #[error("No webhook URL set")]
+    MissingWebhookUrl,
+    #[error("No UDP output target set")]
+    UdpOutputTargetMissing,
+    #[error("Smart plug error: {0}")]
+    SmartPlug(String),
     // Because lnk::Error doesn't impl Display yet
     #[error("Error parsing shortcut: {0}")]
     Lnk(String),
@@ -0,0 +1,187 @@
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver as BReceiver;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info};
+
+use crate::app::AppUpdate;
+use crate::errors::AppError;
+use crate::journal::{self, Severity, Subsystem};
+use crate::settings::{FanBackend, FanSettings};
+
+mod switches;
+
+/// Decides when the fan should be on, from heart rate zone changes.
+///
+/// Turns on at `on_zone` and off at `off_zone`, holding its state in between,
+/// and won't switch again until `min_switch_sec` has passed.
+struct FanState {
+    on_zone: u8,
+    off_zone: u8,
+    min_switch: Duration,
+    // None until the first zone comes in, so we don't switch it off on startup
+    wanted: Option<bool>,
+    is_on: Option<bool>,
+    last_switch: Option<Instant>,
+}
+
+impl FanState {
+    fn new(fan_settings: &FanSettings) -> Self {
+        Self {
+            on_zone: fan_settings.on_zone,
+            // Needs to be below on_zone to have any gap at all
+            off_zone: fan_settings
+                .off_zone
+                .min(fan_settings.on_zone.saturating_sub(1)),
+            min_switch: Duration::from_secs(fan_settings.min_switch_sec as u64),
+            wanted: None,
+            is_on: None,
+            last_switch: None,
+        }
+    }
+    fn zone_changed(&mut self, zone: u8) {
+        if zone >= self.on_zone {
+            self.wanted = Some(true);
+        } else if zone <= self.off_zone {
+            self.wanted = Some(false);
+        }
+    }
+    /// Returns the state to switch to, if it's time to.
+    fn poll(&mut self, now: Instant) -> Option<bool> {
+        let wanted = self.wanted?;
+        if self.is_on == Some(wanted) {
+            return None;
+        }
+        if matches!(self.last_switch, Some(at) if now - at < self.min_switch) {
+            return None;
+        }
+        self.is_on = Some(wanted);
+        self.last_switch = Some(now);
+        Some(wanted)
+    }
+}
+
+struct FanActor {
+    broadcast_rx: BReceiver<AppUpdate>,
+    fan_settings: FanSettings,
+    state: FanState,
+    client: reqwest::Client,
+}
+
+impl FanActor {
+    async fn switch(&self, on: bool) -> Result<(), AppError> {
+        let fan_settings = &self.fan_settings;
+        match fan_settings.backend {
+            FanBackend::HomeAssistant => {
+                switches::home_assistant(
+                    &self.client,
+                    &fan_settings.ha_url,
+                    &fan_settings.ha_token,
+                    &fan_settings.ha_entity_id,
+                    on,
+                )
+                .await
+            }
+            FanBackend::Kasa => switches::kasa(&fan_settings.kasa_address, on).await,
+        }
+    }
+    async fn apply(&mut self) {
+        let Some(on) = self.state.poll(Instant::now()) else {
+            return;
+        };
+        let message = format!("Turning fan {}", if on { "on" } else { "off" });
+        info!("{message}");
+        journal::record(Severity::Info, Subsystem::Fan, &message);
+        // Tried again once `min_switch_sec` has passed
+        if let Err(e) = self.switch(on).await {
+            error!("Failed to switch fan: {e}");
+            journal::record(
+                Severity::Warning,
+                Subsystem::Fan,
+                &format!("Failed to switch fan: {e}"),
+            );
+            self.state.is_on = None;
+        }
+    }
+    async fn rx_loop(&mut self, cancel_token: CancellationToken) {
+        let mut pending_check = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            tokio::select! {
+                update = self.broadcast_rx.recv() => match update {
+                    Ok(AppUpdate::ZoneChanged(zone)) => {
+                        debug!("Fan: Zone {zone}");
+                        self.state.zone_changed(zone);
+                        self.apply().await;
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                },
+                // Picks up switches held back by `min_switch_sec`
+                _ = pending_check.tick() => self.apply().await,
+                _ = cancel_token.cancelled() => break,
+            }
+        }
+        if self.fan_settings.turn_off_on_exit && self.state.is_on != Some(false) {
+            info!("Turning fan off before exiting");
+            if let Err(e) = self.switch(false).await {
+                error!("Failed to turn fan off: {e}");
+            }
+        }
+        info!("Shutting down Fan thread!");
+    }
+}
+
+/// Switches a fan (or anything on a smart plug) on and off as the heart rate zone changes.
+pub async fn fan_thread(
+    broadcast_rx: BReceiver<AppUpdate>,
+    fan_settings: FanSettings,
+    cancel_token: CancellationToken,
+) {
+    info!("Fan thread started, using {:?}", fan_settings.backend);
+    let mut actor = FanActor {
+        broadcast_rx,
+        state: FanState::new(&fan_settings),
+        fan_settings,
+        client: reqwest::Client::new(),
+    };
+    actor.rx_loop(cancel_token).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(min_switch_sec: u16) -> FanState {
+        FanState::new(&FanSettings {
+            on_zone: 3,
+            off_zone: 1,
+            min_switch_sec,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn holds_between_zones() {
+        let mut fan = state(0);
+        let now = Instant::now();
+        fan.zone_changed(2);
+        assert_eq!(fan.poll(now), None);
+        fan.zone_changed(3);
+        assert_eq!(fan.poll(now), Some(true));
+        fan.zone_changed(2);
+        assert_eq!(fan.poll(now), None);
+        fan.zone_changed(1);
+        assert_eq!(fan.poll(now), Some(false));
+    }
+    #[test]
+    fn waits_out_min_switch() {
+        let mut fan = state(30);
+        let now = Instant::now();
+        fan.zone_changed(4);
+        assert_eq!(fan.poll(now), Some(true));
+        fan.zone_changed(0);
+        assert_eq!(fan.poll(now + Duration::from_secs(10)), None);
+        assert_eq!(fan.poll(now + Duration::from_secs(30)), Some(false));
+    }
+}
@@ -0,0 +1,104 @@
+use serde_json::json;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+use crate::errors::AppError;
+
+const KASA_PORT: u16 = 9999;
+const KASA_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Calls Home Assistant's generic `homeassistant.turn_on`/`turn_off` service,
+/// which works for fans, switches, lights, etc.
+///
+/// <https://developers.home-assistant.io/docs/api/rest/>
+pub async fn home_assistant(
+    client: &reqwest::Client,
+    url: &str,
+    token: &str,
+    entity_id: &str,
+    on: bool,
+) -> Result<(), AppError> {
+    let service = if on { "turn_on" } else { "turn_off" };
+    let url = format!(
+        "{}/api/services/homeassistant/{service}",
+        url.trim_end_matches('/')
+    );
+    client
+        .post(url)
+        .bearer_auth(token)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(json!({ "entity_id": entity_id }).to_string())
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// Sets the relay of a TP-Link Kasa plug over its local protocol.
+pub async fn kasa(address: &str, on: bool) -> Result<(), AppError> {
+    let request = json!({ "system": { "set_relay_state": { "state": u8::from(on) } } });
+    let response = timeout(KASA_TIMEOUT, kasa_request(address, &request.to_string()))
+        .await
+        .map_err(|_| AppError::SmartPlug("Timed out".into()))??;
+    let response: serde_json::Value = serde_json::from_str(&response)?;
+    match response["system"]["set_relay_state"]["err_code"].as_i64() {
+        Some(0) => Ok(()),
+        _ => Err(AppError::SmartPlug(format!("Unexpected reply: {response}"))),
+    }
+}
+
+async fn kasa_request(address: &str, request: &str) -> Result<String, AppError> {
+    let mut stream = if address.contains(':') {
+        TcpStream::connect(address).await?
+    } else {
+        TcpStream::connect((address, KASA_PORT)).await?
+    };
+    let payload = kasa_encrypt(request.as_bytes());
+    stream.write_u32(payload.len() as u32).await?;
+    stream.write_all(&payload).await?;
+    let len = stream.read_u32().await? as usize;
+    let mut reply = vec![0; len];
+    stream.read_exact(&mut reply).await?;
+    Ok(String::from_utf8_lossy(&kasa_decrypt(&reply)).into_owned())
+}
+
+// XOR autokey, each byte is the key for the next
+const KASA_INITIAL_KEY: u8 = 171;
+
+fn kasa_encrypt(plain: &[u8]) -> Vec<u8> {
+    let mut key = KASA_INITIAL_KEY;
+    plain
+        .iter()
+        .map(|byte| {
+            key ^= byte;
+            key
+        })
+        .collect()
+}
+
+fn kasa_decrypt(cipher: &[u8]) -> Vec<u8> {
+    let mut key = KASA_INITIAL_KEY;
+    cipher
+        .iter()
+        .map(|&byte| {
+            let plain = key ^ byte;
+            key = byte;
+            plain
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kasa_cipher() {
+        let plain = br#"{"system":{"get_sysinfo":{}}}"#;
+        let cipher = kasa_encrypt(plain);
+        assert_eq!(&cipher[..4], &[0xd0, 0xf2, 0x81, 0xf8]);
+        assert_eq!(kasa_decrypt(&cipher), plain);
+    }
+}
@@ -0,0 +1,75 @@
+use std::fmt::Display;
+use std::time::Duration;
+
+use crate::settings::FormatSettings;
+
+/// Number formatting shared by everything that shows numbers to people (TUI, text file, browser overlay).
+///
+/// Machine-read outputs (CSV, JSON, OSC, Prometheus) stay as-is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumberFormat {
+    decimal_separator: char,
+    bpm_suffix: String,
+    rr_suffix: String,
+}
+
+impl NumberFormat {
+    pub fn new(format_settings: &FormatSettings) -> Self {
+        Self {
+            // Only the first character is used, empty falls back to a dot
+            decimal_separator: format_settings
+                .decimal_separator
+                .chars()
+                .next()
+                .unwrap_or('.'),
+            bpm_suffix: format_settings.bpm_suffix.clone(),
+            rr_suffix: format_settings.rr_suffix.clone(),
+        }
+    }
+    pub fn decimal(&self, value: f64, places: usize) -> String {
+        let formatted = format!("{value:.places$}");
+        if self.decimal_separator == '.' {
+            formatted
+        } else {
+            formatted.replace('.', &self.decimal_separator.to_string())
+        }
+    }
+    /// Separator for lists of decimals, so they don't blend together with a decimal comma.
+    pub fn list_separator(&self) -> &'static str {
+        if self.decimal_separator == ',' {
+            "; "
+        } else {
+            ", "
+        }
+    }
+    pub fn bpm(&self, bpm: impl Display) -> String {
+        format!("{bpm}{}", self.bpm_suffix)
+    }
+    /// RR interval in whole milliseconds.
+    pub fn rr_ms(&self, rr: Duration) -> String {
+        format!("{}{}", rr.as_millis(), self.rr_suffix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locale_and_suffixes() {
+        let format = NumberFormat::new(&FormatSettings {
+            decimal_separator: ",".into(),
+            bpm_suffix: " bpm".into(),
+            rr_suffix: " ms".into(),
+        });
+        assert_eq!(format.decimal(72.46, 1), "72,5");
+        assert_eq!(format.decimal(0.75, 3), "0,750");
+        assert_eq!(format.list_separator(), "; ");
+        assert_eq!(format.bpm(72), "72 bpm");
+        assert_eq!(format.rr_ms(Duration::from_millis(812)), "812 ms");
+
+        let format = NumberFormat::new(&FormatSettings::default());
+        assert_eq!(format.decimal(72.46, 1), "72.5");
+        assert_eq!(format.bpm(72), "72");
+    }
+}
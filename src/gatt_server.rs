@@ -0,0 +1,385 @@
+// Rebroadcasts the heart rate as a standard BLE heart rate monitor, for treadmills, bike computers, Zwift, etc
+// btleplug can only be a central, so this talks to BlueZ over D-Bus directly
+// Only in Linux builds with the "gatt_server" feature
+#![cfg_attr(
+    not(all(feature = "gatt_server", target_os = "linux")),
+    allow(dead_code)
+)]
+
+use std::time::Duration;
+use tokio::sync::broadcast::{Receiver as BReceiver, Sender as BSender};
+use tokio_util::sync::CancellationToken;
+use tracing::error;
+
+use crate::app::{AppUpdate, ErrorPopup};
+use crate::broadcast;
+use crate::settings::GattServerSettings;
+
+// Keeps a notification inside the default 20 byte ATT payload, even with a 16-bit BPM
+const MAX_RR_INTERVALS: usize = 8;
+
+/// Packs a reading into the Heart Rate Measurement characteristic's format, undoing `parse_hrm`.
+///
+/// Only the newest RR intervals are kept if there's more than fit in one notification.
+fn encode_hrm(bpm: u16, rr_intervals: &[Duration]) -> Vec<u8> {
+    let mut flags = 0u8;
+    let mut data = vec![0];
+    match u8::try_from(bpm) {
+        Ok(bpm) => data.push(bpm),
+        Err(_) => {
+            flags |= 0b1;
+            data.extend_from_slice(&bpm.to_le_bytes());
+        }
+    }
+    if !rr_intervals.is_empty() {
+        flags |= 0b1_0000;
+        let skip = rr_intervals.len().saturating_sub(MAX_RR_INTERVALS);
+        for rr in &rr_intervals[skip..] {
+            // In 1/1024ths of a second
+            let rr = (rr.as_secs_f64() * 1024.0).round().min(u16::MAX as f64) as u16;
+            data.extend_from_slice(&rr.to_le_bytes());
+        }
+    }
+    data[0] = flags;
+    data
+}
+
+#[cfg(all(feature = "gatt_server", target_os = "linux"))]
+mod peripheral {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex, PoisonError};
+    use std::time::Duration;
+
+    use dbus::arg::{PropMap, RefArg, Variant};
+    use dbus::channel::{MatchingReceiver, Sender};
+    use dbus::message::{MatchRule, SignalArgs};
+    use dbus::nonblock::stdintf::org_freedesktop_dbus::PropertiesPropertiesChanged;
+    use dbus::nonblock::{Proxy, SyncConnection};
+    use dbus::Path;
+    use dbus_crossroads::Crossroads;
+
+    use crate::errors::AppError;
+
+    const APP_PATH: &str = "/io/github/nullstalgia/iron_heart/gatt";
+    const SERVICE_PATH: &str = "/io/github/nullstalgia/iron_heart/gatt/service0";
+    const MEASUREMENT_PATH: &str = "/io/github/nullstalgia/iron_heart/gatt/service0/char0";
+    const ADVERTISEMENT_PATH: &str = "/io/github/nullstalgia/iron_heart/advertisement0";
+
+    const HEART_RATE_SERVICE: &str = "0000180d-0000-1000-8000-00805f9b34fb";
+    const HEART_RATE_MEASUREMENT: &str = "00002a37-0000-1000-8000-00805f9b34fb";
+    const CHARACTERISTIC_IFACE: &str = "org.bluez.GattCharacteristic1";
+    const GATT_MANAGER: &str = "org.bluez.GattManager1";
+    const ADVERTISING_MANAGER: &str = "org.bluez.LEAdvertisingManager1";
+
+    const DBUS_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// The Heart Rate Measurement's latest value, and whether anyone's subscribed to it.
+    #[derive(Default)]
+    struct Measurement {
+        value: Mutex<Vec<u8>>,
+        notifying: AtomicBool,
+    }
+
+    impl Measurement {
+        fn value(&self) -> Vec<u8> {
+            self.value
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone()
+        }
+    }
+
+    /// Registered with BlueZ until `unregister`, or until our D-Bus connection closes.
+    pub(super) struct Peripheral {
+        conn: Arc<SyncConnection>,
+        adapter_path: String,
+        measurement: Arc<Measurement>,
+    }
+
+    impl Peripheral {
+        /// Serves the objects BlueZ asks about on `conn`, then registers the service and starts advertising.
+        pub(super) async fn register(
+            conn: Arc<SyncConnection>,
+            adapter: &str,
+            local_name: &str,
+        ) -> Result<Self, AppError> {
+            let measurement = Arc::new(Measurement::default());
+            let mut cr = objects(local_name.to_owned(), measurement.clone());
+            conn.start_receive(
+                MatchRule::new_method_call(),
+                Box::new(move |msg, conn| {
+                    let _ = cr.handle_message(msg, conn);
+                    true
+                }),
+            );
+
+            let peripheral = Self {
+                conn,
+                adapter_path: format!("/org/bluez/{adapter}"),
+                measurement,
+            };
+            let adapter = peripheral.adapter();
+            // BlueZ reads the objects back from us before either of these return
+            adapter
+                .method_call::<(), _, _, _>(
+                    GATT_MANAGER,
+                    "RegisterApplication",
+                    (Path::from(APP_PATH), PropMap::new()),
+                )
+                .await?;
+            adapter
+                .method_call::<(), _, _, _>(
+                    ADVERTISING_MANAGER,
+                    "RegisterAdvertisement",
+                    (Path::from(ADVERTISEMENT_PATH), PropMap::new()),
+                )
+                .await?;
+            Ok(peripheral)
+        }
+
+        fn adapter(&self) -> Proxy<'_, Arc<SyncConnection>> {
+            Proxy::new(
+                "org.bluez",
+                self.adapter_path.as_str(),
+                DBUS_TIMEOUT,
+                self.conn.clone(),
+            )
+        }
+
+        /// Stores the new value for reads, and notifies subscribers of it.
+        pub(super) fn update(&self, value: Vec<u8>) -> Result<(), AppError> {
+            *self
+                .measurement
+                .value
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner) = value.clone();
+            if !self.measurement.notifying.load(Ordering::Relaxed) {
+                return Ok(());
+            }
+            let mut changed_properties = PropMap::new();
+            changed_properties.insert("Value".into(), Variant(Box::new(value) as Box<dyn RefArg>));
+            let signal = PropertiesPropertiesChanged {
+                interface_name: CHARACTERISTIC_IFACE.into(),
+                changed_properties,
+                invalidated_properties: Vec::new(),
+            };
+            self.conn
+                .send(signal.to_emit_message(&Path::from(MEASUREMENT_PATH)))
+                .map_err(|_| dbus::Error::new_failed("Couldn't send notification").into())
+                .map(|_| ())
+        }
+
+        /// Stops advertising and removes the service, BlueZ also does this itself if we disconnect.
+        pub(super) async fn unregister(&self) {
+            let adapter = self.adapter();
+            let _ = adapter
+                .method_call::<(), _, _, _>(
+                    ADVERTISING_MANAGER,
+                    "UnregisterAdvertisement",
+                    (Path::from(ADVERTISEMENT_PATH),),
+                )
+                .await;
+            let _ = adapter
+                .method_call::<(), _, _, _>(
+                    GATT_MANAGER,
+                    "UnregisterApplication",
+                    (Path::from(APP_PATH),),
+                )
+                .await;
+        }
+    }
+
+    /// The Heart Rate Service, its Measurement characteristic and the advertisement, as BlueZ expects them.
+    fn objects(local_name: String, measurement: Arc<Measurement>) -> Crossroads {
+        let mut cr = Crossroads::new();
+        let service = cr.register("org.bluez.GattService1", |b| {
+            b.property("UUID")
+                .get(|_, _: &mut ()| Ok(HEART_RATE_SERVICE.to_owned()));
+            b.property("Primary").get(|_, _: &mut ()| Ok(true));
+        });
+        let characteristic = cr.register(CHARACTERISTIC_IFACE, |b| {
+            b.property("UUID")
+                .get(|_, _: &mut Arc<Measurement>| Ok(HEART_RATE_MEASUREMENT.to_owned()));
+            b.property("Service")
+                .get(|_, _: &mut Arc<Measurement>| Ok(Path::from(SERVICE_PATH)));
+            b.property("Flags")
+                .get(|_, _: &mut Arc<Measurement>| Ok(vec!["notify".to_owned()]));
+            // Changes are signalled by hand in `update`, and only while notifying
+            b.property("Value")
+                .emits_changed_false()
+                .get(|_, measurement: &mut Arc<Measurement>| Ok(measurement.value()));
+            b.method(
+                "ReadValue",
+                ("options",),
+                ("value",),
+                |_, measurement: &mut Arc<Measurement>, (_,): (PropMap,)| {
+                    Ok((measurement.value(),))
+                },
+            );
+            b.method(
+                "StartNotify",
+                (),
+                (),
+                |_, measurement: &mut Arc<Measurement>, ()| {
+                    measurement.notifying.store(true, Ordering::Relaxed);
+                    Ok(())
+                },
+            );
+            b.method(
+                "StopNotify",
+                (),
+                (),
+                |_, measurement: &mut Arc<Measurement>, ()| {
+                    measurement.notifying.store(false, Ordering::Relaxed);
+                    Ok(())
+                },
+            );
+        });
+        let advertisement = cr.register("org.bluez.LEAdvertisement1", |b| {
+            b.property("Type")
+                .get(|_, _: &mut String| Ok("peripheral".to_owned()));
+            b.property("ServiceUUIDs")
+                .get(|_, _: &mut String| Ok(vec![HEART_RATE_SERVICE.to_owned()]));
+            b.property("LocalName")
+                .get(|_, local_name: &mut String| Ok(local_name.clone()));
+            b.method("Release", (), (), |_, _: &mut String, ()| Ok(()));
+        });
+        let object_manager = cr.object_manager();
+        cr.insert(APP_PATH, &[object_manager], ());
+        cr.insert(SERVICE_PATH, &[service], ());
+        cr.insert(MEASUREMENT_PATH, &[characteristic], measurement);
+        cr.insert(ADVERTISEMENT_PATH, &[advertisement], local_name);
+        cr
+    }
+}
+
+#[cfg(all(feature = "gatt_server", target_os = "linux"))]
+async fn rx_loop(
+    broadcast_rx: &mut BReceiver<AppUpdate>,
+    gatt_settings: &GattServerSettings,
+    cancel_token: CancellationToken,
+) -> Result<(), crate::errors::AppError> {
+    use tokio::sync::broadcast::error::RecvError;
+    use tracing::{info, warn};
+
+    let (resource, conn) = dbus_tokio::connection::new_system_sync()?;
+    // Handles everything coming in and out, only finishes if the connection's lost
+    let mut connection = tokio::spawn(resource);
+    let result = async {
+        let peripheral = tokio::select! {
+            peripheral = peripheral::Peripheral::register(
+                conn,
+                &gatt_settings.adapter,
+                &gatt_settings.local_name,
+            ) => peripheral?,
+            _ = cancel_token.cancelled() => return Ok(()),
+        };
+        info!(
+            "GATT server: Advertising as \"{}\" on {}",
+            gatt_settings.local_name, gatt_settings.adapter
+        );
+        let mut update_failing = false;
+        loop {
+            tokio::select! {
+                update = broadcast_rx.recv() => match update {
+                    // Nothing to pass along while the source is disconnected
+                    Ok(AppUpdate::HeartRateStatus(hr_status)) if hr_status.heart_rate_bpm > 0 => {
+                        let value = encode_hrm(hr_status.heart_rate_bpm, &hr_status.rr_intervals);
+                        match peripheral.update(value) {
+                            Ok(()) => update_failing = false,
+                            Err(e) if !update_failing => {
+                                warn!("GATT server: Couldn't notify: {e}");
+                                update_failing = true;
+                            }
+                            Err(_) => {}
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(count)) => warn!("GATT server: Lagged! Missed {count} messages"),
+                    Err(RecvError::Closed) => {
+                        error!("GATT server: Channel closed");
+                        break;
+                    }
+                },
+                lost = &mut connection => {
+                    let reason = match lost {
+                        Ok(e) => e.to_string(),
+                        Err(e) => e.to_string(),
+                    };
+                    return Err(dbus::Error::new_failed(&format!("Lost connection to D-Bus: {reason}")).into());
+                }
+                _ = cancel_token.cancelled() => {
+                    info!("Shutting down GATT server thread!");
+                    break;
+                }
+            }
+        }
+        peripheral.unregister().await;
+        Ok(())
+    }
+    .await;
+    connection.abort();
+    result
+}
+
+/// Advertises as a BLE heart rate monitor and notifies subscribers of each reading.
+#[cfg(all(feature = "gatt_server", target_os = "linux"))]
+pub async fn gatt_server_thread(
+    mut broadcast_rx: BReceiver<AppUpdate>,
+    broadcast_tx: BSender<AppUpdate>,
+    gatt_settings: GattServerSettings,
+    cancel_token: CancellationToken,
+) {
+    if let Err(e) = rx_loop(&mut broadcast_rx, &gatt_settings, cancel_token).await {
+        error!("GATT server error: {e}");
+        broadcast!(
+            broadcast_tx,
+            ErrorPopup::detailed("Couldn't act as a BLE heart rate monitor", e)
+        );
+    }
+}
+
+#[cfg(not(all(feature = "gatt_server", target_os = "linux")))]
+pub async fn gatt_server_thread(
+    _broadcast_rx: BReceiver<AppUpdate>,
+    broadcast_tx: BSender<AppUpdate>,
+    _gatt_settings: GattServerSettings,
+    _cancel_token: CancellationToken,
+) {
+    let message = "This build can't act as a BLE heart rate monitor, it needs to be built on Linux with the \"gatt_server\" feature.";
+    error!("{message}");
+    broadcast!(broadcast_tx, ErrorPopup::UserMustDismiss(message.into()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heart_rate::measurement::parse_hrm;
+
+    #[test]
+    fn encodes_what_parse_hrm_reads() {
+        let rr_intervals = [Duration::from_millis(750), Duration::from_millis(1000)];
+        let data = encode_hrm(80, &rr_intervals);
+        assert_eq!(data.len(), 1 + 1 + 4);
+        let parsed = parse_hrm(&data);
+        assert_eq!(parsed.bpm, 80);
+        assert_eq!(parsed.is_sensor_contact_detected, None);
+        for (parsed, sent) in parsed.rr_intervals.iter().zip(rr_intervals) {
+            assert!(parsed.abs_diff(sent) < Duration::from_millis(1));
+        }
+
+        assert_eq!(parse_hrm(&encode_hrm(300, &[])).bpm, 300);
+        assert_eq!(encode_hrm(300, &[]), vec![0b1, 44, 1]);
+    }
+    #[test]
+    fn keeps_newest_rr_intervals() {
+        let rr_intervals: Vec<_> = (1..=10).map(|i| Duration::from_secs(i) / 10).collect();
+        let data = encode_hrm(300, &rr_intervals);
+        assert!(data.len() <= 20);
+        let parsed = parse_hrm(&data);
+        assert_eq!(parsed.rr_intervals.len(), MAX_RR_INTERVALS);
+        assert!(
+            parsed.rr_intervals[0].abs_diff(Duration::from_millis(300)) < Duration::from_millis(1)
+        );
+    }
+}
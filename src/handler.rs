@@ -1,6 +1,6 @@
 use std::sync::atomic::Ordering;
 
-use crate::app::{App, ErrorPopup, SubState};
+use crate::app::{App, AppView, ErrorPopup, SubState};
 use crate::AppResult;
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use tui_input::backend::crossterm::EventHandler;
@@ -56,6 +56,20 @@ pub fn handle_key_events(app: &mut App, key_event: KeyEvent) -> AppResult<()> {
                 .input
                 .handle_event(&crossterm::event::Event::Key(key_event));
         }
+        SubState::PairingPrompt => {
+            app.pairing
+                .input
+                .handle_event(&crossterm::event::Event::Key(key_event));
+        }
+        SubState::SessionCompare => {
+            if let (KeyCode::Char('d'), Some(compare)) =
+                (key_event.code, app.session_compare.as_mut())
+            {
+                compare.delete_selected();
+            }
+        }
+        // Only up/down/enter/esc, handled above
+        SubState::TwitchTuning | SubState::SourceSelection => {}
         _ => match key_event.code {
             KeyCode::Char('e') if app.is_idle_on_ble_selection() => {
                 app.error_message = Some(ErrorPopup::UserMustDismiss(
@@ -69,6 +83,9 @@ pub fn handle_key_events(app: &mut App, key_event: KeyEvent) -> AppResult<()> {
             KeyCode::Char('c') | KeyCode::Char('C') if app.is_idle_on_ble_selection() => {
                 app.connect_for_characteristics();
             }
+            KeyCode::Char('u') if app.is_idle_on_ble_selection() => {
+                app.toggle_unnamed_devices();
+            }
             KeyCode::Char('s') if app.is_idle_on_ble_selection() => {
                 let current_state = app.ble_scan_paused.load(Ordering::SeqCst);
                 app.ble_scan_paused.store(!current_state, Ordering::SeqCst);
@@ -77,6 +94,49 @@ pub fn handle_key_events(app: &mut App, key_event: KeyEvent) -> AppResult<()> {
             KeyCode::Char('a') => {
                 app.activities_select_prompt();
             }
+            KeyCode::Char('r') => {
+                app.reveal_sensitive();
+            }
+            KeyCode::Char('p') => {
+                app.toggle_osc_paused();
+            }
+            KeyCode::Char('f') => {
+                app.toggle_osc_calibration();
+            }
+            KeyCode::Char('o') => {
+                app.toggle_osc_tap();
+            }
+            KeyCode::Char('v') => {
+                app.cycle_osc_preset();
+            }
+            KeyCode::Char(key) if key.is_ascii_digit() && app.osc_preset_key(key) => {}
+            KeyCode::Char('b') if app.view == AppView::HeartRateView => {
+                app.toggle_bpm_chart();
+            }
+            KeyCode::Char('i') if app.view == AppView::HeartRateView => {
+                app.toggle_rr_chart();
+            }
+            KeyCode::Char('l') if app.view == AppView::HeartRateView => {
+                app.toggle_charts_combined();
+            }
+            KeyCode::Char('m') if app.view == AppView::HeartRateView => {
+                app.cycle_layout();
+            }
+            KeyCode::Char('g') if app.view == AppView::HeartRateView => {
+                app.cycle_theme();
+            }
+            KeyCode::Char('t') => {
+                app.twitch_tuning_open();
+            }
+            KeyCode::Char('d') => {
+                app.device_information_open();
+            }
+            KeyCode::Char('h') => {
+                app.session_compare_open();
+            }
+            KeyCode::Char('x') => {
+                app.source_selection_open();
+            }
             KeyCode::Char('j') => {
                 app.scroll_down();
             }
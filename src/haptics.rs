@@ -0,0 +1,405 @@
+// Pulses haptic toys on each heartbeat through Intiface Central, over the Buttplug protocol (v3 messages)
+// https://buttplug-spec.docs.buttplug.io/docs/spec/
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::{Receiver as BReceiver, Sender as BSender};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tokio_websockets::Message;
+use tracing::{debug, error, info, warn};
+
+use crate::app::{AppUpdate, ErrorPopup};
+use crate::broadcast;
+use crate::errors::AppError;
+use crate::heart_rate::beat_timer::BeatTimer;
+use crate::journal::{self, Severity, Subsystem};
+use crate::reconnect::{self, ConnectError, ReconnectingClient, WsClient};
+use crate::settings::HapticsSettings;
+
+const CLIENT_NAME: &str = "Iron Heart";
+const MESSAGE_VERSION: u8 = 3;
+// Only sent if the server asks for pings, well within any sane MaxPingTime
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+// Id of the handshake, an Error with it means the server won't talk to us
+const SERVER_INFO_ID: u32 = 1;
+// Actuators that make sense to pulse, unlike i.e. Constrict or Position
+const PULSED_ACTUATORS: [&str; 2] = ["Vibrate", "Oscillate"];
+
+/// Buttplug messages go out as a list of single-key objects.
+fn message(name: &str, fields: Value) -> Message {
+    Message::text(json!([{ name: fields }]).to_string())
+}
+
+struct HapticsOutput {
+    settings: HapticsSettings,
+    pulse: Duration,
+    next_id: u32,
+    // Device index to its pulsed actuators (scalar index and type)
+    devices: BTreeMap<u64, Vec<(usize, String)>>,
+    ping: bool,
+    zone: Option<u8>,
+    beats: BeatTimer,
+    // Some while a pulse is running
+    pulse_end: Option<Instant>,
+}
+
+impl HapticsOutput {
+    fn new(settings: HapticsSettings) -> Self {
+        Self {
+            pulse: Duration::from_millis(settings.pulse_ms.max(1) as u64),
+            settings,
+            next_id: SERVER_INFO_ID,
+            devices: BTreeMap::new(),
+            ping: false,
+            zone: None,
+            beats: BeatTimer::default(),
+            pulse_end: None,
+        }
+    }
+    fn id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+    fn handshake(&mut self) -> Message {
+        // Fresh session, the server numbers devices again
+        self.next_id = SERVER_INFO_ID;
+        self.devices.clear();
+        self.ping = false;
+        let id = self.id();
+        message(
+            "RequestServerInfo",
+            json!({ "Id": id, "ClientName": CLIENT_NAME, "MessageVersion": MESSAGE_VERSION }),
+        )
+    }
+    /// Remembers a device from a DeviceList or DeviceAdded, if it has anything to pulse.
+    fn add_device(&mut self, device: &Value) {
+        let Some(index) = device["DeviceIndex"].as_u64() else {
+            return;
+        };
+        let name = device["DeviceName"].as_str().unwrap_or("Unknown device");
+        let actuators: Vec<(usize, String)> = device["DeviceMessages"]["ScalarCmd"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .filter_map(|(scalar_index, attributes)| {
+                let actuator = attributes["ActuatorType"].as_str()?;
+                PULSED_ACTUATORS
+                    .contains(&actuator)
+                    .then(|| (scalar_index, actuator.to_owned()))
+            })
+            .collect();
+        if actuators.is_empty() {
+            info!("Haptics: {name} has nothing to vibrate, skipping it");
+            return;
+        }
+        info!("Haptics: Pulsing {name}");
+        journal::record(
+            Severity::Info,
+            Subsystem::Haptics,
+            &format!("Pulsing {name}"),
+        );
+        self.devices.insert(index, actuators);
+    }
+    /// Replies to a message from the server, if it needs any.
+    fn handle_message(&mut self, name: &str, fields: &Value) -> Result<Vec<Message>, AppError> {
+        match name {
+            "ServerInfo" => {
+                info!(
+                    "Haptics: Connected to {}",
+                    fields["ServerName"].as_str().unwrap_or("Intiface")
+                );
+                self.ping = fields["MaxPingTime"].as_u64().is_some_and(|max| max > 0);
+                let id = self.id();
+                Ok(vec![message("RequestDeviceList", json!({ "Id": id }))])
+            }
+            "DeviceList" => {
+                for device in fields["Devices"].as_array().into_iter().flatten() {
+                    self.add_device(device);
+                }
+                Ok(Vec::new())
+            }
+            "DeviceAdded" => {
+                self.add_device(fields);
+                Ok(Vec::new())
+            }
+            "DeviceRemoved" => {
+                if let Some(index) = fields["DeviceIndex"].as_u64() {
+                    self.devices.remove(&index);
+                }
+                Ok(Vec::new())
+            }
+            "Error" => {
+                let reason = fields["ErrorMessage"]
+                    .as_str()
+                    .unwrap_or("no reason given")
+                    .to_owned();
+                if fields["Id"].as_u64() == Some(SERVER_INFO_ID as u64) {
+                    return Err(AppError::IntifaceRejected(reason));
+                }
+                warn!("Haptics: {reason}");
+                Ok(Vec::new())
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+    fn intensity(&self) -> f32 {
+        let settings = &self.settings;
+        settings.intensity_from.scale(
+            self.beats.bpm(),
+            self.zone,
+            (settings.min_bpm, settings.max_bpm),
+            (settings.min_intensity, settings.max_intensity),
+            &settings.zone_intensities,
+        )
+    }
+    fn scalar_commands(&mut self, intensity: f32) -> Vec<Message> {
+        let devices: Vec<(u64, Value)> = self
+            .devices
+            .iter()
+            .map(|(index, actuators)| {
+                let scalars: Vec<Value> = actuators
+                    .iter()
+                    .map(|(scalar_index, actuator)| {
+                        json!({ "Index": scalar_index, "Scalar": intensity, "ActuatorType": actuator })
+                    })
+                    .collect();
+                (*index, json!(scalars))
+            })
+            .collect();
+        devices
+            .into_iter()
+            .map(|(index, scalars)| {
+                let id = self.id();
+                message(
+                    "ScalarCmd",
+                    json!({ "Id": id, "DeviceIndex": index, "Scalars": scalars }),
+                )
+            })
+            .collect()
+    }
+    fn beat(&mut self, now: Instant) -> Vec<Message> {
+        if !self.beats.beat(now) {
+            return Vec::new();
+        }
+        self.pulse_end = Some(now + self.pulse);
+        let intensity = self.intensity();
+        self.scalar_commands(intensity)
+    }
+    fn end_pulse(&mut self) -> Vec<Message> {
+        self.pulse_end = None;
+        self.scalar_commands(0.0)
+    }
+}
+
+impl ReconnectingClient for HapticsOutput {
+    type Connection = WsClient;
+    type Updates = BReceiver<AppUpdate>;
+    fn name(&self) -> &'static str {
+        "Intiface"
+    }
+    fn subsystem(&self) -> Subsystem {
+        Subsystem::Haptics
+    }
+    fn target(&self) -> String {
+        self.settings.url.clone()
+    }
+    async fn connect(&mut self) -> Result<WsClient, ConnectError> {
+        reconnect::connect_websocket(&self.settings.url).await
+    }
+    async fn session(
+        &mut self,
+        mut client: WsClient,
+        broadcast_rx: &mut BReceiver<AppUpdate>,
+        _broadcast_tx: &BSender<AppUpdate>,
+        cancel_token: &CancellationToken,
+    ) -> Result<Option<String>, AppError> {
+        client.send(self.handshake()).await?;
+        let mut ping = tokio::time::interval(PING_INTERVAL);
+        loop {
+            let next_beat = self.beats.next_beat();
+            let pulse_end = self.pulse_end;
+            let outgoing = tokio::select! {
+                item = client.next() => match item {
+                    Some(Ok(msg)) if msg.is_close() => {
+                        return Ok(Some("Intiface closed the connection".into()));
+                    }
+                    Some(Ok(msg)) => {
+                        let text = msg.as_text().unwrap_or_default();
+                        debug!("Haptics: < {text}");
+                        let received: Vec<Value> = serde_json::from_str(text).unwrap_or_default();
+                        let mut outgoing = Vec::new();
+                        for item in received.iter().filter_map(Value::as_object) {
+                            for (name, fields) in item {
+                                outgoing.extend(self.handle_message(name, fields)?);
+                            }
+                        }
+                        outgoing
+                    }
+                    Some(Err(e)) => return Ok(Some(format!("Error receiving from Intiface: {e}"))),
+                    None => return Ok(Some("Intiface closed the connection".into())),
+                },
+                update = broadcast_rx.recv() => match update {
+                    Ok(AppUpdate::HeartRateStatus(hr_status)) => {
+                        self.beats.update(&hr_status, Instant::now());
+                        continue;
+                    }
+                    Ok(AppUpdate::ZoneChanged(zone)) => {
+                        self.zone = Some(zone);
+                        continue;
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(count)) => {
+                        warn!("Haptics: Lagged! Missed {count} messages");
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return Ok(None),
+                },
+                _ = tokio::time::sleep_until(next_beat.unwrap_or_else(Instant::now)), if next_beat.is_some() => {
+                    self.beat(Instant::now())
+                }
+                _ = tokio::time::sleep_until(pulse_end.unwrap_or_else(Instant::now)), if pulse_end.is_some() => {
+                    self.end_pulse()
+                }
+                _ = ping.tick(), if self.ping => {
+                    let id = self.id();
+                    vec![message("Ping", json!({ "Id": id }))]
+                }
+                _ = cancel_token.cancelled() => {
+                    let id = self.id();
+                    let _ = client.send(message("StopAllDevices", json!({ "Id": id }))).await;
+                    let _ = client.close().await;
+                    return Ok(None);
+                }
+            };
+            for msg in outgoing {
+                client.send(msg).await?;
+            }
+        }
+    }
+}
+
+/// Pulses connected toys in time with each heartbeat, harder the higher the BPM (or zone).
+pub async fn haptics_thread(
+    mut broadcast_rx: BReceiver<AppUpdate>,
+    broadcast_tx: BSender<AppUpdate>,
+    haptics_settings: HapticsSettings,
+    cancel_token: CancellationToken,
+) {
+    let mut output = HapticsOutput::new(haptics_settings);
+    if let Err(e) =
+        reconnect::run(&mut output, &mut broadcast_rx, &broadcast_tx, &cancel_token).await
+    {
+        error!("Haptics error: {e}");
+        journal::record(Severity::Error, Subsystem::Haptics, &e.to_string());
+        broadcast!(broadcast_tx, ErrorPopup::detailed("Haptics error", e));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heart_rate::HeartRateStatus;
+    use crate::settings::BeatStrength;
+
+    fn output(intensity_from: BeatStrength) -> HapticsOutput {
+        HapticsOutput::new(HapticsSettings {
+            intensity_from,
+            min_bpm: 60,
+            max_bpm: 180,
+            min_intensity: 0.2,
+            max_intensity: 1.0,
+            zone_intensities: vec![0.1, 0.5, 0.9],
+            pulse_ms: 100,
+            ..Default::default()
+        })
+    }
+    fn sent(messages: &[Message]) -> Vec<Value> {
+        messages
+            .iter()
+            .map(|msg| serde_json::from_str::<Value>(msg.as_text().unwrap()).unwrap()[0].clone())
+            .collect()
+    }
+
+    #[test]
+    fn handshake_and_devices() {
+        let mut haptics = output(BeatStrength::Bpm);
+        let hello = sent(&[haptics.handshake()]);
+        assert_eq!(hello[0]["RequestServerInfo"]["Id"], 1);
+        let list = sent(
+            &haptics
+                .handle_message("ServerInfo", &json!({ "MaxPingTime": 0 }))
+                .unwrap(),
+        );
+        assert_eq!(list[0]["RequestDeviceList"]["Id"], 2);
+        let devices = json!({ "Devices": [
+            {
+                "DeviceName": "Toy",
+                "DeviceIndex": 3,
+                "DeviceMessages": { "ScalarCmd": [
+                    { "ActuatorType": "Constrict" },
+                    { "ActuatorType": "Vibrate" },
+                ]},
+            },
+            { "DeviceName": "Stroker", "DeviceIndex": 4, "DeviceMessages": { "LinearCmd": [{}] } },
+        ]});
+        haptics.handle_message("DeviceList", &devices).unwrap();
+        assert_eq!(haptics.devices.len(), 1);
+        assert_eq!(haptics.devices[&3], vec![(1, "Vibrate".to_owned())]);
+
+        let rejected = haptics.handle_message(
+            "Error",
+            &json!({ "Id": 1, "ErrorMessage": "Client message spec version too new" }),
+        );
+        assert!(matches!(rejected, Err(AppError::IntifaceRejected(_))));
+        haptics
+            .handle_message("DeviceRemoved", &json!({ "DeviceIndex": 3 }))
+            .unwrap();
+        assert!(haptics.devices.is_empty());
+    }
+    fn status(bpm: u16) -> HeartRateStatus {
+        HeartRateStatus {
+            heart_rate_bpm: bpm,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn intensity_from_bpm_and_zone() {
+        let mut haptics = output(BeatStrength::Bpm);
+        let now = Instant::now();
+        haptics.beats.update(&status(40), now);
+        assert_eq!(haptics.intensity(), 0.2);
+        haptics.beats.update(&status(120), now);
+        assert!((haptics.intensity() - 0.6).abs() < 0.001);
+        haptics.beats.update(&status(200), now);
+        assert_eq!(haptics.intensity(), 1.0);
+
+        let mut haptics = output(BeatStrength::Zone);
+        assert_eq!(haptics.intensity(), 0.2);
+        haptics.zone = Some(1);
+        assert_eq!(haptics.intensity(), 0.5);
+        haptics.zone = Some(5);
+        assert_eq!(haptics.intensity(), 0.9);
+    }
+    #[test]
+    fn pulses_on_each_beat() {
+        let mut haptics = output(BeatStrength::Bpm);
+        haptics.devices.insert(0, vec![(0, "Vibrate".to_owned())]);
+        let now = Instant::now();
+        assert!(haptics.beat(now).is_empty());
+        haptics.beats.update(&status(120), now);
+        let on = sent(&haptics.beat(now));
+        assert_eq!(on[0]["ScalarCmd"]["DeviceIndex"], 0);
+        assert!(on[0]["ScalarCmd"]["Scalars"][0]["Scalar"].as_f64().unwrap() > 0.5);
+        assert_eq!(haptics.pulse_end, Some(now + Duration::from_millis(100)));
+        let off = sent(&haptics.end_pulse());
+        assert_eq!(off[0]["ScalarCmd"]["Scalars"][0]["Scalar"], 0.0);
+        assert_eq!(haptics.pulse_end, None);
+    }
+}
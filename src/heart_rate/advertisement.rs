@@ -0,0 +1,177 @@
+use super::ble::HEART_RATE_SERVICE_UUID;
+use super::measurement::parse_hrm;
+use super::twitcher::{TwitchThreshold, Twitcher};
+use super::{BatteryLevel, HeartRateStatus};
+use crate::app::{AppUpdate, ErrorPopup};
+use crate::broadcast;
+use crate::journal::{self, Severity, Subsystem};
+use crate::structs::DeviceInfo;
+
+use btleplug::api::{Central, CentralEvent, Manager as _, Peripheral as _, ScanFilter};
+use btleplug::platform::Manager;
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::Sender as BSender;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+// Advertisements repeat several times a second, the HR inside them doesn't
+const MIN_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Pulls the BPM out of Heart Rate service data in an advertisement.
+///
+/// Uses the same layout as the Heart Rate Measurement characteristic.
+/// RR intervals are dropped, since there's no telling a repeated advertisement from a new beat.
+fn parse_advertised_hr(service_data: &HashMap<Uuid, Vec<u8>>) -> Option<u16> {
+    let data = service_data.get(&HEART_RATE_SERVICE_UUID)?;
+    let flags = *data.first()?;
+    let bpm_len = if flags & 1 == 1 { 2 } else { 1 };
+    let energy_len = if flags & 0b1000 == 0b1000 { 2 } else { 0 };
+    // `parse_hrm` doesn't check the length itself
+    if data.len() < 1 + bpm_len + energy_len {
+        return None;
+    }
+    Some(parse_hrm(data).bpm)
+}
+
+/// Passively listens to advertisements from the chosen device, for HRMs
+/// that broadcast their HR without accepting connections.
+pub async fn start_advertisement_thread(
+    broadcast_tx: BSender<AppUpdate>,
+    peripheral: DeviceInfo,
+    twitch_threshold: TwitchThreshold,
+    cancel_token: CancellationToken,
+) {
+    let no_packet_timeout = Duration::from_secs(30);
+    let adapter = match Manager::new().await {
+        Ok(manager) => manager
+            .adapters()
+            .await
+            .ok()
+            .and_then(|a| a.into_iter().next()),
+        Err(e) => {
+            error!("Failed to create manager: {e}");
+            None
+        }
+    };
+    let Some(adapter) = adapter else {
+        broadcast!(
+            broadcast_tx,
+            ErrorPopup::Fatal("No Bluetooth adapter for advertisement listening".into())
+        );
+        return;
+    };
+    let mut events = match adapter.events().await {
+        Ok(events) => events,
+        Err(e) => {
+            broadcast!(broadcast_tx, ErrorPopup::detailed("BLE failure", e.into()));
+            return;
+        }
+    };
+    if let Err(e) = adapter.start_scan(ScanFilter::default()).await {
+        broadcast!(
+            broadcast_tx,
+            ErrorPopup::detailed("Scanning failure", e.into())
+        );
+        return;
+    }
+    info!(
+        "Listening for HR advertisements from {} ({})",
+        peripheral.name, peripheral.id
+    );
+    journal::record(
+        Severity::Info,
+        Subsystem::Ble,
+        &format!("Listening for advertisements from {}", peripheral.name),
+    );
+
+    let mut twitcher = Twitcher::new(twitch_threshold);
+    let mut last_update: Option<(u16, Instant)> = None;
+    loop {
+        tokio::select! {
+            Some(event) = events.next() => {
+                let (id, service_data) = match event {
+                    CentralEvent::ServiceDataAdvertisement { id, service_data } => (id, service_data),
+                    // Not every platform sends the above, but properties are kept up to date
+                    CentralEvent::DeviceUpdated(id) => {
+                        let Ok(device) = adapter.peripheral(&id).await else {
+                            continue;
+                        };
+                        let Ok(Some(properties)) = device.properties().await else {
+                            continue;
+                        };
+                        (id, properties.service_data)
+                    }
+                    _ => continue,
+                };
+                if id.to_string() != peripheral.id {
+                    continue;
+                }
+                let Some(bpm) = parse_advertised_hr(&service_data) else {
+                    continue;
+                };
+                let now = Instant::now();
+                if matches!(last_update, Some((last_bpm, at)) if last_bpm == bpm && now - at < MIN_UPDATE_INTERVAL) {
+                    continue;
+                }
+                last_update = Some((bpm, now));
+                let (twitch_up, twitch_down) = twitcher.handle(bpm, &[]);
+                broadcast!(broadcast_tx, HeartRateStatus {
+                    heart_rate_bpm: bpm,
+                    battery_level: BatteryLevel::NotReported,
+                    twitch_up,
+                    twitch_down,
+                    timestamp: chrono::Local::now(),
+                    ..Default::default()
+                });
+            }
+            _ = tokio::time::sleep(no_packet_timeout) => {
+                warn!("No HR advertisements in {} seconds", no_packet_timeout.as_secs());
+                broadcast!(
+                    broadcast_tx,
+                    HeartRateStatus::default(),
+                    "Failed to send 0BPM after advertisement timeout"
+                );
+                broadcast!(broadcast_tx, ErrorPopup::Intermittent(
+                    "No HR advertisements received, is the device in range?".into(),
+                ));
+            }
+            _ = cancel_token.cancelled() => {
+                info!("Shutting down HR advertisement thread!");
+                break;
+            }
+        }
+    }
+    if let Err(e) = adapter.stop_scan().await {
+        debug!("Error stopping advertisement scan: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service_data(data: &[u8]) -> HashMap<Uuid, Vec<u8>> {
+        HashMap::from([(HEART_RATE_SERVICE_UUID, data.to_vec())])
+    }
+
+    #[test]
+    fn parses_advertised_bpm() {
+        assert_eq!(parse_advertised_hr(&service_data(&[0x00, 72])), Some(72));
+        // 16-bit BPM, with an RR interval tacked on
+        assert_eq!(
+            parse_advertised_hr(&service_data(&[0x11, 0x2C, 0x01, 0x00, 0x04])),
+            Some(300)
+        );
+    }
+    #[test]
+    fn rejects_short_or_missing_data() {
+        assert_eq!(parse_advertised_hr(&HashMap::new()), None);
+        assert_eq!(parse_advertised_hr(&service_data(&[])), None);
+        assert_eq!(parse_advertised_hr(&service_data(&[0x01, 0x2C])), None);
+        // Energy expended flag set, but missing
+        assert_eq!(parse_advertised_hr(&service_data(&[0x08, 72])), None);
+    }
+}
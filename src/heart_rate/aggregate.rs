@@ -0,0 +1,175 @@
+use super::HeartRateStatus;
+use crate::app::AppUpdate;
+use crate::broadcast;
+use crate::settings::AggregatePolicy;
+
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::Sender as BSender;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info};
+
+// `HeartRateStatus::source` values
+pub const PRIMARY_SOURCE: u8 = 0;
+pub const SECONDARY_SOURCE: u8 = 1;
+
+/// Handed to each device's thread while aggregating, in place of broadcasting HR directly.
+#[derive(Debug, Clone)]
+pub struct AggregateInput {
+    source: u8,
+    tx: Sender<HeartRateStatus>,
+}
+
+impl AggregateInput {
+    pub fn new(source: u8, tx: Sender<HeartRateStatus>) -> Self {
+        Self { source, tx }
+    }
+    pub fn is_secondary(&self) -> bool {
+        self.source == SECONDARY_SOURCE
+    }
+    pub async fn send(&self, mut status: HeartRateStatus) {
+        status.source = self.source;
+        if self.tx.send(status).await.is_err() {
+            debug!("HR aggregator closed, dropping update");
+        }
+    }
+}
+
+/// Merges updates from the primary and secondary devices into a single stream.
+struct Aggregator {
+    policy: AggregatePolicy,
+    // How long a source can go without a reading before it's considered gone
+    gap: Duration,
+    // Last update with an actual reading, per source
+    latest: [Option<(u16, Instant)>; 2],
+}
+
+impl Aggregator {
+    fn new(policy: AggregatePolicy, gap: Duration) -> Self {
+        Self {
+            policy,
+            gap,
+            latest: [None, None],
+        }
+    }
+    fn fresh_bpm(&self, index: usize, now: Instant) -> Option<u16> {
+        self.latest[index]
+            .filter(|(_, at)| now.saturating_duration_since(*at) <= self.gap)
+            .map(|(bpm, _)| bpm)
+    }
+    /// Returns the status to pass along, if any.
+    fn handle(&mut self, mut status: HeartRateStatus, now: Instant) -> Option<HeartRateStatus> {
+        let index = usize::from(status.source != PRIMARY_SOURCE);
+        let other = 1 - index;
+        let has_reading = status.heart_rate_bpm != 0;
+        self.latest[index] = has_reading.then_some((status.heart_rate_bpm, now));
+        let other_bpm = self.fresh_bpm(other, now);
+        if !has_reading {
+            // Only let the 0 BPM through once both have gone quiet,
+            // otherwise the other source's next update takes over
+            return other_bpm.is_none().then_some(status);
+        }
+        let Some(other_bpm) = other_bpm else {
+            return Some(status);
+        };
+        match self.policy {
+            // Secondary only gets through while the primary is in a gap
+            AggregatePolicy::PreferPrimary => (index == 0).then_some(status),
+            AggregatePolicy::Average => {
+                let sum = status.heart_rate_bpm as u32 + other_bpm as u32;
+                status.heart_rate_bpm = sum.div_ceil(2) as u16;
+                // Both are reporting the same beats, so only the primary's
+                // intervals (and twitches) are used to avoid doubling them up
+                if index != 0 {
+                    status.rr_intervals.clear();
+                    status.twitch_up = false;
+                    status.twitch_down = false;
+                }
+                Some(status)
+            }
+        }
+    }
+}
+
+pub async fn aggregator_thread(
+    broadcast_tx: BSender<AppUpdate>,
+    mut hr_rx: Receiver<HeartRateStatus>,
+    policy: AggregatePolicy,
+    gap: Duration,
+    cancel_token: CancellationToken,
+) {
+    info!("HR aggregator started, policy: {policy:?}");
+    let mut aggregator = Aggregator::new(policy, gap);
+    loop {
+        tokio::select! {
+            Some(status) = hr_rx.recv() => {
+                let source = status.source;
+                match aggregator.handle(status, Instant::now()) {
+                    Some(status) => {
+                        broadcast!(broadcast_tx, status);
+                    }
+                    None => debug!("Holding back update from source {source}"),
+                }
+            }
+            _ = cancel_token.cancelled() => {
+                info!("Shutting down HR aggregator thread!");
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(source: u8, bpm: u16) -> HeartRateStatus {
+        HeartRateStatus {
+            heart_rate_bpm: bpm,
+            rr_intervals: vec![Duration::from_millis(800)],
+            source,
+            ..Default::default()
+        }
+    }
+    fn bpm(result: Option<HeartRateStatus>) -> Option<u16> {
+        result.map(|s| s.heart_rate_bpm)
+    }
+
+    #[test]
+    fn prefers_primary_until_gap() {
+        let mut aggregator =
+            Aggregator::new(AggregatePolicy::PreferPrimary, Duration::from_secs(5));
+        let start = Instant::now();
+        assert_eq!(bpm(aggregator.handle(status(1, 70), start)), Some(70));
+        assert_eq!(bpm(aggregator.handle(status(0, 72), start)), Some(72));
+        assert_eq!(bpm(aggregator.handle(status(1, 70), start)), None);
+        // Primary's gone quiet
+        let later = start + Duration::from_secs(6);
+        assert_eq!(bpm(aggregator.handle(status(1, 71), later)), Some(71));
+        assert_eq!(bpm(aggregator.handle(status(0, 73), later)), Some(73));
+    }
+    #[test]
+    fn zero_only_passes_when_both_quiet() {
+        let mut aggregator =
+            Aggregator::new(AggregatePolicy::PreferPrimary, Duration::from_secs(5));
+        let start = Instant::now();
+        aggregator.handle(status(0, 72), start);
+        aggregator.handle(status(1, 70), start);
+        assert_eq!(bpm(aggregator.handle(status(0, 0), start)), None);
+        // Primary dropping out hands over right away
+        assert_eq!(bpm(aggregator.handle(status(1, 70), start)), Some(70));
+        assert_eq!(bpm(aggregator.handle(status(1, 0), start)), Some(0));
+    }
+    #[test]
+    fn averages_fresh_sources() {
+        let mut aggregator = Aggregator::new(AggregatePolicy::Average, Duration::from_secs(5));
+        let start = Instant::now();
+        assert_eq!(bpm(aggregator.handle(status(0, 72), start)), Some(72));
+        let averaged = aggregator.handle(status(1, 75), start).unwrap();
+        assert_eq!(averaged.heart_rate_bpm, 74);
+        assert!(averaged.rr_intervals.is_empty());
+        let averaged = aggregator.handle(status(0, 70), start).unwrap();
+        assert_eq!(averaged.heart_rate_bpm, 73);
+        assert_eq!(averaged.rr_intervals.len(), 1);
+    }
+}
@@ -0,0 +1,527 @@
+// ANT+ Heart Rate Monitor profile, received through a USB ANT stick
+// Framing and decoding are always built (and tested), talking to the stick needs the `ant` feature
+// https://www.thisisant.com/developer/ant-plus/device-profiles/#526_tab
+
+#![cfg_attr(not(feature = "ant"), allow(dead_code))]
+
+use std::time::Duration;
+
+use tokio::sync::broadcast::Sender as BSender;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use super::twitcher::TwitchThreshold;
+use super::BatteryLevel;
+use crate::app::{AppUpdate, AppView};
+use crate::settings::AntSettings;
+use crate::sources::{Capabilities, HeartRateSource, HrSource, SourceContext};
+
+const SYNC: u8 = 0xA4;
+// Longest message data we'll ever see, anything claiming more is noise that happened to look like SYNC
+const MAX_DATA_LEN: usize = 32;
+// Public ANT+ network key
+const ANT_PLUS_NETWORK_KEY: [u8; 8] = [0xB9, 0xA5, 0x21, 0xFB, 0xBD, 0x72, 0xC3, 0x45];
+const HRM_DEVICE_TYPE: u8 = 120;
+// 32768 / 8070 = ~4.06Hz, what HR straps broadcast at
+const HRM_CHANNEL_PERIOD: u16 = 8070;
+// 2457 MHz
+const ANT_PLUS_RF_FREQUENCY: u8 = 57;
+
+const MESG_CHANNEL_EVENT: u8 = 0x40;
+const MESG_ASSIGN_CHANNEL: u8 = 0x42;
+const MESG_CHANNEL_PERIOD: u8 = 0x43;
+const MESG_SEARCH_TIMEOUT: u8 = 0x44;
+const MESG_RF_FREQUENCY: u8 = 0x45;
+const MESG_NETWORK_KEY: u8 = 0x46;
+const MESG_SYSTEM_RESET: u8 = 0x4A;
+const MESG_OPEN_CHANNEL: u8 = 0x4B;
+const MESG_CLOSE_CHANNEL: u8 = 0x4C;
+const MESG_REQUEST: u8 = 0x4D;
+const MESG_BROADCAST_DATA: u8 = 0x4E;
+const MESG_CHANNEL_ID: u8 = 0x51;
+
+// Channel event codes, sent with MESG_CHANNEL_EVENT
+const EVENT_RX_SEARCH_TIMEOUT: u8 = 0x01;
+const EVENT_RX_FAIL_GO_TO_SEARCH: u8 = 0x08;
+const EVENT_CHANNEL_CLOSED: u8 = 0x07;
+
+const HRM_PAGE_PREVIOUS_BEAT: u8 = 4;
+const HRM_PAGE_BATTERY: u8 = 7;
+
+/// One ANT serial message: sync, length, ID, data, then an XOR checksum of everything before it.
+pub fn encode_message(id: u8, data: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(data.len() + 4);
+    message.extend([SYNC, data.len() as u8, id]);
+    message.extend_from_slice(data);
+    message.push(checksum(&message));
+    message
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |acc, byte| acc ^ byte)
+}
+
+/// Pulls every complete message (ID, data) out of `buffer`,
+/// skipping garbage and leaving a trailing partial message for next time.
+pub fn decode_messages(buffer: &mut Vec<u8>) -> Vec<(u8, Vec<u8>)> {
+    let mut messages = Vec::new();
+    let mut start = 0;
+    while let Some(offset) = buffer[start..].iter().position(|&b| b == SYNC) {
+        start += offset;
+        let Some(&len) = buffer.get(start + 1) else {
+            break;
+        };
+        if len as usize > MAX_DATA_LEN {
+            start += 1;
+            continue;
+        }
+        let end = start + len as usize + 4;
+        if buffer.len() < end {
+            break;
+        }
+        let message = &buffer[start..end];
+        if checksum(&message[..message.len() - 1]) == message[message.len() - 1] {
+            messages.push((message[2], message[3..message.len() - 1].to_vec()));
+            start = end;
+        } else {
+            // Not actually the start of a message, keep looking after it
+            start += 1;
+        }
+    }
+    buffer.drain(..start.min(buffer.len()));
+    messages
+}
+
+/// The parts of an HRM broadcast page we use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HrmPage {
+    // Bit 7 flips every 4 messages on current straps, never on legacy ones
+    pub toggle: bool,
+    pub page: u8,
+    // 1/1024ths of a second, rolls over
+    pub beat_event_time: u16,
+    pub beat_count: u8,
+    pub computed_hr: u8,
+    // Only meaningful when `page` is 4 (or 7 for the battery byte), see `AntHeartRate`
+    pub page_bytes: [u8; 3],
+}
+
+pub fn parse_hrm_page(payload: &[u8]) -> Option<HrmPage> {
+    let payload: &[u8; 8] = payload.get(..8)?.try_into().ok()?;
+    Some(HrmPage {
+        toggle: payload[0] & 0x80 != 0,
+        page: payload[0] & 0x7F,
+        page_bytes: [payload[1], payload[2], payload[3]],
+        beat_event_time: u16::from_le_bytes([payload[4], payload[5]]),
+        beat_count: payload[6],
+        computed_hr: payload[7],
+    })
+}
+
+/// Turns the ~4Hz stream of HRM pages into an update per heart beat.
+#[derive(Debug, Default)]
+pub struct AntHeartRate {
+    // (beat count, beat event time) of the last beat seen
+    last_beat: Option<(u8, u16)>,
+    last_toggle: Option<bool>,
+    // Legacy straps put junk in the page byte, so pages are only trusted once the toggle bit flips
+    pages_supported: bool,
+    pub battery_level: BatteryLevel,
+}
+
+impl AntHeartRate {
+    /// Returns the BPM and any RR interval that can be worked out, but only for a new beat.
+    pub fn handle(&mut self, page: &HrmPage) -> Option<(u16, Vec<Duration>)> {
+        if self.last_toggle.is_some_and(|toggle| toggle != page.toggle) {
+            self.pages_supported = true;
+        }
+        self.last_toggle = Some(page.toggle);
+        if self.pages_supported && page.page == HRM_PAGE_BATTERY && page.page_bytes[0] <= 100 {
+            self.battery_level = BatteryLevel::Level(page.page_bytes[0]);
+        }
+
+        let last_beat = self
+            .last_beat
+            .replace((page.beat_count, page.beat_event_time));
+        let (last_count, last_time) = match last_beat {
+            Some((count, _)) if count == page.beat_count => return None,
+            Some(last_beat) => last_beat,
+            // First page only sets the baseline
+            None => return None,
+        };
+        let previous_beat_time = if self.pages_supported && page.page == HRM_PAGE_PREVIOUS_BEAT {
+            Some(u16::from_le_bytes([page.page_bytes[1], page.page_bytes[2]]))
+        } else if page.beat_count.wrapping_sub(last_count) == 1 {
+            Some(last_time)
+        } else {
+            // Missed a beat (or more), can't tell how long this one was
+            None
+        };
+        let rr_intervals = previous_beat_time
+            .map(|previous| page.beat_event_time.wrapping_sub(previous))
+            .filter(|&ticks| ticks > 0)
+            .map(|ticks| vec![Duration::from_secs_f64(ticks as f64 / 1024.0)])
+            .unwrap_or_default();
+        Some((page.computed_hr as u16, rr_intervals))
+    }
+}
+
+/// Sets up channel 0 to receive from an HR strap, `device_number` 0 pairing with the first one found.
+fn channel_setup(device_number: u16) -> Vec<Vec<u8>> {
+    let [number_low, number_high] = device_number.to_le_bytes();
+    let [period_low, period_high] = HRM_CHANNEL_PERIOD.to_le_bytes();
+    vec![
+        encode_message(
+            MESG_NETWORK_KEY,
+            &[&[0][..], &ANT_PLUS_NETWORK_KEY].concat(),
+        ),
+        // Channel 0, bidirectional receive, network 0
+        encode_message(MESG_ASSIGN_CHANNEL, &[0, 0x00, 0]),
+        // Transmission type 0 is a wildcard too
+        encode_message(
+            MESG_CHANNEL_ID,
+            &[0, number_low, number_high, HRM_DEVICE_TYPE, 0],
+        ),
+        encode_message(MESG_CHANNEL_PERIOD, &[0, period_low, period_high]),
+        // 255 never stops searching
+        encode_message(MESG_SEARCH_TIMEOUT, &[0, 255]),
+        encode_message(MESG_RF_FREQUENCY, &[0, ANT_PLUS_RF_FREQUENCY]),
+        encode_message(MESG_OPEN_CHANNEL, &[0]),
+    ]
+}
+
+#[cfg(feature = "ant")]
+mod usb {
+    use std::time::Duration;
+
+    use rusb::{DeviceHandle, Direction, GlobalContext, TransferType};
+
+    use crate::errors::AppError;
+
+    const DYNASTREAM_VENDOR_ID: u16 = 0x0FCF;
+    // ANTUSB2 and ANTUSB-m sticks
+    const ANT_STICK_PRODUCT_IDS: [u16; 2] = [0x1008, 0x1009];
+
+    pub struct AntStick {
+        handle: DeviceHandle<GlobalContext>,
+        endpoint_in: u8,
+        endpoint_out: u8,
+    }
+
+    impl AntStick {
+        /// Opens the first ANT stick plugged in.
+        pub fn open() -> Result<Self, AppError> {
+            let device = rusb::devices()?
+                .iter()
+                .find(|device| {
+                    device.device_descriptor().is_ok_and(|descriptor| {
+                        descriptor.vendor_id() == DYNASTREAM_VENDOR_ID
+                            && ANT_STICK_PRODUCT_IDS.contains(&descriptor.product_id())
+                    })
+                })
+                .ok_or(AppError::AntStickMissing)?;
+            let config = device.active_config_descriptor()?;
+            let mut endpoint_in = None;
+            let mut endpoint_out = None;
+            for endpoint in config
+                .interfaces()
+                .flat_map(|interface| interface.descriptors())
+                .flat_map(|descriptor| descriptor.endpoint_descriptors().collect::<Vec<_>>())
+                .filter(|endpoint| endpoint.transfer_type() == TransferType::Bulk)
+            {
+                match endpoint.direction() {
+                    Direction::In => endpoint_in.get_or_insert(endpoint.address()),
+                    Direction::Out => endpoint_out.get_or_insert(endpoint.address()),
+                };
+            }
+            let (Some(endpoint_in), Some(endpoint_out)) = (endpoint_in, endpoint_out) else {
+                return Err(AppError::AntStickMissing);
+            };
+            let handle = device.open()?;
+            // Not supported on every platform, only matters where a kernel driver grabbed it (Linux)
+            let _ = handle.set_auto_detach_kernel_driver(true);
+            handle.claim_interface(0)?;
+            Ok(Self {
+                handle,
+                endpoint_in,
+                endpoint_out,
+            })
+        }
+        pub fn write(&self, message: &[u8]) -> Result<(), AppError> {
+            self.handle
+                .write_bulk(self.endpoint_out, message, Duration::from_secs(1))?;
+            Ok(())
+        }
+        /// Appends whatever arrives within `timeout` to `buffer`.
+        pub fn read(&self, buffer: &mut Vec<u8>, timeout: Duration) -> Result<(), AppError> {
+            let mut chunk = [0; 64];
+            match self.handle.read_bulk(self.endpoint_in, &mut chunk, timeout) {
+                Ok(len) => buffer.extend_from_slice(&chunk[..len]),
+                Err(rusb::Error::Timeout) => {}
+                Err(e) => return Err(e.into()),
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for AntStick {
+        fn drop(&mut self) {
+            let _ = self.handle.release_interface(0);
+        }
+    }
+}
+
+#[cfg(feature = "ant")]
+pub async fn ant_thread(
+    broadcast_tx: BSender<AppUpdate>,
+    ant_settings: AntSettings,
+    twitch_threshold: TwitchThreshold,
+    stress_index_window: usize,
+    cancel_token: CancellationToken,
+) {
+    use crate::app::ErrorPopup;
+    use crate::broadcast;
+    use tracing::error;
+
+    let result = tokio::task::spawn_blocking({
+        let broadcast_tx = broadcast_tx.clone();
+        move || {
+            receive_loop(
+                &broadcast_tx,
+                &ant_settings,
+                twitch_threshold,
+                stress_index_window,
+                &cancel_token,
+            )
+        }
+    })
+    .await;
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => {
+            error!("ANT+ Error: {e}");
+            broadcast!(broadcast_tx, ErrorPopup::detailed("ANT+ Error", e));
+        }
+        Err(e) => error!("ANT+ thread panicked: {e}"),
+    }
+}
+
+// rusb is blocking, so this runs on its own thread and checks for cancellation between reads
+#[cfg(feature = "ant")]
+fn receive_loop(
+    broadcast_tx: &BSender<AppUpdate>,
+    ant_settings: &AntSettings,
+    twitch_threshold: TwitchThreshold,
+    stress_index_window: usize,
+    cancel_token: &CancellationToken,
+) -> Result<(), crate::errors::AppError> {
+    use super::stress::StressIndex;
+    use super::twitcher::Twitcher;
+    use super::HeartRateStatus;
+    use crate::app::ErrorPopup;
+    use crate::broadcast;
+    use crate::journal::{self, Severity, Subsystem};
+    use tracing::{debug, info, warn};
+
+    let stick = usb::AntStick::open()?;
+    info!("Opened ANT stick");
+    stick.write(&encode_message(MESG_SYSTEM_RESET, &[0]))?;
+    // The stick needs a moment after resetting
+    std::thread::sleep(Duration::from_millis(500));
+    for message in channel_setup(ant_settings.device_number) {
+        stick.write(&message)?;
+    }
+    info!("Searching for ANT+ heart rate strap");
+    journal::record(
+        Severity::Info,
+        Subsystem::Ant,
+        "Searching for ANT+ heart rate strap",
+    );
+
+    let mut heart_rate = AntHeartRate::default();
+    let mut twitcher = Twitcher::new(twitch_threshold);
+    let mut stress_index = StressIndex::new(stress_index_window);
+    let mut buffer = Vec::new();
+    // Asks which strap it found once data starts coming in, so it can be logged
+    let mut paired = false;
+    while !cancel_token.is_cancelled() {
+        stick.read(&mut buffer, Duration::from_millis(500))?;
+        for (id, data) in decode_messages(&mut buffer) {
+            match (id, data.as_slice()) {
+                (MESG_BROADCAST_DATA, [0, payload @ ..]) => {
+                    if !std::mem::replace(&mut paired, true) {
+                        stick.write(&encode_message(MESG_REQUEST, &[0, MESG_CHANNEL_ID]))?;
+                    }
+                    let Some(page) = parse_hrm_page(payload) else {
+                        continue;
+                    };
+                    let Some((bpm, rr_intervals)) = heart_rate.handle(&page) else {
+                        continue;
+                    };
+                    let (twitch_up, twitch_down) = twitcher.handle(bpm, &rr_intervals);
+                    broadcast!(
+                        broadcast_tx,
+                        HeartRateStatus {
+                            heart_rate_bpm: bpm,
+                            stress_index: stress_index.handle(&rr_intervals),
+                            rr_intervals,
+                            battery_level: heart_rate.battery_level,
+                            twitch_up,
+                            twitch_down,
+                            timestamp: chrono::Local::now(),
+                            ..Default::default()
+                        }
+                    );
+                }
+                (MESG_CHANNEL_ID, [0, number_low, number_high, ..]) => {
+                    let message = format!(
+                        "Paired with ANT+ heart rate strap, device number {}",
+                        u16::from_le_bytes([*number_low, *number_high])
+                    );
+                    info!("{message}");
+                    journal::record(Severity::Info, Subsystem::Ant, &message);
+                }
+                (MESG_CHANNEL_EVENT, [0, 1, EVENT_RX_FAIL_GO_TO_SEARCH]) => {
+                    paired = false;
+                    warn!("Lost ANT+ heart rate strap, searching again");
+                    journal::record(Severity::Warning, Subsystem::Ant, "Lost ANT+ strap");
+                    broadcast!(
+                        broadcast_tx,
+                        HeartRateStatus::default(),
+                        "Failed to send 0BPM after losing ANT+ strap"
+                    );
+                    broadcast!(
+                        broadcast_tx,
+                        ErrorPopup::Intermittent("Lost ANT+ strap, searching again".into())
+                    );
+                }
+                (MESG_CHANNEL_EVENT, [0, 1, EVENT_RX_SEARCH_TIMEOUT | EVENT_CHANNEL_CLOSED]) => {
+                    // Shouldn't happen with an infinite search timeout, but reopen if it does
+                    debug!("ANT channel closed, reopening");
+                    stick.write(&encode_message(MESG_OPEN_CHANNEL, &[0]))?;
+                }
+                _ => {}
+            }
+        }
+    }
+    info!("Shutting down ANT+ thread!");
+    stick.write(&encode_message(MESG_CLOSE_CHANNEL, &[0]))?;
+    Ok(())
+}
+
+#[cfg(not(feature = "ant"))]
+pub async fn ant_thread(
+    broadcast_tx: BSender<AppUpdate>,
+    _ant_settings: AntSettings,
+    _twitch_threshold: TwitchThreshold,
+    _stress_index_window: usize,
+    _cancel_token: CancellationToken,
+) {
+    use crate::app::ErrorPopup;
+    use crate::broadcast;
+    use crate::journal::{self, Severity, Subsystem};
+
+    let message =
+        "This build doesn't include ANT+ support, it needs to be built with the \"ant\" feature.";
+    journal::record(Severity::Error, Subsystem::Ant, message);
+    broadcast!(broadcast_tx, ErrorPopup::UserMustDismiss(message.into()));
+}
+
+pub struct AntSource;
+
+impl HeartRateSource for AntSource {
+    fn source(&self) -> HrSource {
+        HrSource::Ant
+    }
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::FULL
+    }
+    fn waiting_view(&self) -> AppView {
+        AppView::WaitingForAnt
+    }
+    fn spawn(self: Box<Self>, context: SourceContext) -> JoinHandle<()> {
+        tokio::spawn(ant_thread(
+            context.broadcast_tx,
+            context.settings.ant,
+            context.rr_twitch_threshold,
+            context.stress_index_window,
+            context.cancel_token,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(toggle: bool, page: u8, page_bytes: [u8; 3], time: u16, count: u8, hr: u8) -> HrmPage {
+        HrmPage {
+            toggle,
+            page,
+            page_bytes,
+            beat_event_time: time,
+            beat_count: count,
+            computed_hr: hr,
+        }
+    }
+
+    #[test]
+    fn message_framing() {
+        let message = encode_message(MESG_OPEN_CHANNEL, &[0]);
+        assert_eq!(message, vec![0xA4, 0x01, 0x4B, 0x00, 0xEE]);
+
+        let mut buffer = vec![0x00, 0xA4];
+        buffer.extend(&message);
+        buffer.extend(&encode_message(MESG_CHANNEL_EVENT, &[0, 1, 8]));
+        // Start of another message
+        buffer.extend([0xA4, 0x09]);
+        let messages = decode_messages(&mut buffer);
+        assert_eq!(
+            messages,
+            vec![
+                (MESG_OPEN_CHANNEL, vec![0]),
+                (MESG_CHANNEL_EVENT, vec![0, 1, 8])
+            ]
+        );
+        assert_eq!(buffer, vec![0xA4, 0x09]);
+    }
+    #[test]
+    fn hrm_pages() {
+        let payload = [0x84, 0xFF, 0x00, 0x04, 0x00, 0x08, 0x05, 0x3C];
+        let parsed = parse_hrm_page(&payload).unwrap();
+        assert_eq!(parsed, page(true, 4, [0xFF, 0x00, 0x04], 0x0800, 5, 60));
+        assert_eq!(parse_hrm_page(&payload[..7]), None);
+    }
+    #[test]
+    fn beats_and_rr() {
+        let mut heart_rate = AntHeartRate::default();
+        // Baseline, then the same beat repeated
+        assert_eq!(
+            heart_rate.handle(&page(false, 0, [0; 3], 1024, 1, 60)),
+            None
+        );
+        assert_eq!(
+            heart_rate.handle(&page(false, 0, [0; 3], 1024, 1, 60)),
+            None
+        );
+        // Next beat, RR from our own record of the last one
+        let (bpm, rr) = heart_rate
+            .handle(&page(false, 0, [0; 3], 2048, 2, 60))
+            .unwrap();
+        assert_eq!(bpm, 60);
+        assert_eq!(rr, vec![Duration::from_secs(1)]);
+        // Skipped a beat, so no RR without page 4
+        let (_, rr) = heart_rate
+            .handle(&page(false, 0, [0; 3], 3584, 4, 80))
+            .unwrap();
+        assert!(rr.is_empty());
+        // Toggle flipped, now page 4's previous beat time is trusted (and rolls over)
+        heart_rate.handle(&page(true, 7, [85, 0, 0], 3584, 4, 80));
+        assert_eq!(heart_rate.battery_level, BatteryLevel::Level(85));
+        let [low, high] = 65000u16.to_le_bytes();
+        let (_, rr) = heart_rate
+            .handle(&page(false, 4, [0, low, high], 476, 6, 80))
+            .unwrap();
+        assert_eq!(rr, vec![Duration::from_secs_f64(1012.0 / 1024.0)]);
+    }
+}
@@ -0,0 +1,117 @@
+use std::time::Duration;
+use tokio::time::Instant;
+
+use super::{rr_from_bpm, HeartRateStatus};
+use crate::settings::BeatStrength;
+
+/// Schedules beats from the latest RR interval (or the BPM, until RR intervals show up),
+/// for outputs that do something on each beat.
+#[derive(Debug, Default)]
+pub struct BeatTimer {
+    bpm: u16,
+    latest_rr: Duration,
+    // Same as the OSC beats, once real RR intervals come in BPM isn't used for timing
+    use_real_rr: bool,
+    // None while there's no reading
+    next_beat: Option<Instant>,
+}
+
+impl BeatTimer {
+    pub fn bpm(&self) -> u16 {
+        self.bpm
+    }
+    pub fn next_beat(&self) -> Option<Instant> {
+        self.next_beat
+    }
+    /// Beats start right away on the first reading, and stop after the next once it's 0 BPM.
+    pub fn update(&mut self, hr_status: &HeartRateStatus, now: Instant) {
+        self.bpm = hr_status.heart_rate_bpm;
+        if self.bpm == 0 {
+            return;
+        }
+        if let Some(rr) = hr_status.rr_intervals.last() {
+            self.latest_rr = *rr;
+            self.use_real_rr = true;
+        } else if !self.use_real_rr {
+            self.latest_rr = rr_from_bpm(self.bpm);
+        }
+        self.next_beat.get_or_insert(now);
+    }
+    /// Called once `next_beat` has passed, true if it's still beating.
+    pub fn beat(&mut self, now: Instant) -> bool {
+        if self.bpm == 0 {
+            self.next_beat = None;
+            return false;
+        }
+        self.next_beat = Some(now + self.latest_rr);
+        true
+    }
+}
+
+impl BeatStrength {
+    /// 0.0 to 1.0. Either `range` scaled by where the BPM is in `bpm_range`,
+    /// or the zone's entry in `per_zone` (the last for any zones past it, the bottom of `range` until there's a zone).
+    pub fn scale(
+        self,
+        bpm: u16,
+        zone: Option<u8>,
+        (min_bpm, max_bpm): (u16, u16),
+        (min, max): (f32, f32),
+        per_zone: &[f32],
+    ) -> f32 {
+        let strength = match self {
+            BeatStrength::Bpm => {
+                let span = max_bpm.saturating_sub(min_bpm).max(1) as f32;
+                let position = (bpm.saturating_sub(min_bpm) as f32 / span).clamp(0.0, 1.0);
+                min + (max - min) * position
+            }
+            BeatStrength::Zone => zone
+                .and_then(|zone| {
+                    let last = per_zone.len().checked_sub(1)?;
+                    per_zone.get((zone as usize).min(last)).copied()
+                })
+                .unwrap_or(min),
+        };
+        strength.clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_rr_then_stops() {
+        let mut timer = BeatTimer::default();
+        let now = Instant::now();
+        assert_eq!(timer.next_beat(), None);
+        timer.update(
+            &HeartRateStatus {
+                heart_rate_bpm: 60,
+                ..Default::default()
+            },
+            now,
+        );
+        assert_eq!(timer.next_beat(), Some(now));
+        assert!(timer.beat(now));
+        assert_eq!(timer.next_beat(), Some(now + Duration::from_secs(1)));
+
+        let later = now + Duration::from_secs(1);
+        timer.update(
+            &HeartRateStatus {
+                heart_rate_bpm: 60,
+                rr_intervals: vec![Duration::from_millis(800)],
+                ..Default::default()
+            },
+            later,
+        );
+        // Already scheduled, not pulled in
+        assert_eq!(timer.next_beat(), Some(now + Duration::from_secs(1)));
+        assert!(timer.beat(later));
+        assert_eq!(timer.next_beat(), Some(later + Duration::from_millis(800)));
+
+        timer.update(&HeartRateStatus::default(), later);
+        assert!(!timer.beat(later + Duration::from_millis(800)));
+        assert_eq!(timer.next_beat(), None);
+    }
+}
@@ -1,12 +1,21 @@
 use super::{BatteryLevel, HeartRateStatus};
 use crate::app::{AppUpdate, ErrorPopup};
+use crate::backoff::Backoff;
 use crate::errors::AppError;
+use crate::hooks::{HookEvent, Hooks};
+use crate::journal::{self, Severity, Subsystem};
+use crate::logging::raw_capture;
+#[cfg(all(feature = "pairing_agent", target_os = "linux"))]
+use crate::pairing;
+use crate::pairing::PairingTx;
+use crate::settings::{BLESettings, RrUnit};
 use crate::structs::DeviceInfo;
 
-use btleplug::api::{Characteristic, Peripheral, ValueNotification};
+use btleplug::api::{CharPropFlags, Characteristic, Peripheral, ValueNotification, WriteType};
 use futures::{Stream, StreamExt};
+use std::path::Path;
 use std::pin::Pin;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::broadcast::Sender as BSender;
 use tokio::sync::mpsc::Sender;
 use tokio_util::sync::CancellationToken;
@@ -15,8 +24,23 @@ use uuid::Uuid;
 
 use crate::broadcast;
 
-use super::measurement::parse_hrm;
-use super::twitcher::Twitcher;
+use super::aggregate::{AggregateInput, PRIMARY_SOURCE};
+use super::cadence::{
+    parse_csc_crank, parse_rsc_cadence, CrankCadence, CSC_MEASUREMENT_CHARACTERISTIC_UUID,
+    CSC_SERVICE_UUID, RSC_MEASUREMENT_CHARACTERISTIC_UUID, RSC_SERVICE_UUID,
+};
+use super::device_information::{
+    is_device_information, DeviceInformation, DEVICE_INFORMATION_SERVICE_UUID,
+};
+use super::ftms::{is_machine_data, parse_machine_heart_rate};
+use super::measurement::{apply_rr_unit, parse_hrm};
+use super::plx::{parse_plx_continuous, PLX_CONTINUOUS_MEASUREMENT_CHARACTERISTIC_UUID};
+use super::polar::{
+    PolarEcg, PMD_CONTROL_POINT_UUID, PMD_DATA_UUID, PMD_SERVICE_UUID, START_ECG_COMMAND,
+};
+use super::raw_dump::RawDump;
+use super::stress::StressIndex;
+use super::twitcher::{TwitchThreshold, Twitcher};
 
 pub const HEART_RATE_SERVICE_UUID: Uuid = Uuid::from_u128(0x0000180d_0000_1000_8000_00805f9b34fb); // 0000180d-0000-1000-8000-00805f9b34fb
 pub const HEART_RATE_MEASUREMENT_CHARACTERISTIC_UUID: Uuid =
@@ -29,16 +53,143 @@ pub const BATTERY_LEVEL_CHARACTERISTIC_UUID: Uuid =
 struct BleMonitorActor {
     peripheral: DeviceInfo,
     rr_cooldown_amount: usize,
-    no_packet_timeout: Duration,
+    // Covers connecting through to subscribing
+    connect_timeout: Duration,
+    watchdog_timeout: Duration,
     battery_characteristic: Option<Characteristic>,
+    // Whichever HR comes in on, so the watchdog can re-subscribe to it
+    hr_characteristic: Option<Characteristic>,
+    battery_poll_interval: Duration,
     cancel_token: CancellationToken,
+    // Both only differ from the spec for devices with a `ble.quirks` entry
+    hr_measurement_uuid: Uuid,
+    rr_unit: RrUnit,
 
     battery_level: BatteryLevel,
     twitcher: Twitcher,
+    stress_index: StressIndex,
     rr_left_to_burn: usize,
+    backoff: Backoff,
+    rssi: Option<i16>,
+    // From the Pulse Oximeter Service, if the device has one
+    spo2: Option<f32>,
+    // Set if the device has no HR service, so the oximeter's pulse rate is used instead
+    plx_pulse_only: bool,
+    // Same, but from a treadmill/bike/etc's Fitness Machine Service data
+    ftms_pulse_only: bool,
+    // Only present if `ble.rssi_warning_threshold` is set
+    weak_signal: Option<WeakSignal>,
+    // Only present while connected to a secondary device
+    aggregate: Option<AggregateInput>,
+    // Only present if `ble.raw_dump_enabled`
+    raw_dump: Option<RawDump>,
+    // Only present if `ble.polar_ecg_enabled`
+    polar_ecg: Option<PolarEcg>,
+    // Only present if `ble.cadence_enabled`
+    crank_cadence: Option<CrankCadence>,
+    // Latest from CSC or RSC, goes out with the next HR update
+    cadence: Option<u16>,
+    hooks: Hooks,
+    // Where PIN prompts go while pairing, only used by builds with the pairing agent
+    #[cfg_attr(
+        not(all(feature = "pairing_agent", target_os = "linux")),
+        allow(dead_code)
+    )]
+    pairing_tx: PairingTx,
+    // Set when subscribing failed in a way that looks like the device wants to be bonded first
+    needs_pairing: bool,
+    // So the pairing hint only shows up once, not on every retry
+    pairing_hint_shown: bool,
 }
 
-// TODO Consider letting this thread be restarted
+/// Sent while the monitor's connection is being retried, so the TUI can show what's going on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BleReconnect {
+    Retrying { attempt: u32, delay: Duration },
+    Connected,
+    // Retry window passed, the notification thread has stopped
+    GaveUp,
+}
+
+/// How far along connecting to the main device is, for the TUI's connecting popup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectStage {
+    // Device went missing, waiting for the scan to find it again
+    Scanning,
+    Connecting,
+    // Discovering services and subscribing to notifications
+    Subscribing,
+    Streaming,
+}
+
+type NotificationStream = Pin<Box<dyn Stream<Item = ValueNotification> + Send>>;
+
+#[derive(Debug, PartialEq, Eq)]
+enum WatchdogAction {
+    Resubscribe,
+    Reconnect,
+}
+
+/// Catches devices that stay connected but quietly stop sending HR.
+///
+/// The first time it goes off we try re-subscribing, if that doesn't
+/// get things going again by the next time, we reconnect.
+struct NotifyWatchdog {
+    timeout: Duration,
+    last_fed: Instant,
+    resubscribed: bool,
+}
+
+impl NotifyWatchdog {
+    fn new(timeout: Duration, now: Instant) -> Self {
+        Self {
+            timeout,
+            last_fed: now,
+            resubscribed: false,
+        }
+    }
+    fn feed(&mut self, now: Instant) {
+        self.last_fed = now;
+        self.resubscribed = false;
+    }
+    fn deadline(&self) -> Instant {
+        self.last_fed + self.timeout
+    }
+    fn expired(&mut self, now: Instant) -> WatchdogAction {
+        if std::mem::replace(&mut self.resubscribed, true) {
+            WatchdogAction::Reconnect
+        } else {
+            // Gives the re-subscription a full timeout to work
+            self.last_fed = now;
+            WatchdogAction::Resubscribe
+        }
+    }
+}
+
+// How far above the threshold the signal needs to climb before we'd warn again
+const WEAK_SIGNAL_MARGIN: i16 = 5;
+
+/// Keeps track of whether the signal is below `ble.rssi_warning_threshold`.
+struct WeakSignal {
+    threshold: i16,
+    weak: bool,
+}
+
+impl WeakSignal {
+    /// Returns Some(true) when the signal drops below the threshold,
+    /// and Some(false) once it's comfortably back above it.
+    fn update(&mut self, rssi: i16) -> Option<bool> {
+        if !self.weak && rssi < self.threshold {
+            self.weak = true;
+            Some(true)
+        } else if self.weak && rssi >= self.threshold.saturating_add(WEAK_SIGNAL_MARGIN) {
+            self.weak = false;
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
 
 impl BleMonitorActor {
     async fn connect(
@@ -46,6 +197,7 @@ impl BleMonitorActor {
         broadcast_tx: &BSender<AppUpdate>,
         restart_tx: Sender<()>,
     ) -> Result<(), AppError> {
+        let mut first_attempt = true;
         'connection: loop {
             let device = self
                 .peripheral
@@ -55,66 +207,48 @@ impl BleMonitorActor {
             if self.cancel_token.is_cancelled() {
                 break 'connection;
             }
+            if !std::mem::take(&mut first_attempt) && !self.wait_to_retry(broadcast_tx).await {
+                break 'connection;
+            }
             info!(
                 "Connecting to Heart Rate Monitor! Name: {:?} | Address: {:?}",
                 self.peripheral.name, self.peripheral.address
             );
+            self.send_connect_stage(broadcast_tx, ConnectStage::Connecting);
+            // Cloned so the setup below can borrow self
+            let cancel_token = self.cancel_token.clone();
             tokio::select! {
-                conn_result = device.connect() => {
-                    match conn_result {
-                        Ok(_) => {
-                            if let Err(e) = device.discover_services().await {
-                                error!("Couldn't read services from connected device: {}", e);
-                                continue 'connection;
-                            }
-                            let characteristics = device.characteristics();
-                            let len = characteristics.len();
-                            debug!("Found {len} characteristics");
-                            // Save battery characteristic if present
-                            if let Some(characteristic) = characteristics
-                                .iter()
-                                .find(|c| c.uuid == BATTERY_LEVEL_CHARACTERISTIC_UUID)
-                            {
-                                self.battery_characteristic = Some(characteristic.to_owned());
-                                self.get_monitor_battery(&device).await;
-                            }
-
-                            if let Some(characteristic) = characteristics
-                                .iter()
-                                .find(|c| c.uuid == HEART_RATE_MEASUREMENT_CHARACTERISTIC_UUID)
-                            {
-                                if device.subscribe(characteristic).await.is_err() {
-                                    error!("Failed to subscribe to HR service!");
-                                    device.disconnect().await?;
-                                    continue 'connection;
-                                }
-                            } else {
-                                error!("Didn't find HR service during notification setup!");
-                                device.disconnect().await?;
-                                continue 'connection;
-                            }
-
-                            let notification_stream = match device.notifications().await {
-                                Ok(stream) => stream,
-                                Err(e) => {
-                                    error!("Failed to get HR BLE notification stream: {}", e);
-                                    device.disconnect().await?;
-                                    continue 'connection;
-                                }
-                            };
+                setup = tokio::time::timeout(self.connect_timeout, self.connect_and_subscribe(&device, broadcast_tx)) => {
+                    match setup {
+                        Ok(Ok(Some(notification_stream))) => {
+                            self.backoff.reset();
+                            journal::record(Severity::Info, Subsystem::Ble, &format!("Subscribed to {}", self.peripheral.name));
+                            self.refresh_rssi(&device, broadcast_tx).await;
+                            self.send_connect_stage(broadcast_tx, ConnectStage::Streaming);
+                            self.send_reconnect_status(broadcast_tx, BleReconnect::Connected);
+                            self.run_hook(HookEvent::Connect);
 
                             self.notification_loop(broadcast_tx, notification_stream, &device).await?;
 
                             info!("Heart Rate Monitor stream closed!");
+                            journal::record(Severity::Warning, Subsystem::Ble, "Heart Rate Monitor stream closed");
                             device.disconnect().await?;
                             if self.cancel_token.is_cancelled() {
                                 break 'connection;
                             }
+                            self.run_hook(HookEvent::Disconnect);
                             broadcast!(broadcast_tx, ErrorPopup::Intermittent(
                                 "Connection timed out".into(),
                             ));
                         }
-                        Err(e) => {
+                        // Already logged and disconnected
+                        Ok(Ok(None)) => {
+                            if std::mem::take(&mut self.needs_pairing) && self.pair(broadcast_tx).await {
+                                // Straight back to subscribing, no need to wait out the backoff
+                                first_attempt = true;
+                            }
+                        }
+                        Ok(Err(e)) => {
                             device.disconnect().await?;
 
                             error!("BLE Connection error: {}", e);
@@ -128,54 +262,273 @@ impl BleMonitorActor {
                             // Telling the manager to restart its scan when these crop up help avoid needing to restart the whole app
                             match e {
                                 btleplug::Error::NotConnected | btleplug::Error::DeviceNotFound => {
+                                    self.send_connect_stage(broadcast_tx, ConnectStage::Scanning);
                                     restart_tx.send(()).await.expect("Couldn't trigger BLE Scan!");
                                 },
                                 _ => {}
                             }
-                            tokio::time::sleep(Duration::from_secs(20)).await;
+                        }
+                        Err(_) => {
+                            // Usually means the device is off or out of range
+                            let message = format!(
+                                "Couldn't connect to {} within {} seconds",
+                                self.peripheral.name,
+                                self.connect_timeout.as_secs()
+                            );
+                            error!("{message}");
+                            journal::record(Severity::Warning, Subsystem::Ble, &message);
+                            if device.is_connected().await.unwrap_or(false) {
+                                device.disconnect().await?;
+                            }
+                            broadcast!(broadcast_tx, ErrorPopup::Intermittent(message));
                         }
                     }
                 }
-                _ = self.cancel_token.cancelled() => {
+                _ = cancel_token.cancelled() => {
                     if device.is_connected().await.unwrap_or(false) {
                         device.disconnect().await?;
                     }
                     break 'connection;
                 }
-                _ = tokio::time::sleep(self.no_packet_timeout) => {
-                    error!("Connection timed out");
-                    broadcast!(broadcast_tx, ErrorPopup::Intermittent(
-                        "Connection timed out".into(),
-                    ));
-                }
             }
-            tokio::time::sleep(Duration::from_secs(10)).await;
         }
         Ok(())
     }
+    /// Connects, discovers services, and subscribes to whatever HR source the device has.
+    ///
+    /// Returns None (after disconnecting) if the device was reachable but unusable this time around.
+    async fn connect_and_subscribe(
+        &mut self,
+        device: &btleplug::platform::Peripheral,
+        broadcast_tx: &BSender<AppUpdate>,
+    ) -> Result<Option<NotificationStream>, btleplug::Error> {
+        device.connect().await?;
+        self.send_connect_stage(broadcast_tx, ConnectStage::Subscribing);
+        if let Err(e) = device.discover_services().await {
+            error!("Couldn't read services from connected device: {}", e);
+            device.disconnect().await?;
+            return Ok(None);
+        }
+        let characteristics = device.characteristics();
+        let len = characteristics.len();
+        debug!("Found {len} characteristics");
+        // Save battery characteristic if present
+        if let Some(characteristic) = characteristics
+            .iter()
+            .find(|c| c.uuid == BATTERY_LEVEL_CHARACTERISTIC_UUID)
+        {
+            self.battery_characteristic = Some(characteristic.to_owned());
+            self.get_monitor_battery(device).await;
+            // Not required by the spec, we'll keep polling if it's not supported
+            if characteristic.properties.contains(CharPropFlags::NOTIFY) {
+                match device.subscribe(characteristic).await {
+                    Ok(_) => debug!("Subscribed to battery notifications"),
+                    Err(e) => warn!("Failed to subscribe to battery notifications: {e}"),
+                }
+            }
+        }
+
+        let device_information = read_device_information(device, &characteristics).await;
+        if !device_information.is_empty() {
+            let message = format!("{}: {device_information}", self.peripheral.name);
+            info!("Device information for {message}");
+            journal::record(Severity::Info, Subsystem::Ble, &message);
+        }
+        self.send_device_information(broadcast_tx, device_information);
+
+        let plx_characteristic = characteristics
+            .iter()
+            .find(|c| c.uuid == PLX_CONTINUOUS_MEASUREMENT_CHARACTERISTIC_UUID);
+        let plx_subscribed = match plx_characteristic {
+            Some(characteristic) => match device.subscribe(characteristic).await {
+                Ok(_) => {
+                    debug!("Subscribed to pulse oximeter measurements");
+                    true
+                }
+                Err(e) => {
+                    warn!("Failed to subscribe to pulse oximeter measurements: {e}");
+                    false
+                }
+            },
+            None => false,
+        };
+
+        if let Some(characteristic) = characteristics
+            .iter()
+            .find(|c| c.uuid == self.hr_measurement_uuid)
+        {
+            self.plx_pulse_only = false;
+            self.ftms_pulse_only = false;
+            self.hr_characteristic = Some(characteristic.to_owned());
+            if let Err(e) = device.subscribe(characteristic).await {
+                error!("Failed to subscribe to HR service: {e}");
+                self.needs_pairing = looks_unpaired(&e);
+                device.disconnect().await?;
+                return Ok(None);
+            }
+        } else if plx_subscribed {
+            info!("No HR service, using the pulse oximeter's pulse rate instead");
+            self.plx_pulse_only = true;
+            self.ftms_pulse_only = false;
+            self.hr_characteristic = plx_characteristic.cloned();
+        } else if let Some(characteristic) =
+            characteristics.iter().find(|c| is_machine_data(c.uuid))
+        {
+            info!("No HR service, using the fitness machine's heart rate instead");
+            self.plx_pulse_only = false;
+            self.ftms_pulse_only = true;
+            self.hr_characteristic = Some(characteristic.to_owned());
+            if let Err(e) = device.subscribe(characteristic).await {
+                error!("Failed to subscribe to fitness machine data: {e}");
+                self.needs_pairing = looks_unpaired(&e);
+                device.disconnect().await?;
+                return Ok(None);
+            }
+        } else {
+            error!("Didn't find HR service during notification setup!");
+            device.disconnect().await?;
+            return Ok(None);
+        }
+
+        if self.polar_ecg.is_some() {
+            start_polar_ecg(device, &characteristics).await;
+        }
+
+        if self.crank_cadence.is_some() {
+            subscribe_cadence(device, &characteristics).await;
+        }
+
+        match device.notifications().await {
+            Ok(stream) => Ok(Some(stream)),
+            Err(e) => {
+                error!("Failed to get HR BLE notification stream: {}", e);
+                device.disconnect().await?;
+                Ok(None)
+            }
+        }
+    }
+    /// Waits out the backoff before the next attempt, keeping listeners informed.
+    ///
+    /// Returns false if we should stop trying (shutting down, or out of retries).
+    async fn wait_to_retry(&mut self, broadcast_tx: &BSender<AppUpdate>) -> bool {
+        // Makes sure OSC's `connected` param goes false while we're retrying
+        self.send_hr(broadcast_tx, HeartRateStatus::default()).await;
+        let Some(delay) = self.backoff.next_delay(Instant::now()) else {
+            let window = self.backoff.window().unwrap_or_default().as_secs();
+            warn!("Couldn't reconnect to Heart Rate Monitor within {window} seconds, giving up");
+            self.send_reconnect_status(broadcast_tx, BleReconnect::GaveUp);
+            broadcast!(
+                broadcast_tx,
+                ErrorPopup::UserMustDismiss(format!(
+                    "Couldn't reconnect to device within {window} seconds."
+                ))
+            );
+            return false;
+        };
+        let attempt = self.backoff.attempt();
+        let message = format!(
+            "Reconnect attempt {attempt} in {} seconds",
+            delay.as_secs_f32()
+        );
+        info!("{message}");
+        journal::record(Severity::Info, Subsystem::Ble, &message);
+        self.send_reconnect_status(broadcast_tx, BleReconnect::Retrying { attempt, delay });
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => true,
+            _ = self.cancel_token.cancelled() => false,
+        }
+    }
     async fn notification_loop(
         &mut self,
         broadcast_tx: &BSender<AppUpdate>,
-        mut notification_stream: Pin<Box<dyn Stream<Item = ValueNotification> + Send>>,
+        mut notification_stream: NotificationStream,
         device: &btleplug::platform::Peripheral,
     ) -> Result<(), AppError> {
-        let mut battery_checking_interval = tokio::time::interval(Duration::from_secs(60 * 5));
+        // Fallback for devices that don't notify on battery changes
+        let mut battery_checking_interval = tokio::time::interval(self.battery_poll_interval);
+        let mut rssi_checking_interval = tokio::time::interval(Duration::from_secs(10));
+        let mut watchdog = NotifyWatchdog::new(self.watchdog_timeout, Instant::now());
         loop {
             tokio::select! {
                 // Assume we have a good connection if we keep getting updates
                 // HR update received
                 Some(data) = notification_stream.next() => {
-                    if data.uuid == HEART_RATE_MEASUREMENT_CHARACTERISTIC_UUID {
+                    if data.uuid == self.hr_measurement_uuid {
+                        watchdog.feed(Instant::now());
+                        raw_capture::record_ble(&data.value);
+                        if let Some(raw_dump) = self.raw_dump.as_mut() {
+                            if let Err(e) = raw_dump.record(&data.value) {
+                                warn!("Failed to write raw notification, no longer dumping: {e}");
+                                self.raw_dump = None;
+                            }
+                        }
                         let hr = self.handle_ble_hr(&data);
-                        broadcast!(broadcast_tx, hr);
+                        self.send_hr(broadcast_tx, hr).await;
+                    } else if data.uuid == PLX_CONTINUOUS_MEASUREMENT_CHARACTERISTIC_UUID {
+                        if let Some(measurement) = parse_plx_continuous(&data.value) {
+                            self.spo2 = Some(measurement.spo2);
+                            if self.plx_pulse_only {
+                                watchdog.feed(Instant::now());
+                                let bpm = measurement.pulse_rate.round().clamp(0.0, u16::MAX as f32) as u16;
+                                let hr = self.handle_pulse_only(bpm);
+                                self.send_hr(broadcast_tx, hr).await;
+                            }
+                        }
+                    } else if self.ftms_pulse_only && is_machine_data(data.uuid) {
+                        // Machines keep sending while nobody's holding the grips, just without HR
+                        watchdog.feed(Instant::now());
+                        raw_capture::record_ble(&data.value);
+                        if let Some(bpm) = parse_machine_heart_rate(data.uuid, &data.value) {
+                            let hr = self.handle_pulse_only(bpm);
+                            self.send_hr(broadcast_tx, hr).await;
+                        }
+                    } else if data.uuid == RSC_MEASUREMENT_CHARACTERISTIC_UUID {
+                        if self.crank_cadence.is_some() {
+                            self.cadence = parse_rsc_cadence(&data.value);
+                        }
+                    } else if data.uuid == CSC_MEASUREMENT_CHARACTERISTIC_UUID {
+                        if let Some(crank_cadence) = self.crank_cadence.as_mut() {
+                            if let Some((revolutions, event_time)) = parse_csc_crank(&data.value) {
+                                self.cadence = Some(crank_cadence.handle(revolutions, event_time, Instant::now()));
+                            }
+                        }
+                    } else if data.uuid == PMD_DATA_UUID {
+                        if let Some(polar_ecg) = self.polar_ecg.as_mut() {
+                            polar_ecg.handle_data(&data.value);
+                        }
+                    } else if data.uuid == BATTERY_LEVEL_CHARACTERISTIC_UUID {
+                        // Goes out with the next HR update
+                        if let Some(level) = parse_battery_level(&data.value) {
+                            debug!("Battery level notification: {level:?}");
+                            self.battery_level = level;
+                        }
                     }
                 }
                 _ = battery_checking_interval.tick() => {
                     self.get_monitor_battery(device).await;
                 }
-                _ = tokio::time::sleep(self.no_packet_timeout) => {
-                    error!("No HR data received in {} seconds!", self.no_packet_timeout.as_secs());
-                    return Ok(());
+                _ = rssi_checking_interval.tick() => {
+                    self.refresh_rssi(device, broadcast_tx).await;
+                }
+                _ = tokio::time::sleep_until(watchdog.deadline().into()) => {
+                    match watchdog.expired(Instant::now()) {
+                        WatchdogAction::Resubscribe => {
+                            let message = format!(
+                                "No HR data received in {} seconds, re-subscribing",
+                                self.watchdog_timeout.as_secs()
+                            );
+                            warn!("{message}");
+                            journal::record(Severity::Warning, Subsystem::Ble, &message);
+                            broadcast!(broadcast_tx, ErrorPopup::Intermittent(message));
+                            if !self.resubscribe(device).await {
+                                return Ok(());
+                            }
+                        }
+                        WatchdogAction::Reconnect => {
+                            error!("Still no HR data after re-subscribing, reconnecting");
+                            return Ok(());
+                        }
+                    }
                 }
                 _ = self.cancel_token.cancelled() => {
                     info!("Shutting down HR Notification thread!");
@@ -184,9 +537,121 @@ impl BleMonitorActor {
             }
         }
     }
+    /// Returns false if it didn't work out and we should reconnect instead.
+    async fn resubscribe(&self, device: &btleplug::platform::Peripheral) -> bool {
+        let Some(characteristic) = self.hr_characteristic.as_ref() else {
+            return false;
+        };
+        // Some devices refuse a second subscription, so failing here is fine
+        if let Err(e) = device.unsubscribe(characteristic).await {
+            debug!("Failed to unsubscribe from HR: {e}");
+        }
+        match device.subscribe(characteristic).await {
+            Ok(_) => true,
+            Err(e) => {
+                error!("Failed to re-subscribe to HR: {e}");
+                false
+            }
+        }
+    }
+    // The TUI only tracks the main device's connection
+    fn send_reconnect_status(&self, broadcast_tx: &BSender<AppUpdate>, status: BleReconnect) {
+        if self
+            .aggregate
+            .as_ref()
+            .is_some_and(AggregateInput::is_secondary)
+        {
+            debug!("Secondary device reconnect status: {status:?}");
+            return;
+        }
+        broadcast!(broadcast_tx, AppUpdate::BleReconnect(status));
+    }
+    // Same as above
+    fn send_connect_stage(&self, broadcast_tx: &BSender<AppUpdate>, stage: ConnectStage) {
+        if self
+            .aggregate
+            .as_ref()
+            .is_some_and(AggregateInput::is_secondary)
+        {
+            return;
+        }
+        broadcast!(broadcast_tx, AppUpdate::ConnectStage(stage));
+    }
+    /// Pairs through our own BlueZ agent, with any PIN the device asks for prompted for in the TUI.
+    ///
+    /// Returns whether it paired, pointing the user to the OS instead if it didn't.
+    #[cfg(all(feature = "pairing_agent", target_os = "linux"))]
+    async fn pair(&mut self, broadcast_tx: &BSender<AppUpdate>) -> bool {
+        let paired = pairing::pair(
+            &self.peripheral.id,
+            &self.peripheral.name,
+            self.pairing_tx.clone(),
+            &self.cancel_token,
+        )
+        .await;
+        match paired {
+            Ok(()) => {
+                let message = format!("Paired with {}", self.peripheral.name);
+                info!("{message}");
+                journal::record(Severity::Info, Subsystem::Ble, &message);
+                true
+            }
+            Err(e) => {
+                warn!("Couldn't pair with {}: {e}", self.peripheral.name);
+                self.hint_pairing(broadcast_tx);
+                false
+            }
+        }
+    }
+    /// btleplug can't pair (or answer a PIN prompt) on its own here, so the best we can do is point them to the OS.
+    #[cfg(not(all(feature = "pairing_agent", target_os = "linux")))]
+    async fn pair(&mut self, broadcast_tx: &BSender<AppUpdate>) -> bool {
+        self.hint_pairing(broadcast_tx);
+        false
+    }
+    /// Tells the user to pair the device in their system's Bluetooth settings, once.
+    fn hint_pairing(&mut self, broadcast_tx: &BSender<AppUpdate>) {
+        if std::mem::replace(&mut self.pairing_hint_shown, true) {
+            return;
+        }
+        let message = format!(
+            "{} refused HR notifications until it's paired. Pair it in your system's Bluetooth settings (entering its PIN there if it asks), it'll keep retrying in the meantime.",
+            self.peripheral.name
+        );
+        warn!("{message}");
+        journal::record(Severity::Warning, Subsystem::Ble, &message);
+        broadcast!(broadcast_tx, ErrorPopup::UserMustDismiss(message));
+    }
+    // Same as above, the popup's only for the main device
+    fn send_device_information(
+        &self,
+        broadcast_tx: &BSender<AppUpdate>,
+        device_information: DeviceInformation,
+    ) {
+        if self
+            .aggregate
+            .as_ref()
+            .is_some_and(AggregateInput::is_secondary)
+        {
+            return;
+        }
+        broadcast!(
+            broadcast_tx,
+            AppUpdate::DeviceInformation(device_information)
+        );
+    }
+    async fn send_hr(&self, broadcast_tx: &BSender<AppUpdate>, hr: HeartRateStatus) {
+        match self.aggregate.as_ref() {
+            Some(aggregate) => aggregate.send(hr).await,
+            None => {
+                broadcast!(broadcast_tx, hr, "Failed to broadcast HR update");
+            }
+        }
+    }
     fn handle_ble_hr(&mut self, data: &ValueNotification) -> HeartRateStatus {
         let timestamp = chrono::Local::now();
-        let new_hr_status = parse_hrm(&data.value);
+        let mut new_hr_status = parse_hrm(&data.value);
+        apply_rr_unit(&mut new_hr_status.rr_intervals, self.rr_unit);
         // An oddity I've noticed, is if we don't get an RR interval each update,
         // there's a decent chance that the next one we do get will be weirdly high.
         // So we'll just ignore the first few values we get after an empty set.
@@ -202,7 +667,11 @@ impl BleMonitorActor {
         } else {
             self.rr_left_to_burn.saturating_sub(new_interval_count)
         };
+        if let Some(polar_ecg) = self.polar_ecg.as_ref() {
+            polar_ecg.compare_rr(rr_intervals.last());
+        }
         let (twitch_up, twitch_down) = self.twitcher.handle(new_hr_status.bpm, &rr_intervals);
+        let stress_index = self.stress_index.handle(&rr_intervals);
 
         HeartRateStatus {
             heart_rate_bpm: new_hr_status.bpm,
@@ -210,41 +679,307 @@ impl BleMonitorActor {
             battery_level: self.battery_level,
             twitch_up,
             twitch_down,
+            stress_index,
+            rssi: self.rssi,
+            spo2: self.spo2,
+            cadence: self.cadence,
+            source: PRIMARY_SOURCE,
             timestamp,
         }
     }
+    // Pulse oximeters don't send RR intervals
+    /// For sources without RR intervals, i.e. the pulse oximeter or a fitness machine.
+    fn handle_pulse_only(&mut self, bpm: u16) -> HeartRateStatus {
+        let (twitch_up, twitch_down) = self.twitcher.handle(bpm, &[]);
+        HeartRateStatus {
+            heart_rate_bpm: bpm,
+            battery_level: self.battery_level,
+            twitch_up,
+            twitch_down,
+            rssi: self.rssi,
+            spo2: self.spo2,
+            cadence: self.cadence,
+            timestamp: chrono::Local::now(),
+            ..Default::default()
+        }
+    }
+    // Not every platform updates this while connected, in which case we keep the last one
+    async fn refresh_rssi(
+        &mut self,
+        device: &btleplug::platform::Peripheral,
+        broadcast_tx: &BSender<AppUpdate>,
+    ) {
+        let rssi = match device.properties().await {
+            Ok(Some(properties)) => properties.rssi,
+            Ok(None) => None,
+            Err(e) => {
+                debug!("Failed to read device properties: {e}");
+                None
+            }
+        };
+        let Some(rssi) = rssi else {
+            return;
+        };
+        if self.rssi != Some(rssi) {
+            debug!("RSSI: {rssi} dBm");
+        }
+        self.rssi = Some(rssi);
+        match self.weak_signal.as_mut().and_then(|weak| weak.update(rssi)) {
+            Some(true) => {
+                let message = format!("Weak signal from device ({rssi} dBm), data may be choppy");
+                warn!("{message}");
+                broadcast!(broadcast_tx, ErrorPopup::Intermittent(message));
+            }
+            Some(false) => info!("Signal recovered ({rssi} dBm)"),
+            None => {}
+        }
+    }
     async fn get_monitor_battery(&mut self, device: &btleplug::platform::Peripheral) {
         if let Some(characteristic) = self.battery_characteristic.as_ref() {
-            self.battery_level = device.read(characteristic).await.map_or_else(
-                |_| {
-                    warn!("Failed to refresh battery level, keeping last");
-                    self.battery_level
-                },
-                |v| BatteryLevel::Level(v[0]),
+            match device
+                .read(characteristic)
+                .await
+                .map(|v| parse_battery_level(&v))
+            {
+                Ok(Some(level)) => {
+                    self.battery_level = level;
+                }
+                _ => warn!("Failed to refresh battery level, keeping last"),
+            }
+        }
+    }
+    fn run_hook(&self, event: HookEvent) {
+        self.hooks.run(
+            event,
+            &self.peripheral.name,
+            &self.peripheral.address,
+            self.battery_level,
+        );
+    }
+}
+
+/// Asks a Polar device to start streaming ECG over its PMD service.
+///
+/// Failures are only logged, HR carries on either way.
+/// Streaming stops by itself when disconnected.
+async fn start_polar_ecg(
+    device: &btleplug::platform::Peripheral,
+    characteristics: &std::collections::BTreeSet<Characteristic>,
+) {
+    let find = |uuid| {
+        characteristics
+            .iter()
+            .find(|c| c.service_uuid == PMD_SERVICE_UUID && c.uuid == uuid)
+    };
+    let (Some(control_point), Some(data)) = (find(PMD_CONTROL_POINT_UUID), find(PMD_DATA_UUID))
+    else {
+        warn!("Polar ECG enabled, but the device doesn't have the PMD service");
+        return;
+    };
+    // The control point's responses come as indications, which need to be on before writing
+    for characteristic in [control_point, data] {
+        if let Err(e) = device.subscribe(characteristic).await {
+            warn!(
+                "Failed to subscribe to PMD characteristic {}: {e}",
+                characteristic.uuid
             );
+            return;
+        }
+    }
+    match device
+        .write(control_point, &START_ECG_COMMAND, WriteType::WithResponse)
+        .await
+    {
+        Ok(_) => info!("Started Polar ECG stream"),
+        Err(e) => warn!("Failed to start Polar ECG stream: {e}"),
+    }
+}
+
+/// Reads whichever Device Information strings the device has.
+///
+/// Failures are only logged, it's purely informational.
+async fn read_device_information(
+    device: &btleplug::platform::Peripheral,
+    characteristics: &std::collections::BTreeSet<Characteristic>,
+) -> DeviceInformation {
+    let mut device_information = DeviceInformation::default();
+    for characteristic in characteristics.iter().filter(|c| {
+        c.service_uuid == DEVICE_INFORMATION_SERVICE_UUID
+            && is_device_information(c.uuid)
+            && c.properties.contains(CharPropFlags::READ)
+    }) {
+        match device.read(characteristic).await {
+            Ok(value) => device_information.set(characteristic.uuid, &value),
+            Err(e) => debug!(
+                "Failed to read device information ({}): {e}",
+                characteristic.uuid
+            ),
         }
     }
+    device_information
+}
+
+/// Subscribes to whichever of CSC and RSC the device has, alongside HR.
+///
+/// Failures are only logged, HR carries on either way.
+async fn subscribe_cadence(
+    device: &btleplug::platform::Peripheral,
+    characteristics: &std::collections::BTreeSet<Characteristic>,
+) {
+    let mut found = false;
+    for characteristic in characteristics.iter().filter(|c| {
+        (c.service_uuid == CSC_SERVICE_UUID && c.uuid == CSC_MEASUREMENT_CHARACTERISTIC_UUID)
+            || (c.service_uuid == RSC_SERVICE_UUID && c.uuid == RSC_MEASUREMENT_CHARACTERISTIC_UUID)
+    }) {
+        found = true;
+        match device.subscribe(characteristic).await {
+            Ok(_) => debug!("Subscribed to cadence ({})", characteristic.uuid),
+            Err(e) => warn!(
+                "Failed to subscribe to cadence ({}): {e}",
+                characteristic.uuid
+            ),
+        }
+    }
+    if !found {
+        info!("Cadence enabled, but the device doesn't have a cadence service");
+    }
+}
+
+/// Full UUIDs, or the 16-bit short form (i.e. "2a37") on the Bluetooth base UUID.
+fn parse_characteristic_uuid(uuid: &str) -> Option<Uuid> {
+    let uuid = uuid.trim();
+    if uuid.len() == 4 {
+        let short = u16::from_str_radix(uuid, 16).ok()?;
+        return Some(Uuid::from_u128(
+            ((short as u128) << 96) | 0x00000000_0000_1000_8000_00805f9b34fb,
+        ));
+    }
+    Uuid::parse_str(uuid).ok()
 }
 
+/// Whether a GATT error looks like the device wants to be bonded first.
+///
+/// Every platform words it differently, and btleplug passes most through as text.
+fn looks_unpaired(error: &btleplug::Error) -> bool {
+    if matches!(error, btleplug::Error::PermissionDenied) {
+        return true;
+    }
+    let error = error.to_string().to_lowercase();
+    [
+        // BlueZ and CoreBluetooth
+        "authentication",
+        "encryption",
+        "notpermitted",
+        "not permitted",
+        // WinRT's GattCommunicationStatus
+        "accessdenied",
+        "access denied",
+    ]
+    .iter()
+    .any(|hint| error.contains(hint))
+}
+
+/// Battery Level is a single byte percentage, anything over 100 is out of spec.
+fn parse_battery_level(value: &[u8]) -> Option<BatteryLevel> {
+    value
+        .first()
+        .map(|&level| BatteryLevel::Level(level.min(100)))
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn start_notification_thread(
     broadcast_tx: BSender<AppUpdate>,
     restart_tx: Sender<()>,
     peripheral: DeviceInfo,
-    rr_cooldown_amount: usize,
-    twitch_threshold: f32,
+    ble_settings: BLESettings,
+    twitch_threshold: TwitchThreshold,
+    stress_index_window: usize,
+    aggregate: Option<AggregateInput>,
+    pairing_tx: PairingTx,
     cancel_token: CancellationToken,
 ) {
-    let no_packet_timeout = Duration::from_secs(30);
     let battery_level = BatteryLevel::NotReported;
+    let rr_cooldown_amount = ble_settings.rr_ignore_after_empty as usize;
+    let reconnect_window = match ble_settings.reconnect_window_sec {
+        0 => None,
+        secs => Some(Duration::from_secs(secs as u64)),
+    };
+    let weak_signal = (ble_settings.rssi_warning_threshold != 0).then_some(WeakSignal {
+        threshold: ble_settings.rssi_warning_threshold,
+        weak: false,
+    });
+    let backoff = Backoff::new(
+        Duration::from_secs(ble_settings.reconnect_initial_delay_sec.max(1) as u64),
+        Duration::from_secs(ble_settings.reconnect_max_delay_sec.max(1) as u64),
+        reconnect_window,
+    );
+    let raw_dump = if ble_settings.raw_dump_enabled {
+        match RawDump::open(Path::new(&ble_settings.raw_dump_path), &peripheral.name) {
+            Ok(raw_dump) => {
+                info!(
+                    "Dumping raw HR notifications to {}",
+                    ble_settings.raw_dump_path
+                );
+                Some(raw_dump)
+            }
+            Err(e) => {
+                warn!("Couldn't open raw notification dump: {e}");
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let quirk = ble_settings
+        .quirk_for(&peripheral.name, &peripheral.address)
+        .cloned()
+        .unwrap_or_default();
+    let hr_measurement_uuid = match quirk.hr_characteristic.as_str() {
+        "" => HEART_RATE_MEASUREMENT_CHARACTERISTIC_UUID,
+        uuid => parse_characteristic_uuid(uuid).unwrap_or_else(|| {
+            warn!("Invalid quirk hr_characteristic \"{uuid}\", using the standard one");
+            HEART_RATE_MEASUREMENT_CHARACTERISTIC_UUID
+        }),
+    };
+    if quirk != Default::default() {
+        info!(
+            "Using quirks for {}: HR from {hr_measurement_uuid}, RR in {:?}",
+            peripheral.name, quirk.rr_unit
+        );
+    }
+    let polar_ecg = ble_settings
+        .polar_ecg_enabled
+        .then(|| PolarEcg::new(&ble_settings.polar_ecg_log_path));
     let mut ble_monitor = BleMonitorActor {
         peripheral,
-        no_packet_timeout,
+        connect_timeout: Duration::from_secs(ble_settings.connect_timeout_sec.max(1) as u64),
+        watchdog_timeout: Duration::from_secs(ble_settings.notification_watchdog_sec.max(1) as u64),
         battery_characteristic: None,
+        hr_characteristic: None,
+        battery_poll_interval: Duration::from_secs(ble_settings.battery_poll_sec.max(1) as u64),
         cancel_token,
+        hr_measurement_uuid,
+        rr_unit: quirk.rr_unit,
         battery_level,
         twitcher: Twitcher::new(twitch_threshold),
+        stress_index: StressIndex::new(stress_index_window),
         rr_cooldown_amount,
         rr_left_to_burn: rr_cooldown_amount,
+        backoff,
+        rssi: None,
+        spo2: None,
+        plx_pulse_only: false,
+        ftms_pulse_only: false,
+        weak_signal,
+        aggregate,
+        raw_dump,
+        polar_ecg,
+        crank_cadence: ble_settings.cadence_enabled.then(CrankCadence::default),
+        cadence: None,
+        hooks: Hooks::new(ble_settings.hooks),
+        pairing_tx,
+        needs_pairing: false,
+        pairing_hint_shown: false,
     };
 
     if let Err(e) = ble_monitor.connect(&broadcast_tx, restart_tx).await {
@@ -253,3 +988,68 @@ pub async fn start_notification_thread(
         broadcast!(broadcast_tx, ErrorPopup::detailed(message, e));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weak_signal_warns_once() {
+        let mut weak = WeakSignal {
+            threshold: -85,
+            weak: false,
+        };
+        assert_eq!(weak.update(-70), None);
+        assert_eq!(weak.update(-90), Some(true));
+        assert_eq!(weak.update(-95), None);
+        // Hovering right around the threshold doesn't count as recovered
+        assert_eq!(weak.update(-84), None);
+        assert_eq!(weak.update(-80), Some(false));
+    }
+    #[test]
+    fn battery_level_parsing() {
+        assert_eq!(parse_battery_level(&[]), None);
+        assert_eq!(parse_battery_level(&[42]), Some(BatteryLevel::Level(42)));
+        assert_eq!(parse_battery_level(&[255]), Some(BatteryLevel::Level(100)));
+    }
+    #[test]
+    fn quirk_characteristic_uuids() {
+        assert_eq!(
+            parse_characteristic_uuid("2a37"),
+            Some(HEART_RATE_MEASUREMENT_CHARACTERISTIC_UUID)
+        );
+        assert_eq!(
+            parse_characteristic_uuid("00002A37-0000-1000-8000-00805F9B34FB"),
+            Some(HEART_RATE_MEASUREMENT_CHARACTERISTIC_UUID)
+        );
+        assert_eq!(parse_characteristic_uuid("zzzz"), None);
+        assert_eq!(parse_characteristic_uuid("2a3"), None);
+    }
+    #[test]
+    fn unpaired_errors() {
+        assert!(looks_unpaired(&btleplug::Error::Other(
+            "org.bluez.Error.NotPermitted: Insufficient Authentication".into()
+        )));
+        assert!(looks_unpaired(&btleplug::Error::Other(
+            "Authentication is insufficient.".into()
+        )));
+        assert!(looks_unpaired(&btleplug::Error::PermissionDenied));
+        assert!(!looks_unpaired(&btleplug::Error::NotConnected));
+        assert!(!looks_unpaired(&btleplug::Error::Other(
+            "Operation timed out".into()
+        )));
+    }
+    #[test]
+    fn watchdog_resubscribes_then_reconnects() {
+        let start = Instant::now();
+        let mut watchdog = NotifyWatchdog::new(Duration::from_secs(10), start);
+        assert_eq!(watchdog.deadline(), start + Duration::from_secs(10));
+        let first = start + Duration::from_secs(10);
+        assert_eq!(watchdog.expired(first), WatchdogAction::Resubscribe);
+        assert_eq!(watchdog.deadline(), first + Duration::from_secs(10));
+        assert_eq!(watchdog.expired(first), WatchdogAction::Reconnect);
+        // Data coming back in means the next stall gets a re-subscribe again
+        watchdog.feed(first);
+        assert_eq!(watchdog.expired(first), WatchdogAction::Resubscribe);
+    }
+}
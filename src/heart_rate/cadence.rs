@@ -0,0 +1,139 @@
+// Cycling Speed and Cadence (CSC) and Running Speed and Cadence (RSC) parsing,
+// for showing cadence from the same device alongside HR
+// https://www.bluetooth.com/specifications/specs/cycling-speed-and-cadence-service-1-0/
+// https://www.bluetooth.com/specifications/specs/running-speed-and-cadence-service-1-0/
+
+use std::time::{Duration, Instant};
+
+use uuid::Uuid;
+
+pub const CSC_SERVICE_UUID: Uuid = Uuid::from_u128(0x00001816_0000_1000_8000_00805f9b34fb); // 00001816-0000-1000-8000-00805f9b34fb
+pub const CSC_MEASUREMENT_CHARACTERISTIC_UUID: Uuid =
+    Uuid::from_u128(0x00002a5b_0000_1000_8000_00805f9b34fb); // 00002a5b-0000-1000-8000-00805f9b34fb
+pub const RSC_SERVICE_UUID: Uuid = Uuid::from_u128(0x00001814_0000_1000_8000_00805f9b34fb); // 00001814-0000-1000-8000-00805f9b34fb
+pub const RSC_MEASUREMENT_CHARACTERISTIC_UUID: Uuid =
+    Uuid::from_u128(0x00002a53_0000_1000_8000_00805f9b34fb); // 00002a53-0000-1000-8000-00805f9b34fb
+
+// CSC flag bits
+const WHEEL_REVOLUTION_DATA_PRESENT: u8 = 1 << 0;
+const CRANK_REVOLUTION_DATA_PRESENT: u8 = 1 << 1;
+// Crank event times are in 1/1024ths of a second
+const EVENT_TIME_RESOLUTION: f32 = 1024.0;
+// No new crank event for this long means they stopped pedaling
+const CRANK_STOPPED_AFTER: Duration = Duration::from_secs(3);
+
+/// Instantaneous Cadence from an RSC Measurement, in steps per minute.
+pub fn parse_rsc_cadence(data: &[u8]) -> Option<u16> {
+    // Flags, Instantaneous Speed (u16), then Instantaneous Cadence
+    data.get(3).map(|&cadence| cadence as u16)
+}
+
+/// Cumulative Crank Revolutions and Last Crank Event Time from a CSC Measurement.
+///
+/// None if the sensor only sends wheel data (i.e. a speed-only sensor).
+pub fn parse_csc_crank(data: &[u8]) -> Option<(u16, u16)> {
+    let flags = *data.first()?;
+    if flags & CRANK_REVOLUTION_DATA_PRESENT == 0 {
+        return None;
+    }
+    // Skipping Cumulative Wheel Revolutions (u32) and Last Wheel Event Time (u16)
+    let offset = if flags & WHEEL_REVOLUTION_DATA_PRESENT != 0 {
+        7
+    } else {
+        1
+    };
+    let crank = data.get(offset..offset + 4)?;
+    Some((
+        u16::from_le_bytes([crank[0], crank[1]]),
+        u16::from_le_bytes([crank[2], crank[3]]),
+    ))
+}
+
+/// Turns CSC's running crank totals into RPM.
+///
+/// Sensors repeat the last event while nothing's changed, so the
+/// RPM is held until no new revolution shows up for a few seconds.
+#[derive(Debug, Default)]
+pub struct CrankCadence {
+    // (Cumulative revolutions, last event time)
+    last_event: Option<(u16, u16)>,
+    last_change: Option<Instant>,
+    rpm: u16,
+}
+
+impl CrankCadence {
+    pub fn handle(&mut self, revolutions: u16, event_time: u16, now: Instant) -> u16 {
+        match self.last_event {
+            Some((last_revolutions, last_time)) if last_time != event_time => {
+                // Both wrap around, ~64 seconds for the event time
+                let revolutions = revolutions.wrapping_sub(last_revolutions) as f32;
+                let elapsed = event_time.wrapping_sub(last_time) as f32 / EVENT_TIME_RESOLUTION;
+                self.rpm = (revolutions * 60.0 / elapsed).round() as u16;
+                self.last_change = Some(now);
+            }
+            Some(_) => {
+                if self
+                    .last_change
+                    .is_none_or(|last| now.saturating_duration_since(last) >= CRANK_STOPPED_AFTER)
+                {
+                    self.rpm = 0;
+                }
+            }
+            None => self.last_change = Some(now),
+        }
+        self.last_event = Some((revolutions, event_time));
+        self.rpm
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rsc_cadence() {
+        // Flags, 3.00 m/s, 172 steps/min, then Instantaneous Stride Length
+        let data = [0x01, 0x00, 0x03, 0xAC, 0x8C, 0x00];
+        assert_eq!(parse_rsc_cadence(&data), Some(172));
+        assert_eq!(parse_rsc_cadence(&data[..3]), None);
+    }
+    #[test]
+    fn csc_crank_data() {
+        // Wheel and crank data
+        let data = [
+            0x03, // Flags
+            0x10, 0x00, 0x00, 0x00, // 16 wheel revolutions
+            0x00, 0x04, // Wheel event at 1s
+            0x2A, 0x00, // 42 crank revolutions
+            0x00, 0x08, // Crank event at 2s
+        ];
+        assert_eq!(parse_csc_crank(&data), Some((42, 2048)));
+        // Crank data only
+        assert_eq!(
+            parse_csc_crank(&[0x02, 0x2A, 0x00, 0x00, 0x08]),
+            Some((42, 2048))
+        );
+        // Speed sensor
+        assert_eq!(parse_csc_crank(&data[..7]), None);
+        assert_eq!(
+            parse_csc_crank(&[0x01, 0x10, 0x00, 0x00, 0x00, 0x00, 0x04]),
+            None
+        );
+    }
+    #[test]
+    fn crank_rpm() {
+        let mut cadence = CrankCadence::default();
+        let start = Instant::now();
+        assert_eq!(cadence.handle(10, 0, start), 0);
+        // 1.5 revolutions per second
+        assert_eq!(cadence.handle(13, 2048, start + Duration::from_secs(2)), 90);
+        // Same event repeated, still pedaling as far as we know
+        assert_eq!(cadence.handle(13, 2048, start + Duration::from_secs(3)), 90);
+        // Wrapping around both counters
+        let mut cadence = CrankCadence::default();
+        cadence.handle(u16::MAX, u16::MAX - 511, start);
+        assert_eq!(cadence.handle(0, 512, start + Duration::from_secs(1)), 60);
+        // Then stopped
+        assert_eq!(cadence.handle(0, 512, start + Duration::from_secs(5)), 0);
+    }
+}
@@ -0,0 +1,121 @@
+// Device Information Service (DIS), read once on connect so issue reports can say
+// exactly which model/firmware a parsing quirk showed up on
+// https://www.bluetooth.com/specifications/specs/device-information-service-1-1/
+
+use std::fmt;
+
+use uuid::Uuid;
+
+pub const DEVICE_INFORMATION_SERVICE_UUID: Uuid =
+    Uuid::from_u128(0x0000180a_0000_1000_8000_00805f9b34fb); // 0000180a-0000-1000-8000-00805f9b34fb
+pub const MODEL_NUMBER_CHARACTERISTIC_UUID: Uuid =
+    Uuid::from_u128(0x00002a24_0000_1000_8000_00805f9b34fb); // 00002a24-0000-1000-8000-00805f9b34fb
+pub const SERIAL_NUMBER_CHARACTERISTIC_UUID: Uuid =
+    Uuid::from_u128(0x00002a25_0000_1000_8000_00805f9b34fb); // 00002a25-0000-1000-8000-00805f9b34fb
+pub const FIRMWARE_REVISION_CHARACTERISTIC_UUID: Uuid =
+    Uuid::from_u128(0x00002a26_0000_1000_8000_00805f9b34fb); // 00002a26-0000-1000-8000-00805f9b34fb
+pub const HARDWARE_REVISION_CHARACTERISTIC_UUID: Uuid =
+    Uuid::from_u128(0x00002a27_0000_1000_8000_00805f9b34fb); // 00002a27-0000-1000-8000-00805f9b34fb
+pub const SOFTWARE_REVISION_CHARACTERISTIC_UUID: Uuid =
+    Uuid::from_u128(0x00002a28_0000_1000_8000_00805f9b34fb); // 00002a28-0000-1000-8000-00805f9b34fb
+pub const MANUFACTURER_NAME_CHARACTERISTIC_UUID: Uuid =
+    Uuid::from_u128(0x00002a29_0000_1000_8000_00805f9b34fb); // 00002a29-0000-1000-8000-00805f9b34fb
+
+/// Whatever the device was willing to tell us, every field's optional.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceInformation {
+    pub manufacturer: Option<String>,
+    pub model: Option<String>,
+    pub serial: Option<String>,
+    pub firmware: Option<String>,
+    pub hardware: Option<String>,
+    pub software: Option<String>,
+}
+
+impl DeviceInformation {
+    /// Stores a read value if `uuid` is one of the DIS strings we care about.
+    pub fn set(&mut self, uuid: Uuid, value: &[u8]) {
+        let field = match uuid {
+            MANUFACTURER_NAME_CHARACTERISTIC_UUID => &mut self.manufacturer,
+            MODEL_NUMBER_CHARACTERISTIC_UUID => &mut self.model,
+            SERIAL_NUMBER_CHARACTERISTIC_UUID => &mut self.serial,
+            FIRMWARE_REVISION_CHARACTERISTIC_UUID => &mut self.firmware,
+            HARDWARE_REVISION_CHARACTERISTIC_UUID => &mut self.hardware,
+            SOFTWARE_REVISION_CHARACTERISTIC_UUID => &mut self.software,
+            _ => return,
+        };
+        *field = parse_string(value);
+    }
+    pub fn is_empty(&self) -> bool {
+        self.rows().iter().all(|(_, value)| value.is_none())
+    }
+    /// Rows that could pick out the exact device, hidden while sensitive info is.
+    pub const IDENTIFYING_ROWS: [&'static str; 1] = ["Serial"];
+    pub fn rows(&self) -> [(&'static str, Option<&str>); 6] {
+        [
+            ("Manufacturer", self.manufacturer.as_deref()),
+            ("Model", self.model.as_deref()),
+            ("Serial", self.serial.as_deref()),
+            ("Firmware", self.firmware.as_deref()),
+            ("Hardware", self.hardware.as_deref()),
+            ("Software", self.software.as_deref()),
+        ]
+    }
+}
+
+pub fn is_device_information(uuid: Uuid) -> bool {
+    matches!(
+        uuid,
+        MANUFACTURER_NAME_CHARACTERISTIC_UUID
+            | MODEL_NUMBER_CHARACTERISTIC_UUID
+            | SERIAL_NUMBER_CHARACTERISTIC_UUID
+            | FIRMWARE_REVISION_CHARACTERISTIC_UUID
+            | HARDWARE_REVISION_CHARACTERISTIC_UUID
+            | SOFTWARE_REVISION_CHARACTERISTIC_UUID
+    )
+}
+
+// Meant to be UTF-8, but some devices pad with NULs or spaces
+fn parse_string(value: &[u8]) -> Option<String> {
+    let value = String::from_utf8_lossy(value);
+    let value = value.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    (!value.is_empty()).then(|| value.to_owned())
+}
+
+impl fmt::Display for DeviceInformation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+        for (name, value) in self.rows() {
+            let Some(value) = value else {
+                continue;
+            };
+            if !std::mem::take(&mut first) {
+                write!(f, ", ")?;
+            }
+            write!(f, "{name}: {value}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_padded_strings() {
+        let mut info = DeviceInformation::default();
+        assert!(info.is_empty());
+        info.set(MANUFACTURER_NAME_CHARACTERISTIC_UUID, b"Polar Electro Oy\0");
+        info.set(FIRMWARE_REVISION_CHARACTERISTIC_UUID, b"5.0.0  ");
+        info.set(SERIAL_NUMBER_CHARACTERISTIC_UUID, b"\0\0\0");
+        // Not a DIS string
+        info.set(DEVICE_INFORMATION_SERVICE_UUID, b"nope");
+        assert_eq!(info.manufacturer.as_deref(), Some("Polar Electro Oy"));
+        assert_eq!(info.serial, None);
+        assert_eq!(
+            info.to_string(),
+            "Manufacturer: Polar Electro Oy, Firmware: 5.0.0"
+        );
+    }
+}
@@ -1,68 +1,165 @@
+use super::stress::StressIndex;
 use super::{rr_from_bpm, BatteryLevel, HeartRateStatus};
-use crate::app::{AppUpdate, ErrorPopup};
+use crate::app::{AppUpdate, AppView, ErrorPopup};
 use crate::broadcast;
-use crate::settings::DummySettings;
+use crate::settings::{DummyMode, DummySettings};
+use crate::sources::{Capabilities, HeartRateSource, HrSource, SourceContext};
 
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::f32::consts::PI;
 use std::time::Duration;
 use tracing::info;
 
+use tokio::task::JoinHandle;
 use tokio::time;
 use tokio_util::sync::CancellationToken;
 
 use tokio::sync::broadcast::Sender as BSender;
 
+// How hard the random walk gets pulled back towards the middle of the range
+const WALK_PULL: f32 = 0.05;
+const WALK_STEP: f32 = 2.0;
+
+enum DummyTick {
+    Reading { bpm: u16, rr: Duration },
+    // Simulating a lost connection
+    Disconnected { ticks_left: u32 },
+}
+
+struct Simulator {
+    mode: DummyMode,
+    low_bpm: f32,
+    high_bpm: f32,
+    loops_before_dc: u32,
+    rr_jitter_ms: f32,
+    artifact_chance: f64,
+    tick: u32,
+    // Only used by the random walk
+    walk_bpm: f32,
+    rng: StdRng,
+}
+
+impl Simulator {
+    fn new(dummy_settings: &DummySettings, vhs_mode: bool, rng: StdRng) -> Self {
+        let low_bpm = dummy_settings.low_bpm.min(dummy_settings.high_bpm) as f32;
+        let high_bpm = dummy_settings.high_bpm.max(dummy_settings.low_bpm) as f32;
+        // VHS recordings need to come out the same every time
+        let (loops_before_dc, rr_jitter_ms, artifact_chance) = if vhs_mode {
+            (0, 0.0, 0.0)
+        } else {
+            (
+                dummy_settings.loops_before_dc as u32,
+                dummy_settings.rr_jitter_ms as f32,
+                dummy_settings.artifact_chance.clamp(0.0, 1.0) as f64,
+            )
+        };
+        Self {
+            mode: dummy_settings.mode,
+            low_bpm,
+            high_bpm,
+            loops_before_dc,
+            rr_jitter_ms,
+            artifact_chance,
+            tick: 0,
+            walk_bpm: (low_bpm + high_bpm) / 2.0,
+            rng,
+        }
+    }
+    // Ticks it takes to go from low to high (or back)
+    fn leg_ticks(&self) -> u32 {
+        ((self.high_bpm - self.low_bpm) as u32).max(1)
+    }
+    fn bpm(&mut self) -> f32 {
+        let leg = self.leg_ticks();
+        let position = self.tick % (leg * 2);
+        // 0 at low, 1 at high
+        let progress = if position <= leg {
+            position as f32 / leg as f32
+        } else {
+            (leg * 2 - position) as f32 / leg as f32
+        };
+        let range = self.high_bpm - self.low_bpm;
+        match self.mode {
+            DummyMode::Triangle => self.low_bpm + range * progress,
+            DummyMode::Sine => {
+                let phase = self.tick as f32 / leg as f32 * PI;
+                self.low_bpm + range * (1.0 - phase.cos()) / 2.0
+            }
+            DummyMode::RandomWalk => {
+                let middle = (self.low_bpm + self.high_bpm) / 2.0;
+                let step = self.rng.gen_range(-WALK_STEP..=WALK_STEP);
+                self.walk_bpm += step + (middle - self.walk_bpm) * WALK_PULL;
+                self.walk_bpm = self.walk_bpm.clamp(self.low_bpm, self.high_bpm);
+                self.walk_bpm
+            }
+        }
+    }
+    fn rr(&mut self, bpm: u16) -> Duration {
+        let mut rr = rr_from_bpm(bpm).as_secs_f32() * 1000.0;
+        if self.rr_jitter_ms > 0.0 {
+            rr += self.rng.gen_range(-self.rr_jitter_ms..=self.rr_jitter_ms);
+        }
+        if self.rng.gen_bool(self.artifact_chance) {
+            // Missed beat or an extra one squeezed in
+            rr *= if self.rng.gen_bool(0.5) { 2.0 } else { 0.5 };
+        }
+        Duration::from_secs_f32(rr.max(1.0) / 1000.0)
+    }
+    fn next(&mut self) -> DummyTick {
+        let leg = self.leg_ticks();
+        let bpm = self.bpm().round() as u16;
+        let rr = self.rr(bpm);
+        let legs_done = self.tick / leg;
+        let ticks_left = leg - self.tick % leg;
+        self.tick = self.tick.wrapping_add(1);
+        // Every loops_before_dc legs, the next one is spent "disconnected"
+        if self.loops_before_dc != 0
+            && legs_done % (self.loops_before_dc + 1) == self.loops_before_dc
+        {
+            DummyTick::Disconnected { ticks_left }
+        } else {
+            DummyTick::Reading { bpm, rr }
+        }
+    }
+}
+
 pub async fn dummy_thread(
     broadcast_tx: BSender<AppUpdate>,
     dummy_settings: DummySettings,
     seconds_override: Option<f32>,
     vhs_mode: bool,
+    stress_index_window: usize,
     cancel_token: CancellationToken,
 ) {
     let bpm_updates_per_sec = seconds_override.unwrap_or(dummy_settings.bpm_speed);
     let bpm_update_interval = Duration::from_secs_f32(1.0 / (bpm_updates_per_sec));
     let mut bpm_update_interval = time::interval(bpm_update_interval);
-    let low_bpm = dummy_settings.low_bpm;
-    let high_bpm = dummy_settings.high_bpm;
-    let mut loops_before_dc = dummy_settings.loops_before_dc;
 
-    let mut loops: u16 = 0;
-    let mut positive_direction = true;
+    let mut simulator = Simulator::new(&dummy_settings, vhs_mode, StdRng::from_entropy());
     let mut hr_status = HeartRateStatus {
-        heart_rate_bpm: low_bpm.saturating_sub(1),
         battery_level: BatteryLevel::Level(100),
         ..Default::default()
     };
 
-    let mut dummy_tick = || {
-        hr_status.timestamp = chrono::Local::now();
-        if vhs_mode {
-            loops_before_dc = 0;
-        }
-        let bound = if positive_direction {
-            hr_status.heart_rate_bpm += 1;
-            high_bpm
-        } else {
-            hr_status.heart_rate_bpm -= 1;
-            low_bpm
-        };
-        hr_status.rr_intervals = vec![rr_from_bpm(hr_status.heart_rate_bpm)];
-        if hr_status.heart_rate_bpm == bound {
-            positive_direction = !positive_direction;
-            loops += 1;
-            if loops > loops_before_dc {
-                loops = 0;
-            }
+    let mut stress_index = StressIndex::new(stress_index_window);
+
+    let mut dummy_tick = || match simulator.next() {
+        DummyTick::Reading { bpm, rr } => {
+            hr_status.timestamp = chrono::Local::now();
+            hr_status.heart_rate_bpm = bpm;
+            hr_status.rr_intervals = vec![rr];
+            hr_status.stress_index = stress_index.handle(&hr_status.rr_intervals);
+            broadcast!(broadcast_tx, hr_status.clone());
         }
-        if loops == loops_before_dc && loops_before_dc != 0 {
+        DummyTick::Disconnected { ticks_left } => {
             broadcast!(
                 broadcast_tx,
                 ErrorPopup::Intermittent(format!(
                     "Simulating lost connection ({:.0} seconds left)",
-                    bound.abs_diff(hr_status.heart_rate_bpm) as f32 / dummy_settings.bpm_speed
+                    ticks_left as f32 / bpm_updates_per_sec
                 ),)
             );
-        } else {
-            broadcast!(broadcast_tx, hr_status.clone());
         }
     };
 
@@ -90,3 +187,105 @@ pub async fn dummy_thread(
         }
     }
 }
+
+#[derive(Default)]
+pub struct DummySource {
+    // From the `dummy` subcommand
+    pub speed_override: Option<f32>,
+    pub vhs_mode: bool,
+}
+
+impl HeartRateSource for DummySource {
+    fn source(&self) -> HrSource {
+        HrSource::Dummy
+    }
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::FULL
+    }
+    // Nothing to wait on
+    fn waiting_view(&self) -> AppView {
+        AppView::HeartRateView
+    }
+    fn spawn(self: Box<Self>, context: SourceContext) -> JoinHandle<()> {
+        tokio::spawn(dummy_thread(
+            context.broadcast_tx,
+            context.settings.dummy,
+            self.speed_override,
+            self.vhs_mode,
+            context.stress_index_window,
+            context.cancel_token,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(mode: DummyMode) -> DummySettings {
+        DummySettings {
+            mode,
+            low_bpm: 60,
+            high_bpm: 70,
+            loops_before_dc: 2,
+            rr_jitter_ms: 20,
+            artifact_chance: 0.0,
+            ..Default::default()
+        }
+    }
+    fn readings(simulator: &mut Simulator, count: usize) -> Vec<Option<(u16, Duration)>> {
+        (0..count)
+            .map(|_| match simulator.next() {
+                DummyTick::Reading { bpm, rr } => Some((bpm, rr)),
+                DummyTick::Disconnected { .. } => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shapes_stay_in_range() {
+        for mode in [DummyMode::Triangle, DummyMode::Sine, DummyMode::RandomWalk] {
+            let mut simulator = Simulator::new(&settings(mode), false, StdRng::seed_from_u64(1));
+            for (bpm, rr) in readings(&mut simulator, 200).into_iter().flatten() {
+                assert!((60..=70).contains(&bpm), "{mode:?} gave {bpm}");
+                let expected = rr_from_bpm(bpm).as_millis() as i64;
+                assert!((rr.as_millis() as i64 - expected).abs() <= 21);
+            }
+        }
+        let mut simulator =
+            Simulator::new(&settings(DummyMode::Sine), true, StdRng::seed_from_u64(1));
+        let bpms: Vec<u16> = readings(&mut simulator, 11)
+            .into_iter()
+            .map(|reading| reading.unwrap().0)
+            .collect();
+        assert_eq!(bpms.first(), Some(&60));
+        assert_eq!(bpms.last(), Some(&70));
+    }
+    #[test]
+    fn disconnects_every_few_loops() {
+        let mut simulator = Simulator::new(
+            &settings(DummyMode::Triangle),
+            false,
+            StdRng::seed_from_u64(1),
+        );
+        let ticks = readings(&mut simulator, 40);
+        // Two legs up and down, then one leg "disconnected"
+        assert!(ticks[..20].iter().all(Option::is_some));
+        assert!(ticks[20..30].iter().all(Option::is_none));
+        assert!(ticks[30..].iter().all(Option::is_some));
+    }
+    #[test]
+    fn artifacts_skip_or_add_beats() {
+        let dummy_settings = DummySettings {
+            rr_jitter_ms: 0,
+            artifact_chance: 1.0,
+            ..settings(DummyMode::Triangle)
+        };
+        let mut simulator = Simulator::new(&dummy_settings, false, StdRng::seed_from_u64(1));
+        for (bpm, rr) in readings(&mut simulator, 20).into_iter().flatten() {
+            let normal = rr_from_bpm(bpm).as_secs_f32();
+            let ratio = rr.as_secs_f32() / normal;
+            assert!((ratio - 2.0).abs() < 0.01 || (ratio - 0.5).abs() < 0.01);
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use super::HeartRateStatus;
+use crate::app::AppUpdate;
+use crate::broadcast;
+use crate::journal::{self, Severity, Subsystem};
+use crate::sources::HrSource;
+
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::{self, Sender as BSender};
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info};
+
+/// Picks which of several running sources drives the outputs,
+/// the first in priority order that's had a reading recently.
+struct Failover {
+    stale_after: Duration,
+    // Last update with an actual reading, per source
+    latest: Vec<Option<Instant>>,
+    active: Option<usize>,
+}
+
+impl Failover {
+    fn new(count: usize, stale_after: Duration) -> Self {
+        Self {
+            stale_after,
+            latest: vec![None; count],
+            active: None,
+        }
+    }
+    fn pick(&self, now: Instant) -> Option<usize> {
+        self.latest.iter().position(|at| {
+            at.is_some_and(|at| now.saturating_duration_since(at) <= self.stale_after)
+        })
+    }
+    /// Returns the status to pass along (if any), and whether the active source changed.
+    fn handle(
+        &mut self,
+        index: usize,
+        status: HeartRateStatus,
+        now: Instant,
+    ) -> (Option<HeartRateStatus>, bool) {
+        let has_reading = status.heart_rate_bpm != 0;
+        self.latest[index] = has_reading.then_some(now);
+        let pick = self.pick(now);
+        let changed = pick != self.active;
+        self.active = pick;
+        let status = match pick {
+            Some(active) => (active == index).then_some(status),
+            // Nobody's left, so listeners get the 0 BPM
+            None => (!has_reading).then_some(status),
+        };
+        (status, changed)
+    }
+}
+
+/// Handed to each source's relay, in place of broadcasting HR directly.
+#[derive(Debug, Clone)]
+pub struct FailoverInput {
+    index: usize,
+    tx: Sender<(usize, HeartRateStatus)>,
+}
+
+impl FailoverInput {
+    pub fn new(index: usize, tx: Sender<(usize, HeartRateStatus)>) -> Self {
+        Self { index, tx }
+    }
+    /// For the app itself, which can't wait on the channel.
+    pub fn try_send(&self, status: HeartRateStatus) {
+        if self.tx.try_send((self.index, status)).is_err() {
+            debug!("Failover manager busy or closed, dropping update");
+        }
+    }
+    /// A sender for a source thread to use as if it were the app's.
+    /// Heart rate goes through the failover manager, everything else straight to the app.
+    pub fn relay(self, broadcast_tx: BSender<AppUpdate>) -> BSender<AppUpdate> {
+        let (source_tx, mut source_rx) = broadcast::channel(20);
+        // Ends once the source drops its sender, so it never broadcasts into the void
+        tokio::spawn(async move {
+            loop {
+                match source_rx.recv().await {
+                    Ok(AppUpdate::HeartRateStatus(status)) => {
+                        if self.tx.send((self.index, status)).await.is_err() {
+                            debug!("Failover manager closed, dropping update");
+                        }
+                    }
+                    Ok(update) => {
+                        broadcast!(broadcast_tx, update);
+                    }
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+        source_tx
+    }
+}
+
+pub async fn failover_thread(
+    broadcast_tx: BSender<AppUpdate>,
+    mut hr_rx: Receiver<(usize, HeartRateStatus)>,
+    sources: Vec<HrSource>,
+    stale_after: Duration,
+    cancel_token: CancellationToken,
+) {
+    info!("Failover manager started, priority: {sources:?}");
+    let mut failover = Failover::new(sources.len(), stale_after);
+    loop {
+        tokio::select! {
+            Some((index, status)) = hr_rx.recv() => {
+                let (status, changed) = failover.handle(index, status, Instant::now());
+                if changed {
+                    let active = failover.active.map(|index| sources[index]);
+                    let message = match active {
+                        Some(source) => format!("Heart rate now from {}", source.name()),
+                        None => "No source has heart rate".to_owned(),
+                    };
+                    info!("{message}");
+                    let severity = if failover.active == Some(0) {
+                        Severity::Info
+                    } else {
+                        Severity::Warning
+                    };
+                    journal::record(severity, Subsystem::App, &message);
+                    broadcast!(broadcast_tx, AppUpdate::FailoverActive(active));
+                }
+                if let Some(status) = status {
+                    broadcast!(broadcast_tx, status);
+                }
+            }
+            _ = cancel_token.cancelled() => {
+                info!("Shutting down failover manager thread!");
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(bpm: u16) -> HeartRateStatus {
+        HeartRateStatus {
+            heart_rate_bpm: bpm,
+            ..Default::default()
+        }
+    }
+    fn bpm(result: (Option<HeartRateStatus>, bool)) -> (Option<u16>, bool) {
+        (result.0.map(|s| s.heart_rate_bpm), result.1)
+    }
+
+    #[test]
+    fn fails_over_and_back() {
+        let mut failover = Failover::new(2, Duration::from_secs(5));
+        let start = Instant::now();
+        // Fallback's fine on its own
+        assert_eq!(bpm(failover.handle(1, status(70), start)), (Some(70), true));
+        // Primary takes over as soon as it shows up
+        assert_eq!(bpm(failover.handle(0, status(72), start)), (Some(72), true));
+        assert_eq!(bpm(failover.handle(1, status(70), start)), (None, false));
+        // Primary's gone quiet
+        let later = start + Duration::from_secs(6);
+        assert_eq!(bpm(failover.handle(1, status(71), later)), (Some(71), true));
+        assert_eq!(bpm(failover.handle(0, status(73), later)), (Some(73), true));
+    }
+    #[test]
+    fn zero_only_passes_when_all_quiet() {
+        let mut failover = Failover::new(2, Duration::from_secs(5));
+        let start = Instant::now();
+        failover.handle(0, status(72), start);
+        failover.handle(1, status(70), start);
+        // Primary dropping out hands over right away
+        assert_eq!(bpm(failover.handle(0, status(0), start)), (None, true));
+        assert_eq!(failover.active, Some(1));
+        assert_eq!(bpm(failover.handle(1, status(0), start)), (Some(0), true));
+        assert_eq!(failover.active, None);
+    }
+}
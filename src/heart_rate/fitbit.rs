@@ -0,0 +1,451 @@
+// Heart rate from Fitbit's intraday API, for wearables that can't broadcast over BLE
+// Readings only show up after the watch syncs with Fitbit's servers, so expect minutes of delay
+
+use super::simple_reading::{SimpleReading, SimpleReadings};
+use super::twitcher::TwitchThreshold;
+use crate::app::{AppUpdate, ErrorPopup};
+use crate::broadcast;
+use crate::errors::AppError;
+use crate::journal::{self, Severity, Subsystem};
+use crate::settings::FitbitSettings;
+use crate::sources::{Capabilities, HeartRateSource, HrSource, SourceContext};
+
+use rand::distributions::{Alphanumeric, DistString};
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::Sender as BSender;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+// https://dev.fitbit.com/build/reference/web-api/authorization/
+// Fitbit doesn't offer the device flow, so it's the authorization code flow with PKCE,
+// catching the redirect on localhost
+const AUTHORIZE_URL: &str = "https://www.fitbit.com/oauth2/authorize";
+const TOKEN_URL: &str = "https://api.fitbit.com/oauth2/token";
+const INTRADAY_URL: &str =
+    "https://api.fitbit.com/1/user/-/activities/heart/date/today/1d/1sec.json";
+// In the config dir, next to the config
+const TOKEN_CACHE_PATH: &str = "fitbit_token.json";
+// Fitbit allows 150 requests an hour
+const MIN_POLL_INTERVAL: Duration = Duration::from_secs(30);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// Refreshing a bit early so a poll never goes out with a stale token
+const EXPIRY_MARGIN_SECS: i64 = 60;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CachedToken {
+    access_token: String,
+    refresh_token: String,
+    // Unix timestamp
+    expires_at: i64,
+}
+
+impl CachedToken {
+    fn load() -> Option<Self> {
+        let text = std::fs::read_to_string(TOKEN_CACHE_PATH).ok()?;
+        serde_json::from_str(&text).ok()
+    }
+    fn save(&self) -> Result<(), AppError> {
+        std::fs::write(TOKEN_CACHE_PATH, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+    fn is_expired(&self) -> bool {
+        chrono::Utc::now().timestamp() + EXPIRY_MARGIN_SECS >= self.expires_at
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: String,
+    expires_in: i64,
+}
+
+impl From<TokenResponse> for CachedToken {
+    fn from(response: TokenResponse) -> Self {
+        Self {
+            access_token: response.access_token,
+            refresh_token: response.refresh_token,
+            expires_at: chrono::Utc::now().timestamp() + response.expires_in,
+        }
+    }
+}
+
+// {"activities-heart-intraday": {"dataset": [{"time": "08:00:00", "value": 68}, ...]}}
+#[derive(Debug, Deserialize)]
+struct IntradayResponse {
+    #[serde(rename = "activities-heart-intraday")]
+    intraday: Intraday,
+}
+
+#[derive(Debug, Deserialize)]
+struct Intraday {
+    dataset: Vec<IntradaySample>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct IntradaySample {
+    time: String,
+    value: u16,
+}
+
+fn base64_url(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+            bits | (byte as u32) << (16 - 8 * i)
+        });
+        // No padding, as PKCE wants
+        for i in 0..=chunk.len() {
+            encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+        }
+    }
+    encoded
+}
+
+fn pkce_challenge(verifier: &str) -> String {
+    base64_url(&Sha256::digest(verifier.as_bytes()))
+}
+
+/// The code from the redirect's request line (`GET /?code=...&state=... HTTP/1.1`).
+/// None if it's some other request, i.e. the browser asking for a favicon.
+fn code_from_request(request_line: &str, state: &str) -> Option<Result<String, AppError>> {
+    let path = request_line.split_whitespace().nth(1)?;
+    let url = Url::parse(&format!("http://localhost{path}")).ok()?;
+    let query = |key: &str| {
+        url.query_pairs()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.into_owned())
+    };
+    if let Some(error) = query("error") {
+        return Some(Err(AppError::FitbitAuth(error)));
+    }
+    let code = query("code")?;
+    if query("state").as_deref() != Some(state) {
+        return Some(Err(AppError::FitbitAuth("state mismatch".into())));
+    }
+    Some(Ok(code))
+}
+
+fn latest_sample(response: IntradayResponse) -> Option<IntradaySample> {
+    response.intraday.dataset.into_iter().last()
+}
+
+struct FitbitActor {
+    fitbit_settings: FitbitSettings,
+    client: reqwest::Client,
+    token: Option<CachedToken>,
+    readings: SimpleReadings,
+    // Only new samples are sent on, the same one comes back until the watch syncs again
+    last_sample_time: Option<String>,
+}
+
+impl FitbitActor {
+    fn redirect_uri(&self) -> String {
+        format!("http://localhost:{}/", self.fitbit_settings.redirect_port)
+    }
+    async fn request_token(&self, params: &[(&str, &str)]) -> Result<CachedToken, AppError> {
+        let response = self
+            .client
+            .post(TOKEN_URL)
+            .form(params)
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(AppError::FitbitAuth(format!(
+                "token request failed with HTTP {}",
+                response.status().as_u16()
+            )));
+        }
+        let token: CachedToken = response.json::<TokenResponse>().await?.into();
+        token.save()?;
+        Ok(token)
+    }
+    async fn refresh(&mut self) -> Result<(), AppError> {
+        let refresh_token = match &self.token {
+            Some(token) => token.refresh_token.clone(),
+            None => return Err(AppError::FitbitAuth("not signed in".into())),
+        };
+        let token = self
+            .request_token(&[
+                ("grant_type", "refresh_token"),
+                ("client_id", &self.fitbit_settings.client_id),
+                ("refresh_token", &refresh_token),
+            ])
+            .await?;
+        self.token = Some(token);
+        Ok(())
+    }
+    /// Sends the user to Fitbit to sign in, waiting for the redirect to come back.
+    async fn sign_in(
+        &mut self,
+        broadcast_tx: &BSender<AppUpdate>,
+        cancel_token: &CancellationToken,
+    ) -> Result<(), AppError> {
+        let verifier = Alphanumeric.sample_string(&mut rand::thread_rng(), 64);
+        let state = Alphanumeric.sample_string(&mut rand::thread_rng(), 16);
+        let redirect_uri = self.redirect_uri();
+        let authorize_url = Url::parse_with_params(
+            AUTHORIZE_URL,
+            &[
+                ("response_type", "code"),
+                ("client_id", &self.fitbit_settings.client_id),
+                ("redirect_uri", &redirect_uri),
+                ("scope", "heartrate"),
+                ("code_challenge", &pkce_challenge(&verifier)),
+                ("code_challenge_method", "S256"),
+                ("state", &state),
+            ],
+        )
+        .expect("Fitbit authorize URL should be valid");
+
+        // Listening before sending them off, so the redirect can't beat us
+        let listener = TcpListener::bind(("127.0.0.1", self.fitbit_settings.redirect_port)).await?;
+        info!("Waiting for Fitbit sign in on {redirect_uri}");
+        journal::record(Severity::Info, Subsystem::Fitbit, "Waiting for sign in");
+        if let Err(e) = opener::open_browser(authorize_url.as_str()) {
+            warn!("Couldn't open browser for Fitbit sign in: {e}");
+        }
+        broadcast!(
+            broadcast_tx,
+            ErrorPopup::UserMustDismiss(format!(
+                "Sign in to Fitbit in your browser to continue. If it didn't open, visit:\n{authorize_url}"
+            ))
+        );
+
+        let code = loop {
+            let (mut stream, _) = tokio::select! {
+                accepted = listener.accept() => accepted?,
+                _ = cancel_token.cancelled() => return Ok(()),
+            };
+            let mut buf = [0; 4096];
+            let len = stream.read(&mut buf).await?;
+            let request = String::from_utf8_lossy(&buf[..len]);
+            let request_line = request.lines().next().unwrap_or_default();
+            let result = code_from_request(request_line, &state);
+            let body = match &result {
+                Some(Ok(_)) => "Signed in to Fitbit, this tab can be closed.",
+                Some(Err(_)) => "Fitbit sign in failed, check the app for details.",
+                None => "",
+            };
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            // Not much to do if the browser went away
+            let _ = stream.write_all(response.as_bytes()).await;
+            if let Some(result) = result {
+                break result?;
+            }
+        };
+
+        let token = self
+            .request_token(&[
+                ("grant_type", "authorization_code"),
+                ("client_id", &self.fitbit_settings.client_id),
+                ("redirect_uri", &redirect_uri),
+                ("code", &code),
+                ("code_verifier", &verifier),
+            ])
+            .await?;
+        self.token = Some(token);
+        journal::record(Severity::Info, Subsystem::Fitbit, "Signed in");
+        Ok(())
+    }
+    async fn poll(&self, access_token: &str) -> Result<Option<IntradaySample>, AppError> {
+        let response = self
+            .client
+            .get(INTRADAY_URL)
+            .bearer_auth(access_token)
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .await?;
+        match response.status() {
+            StatusCode::UNAUTHORIZED => Err(AppError::FitbitUnauthorized),
+            status if !status.is_success() => Err(AppError::HttpStatus(status.as_u16())),
+            _ => Ok(latest_sample(response.json().await?)),
+        }
+    }
+    async fn poll_loop(
+        &mut self,
+        broadcast_tx: &BSender<AppUpdate>,
+        cancel_token: CancellationToken,
+    ) -> Result<(), AppError> {
+        if self.fitbit_settings.client_id.is_empty() {
+            return Err(AppError::FitbitClientIdMissing);
+        }
+        let interval = Duration::from_secs(self.fitbit_settings.poll_interval_secs as u64)
+            .max(MIN_POLL_INTERVAL);
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // Only shows a popup when it starts failing, not on every poll after
+        let mut failing = false;
+        loop {
+            if self.token.is_none() {
+                self.sign_in(broadcast_tx, &cancel_token).await?;
+            }
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = cancel_token.cancelled() => {
+                    info!("Shutting down Fitbit thread!");
+                    return Ok(());
+                }
+            }
+            let Some(token) = &self.token else {
+                continue;
+            };
+            if token.is_expired() {
+                if let Err(e) = self.refresh().await {
+                    // Revoked or expired for good, time to sign in again
+                    warn!("Fitbit token refresh failed: {e}");
+                    journal::record(Severity::Warning, Subsystem::Fitbit, &e.to_string());
+                    self.token = None;
+                    let _ = std::fs::remove_file(TOKEN_CACHE_PATH);
+                    continue;
+                }
+            }
+            let Some(token) = &self.token else {
+                continue;
+            };
+            let result = tokio::select! {
+                result = self.poll(&token.access_token) => result,
+                _ = cancel_token.cancelled() => return Ok(()),
+            };
+            match result {
+                Ok(sample) => {
+                    if std::mem::replace(&mut failing, false) {
+                        journal::record(Severity::Info, Subsystem::Fitbit, "Polling recovered");
+                    }
+                    let Some(sample) = sample else {
+                        continue;
+                    };
+                    if self.last_sample_time.as_ref() == Some(&sample.time) {
+                        continue;
+                    }
+                    self.last_sample_time = Some(sample.time);
+                    let reading = SimpleReading {
+                        bpm: sample.value,
+                        latest_rr_ms: None,
+                        battery: None,
+                    };
+                    broadcast!(broadcast_tx, self.readings.apply(reading));
+                }
+                Err(AppError::FitbitUnauthorized) => {
+                    // Refreshed on the next tick
+                    if let Some(token) = &mut self.token {
+                        token.expires_at = 0;
+                    }
+                }
+                Err(e) if !failing => {
+                    failing = true;
+                    warn!("Fitbit poll failed: {e}");
+                    journal::record(Severity::Warning, Subsystem::Fitbit, &e.to_string());
+                    broadcast!(
+                        broadcast_tx,
+                        ErrorPopup::Intermittent(format!("Fitbit poll failed: {e}"))
+                    );
+                }
+                Err(_) => {}
+            }
+        }
+    }
+}
+
+pub async fn fitbit_thread(
+    broadcast_tx: BSender<AppUpdate>,
+    fitbit_settings: FitbitSettings,
+    rr_twitch_threshold: TwitchThreshold,
+    stress_index_window: usize,
+    cancel_token: CancellationToken,
+) {
+    let mut fitbit = FitbitActor {
+        fitbit_settings,
+        client: reqwest::Client::new(),
+        token: CachedToken::load(),
+        readings: SimpleReadings::new(rr_twitch_threshold, stress_index_window),
+        last_sample_time: None,
+    };
+    if let Err(e) = fitbit.poll_loop(&broadcast_tx, cancel_token).await {
+        error!("Fitbit error: {e}");
+        journal::record(Severity::Error, Subsystem::Fitbit, &e.to_string());
+        broadcast!(broadcast_tx, ErrorPopup::detailed("Fitbit error", e));
+    }
+}
+
+pub struct FitbitSource;
+
+impl HeartRateSource for FitbitSource {
+    fn source(&self) -> HrSource {
+        HrSource::Fitbit
+    }
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::BPM_ONLY
+    }
+    fn spawn(self: Box<Self>, context: SourceContext) -> JoinHandle<()> {
+        tokio::spawn(fitbit_thread(
+            context.broadcast_tx,
+            context.settings.fitbit,
+            context.rr_twitch_threshold,
+            context.stress_index_window,
+            context.cancel_token,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pkce_challenge_matches_rfc() {
+        // RFC 7636, Appendix B
+        assert_eq!(
+            pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+        assert_eq!(base64_url(b"ab"), "YWI");
+        assert_eq!(base64_url(b"abcd"), "YWJjZA");
+    }
+
+    #[test]
+    fn redirect_requests() {
+        let code = code_from_request("GET /?code=abc%2B1&state=xyz HTTP/1.1", "xyz");
+        assert_eq!(code.unwrap().unwrap(), "abc+1");
+        assert!(
+            code_from_request("GET /?code=abc&state=nope HTTP/1.1", "xyz")
+                .unwrap()
+                .is_err()
+        );
+        assert!(
+            code_from_request("GET /?error=access_denied HTTP/1.1", "xyz")
+                .unwrap()
+                .is_err()
+        );
+        assert!(code_from_request("GET /favicon.ico HTTP/1.1", "xyz").is_none());
+    }
+
+    #[test]
+    fn latest_intraday_sample() {
+        let response: IntradayResponse = serde_json::from_str(
+            r#"{"activities-heart": [], "activities-heart-intraday": {"dataset": [
+                {"time": "08:00:00", "value": 68}, {"time": "08:00:05", "value": 71}
+            ], "datasetInterval": 1, "datasetType": "second"}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            latest_sample(response),
+            Some(IntradaySample {
+                time: "08:00:05".into(),
+                value: 71
+            })
+        );
+    }
+}
@@ -0,0 +1,197 @@
+// Fitness Machine Service (FTMS) parsing, for treadmills/bikes/rowers that pass along HR
+// https://www.bluetooth.com/specifications/specs/fitness-machine-service-1-0/
+
+use uuid::Uuid;
+
+pub const FITNESS_MACHINE_SERVICE_UUID: Uuid =
+    Uuid::from_u128(0x00001826_0000_1000_8000_00805f9b34fb); // 00001826-0000-1000-8000-00805f9b34fb
+pub const TREADMILL_DATA_CHARACTERISTIC_UUID: Uuid =
+    Uuid::from_u128(0x00002acd_0000_1000_8000_00805f9b34fb); // 00002acd-0000-1000-8000-00805f9b34fb
+pub const CROSS_TRAINER_DATA_CHARACTERISTIC_UUID: Uuid =
+    Uuid::from_u128(0x00002ace_0000_1000_8000_00805f9b34fb); // 00002ace-0000-1000-8000-00805f9b34fb
+pub const ROWER_DATA_CHARACTERISTIC_UUID: Uuid =
+    Uuid::from_u128(0x00002ad1_0000_1000_8000_00805f9b34fb); // 00002ad1-0000-1000-8000-00805f9b34fb
+pub const INDOOR_BIKE_DATA_CHARACTERISTIC_UUID: Uuid =
+    Uuid::from_u128(0x00002ad2_0000_1000_8000_00805f9b34fb); // 00002ad2-0000-1000-8000-00805f9b34fb
+
+/// Where the Heart Rate field sits in a machine's data characteristic.
+///
+/// Every field is optional, with a flag bit each. Only the ones before
+/// Heart Rate matter, as that's all we need to skip over.
+struct MachineLayout {
+    uuid: Uuid,
+    flags_len: usize,
+    // (flag bit, field size in bytes), in the order they show up
+    fields_before_hr: &'static [(u8, usize)],
+    heart_rate_bit: u8,
+}
+
+// Bit 0 is "More Data", where the first field is present when it's *cleared*
+const MORE_DATA_BIT: u8 = 0;
+
+const MACHINES: [MachineLayout; 4] = [
+    MachineLayout {
+        uuid: TREADMILL_DATA_CHARACTERISTIC_UUID,
+        flags_len: 2,
+        // Speed, Average Speed, Total Distance, Inclination + Ramp Angle,
+        // Elevation Gain, Pace, Average Pace, Expended Energy
+        fields_before_hr: &[
+            (0, 2),
+            (1, 2),
+            (2, 3),
+            (3, 4),
+            (4, 4),
+            (5, 1),
+            (6, 1),
+            (7, 5),
+        ],
+        heart_rate_bit: 8,
+    },
+    MachineLayout {
+        uuid: CROSS_TRAINER_DATA_CHARACTERISTIC_UUID,
+        flags_len: 3,
+        // Speed, Average Speed, Total Distance, Step Rates, Stride Count, Elevation Gain,
+        // Inclination + Ramp Angle, Resistance, Power, Average Power, Expended Energy
+        fields_before_hr: &[
+            (0, 2),
+            (1, 2),
+            (2, 3),
+            (3, 4),
+            (4, 2),
+            (5, 4),
+            (6, 4),
+            (7, 2),
+            (8, 2),
+            (9, 2),
+            (10, 5),
+        ],
+        heart_rate_bit: 11,
+    },
+    MachineLayout {
+        uuid: ROWER_DATA_CHARACTERISTIC_UUID,
+        flags_len: 2,
+        // Stroke Rate + Count, Average Stroke Rate, Total Distance, Pace, Average Pace,
+        // Power, Average Power, Resistance, Expended Energy
+        fields_before_hr: &[
+            (0, 3),
+            (1, 1),
+            (2, 3),
+            (3, 2),
+            (4, 2),
+            (5, 2),
+            (6, 2),
+            (7, 2),
+            (8, 5),
+        ],
+        heart_rate_bit: 9,
+    },
+    MachineLayout {
+        uuid: INDOOR_BIKE_DATA_CHARACTERISTIC_UUID,
+        flags_len: 2,
+        // Speed, Average Speed, Cadence, Average Cadence, Total Distance,
+        // Resistance, Power, Average Power, Expended Energy
+        fields_before_hr: &[
+            (0, 2),
+            (1, 2),
+            (2, 2),
+            (3, 2),
+            (4, 3),
+            (5, 2),
+            (6, 2),
+            (7, 2),
+            (8, 5),
+        ],
+        heart_rate_bit: 9,
+    },
+];
+
+/// Whether this is one of the machine data characteristics we can get HR from.
+pub fn is_machine_data(uuid: Uuid) -> bool {
+    MACHINES.iter().any(|machine| machine.uuid == uuid)
+}
+
+/// Pulls the Heart Rate out of a machine data notification.
+///
+/// None if the machine didn't include one (i.e. nobody's holding the grips),
+/// or it's 0, which some machines send instead of leaving it out.
+pub fn parse_machine_heart_rate(uuid: Uuid, data: &[u8]) -> Option<u16> {
+    let machine = MACHINES.iter().find(|machine| machine.uuid == uuid)?;
+    let mut flag_bytes = [0u8; 4];
+    flag_bytes[..machine.flags_len].copy_from_slice(data.get(..machine.flags_len)?);
+    let flags = u32::from_le_bytes(flag_bytes);
+    let present = |bit: u8| {
+        let set = flags & (1 << bit) != 0;
+        if bit == MORE_DATA_BIT {
+            !set
+        } else {
+            set
+        }
+    };
+    if !present(machine.heart_rate_bit) {
+        return None;
+    }
+    let offset = machine.flags_len
+        + machine
+            .fields_before_hr
+            .iter()
+            .filter(|(bit, _)| present(*bit))
+            .map(|(_, size)| size)
+            .sum::<usize>();
+    let bpm = *data.get(offset)?;
+    (bpm != 0).then_some(bpm as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn treadmill_heart_rate() {
+        // Speed (flag bit 0 cleared), Total Distance, and Heart Rate
+        let data = [
+            0x04, 0x01, // Flags
+            0xE8, 0x03, // 10.00 km/h
+            0x10, 0x27, 0x00, // 10000m
+            0x8C, // 140 BPM
+        ];
+        assert_eq!(
+            parse_machine_heart_rate(TREADMILL_DATA_CHARACTERISTIC_UUID, &data),
+            Some(140)
+        );
+        // Same, but no Heart Rate
+        let data = [0x04, 0x00, 0xE8, 0x03, 0x10, 0x27, 0x00];
+        assert_eq!(
+            parse_machine_heart_rate(TREADMILL_DATA_CHARACTERISTIC_UUID, &data),
+            None
+        );
+    }
+    #[test]
+    fn indoor_bike_heart_rate() {
+        // "More Data" set so no Speed, then Cadence, Power, and Heart Rate
+        let data = [
+            0x45, 0x02, // Flags
+            0xB4, 0x00, // 90 RPM
+            0xC8, 0x00, // 200W
+            0x7D, // 125 BPM
+        ];
+        assert_eq!(
+            parse_machine_heart_rate(INDOOR_BIKE_DATA_CHARACTERISTIC_UUID, &data),
+            Some(125)
+        );
+        // Cut off before the Heart Rate
+        assert_eq!(
+            parse_machine_heart_rate(INDOOR_BIKE_DATA_CHARACTERISTIC_UUID, &data[..6]),
+            None
+        );
+    }
+    #[test]
+    fn cross_trainer_three_byte_flags() {
+        // Just Heart Rate (bit 11), with "More Data" set
+        let data = [0x01, 0x08, 0x00, 0x64];
+        assert_eq!(
+            parse_machine_heart_rate(CROSS_TRAINER_DATA_CHARACTERISTIC_UUID, &data),
+            Some(100)
+        );
+        assert!(!is_machine_data(FITNESS_MACHINE_SERVICE_UUID));
+    }
+}
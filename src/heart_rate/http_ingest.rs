@@ -0,0 +1,317 @@
+use std::time::Duration;
+
+use reqwest::Url;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::{debug, warn};
+
+use super::simple_reading::SimpleReading;
+use crate::errors::AppError;
+
+pub(super) const SIMPLE_INGEST_PATH: &str = "/ingest/simple";
+pub(super) const HR_PATH: &str = "/hr";
+pub(super) const HEALTH_PATH: &str = "/health";
+// Requests from Tasker/Shortcuts are tiny, anything bigger is probably not for us
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum IngestRejection {
+    NotFound,
+    Unauthorized,
+    BadRequest(&'static str),
+}
+
+impl IngestRejection {
+    fn status_line(&self) -> &'static str {
+        match self {
+            IngestRejection::NotFound => "404 Not Found",
+            IngestRejection::Unauthorized => "401 Unauthorized",
+            IngestRejection::BadRequest(_) => "400 Bad Request",
+        }
+    }
+    fn body(&self) -> &'static str {
+        match self {
+            IngestRejection::NotFound => "Not Found",
+            IngestRejection::Unauthorized => "Missing or invalid token",
+            IngestRejection::BadRequest(reason) => reason,
+        }
+    }
+}
+
+/// Checks if a request head is a websocket handshake, anything else is served as plain HTTP.
+pub(super) fn is_websocket_upgrade(request_head: &str) -> bool {
+    header(request_head, "upgrade").is_some_and(|value| value.eq_ignore_ascii_case("websocket"))
+}
+
+fn header<'a>(request_head: &'a str, name: &str) -> Option<&'a str> {
+    request_head
+        .lines()
+        .skip(1)
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .find(|(header, _)| header.trim().eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim())
+}
+
+fn get_url(request_head: &str) -> Result<Url, IngestRejection> {
+    let request_line = request_head.lines().next().unwrap_or_default();
+    let mut parts = request_line.split_whitespace();
+    let (Some("GET"), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(IngestRejection::BadRequest("Expected a GET request"));
+    };
+    Url::parse(&format!("http://localhost{target}"))
+        .map_err(|_| IngestRejection::BadRequest("Malformed URL"))
+}
+
+/// The path of a GET request, without the query or any trailing slash.
+pub(super) fn get_path(request_head: &str) -> Result<String, IngestRejection> {
+    let url = get_url(request_head)?;
+    Ok(url.path().trim_end_matches('/').to_owned())
+}
+
+/// Whether the request has the token in either a `token` query parameter or an `Authorization: Bearer` header.
+///
+/// An empty `expected_token` never matches.
+pub(super) fn authorized(request_head: &str, expected_token: &str) -> bool {
+    let header_token = header(request_head, "authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_owned());
+    !expected_token.is_empty()
+        && [header_token, request_token(request_head)]
+            .iter()
+            .flatten()
+            .any(|token| token == expected_token)
+}
+
+/// The `token` query parameter from a request head, if it has one.
+pub(super) fn request_token(request_head: &str) -> Option<String> {
+    let request_line = request_head.lines().next()?;
+    let target = request_line.split_whitespace().nth(1)?;
+    let url = Url::parse(&format!("http://localhost{target}")).ok()?;
+    url.query_pairs()
+        .find(|(key, _)| key == "token")
+        .map(|(_, value)| value.into_owned())
+}
+
+/// Reads the request head (request line + headers) from the connection, as-is.
+pub(super) async fn read_request_head<S: AsyncRead + Unpin>(
+    connection: &mut S,
+) -> Result<Vec<u8>, AppError> {
+    let mut head = Vec::with_capacity(512);
+    let mut buf = [0; 512];
+    let read_head = async {
+        while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < MAX_REQUEST_HEAD {
+            let read = connection.read(&mut buf).await?;
+            if read == 0 {
+                break;
+            }
+            head.extend_from_slice(&buf[..read]);
+        }
+        Ok::<(), AppError>(())
+    };
+    match tokio::time::timeout(READ_TIMEOUT, read_head).await {
+        Ok(result) => result?,
+        Err(_) => warn!("Timed out reading request"),
+    }
+    Ok(head)
+}
+
+/// Parses and authenticates a request head.
+///
+/// The token can be given either as a `token` query parameter or an `Authorization: Bearer` header.
+/// An empty `expected_token` rejects everything, the endpoint must not be left open.
+pub(super) fn parse_simple_ingest(
+    request_head: &str,
+    expected_token: &str,
+) -> Result<SimpleReading, IngestRejection> {
+    let url = get_url(request_head)?;
+    if url.path().trim_end_matches('/') != SIMPLE_INGEST_PATH {
+        return Err(IngestRejection::NotFound);
+    }
+    // Before looking at anything else, so callers without the token can't learn what's expected
+    if !authorized(request_head, expected_token) {
+        return Err(IngestRejection::Unauthorized);
+    }
+
+    let mut bpm = None;
+    let mut latest_rr_ms = None;
+    let mut battery = None;
+    for (key, value) in url.query_pairs() {
+        match key.as_ref() {
+            "bpm" | "hr" | "heartrate" => {
+                bpm = Some(
+                    value
+                        .parse::<u16>()
+                        .map_err(|_| IngestRejection::BadRequest("Invalid bpm"))?,
+                )
+            }
+            "rr" | "latest_rr_ms" => {
+                latest_rr_ms = Some(
+                    value
+                        .parse::<u64>()
+                        .map_err(|_| IngestRejection::BadRequest("Invalid rr"))?,
+                )
+            }
+            "battery" => {
+                battery = Some(
+                    value
+                        .parse::<u8>()
+                        .map_err(|_| IngestRejection::BadRequest("Invalid battery"))?,
+                )
+            }
+            _ => {}
+        }
+    }
+
+    let bpm = bpm.ok_or(IngestRejection::BadRequest("Missing bpm"))?;
+    Ok(SimpleReading {
+        bpm,
+        latest_rr_ms,
+        battery,
+    })
+}
+
+pub(super) async fn respond<S: AsyncWrite + Unpin>(
+    connection: &mut S,
+    result: &Result<SimpleReading, IngestRejection>,
+) -> Result<(), AppError> {
+    match result {
+        Ok(_) => write_response(connection, "200 OK", "text/plain", "OK").await,
+        Err(rejection) => reject(connection, rejection).await,
+    }
+}
+
+pub(super) async fn reject<S: AsyncWrite + Unpin>(
+    connection: &mut S,
+    rejection: &IngestRejection,
+) -> Result<(), AppError> {
+    debug!("Rejected HTTP request: {rejection:?}");
+    write_response(
+        connection,
+        rejection.status_line(),
+        "text/plain",
+        rejection.body(),
+    )
+    .await
+}
+
+// Any origin's allowed, so browser-based things like Stream Deck plugins can poll too
+pub(super) async fn write_response<S: AsyncWrite + Unpin>(
+    connection: &mut S,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> Result<(), AppError> {
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    connection.write_all(response.as_bytes()).await?;
+    connection.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "hunter2";
+
+    #[test]
+    fn query_token() {
+        let head = "GET /ingest/simple?bpm=123&token=hunter2 HTTP/1.1\r\nHost: pc\r\n\r\n";
+        let reading = parse_simple_ingest(head, TOKEN).unwrap();
+        assert_eq!(
+            reading,
+            SimpleReading {
+                bpm: 123,
+                latest_rr_ms: None,
+                battery: None
+            }
+        );
+    }
+    #[test]
+    fn bearer_token_and_extras() {
+        let head = "GET /ingest/simple/?hr=80&rr=750&battery=42 HTTP/1.1\r\nHost: pc\r\nauthorization: Bearer hunter2\r\n\r\n";
+        let reading = parse_simple_ingest(head, TOKEN).unwrap();
+        assert_eq!(
+            reading,
+            SimpleReading {
+                bpm: 80,
+                latest_rr_ms: Some(750),
+                battery: Some(42)
+            }
+        );
+    }
+    #[test]
+    fn percent_encoded_token() {
+        let head = "GET /ingest/simple?bpm=60&token=a%20b%26c HTTP/1.1\r\n\r\n";
+        assert!(parse_simple_ingest(head, "a b&c").is_ok());
+    }
+    #[test]
+    fn wrong_or_missing_token() {
+        let head = "GET /ingest/simple?bpm=123&token=nope HTTP/1.1\r\n\r\n";
+        assert_eq!(
+            parse_simple_ingest(head, TOKEN),
+            Err(IngestRejection::Unauthorized)
+        );
+        let head = "GET /ingest/simple?bpm=123 HTTP/1.1\r\n\r\n";
+        assert_eq!(
+            parse_simple_ingest(head, TOKEN),
+            Err(IngestRejection::Unauthorized)
+        );
+    }
+    #[test]
+    fn unauthorized_before_bad_request() {
+        let head = "GET /ingest/simple?bpm=abc HTTP/1.1\r\n\r\n";
+        assert_eq!(
+            parse_simple_ingest(head, TOKEN),
+            Err(IngestRejection::Unauthorized)
+        );
+    }
+    #[test]
+    fn empty_token_never_authorizes() {
+        let head = "GET /ingest/simple?bpm=123&token= HTTP/1.1\r\n\r\n";
+        assert_eq!(
+            parse_simple_ingest(head, ""),
+            Err(IngestRejection::Unauthorized)
+        );
+    }
+    #[test]
+    fn token_from_request_head() {
+        let head = "GET /?token=hunter%202 HTTP/1.1\r\nHost: pc\r\n\r\n";
+        assert_eq!(request_token(head).as_deref(), Some("hunter 2"));
+        assert_eq!(request_token("GET /?bpm=70 HTTP/1.1"), None);
+        assert_eq!(request_token("GET / HTTP/1.1"), None);
+        assert_eq!(request_token("garbage"), None);
+    }
+    #[test]
+    fn paths_and_upgrades() {
+        let head = "GET /hr/?token=hunter2 HTTP/1.1\r\nHost: pc\r\n\r\n";
+        assert_eq!(get_path(head).unwrap(), "/hr");
+        assert!(authorized(head, TOKEN));
+        assert!(!is_websocket_upgrade(head));
+        let head = "GET / HTTP/1.1\r\nConnection: Upgrade\r\nupgrade: WebSocket\r\n\r\n";
+        assert!(is_websocket_upgrade(head));
+        assert!(!authorized(head, TOKEN));
+        assert!(get_path("POST /hr HTTP/1.1\r\n\r\n").is_err());
+    }
+    #[test]
+    fn bad_requests() {
+        let head = "GET /ingest/simple?bpm=abc&token=hunter2 HTTP/1.1\r\n\r\n";
+        assert!(matches!(
+            parse_simple_ingest(head, TOKEN),
+            Err(IngestRejection::BadRequest(_))
+        ));
+        let head = "GET /ingest/simple?token=hunter2 HTTP/1.1\r\n\r\n";
+        assert!(matches!(
+            parse_simple_ingest(head, TOKEN),
+            Err(IngestRejection::BadRequest(_))
+        ));
+        let head = "GET /ingest/other?bpm=1&token=hunter2 HTTP/1.1\r\n\r\n";
+        assert_eq!(
+            parse_simple_ingest(head, TOKEN),
+            Err(IngestRejection::NotFound)
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use super::simple_reading::{SimpleReading, SimpleReadings};
+use super::twitcher::TwitchThreshold;
+use super::HeartRateStatus;
+use crate::app::{AppUpdate, ErrorPopup};
+use crate::broadcast;
+use crate::errors::AppError;
+use crate::journal::{self, Severity, Subsystem};
+use crate::logging::raw_capture;
+use crate::settings::HttpPollSettings;
+use crate::sources::{Capabilities, HeartRateSource, HrSource, SourceContext};
+
+use serde_json::Value;
+use std::time::Duration;
+use tokio::sync::broadcast::Sender as BSender;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+// Anything slower than a couple of polls is as good as gone
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+// Not hammering someone's API by accident
+const MIN_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Numbers or numeric strings, since some APIs quote everything.
+fn value_at(json: &Value, pointer: &str) -> Option<u64> {
+    match json.pointer(pointer)? {
+        Value::Number(number) => number
+            .as_u64()
+            .or_else(|| number.as_f64().map(|float| float.round() as u64)),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn extract_reading(json: &Value, http_poll_settings: &HttpPollSettings) -> Option<SimpleReading> {
+    let bpm = value_at(json, &http_poll_settings.bpm_pointer)?;
+    let optional = |pointer: &str| {
+        if pointer.is_empty() {
+            None
+        } else {
+            value_at(json, pointer)
+        }
+    };
+    Some(SimpleReading {
+        bpm: u16::try_from(bpm).ok()?,
+        latest_rr_ms: optional(&http_poll_settings.rr_pointer),
+        battery: optional(&http_poll_settings.battery_pointer)
+            .and_then(|battery| u8::try_from(battery).ok()),
+    })
+}
+
+struct HttpPollActor {
+    http_poll_settings: HttpPollSettings,
+    client: reqwest::Client,
+    readings: SimpleReadings,
+}
+
+impl HttpPollActor {
+    async fn poll(&self) -> Result<Value, AppError> {
+        let response = self
+            .client
+            .get(&self.http_poll_settings.url)
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(AppError::HttpStatus(response.status().as_u16()));
+        }
+        let body = response.text().await?;
+        raw_capture::record_websocket(&body);
+        Ok(serde_json::from_str(&body)?)
+    }
+    async fn poll_loop(
+        &mut self,
+        broadcast_tx: &BSender<AppUpdate>,
+        cancel_token: CancellationToken,
+    ) -> Result<(), AppError> {
+        if self.http_poll_settings.url.is_empty() {
+            return Err(AppError::HttpPollUrlMissing);
+        }
+        let interval = Duration::from_millis(self.http_poll_settings.interval_ms).max(MIN_INTERVAL);
+        let mut ticker = tokio::time::interval(interval);
+        // A slow response shouldn't cause a burst of requests after it
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // Only shows a popup when it starts failing, not on every poll after
+        let mut failing = false;
+        // URL's left out, it might have a token in it
+        info!("Polling for heart rate every {interval:?}");
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {}
+                _ = cancel_token.cancelled() => {
+                    info!("Shutting down HTTP polling thread!");
+                    return Ok(());
+                }
+            }
+            let result = tokio::select! {
+                result = self.poll() => result,
+                _ = cancel_token.cancelled() => return Ok(()),
+            };
+            let reading = match result {
+                Ok(json) => extract_reading(&json, &self.http_poll_settings).ok_or_else(|| {
+                    format!(
+                        "No heart rate at \"{}\" in response",
+                        self.http_poll_settings.bpm_pointer
+                    )
+                }),
+                Err(e) => Err(format!("Failed to poll heart rate: {e}")),
+            };
+            match reading {
+                Ok(reading) => {
+                    if std::mem::replace(&mut failing, false) {
+                        journal::record(Severity::Info, Subsystem::HttpPoll, "Polling recovered");
+                    }
+                    broadcast!(broadcast_tx, self.readings.apply(reading));
+                }
+                Err(reason) if !failing => {
+                    failing = true;
+                    warn!("{reason}");
+                    journal::record(Severity::Warning, Subsystem::HttpPoll, &reason);
+                    broadcast!(
+                        broadcast_tx,
+                        HeartRateStatus::default(),
+                        "Failed to send 0BPM after failed poll"
+                    );
+                    broadcast!(broadcast_tx, ErrorPopup::Intermittent(reason));
+                }
+                Err(_) => {}
+            }
+        }
+    }
+}
+
+pub async fn http_poll_thread(
+    broadcast_tx: BSender<AppUpdate>,
+    http_poll_settings: HttpPollSettings,
+    rr_twitch_threshold: TwitchThreshold,
+    stress_index_window: usize,
+    cancel_token: CancellationToken,
+) {
+    let mut http_poll = HttpPollActor {
+        http_poll_settings,
+        client: reqwest::Client::new(),
+        readings: SimpleReadings::new(rr_twitch_threshold, stress_index_window),
+    };
+    if let Err(e) = http_poll.poll_loop(&broadcast_tx, cancel_token).await {
+        error!("HTTP polling error: {e}");
+        journal::record(Severity::Error, Subsystem::HttpPoll, &e.to_string());
+        broadcast!(broadcast_tx, ErrorPopup::detailed("HTTP polling error", e));
+    }
+}
+
+pub struct HttpPollSource;
+
+impl HeartRateSource for HttpPollSource {
+    fn source(&self) -> HrSource {
+        HrSource::HttpPoll
+    }
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::FULL
+    }
+    fn spawn(self: Box<Self>, context: SourceContext) -> JoinHandle<()> {
+        tokio::spawn(http_poll_thread(
+            context.broadcast_tx,
+            context.settings.http_poll,
+            context.rr_twitch_threshold,
+            context.stress_index_window,
+            context.cancel_token,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn json_pointers() {
+        let settings = HttpPollSettings {
+            bpm_pointer: "/data/heart_rate".into(),
+            rr_pointer: "/data/rr".into(),
+            ..Default::default()
+        };
+        let response = json!({"data": {"heart_rate": 71.6, "rr": "838"}});
+        assert_eq!(
+            extract_reading(&response, &settings),
+            Some(SimpleReading {
+                bpm: 72,
+                latest_rr_ms: Some(838),
+                battery: None,
+            })
+        );
+        assert_eq!(extract_reading(&json!({"bpm": 70}), &settings), None);
+        assert_eq!(
+            extract_reading(&json!({"data": {"heart_rate": "n/a"}}), &settings),
+            None
+        );
+    }
+}
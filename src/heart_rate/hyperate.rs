@@ -0,0 +1,152 @@
+use super::remote::{remote_thread, RemoteProvider};
+use super::twitcher::TwitchThreshold;
+use crate::app::AppUpdate;
+use crate::errors::AppError;
+use crate::journal::Subsystem;
+use crate::settings::HypeRateSettings;
+use crate::sources::{Capabilities, HeartRateSource, HrSource, SourceContext};
+
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+use tokio::sync::broadcast::Sender as BSender;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+// Phoenix Channels under the hood
+// https://github.com/HypeRate/DevDocs
+const SOCKET_URL: &str = "wss://app.hyperate.io/socket/websocket";
+// They drop clients that go quiet for ~30s
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+// {"topic": "hr:ABCD", "event": "hr_update", "payload": {"hr": 82}, "ref": null}
+#[derive(Debug, Deserialize)]
+struct PhoenixMessage {
+    event: String,
+    payload: serde_json::Value,
+}
+
+struct HypeRate {
+    api_key: String,
+    session_id: String,
+    // Phoenix wants every message numbered
+    message_ref: u64,
+}
+
+impl HypeRate {
+    fn message(&mut self, topic: &str, event: &str) -> String {
+        self.message_ref += 1;
+        json!({
+            "topic": topic,
+            "event": event,
+            "payload": {},
+            "ref": self.message_ref,
+        })
+        .to_string()
+    }
+}
+
+impl RemoteProvider for HypeRate {
+    fn name(&self) -> &'static str {
+        "HypeRate"
+    }
+    fn subsystem(&self) -> Subsystem {
+        Subsystem::HypeRate
+    }
+    fn uri(&self) -> String {
+        format!("{SOCKET_URL}?token={}", self.api_key)
+    }
+    fn validate(&self) -> Result<(), AppError> {
+        if self.api_key.is_empty() {
+            return Err(AppError::HypeRateKeyMissing);
+        }
+        if self.session_id.is_empty() {
+            return Err(AppError::HypeRateSessionMissing);
+        }
+        Ok(())
+    }
+    fn unauthorized(&self) -> AppError {
+        AppError::HypeRateUnauthorized
+    }
+    fn on_connect(&mut self) -> Vec<String> {
+        let topic = format!("hr:{}", self.session_id);
+        vec![self.message(&topic, "phx_join")]
+    }
+    fn keepalive_interval(&self) -> Option<Duration> {
+        Some(HEARTBEAT_INTERVAL)
+    }
+    fn keepalive_message(&mut self) -> Option<String> {
+        Some(self.message("phoenix", "heartbeat"))
+    }
+    fn parse(&self, text: &str) -> Option<u16> {
+        let message = serde_json::from_str::<PhoenixMessage>(text).ok()?;
+        if message.event != "hr_update" {
+            return None;
+        }
+        message
+            .payload
+            .get("hr")?
+            .as_u64()
+            .and_then(|hr| u16::try_from(hr).ok())
+    }
+}
+
+pub async fn hyperate_thread(
+    broadcast_tx: BSender<AppUpdate>,
+    hyperate_settings: HypeRateSettings,
+    rr_twitch_threshold: TwitchThreshold,
+    cancel_token: CancellationToken,
+) {
+    let hyperate = HypeRate {
+        api_key: hyperate_settings.api_key,
+        // Easy to copy with a stray space from the overlay URL
+        session_id: hyperate_settings.session_id.trim().to_owned(),
+        message_ref: 0,
+    };
+    remote_thread(broadcast_tx, hyperate, rr_twitch_threshold, cancel_token).await;
+}
+
+pub struct HypeRateSource;
+
+impl HeartRateSource for HypeRateSource {
+    fn source(&self) -> HrSource {
+        HrSource::HypeRate
+    }
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::BPM_ONLY
+    }
+    fn spawn(self: Box<Self>, context: SourceContext) -> JoinHandle<()> {
+        tokio::spawn(hyperate_thread(
+            context.broadcast_tx,
+            context.settings.hyperate,
+            context.rr_twitch_threshold,
+            context.cancel_token,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn joins_and_parses_updates() {
+        let mut hyperate = HypeRate {
+            api_key: "key".into(),
+            session_id: "ABCD".into(),
+            message_ref: 0,
+        };
+        assert!(hyperate.validate().is_ok());
+        let join: serde_json::Value = serde_json::from_str(&hyperate.on_connect()[0]).unwrap();
+        assert_eq!(join["topic"], "hr:ABCD");
+        assert_eq!(join["event"], "phx_join");
+        let heartbeat: serde_json::Value =
+            serde_json::from_str(&hyperate.keepalive_message().unwrap()).unwrap();
+        assert_eq!(heartbeat["ref"], 2);
+
+        let update = r#"{"topic":"hr:ABCD","event":"hr_update","payload":{"hr":82},"ref":null}"#;
+        assert_eq!(hyperate.parse(update), Some(82));
+        let reply = r#"{"topic":"hr:ABCD","event":"phx_reply","payload":{"status":"ok","response":{}},"ref":1}"#;
+        assert_eq!(hyperate.parse(reply), None);
+    }
+}
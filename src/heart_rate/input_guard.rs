@@ -0,0 +1,138 @@
+use std::time::{Duration, Instant};
+
+use super::simple_reading::SimpleReading;
+use crate::settings::OutOfRangePolicy;
+
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+// How often what got thrown away is reported, at most
+pub(super) const SUMMARY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Keeps one misbehaving client from flooding the app. Rate limits it, drops or clamps
+/// implausible BPM, and rolls up everything it threw away into one warning every so often.
+#[derive(Debug, Clone)]
+pub(super) struct InputGuard {
+    max_per_sec: u32,
+    min_bpm: u16,
+    max_bpm: u16,
+    policy: OutOfRangePolicy,
+    window_start: Option<Instant>,
+    in_window: u32,
+    rate_limited: u32,
+    invalid: u32,
+    out_of_range: u32,
+}
+
+impl InputGuard {
+    pub fn new(max_per_sec: u32, min_bpm: u16, max_bpm: u16, policy: OutOfRangePolicy) -> Self {
+        Self {
+            max_per_sec,
+            // Backwards bounds would make clamping panic
+            min_bpm: min_bpm.min(max_bpm),
+            max_bpm: max_bpm.max(min_bpm),
+            policy,
+            window_start: None,
+            in_window: 0,
+            rate_limited: 0,
+            invalid: 0,
+            out_of_range: 0,
+        }
+    }
+    /// Whether a message that just came in should be looked at at all.
+    pub fn admit(&mut self, now: Instant) -> bool {
+        if self.max_per_sec == 0 {
+            return true;
+        }
+        match self.window_start {
+            Some(start) if now.saturating_duration_since(start) < RATE_WINDOW => {}
+            _ => {
+                self.window_start = Some(now);
+                self.in_window = 0;
+            }
+        }
+        self.in_window += 1;
+        let admitted = self.in_window <= self.max_per_sec;
+        if !admitted {
+            self.rate_limited += 1;
+        }
+        admitted
+    }
+    pub fn invalid(&mut self) {
+        self.invalid += 1;
+    }
+    /// The reading if its BPM is plausible (or could be made so), 0 always is.
+    pub fn check(&mut self, mut reading: SimpleReading) -> Option<SimpleReading> {
+        let bpm = reading.bpm;
+        if bpm == 0 || (self.min_bpm..=self.max_bpm).contains(&bpm) {
+            return Some(reading);
+        }
+        self.out_of_range += 1;
+        match self.policy {
+            OutOfRangePolicy::Drop => None,
+            OutOfRangePolicy::Clamp => {
+                reading.bpm = bpm.clamp(self.min_bpm, self.max_bpm);
+                Some(reading)
+            }
+        }
+    }
+    /// What was thrown away since the last call, if anything.
+    pub fn summary(&mut self) -> Option<String> {
+        let mut parts = Vec::new();
+        if self.rate_limited > 0 {
+            parts.push(format!("{} over the rate limit", self.rate_limited));
+        }
+        if self.invalid > 0 {
+            parts.push(format!("{} invalid", self.invalid));
+        }
+        if self.out_of_range > 0 {
+            let action = match self.policy {
+                OutOfRangePolicy::Drop => "dropped",
+                OutOfRangePolicy::Clamp => "clamped",
+            };
+            parts.push(format!("{} out of range ({action})", self.out_of_range));
+        }
+        self.rate_limited = 0;
+        self.invalid = 0;
+        self.out_of_range = 0;
+        (!parts.is_empty()).then(|| parts.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(bpm: u16) -> SimpleReading {
+        SimpleReading {
+            bpm,
+            latest_rr_ms: None,
+            battery: None,
+        }
+    }
+
+    #[test]
+    fn rate_limits_per_second() {
+        let mut guard = InputGuard::new(2, 20, 250, OutOfRangePolicy::Drop);
+        let start = Instant::now();
+        let admitted: Vec<bool> = (0..4).map(|_| guard.admit(start)).collect();
+        assert_eq!(admitted, vec![true, true, false, false]);
+        assert!(guard.admit(start + Duration::from_secs(1)));
+        assert_eq!(guard.summary().as_deref(), Some("2 over the rate limit"));
+        assert_eq!(guard.summary(), None);
+    }
+    #[test]
+    fn drops_or_clamps_out_of_range() {
+        let mut guard = InputGuard::new(0, 20, 250, OutOfRangePolicy::Drop);
+        assert_eq!(guard.check(reading(300)), None);
+        assert_eq!(guard.check(reading(0)), Some(reading(0)));
+        guard.invalid();
+        assert_eq!(
+            guard.summary().as_deref(),
+            Some("1 invalid, 1 out of range (dropped)")
+        );
+
+        let mut guard = InputGuard::new(0, 20, 250, OutOfRangePolicy::Clamp);
+        assert_eq!(guard.check(reading(300)), Some(reading(250)));
+        assert_eq!(guard.check(reading(5)), Some(reading(20)));
+        assert_eq!(guard.summary().as_deref(), Some("2 out of range (clamped)"));
+    }
+}
@@ -0,0 +1,64 @@
+use super::BatteryLevel;
+
+// How far the battery has to climb back past the threshold (i.e. charged) before it counts as low again,
+// so a reading wobbling between 15% and 16% doesn't keep setting it off
+const REARM_MARGIN: u8 = 5;
+
+/// Notices the sensor's battery dropping to `misc.low_battery_percent`,
+/// for both the `low_battery` alert and the `on_low_battery` hook.
+#[derive(Debug)]
+pub struct LowBattery {
+    // 0 never counts as low
+    threshold: u8,
+    low: bool,
+}
+
+impl LowBattery {
+    pub fn new(threshold: u8) -> Self {
+        Self {
+            threshold,
+            low: false,
+        }
+    }
+    /// The level the moment it drops to the threshold, then None until it's been charged back up.
+    pub fn update(&mut self, level: BatteryLevel) -> Option<u8> {
+        let BatteryLevel::Level(level) = level else {
+            return None;
+        };
+        if self.threshold == 0 {
+            return None;
+        }
+        if !self.low && level <= self.threshold {
+            self.low = true;
+            return Some(level);
+        }
+        if self.low && level > self.threshold.saturating_add(REARM_MARGIN) {
+            self.low = false;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn once_until_recharged() {
+        let mut low_battery = LowBattery::new(15);
+        assert_eq!(low_battery.update(BatteryLevel::Level(50)), None);
+        assert_eq!(low_battery.update(BatteryLevel::NotReported), None);
+        assert_eq!(low_battery.update(BatteryLevel::Level(15)), Some(15));
+        assert_eq!(low_battery.update(BatteryLevel::Level(14)), None);
+        // Bouncing around the threshold doesn't count as charged
+        assert_eq!(low_battery.update(BatteryLevel::Level(18)), None);
+        assert_eq!(low_battery.update(BatteryLevel::Level(15)), None);
+        assert_eq!(low_battery.update(BatteryLevel::Level(80)), None);
+        assert_eq!(low_battery.update(BatteryLevel::Level(10)), Some(10));
+    }
+    #[test]
+    fn zero_turns_it_off() {
+        let mut low_battery = LowBattery::new(0);
+        assert_eq!(low_battery.update(BatteryLevel::Level(0)), None);
+    }
+}
@@ -4,6 +4,8 @@
 
 use std::time::Duration;
 
+use crate::settings::RrUnit;
+
 // A Struct that does not care about bit compression
 #[derive(Debug, PartialEq, Clone)]
 pub struct HeartRateMeasurement {
@@ -70,10 +72,20 @@ pub fn parse_hrm(data: &[u8]) -> HeartRateMeasurement {
     }
 }
 
+/// Undoes `parse_hrm`'s 1/1024 second scaling for devices that send milliseconds.
+pub fn apply_rr_unit(rr_intervals: &mut [Duration], rr_unit: RrUnit) {
+    if rr_unit == RrUnit::Milliseconds {
+        for rr in rr_intervals {
+            *rr = rr.mul_f64(1024.0 / 1000.0);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::parse_hrm;
     use super::HeartRateMeasurement;
+    use super::{apply_rr_unit, RrUnit};
     use std::time::Duration;
 
     #[test]
@@ -222,4 +234,14 @@ mod tests {
             parse_hrm(&[0, 70])
         );
     }
+
+    #[test]
+    fn rr_in_milliseconds() {
+        // 800ms, read as 800/1024ths
+        let mut rr_intervals = parse_hrm(&[0x10, 75, 0x20, 0x03]).rr_intervals;
+        apply_rr_unit(&mut rr_intervals, RrUnit::Standard);
+        assert_eq!(rr_intervals[0].as_millis(), 781);
+        apply_rr_unit(&mut rr_intervals, RrUnit::Milliseconds);
+        assert_eq!(rr_intervals[0].as_millis(), 800);
+    }
 }
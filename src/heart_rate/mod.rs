@@ -1,9 +1,37 @@
+pub mod advertisement;
+pub mod aggregate;
+pub mod ant;
+pub mod beat_timer;
 pub mod ble;
+pub mod cadence;
+pub mod device_information;
 pub mod dummy;
+pub mod failover;
+pub mod fitbit;
+pub mod ftms;
+pub mod http_poll;
+pub mod hyperate;
+pub mod low_battery;
 pub mod measurement;
+pub mod mqtt;
+pub mod plx;
+pub mod polar;
+pub mod pulsoid;
+pub mod remote;
+pub mod serial;
+pub mod stdin;
+pub mod stromno;
+pub mod twitcher;
+pub mod udp;
 pub mod websocket;
 
-mod twitcher;
+mod http_ingest;
+mod input_guard;
+mod obs_mimic;
+mod raw_dump;
+mod simple_reading;
+mod stress;
+mod wire_format;
 
 use std::time::Duration;
 
@@ -35,6 +63,16 @@ pub struct HeartRateStatus {
     // all listeners see twitches at the same time
     pub twitch_up: bool,
     pub twitch_down: bool,
+    // Baevsky's Stress Index, once enough RR intervals have come in
+    pub stress_index: Option<f32>,
+    // Signal strength in dBm, only reported by BLE (and not on every platform)
+    pub rssi: Option<i16>,
+    // Blood oxygen saturation percentage, from devices with the Pulse Oximeter Service
+    pub spo2: Option<f32>,
+    // Steps or crank RPM, from a device that also has the Running/Cycling Speed and Cadence Service
+    pub cadence: Option<u16>,
+    // Which device this came from when connected to two at once, see `aggregate`
+    pub source: u8,
     pub timestamp: DateTime<Local>,
 }
 
@@ -47,6 +85,12 @@ pub fn rr_from_bpm(bpm: u16) -> Duration {
     Duration::from_secs_f32(60.0 / bpm as f32)
 }
 
+// Beat-to-beat BPM, as opposed to the (usually smoothed) BPM the device reports
+pub fn bpm_from_rr(rr: Duration) -> Option<f32> {
+    let secs = rr.as_secs_f32();
+    (secs > 0.0).then(|| 60.0 / secs)
+}
+
 // #[derive(Error, Debug)]
 // pub enum MonitorError {
 //     #[error("Device is missing HR service")]
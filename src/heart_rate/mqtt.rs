@@ -0,0 +1,451 @@
+// Just enough of an MQTT 3.1.1 client to subscribe to a topic and receive what's published to it
+// http://docs.oasis-open.org/mqtt/mqtt/v3.1.1/os/mqtt-v3.1.1-os.html
+
+use super::simple_reading::{parse_json_or_bpm, SimpleReadings};
+use super::twitcher::TwitchThreshold;
+use super::HeartRateStatus;
+use crate::app::{AppUpdate, ErrorPopup};
+use crate::broadcast;
+use crate::errors::AppError;
+use crate::journal::{self, Severity, Subsystem};
+use crate::logging::raw_capture;
+use crate::reconnect::{self, ConnectError, ReconnectingClient};
+use crate::settings::MqttSettings;
+use crate::sources::{Capabilities, HeartRateSource, HrSource, SourceContext};
+
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast::Sender as BSender;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info};
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+pub(crate) const PUBLISH: u8 = 0x30;
+const PUBACK: u8 = 0x40;
+// Reserved flag bits are 0b0010 for SUBSCRIBE
+const SUBSCRIBE: u8 = 0x82;
+const SUBACK: u8 = 0x90;
+pub(crate) const PINGREQ: u8 = 0xC0;
+const PINGRESP: u8 = 0xD0;
+pub(crate) const DISCONNECT: u8 = 0xE0;
+
+const PROTOCOL_LEVEL: u8 = 4;
+const CONNECT_FLAG_CLEAN_SESSION: u8 = 0x02;
+const CONNECT_FLAG_WILL: u8 = 0x04;
+const CONNECT_FLAG_WILL_RETAIN: u8 = 0x20;
+const CONNECT_FLAG_PASSWORD: u8 = 0x40;
+const CONNECT_FLAG_USERNAME: u8 = 0x80;
+const SUBACK_FAILURE: u8 = 0x80;
+
+pub(crate) const KEEP_ALIVE: Duration = Duration::from_secs(30);
+// Way more than any HR reading, but topics with retained blobs exist
+const MAX_PACKET: usize = 64 * 1024;
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Packet {
+    ConnAck {
+        return_code: u8,
+    },
+    SubAck {
+        granted_qos: u8,
+    },
+    Publish {
+        topic: String,
+        packet_id: Option<u16>,
+        payload: Vec<u8>,
+    },
+    PingResp,
+    Other(u8),
+}
+
+fn encode_remaining_length(mut len: usize, out: &mut Vec<u8>) {
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        out.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+}
+
+fn encode_string(text: &str, out: &mut Vec<u8>) {
+    out.extend((text.len() as u16).to_be_bytes());
+    out.extend(text.as_bytes());
+}
+
+pub(crate) fn packet(packet_type: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![packet_type];
+    encode_remaining_length(body.len(), &mut packet);
+    packet.extend(body);
+    packet
+}
+
+fn connect_packet(mqtt_settings: &MqttSettings) -> Vec<u8> {
+    connect_packet_with_will(mqtt_settings, None)
+}
+
+/// CONNECT, with a retained message (topic, payload) for the broker to publish if we vanish without a DISCONNECT.
+pub(crate) fn connect_packet_with_will(
+    mqtt_settings: &MqttSettings,
+    will: Option<(&str, &str)>,
+) -> Vec<u8> {
+    let mut flags = CONNECT_FLAG_CLEAN_SESSION;
+    if will.is_some() {
+        flags |= CONNECT_FLAG_WILL | CONNECT_FLAG_WILL_RETAIN;
+    }
+    if !mqtt_settings.username.is_empty() {
+        flags |= CONNECT_FLAG_USERNAME;
+        // Password without a username isn't allowed
+        if !mqtt_settings.password.is_empty() {
+            flags |= CONNECT_FLAG_PASSWORD;
+        }
+    }
+    let mut body = Vec::new();
+    encode_string("MQTT", &mut body);
+    body.push(PROTOCOL_LEVEL);
+    body.push(flags);
+    body.extend((KEEP_ALIVE.as_secs() as u16).to_be_bytes());
+    encode_string(&mqtt_settings.client_id, &mut body);
+    if let Some((topic, payload)) = will {
+        encode_string(topic, &mut body);
+        encode_string(payload, &mut body);
+    }
+    if flags & CONNECT_FLAG_USERNAME != 0 {
+        encode_string(&mqtt_settings.username, &mut body);
+    }
+    if flags & CONNECT_FLAG_PASSWORD != 0 {
+        encode_string(&mqtt_settings.password, &mut body);
+    }
+    packet(CONNECT, &body)
+}
+
+// QoS 0, a missed reading will be replaced by the next one soon enough
+fn subscribe_packet(packet_id: u16, topic: &str) -> Vec<u8> {
+    let mut body = packet_id.to_be_bytes().to_vec();
+    encode_string(topic, &mut body);
+    body.push(0);
+    packet(SUBSCRIBE, &body)
+}
+
+// QoS 0 as well, nothing's resent if it doesn't make it
+pub(crate) fn publish_packet(topic: &str, payload: &[u8], retain: bool) -> Vec<u8> {
+    let mut body = Vec::new();
+    encode_string(topic, &mut body);
+    body.extend(payload);
+    packet(PUBLISH | u8::from(retain), &body)
+}
+
+/// Pulls every complete packet out of `buffer`, leaving anything partial for next time.
+pub(crate) fn decode_packets(buffer: &mut Vec<u8>) -> Result<Vec<Packet>, String> {
+    let mut packets = Vec::new();
+    while let Some(&header) = buffer.first() {
+        let mut remaining_len = 0;
+        let mut header_len = 1;
+        let mut complete_len = false;
+        for (i, byte) in buffer.iter().skip(1).take(4).enumerate() {
+            remaining_len |= ((byte & 0x7F) as usize) << (7 * i);
+            header_len += 1;
+            if byte & 0x80 == 0 {
+                complete_len = true;
+                break;
+            }
+        }
+        if !complete_len {
+            if buffer.len() > 4 {
+                return Err("Malformed packet length".into());
+            }
+            break;
+        }
+        if remaining_len > MAX_PACKET {
+            return Err(format!("Packet too large ({remaining_len} bytes)"));
+        }
+        if buffer.len() < header_len + remaining_len {
+            break;
+        }
+        let body: Vec<u8> = buffer
+            .drain(..header_len + remaining_len)
+            .skip(header_len)
+            .collect();
+        packets.push(parse_packet(header, &body)?);
+    }
+    Ok(packets)
+}
+
+fn parse_packet(header: u8, body: &[u8]) -> Result<Packet, String> {
+    let packet = match (header & 0xF0, body) {
+        (CONNACK, [_, return_code]) => Packet::ConnAck {
+            return_code: *return_code,
+        },
+        (SUBACK, [_, _, granted_qos, ..]) => Packet::SubAck {
+            granted_qos: *granted_qos,
+        },
+        (PUBLISH, [len_high, len_low, rest @ ..]) => {
+            let topic_len = u16::from_be_bytes([*len_high, *len_low]) as usize;
+            let qos = (header >> 1) & 0x03;
+            let id_len = if qos > 0 { 2 } else { 0 };
+            if rest.len() < topic_len + id_len {
+                return Err("Truncated PUBLISH".into());
+            }
+            let (topic, rest) = rest.split_at(topic_len);
+            let (packet_id, payload) = rest.split_at(id_len);
+            Packet::Publish {
+                topic: String::from_utf8_lossy(topic).into_owned(),
+                packet_id: (qos > 0).then(|| u16::from_be_bytes([packet_id[0], packet_id[1]])),
+                payload: payload.to_vec(),
+            }
+        }
+        (PINGRESP, _) => Packet::PingResp,
+        (packet_type, _) => Packet::Other(packet_type),
+    };
+    Ok(packet)
+}
+
+pub(crate) fn refusal_reason(return_code: u8) -> &'static str {
+    match return_code {
+        1 => "unsupported protocol version",
+        2 => "client ID rejected",
+        3 => "server unavailable",
+        4 => "bad username or password",
+        5 => "not authorized",
+        _ => "unknown reason",
+    }
+}
+
+struct MqttActor {
+    mqtt_settings: MqttSettings,
+    readings: SimpleReadings,
+    buffer: Vec<u8>,
+}
+
+impl ReconnectingClient for MqttActor {
+    type Connection = TcpStream;
+    type Updates = ();
+    fn name(&self) -> &'static str {
+        "MQTT"
+    }
+    fn subsystem(&self) -> Subsystem {
+        Subsystem::Mqtt
+    }
+    fn target(&self) -> String {
+        format!("{}:{}", self.mqtt_settings.host, self.mqtt_settings.port)
+    }
+    async fn connect(&mut self) -> Result<TcpStream, ConnectError> {
+        TcpStream::connect(self.target())
+            .await
+            .map_err(|e| ConnectError::Retry(e.to_string()))
+    }
+    async fn session(
+        &mut self,
+        mut stream: TcpStream,
+        _updates: &mut (),
+        broadcast_tx: &BSender<AppUpdate>,
+        cancel_token: &CancellationToken,
+    ) -> Result<Option<String>, AppError> {
+        self.buffer.clear();
+        stream
+            .write_all(&connect_packet(&self.mqtt_settings))
+            .await?;
+
+        let mut ping = tokio::time::interval(KEEP_ALIVE / 2);
+        // First tick is immediate
+        ping.tick().await;
+        let mut read_buf = [0; 4096];
+        loop {
+            let read = tokio::select! {
+                read = stream.read(&mut read_buf) => read,
+                _ = ping.tick() => {
+                    stream.write_all(&[PINGREQ, 0]).await?;
+                    continue;
+                }
+                _ = cancel_token.cancelled() => {
+                    // Polite, but the broker will figure it out either way
+                    let _ = stream.write_all(&[DISCONNECT, 0]).await;
+                    return Ok(None);
+                }
+            };
+            match read {
+                Ok(0) => return Ok(Some("MQTT broker closed the connection".into())),
+                Ok(len) => self.buffer.extend(&read_buf[..len]),
+                Err(e) => return Ok(Some(format!("Error receiving from MQTT broker: {e}"))),
+            }
+            let packets = match decode_packets(&mut self.buffer) {
+                Ok(packets) => packets,
+                Err(e) => return Ok(Some(e)),
+            };
+            for packet in packets {
+                match packet {
+                    Packet::ConnAck { return_code: 0 } => {
+                        info!("Connected to MQTT broker at {}", self.target());
+                        stream
+                            .write_all(&subscribe_packet(1, &self.mqtt_settings.topic))
+                            .await?;
+                    }
+                    Packet::ConnAck {
+                        return_code: return_code @ (4 | 5),
+                    } => {
+                        return Err(AppError::MqttRefused(refusal_reason(return_code)));
+                    }
+                    Packet::ConnAck { return_code } => {
+                        return Ok(Some(format!(
+                            "MQTT broker refused connection: {}",
+                            refusal_reason(return_code)
+                        )));
+                    }
+                    Packet::SubAck {
+                        granted_qos: SUBACK_FAILURE,
+                    } => {
+                        return Err(AppError::MqttSubscribe(self.mqtt_settings.topic.clone()));
+                    }
+                    Packet::SubAck { .. } => {
+                        debug!("Subscribed to {}", self.mqtt_settings.topic);
+                    }
+                    Packet::Publish {
+                        topic,
+                        packet_id,
+                        payload,
+                    } => {
+                        // Shouldn't happen since we ask for QoS 0, but brokers are allowed to
+                        if let Some(packet_id) = packet_id {
+                            let [high, low] = packet_id.to_be_bytes();
+                            stream.write_all(&[PUBACK, 2, high, low]).await?;
+                        }
+                        let payload = String::from_utf8_lossy(&payload);
+                        raw_capture::record_mqtt(&payload);
+                        match parse_json_or_bpm(&payload) {
+                            Some(reading) => {
+                                broadcast!(broadcast_tx, self.readings.apply(reading));
+                            }
+                            None => debug!("Ignoring MQTT payload on {topic}: {payload}"),
+                        }
+                    }
+                    Packet::PingResp => {}
+                    Packet::Other(packet_type) => {
+                        debug!("Ignoring MQTT packet type {packet_type:#04x}");
+                    }
+                }
+            }
+        }
+    }
+    fn on_lost(&mut self, broadcast_tx: &BSender<AppUpdate>) {
+        broadcast!(
+            broadcast_tx,
+            HeartRateStatus::default(),
+            "Failed to send 0BPM after losing MQTT broker"
+        );
+    }
+}
+
+pub async fn mqtt_thread(
+    broadcast_tx: BSender<AppUpdate>,
+    mqtt_settings: MqttSettings,
+    rr_twitch_threshold: TwitchThreshold,
+    stress_index_window: usize,
+    cancel_token: CancellationToken,
+) {
+    let mut mqtt = MqttActor {
+        mqtt_settings,
+        readings: SimpleReadings::new(rr_twitch_threshold, stress_index_window),
+        buffer: Vec::new(),
+    };
+    let result = async {
+        if mqtt.mqtt_settings.topic.is_empty() {
+            return Err(AppError::MqttTopicMissing);
+        }
+        reconnect::run(&mut mqtt, &mut (), &broadcast_tx, &cancel_token).await
+    };
+    if let Err(e) = result.await {
+        error!("MQTT error: {e}");
+        journal::record(Severity::Error, Subsystem::Mqtt, &e.to_string());
+        broadcast!(broadcast_tx, ErrorPopup::detailed("MQTT error", e));
+    }
+}
+
+pub struct MqttSource;
+
+impl HeartRateSource for MqttSource {
+    fn source(&self) -> HrSource {
+        HrSource::Mqtt
+    }
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::FULL
+    }
+    fn spawn(self: Box<Self>, context: SourceContext) -> JoinHandle<()> {
+        tokio::spawn(mqtt_thread(
+            context.broadcast_tx,
+            context.settings.mqtt,
+            context.rr_twitch_threshold,
+            context.stress_index_window,
+            context.cancel_token,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_connect_and_subscribe() {
+        let settings = MqttSettings {
+            client_id: "iron-heart".into(),
+            username: "user".into(),
+            password: "pass".into(),
+            ..Default::default()
+        };
+        let connect = connect_packet(&settings);
+        assert_eq!(&connect[..2], &[CONNECT, 34]);
+        assert_eq!(&connect[2..8], b"\x00\x04MQTT");
+        // Level, flags, keep alive
+        assert_eq!(&connect[8..12], &[4, 0xC2, 0, 30]);
+        assert!(connect.ends_with(b"\x00\x04user\x00\x04pass"));
+
+        let subscribe = subscribe_packet(1, "hr");
+        assert_eq!(subscribe, vec![SUBSCRIBE, 7, 0, 1, 0, 2, b'h', b'r', 0]);
+
+        let connect = connect_packet_with_will(&settings, Some(("hr/status", "offline")));
+        assert_eq!(connect[9], 0xC2 | 0x24);
+        assert!(connect.ends_with(b"\x00\x09hr/status\x00\x07offline\x00\x04user\x00\x04pass"));
+
+        let publish = publish_packet("hr", b"72", true);
+        assert_eq!(publish, vec![PUBLISH | 1, 6, 0, 2, b'h', b'r', b'7', b'2']);
+
+        let mut long = Vec::new();
+        encode_remaining_length(321, &mut long);
+        assert_eq!(long, vec![0xC1, 0x02]);
+    }
+    #[test]
+    fn decodes_split_packets() {
+        let mut buffer = vec![CONNACK, 2, 0, 0, SUBACK, 3, 0, 1, 0];
+        // Start of a QoS 1 PUBLISH to "hr" with "72"
+        let publish = [PUBLISH | 0x02, 8, 0, 2, b'h', b'r', 0, 5, b'7', b'2'];
+        buffer.extend(&publish[..5]);
+        assert_eq!(
+            decode_packets(&mut buffer).unwrap(),
+            vec![
+                Packet::ConnAck { return_code: 0 },
+                Packet::SubAck { granted_qos: 0 }
+            ]
+        );
+        buffer.extend(&publish[5..]);
+        buffer.extend([PINGRESP, 0]);
+        assert_eq!(
+            decode_packets(&mut buffer).unwrap(),
+            vec![
+                Packet::Publish {
+                    topic: "hr".into(),
+                    packet_id: Some(5),
+                    payload: b"72".to_vec(),
+                },
+                Packet::PingResp
+            ]
+        );
+        assert!(buffer.is_empty());
+    }
+}
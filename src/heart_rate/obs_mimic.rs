@@ -0,0 +1,154 @@
+// Just enough of obs-websocket for HeartRateOnStream (watchOS/Wear OS) to think it's talking to OBS,
+// it "updates a text source" with the BPM, which is all we actually want
+// Reference: https://github.com/Curtis-VL/HeartRateOnStream-OSC/blob/main/Program.cs
+// v5: https://github.com/obsproject/obs-websocket/blob/master/docs/generated/protocol.md
+// v4 is still spoken by older versions of the app
+
+use serde_json::{json, Value};
+
+// v5 OpCodes
+const OP_HELLO: u64 = 0;
+const OP_IDENTIFY: u64 = 1;
+const OP_IDENTIFIED: u64 = 2;
+const OP_REQUEST: u64 = 6;
+const OP_REQUEST_RESPONSE: u64 = 7;
+// RequestStatus::Success
+const REQUEST_SUCCESS: u64 = 100;
+
+/// What to do with a message from an OBS client.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) struct ObsReply {
+    pub response: Option<String>,
+    // From whatever text it tried to set
+    pub bpm: Option<u16>,
+}
+
+/// Sent as soon as a v5 client connects, no authentication.
+pub(super) fn hello() -> String {
+    json!({
+        "op": OP_HELLO,
+        "d": {
+            "obsWebSocketVersion": "5.0.0",
+            "rpcVersion": 1,
+        }
+    })
+    .to_string()
+}
+
+/// Returns None if `text` isn't an obs-websocket message (i.e. one of our own JSON payloads).
+pub(super) fn handle_message(text: &str) -> Option<ObsReply> {
+    let message: Value = serde_json::from_str(text).ok()?;
+    if let Some(op) = message.get("op").and_then(Value::as_u64) {
+        return Some(handle_v5(op, message.get("d").unwrap_or(&Value::Null)));
+    }
+    message.get("request-type")?;
+    Some(handle_v4(&message))
+}
+
+fn handle_v5(op: u64, data: &Value) -> ObsReply {
+    match op {
+        OP_IDENTIFY => ObsReply {
+            response: Some(
+                json!({"op": OP_IDENTIFIED, "d": {"negotiatedRpcVersion": 1}}).to_string(),
+            ),
+            bpm: None,
+        },
+        OP_REQUEST => {
+            let request_data = data.get("requestData");
+            // SetInputSettings puts it under inputSettings, older text source requests have it at the top
+            let text = request_data
+                .and_then(|d| d.pointer("/inputSettings/text").or_else(|| d.get("text")));
+            let response = json!({
+                "op": OP_REQUEST_RESPONSE,
+                "d": {
+                    "requestType": data.get("requestType"),
+                    "requestId": data.get("requestId"),
+                    "requestStatus": {"result": true, "code": REQUEST_SUCCESS},
+                    "responseData": fake_response_data(data.get("requestType")),
+                }
+            });
+            ObsReply {
+                response: Some(response.to_string()),
+                bpm: text.and_then(Value::as_str).and_then(parse_bpm),
+            }
+        }
+        // Reidentify/batches/etc, nothing HeartRateOnStream needs
+        _ => ObsReply {
+            response: None,
+            bpm: None,
+        },
+    }
+}
+
+// Only the list requests need something there for clients to be happy
+fn fake_response_data(request_type: Option<&Value>) -> Value {
+    match request_type.and_then(Value::as_str) {
+        Some("GetInputList") => json!({"inputs": []}),
+        Some("GetSceneList") => json!({"scenes": [], "currentProgramSceneName": "Scene"}),
+        Some("GetVersion") => json!({"obsWebSocketVersion": "5.0.0", "rpcVersion": 1}),
+        _ => json!({}),
+    }
+}
+
+fn handle_v4(message: &Value) -> ObsReply {
+    let text = message
+        .get("text")
+        .or_else(|| message.pointer("/sourceSettings/text"));
+    let mut response = json!({
+        "message-id": message.get("message-id"),
+        "status": "ok",
+    });
+    if message.get("request-type").and_then(Value::as_str) == Some("GetAuthRequired") {
+        response["authRequired"] = false.into();
+    }
+    ObsReply {
+        response: Some(response.to_string()),
+        bpm: text.and_then(Value::as_str).and_then(parse_bpm),
+    }
+}
+
+// The text is whatever the user set up in the app, i.e. "❤️ 82" or "82 BPM"
+fn parse_bpm(text: &str) -> Option<u16> {
+    let start = text.find(|c: char| c.is_ascii_digit())?;
+    let digits: String = text[start..]
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect();
+    digits.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn v5_handshake_and_text() {
+        let identified = handle_message(r#"{"op":1,"d":{"rpcVersion":1}}"#).unwrap();
+        assert!(identified.response.unwrap().contains(r#""op":2"#));
+
+        let request = r#"{"op":6,"d":{"requestType":"SetInputSettings","requestId":"abc",
+            "requestData":{"inputName":"HR","inputSettings":{"text":"❤️ 82 BPM"}}}}"#;
+        let reply = handle_message(request).unwrap();
+        assert_eq!(reply.bpm, Some(82));
+        let response: Value = serde_json::from_str(&reply.response.unwrap()).unwrap();
+        assert_eq!(response["op"], 7);
+        assert_eq!(response["d"]["requestId"], "abc");
+        assert_eq!(response["d"]["requestStatus"]["result"], true);
+    }
+    #[test]
+    fn v4_requests() {
+        let reply =
+            handle_message(r#"{"request-type":"GetAuthRequired","message-id":"1"}"#).unwrap();
+        assert_eq!(reply.bpm, None);
+        assert!(reply.response.unwrap().contains(r#""authRequired":false"#));
+
+        let request = r#"{"request-type":"SetTextGDIPlusProperties","message-id":"2","source":"HR","text":"75"}"#;
+        assert_eq!(handle_message(request).unwrap().bpm, Some(75));
+    }
+    #[test]
+    fn ignores_other_json() {
+        assert_eq!(handle_message(r#"{"bpm":80}"#), None);
+        assert_eq!(handle_message("not json"), None);
+        assert_eq!(parse_bpm("no numbers"), None);
+    }
+}
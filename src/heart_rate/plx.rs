@@ -0,0 +1,81 @@
+// Pulse Oximeter Service (PLXS) parsing, for SpO2-capable devices
+// https://www.bluetooth.com/specifications/specs/pulse-oximeter-service-1-0-1/
+
+use uuid::Uuid;
+
+pub const PULSE_OXIMETER_SERVICE_UUID: Uuid =
+    Uuid::from_u128(0x00001822_0000_1000_8000_00805f9b34fb); // 00001822-0000-1000-8000-00805f9b34fb
+pub const PLX_CONTINUOUS_MEASUREMENT_CHARACTERISTIC_UUID: Uuid =
+    Uuid::from_u128(0x00002a5f_0000_1000_8000_00805f9b34fb); // 00002a5f-0000-1000-8000-00805f9b34fb
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PlxMeasurement {
+    // Percent
+    pub spo2: f32,
+    pub pulse_rate: f32,
+}
+
+/// IEEE 11073 16-bit SFLOAT, a 4-bit exponent and 12-bit mantissa (both signed).
+///
+/// Returns None for the special values (NaN, NRes, +/-INFINITY, reserved).
+fn parse_sfloat(raw: u16) -> Option<f32> {
+    let mantissa = raw & 0x0FFF;
+    if (0x07FE..=0x0802).contains(&mantissa) {
+        return None;
+    }
+    // Sign extending both
+    let mantissa = ((mantissa << 4) as i16 >> 4) as f32;
+    let exponent = (raw as i16) >> 12;
+    Some(mantissa * 10f32.powi(exponent as i32))
+}
+
+/// Parses a PLX Continuous Measurement, only the required "normal" SpO2 and pulse rate.
+///
+/// The optional fields that follow (fast/slow readings, status flags, etc.) are ignored.
+pub fn parse_plx_continuous(data: &[u8]) -> Option<PlxMeasurement> {
+    // Flags byte, then two SFLOATs
+    let [_flags, spo2_lo, spo2_hi, pr_lo, pr_hi, ..] = *data else {
+        return None;
+    };
+    Some(PlxMeasurement {
+        spo2: parse_sfloat(u16::from_le_bytes([spo2_lo, spo2_hi]))?,
+        pulse_rate: parse_sfloat(u16::from_le_bytes([pr_lo, pr_hi]))?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sfloat_values() {
+        assert_eq!(parse_sfloat(0x0062), Some(98.0));
+        // 975 * 10^-1
+        assert_eq!(parse_sfloat(0xF3CF), Some(97.5));
+        // -1 * 10^0
+        assert_eq!(parse_sfloat(0x0FFF), Some(-1.0));
+        assert_eq!(parse_sfloat(0x07FF), None);
+        assert_eq!(parse_sfloat(0x0800), None);
+    }
+    #[test]
+    fn continuous_measurement() {
+        // No optional fields, 98% at 72 BPM
+        let data = [0x00, 0x62, 0x00, 0x48, 0x00];
+        assert_eq!(
+            parse_plx_continuous(&data),
+            Some(PlxMeasurement {
+                spo2: 98.0,
+                pulse_rate: 72.0
+            })
+        );
+        // Trailing optional fields are fine
+        let data = [0x01, 0x62, 0x00, 0x48, 0x00, 0x61, 0x00, 0x47, 0x00];
+        assert!(parse_plx_continuous(&data).is_some());
+    }
+    #[test]
+    fn invalid_measurements() {
+        assert_eq!(parse_plx_continuous(&[0x00, 0x62, 0x00]), None);
+        // Sensor hasn't got a reading yet
+        assert_eq!(parse_plx_continuous(&[0x00, 0xFF, 0x07, 0xFF, 0x07]), None);
+    }
+}
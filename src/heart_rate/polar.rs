@@ -0,0 +1,252 @@
+// Polar Measurement Data (PMD) service, for raw ECG from the H10 (and similar)
+// https://github.com/polarofficial/polar-ble-sdk/blob/master/technical_documentation/Polar_Measurement_Data_Specification.pdf
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+
+pub const PMD_SERVICE_UUID: Uuid = Uuid::from_u128(0xfb005c80_02e7_f387_1cad_8acd2d8df0c8); // fb005c80-02e7-f387-1cad-8acd2d8df0c8
+pub const PMD_CONTROL_POINT_UUID: Uuid = Uuid::from_u128(0xfb005c81_02e7_f387_1cad_8acd2d8df0c8); // fb005c81-02e7-f387-1cad-8acd2d8df0c8
+pub const PMD_DATA_UUID: Uuid = Uuid::from_u128(0xfb005c82_02e7_f387_1cad_8acd2d8df0c8); // fb005c82-02e7-f387-1cad-8acd2d8df0c8
+
+// The H10 only does ECG at 130Hz with 14 bit resolution
+const ECG_SAMPLE_RATE: u32 = 130;
+
+/// Start ECG: Sample rate (130Hz) then resolution (14 bits) settings.
+pub const START_ECG_COMMAND: [u8; 10] =
+    [0x02, 0x00, 0x00, 0x01, 0x82, 0x00, 0x01, 0x01, 0x0E, 0x00];
+
+const MEASUREMENT_TYPE_ECG: u8 = 0x00;
+const FRAME_TYPE_RAW: u8 = 0x00;
+
+#[derive(Debug, PartialEq)]
+pub struct EcgFrame {
+    // Of the last sample, in nanoseconds since 2000-01-01
+    pub timestamp_ns: u64,
+    // Microvolts
+    pub samples: Vec<i32>,
+}
+
+/// Parses a PMD data notification, only uncompressed ECG frames are supported.
+pub fn parse_ecg_frame(data: &[u8]) -> Option<EcgFrame> {
+    let (&measurement_type, rest) = data.split_first()?;
+    if measurement_type != MEASUREMENT_TYPE_ECG || rest.len() < 9 {
+        return None;
+    }
+    let timestamp_ns = u64::from_le_bytes(rest[..8].try_into().ok()?);
+    if rest[8] != FRAME_TYPE_RAW {
+        return None;
+    }
+    let samples = rest[9..]
+        .chunks_exact(3)
+        .map(|sample| {
+            // Sign extending the 24 bit sample
+            i32::from_le_bytes([sample[0], sample[1], sample[2], 0]) << 8 >> 8
+        })
+        .collect();
+    Some(EcgFrame {
+        timestamp_ns,
+        samples,
+    })
+}
+
+// Steepest recent upslope fades by this much each sample (about a 1 second half-life)
+const SLOPE_DECAY: f32 = 0.995;
+// An upslope this close to the steepest recent one counts as an R wave
+const SLOPE_THRESHOLD: f32 = 0.6;
+// Nothing's detected until the steepest slope has had a chance to settle
+const WARMUP_SAMPLES: u64 = ECG_SAMPLE_RATE as u64;
+// No two beats within 250ms (240 BPM)
+const REFRACTORY_SAMPLES: u64 = ECG_SAMPLE_RATE as u64 / 4;
+// Anything longer is a gap in detection rather than an interval
+const MAX_RR: Duration = Duration::from_secs(2);
+
+/// A bare-bones R wave detector, looking for sharp upslopes in the ECG.
+///
+/// Not meant to replace the device's own RR intervals, just to check them against.
+#[derive(Debug, Default)]
+pub struct RPeakDetector {
+    previous: Option<i32>,
+    steepest_slope: f32,
+    sample_index: u64,
+    last_beat: Option<u64>,
+}
+
+impl RPeakDetector {
+    /// Returns the RR interval if this sample was an R wave (and there was one before it).
+    pub fn push(&mut self, sample: i32) -> Option<Duration> {
+        let index = self.sample_index;
+        self.sample_index += 1;
+        let slope = sample.saturating_sub(self.previous.replace(sample)?) as f32;
+        self.steepest_slope = (self.steepest_slope * SLOPE_DECAY).max(slope);
+        if index < WARMUP_SAMPLES || slope < self.steepest_slope * SLOPE_THRESHOLD {
+            return None;
+        }
+        if matches!(self.last_beat, Some(last) if index - last < REFRACTORY_SAMPLES) {
+            return None;
+        }
+        let rr = self
+            .last_beat
+            .replace(index)
+            .map(|last| Duration::from_secs_f64((index - last) as f64 / ECG_SAMPLE_RATE as f64));
+        rr.filter(|rr| *rr <= MAX_RR)
+    }
+}
+
+/// Writes ECG samples out as CSV, one per line: `timestamp_ns,microvolts,beat`
+pub struct EcgLog {
+    writer: BufWriter<File>,
+}
+
+impl EcgLog {
+    pub fn open(path: &Path) -> Result<Self, AppError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| AppError::CreateFile {
+                path: path.to_owned(),
+                source: e,
+            })?;
+        // Appending to an earlier log, it already has the header
+        let is_new = file.metadata()?.len() == 0;
+        let mut writer = BufWriter::new(file);
+        if is_new {
+            writeln!(writer, "timestamp_ns,microvolts,beat")?;
+        }
+        Ok(Self { writer })
+    }
+    /// Timestamps are worked back from the frame's, which is for the last sample.
+    pub fn write_frame(&mut self, frame: &EcgFrame, beats: &[bool]) -> Result<(), AppError> {
+        let period_ns = 1_000_000_000 / ECG_SAMPLE_RATE as u64;
+        let count = frame.samples.len() as u64;
+        for (i, (sample, beat)) in frame.samples.iter().zip(beats).enumerate() {
+            let timestamp = frame
+                .timestamp_ns
+                .saturating_sub((count - 1 - i as u64) * period_ns);
+            writeln!(self.writer, "{timestamp},{sample},{}", u8::from(*beat))?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// ECG streaming state for a connected Polar device.
+pub struct PolarEcg {
+    detector: RPeakDetector,
+    // Only present if `ble.polar_ecg_log_path` is set
+    log: Option<EcgLog>,
+    // Latest interval found in the ECG, to check the device's against
+    latest_rr: Option<Duration>,
+}
+
+impl PolarEcg {
+    pub fn new(log_path: &str) -> Self {
+        let log = if log_path.is_empty() {
+            None
+        } else {
+            match EcgLog::open(Path::new(log_path)) {
+                Ok(log) => Some(log),
+                Err(e) => {
+                    warn!("Couldn't open ECG log: {e}");
+                    None
+                }
+            }
+        };
+        Self {
+            detector: RPeakDetector::default(),
+            log,
+            latest_rr: None,
+        }
+    }
+    pub fn handle_data(&mut self, data: &[u8]) {
+        let Some(frame) = parse_ecg_frame(data) else {
+            return;
+        };
+        let mut beats = Vec::with_capacity(frame.samples.len());
+        for &sample in &frame.samples {
+            let rr = self.detector.push(sample);
+            beats.push(rr.is_some());
+            if rr.is_some() {
+                self.latest_rr = rr;
+            }
+        }
+        if let Some(log) = self.log.as_mut() {
+            if let Err(e) = log.write_frame(&frame, &beats) {
+                warn!("Failed to write ECG log, no longer logging: {e}");
+                self.log = None;
+            }
+        }
+    }
+    /// Logs how the device's latest RR interval compares to the ECG's.
+    pub fn compare_rr(&self, device_rr: Option<&Duration>) {
+        if let (Some(device_rr), Some(ecg_rr)) = (device_rr, self.latest_rr) {
+            debug!(
+                "RR check: Device {}ms, ECG {}ms",
+                device_rr.as_millis(),
+                ecg_rr.as_millis()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ecg_frame_parsing() {
+        let mut data = vec![0x00];
+        data.extend_from_slice(&1_000u64.to_le_bytes());
+        data.push(0x00);
+        // 100uV, then -2uV
+        data.extend_from_slice(&[0x64, 0x00, 0x00, 0xFE, 0xFF, 0xFF]);
+        assert_eq!(
+            parse_ecg_frame(&data),
+            Some(EcgFrame {
+                timestamp_ns: 1_000,
+                samples: vec![100, -2]
+            })
+        );
+        // Accelerometer frame
+        data[0] = 0x02;
+        assert_eq!(parse_ecg_frame(&data), None);
+        assert_eq!(parse_ecg_frame(&[0x00, 0x01]), None);
+    }
+    #[test]
+    fn detects_regular_beats() {
+        let mut detector = RPeakDetector::default();
+        // A spike every 100 samples, with a bit of wander in between
+        let intervals: Vec<Duration> = (0..1000)
+            .map(|i| if i % 100 == 50 { 1200 } else { (i % 7) * 5 })
+            .filter_map(|sample| detector.push(sample))
+            .collect();
+        assert!(!intervals.is_empty());
+        let expected = Duration::from_secs_f64(100.0 / 130.0);
+        assert!(intervals.iter().all(|rr| *rr == expected), "{intervals:?}");
+    }
+    #[test]
+    fn ecg_log_header_once() {
+        let path = std::env::temp_dir().join(format!("nih-ecg-{}.csv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let frame = EcgFrame {
+            timestamp_ns: 1_000,
+            samples: vec![100],
+        };
+        for _ in 0..2 {
+            let mut log = EcgLog::open(&path).unwrap();
+            log.write_frame(&frame, &[false]).unwrap();
+        }
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            contents,
+            "timestamp_ns,microvolts,beat\n1000,100,0\n1000,100,0\n"
+        );
+    }
+}
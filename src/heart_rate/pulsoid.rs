@@ -0,0 +1,106 @@
+use super::remote::{remote_thread, RemoteProvider};
+use super::twitcher::TwitchThreshold;
+use crate::app::AppUpdate;
+use crate::errors::AppError;
+use crate::journal::Subsystem;
+use crate::settings::PulsoidSettings;
+use crate::sources::{Capabilities, HeartRateSource, HrSource, SourceContext};
+
+use serde::Deserialize;
+use tokio::sync::broadcast::Sender as BSender;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+// https://docs.pulsoid.net/access-token-management/manual-token-issuing
+const REAL_TIME_URL: &str = "wss://dev.pulsoid.net/api/v1/data/real_time";
+
+// {"measured_at": 1625310655000, "data": {"heart_rate": 40}}
+#[derive(Debug, Deserialize)]
+struct PulsoidMessage {
+    data: PulsoidData,
+}
+
+#[derive(Debug, Deserialize)]
+struct PulsoidData {
+    heart_rate: u16,
+}
+
+struct Pulsoid {
+    token: String,
+}
+
+impl RemoteProvider for Pulsoid {
+    fn name(&self) -> &'static str {
+        "Pulsoid"
+    }
+    fn subsystem(&self) -> Subsystem {
+        Subsystem::Pulsoid
+    }
+    fn uri(&self) -> String {
+        format!("{REAL_TIME_URL}?access_token={}", self.token)
+    }
+    fn validate(&self) -> Result<(), AppError> {
+        if self.token.is_empty() {
+            return Err(AppError::PulsoidTokenMissing);
+        }
+        Ok(())
+    }
+    fn unauthorized(&self) -> AppError {
+        AppError::PulsoidUnauthorized
+    }
+    fn parse(&self, text: &str) -> Option<u16> {
+        serde_json::from_str::<PulsoidMessage>(text)
+            .ok()
+            .map(|message| message.data.heart_rate)
+    }
+}
+
+pub async fn pulsoid_thread(
+    broadcast_tx: BSender<AppUpdate>,
+    pulsoid_settings: PulsoidSettings,
+    rr_twitch_threshold: TwitchThreshold,
+    cancel_token: CancellationToken,
+) {
+    let pulsoid = Pulsoid {
+        token: pulsoid_settings.token,
+    };
+    remote_thread(broadcast_tx, pulsoid, rr_twitch_threshold, cancel_token).await;
+}
+
+pub struct PulsoidSource;
+
+impl HeartRateSource for PulsoidSource {
+    fn source(&self) -> HrSource {
+        HrSource::Pulsoid
+    }
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::BPM_ONLY
+    }
+    fn spawn(self: Box<Self>, context: SourceContext) -> JoinHandle<()> {
+        tokio::spawn(pulsoid_thread(
+            context.broadcast_tx,
+            context.settings.pulsoid,
+            context.rr_twitch_threshold,
+            context.cancel_token,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_real_time_messages() {
+        let pulsoid = Pulsoid {
+            token: String::new(),
+        };
+        assert_eq!(
+            pulsoid.parse(r#"{"measured_at":1625310655000,"data":{"heart_rate":40}}"#),
+            Some(40)
+        );
+        assert_eq!(pulsoid.parse(r#"{"data":{}}"#), None);
+        assert_eq!(pulsoid.parse("82"), None);
+        assert!(pulsoid.validate().is_err());
+    }
+}
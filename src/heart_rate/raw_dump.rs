@@ -0,0 +1,104 @@
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use crate::errors::AppError;
+
+/// Appends raw Heart Rate Measurement notifications to a file, one per line:
+///
+/// `<RFC 3339 timestamp> <bytes as space separated hex>`
+///
+/// Lines starting with `#` are comments. Dumps dropped into `tests/hrm_dumps`
+/// get replayed through `parse_hrm` by the tests below.
+pub struct RawDump {
+    file: File,
+}
+
+impl RawDump {
+    pub fn open(path: &Path, device_name: &str) -> Result<Self, AppError> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| AppError::CreateFile {
+                path: path.to_owned(),
+                source: e,
+            })?;
+        writeln!(
+            file,
+            "# {device_name}, dumped by v{}",
+            env!("CARGO_PKG_VERSION")
+        )?;
+        Ok(Self { file })
+    }
+    /// Written before parsing, so it's there even if the parser chokes on it.
+    pub fn record(&mut self, data: &[u8]) -> Result<(), AppError> {
+        let line = format_line(&chrono::Local::now().to_rfc3339(), data);
+        self.file.write_all(line.as_bytes())?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+fn format_line(timestamp: &str, data: &[u8]) -> String {
+    let mut line = timestamp.to_owned();
+    for byte in data {
+        let _ = write!(line, " {byte:02x}");
+    }
+    line.push('\n');
+    line
+}
+
+/// The notification bytes from a dump line, None for comments and blank lines.
+#[cfg(test)]
+fn parse_line(line: &str) -> Option<Vec<u8>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    line.split_whitespace()
+        .skip(1)
+        .map(|byte| u8::from_str_radix(byte, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heart_rate::measurement::parse_hrm;
+
+    #[test]
+    fn line_roundtrip() {
+        let line = format_line("2024-01-01T00:00:00+00:00", &[0x10, 0x48, 0x00, 0x04]);
+        assert_eq!(line, "2024-01-01T00:00:00+00:00 10 48 00 04\n");
+        assert_eq!(parse_line(&line), Some(vec![0x10, 0x48, 0x00, 0x04]));
+        assert_eq!(parse_line("# Polar H10"), None);
+    }
+    #[test]
+    fn replay_dumps() {
+        let dump_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/hrm_dumps");
+        let Ok(entries) = std::fs::read_dir(dump_dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "txt") {
+                continue;
+            }
+            let dump = std::fs::read_to_string(&path).unwrap();
+            for (number, line) in dump.lines().enumerate() {
+                let Some(data) = parse_line(line) else {
+                    continue;
+                };
+                let measurement = parse_hrm(&data);
+                assert!(
+                    measurement.bpm > 0,
+                    "{}:{} parsed as 0 BPM",
+                    path.display(),
+                    number + 1
+                );
+            }
+        }
+    }
+}
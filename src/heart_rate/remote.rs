@@ -0,0 +1,198 @@
+use super::twitcher::{TwitchThreshold, Twitcher};
+use super::{BatteryLevel, HeartRateStatus};
+use crate::app::{AppUpdate, ErrorPopup};
+use crate::broadcast;
+use crate::errors::AppError;
+use crate::journal::{self, Severity, Subsystem};
+use crate::logging::raw_capture;
+use crate::reconnect::{self, ConnectError, ReconnectingClient, WsClient};
+
+use futures_util::{SinkExt, StreamExt};
+use std::time::Duration;
+use tokio::sync::broadcast::Sender as BSender;
+use tokio_util::sync::CancellationToken;
+use tokio_websockets::{upgrade, ClientBuilder, Message};
+use tracing::{debug, error};
+
+/// An online service relaying heart rate over a WebSocket (Pulsoid, HypeRate, etc).
+///
+/// Connecting, reconnecting and feeding readings into the app is shared,
+/// providers only need to say where to connect and how to read their messages.
+pub trait RemoteProvider: Send {
+    fn name(&self) -> &'static str;
+    fn subsystem(&self) -> Subsystem;
+    /// Includes any tokens/keys, so it shouldn't be logged.
+    fn uri(&self) -> String;
+    /// Checked before connecting, i.e. for missing tokens.
+    fn validate(&self) -> Result<(), AppError>;
+    /// What to report if the service rejects our credentials.
+    fn unauthorized(&self) -> AppError;
+    /// Sent right after connecting, i.e. to join a channel.
+    fn on_connect(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+    /// For services that drop quiet clients.
+    fn keepalive_interval(&self) -> Option<Duration> {
+        None
+    }
+    fn keepalive_message(&mut self) -> Option<String> {
+        None
+    }
+    /// BPM from a text message, None if it wasn't a reading.
+    fn parse(&self, text: &str) -> Option<u16>;
+}
+
+// Services answer the upgrade with one of these if the credentials are bad,
+// no point retrying until the user fixes them
+fn is_auth_error(error: &tokio_websockets::Error) -> bool {
+    matches!(
+        error,
+        tokio_websockets::Error::Upgrade(upgrade::Error::DidNotSwitchProtocols(401 | 403))
+    )
+}
+
+struct RemoteActor<P: RemoteProvider> {
+    provider: P,
+    hr_status: HeartRateStatus,
+    // Remote services only give BPM, so just for the twitch params
+    twitcher: Twitcher,
+}
+
+impl<P: RemoteProvider> RemoteActor<P> {
+    fn new(provider: P, rr_twitch_threshold: TwitchThreshold) -> Self {
+        Self {
+            provider,
+            hr_status: HeartRateStatus {
+                battery_level: BatteryLevel::NotReported,
+                ..Default::default()
+            },
+            twitcher: Twitcher::new(rr_twitch_threshold),
+        }
+    }
+    // Only returns once the connection's done for, with why
+    async fn receive_loop(
+        &mut self,
+        client: &mut WsClient,
+        broadcast_tx: &BSender<AppUpdate>,
+    ) -> String {
+        let name = self.provider.name();
+        for message in self.provider.on_connect() {
+            if let Err(e) = client.send(Message::text(message)).await {
+                return format!("Error sending to {name}: {e}");
+            }
+        }
+        // Never ticks for providers that don't need it
+        let mut keepalive = self
+            .provider
+            .keepalive_interval()
+            .map(tokio::time::interval);
+        loop {
+            let item = tokio::select! {
+                item = client.next() => item,
+                _ = async { keepalive.as_mut().unwrap().tick().await }, if keepalive.is_some() => {
+                    if let Some(message) = self.provider.keepalive_message() {
+                        if let Err(e) = client.send(Message::text(message)).await {
+                            return format!("Error sending to {name}: {e}");
+                        }
+                    }
+                    continue;
+                }
+            };
+            let message = match item {
+                Some(Ok(message)) => message,
+                Some(Err(e)) => return format!("Error receiving from {name}: {e}"),
+                None => return format!("{name} closed the connection"),
+            };
+            if message.is_close() {
+                return format!("{name} closed the connection");
+            }
+            let Some(text) = message.as_text() else {
+                continue;
+            };
+            raw_capture::record_websocket(text);
+            match self.provider.parse(text) {
+                Some(bpm) => {
+                    self.apply_bpm(bpm);
+                    broadcast!(broadcast_tx, self.hr_status.clone());
+                }
+                None => debug!("Ignoring {name} message: {text}"),
+            }
+        }
+    }
+    fn apply_bpm(&mut self, bpm: u16) {
+        self.hr_status.heart_rate_bpm = bpm;
+        let (twitch_up, twitch_down) = self.twitcher.handle(bpm, &self.hr_status.rr_intervals);
+        self.hr_status.twitch_up = twitch_up;
+        self.hr_status.twitch_down = twitch_down;
+        self.hr_status.timestamp = chrono::Local::now();
+    }
+}
+
+impl<P: RemoteProvider> ReconnectingClient for RemoteActor<P> {
+    type Connection = WsClient;
+    type Updates = ();
+    fn name(&self) -> &'static str {
+        self.provider.name()
+    }
+    fn subsystem(&self) -> Subsystem {
+        self.provider.subsystem()
+    }
+    fn target(&self) -> String {
+        // The URI has the tokens in it
+        self.provider.name().to_owned()
+    }
+    async fn connect(&mut self) -> Result<WsClient, ConnectError> {
+        let client_builder = ClientBuilder::new()
+            .uri(&self.provider.uri())
+            .map_err(AppError::from)?;
+        match client_builder.connect().await {
+            Ok((client, _)) => Ok(client),
+            Err(e) if is_auth_error(&e) => Err(self.provider.unauthorized().into()),
+            Err(e) => Err(ConnectError::Retry(e.to_string())),
+        }
+    }
+    async fn session(
+        &mut self,
+        mut client: WsClient,
+        _updates: &mut (),
+        broadcast_tx: &BSender<AppUpdate>,
+        cancel_token: &CancellationToken,
+    ) -> Result<Option<String>, AppError> {
+        tokio::select! {
+            reason = self.receive_loop(&mut client, broadcast_tx) => Ok(Some(reason)),
+            _ = cancel_token.cancelled() => {
+                client.close().await?;
+                Ok(None)
+            }
+        }
+    }
+    fn on_lost(&mut self, broadcast_tx: &BSender<AppUpdate>) {
+        // Don't leave the last reading hanging while we're not getting any
+        self.apply_bpm(0);
+        broadcast!(broadcast_tx, self.hr_status.clone());
+    }
+}
+
+/// Runs until cancelled, or the provider couldn't be set up/rejected us.
+pub async fn remote_thread<P: RemoteProvider>(
+    broadcast_tx: BSender<AppUpdate>,
+    provider: P,
+    rr_twitch_threshold: TwitchThreshold,
+    cancel_token: CancellationToken,
+) {
+    let name = provider.name();
+    let subsystem = provider.subsystem();
+    let mut actor = RemoteActor::new(provider, rr_twitch_threshold);
+    let result = async {
+        actor.provider.validate()?;
+        reconnect::run(&mut actor, &mut (), &broadcast_tx, &cancel_token).await
+    };
+    if let Err(e) = result.await {
+        error!("{name} error: {e}");
+        journal::record(Severity::Error, subsystem, &e.to_string());
+        broadcast!(
+            broadcast_tx,
+            ErrorPopup::detailed(&format!("{name} error"), e)
+        );
+    }
+}
@@ -0,0 +1,196 @@
+// Heart rate from a serial device (i.e. an ESP32 with a MAX30102), one reading per line
+// Opening the port needs the `serial` feature
+
+use tokio::sync::broadcast::Sender as BSender;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use super::twitcher::TwitchThreshold;
+use crate::app::{AppUpdate, AppView};
+use crate::settings::SerialSettings;
+use crate::sources::{Capabilities, HeartRateSource, HrSource, SourceContext};
+
+#[cfg(feature = "serial")]
+pub async fn serial_thread(
+    broadcast_tx: BSender<AppUpdate>,
+    serial_settings: SerialSettings,
+    twitch_threshold: TwitchThreshold,
+    stress_index_window: usize,
+    cancel_token: CancellationToken,
+) {
+    use crate::app::ErrorPopup;
+    use crate::broadcast;
+    use tracing::error;
+
+    let result = tokio::task::spawn_blocking({
+        let broadcast_tx = broadcast_tx.clone();
+        move || {
+            connection_loop(
+                &broadcast_tx,
+                &serial_settings,
+                twitch_threshold,
+                stress_index_window,
+                &cancel_token,
+            )
+        }
+    })
+    .await;
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => {
+            error!("Serial Error: {e}");
+            broadcast!(broadcast_tx, ErrorPopup::detailed("Serial Error", e));
+        }
+        Err(e) => error!("Serial thread panicked: {e}"),
+    }
+}
+
+// serialport is blocking, so this runs on its own thread and checks for cancellation between reads
+#[cfg(feature = "serial")]
+fn connection_loop(
+    broadcast_tx: &BSender<AppUpdate>,
+    serial_settings: &SerialSettings,
+    twitch_threshold: TwitchThreshold,
+    stress_index_window: usize,
+    cancel_token: &CancellationToken,
+) -> Result<(), crate::errors::AppError> {
+    use std::io::{BufRead, BufReader, ErrorKind};
+    use std::time::Duration;
+
+    use super::simple_reading::{parse_json_or_bpm, SimpleReadings};
+    use super::HeartRateStatus;
+    use crate::app::ErrorPopup;
+    use crate::broadcast;
+    use crate::errors::AppError;
+    use crate::journal::{self, Severity, Subsystem};
+    use tracing::{debug, info, warn};
+
+    const READ_TIMEOUT: Duration = Duration::from_millis(500);
+    // Boards (re)enumerating after being plugged in take a moment
+    const REOPEN_DELAY: Duration = Duration::from_secs(2);
+
+    if serial_settings.port.is_empty() {
+        return Err(AppError::SerialPortMissing);
+    }
+    let mut readings = SimpleReadings::new(twitch_threshold, stress_index_window);
+    // Only nag about a missing port once per disconnect
+    let mut reported_missing = false;
+    while !cancel_token.is_cancelled() {
+        let port = serialport::new(&serial_settings.port, serial_settings.baud)
+            .timeout(READ_TIMEOUT)
+            .open();
+        let port = match port {
+            Ok(port) => port,
+            Err(e) => {
+                if !std::mem::replace(&mut reported_missing, true) {
+                    warn!("Couldn't open {}: {e}", serial_settings.port);
+                    broadcast!(
+                        broadcast_tx,
+                        ErrorPopup::Intermittent(format!(
+                            "Couldn't open {}, retrying: {e}",
+                            serial_settings.port
+                        ))
+                    );
+                }
+                std::thread::sleep(REOPEN_DELAY);
+                continue;
+            }
+        };
+        reported_missing = false;
+        let message = format!(
+            "Opened {} at {} baud",
+            serial_settings.port, serial_settings.baud
+        );
+        info!("{message}");
+        journal::record(Severity::Info, Subsystem::Serial, &message);
+
+        let mut reader = BufReader::new(port);
+        let mut line = String::new();
+        while !cancel_token.is_cancelled() {
+            // Partial lines stay in `line` through timeouts
+            match reader.read_line(&mut line) {
+                Ok(0) => break,
+                Ok(_) => {
+                    match parse_json_or_bpm(&line) {
+                        Some(reading) => {
+                            broadcast!(broadcast_tx, readings.apply(reading));
+                        }
+                        None => debug!("Ignoring serial line: {}", line.trim_end()),
+                    }
+                    line.clear();
+                }
+                Err(e) if e.kind() == ErrorKind::TimedOut => {}
+                // Debug prints and such from the board
+                Err(e) if e.kind() == ErrorKind::InvalidData => line.clear(),
+                Err(e) => {
+                    warn!("Lost serial port {}: {e}", serial_settings.port);
+                    break;
+                }
+            }
+        }
+        if cancel_token.is_cancelled() {
+            break;
+        }
+        journal::record(
+            Severity::Warning,
+            Subsystem::Serial,
+            &format!("Lost {}", serial_settings.port),
+        );
+        broadcast!(
+            broadcast_tx,
+            HeartRateStatus::default(),
+            "Failed to send 0BPM after losing serial port"
+        );
+        broadcast!(
+            broadcast_tx,
+            ErrorPopup::Intermittent(format!(
+                "Lost {}, waiting for it to come back",
+                serial_settings.port
+            ))
+        );
+        // Already told them it's gone
+        reported_missing = true;
+    }
+    info!("Shutting down serial thread!");
+    Ok(())
+}
+
+#[cfg(not(feature = "serial"))]
+pub async fn serial_thread(
+    broadcast_tx: BSender<AppUpdate>,
+    _serial_settings: SerialSettings,
+    _twitch_threshold: TwitchThreshold,
+    _stress_index_window: usize,
+    _cancel_token: CancellationToken,
+) {
+    use crate::app::ErrorPopup;
+    use crate::broadcast;
+    use crate::journal::{self, Severity, Subsystem};
+
+    let message = "This build doesn't include serial support, it needs to be built with the \"serial\" feature.";
+    journal::record(Severity::Error, Subsystem::Serial, message);
+    broadcast!(broadcast_tx, ErrorPopup::UserMustDismiss(message.into()));
+}
+
+pub struct SerialSource;
+
+impl HeartRateSource for SerialSource {
+    fn source(&self) -> HrSource {
+        HrSource::Serial
+    }
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::FULL
+    }
+    fn waiting_view(&self) -> AppView {
+        AppView::WaitingForSerial
+    }
+    fn spawn(self: Box<Self>, context: SourceContext) -> JoinHandle<()> {
+        tokio::spawn(serial_thread(
+            context.broadcast_tx,
+            context.settings.serial,
+            context.rr_twitch_threshold,
+            context.stress_index_window,
+            context.cancel_token,
+        ))
+    }
+}
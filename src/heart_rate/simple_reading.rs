@@ -0,0 +1,180 @@
+use std::time::Duration;
+
+use serde_json::Value;
+
+use super::stress::StressIndex;
+use super::twitcher::{TwitchThreshold, Twitcher};
+use super::{BatteryLevel, HeartRateStatus};
+use crate::settings::JsonMapping;
+
+/// A reading from a source that only sends the basics (websocket/HTTP/serial/UDP clients).
+#[derive(Debug, PartialEq, Eq)]
+pub(super) struct SimpleReading {
+    pub bpm: u16,
+    pub latest_rr_ms: Option<u64>,
+    pub battery: Option<u8>,
+}
+
+// Where readings are if the mapping doesn't say otherwise
+const DEFAULT_BPM_POINTERS: [&str; 3] = ["/bpm", "/heartrate", "/heartRate"];
+const DEFAULT_RR_POINTER: &str = "/latest_rr_ms";
+const DEFAULT_BATTERY_POINTER: &str = "/battery";
+
+// Apps disagree on whether numbers should be strings, or have decimals
+fn number_at(json: &Value, pointer: &str) -> Option<u64> {
+    match json.pointer(pointer)? {
+        Value::Number(number) => number.as_u64().or_else(|| {
+            number
+                .as_f64()
+                .filter(|n| *n >= 0.0)
+                .map(|n| n.round() as u64)
+        }),
+        Value::String(text) => text.trim().parse::<f64>().ok().and_then(|n| {
+            let n = n.round();
+            (n >= 0.0).then_some(n as u64)
+        }),
+        _ => None,
+    }
+}
+
+fn pointer_or<'a>(pointer: &'a str, default: &'a str) -> &'a str {
+    if pointer.is_empty() {
+        default
+    } else {
+        pointer
+    }
+}
+
+/// `{"bpm": 123, "latest_rr_ms": 456, "battery": 78}`, as sent to the websocket server.
+pub(super) fn parse_json(text: &str) -> Option<SimpleReading> {
+    parse_mapped_json(text, &JsonMapping::default())
+}
+
+/// JSON with the readings wherever the mapping points, anything it leaves empty is looked for
+/// where [`parse_json`] would.
+pub(super) fn parse_mapped_json(text: &str, mapping: &JsonMapping) -> Option<SimpleReading> {
+    parse_mapped_value(&serde_json::from_str::<Value>(text).ok()?, mapping)
+}
+
+/// Same as [`parse_mapped_json`], for JSON-like data that's already been decoded.
+pub(super) fn parse_mapped_value(json: &Value, mapping: &JsonMapping) -> Option<SimpleReading> {
+    let bpm = if mapping.bpm.is_empty() {
+        DEFAULT_BPM_POINTERS
+            .iter()
+            .find_map(|pointer| number_at(json, pointer))
+    } else {
+        number_at(json, &mapping.bpm)
+    };
+    let rr_pointer = pointer_or(&mapping.rr, DEFAULT_RR_POINTER);
+    let battery_pointer = pointer_or(&mapping.battery, DEFAULT_BATTERY_POINTER);
+    Some(SimpleReading {
+        bpm: bpm?.try_into().ok()?,
+        latest_rr_ms: number_at(json, rr_pointer),
+        battery: number_at(json, battery_pointer).and_then(|battery| battery.try_into().ok()),
+    })
+}
+
+/// Either the JSON above or just the BPM (`72`), for line/message based sources.
+pub(super) fn parse_json_or_bpm(text: &str) -> Option<SimpleReading> {
+    let text = text.trim();
+    if text.starts_with('{') {
+        return parse_json(text);
+    }
+    text.parse().ok().map(|bpm| SimpleReading {
+        bpm,
+        latest_rr_ms: None,
+        battery: None,
+    })
+}
+
+/// Turns simple readings into full statuses, keeping RR/battery around between them.
+pub(super) struct SimpleReadings {
+    hr_status: HeartRateStatus,
+    twitcher: Twitcher,
+    stress_index: StressIndex,
+}
+
+impl SimpleReadings {
+    pub fn new(rr_twitch_threshold: TwitchThreshold, stress_index_window: usize) -> Self {
+        Self {
+            hr_status: HeartRateStatus {
+                battery_level: BatteryLevel::NotReported,
+                ..Default::default()
+            },
+            twitcher: Twitcher::new(rr_twitch_threshold),
+            stress_index: StressIndex::new(stress_index_window),
+        }
+    }
+    pub fn apply(&mut self, reading: SimpleReading) -> HeartRateStatus {
+        let now = chrono::Local::now();
+        self.hr_status.heart_rate_bpm = reading.bpm;
+        if let Some(battery) = reading.battery {
+            self.hr_status.battery_level = BatteryLevel::Level(battery);
+        }
+        if let Some(rr) = reading.latest_rr_ms {
+            while !self.hr_status.rr_intervals.is_empty() {
+                self.hr_status.rr_intervals.pop();
+            }
+            self.hr_status.rr_intervals.push(Duration::from_millis(rr));
+        }
+
+        let (twitch_up, twitch_down) = self
+            .twitcher
+            .handle(reading.bpm, &self.hr_status.rr_intervals);
+        self.hr_status.twitch_up = twitch_up;
+        self.hr_status.twitch_down = twitch_down;
+        // Only new intervals, since they're kept between messages
+        self.hr_status.stress_index = match reading.latest_rr_ms {
+            Some(_) => self.stress_index.handle(&self.hr_status.rr_intervals),
+            None => self.hr_status.stress_index,
+        };
+        self.hr_status.timestamp = now;
+
+        self.hr_status.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_and_plain_lines() {
+        assert_eq!(
+            parse_json_or_bpm("{\"bpm\": 72, \"latest_rr_ms\": 833}\r\n"),
+            Some(SimpleReading {
+                bpm: 72,
+                latest_rr_ms: Some(833),
+                battery: None,
+            })
+        );
+        assert_eq!(
+            parse_json_or_bpm("81\n").map(|reading| reading.bpm),
+            Some(81)
+        );
+        assert_eq!(parse_json_or_bpm("MAX30102 ready"), None);
+        assert_eq!(parse_json_or_bpm(""), None);
+    }
+    #[test]
+    fn mapped_json() {
+        let mapping = JsonMapping {
+            bpm: "/data/heartRate".to_string(),
+            rr: "/data/rr/0".to_string(),
+            battery: String::new(),
+        };
+        let text = r#"{"data": {"heartRate": "71.6", "rr": [845, 850]}, "battery": 55}"#;
+        assert_eq!(
+            parse_mapped_json(text, &mapping),
+            Some(SimpleReading {
+                bpm: 72,
+                latest_rr_ms: Some(845),
+                battery: Some(55),
+            })
+        );
+        assert_eq!(parse_mapped_json(r#"{"bpm": 70}"#, &mapping), None);
+        // Built-in names still work without a mapping
+        assert_eq!(parse_json(r#"{"heartRate": 64}"#).map(|r| r.bpm), Some(64));
+        assert_eq!(parse_json(r#"{"bpm": -5}"#), None);
+        assert_eq!(parse_json(r#"{"bpm": 70000}"#), None);
+    }
+}
@@ -0,0 +1,149 @@
+// Heart rate piped in from another program (`my_script | null_iron_heart --stdin`), one reading per line
+
+use super::simple_reading::{parse_json_or_bpm, SimpleReadings};
+use super::twitcher::TwitchThreshold;
+use super::HeartRateStatus;
+use crate::app::{AppUpdate, ErrorPopup};
+use crate::broadcast;
+use crate::journal::{self, Severity, Subsystem};
+use crate::logging::raw_capture;
+use crate::sources::{Capabilities, HeartRateSource, HrSource, SourceContext};
+
+use std::io::BufRead;
+use tokio::sync::broadcast::Sender as BSender;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info};
+
+// tokio's stdin can't be cancelled and would hold up shutdown until the next line,
+// so lines are read on a plain thread that's left behind when the app exits
+fn spawn_line_reader() -> mpsc::UnboundedReceiver<std::io::Result<String>> {
+    let (line_tx, line_rx) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            if line_tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    line_rx
+}
+
+async fn receive_loop(
+    broadcast_tx: &BSender<AppUpdate>,
+    mut line_rx: mpsc::UnboundedReceiver<std::io::Result<String>>,
+    mut readings: SimpleReadings,
+    cancel_token: CancellationToken,
+) {
+    loop {
+        let line = tokio::select! {
+            line = line_rx.recv() => line,
+            _ = cancel_token.cancelled() => {
+                info!("Shutting down stdin thread!");
+                return;
+            }
+        };
+        match line {
+            Some(Ok(line)) => {
+                raw_capture::record_websocket(&line);
+                match parse_json_or_bpm(&line) {
+                    Some(reading) => {
+                        broadcast!(broadcast_tx, readings.apply(reading));
+                    }
+                    None => debug!("Ignoring stdin line: {line}"),
+                }
+            }
+            Some(Err(e)) => {
+                error!("Failed to read stdin: {e}");
+                broadcast!(
+                    broadcast_tx,
+                    ErrorPopup::detailed("Failed to read stdin", e.into())
+                );
+                return;
+            }
+            None => {
+                info!("stdin closed");
+                journal::record(Severity::Warning, Subsystem::Stdin, "stdin closed");
+                broadcast!(
+                    broadcast_tx,
+                    HeartRateStatus::default(),
+                    "Failed to send 0BPM after stdin closed"
+                );
+                broadcast!(
+                    broadcast_tx,
+                    ErrorPopup::UserMustDismiss(
+                        "stdin closed, no more heart rate will come from it.".into()
+                    )
+                );
+                return;
+            }
+        }
+    }
+}
+
+pub async fn stdin_thread(
+    broadcast_tx: BSender<AppUpdate>,
+    rr_twitch_threshold: TwitchThreshold,
+    stress_index_window: usize,
+    cancel_token: CancellationToken,
+) {
+    journal::record(
+        Severity::Info,
+        Subsystem::Stdin,
+        "Reading heart rate from stdin",
+    );
+    let readings = SimpleReadings::new(rr_twitch_threshold, stress_index_window);
+    receive_loop(&broadcast_tx, spawn_line_reader(), readings, cancel_token).await;
+}
+
+pub struct StdinSource;
+
+impl HeartRateSource for StdinSource {
+    fn source(&self) -> HrSource {
+        HrSource::Stdin
+    }
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::FULL
+    }
+    fn spawn(self: Box<Self>, context: SourceContext) -> JoinHandle<()> {
+        tokio::spawn(stdin_thread(
+            context.broadcast_tx,
+            context.rr_twitch_threshold,
+            context.stress_index_window,
+            context.cancel_token,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::broadcast;
+
+    #[tokio::test]
+    async fn reads_until_closed() {
+        let (line_tx, line_rx) = mpsc::unbounded_channel();
+        let (broadcast_tx, mut broadcast_rx) = broadcast::channel(8);
+        for line in ["72", "not a heart rate", r#"{"bpm": 80, "battery": 40}"#] {
+            line_tx.send(Ok(line.to_string())).unwrap();
+        }
+        drop(line_tx);
+        receive_loop(
+            &broadcast_tx,
+            line_rx,
+            SimpleReadings::new(TwitchThreshold::new(0.0), 30),
+            CancellationToken::new(),
+        )
+        .await;
+
+        let mut bpms = Vec::new();
+        while let Ok(update) = broadcast_rx.try_recv() {
+            if let AppUpdate::HeartRateStatus(status) = update {
+                bpms.push(status.heart_rate_bpm);
+            }
+        }
+        // The last one's the 0 BPM sent once it closed
+        assert_eq!(bpms, vec![72, 80, 0]);
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+// Baevsky's method uses 50ms wide bins for the RR histogram
+const BIN_WIDTH_SEC: f32 = 0.05;
+// Not much point in calculating it off of just a few beats
+const MIN_INTERVALS: usize = 30;
+
+/// Rolling calculation of Baevsky's Stress Index over the last `window` RR intervals.
+///
+/// SI = AMo / (2 * Mo * MxDMn)
+///
+/// - Mo: the most common RR interval (center of the modal 50ms bin), in seconds
+/// - AMo: percentage of intervals that fall in the modal bin
+/// - MxDMn: difference between the longest and shortest interval, in seconds
+///
+/// Resting values are usually somewhere around 50-150, and rise under (physical or mental) stress.
+pub struct StressIndex {
+    window: usize,
+    rr_intervals: VecDeque<Duration>,
+}
+
+impl StressIndex {
+    pub fn new(window: usize) -> Self {
+        let window = window.max(MIN_INTERVALS);
+        Self {
+            window,
+            rr_intervals: VecDeque::with_capacity(window),
+        }
+    }
+
+    /// Adds the new intervals to the window, returning the index if there's enough data for one.
+    pub fn handle(&mut self, rr_intervals: &[Duration]) -> Option<f32> {
+        for rr in rr_intervals.iter().filter(|rr| !rr.is_zero()) {
+            if self.rr_intervals.len() == self.window {
+                self.rr_intervals.pop_front();
+            }
+            self.rr_intervals.push_back(*rr);
+        }
+        self.calculate()
+    }
+
+    fn calculate(&self) -> Option<f32> {
+        if self.rr_intervals.len() < MIN_INTERVALS {
+            return None;
+        }
+        let intervals: Vec<f32> = self
+            .rr_intervals
+            .iter()
+            .map(Duration::as_secs_f32)
+            .collect();
+        let min = intervals.iter().cloned().fold(f32::MAX, f32::min);
+        let max = intervals.iter().cloned().fold(f32::MIN, f32::max);
+        let range = max - min;
+        // Perfectly steady intervals only really happen with synthetic data
+        if range <= f32::EPSILON {
+            return None;
+        }
+
+        let bin_count = (range / BIN_WIDTH_SEC) as usize + 1;
+        let mut bins = vec![0usize; bin_count];
+        for rr in &intervals {
+            let bin = (((rr - min) / BIN_WIDTH_SEC) as usize).min(bin_count - 1);
+            bins[bin] += 1;
+        }
+        let (modal_bin, modal_count) = bins
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, count)| **count)
+            .map(|(bin, count)| (bin, *count))?;
+
+        let mode = min + (modal_bin as f32 + 0.5) * BIN_WIDTH_SEC;
+        let amplitude_of_mode = modal_count as f32 / intervals.len() as f32 * 100.0;
+
+        Some(amplitude_of_mode / (2.0 * mode * range))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(rr: &[u64]) -> Vec<Duration> {
+        rr.iter().map(|ms| Duration::from_millis(*ms)).collect()
+    }
+
+    #[test]
+    fn needs_enough_data() {
+        let mut stress = StressIndex::new(60);
+        let rr = ms(&[800, 820]);
+        for _ in 0..(MIN_INTERVALS / 2) - 1 {
+            assert!(stress.handle(&rr).is_none());
+        }
+        assert!(stress.handle(&rr).is_some());
+    }
+    #[test]
+    fn steady_intervals_have_no_index() {
+        let mut stress = StressIndex::new(60);
+        let rr = ms(&[800; MIN_INTERVALS]);
+        assert!(stress.handle(&rr).is_none());
+    }
+    #[test]
+    fn known_value() {
+        let mut stress = StressIndex::new(40);
+        // 30 in the lower bin, 10 in the upper
+        let mut rr = ms(&[800; 30]);
+        rr.extend(ms(&[900; 10]));
+        let si = stress.handle(&rr).unwrap();
+        // Mo = 0.825, AMo = 75%, MxDMn = 0.1
+        let expected = 75.0 / (2.0 * 0.825 * 0.1);
+        assert!((si - expected).abs() < 0.1, "{si} != {expected}");
+    }
+    #[test]
+    fn less_variability_is_more_stress() {
+        let mut relaxed = StressIndex::new(60);
+        let mut stressed = StressIndex::new(60);
+        let relaxed_rr: Vec<Duration> = (0..60)
+            .map(|i| Duration::from_millis(800 + (i % 10) * 30))
+            .collect();
+        let stressed_rr: Vec<Duration> = (0..60)
+            .map(|i| Duration::from_millis(500 + (i % 3) * 10))
+            .collect();
+        let relaxed = relaxed.handle(&relaxed_rr).unwrap();
+        let stressed = stressed.handle(&stressed_rr).unwrap();
+        assert!(stressed > relaxed);
+    }
+    #[test]
+    fn window_rolls() {
+        let mut stress = StressIndex::new(MIN_INTERVALS);
+        let mut rr = ms(&[1000]);
+        rr.extend(ms(&[800; MIN_INTERVALS - 1]));
+        assert!(stress.handle(&rr).is_some());
+        // Pushes the outlier out of the window
+        assert!(stress.handle(&ms(&[800])).is_none());
+    }
+}
@@ -0,0 +1,126 @@
+use super::remote::{remote_thread, RemoteProvider};
+use super::twitcher::TwitchThreshold;
+use crate::app::AppUpdate;
+use crate::errors::AppError;
+use crate::journal::Subsystem;
+use crate::settings::StromnoSettings;
+use crate::sources::{Capabilities, HeartRateSource, HrSource, SourceContext};
+
+use serde::Deserialize;
+use tokio::sync::broadcast::Sender as BSender;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+// Same socket the widget page listens on
+const WIDGET_SOCKET_URL: &str = "wss://api.stromno.com/v1/api/public/ws";
+
+// {"data": {"heartRate": 82}}, older widgets send it at the top level
+#[derive(Debug, Deserialize)]
+struct StromnoMessage {
+    data: Option<StromnoData>,
+    #[serde(flatten)]
+    top_level: StromnoData,
+}
+
+#[derive(Debug, Deserialize)]
+struct StromnoData {
+    #[serde(rename = "heartRate", alias = "heart_rate")]
+    heart_rate: Option<u16>,
+}
+
+struct Stromno {
+    widget_id: String,
+}
+
+impl Stromno {
+    // People tend to paste the whole widget link, so take the ID out of it
+    fn new(widget_id: &str) -> Self {
+        let widget_id = widget_id.trim();
+        let widget_id = match widget_id.split_once("widget=") {
+            Some((_, query)) => query.split('&').next().unwrap_or_default(),
+            None => widget_id.rsplit('/').next().unwrap_or_default(),
+        };
+        Self {
+            widget_id: widget_id.to_owned(),
+        }
+    }
+}
+
+impl RemoteProvider for Stromno {
+    fn name(&self) -> &'static str {
+        "Stromno"
+    }
+    fn subsystem(&self) -> Subsystem {
+        Subsystem::Stromno
+    }
+    fn uri(&self) -> String {
+        format!("{WIDGET_SOCKET_URL}?widget={}", self.widget_id)
+    }
+    fn validate(&self) -> Result<(), AppError> {
+        if self.widget_id.is_empty() {
+            return Err(AppError::StromnoWidgetMissing);
+        }
+        Ok(())
+    }
+    fn unauthorized(&self) -> AppError {
+        AppError::StromnoUnauthorized
+    }
+    fn parse(&self, text: &str) -> Option<u16> {
+        let message = serde_json::from_str::<StromnoMessage>(text).ok()?;
+        message
+            .data
+            .and_then(|data| data.heart_rate)
+            .or(message.top_level.heart_rate)
+    }
+}
+
+pub async fn stromno_thread(
+    broadcast_tx: BSender<AppUpdate>,
+    stromno_settings: StromnoSettings,
+    rr_twitch_threshold: TwitchThreshold,
+    cancel_token: CancellationToken,
+) {
+    let stromno = Stromno::new(&stromno_settings.widget_id);
+    remote_thread(broadcast_tx, stromno, rr_twitch_threshold, cancel_token).await;
+}
+
+pub struct StromnoSource;
+
+impl HeartRateSource for StromnoSource {
+    fn source(&self) -> HrSource {
+        HrSource::Stromno
+    }
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::BPM_ONLY
+    }
+    fn spawn(self: Box<Self>, context: SourceContext) -> JoinHandle<()> {
+        tokio::spawn(stromno_thread(
+            context.broadcast_tx,
+            context.settings.stromno,
+            context.rr_twitch_threshold,
+            context.cancel_token,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn widget_ids_and_messages() {
+        for pasted in [
+            "abc123",
+            " abc123 ",
+            "https://stromno.com/widget/view?widget=abc123&theme=dark",
+            "https://stromno.com/widget/abc123",
+        ] {
+            assert_eq!(Stromno::new(pasted).widget_id, "abc123", "{pasted}");
+        }
+        let stromno = Stromno::new("abc123");
+        assert_eq!(stromno.parse(r#"{"data":{"heartRate":77}}"#), Some(77));
+        assert_eq!(stromno.parse(r#"{"heart_rate":64}"#), Some(64));
+        assert_eq!(stromno.parse(r#"{"type":"ping"}"#), None);
+        assert!(Stromno::new("").validate().is_err());
+    }
+}
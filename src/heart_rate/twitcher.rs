@@ -1,20 +1,48 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
 use std::time::Duration;
 
 use super::rr_from_bpm;
 
+/// The twitch threshold (in seconds), shared with every running source
+/// so a new one from the tuning screen takes effect without reconnecting.
+#[derive(Debug, Clone)]
+pub struct TwitchThreshold(Arc<AtomicU32>);
+
+impl TwitchThreshold {
+    pub fn new(threshold: f32) -> Self {
+        Self(Arc::new(AtomicU32::new(threshold.to_bits())))
+    }
+    pub fn from_ms(threshold_ms: u16) -> Self {
+        Self::new(ms_to_secs(threshold_ms))
+    }
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+    pub fn set_ms(&self, threshold_ms: u16) {
+        self.0
+            .store(ms_to_secs(threshold_ms).to_bits(), Ordering::Relaxed);
+    }
+}
+
+// Not leaving as Duration as it's being used to check an abs difference
+fn ms_to_secs(threshold_ms: u16) -> f32 {
+    Duration::from_millis(threshold_ms as u64).as_secs_f32()
+}
+
 /// A snippet whose intended use is to drive simple avatar effects (like left/right ears twitching)
 /// using the changes in the user's heart rate (or more specifically, the interval between beats).
 ///
 /// If an RR Duration (or BPM conversion if RR isn't available) changes more than the set threshold
 /// compared to the last compared one, then a flag is flipped depending on if RR raised or lowered.
 pub struct Twitcher {
-    twitch_threshold: f32,
+    twitch_threshold: TwitchThreshold,
     latest_rr: Duration,
     use_real_rr: bool,
 }
 
 impl Twitcher {
-    pub fn new(twitch_threshold: f32) -> Self {
+    pub fn new(twitch_threshold: TwitchThreshold) -> Self {
         Self {
             twitch_threshold,
             latest_rr: Duration::from_secs(1),
@@ -39,9 +67,10 @@ impl Twitcher {
             vec![rr_from_bpm(bpm)]
         };
 
+        let twitch_threshold = self.twitch_threshold.get();
         for new_rr in rr_intervals {
             // Duration.abs_diff() is nightly only for now, agh
-            if (new_rr.as_secs_f32() - self.latest_rr.as_secs_f32()).abs() > self.twitch_threshold {
+            if (new_rr.as_secs_f32() - self.latest_rr.as_secs_f32()).abs() > twitch_threshold {
                 twitch_up |= new_rr > self.latest_rr;
                 twitch_down |= new_rr < self.latest_rr;
             }
@@ -52,6 +81,34 @@ impl Twitcher {
     }
 }
 
+/// An HR update as the Twitcher saw it, kept around for `replay`.
+#[derive(Debug, Clone)]
+pub struct TwitchSample {
+    pub bpm: u16,
+    pub rr_intervals: Vec<Duration>,
+}
+
+impl TwitchSample {
+    /// What the Twitcher compared against last for this update.
+    pub fn latest_rr(&self) -> Duration {
+        self.rr_intervals
+            .last()
+            .copied()
+            .unwrap_or_else(|| rr_from_bpm(self.bpm))
+    }
+}
+
+/// Runs past updates through a fresh Twitcher with a different threshold.
+///
+/// Returns (twitch_up, twitch_down) for each sample.
+pub fn replay(twitch_threshold: f32, samples: &[TwitchSample]) -> Vec<(bool, bool)> {
+    let mut twitcher = Twitcher::new(TwitchThreshold::new(twitch_threshold));
+    samples
+        .iter()
+        .map(|sample| twitcher.handle(sample.bpm, &sample.rr_intervals))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -59,7 +116,7 @@ mod tests {
     #[test]
     fn ignores_bpm_when_rr_used() {
         let twitch_threshold = Duration::from_millis(50).as_secs_f32();
-        let mut twitcher = Twitcher::new(twitch_threshold);
+        let mut twitcher = Twitcher::new(TwitchThreshold::new(twitch_threshold));
 
         // Initial check, BPM only
         let mut bpm = 60;
@@ -91,9 +148,35 @@ mod tests {
         assert_eq!(output, (false, false));
     }
     #[test]
+    fn replay_with_thresholds() {
+        let samples: Vec<TwitchSample> = [60, 62, 70, 69, 60]
+            .into_iter()
+            .map(|bpm| TwitchSample {
+                bpm,
+                rr_intervals: vec![rr_from_bpm(bpm)],
+            })
+            .collect();
+        let low = replay(Duration::from_millis(10).as_secs_f32(), &samples);
+        let high = replay(Duration::from_millis(100).as_secs_f32(), &samples);
+        let count = |results: &[(bool, bool)]| results.iter().filter(|(u, d)| *u || *d).count();
+        assert_eq!(count(&low), 4);
+        assert_eq!(count(&high), 2);
+        // 70 -> 60 BPM is a longer interval
+        assert_eq!(high[4], (true, false));
+    }
+    #[test]
+    fn threshold_change_applies_to_running_twitcher() {
+        let threshold = TwitchThreshold::from_ms(100);
+        let mut twitcher = Twitcher::new(threshold.clone());
+        twitcher.handle(60, &[]);
+        assert_eq!(twitcher.handle(65, &[]), (false, false));
+        threshold.set_ms(10);
+        assert_eq!(twitcher.handle(60, &[]), (true, false));
+    }
+    #[test]
     fn bpm_only() {
         let twitch_threshold = Duration::from_millis(50).as_secs_f32();
-        let mut twitcher = Twitcher::new(twitch_threshold);
+        let mut twitcher = Twitcher::new(TwitchThreshold::new(twitch_threshold));
 
         let mut bpm = 60;
         let rr_intervals = Vec::new();
@@ -124,7 +207,7 @@ mod tests {
     #[test]
     fn multiple_rr_intervals() {
         let twitch_threshold = Duration::from_millis(50).as_secs_f32();
-        let mut twitcher = Twitcher::new(twitch_threshold);
+        let mut twitcher = Twitcher::new(TwitchThreshold::new(twitch_threshold));
 
         let bpm = 60;
         let mut rr_intervals = vec![rr_from_bpm(60)];
@@ -0,0 +1,170 @@
+use super::simple_reading::{self, SimpleReadings};
+use super::twitcher::TwitchThreshold;
+use crate::app::{AppUpdate, AppView, ErrorPopup};
+use crate::broadcast;
+use crate::errors::AppError;
+use crate::journal::{self, Severity, Subsystem};
+use crate::logging::raw_capture;
+use crate::settings::UdpSettings;
+use crate::sources::{Capabilities, HeartRateSource, HrSource, SourceContext};
+
+use std::net::{SocketAddr, SocketAddrV4};
+use tokio::net::UdpSocket;
+use tokio::sync::broadcast::Sender as BSender;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info};
+
+// Readings are tiny, anything bigger isn't for us
+const MAX_DATAGRAM: usize = 1024;
+
+struct UdpActor {
+    socket: UdpSocket,
+    readings: SimpleReadings,
+    // Only journaled when it changes, rather than every datagram
+    last_peer: Option<SocketAddr>,
+}
+
+impl UdpActor {
+    async fn build(
+        udp_settings: UdpSettings,
+        rr_twitch_threshold: TwitchThreshold,
+        stress_index_window: usize,
+    ) -> Result<(Self, SocketAddr), AppError> {
+        let host_addr = SocketAddrV4::new([0, 0, 0, 0].into(), udp_settings.port);
+        let socket = UdpSocket::bind(host_addr).await?;
+        let local_addr = socket.local_addr()?;
+        Ok((
+            Self {
+                socket,
+                readings: SimpleReadings::new(rr_twitch_threshold, stress_index_window),
+                last_peer: None,
+            },
+            local_addr,
+        ))
+    }
+    async fn receive_loop(
+        &mut self,
+        broadcast_tx: &BSender<AppUpdate>,
+        cancel_token: CancellationToken,
+    ) -> Result<(), AppError> {
+        let mut buf = [0; MAX_DATAGRAM];
+        loop {
+            let (len, peer) = tokio::select! {
+                result = self.socket.recv_from(&mut buf) => result?,
+                _ = cancel_token.cancelled() => {
+                    info!("Shutting down UDP thread!");
+                    return Ok(());
+                }
+            };
+            if self.last_peer.replace(peer) != Some(peer) {
+                journal::record(
+                    Severity::Info,
+                    Subsystem::Udp,
+                    &format!("Receiving from {peer}"),
+                );
+            }
+            let Ok(text) = std::str::from_utf8(&buf[..len]) else {
+                debug!("Ignoring non-UTF8 datagram from {peer}");
+                continue;
+            };
+            raw_capture::record_websocket(text);
+            match simple_reading::parse_json(text.trim()) {
+                Some(reading) => {
+                    broadcast!(broadcast_tx, self.readings.apply(reading));
+                }
+                None => {
+                    error!("Invalid heart rate datagram: {text}");
+                    broadcast!(
+                        broadcast_tx,
+                        ErrorPopup::Intermittent(format!("Invalid heart rate datagram: {text}"))
+                    );
+                }
+            }
+        }
+    }
+}
+
+pub async fn udp_thread(
+    broadcast_tx: BSender<AppUpdate>,
+    udp_settings: UdpSettings,
+    rr_twitch_threshold: TwitchThreshold,
+    stress_index_window: usize,
+    cancel_token: CancellationToken,
+) {
+    let (mut udp, local_addr) =
+        match UdpActor::build(udp_settings, rr_twitch_threshold, stress_index_window).await {
+            Ok((udp, addr)) => (udp, addr),
+            Err(e) => {
+                let message = "Failed to bind UDP socket.";
+                broadcast!(broadcast_tx, ErrorPopup::detailed(message, e));
+                return;
+            }
+        };
+    info!("Listening for UDP heart rate on {local_addr}");
+
+    if let Err(e) = udp.receive_loop(&broadcast_tx, cancel_token).await {
+        error!("UDP listener error: {e}");
+        broadcast!(broadcast_tx, ErrorPopup::detailed("UDP listener error", e));
+    }
+}
+
+pub struct UdpSource;
+
+impl HeartRateSource for UdpSource {
+    fn source(&self) -> HrSource {
+        HrSource::Udp
+    }
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::FULL
+    }
+    fn waiting_view(&self) -> AppView {
+        AppView::WaitingForUdp
+    }
+    fn spawn(self: Box<Self>, context: SourceContext) -> JoinHandle<()> {
+        tokio::spawn(udp_thread(
+            context.broadcast_tx,
+            context.settings.udp,
+            context.rr_twitch_threshold,
+            context.stress_index_window,
+            context.cancel_token,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::broadcast;
+
+    #[tokio::test]
+    async fn receives_json_datagrams() {
+        let settings = UdpSettings {
+            enabled: true,
+            port: 0,
+        };
+        let (mut udp, local_addr) = UdpActor::build(settings, TwitchThreshold::new(0.0), 30)
+            .await
+            .unwrap();
+        let (broadcast_tx, mut broadcast_rx) = broadcast::channel(4);
+        let cancel_token = CancellationToken::new();
+        let listener = tokio::spawn({
+            let cancel_token = cancel_token.clone();
+            async move { udp.receive_loop(&broadcast_tx, cancel_token).await }
+        });
+
+        let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let target = SocketAddr::from(([127, 0, 0, 1], local_addr.port()));
+        sender
+            .send_to(br#"{"bpm": 91, "battery": 50}"#, target)
+            .await
+            .unwrap();
+        match broadcast_rx.recv().await.unwrap() {
+            AppUpdate::HeartRateStatus(status) => assert_eq!(status.heart_rate_bpm, 91),
+            other => panic!("Unexpected update: {other:?}"),
+        }
+
+        cancel_token.cancel();
+        listener.await.unwrap().unwrap();
+    }
+}
@@ -1,64 +1,164 @@
-use super::twitcher::Twitcher;
-use super::{BatteryLevel, HeartRateStatus};
-use crate::app::{AppUpdate, ErrorPopup};
+use super::http_ingest::{
+    authorized, get_path, is_websocket_upgrade, parse_simple_ingest, read_request_head, reject,
+    respond, write_response, IngestRejection, HEALTH_PATH, HR_PATH, SIMPLE_INGEST_PATH,
+};
+use super::input_guard::{InputGuard, SUMMARY_INTERVAL};
+use super::obs_mimic;
+use super::simple_reading::{self, SimpleReading, SimpleReadings};
+use super::twitcher::TwitchThreshold;
+use super::wire_format::WireFormat;
+use super::HeartRateStatus;
+use crate::app::{AppUpdate, AppView, ErrorPopup};
 use crate::broadcast;
 use crate::errors::AppError;
-use crate::settings::WebSocketSettings;
+use crate::journal::{self, Severity, Subsystem};
+use crate::logging::overlay::OverlayPayload;
+use crate::logging::raw_capture;
+use crate::reconnect::{self, ConnectError, ReconnectingClient, WsClient};
+use crate::settings::{JsonMapping, WebSocketSettings};
+use crate::sources::{Capabilities, HeartRateSource, HrSource, SourceContext};
 
-use serde::Deserialize;
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
 use std::net::{SocketAddr, SocketAddrV4};
+use std::pin::Pin;
 use std::str::FromStr;
-use std::time::Duration;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio::sync::broadcast::Sender as BSender;
+use tokio::task::JoinHandle;
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
 
 use futures_util::{SinkExt, StreamExt};
-use tokio::net::TcpListener;
-use tokio_websockets::{Message, ServerBuilder};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+use tokio_native_tls::{native_tls, TlsAcceptor};
+use tokio_websockets::{CloseCode, Message, ServerBuilder, WebSocketStream};
 
-#[derive(Debug, Deserialize)]
-struct JSONHeartRate {
-    #[serde(alias = "heartrate", alias = "heartRate")]
-    bpm: u16,
-    // Options since no guarantee they'll exist
-    latest_rr_ms: Option<u64>,
-    battery: Option<u8>,
+const MDNS_SERVICE: &str = "_nih-hr._tcp.local.";
+// How long a client without a token in its URL has to send one
+const AUTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+// What each connection's task hands back to the server
+enum ConnectionEvent {
+    Opened,
+    Reading(SimpleReading),
+    // From /ingest/simple, not guarded yet since every request is its own connection
+    Ingested(SimpleReading),
+    // Already logged with the peer, the server only tallies these up for the journal
+    Problem(String),
+    Closed,
+}
+
+// The latest of everything, for plain HTTP requests to answer from
+#[derive(Debug, Clone, Default)]
+struct ServerSnapshot {
+    hr_status: HeartRateStatus,
+    clients: usize,
 }
 
-// TODO Add support for HeartRateOnStream, can use this as a reference: (thanks Curtis)
-// (Need to mimic an OBS instance, agh)
-// https://github.com/Curtis-VL/HeartRateOnStream-OSC/blob/main/Program.cs
+#[derive(Debug, Serialize)]
+struct HealthResponse {
+    status: &'static str,
+    clients: usize,
+    // Seconds since the last reading from any client, if there's been one
+    last_reading_secs: Option<i64>,
+}
+
+// Handed to every connection's task
+#[derive(Clone)]
+struct ConnectionSettings {
+    // Only Some if the plain HTTP endpoint is enabled *and* has a token set
+    simple_ingest_token: Option<String>,
+    // Only Some if set, websocket clients are let in without one otherwise
+    auth_token: Option<String>,
+    heart_rate_on_stream: bool,
+    json_mapping: JsonMapping,
+    // Each connection gets its own copy
+    input_guard: InputGuard,
+    // Only Some if a certificate and key are set, for wss://
+    tls_acceptor: Option<TlsAcceptor>,
+    snapshot_rx: watch::Receiver<ServerSnapshot>,
+}
+
+// Clients that can't put the token in the URL send {"auth": "<token>"} first instead
+#[derive(Deserialize)]
+struct AuthMessage {
+    auth: String,
+}
 
 struct WebsocketActor {
     listener: TcpListener,
-    hr_status: HeartRateStatus,
-    twitcher: Twitcher,
+    // Shared by every client, so whoever sent last is what the app shows
+    readings: SimpleReadings,
+    connection_settings: ConnectionSettings,
+    clients: usize,
+    snapshot_tx: watch::Sender<ServerSnapshot>,
+    // Shared by all simple ingest requests, websockets get their own
+    ingest_guard: InputGuard,
+    // Connection problems since the last summary, and the latest one
+    problems: u32,
+    latest_problem: Option<String>,
 }
 
 impl WebsocketActor {
     async fn build(
         websocket_settings: WebSocketSettings,
         port_override: Option<u16>,
-        rr_twitch_threshold: f32,
+        rr_twitch_threshold: TwitchThreshold,
+        stress_index_window: usize,
     ) -> Result<(Self, SocketAddr), AppError> {
         let port = port_override.unwrap_or(websocket_settings.port);
         let host_addr = SocketAddrV4::from_str(&format!("0.0.0.0:{}", port))?;
 
-        let hr_status = HeartRateStatus {
-            battery_level: BatteryLevel::NotReported,
-            ..Default::default()
-        };
-
         let listener = TcpListener::bind(host_addr).await?;
 
+        let simple_ingest_token = if !websocket_settings.simple_ingest_enabled {
+            None
+        } else if websocket_settings.simple_ingest_token.is_empty() {
+            warn!("Simple ingest endpoint enabled without a token, not enabling!");
+            None
+        } else {
+            Some(websocket_settings.simple_ingest_token)
+        };
+        let auth_token = Some(websocket_settings.auth_token).filter(|token| !token.is_empty());
+        let tls_acceptor = load_tls_acceptor(
+            &websocket_settings.tls_cert_path,
+            &websocket_settings.tls_key_path,
+        )?;
+
         let local_addr = listener.local_addr()?;
+        let (snapshot_tx, snapshot_rx) = watch::channel(ServerSnapshot::default());
+        let input_guard = InputGuard::new(
+            websocket_settings.max_messages_per_sec,
+            websocket_settings.min_bpm,
+            websocket_settings.max_bpm,
+            websocket_settings.out_of_range,
+        );
 
         Ok((
             Self {
                 listener,
-                hr_status,
-                twitcher: Twitcher::new(rr_twitch_threshold),
+                readings: SimpleReadings::new(rr_twitch_threshold, stress_index_window),
+                connection_settings: ConnectionSettings {
+                    simple_ingest_token,
+                    auth_token,
+                    heart_rate_on_stream: websocket_settings.heart_rate_on_stream,
+                    json_mapping: websocket_settings.json_mapping,
+                    input_guard: input_guard.clone(),
+                    tls_acceptor,
+                    snapshot_rx,
+                },
+                clients: 0,
+                snapshot_tx,
+                ingest_guard: input_guard,
+                problems: 0,
+                latest_problem: None,
             },
             local_addr,
         ))
@@ -68,170 +168,965 @@ impl WebsocketActor {
         broadcast_tx: &BSender<AppUpdate>,
         cancel_token: CancellationToken,
     ) -> Result<(), AppError> {
-        'server: loop {
-            info!("Websocket server (re)starting!");
-            let connection: tokio::net::TcpStream;
+        info!("Websocket server starting!");
+        let (event_tx, mut event_rx) = mpsc::channel(32);
+        let mut summary_interval = tokio::time::interval(SUMMARY_INTERVAL);
+        loop {
             tokio::select! {
                 result = self.listener.accept() => {
                     match result {
-                        Ok((conn, _)) => {
-                            connection = conn;
+                        Ok((connection, peer)) => {
+                            journal::record(Severity::Info, Subsystem::Websocket, &format!("Client connected from {peer}"));
+                            tokio::spawn(connection_task(
+                                connection,
+                                peer,
+                                self.connection_settings.clone(),
+                                event_tx.clone(),
+                                cancel_token.child_token(),
+                            ));
                         }
+                        // The listener itself, not just one client
                         Err(err) => {
-                            broadcast!(broadcast_tx, ErrorPopup::UserMustDismiss(format!(
-                                "Handshake failed: {:?}",
-                                err
-                            )));
-                            continue 'server;
+                            let message = format!("Websocket server couldn't accept a connection: {err}");
+                            error!("{message}");
+                            journal::record(Severity::Error, Subsystem::Websocket, &message);
+                            broadcast!(broadcast_tx, ErrorPopup::Intermittent(message));
                         }
                     }
                 }
+                Some(event) = event_rx.recv() => self.handle_event(event, broadcast_tx),
+                _ = summary_interval.tick() => self.summarize_problems(),
                 _ = cancel_token.cancelled() => {
                     info!("Shutting down Websocket thread!");
                     return Ok(());
                 }
             }
-            let mut server = match ServerBuilder::new().accept(connection).await {
-                Ok(server) => server,
-                Err(err) => {
-                    error!("Handshake failed: {:?}", err);
-                    broadcast!(
-                        broadcast_tx,
-                        ErrorPopup::UserMustDismiss(format!("Handshake failed: {:?}", err))
-                    );
-                    continue 'server;
+        }
+    }
+    fn handle_event(&mut self, event: ConnectionEvent, broadcast_tx: &BSender<AppUpdate>) {
+        match event {
+            ConnectionEvent::Opened => {
+                self.clients += 1;
+                self.snapshot_tx
+                    .send_modify(|snapshot| snapshot.clients = self.clients);
+                broadcast!(broadcast_tx, AppUpdate::WebsocketClients(self.clients));
+            }
+            ConnectionEvent::Closed => {
+                self.clients = self.clients.saturating_sub(1);
+                self.snapshot_tx
+                    .send_modify(|snapshot| snapshot.clients = self.clients);
+                broadcast!(broadcast_tx, AppUpdate::WebsocketClients(self.clients));
+            }
+            ConnectionEvent::Reading(reading) => self.apply_reading(reading, broadcast_tx),
+            ConnectionEvent::Ingested(reading) => {
+                if !self.ingest_guard.admit(Instant::now()) {
+                    return;
                 }
-            };
-            debug!("Websocket handshake complete, starting rx loop.");
-            'receiving: loop {
-                tokio::select! {
-                    item = server.next() => {
-                        let (message, keep_conn) = self.handle_ws_message(item)?;
-                        broadcast!(broadcast_tx, message);
-                        if !keep_conn {
-                            break 'receiving;
-                        }
-                    }
-                    _ = cancel_token.cancelled() => {
-                        info!("Shutting down Websocket thread!");
-                        server.close().await?
-                    }
+                if let Some(reading) = self.ingest_guard.check(reading) {
+                    self.apply_reading(reading, broadcast_tx);
                 }
             }
+            ConnectionEvent::Problem(problem) => {
+                self.problems += 1;
+                self.latest_problem = Some(problem);
+            }
+        }
+    }
+    fn apply_reading(&mut self, reading: SimpleReading, broadcast_tx: &BSender<AppUpdate>) {
+        let hr_status = self.readings.apply(reading);
+        self.snapshot_tx
+            .send_modify(|snapshot| snapshot.hr_status = hr_status.clone());
+        broadcast!(broadcast_tx, hr_status);
+    }
+    // One line for however many clients misbehaved, rather than a popup each
+    fn summarize_problems(&mut self) {
+        if let Some(summary) = self.ingest_guard.summary() {
+            let problem = format!("Simple ingest requests ignored: {summary}");
+            warn!("Websocket: {problem}");
+            self.problems += 1;
+            self.latest_problem = Some(problem);
         }
+        let Some(latest) = self.latest_problem.take() else {
+            return;
+        };
+        let message = match std::mem::take(&mut self.problems) {
+            1 => latest,
+            count => format!("{count} connection problems, latest: {latest}"),
+        };
+        journal::record(Severity::Warning, Subsystem::Websocket, &message);
     }
+}
 
-    // async fn recieving_loop<S: AsyncRead + AsyncWrite + Unpin>(
-    //     &self,
-    //     server: WebSocketStream<S>,
-    // ) -> Result<(), AppError> {
-    //     unimplemented!();
-    // }
+/// A stream with the request head that was already read put back in front,
+/// so the websocket handshake gets to read it too.
+struct Rewind<S> {
+    head: Vec<u8>,
+    position: usize,
+    inner: S,
+    // The handshake can't pick a subprotocol itself, so it's slipped into its response
+    response_header: Option<String>,
+    pending: Vec<u8>,
+    pending_len: Option<usize>,
+}
 
-    fn handle_ws_message(
-        &mut self,
-        item: Option<Result<Message, tokio_websockets::Error>>,
-    ) -> Result<(AppUpdate, bool), AppError> {
-        let message = match item {
-            // Got a text-type message!
-            Some(Ok(msg)) if msg.is_text() => {
-                let msg = msg.as_text().unwrap().to_owned();
-                msg
-            }
-            Some(Ok(msg)) if msg.is_close() => {
-                warn!("Websocket client sent close opcode!");
-                return Ok((
-                    ErrorPopup::Intermittent("Device closed connection!".to_string()).into(),
-                    false,
-                ));
-            }
-            //
-            Some(Ok(msg)) => {
-                error!("Invalid message type: {:?}", msg);
-                return Ok((
-                    ErrorPopup::UserMustDismiss(format!(
-                        "Invalid message type (expected text): {:?}",
-                        msg
-                    ))
-                    .into(),
-                    true,
-                ));
-            }
-            Some(Err(e)) => {
-                error!("Error receiving message: {:?}", e);
-                return Ok((
-                    ErrorPopup::Intermittent(format!("Error receiving message: {:?}", e)).into(),
-                    false,
-                ));
-                //break 'receiving;
-            }
-            None => {
-                info!("Websocket client disconnected");
-                return Ok((
-                    ErrorPopup::Intermittent("Websocket client disconnected".to_string()).into(),
-                    false,
-                ));
-                //break 'receiving;
+impl<S> Rewind<S> {
+    fn new(head: Vec<u8>, inner: S) -> Self {
+        Self {
+            head,
+            position: 0,
+            inner,
+            response_header: None,
+            pending: Vec::new(),
+            pending_len: None,
+        }
+    }
+    fn with_protocol(mut self, protocol: &str) -> Self {
+        self.response_header = Some(format!("Sec-WebSocket-Protocol: {protocol}\r\n"));
+        self
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Rewind<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.position < this.head.len() {
+            let remaining = &this.head[this.position..];
+            let len = remaining.len().min(buf.remaining());
+            buf.put_slice(&remaining[..len]);
+            this.position += len;
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Rewind<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if let Some(header) = this.response_header.take() {
+            // First thing written is the whole response, headers end right before the blank line
+            if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                let (headers, rest) = buf.split_at(end + 2);
+                this.pending = [headers, header.as_bytes(), rest].concat();
+                this.pending_len = Some(buf.len());
             }
-        };
-        if let Ok(new_status) = serde_json::from_str::<JSONHeartRate>(&message) {
-            let now = chrono::Local::now();
-            self.hr_status.heart_rate_bpm = new_status.bpm;
-            if let Some(battery) = new_status.battery {
-                self.hr_status.battery_level = BatteryLevel::Level(battery);
-            }
-            if let Some(rr) = new_status.latest_rr_ms {
-                while !self.hr_status.rr_intervals.is_empty() {
-                    self.hr_status.rr_intervals.pop();
+        }
+        while !this.pending.is_empty() {
+            match Pin::new(&mut this.inner).poll_write(cx, &this.pending) {
+                Poll::Ready(Ok(0)) => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                Poll::Ready(Ok(written)) => {
+                    this.pending.drain(..written);
                 }
-                self.hr_status.rr_intervals.push(Duration::from_millis(rr));
+                other => return other,
             }
+        }
+        if let Some(len) = this.pending_len.take() {
+            return Poll::Ready(Ok(len));
+        }
+        Pin::new(&mut this.inner).poll_write(cx, buf)
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
 
-            let (twitch_up, twitch_down) = self
-                .twitcher
-                .handle(new_status.bpm, &self.hr_status.rr_intervals);
-            self.hr_status.twitch_up = twitch_up;
-            self.hr_status.twitch_down = twitch_down;
-            self.hr_status.timestamp = now;
+/// Lets companion apps find the server over mDNS, instead of the user typing in an IP.
+///
+/// Like OSCQuery, the instance name gets a random suffix so multiple copies don't collide.
+struct Advertisement {
+    daemon: ServiceDaemon,
+    name: String,
+    fullname: String,
+}
 
-            Ok((self.hr_status.clone().into(), true))
-        } else {
-            error!("Invalid heart rate message: {}", message);
+impl Advertisement {
+    fn start(port: u16, tls: bool, auth: bool) -> Result<Self, AppError> {
+        let name = format!("IronHeart-{:04X}", rand::thread_rng().gen::<u16>());
+        let host_name = format!("{name}.local.");
+        // So apps know to use wss:// and ask for a token before connecting
+        let properties = HashMap::from([
+            ("tls".to_owned(), u8::from(tls).to_string()),
+            ("auth".to_owned(), u8::from(auth).to_string()),
+        ]);
+        let daemon = ServiceDaemon::new()?;
+        let service_info = ServiceInfo::new(MDNS_SERVICE, &name, &host_name, "", port, properties)?
+            .enable_addr_auto();
+        let fullname = service_info.get_fullname().to_owned();
+        daemon.register(service_info)?;
+        info!("Websocket: Advertising as {name} (port {port})");
+        Ok(Self {
+            daemon,
+            name,
+            fullname,
+        })
+    }
+    fn stop(self) {
+        if let Err(e) = self.daemon.unregister(&self.fullname) {
+            warn!("Websocket: Failed to unregister {}: {e}", self.fullname);
+        }
+        if let Err(e) = self.daemon.shutdown() {
+            warn!("Websocket: Failed to shut down mDNS daemon: {e}");
+        }
+        info!("Websocket: Stopped advertising {}", self.name);
+    }
+}
+
+fn load_tls_acceptor(cert_path: &str, key_path: &str) -> Result<Option<TlsAcceptor>, AppError> {
+    match (cert_path.is_empty(), key_path.is_empty()) {
+        (true, true) => return Ok(None),
+        (false, false) => {}
+        _ => return Err(AppError::WebsocketTlsIncomplete),
+    }
+    let read = |path: &str| {
+        std::fs::read(path).map_err(|source| AppError::ReadFile {
+            path: path.into(),
+            source,
+        })
+    };
+    let identity = native_tls::Identity::from_pkcs8(&read(cert_path)?, &read(key_path)?)?;
+    let acceptor = native_tls::TlsAcceptor::new(identity)?;
+    Ok(Some(acceptor.into()))
+}
+
+async fn connection_task(
+    connection: TcpStream,
+    peer: SocketAddr,
+    settings: ConnectionSettings,
+    event_tx: mpsc::Sender<ConnectionEvent>,
+    cancel_token: CancellationToken,
+) {
+    let result = match settings.tls_acceptor.as_ref() {
+        Some(acceptor) => match acceptor.accept(connection).await {
+            Ok(connection) => {
+                serve_connection(connection, peer, &settings, &event_tx, cancel_token).await
+            }
+            Err(e) => Err(e.into()),
+        },
+        None => serve_connection(connection, peer, &settings, &event_tx, cancel_token).await,
+    };
+    if let Err(e) = result {
+        let problem = format!("Connection from {peer} failed: {e}");
+        warn!("Websocket: {problem}");
+        // Server's already gone if this fails
+        let _ = event_tx.send(ConnectionEvent::Problem(problem)).await;
+    }
+}
+
+async fn serve_connection<S: AsyncRead + AsyncWrite + Unpin>(
+    mut connection: S,
+    peer: SocketAddr,
+    settings: &ConnectionSettings,
+    event_tx: &mpsc::Sender<ConnectionEvent>,
+    cancel_token: CancellationToken,
+) -> Result<(), AppError> {
+    let request_head = read_request_head(&mut connection).await?;
+    let request_head_text = String::from_utf8_lossy(&request_head).into_owned();
+    if !is_websocket_upgrade(&request_head_text) {
+        if let Err(e) = serve_http(&mut connection, &request_head_text, settings, event_tx).await {
+            warn!("Failed to respond to HTTP request from {peer}: {e}");
+        }
+        return Ok(());
+    }
+    let query_authorized = match settings.auth_token.as_deref() {
+        Some(token) => authorized(&request_head_text, token),
+        None => true,
+    };
+    let mut connection = Rewind::new(request_head, connection);
+    let format = match WireFormat::negotiate(&request_head_text) {
+        Some(format) => {
+            connection = connection.with_protocol(format.subprotocol());
+            format
+        }
+        None => WireFormat::Json,
+    };
+    let mut server = match ServerBuilder::new().accept(connection).await {
+        Ok(server) => server,
+        Err(err) => {
+            let problem = format!("Handshake with {peer} failed: {err}");
+            warn!("Websocket: {problem}");
+            let _ = event_tx.send(ConnectionEvent::Problem(problem)).await;
+            return Ok(());
+        }
+    };
+    if let Some(token) = settings.auth_token.as_deref() {
+        if !query_authorized && !first_message_auth(&mut server, token).await {
+            let problem = format!("Rejected client from {peer}, missing or invalid token");
+            warn!("Websocket: {problem}");
+            let _ = event_tx.send(ConnectionEvent::Problem(problem)).await;
+            let close = Message::close(
+                Some(CloseCode::POLICY_VIOLATION),
+                "Missing or invalid token",
+            );
+            server.send(close).await?;
+            return Ok(());
+        }
+    }
+    debug!("Websocket handshake complete, starting rx loop.");
+    // Only counting websockets, simple ingest requests are gone right away
+    let _ = event_tx.send(ConnectionEvent::Opened).await;
+    let result = receive_loop(&mut server, peer, format, settings, event_tx, cancel_token).await;
+    let _ = event_tx.send(ConnectionEvent::Closed).await;
+    result
+}
+
+/// Answers anything that isn't a websocket handshake, so scripts can poll without a websocket library.
+async fn serve_http<S: AsyncWrite + Unpin>(
+    connection: &mut S,
+    request_head: &str,
+    settings: &ConnectionSettings,
+    event_tx: &mpsc::Sender<ConnectionEvent>,
+) -> Result<(), AppError> {
+    let path = match get_path(request_head) {
+        Ok(path) => path,
+        Err(rejection) => return reject(connection, &rejection).await,
+    };
+    match path.as_str() {
+        SIMPLE_INGEST_PATH if settings.simple_ingest_token.is_some() => {
+            let token = settings.simple_ingest_token.as_deref().unwrap_or_default();
+            let result = parse_simple_ingest(request_head, token);
+            respond(connection, &result).await?;
+            if let Ok(reading) = result {
+                let _ = event_tx.send(ConnectionEvent::Ingested(reading)).await;
+            }
+            Ok(())
+        }
+        HEALTH_PATH => {
+            let snapshot = settings.snapshot_rx.borrow().clone();
+            let last_reading_secs = (snapshot.hr_status.heart_rate_bpm != 0).then(|| {
+                (chrono::Local::now() - snapshot.hr_status.timestamp)
+                    .num_seconds()
+                    .max(0)
+            });
+            let health = HealthResponse {
+                status: "ok",
+                clients: snapshot.clients,
+                last_reading_secs,
+            };
+            let body = serde_json::to_string(&health)?;
+            write_response(connection, "200 OK", "application/json", &body).await
+        }
+        HR_PATH => {
+            // Same token as the websocket itself, if one's set
+            if let Some(token) = settings.auth_token.as_deref() {
+                if !authorized(request_head, token) {
+                    return reject(connection, &IngestRejection::Unauthorized).await;
+                }
+            }
+            let hr_status = settings.snapshot_rx.borrow().hr_status.clone();
+            let body = serde_json::to_string(&OverlayPayload::from(&hr_status))?;
+            write_response(connection, "200 OK", "application/json", &body).await
+        }
+        _ => reject(connection, &IngestRejection::NotFound).await,
+    }
+}
+
+async fn first_message_auth<S: AsyncRead + AsyncWrite + Unpin>(
+    server: &mut WebSocketStream<S>,
+    token: &str,
+) -> bool {
+    let Ok(Some(Ok(message))) = tokio::time::timeout(AUTH_TIMEOUT, server.next()).await else {
+        return false;
+    };
+    message
+        .as_text()
+        .and_then(|text| serde_json::from_str::<AuthMessage>(text).ok())
+        .is_some_and(|message| message.auth == token)
+}
+
+async fn receive_loop<S: AsyncRead + AsyncWrite + Unpin>(
+    server: &mut WebSocketStream<S>,
+    peer: SocketAddr,
+    format: WireFormat,
+    settings: &ConnectionSettings,
+    event_tx: &mpsc::Sender<ConnectionEvent>,
+    cancel_token: CancellationToken,
+) -> Result<(), AppError> {
+    let heart_rate_on_stream = settings.heart_rate_on_stream;
+    if heart_rate_on_stream {
+        server.send(Message::text(obs_mimic::hello())).await?;
+    }
+    let mut guard = settings.input_guard.clone();
+    let mut summary_interval = tokio::time::interval(SUMMARY_INTERVAL);
+    let result = loop {
+        tokio::select! {
+            item = server.next() => {
+                let is_data = matches!(&item, Some(Ok(msg)) if msg.is_text() || msg.is_binary());
+                if is_data && !guard.admit(Instant::now()) {
+                    continue;
+                }
+                if let Some(reply) = obs_reply(heart_rate_on_stream, &item) {
+                    if let Some(response) = reply.response {
+                        server.send(Message::text(response)).await?;
+                    }
+                    if let Some(bpm) = reply.bpm {
+                        let reading = SimpleReading {
+                            bpm,
+                            latest_rr_ms: None,
+                            battery: None,
+                        };
+                        if let Some(reading) = guard.check(reading) {
+                            let _ = event_tx.send(ConnectionEvent::Reading(reading)).await;
+                        }
+                    }
+                    continue;
+                }
+                let (result, keep_conn) = handle_ws_message(item, format, &settings.json_mapping);
+                match result {
+                    Ok(reading) => {
+                        if let Some(reading) = guard.check(reading) {
+                            let _ = event_tx.send(ConnectionEvent::Reading(reading)).await;
+                        }
+                    }
+                    // Bad messages get rolled into the summary instead of a popup each
+                    Err(_) if keep_conn => guard.invalid(),
+                    // Client's gone, already logged and the client count shows it
+                    Err(_) => {}
+                }
+                if !keep_conn {
+                    break Ok(());
+                }
+            }
+            _ = summary_interval.tick() => {
+                report_dropped(&mut guard, peer, event_tx).await;
+            }
+            _ = cancel_token.cancelled() => {
+                server.close().await?;
+                break Ok(());
+            }
+        }
+    };
+    report_dropped(&mut guard, peer, event_tx).await;
+    result
+}
+
+async fn report_dropped(
+    guard: &mut InputGuard,
+    peer: SocketAddr,
+    event_tx: &mpsc::Sender<ConnectionEvent>,
+) {
+    let Some(summary) = guard.summary() else {
+        return;
+    };
+    let problem = format!("Messages from {peer} ignored: {summary}");
+    warn!("Websocket: {problem}");
+    let _ = event_tx.send(ConnectionEvent::Problem(problem)).await;
+}
+
+// HeartRateOnStream only talks to OBS, anything else goes through as usual
+fn obs_reply(
+    heart_rate_on_stream: bool,
+    item: &Option<Result<Message, tokio_websockets::Error>>,
+) -> Option<obs_mimic::ObsReply> {
+    if !heart_rate_on_stream {
+        return None;
+    }
+    let text = item.as_ref()?.as_ref().ok()?.as_text()?;
+    let reply = obs_mimic::handle_message(text)?;
+    raw_capture::record_websocket(text);
+    Some(reply)
+}
 
-            Ok((
-                AppUpdate::Error(ErrorPopup::Intermittent(format!(
-                    "Invalid heart rate message: {}",
-                    message
+fn handle_ws_message(
+    item: Option<Result<Message, tokio_websockets::Error>>,
+    format: WireFormat,
+    json_mapping: &JsonMapping,
+) -> (Result<SimpleReading, ErrorPopup>, bool) {
+    let message = match item {
+        // Got a text-type message!
+        Some(Ok(msg)) if msg.is_text() => {
+            let msg = msg.as_text().unwrap().to_owned();
+            msg
+        }
+        Some(Ok(msg)) if msg.is_close() => {
+            warn!("Websocket client sent close opcode!");
+            return (
+                Err(ErrorPopup::Intermittent(
+                    "Device closed connection!".to_string(),
+                )),
+                false,
+            );
+        }
+        Some(Ok(msg)) if msg.is_binary() && format != WireFormat::Json => {
+            let payload = msg.as_payload();
+            return match format.parse_binary(payload, json_mapping) {
+                Some(reading) => (Ok(reading), true),
+                None => {
+                    debug!("Invalid binary heart rate message: {:02x?}", &payload[..]);
+                    (
+                        Err(ErrorPopup::Intermittent(format!(
+                            "Invalid binary heart rate message ({} bytes)",
+                            payload.len()
+                        ))),
+                        true,
+                    )
+                }
+            };
+        }
+        //
+        Some(Ok(msg)) => {
+            debug!("Invalid message type: {:?}", msg);
+            return (
+                Err(ErrorPopup::UserMustDismiss(format!(
+                    "Invalid message type (expected text): {:?}",
+                    msg
                 ))),
                 true,
-            ))
+            );
+        }
+        Some(Err(e)) => {
+            error!("Error receiving message: {:?}", e);
+            return (
+                Err(ErrorPopup::Intermittent(format!(
+                    "Error receiving message: {:?}",
+                    e
+                ))),
+                false,
+            );
+        }
+        None => {
+            info!("Websocket client disconnected");
+            return (
+                Err(ErrorPopup::Intermittent(
+                    "Websocket client disconnected".to_string(),
+                )),
+                false,
+            );
+        }
+    };
+    raw_capture::record_websocket(&message);
+    if let Some(reading) = simple_reading::parse_mapped_json(&message, json_mapping) {
+        (Ok(reading), true)
+    } else {
+        debug!("Invalid heart rate message: {}", message);
+        (
+            Err(ErrorPopup::Intermittent(format!(
+                "Invalid heart rate message: {}",
+                message
+            ))),
+            true,
+        )
+    }
+}
+
+// Client mode, for senders that only run a server of their own
+struct WebsocketClient {
+    url: String,
+    json_mapping: JsonMapping,
+    readings: SimpleReadings,
+}
+
+impl ReconnectingClient for WebsocketClient {
+    type Connection = WsClient;
+    type Updates = ();
+    fn name(&self) -> &'static str {
+        "Websocket server"
+    }
+    fn subsystem(&self) -> Subsystem {
+        Subsystem::Websocket
+    }
+    fn target(&self) -> String {
+        // Leaving out any token in the query
+        self.url.split('?').next().unwrap_or_default().to_owned()
+    }
+    async fn connect(&mut self) -> Result<WsClient, ConnectError> {
+        reconnect::connect_websocket(&self.url).await
+    }
+    async fn session(
+        &mut self,
+        mut client: WsClient,
+        _updates: &mut (),
+        broadcast_tx: &BSender<AppUpdate>,
+        cancel_token: &CancellationToken,
+    ) -> Result<Option<String>, AppError> {
+        loop {
+            tokio::select! {
+                item = client.next() => {
+                    match handle_ws_message(item, WireFormat::Json, &self.json_mapping) {
+                        (Ok(reading), _) => {
+                            broadcast!(broadcast_tx, self.readings.apply(reading));
+                        }
+                        (Err(ErrorPopup::Intermittent(reason)), false) => return Ok(Some(reason)),
+                        (Err(popup), keep_conn) => {
+                            broadcast!(broadcast_tx, popup);
+                            if !keep_conn {
+                                return Ok(Some("Lost connection".into()));
+                            }
+                        }
+                    }
+                }
+                _ = cancel_token.cancelled() => {
+                    client.close().await?;
+                    return Ok(None);
+                }
+            }
         }
     }
+    fn on_lost(&mut self, broadcast_tx: &BSender<AppUpdate>) {
+        // Don't leave the last reading hanging while we're not getting any
+        broadcast!(
+            broadcast_tx,
+            HeartRateStatus::default(),
+            "Failed to send 0BPM after websocket server disconnected"
+        );
+    }
 }
 
 pub async fn websocket_thread(
     broadcast_tx: BSender<AppUpdate>,
     websocket_settings: WebSocketSettings,
     port_override: Option<u16>,
-    rr_twitch_threshold: f32,
+    rr_twitch_threshold: TwitchThreshold,
+    stress_index_window: usize,
     cancel_token: CancellationToken,
 ) {
-    let (mut websocket, local_addr) =
-        match WebsocketActor::build(websocket_settings, port_override, rr_twitch_threshold).await {
-            Ok((ws, addr)) => (ws, addr),
-            Err(e) => {
-                let message = "Failed to build websocket.";
-                broadcast!(broadcast_tx, ErrorPopup::detailed(message, e));
-                return;
-            }
+    if !websocket_settings.client_url.is_empty() {
+        let mut client = WebsocketClient {
+            url: websocket_settings.client_url,
+            json_mapping: websocket_settings.json_mapping,
+            readings: SimpleReadings::new(rr_twitch_threshold, stress_index_window),
         };
+        if let Err(e) = reconnect::run(&mut client, &mut (), &broadcast_tx, &cancel_token).await {
+            error!("Websocket client error: {e}");
+            let message = "Websocket client error";
+            broadcast!(broadcast_tx, ErrorPopup::detailed(message, e));
+        }
+        return;
+    }
+    let advertise = websocket_settings.advertise;
+    let tls = !websocket_settings.tls_cert_path.is_empty();
+    let auth = !websocket_settings.auth_token.is_empty();
+    let (mut websocket, local_addr) = match WebsocketActor::build(
+        websocket_settings,
+        port_override,
+        rr_twitch_threshold,
+        stress_index_window,
+    )
+    .await
+    {
+        Ok((ws, addr)) => (ws, addr),
+        Err(e) => {
+            let message = "Failed to build websocket.";
+            broadcast!(broadcast_tx, ErrorPopup::detailed(message, e));
+            return;
+        }
+    };
 
     // Sharing the URL with the UI
     broadcast!(broadcast_tx, local_addr);
 
+    let advertisement = if advertise {
+        match Advertisement::start(local_addr.port(), tls, auth) {
+            Ok(advertisement) => {
+                let name = advertisement.name.clone();
+                broadcast!(broadcast_tx, AppUpdate::WebsocketAdvertised(name));
+                Some(advertisement)
+            }
+            Err(e) => {
+                warn!("Failed to advertise websocket server: {e}");
+                let message = "Failed to advertise websocket server over mDNS";
+                broadcast!(broadcast_tx, ErrorPopup::detailed(message, e));
+                None
+            }
+        }
+    } else {
+        None
+    };
+
     if let Err(e) = websocket.server_loop(&broadcast_tx, cancel_token).await {
         error!("Websocket server error: {e}");
         let message = "Websocket server error";
         broadcast!(broadcast_tx, ErrorPopup::detailed(message, e));
     }
+    if let Some(advertisement) = advertisement {
+        advertisement.stop();
+    }
+}
+
+#[derive(Default)]
+pub struct WebSocketSource {
+    // From the `websocket` subcommand
+    pub port_override: Option<u16>,
+}
+
+impl HeartRateSource for WebSocketSource {
+    fn source(&self) -> HrSource {
+        HrSource::WebSocket
+    }
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::FULL
+    }
+    fn waiting_view(&self) -> AppView {
+        AppView::WaitingForWebsocket
+    }
+    fn spawn(self: Box<Self>, context: SourceContext) -> JoinHandle<()> {
+        tokio::spawn(websocket_thread(
+            context.broadcast_tx,
+            context.settings.websocket,
+            self.port_override,
+            context.rr_twitch_threshold,
+            context.stress_index_window,
+            context.cancel_token,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::sync::broadcast;
+    use tokio_websockets::ClientBuilder;
+
+    // What Settings::load would fill in, the derived default has no plausible BPM at all
+    fn settings() -> WebSocketSettings {
+        WebSocketSettings {
+            max_messages_per_sec: 20,
+            min_bpm: 20,
+            max_bpm: 250,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_clients_at_once() {
+        let (mut websocket, local_addr) =
+            WebsocketActor::build(settings(), Some(0), TwitchThreshold::new(0.0), 30)
+                .await
+                .unwrap();
+        let (broadcast_tx, mut broadcast_rx) = broadcast::channel(32);
+        let cancel_token = CancellationToken::new();
+        let server_cancel = cancel_token.clone();
+        let server =
+            tokio::spawn(async move { websocket.server_loop(&broadcast_tx, server_cancel).await });
+
+        let uri = format!("ws://127.0.0.1:{}", local_addr.port());
+        let mut clients = Vec::new();
+        for bpm in [70, 80] {
+            let builder = ClientBuilder::new().uri(&uri).unwrap();
+            let (mut client, _) = builder.connect().await.unwrap();
+            client
+                .send(Message::text(format!(r#"{{"bpm": {bpm}}}"#)))
+                .await
+                .unwrap();
+            clients.push(client);
+        }
+
+        let mut most_clients = 0;
+        let mut bpms = Vec::new();
+        while bpms.len() < 2 || most_clients < 2 {
+            match broadcast_rx.recv().await.unwrap() {
+                AppUpdate::WebsocketClients(clients) => most_clients = most_clients.max(clients),
+                AppUpdate::HeartRateStatus(status) => bpms.push(status.heart_rate_bpm),
+                _ => {}
+            }
+        }
+        // The first one's still connected while the second sends
+        assert_eq!(most_clients, 2);
+        bpms.sort();
+        assert_eq!(bpms, vec![70, 80]);
+
+        cancel_token.cancel();
+        server.await.unwrap().unwrap();
+    }
+    #[tokio::test]
+    async fn rejects_clients_without_token() {
+        let settings = WebSocketSettings {
+            auth_token: "hunter2".to_string(),
+            ..settings()
+        };
+        let (mut websocket, local_addr) =
+            WebsocketActor::build(settings, Some(0), TwitchThreshold::new(0.0), 30)
+                .await
+                .unwrap();
+        let (broadcast_tx, mut broadcast_rx) = broadcast::channel(32);
+        let cancel_token = CancellationToken::new();
+        let server_cancel = cancel_token.clone();
+        let server =
+            tokio::spawn(async move { websocket.server_loop(&broadcast_tx, server_cancel).await });
+        let base = format!("ws://127.0.0.1:{}", local_addr.port());
+
+        for uri in [format!("{base}/?token=wrong"), base.clone()] {
+            let builder = ClientBuilder::new().uri(&uri).unwrap();
+            let (mut client, _) = builder.connect().await.unwrap();
+            client
+                .send(Message::text(r#"{"bpm": 60}"#.to_string()))
+                .await
+                .unwrap();
+            let close = client.next().await.unwrap().unwrap();
+            assert_eq!(close.as_close().unwrap().0, CloseCode::POLICY_VIOLATION);
+        }
+
+        let builder = ClientBuilder::new()
+            .uri(&format!("{base}/?token=hunter2"))
+            .unwrap();
+        let (mut in_url, _) = builder.connect().await.unwrap();
+        in_url
+            .send(Message::text(r#"{"bpm": 70}"#.to_string()))
+            .await
+            .unwrap();
+        let builder = ClientBuilder::new().uri(&base).unwrap();
+        let (mut first_message, _) = builder.connect().await.unwrap();
+        for message in [r#"{"auth": "hunter2"}"#, r#"{"bpm": 80}"#] {
+            first_message
+                .send(Message::text(message.to_string()))
+                .await
+                .unwrap();
+        }
+
+        let mut bpms = Vec::new();
+        while bpms.len() < 2 {
+            match broadcast_rx.recv().await.unwrap() {
+                AppUpdate::HeartRateStatus(status) => bpms.push(status.heart_rate_bpm),
+                // Rejected clients only get logged
+                AppUpdate::Error(popup) => panic!("Unexpected popup: {popup:?}"),
+                _ => {}
+            }
+        }
+        bpms.sort();
+        assert_eq!(bpms, vec![70, 80]);
+
+        cancel_token.cancel();
+        server.await.unwrap().unwrap();
+    }
+    #[tokio::test]
+    async fn simple_ingest_on_same_port() {
+        let settings = WebSocketSettings {
+            simple_ingest_enabled: true,
+            simple_ingest_token: "hunter2".to_string(),
+            ..settings()
+        };
+        let (mut websocket, local_addr) =
+            WebsocketActor::build(settings, Some(0), TwitchThreshold::new(0.0), 30)
+                .await
+                .unwrap();
+        let (broadcast_tx, mut broadcast_rx) = broadcast::channel(32);
+        let cancel_token = CancellationToken::new();
+        let server_cancel = cancel_token.clone();
+        let server =
+            tokio::spawn(async move { websocket.server_loop(&broadcast_tx, server_cancel).await });
+
+        // Out of range, so it's dropped like it would be from a websocket
+        for bpm in [300, 65] {
+            let response = http_get(
+                local_addr,
+                &format!("/ingest/simple?bpm={bpm}&token=hunter2"),
+            )
+            .await;
+            assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        }
+        loop {
+            if let AppUpdate::HeartRateStatus(status) = broadcast_rx.recv().await.unwrap() {
+                assert_eq!(status.heart_rate_bpm, 65);
+                break;
+            }
+        }
+
+        cancel_token.cancel();
+        server.await.unwrap().unwrap();
+    }
+    async fn http_get(local_addr: SocketAddr, target: &str) -> String {
+        let mut request = TcpStream::connect(local_addr).await.unwrap();
+        let head = format!("GET {target} HTTP/1.1\r\nHost: pc\r\n\r\n");
+        request.write_all(head.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        request.read_to_string(&mut response).await.unwrap();
+        response
+    }
+    #[tokio::test]
+    async fn http_status_endpoints() {
+        let settings = WebSocketSettings {
+            auth_token: "hunter2".to_string(),
+            ..settings()
+        };
+        let (mut websocket, local_addr) =
+            WebsocketActor::build(settings, Some(0), TwitchThreshold::new(0.0), 30)
+                .await
+                .unwrap();
+        let (broadcast_tx, mut broadcast_rx) = broadcast::channel(32);
+        let cancel_token = CancellationToken::new();
+        let server_cancel = cancel_token.clone();
+        let server =
+            tokio::spawn(async move { websocket.server_loop(&broadcast_tx, server_cancel).await });
+
+        let response = http_get(local_addr, "/health").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        assert!(response.ends_with(r#"{"status":"ok","clients":0,"last_reading_secs":null}"#));
+
+        let uri = format!("ws://127.0.0.1:{}/?token=hunter2", local_addr.port());
+        let (mut client, _) = ClientBuilder::new()
+            .uri(&uri)
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        client
+            .send(Message::text(r#"{"bpm": 75}"#.to_string()))
+            .await
+            .unwrap();
+        loop {
+            if let AppUpdate::HeartRateStatus(status) = broadcast_rx.recv().await.unwrap() {
+                assert_eq!(status.heart_rate_bpm, 75);
+                break;
+            }
+        }
+
+        let response = http_get(local_addr, "/hr").await;
+        assert!(response.starts_with("HTTP/1.1 401"), "{response}");
+        let response = http_get(local_addr, "/hr?token=hunter2").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        assert!(response.contains(r#""bpm":75"#), "{response}");
+        let response = http_get(local_addr, "/health").await;
+        assert!(response.contains(r#""clients":1"#), "{response}");
+        let response = http_get(local_addr, "/nope").await;
+        assert!(response.starts_with("HTTP/1.1 404"), "{response}");
+
+        cancel_token.cancel();
+        server.await.unwrap().unwrap();
+    }
+    #[tokio::test]
+    async fn binary_by_subprotocol() {
+        let (mut websocket, local_addr) =
+            WebsocketActor::build(settings(), Some(0), TwitchThreshold::new(0.0), 30)
+                .await
+                .unwrap();
+        let (broadcast_tx, mut broadcast_rx) = broadcast::channel(32);
+        let cancel_token = CancellationToken::new();
+        let server_cancel = cancel_token.clone();
+        let server =
+            tokio::spawn(async move { websocket.server_loop(&broadcast_tx, server_cancel).await });
+
+        let builder = ClientBuilder::new()
+            .uri(&format!("ws://127.0.0.1:{}", local_addr.port()))
+            .unwrap()
+            .add_header(
+                http::header::SEC_WEBSOCKET_PROTOCOL,
+                http::HeaderValue::from_static("nih-binary"),
+            );
+        let (mut client, response) = builder.connect().await.unwrap();
+        assert_eq!(
+            response.headers()[http::header::SEC_WEBSOCKET_PROTOCOL],
+            "nih-binary"
+        );
+        client.send(Message::binary(vec![91, 0])).await.unwrap();
+        loop {
+            if let AppUpdate::HeartRateStatus(status) = broadcast_rx.recv().await.unwrap() {
+                assert_eq!(status.heart_rate_bpm, 91);
+                break;
+            }
+        }
+
+        cancel_token.cancel();
+        server.await.unwrap().unwrap();
+    }
 }
@@ -0,0 +1,250 @@
+// Compact payloads for embedded websocket senders, picked with a subprotocol during the handshake
+
+use serde_json::{Map, Number, Value};
+
+use super::simple_reading::{parse_mapped_value, SimpleReading};
+use crate::settings::JsonMapping;
+
+// Nested deeper than any heart rate message would be, so garbage can't blow the stack
+const MAX_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) enum WireFormat {
+    #[default]
+    Json,
+    /// The same fields as the JSON (and the same mapping), just encoded as MessagePack.
+    MessagePack,
+    /// Little endian `[bpm: u16][latest_rr_ms: u16][battery: u8]`, the last two optional.
+    /// An RR of 0 or battery of 255 means not reported.
+    Fixed,
+}
+
+impl WireFormat {
+    pub fn subprotocol(self) -> &'static str {
+        match self {
+            WireFormat::Json => "nih-json",
+            WireFormat::MessagePack => "nih-msgpack",
+            WireFormat::Fixed => "nih-binary",
+        }
+    }
+    /// The first format the client offered in `Sec-WebSocket-Protocol` that we know, if any.
+    pub fn negotiate(request_head: &str) -> Option<Self> {
+        request_head
+            .lines()
+            .skip(1)
+            .take_while(|line| !line.is_empty())
+            .filter_map(|line| line.split_once(':'))
+            .filter(|(name, _)| name.trim().eq_ignore_ascii_case("sec-websocket-protocol"))
+            .flat_map(|(_, value)| value.split(','))
+            .find_map(|offered| {
+                [WireFormat::Json, WireFormat::MessagePack, WireFormat::Fixed]
+                    .into_iter()
+                    .find(|format| format.subprotocol() == offered.trim())
+            })
+    }
+    /// A reading from a binary message, None if it isn't one in this format.
+    pub fn parse_binary(self, bytes: &[u8], mapping: &JsonMapping) -> Option<SimpleReading> {
+        match self {
+            WireFormat::Json => None,
+            WireFormat::MessagePack => parse_mapped_value(&decode_msgpack(bytes)?, mapping),
+            WireFormat::Fixed => parse_fixed(bytes),
+        }
+    }
+}
+
+fn parse_fixed(bytes: &[u8]) -> Option<SimpleReading> {
+    if !matches!(bytes.len(), 2 | 4 | 5) {
+        return None;
+    }
+    let bpm = u16::from_le_bytes([bytes[0], bytes[1]]);
+    let latest_rr_ms = bytes
+        .get(2..4)
+        .map(|rr| u16::from_le_bytes([rr[0], rr[1]]))
+        .filter(|rr| *rr != 0)
+        .map(u64::from);
+    let battery = bytes.get(4).copied().filter(|battery| *battery != u8::MAX);
+    Some(SimpleReading {
+        bpm,
+        latest_rr_ms,
+        battery,
+    })
+}
+
+/// Just enough MessagePack to read a reading, anything else (i.e. extension types) is rejected.
+fn decode_msgpack(bytes: &[u8]) -> Option<Value> {
+    let mut decoder = MsgpackDecoder { bytes };
+    let value = decoder.value(0)?;
+    decoder.bytes.is_empty().then_some(value)
+}
+
+struct MsgpackDecoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> MsgpackDecoder<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(taken)
+    }
+    fn uint(&mut self, len: usize) -> Option<u64> {
+        let bytes = self.take(len)?;
+        Some(bytes.iter().fold(0, |n, byte| n << 8 | u64::from(*byte)))
+    }
+    fn int(&mut self, len: usize) -> Option<i64> {
+        let unsigned = self.uint(len)?;
+        // Sign extend from however many bytes it was
+        let shift = 64 - len * 8;
+        Some((unsigned << shift) as i64 >> shift)
+    }
+    fn string(&mut self, len: usize) -> Option<String> {
+        String::from_utf8(self.take(len)?.to_vec()).ok()
+    }
+    fn array(&mut self, len: usize, depth: usize) -> Option<Value> {
+        let items = (0..len)
+            .map(|_| self.value(depth + 1))
+            .collect::<Option<Vec<_>>>()?;
+        Some(Value::Array(items))
+    }
+    fn map(&mut self, len: usize, depth: usize) -> Option<Value> {
+        let mut map = Map::new();
+        for _ in 0..len {
+            let key = match self.value(depth + 1)? {
+                Value::String(key) => key,
+                Value::Number(key) => key.to_string(),
+                _ => return None,
+            };
+            let value = self.value(depth + 1)?;
+            map.insert(key, value);
+        }
+        Some(Value::Object(map))
+    }
+    fn value(&mut self, depth: usize) -> Option<Value> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let marker = self.take(1)?[0];
+        let value = match marker {
+            0x00..=0x7f => Value::from(marker),
+            0x80..=0x8f => self.map(usize::from(marker & 0x0f), depth)?,
+            0x90..=0x9f => self.array(usize::from(marker & 0x0f), depth)?,
+            0xa0..=0xbf => Value::String(self.string(usize::from(marker & 0x1f))?),
+            0xc0 => Value::Null,
+            0xc2 => Value::Bool(false),
+            0xc3 => Value::Bool(true),
+            0xca => {
+                let bits = self.uint(4)? as u32;
+                Value::Number(Number::from_f64(f64::from(f32::from_bits(bits)))?)
+            }
+            0xcb => Value::Number(Number::from_f64(f64::from_bits(self.uint(8)?))?),
+            0xcc => Value::from(self.uint(1)?),
+            0xcd => Value::from(self.uint(2)?),
+            0xce => Value::from(self.uint(4)?),
+            0xcf => Value::from(self.uint(8)?),
+            0xd0 => Value::from(self.int(1)?),
+            0xd1 => Value::from(self.int(2)?),
+            0xd2 => Value::from(self.int(4)?),
+            0xd3 => Value::from(self.int(8)?),
+            0xd9 => {
+                let len = self.uint(1)? as usize;
+                Value::String(self.string(len)?)
+            }
+            0xda => {
+                let len = self.uint(2)? as usize;
+                Value::String(self.string(len)?)
+            }
+            0xdb => {
+                let len = self.uint(4)? as usize;
+                Value::String(self.string(len)?)
+            }
+            0xdc => {
+                let len = self.uint(2)? as usize;
+                self.array(len, depth)?
+            }
+            0xdd => {
+                let len = self.uint(4)? as usize;
+                self.array(len, depth)?
+            }
+            0xde => {
+                let len = self.uint(2)? as usize;
+                self.map(len, depth)?
+            }
+            0xdf => {
+                let len = self.uint(4)? as usize;
+                self.map(len, depth)?
+            }
+            0xe0..=0xff => Value::from(marker as i8),
+            // Binary and extension types
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiates_offered_format() {
+        let head =
+            "GET / HTTP/1.1\r\nHost: pc\r\nSec-WebSocket-Protocol: mqtt, nih-msgpack\r\n\r\n";
+        assert_eq!(WireFormat::negotiate(head), Some(WireFormat::MessagePack));
+        assert_eq!(
+            WireFormat::negotiate("GET / HTTP/1.1\r\nHost: pc\r\n\r\n"),
+            None
+        );
+    }
+    #[test]
+    fn msgpack_readings() {
+        let mapping = JsonMapping::default();
+        // {"bpm": 72, "latest_rr_ms": 833, "battery": 40}
+        let mut bytes = vec![0x83, 0xa3, b'b', b'p', b'm', 72, 0xac];
+        bytes.extend_from_slice(b"latest_rr_ms");
+        bytes.extend_from_slice(&[0xcd, 0x03, 0x41, 0xa7]);
+        bytes.extend_from_slice(b"battery");
+        bytes.push(40);
+        assert_eq!(
+            WireFormat::MessagePack.parse_binary(&bytes, &mapping),
+            Some(SimpleReading {
+                bpm: 72,
+                latest_rr_ms: Some(833),
+                battery: Some(40),
+            })
+        );
+        // Trailing garbage, truncated, and a negative BPM
+        bytes.push(0);
+        assert_eq!(WireFormat::MessagePack.parse_binary(&bytes, &mapping), None);
+        assert_eq!(
+            WireFormat::MessagePack.parse_binary(&bytes[..8], &mapping),
+            None
+        );
+        let negative = [0x81, 0xa3, b'b', b'p', b'm', 0xfb];
+        assert_eq!(
+            WireFormat::MessagePack.parse_binary(&negative, &mapping),
+            None
+        );
+        // Nested as deep as it goes
+        assert_eq!(decode_msgpack(&[0x91; 64]), None);
+    }
+    #[test]
+    fn fixed_readings() {
+        let mapping = JsonMapping::default();
+        let reading = WireFormat::Fixed.parse_binary(&[72, 0, 0x41, 0x03, 0xff], &mapping);
+        assert_eq!(
+            reading,
+            Some(SimpleReading {
+                bpm: 72,
+                latest_rr_ms: Some(833),
+                battery: None,
+            })
+        );
+        let reading = WireFormat::Fixed.parse_binary(&[80, 0], &mapping);
+        assert_eq!(reading.map(|reading| reading.bpm), Some(80));
+        assert_eq!(WireFormat::Fixed.parse_binary(&[80], &mapping), None);
+        assert_eq!(WireFormat::Json.parse_binary(&[80, 0], &mapping), None);
+    }
+}
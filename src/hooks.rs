@@ -0,0 +1,111 @@
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::process::Command;
+use tracing::{debug, info, warn};
+
+use crate::heart_rate::BatteryLevel;
+use crate::journal::{self, Severity, Subsystem};
+use crate::settings::HookSettings;
+
+// Hooks still running past this get killed, so a stuck script doesn't pile up
+const HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    Connect,
+    Disconnect,
+    LowBattery,
+}
+
+impl HookEvent {
+    fn as_str(&self) -> &'static str {
+        match self {
+            HookEvent::Connect => "connect",
+            HookEvent::Disconnect => "disconnect",
+            HookEvent::LowBattery => "low_battery",
+        }
+    }
+}
+
+/// Runs the user's `[ble.hooks]` shell commands on BLE device events.
+pub struct Hooks {
+    settings: HookSettings,
+}
+
+impl Hooks {
+    pub fn new(settings: HookSettings) -> Self {
+        Self { settings }
+    }
+    /// Starts the hook for `event` if one's set, without waiting on it.
+    pub fn run(
+        &self,
+        event: HookEvent,
+        device_name: &str,
+        device_address: &str,
+        battery: BatteryLevel,
+    ) {
+        let command = match event {
+            HookEvent::Connect => &self.settings.on_connect,
+            HookEvent::Disconnect => &self.settings.on_disconnect,
+            HookEvent::LowBattery => &self.settings.on_low_battery,
+        };
+        if command.is_empty() {
+            return;
+        }
+        let battery = match battery {
+            BatteryLevel::Level(level) => level.to_string(),
+            _ => String::new(),
+        };
+        let mut child = shell(command);
+        child
+            .env("HR_EVENT", event.as_str())
+            .env("HR_DEVICE_NAME", device_name)
+            .env("HR_DEVICE_ADDRESS", device_address)
+            .env("HR_BATTERY", battery)
+            .stdin(Stdio::null())
+            // Would otherwise draw over the TUI
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true);
+        let mut child = match child.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                let message = format!("Failed to run {} hook: {e}", event.as_str());
+                warn!("{message}");
+                journal::record(Severity::Warning, Subsystem::Ble, &message);
+                return;
+            }
+        };
+        info!("Running {} hook", event.as_str());
+        tokio::spawn(async move {
+            match tokio::time::timeout(HOOK_TIMEOUT, child.wait()).await {
+                Ok(Ok(status)) if status.success() => {
+                    debug!("{} hook finished", event.as_str());
+                }
+                Ok(Ok(status)) => warn!("{} hook exited with {status}", event.as_str()),
+                Ok(Err(e)) => warn!("Couldn't wait on {} hook: {e}", event.as_str()),
+                // Dropping the child kills it
+                Err(_) => warn!(
+                    "{} hook took longer than {} seconds, killing it",
+                    event.as_str(),
+                    HOOK_TIMEOUT.as_secs()
+                ),
+            }
+        });
+    }
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
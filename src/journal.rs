@@ -0,0 +1,151 @@
+use serde_derive::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use tracing::warn;
+
+use crate::app::ErrorPopup;
+use crate::errors::AppError;
+use crate::logging::syslog;
+
+// Only set if `misc.journal_enabled`
+static JOURNAL: OnceLock<Mutex<File>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl From<&ErrorPopup> for Severity {
+    fn from(popup: &ErrorPopup) -> Self {
+        match popup {
+            ErrorPopup::Intermittent(_) => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+/// Which part of the app an entry came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Subsystem {
+    App,
+    Ble,
+    Osc,
+    Websocket,
+    Fan,
+    Ant,
+    Pulsoid,
+    HypeRate,
+    Serial,
+    Udp,
+    Mqtt,
+    HttpPoll,
+    Stdin,
+    Stromno,
+    Fitbit,
+    Twitch,
+    Haptics,
+    Obs,
+    Streamerbot,
+}
+
+impl Subsystem {
+    fn name(&self) -> &'static str {
+        match self {
+            Subsystem::App => "app",
+            Subsystem::Ble => "ble",
+            Subsystem::Osc => "osc",
+            Subsystem::Websocket => "websocket",
+            Subsystem::Fan => "fan",
+            Subsystem::Ant => "ant",
+            Subsystem::Pulsoid => "pulsoid",
+            Subsystem::HypeRate => "hyperate",
+            Subsystem::Serial => "serial",
+            Subsystem::Udp => "udp",
+            Subsystem::Mqtt => "mqtt",
+            Subsystem::HttpPoll => "http_poll",
+            Subsystem::Stdin => "stdin",
+            Subsystem::Stromno => "stromno",
+            Subsystem::Fitbit => "fitbit",
+            Subsystem::Twitch => "twitch",
+            Subsystem::Haptics => "haptics",
+            Subsystem::Obs => "obs",
+            Subsystem::Streamerbot => "streamerbot",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct JournalEntry<'a> {
+    timestamp: String,
+    severity: Severity,
+    subsystem: Subsystem,
+    message: &'a str,
+}
+
+/// Opens the journal for appending, after which `record` starts writing to it.
+pub fn init(path: &Path) -> Result<(), AppError> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| AppError::CreateFile {
+            path: path.to_owned(),
+            source: e,
+        })?;
+    // Already being written to if this fails, nothing to do
+    let _ = JOURNAL.set(Mutex::new(file));
+    Ok(())
+}
+
+/// Appends a line to the journal (if enabled), regardless of log level.
+/// Also sent to syslog (if enabled).
+///
+/// Meant for errors and lifecycle events (connections, disconnections, etc.),
+/// so overnight sessions can be looked back on.
+pub fn record(severity: Severity, subsystem: Subsystem, message: &str) {
+    syslog::forward_session_event(severity, subsystem.name(), message);
+    let Some(journal) = JOURNAL.get() else {
+        return;
+    };
+    let entry = JournalEntry {
+        timestamp: chrono::Local::now().to_rfc3339(),
+        severity,
+        subsystem,
+        message,
+    };
+    let Ok(mut line) = serde_json::to_string(&entry) else {
+        return;
+    };
+    line.push('\n');
+    let Ok(mut file) = journal.lock() else {
+        return;
+    };
+    if let Err(e) = file.write_all(line.as_bytes()).and_then(|_| file.flush()) {
+        warn!("Failed to write to journal: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_format() {
+        let entry = JournalEntry {
+            timestamp: "2024-01-01T00:00:00+00:00".into(),
+            severity: Severity::Warning,
+            subsystem: Subsystem::Ble,
+            message: "Connection timed out",
+        };
+        assert_eq!(
+            serde_json::to_string(&entry).unwrap(),
+            r#"{"timestamp":"2024-01-01T00:00:00+00:00","severity":"warning","subsystem":"ble","message":"Connection timed out"}"#
+        );
+    }
+}
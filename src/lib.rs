@@ -2,14 +2,19 @@
 #[macro_use]
 extern crate lazy_static;
 
-use args::TopLevelCmd;
+use args::{SubCommands, TopLevelCmd};
 use errors::AppError;
 use ratatui::{backend::CrosstermBackend, Terminal};
 use self_update::cargo_crate_version;
-use std::{io, path::PathBuf};
+use std::{
+    io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
 use tokio::fs::create_dir;
 use tokio_util::sync::CancellationToken;
 
+use crate::alerts::{Alert, AlertKind};
 use crate::app::App;
 use event::{Event, EventHandler};
 use handler::handle_key_events;
@@ -18,7 +23,7 @@ use std::error;
 use tui::Tui;
 
 use rolling_file::{BasicRollingFileAppender, RollingConditionBasic};
-use tracing::info;
+use tracing::{error, info, warn};
 use tracing_subscriber::{filter, prelude::*};
 use tracing_subscriber::{fmt::time::ChronoLocal, layer::SubscriberExt, util::SubscriberInitExt};
 
@@ -29,20 +34,38 @@ pub mod args;
 pub mod errors;
 
 mod activities;
+mod alerts;
 mod app;
+mod audio;
+mod backoff;
 mod company_codes;
+mod fan;
+mod format;
+mod gatt_server;
+mod haptics;
 mod heart_rate;
+mod hooks;
+mod journal;
 mod logging;
 mod macros;
 mod osc;
+mod pairing;
 mod panic_handler;
+mod preflight;
+mod reconnect;
 mod scan;
 mod settings;
+mod sleep_inhibit;
+mod sources;
 mod structs;
+mod twitch;
+mod ui_state;
 mod updates;
 mod utils;
 mod vrcx;
 mod widgets;
+mod wled;
+mod zones;
 
 mod event;
 mod handler;
@@ -54,6 +77,20 @@ mod ui;
 pub type AppResult<T> = std::result::Result<T, Box<dyn error::Error>>;
 
 pub async fn run_tui(mut arg_config: TopLevelCmd) -> AppResult<()> {
+    // Just a debugging tool, doesn't need any of the app's setup
+    if let Some(SubCommands::Listen(listen)) = &arg_config.subcommands {
+        let addr = SocketAddr::new(listen.host.parse()?, listen.port);
+        return Ok(osc::listen(addr).await?);
+    }
+    if let Some(SubCommands::Export(export)) = &arg_config.subcommands {
+        let output = logging::tcx::export(
+            &export.session,
+            export.output.as_deref(),
+            export.lap_minutes,
+        )?;
+        println!("Exported to {}", output.display());
+        return Ok(());
+    }
     let working_directory = determine_working_directory().ok_or(AppError::WorkDir)?;
     arg_config.config_override = arg_config.config_override.map(|p| {
         p.canonicalize()
@@ -75,6 +112,7 @@ pub async fn run_tui(mut arg_config: TopLevelCmd) -> AppResult<()> {
         .expect("Couldn't build log path!")
         .to_owned();
     // let console = console_subscriber::spawn();
+    let log_path = working_directory.join(&log_name);
     let file_appender = BasicRollingFileAppender::new(
         log_name,
         RollingConditionBasic::new().max_size(1024 * 1024 * 5),
@@ -99,6 +137,7 @@ pub async fn run_tui(mut arg_config: TopLevelCmd) -> AppResult<()> {
         // .with(console)
         .with(env_filter)
         .with(fmt_layer)
+        .with(logging::syslog::SyslogLayer)
         .init();
 
     let mut app = App::build(&arg_config, None);
@@ -108,22 +147,44 @@ pub async fn run_tui(mut arg_config: TopLevelCmd) -> AppResult<()> {
     let terminal = Terminal::new(backend)?;
     let events = EventHandler::new(100);
     let mut tui = Tui::new(terminal, events);
-    tui.init()?;
+    let tui_ready = tui.init();
 
     info!("Starting app... v{}", cargo_crate_version!());
 
     // Starting off at DEBUG, and setting to whatever user has defined
     reload_handle.modify(|layer| *layer.filter_mut() = app.settings.get_log_level())?;
 
+    if let Err(e) = tui_ready {
+        warn!("Couldn't set up the terminal: {e}");
+        drop(tui);
+        app.init(&arg_config).await;
+        return run_detached(app, &log_path).await;
+    }
+
+    app.interactive = true;
     app.init(&arg_config).await;
 
     // Only when running TUI
     app.first_time_setup(&arg_config).await;
 
+    let hangup = tui::terminal_hangup();
+    let mut terminal_lost = false;
+
     // Start the main loop.
     while !app.cancel_app.is_cancelled() {
         // Render the user interface.
-        tui.draw(&mut app)?;
+        let draw_start = std::time::Instant::now();
+        if let Err(e) = tui.draw(&mut app) {
+            error!("Failed to draw to the terminal: {e}");
+            terminal_lost = true;
+            break;
+        }
+        app.chart_refresh.record_draw(draw_start.elapsed());
+        if app.take_bell() {
+            if let Err(e) = tui.ring_bell() {
+                warn!("Couldn't ring the terminal bell: {e}");
+            }
+        }
         tokio::select! {
             // Handle Crossterm events.
             val = tui.events.next() => {
@@ -141,9 +202,21 @@ pub async fn run_tui(mut arg_config: TopLevelCmd) -> AppResult<()> {
                 }
             }
             // Handle BLE Manager Events/Update UI with HR info
-            data = app.app_receivers() => app.app_handlers(data).await
+            data = app.app_receivers() => app.app_handlers(data).await,
+            _ = hangup.cancelled() => {
+                warn!("Terminal was closed");
+                terminal_lost = true;
+                break;
+            }
         }
     }
+
+    if terminal_lost {
+        // Terminal's gone, so there's not much point in checking if this worked
+        let _ = tui.exit();
+        return run_detached(app, &log_path).await;
+    }
+
     // After while loop closes
     app.join_threads().await;
 
@@ -186,6 +259,49 @@ pub async fn run_headless(
     Ok(())
 }
 
+/// Keeps the app running without a TUI when there's no terminal to draw to,
+/// (launched from a desktop shortcut, or the terminal window was closed)
+/// letting the user know where to look with a desktop notification.
+async fn run_detached(mut app: App, log_path: &Path) -> AppResult<()> {
+    let message = format!(
+        "No terminal available, continuing in the background. Status and errors are logged to {}",
+        log_path.display()
+    );
+    warn!("{message}");
+    let alert = Alert {
+        kind: AlertKind::Warning,
+        message,
+    };
+    if let Err(e) = alerts::toast(&alert).await {
+        warn!("Couldn't show headless notification: {e}");
+    }
+    // Nobody's left to answer it
+    app.skip_preflight();
+
+    while !app.cancel_app.is_cancelled() && !app.cancel_actors.is_cancelled() {
+        // Nobody's around to dismiss popups, so they just get logged
+        if let Some(error) = app.error_message.take() {
+            warn!("{error:?}");
+        }
+        tokio::select! {
+            data = app.app_receivers() => app.app_handlers(data).await,
+            _ = tokio::signal::ctrl_c() => {
+                info!("Received Ctrl+C");
+                app.cancel_app.cancel();
+            }
+        }
+    }
+    if let Some(error) = app.error_message.take() {
+        error!("{error:?}");
+    }
+    // After while loop closes
+    app.join_threads().await;
+
+    info!("Shutting down gracefully...");
+
+    Ok(())
+}
+
 /// Returns the directory that logs, config, and other files should be placed in by default.
 // The rules for how it determines the directory is as follows:
 // If the app is built with the portable feature, it will just return it's parent directory.
@@ -0,0 +1,243 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use prometheus::{Counter, Encoder, Gauge, IntCounter, IntGauge, Registry, TextEncoder};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Sender as BSender;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use crate::app::AppUpdate;
+use crate::errors::AppError;
+use crate::heart_rate::{BatteryLevel, HeartRateStatus};
+use crate::settings::PrometheusExporterSettings;
+
+const METRICS_PATH: &str = "/metrics";
+// Scrapers send a short GET, anything bigger isn't one
+const MAX_REQUEST_HEAD: usize = 4096;
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Gauges and counters for Prometheus to scrape, kept up to date from every HR update.
+///
+/// Unlike the push logging, the names are fixed so dashboards can be shared.
+struct Exporter {
+    registry: Registry,
+    bpm: IntGauge,
+    rr_seconds: Gauge,
+    battery_percent: IntGauge,
+    source_connected: IntGauge,
+    updates: IntCounter,
+    connections: IntCounter,
+    connected_seconds: Counter,
+    // Last update with a reading, to add up how long it's been connected
+    last_connected: Option<DateTime<Local>>,
+}
+
+impl Exporter {
+    fn build() -> Result<Self, AppError> {
+        let registry = Registry::new();
+        let bpm = IntGauge::new("heart_rate_bpm", "Heart rate in beats per minute")?;
+        let rr_seconds = Gauge::new("rr_interval_seconds", "Latest time between heart beats")?;
+        let battery_percent = IntGauge::new("battery_percent", "Battery level of the HR monitor")?;
+        let source_connected = IntGauge::new(
+            "source_connected",
+            "1 if the HR source is sending readings, 0 if not",
+        )?;
+        let updates = IntCounter::new("heart_rate_updates_total", "HR updates received")?;
+        let connections = IntCounter::new(
+            "source_connections_total",
+            "Times the HR source went from no reading to having one",
+        )?;
+        let connected_seconds = Counter::new(
+            "source_connected_seconds_total",
+            "Time spent with the HR source sending readings",
+        )?;
+        registry.register(Box::new(bpm.clone()))?;
+        registry.register(Box::new(rr_seconds.clone()))?;
+        registry.register(Box::new(battery_percent.clone()))?;
+        registry.register(Box::new(source_connected.clone()))?;
+        registry.register(Box::new(updates.clone()))?;
+        registry.register(Box::new(connections.clone()))?;
+        registry.register(Box::new(connected_seconds.clone()))?;
+        Ok(Self {
+            registry,
+            bpm,
+            rr_seconds,
+            battery_percent,
+            source_connected,
+            updates,
+            connections,
+            connected_seconds,
+            last_connected: None,
+        })
+    }
+    fn update(&mut self, hr_status: &HeartRateStatus) {
+        let connected = hr_status.heart_rate_bpm != 0;
+        self.updates.inc();
+        self.bpm.set(hr_status.heart_rate_bpm as i64);
+        if let Some(rr) = hr_status.rr_intervals.last() {
+            self.rr_seconds.set(rr.as_secs_f64());
+        }
+        if let BatteryLevel::Level(level) = hr_status.battery_level {
+            self.battery_percent.set(level as i64);
+        }
+        match (self.last_connected, connected) {
+            (None, true) => self.connections.inc(),
+            (Some(last), _) => {
+                let elapsed = (hr_status.timestamp - last).to_std().unwrap_or_default();
+                self.connected_seconds.inc_by(elapsed.as_secs_f64());
+            }
+            (None, false) => {}
+        }
+        self.last_connected = connected.then_some(hr_status.timestamp);
+        self.source_connected.set(connected as i64);
+    }
+    fn encode(&self) -> Result<Vec<u8>, AppError> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+pub(super) async fn serve(
+    broadcast_tx: &BSender<AppUpdate>,
+    exporter_settings: PrometheusExporterSettings,
+    cancel_token: CancellationToken,
+) -> Result<(), AppError> {
+    let ip = if exporter_settings.allow_lan {
+        Ipv4Addr::UNSPECIFIED
+    } else {
+        Ipv4Addr::LOCALHOST
+    };
+    let host_addr = SocketAddr::from((ip, exporter_settings.port));
+    let mut exporter = Exporter::build()?;
+    let mut broadcast_rx = broadcast_tx.subscribe();
+    let listener = TcpListener::bind(host_addr).await?;
+    info!("Prometheus exporter listening on http://{host_addr}{METRICS_PATH}");
+    loop {
+        tokio::select! {
+            update = broadcast_rx.recv() => match update {
+                Ok(AppUpdate::HeartRateStatus(hr_status)) => exporter.update(&hr_status),
+                Ok(_) => {}
+                Err(RecvError::Lagged(count)) => {
+                    warn!("Prometheus exporter: Lagged! Missed {count} messages");
+                }
+                Err(RecvError::Closed) => return Ok(()),
+            },
+            result = listener.accept() => {
+                let (mut connection, peer) = match result {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        warn!("Failed to accept Prometheus scrape: {e}");
+                        continue;
+                    }
+                };
+                // Encoded up front, so the scrape can be answered without holding up updates
+                let metrics = exporter.encode()?;
+                tokio::spawn(async move {
+                    if let Err(e) = respond(&mut connection, metrics).await {
+                        debug!("Prometheus scrape from {peer} failed: {e}");
+                    }
+                });
+            }
+            _ = cancel_token.cancelled() => {
+                info!("Shutting down Prometheus exporter thread!");
+                return Ok(());
+            }
+        }
+    }
+}
+
+async fn respond(connection: &mut TcpStream, metrics: Vec<u8>) -> Result<(), AppError> {
+    let mut head = Vec::with_capacity(512);
+    let mut buf = [0; 512];
+    let read_head = async {
+        while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < MAX_REQUEST_HEAD {
+            let read = connection.read(&mut buf).await?;
+            if read == 0 {
+                break;
+            }
+            head.extend_from_slice(&buf[..read]);
+        }
+        Ok::<(), AppError>(())
+    };
+    match tokio::time::timeout(READ_TIMEOUT, read_head).await {
+        Ok(result) => result?,
+        Err(_) => {
+            warn!("Timed out reading Prometheus scrape");
+            return Ok(());
+        }
+    }
+    let request_line = String::from_utf8_lossy(&head);
+    let mut parts = request_line.split_whitespace();
+    let is_metrics = parts.next() == Some("GET")
+        && parts.next().is_some_and(|target| {
+            target
+                .split('?')
+                .next()
+                .unwrap_or_default()
+                .trim_end_matches('/')
+                == METRICS_PATH
+        });
+    let (status, content_type, body) = if is_metrics {
+        (
+            "200 OK",
+            TextEncoder::new().format_type().to_owned(),
+            metrics,
+        )
+    } else {
+        (
+            "404 Not Found",
+            "text/plain".to_owned(),
+            b"Not Found".to_vec(),
+        )
+    };
+    let head = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    connection.write_all(head.as_bytes()).await?;
+    connection.write_all(&body).await?;
+    connection.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(bpm: u16, secs: i64) -> HeartRateStatus {
+        let start = DateTime::parse_from_rfc3339("2024-08-01T20:00:00+00:00").unwrap();
+        HeartRateStatus {
+            heart_rate_bpm: bpm,
+            rr_intervals: vec![Duration::from_millis(800)],
+            battery_level: BatteryLevel::Level(55),
+            timestamp: (start + chrono::Duration::seconds(secs)).with_timezone(&Local),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn counts_connections_and_time() {
+        let mut exporter = Exporter::build().unwrap();
+        exporter.update(&status(70, 0));
+        exporter.update(&status(72, 10));
+        exporter.update(&status(0, 15));
+        exporter.update(&status(0, 20));
+        exporter.update(&status(75, 30));
+        assert_eq!(exporter.bpm.get(), 75);
+        assert_eq!(exporter.rr_seconds.get(), 0.8);
+        assert_eq!(exporter.battery_percent.get(), 55);
+        assert_eq!(exporter.source_connected.get(), 1);
+        assert_eq!(exporter.updates.get(), 5);
+        assert_eq!(exporter.connections.get(), 2);
+        assert_eq!(exporter.connected_seconds.get(), 15.0);
+
+        let text = String::from_utf8(exporter.encode().unwrap()).unwrap();
+        assert!(text.contains("heart_rate_bpm 75"), "{text}");
+        assert!(text.contains("source_connections_total 2"), "{text}");
+    }
+}
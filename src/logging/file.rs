@@ -1,20 +1,28 @@
 use crate::app::AppUpdate;
+use crate::broadcast;
 use crate::errors::AppError;
+use crate::format::NumberFormat;
 use crate::heart_rate::HeartRateStatus;
 use crate::settings::MiscSettings;
 
+use super::raw_capture::RawCapture;
+use super::session_db::SessionDb;
+use super::sessions::{delete_session, list_sessions, stored_session_log_name};
+use super::text_file::TemplateFile;
+
 use csv_async::AsyncSerializer;
 use serde::Serialize;
 use std::path::PathBuf;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::fs::{create_dir, File};
-use tokio::io::{AsyncSeekExt, AsyncWriteExt, BufWriter};
+use tokio::io::{AsyncWriteExt, BufWriter};
 use tokio::sync::broadcast::error::RecvError;
-use tokio::sync::broadcast::Receiver as BReceiver;
+use tokio::sync::broadcast::{Receiver as BReceiver, Sender as BSender};
+use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 use tracing::{error, info, warn};
 
-const CSV_FILE_PREFIX: &str = "nih-";
+pub(super) const CSV_FILE_PREFIX: &str = "nih-";
 
 #[allow(non_snake_case)]
 #[derive(Debug, Serialize)]
@@ -22,18 +30,55 @@ struct CsvData {
     Timestamp: String,
     BPM: u16,
     RR: u16,
+    // Every interval in this update in ms, space separated. RR only has the last
+    RRIntervals: String,
     Battery: u8,
     TwitchUp: u8,
     TwitchDown: u8,
     Activity: u8,
+    // Which device this came from when connected to two at once
+    Source: u8,
+}
+
+impl CsvData {
+    fn new(heart_rate_status: &HeartRateStatus, reported_rr: Duration, activity: u8) -> Self {
+        let rr_intervals: Vec<String> = heart_rate_status
+            .rr_intervals
+            .iter()
+            .map(|rr| rr.as_millis().to_string())
+            .collect();
+        Self {
+            Timestamp: heart_rate_status
+                .timestamp
+                .format("%Y-%m-%d %H:%M:%S%.3f")
+                .to_string(),
+            BPM: heart_rate_status.heart_rate_bpm,
+            RR: reported_rr.as_millis() as u16,
+            RRIntervals: rr_intervals.join(" "),
+            Battery: heart_rate_status.battery_level.into(),
+            TwitchUp: heart_rate_status.twitch_up as u8,
+            TwitchDown: heart_rate_status.twitch_down as u8,
+            Activity: activity,
+            Source: heart_rate_status.source,
+        }
+    }
 }
 
 pub(super) struct FileLoggingActor {
     misc_settings: MiscSettings,
+    broadcast_tx: BSender<AppUpdate>,
     csv_writer: Option<AsyncSerializer<File>>,
     csv_path: Option<PathBuf>,
-    txt_writer: Option<BufWriter<File>>,
-    txt_path: Option<PathBuf>,
+    // When the current CSV (or stored session) was started, for `log_sessions_rotate_minutes`
+    session_started: Option<Instant>,
+    text_file: Option<TemplateFile>,
+    // Only present with `misc.raw_capture`, taken by `rx_loop`
+    raw_rx: Option<mpsc::Receiver<RawCapture>>,
+    raw_writer: Option<BufWriter<File>>,
+    raw_path: Option<PathBuf>,
+    // Only opened with `log_sessions_to_sqlite`, along with the session being logged to
+    session_db: Option<SessionDb>,
+    stored_session: Option<i64>,
     files_initialized: bool,
     // Loop-specific vars
     last_rr: Duration,
@@ -41,13 +86,28 @@ pub(super) struct FileLoggingActor {
 }
 
 impl FileLoggingActor {
-    pub(super) fn new(initial_activity: u8, misc_settings: MiscSettings) -> Self {
+    pub(super) fn new(
+        initial_activity: u8,
+        misc_settings: MiscSettings,
+        number_format: NumberFormat,
+        raw_rx: Option<mpsc::Receiver<RawCapture>>,
+        broadcast_tx: BSender<AppUpdate>,
+    ) -> Self {
+        let text_file = misc_settings
+            .write_bpm_to_file
+            .then(|| TemplateFile::new(&misc_settings, number_format));
         Self {
             misc_settings,
+            broadcast_tx,
             csv_writer: None,
             csv_path: None,
-            txt_writer: None,
-            txt_path: None,
+            session_started: None,
+            text_file,
+            raw_rx,
+            raw_writer: None,
+            raw_path: None,
+            session_db: None,
+            stored_session: None,
             last_rr: Duration::from_secs(0),
             files_initialized: false,
             activity: initial_activity,
@@ -58,8 +118,32 @@ impl FileLoggingActor {
         broadcast_rx: &mut BReceiver<AppUpdate>,
         cancel_token: CancellationToken,
     ) -> Result<(), AppError> {
+        let mut raw_rx = self.raw_rx.take();
+        let mut text_interval = self
+            .text_file
+            .as_ref()
+            .and_then(|text_file| text_file.interval)
+            .map(tokio::time::interval);
         loop {
             tokio::select! {
+                _ = async {
+                    match text_interval.as_mut() {
+                        Some(interval) => interval.tick().await,
+                        None => std::future::pending().await,
+                    }
+                } => {
+                    if let Some(text_file) = self.text_file.as_mut() {
+                        text_file.flush().await?;
+                    }
+                }
+                Some(capture) = async {
+                    match raw_rx.as_mut() {
+                        Some(rx) => rx.recv().await,
+                        None => std::future::pending().await,
+                    }
+                } => {
+                    self.handle_raw(capture).await?;
+                }
                 heart_rate_status = broadcast_rx.recv() => {
                     match heart_rate_status {
                         Ok(AppUpdate::HeartRateStatus(data)) => {
@@ -69,10 +153,15 @@ impl FileLoggingActor {
                             // Dunno if I want to trigger a CSV save here
                             self.activity = index;
                         },
+                        Ok(AppUpdate::ZoneChanged(zone)) => {
+                            if let Some(text_file) = self.text_file.as_mut() {
+                                text_file.set_zone(zone);
+                            }
+                        },
                         Ok(_) => {},
                         Err(RecvError::Closed) => {
                             error!("File Logging: Channel closed");
-                            return Ok(());
+                            return self.end_stored_session();
                         },
                         Err(RecvError::Lagged(count)) => {
                             warn!("File Logging: Lagged! Missed {count} messages");
@@ -81,14 +170,12 @@ impl FileLoggingActor {
                 }
                 _ = cancel_token.cancelled() => {
                     info!("Logging thread shutting down");
-                    return Ok(());
+                    return self.end_stored_session();
                 }
             }
         }
     }
     async fn initialize_files(&mut self) -> Result<(), AppError> {
-        let txt_path = self.misc_settings.bpm_file_path.clone();
-
         let csv_folder = PathBuf::from(self.misc_settings.log_sessions_csv_path.clone());
         let mut csv_file_path = csv_folder.clone();
         let csv_file_name = format!(
@@ -103,35 +190,144 @@ impl FileLoggingActor {
                 create_dir(&csv_folder)
                     .await
                     .map_err(|e| AppError::CreateDir {
-                        path: csv_folder,
+                        path: csv_folder.clone(),
                         source: e,
                     })?;
             }
+            if self.misc_settings.raw_capture {
+                // Same name as the CSV, so they're easy to pair up
+                let raw_path = csv_file_path.with_extension("raw.jsonl");
+                let file = File::create(&raw_path)
+                    .await
+                    .map_err(|e| AppError::CreateFile {
+                        path: raw_path.clone(),
+                        source: e,
+                    })?;
+                self.raw_writer = Some(BufWriter::new(file));
+                self.raw_path = Some(raw_path);
+            }
             self.csv_writer = Some(AsyncSerializer::from_writer(
                 File::create(&csv_file_path)
                     .await
                     .map_err(|e| AppError::CreateFile {
-                        path: csv_file_path,
+                        path: csv_file_path.clone(),
                         source: e,
                     })?,
             ));
+            let file_name = csv_file_path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            info!("Logging session to {}", csv_file_path.display());
+            broadcast!(
+                self.broadcast_tx,
+                AppUpdate::SessionLogging(Some(file_name))
+            );
+            self.csv_path = Some(csv_file_path);
+            self.prune_sessions(&csv_folder);
         }
-        if self.misc_settings.write_bpm_to_file {
-            let file = File::create(&txt_path)
-                .await
-                .map_err(|e| AppError::CreateFile {
-                    path: PathBuf::from(txt_path),
-                    source: e,
-                })?;
-            self.txt_writer = Some(BufWriter::new(file));
+        if self.misc_settings.log_sessions_to_sqlite {
+            self.start_stored_session()?;
         }
+        self.session_started = Some(Instant::now());
         self.files_initialized = true;
         Ok(())
     }
+    // Each write is a small transaction, quick enough to not need a thread of its own
+    fn start_stored_session(&mut self) -> Result<(), AppError> {
+        let session_db = match self.session_db.as_mut() {
+            Some(session_db) => session_db,
+            None => {
+                let path = PathBuf::from(&self.misc_settings.log_sessions_sqlite_path);
+                let session_db = SessionDb::open(&path)?;
+                info!("Logging sessions to {}", path.display());
+                self.session_db.insert(session_db)
+            }
+        };
+        let id = session_db.start_session(chrono::Local::now())?;
+        self.stored_session = Some(id);
+        let keep = self.misc_settings.log_sessions_keep;
+        if keep > 0 {
+            let removed = session_db.prune(keep)?;
+            if removed > 0 {
+                info!("Removed {removed} old stored sessions");
+            }
+        }
+        broadcast!(
+            self.broadcast_tx,
+            AppUpdate::SessionLogging(Some(stored_session_log_name(id)))
+        );
+        Ok(())
+    }
+    fn end_stored_session(&mut self) -> Result<(), AppError> {
+        if let (Some(session_db), Some(id)) = (self.session_db.as_mut(), self.stored_session.take())
+        {
+            session_db.end_session(id, chrono::Local::now())?;
+        }
+        Ok(())
+    }
+    /// Deletes the oldest session CSVs (and their raw captures) past `log_sessions_keep`.
+    fn prune_sessions(&self, csv_folder: &std::path::Path) {
+        let keep = self.misc_settings.log_sessions_keep as usize;
+        if keep == 0 {
+            return;
+        }
+        let sessions = match list_sessions(csv_folder) {
+            Ok(sessions) => sessions,
+            Err(e) => {
+                warn!("Couldn't list old sessions to clean up: {e}");
+                return;
+            }
+        };
+        for old in sessions.into_iter().skip(keep) {
+            info!("Removing old session log {}", old.display());
+            if let Err(e) = delete_session(&old) {
+                warn!("Couldn't remove {}: {e}", old.display());
+            }
+        }
+    }
+    /// Starts new files (and a new stored session) if the current CSV's been going for `log_sessions_rotate_minutes`.
+    fn rotation_due(&self) -> bool {
+        let rotate_minutes = self.misc_settings.log_sessions_rotate_minutes;
+        rotate_minutes > 0
+            && self.session_started.is_some_and(|started| {
+                started.elapsed() >= Duration::from_secs(rotate_minutes as u64 * 60)
+            })
+    }
+    /// Written as it comes in, so it lines up with the CSV next to it.
+    async fn handle_raw(&mut self, capture: RawCapture) -> Result<(), AppError> {
+        if !self.files_initialized {
+            self.initialize_files().await?;
+        }
+        let Some(raw_writer) = self.raw_writer.as_mut() else {
+            return Ok(());
+        };
+        let line = capture.to_json_line()?;
+        let path = self.raw_path.clone().unwrap_or_default();
+        raw_writer
+            .write_all(line.as_bytes())
+            .await
+            .map_err(|e| AppError::WriteFile {
+                path: path.clone(),
+                source: e,
+            })?;
+        raw_writer
+            .flush()
+            .await
+            .map_err(|e| AppError::WriteFile { path, source: e })?;
+        Ok(())
+    }
     async fn handle_data(&mut self, heart_rate_status: HeartRateStatus) -> Result<(), AppError> {
         if heart_rate_status.heart_rate_bpm == 0 {
             return Ok(());
         }
+        if self.rotation_due() {
+            // Everything's flushed after each row, so they can just be dropped
+            self.csv_writer = None;
+            self.raw_writer = None;
+            self.end_stored_session()?;
+            self.files_initialized = false;
+        }
         if !self.files_initialized {
             self.initialize_files().await?;
         }
@@ -141,53 +337,62 @@ impl FileLoggingActor {
             .unwrap_or(&self.last_rr);
 
         if let Some(csv_writer) = &mut self.csv_writer {
-            let timestamp = heart_rate_status.timestamp.format("%Y-%m-%d %H:%M:%S");
-            let csv_data = CsvData {
-                Timestamp: timestamp.to_string(),
-                BPM: heart_rate_status.heart_rate_bpm,
-                RR: reported_rr.as_millis() as u16,
-                Battery: heart_rate_status.battery_level.into(),
-                TwitchUp: heart_rate_status.twitch_up as u8,
-                TwitchDown: heart_rate_status.twitch_down as u8,
-                Activity: self.activity,
-            };
+            let csv_data = CsvData::new(&heart_rate_status, *reported_rr, self.activity);
             csv_writer.serialize(csv_data).await?;
             csv_writer.flush().await.map_err(|e| AppError::WriteFile {
-                path: self.csv_path.as_ref().unwrap().to_owned(),
+                path: self.csv_path.clone().unwrap_or_default(),
                 source: e,
             })?;
         }
-        if let Some(txt_writer) = &mut self.txt_writer {
-            let txt_output = if self.misc_settings.write_rr_to_file {
-                format!(
-                    "{}\n{}\n",
-                    heart_rate_status.heart_rate_bpm,
-                    reported_rr.as_millis()
-                )
-            } else {
-                format!("{}\n", heart_rate_status.heart_rate_bpm)
-            };
-            txt_writer
-                .seek(tokio::io::SeekFrom::Start(0))
-                .await
-                .map_err(|e| AppError::WriteFile {
-                    path: self.txt_path.as_ref().unwrap().to_owned(),
-                    source: e,
-                })?;
-            txt_writer
-                .write_all(txt_output.as_bytes())
-                .await
-                .map_err(|e| AppError::WriteFile {
-                    path: self.txt_path.as_ref().unwrap().to_owned(),
-                    source: e,
-                })?;
-            txt_writer.flush().await.map_err(|e| AppError::WriteFile {
-                path: self.txt_path.as_ref().unwrap().to_owned(),
-                source: e,
-            })?;
+        if let (Some(session_db), Some(id)) = (self.session_db.as_mut(), self.stored_session) {
+            session_db.add_sample(id, &heart_rate_status, self.activity)?;
+        }
+        if let Some(text_file) = self.text_file.as_mut() {
+            text_file.update(&heart_rate_status).await?;
         }
         self.last_rr = *reported_rr;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heart_rate::BatteryLevel;
+    use chrono::{DateTime, Local};
+
+    #[test]
+    fn csv_row_has_every_rr() {
+        let timestamp: DateTime<Local> = DateTime::parse_from_rfc3339("2024-08-01T20:00:00.250Z")
+            .unwrap()
+            .into();
+        let heart_rate_status = HeartRateStatus {
+            heart_rate_bpm: 75,
+            rr_intervals: vec![Duration::from_millis(790), Duration::from_millis(810)],
+            battery_level: BatteryLevel::Level(88),
+            twitch_up: true,
+            source: 1,
+            timestamp,
+            ..Default::default()
+        };
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer
+            .serialize(CsvData::new(
+                &heart_rate_status,
+                Duration::from_millis(810),
+                2,
+            ))
+            .unwrap();
+        let csv = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        let expected_time = timestamp.format("%Y-%m-%d %H:%M:%S%.3f");
+        assert_eq!(
+            csv,
+            format!(
+                "Timestamp,BPM,RR,RRIntervals,Battery,TwitchUp,TwitchDown,Activity,Source\n\
+                 {expected_time},75,810,790 810,88,1,0,2,1\n"
+            )
+        );
+        assert!(expected_time.to_string().ends_with(".250"));
+    }
+}
@@ -0,0 +1,113 @@
+use serde::Serialize;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use tokio::sync::broadcast::Sender as BSender;
+use tokio_util::sync::CancellationToken;
+
+use super::push;
+use crate::app::AppUpdate;
+use crate::errors::AppError;
+use crate::heart_rate::HeartRateStatus;
+use crate::settings::HrtoVrchatSettings;
+
+/// Same names and ranges as the parameters HRtoVRChat_OSC sends,
+/// so overlays written against it can read these as-is.
+#[derive(Debug, Serialize, PartialEq)]
+struct HrtoVrchatPayload {
+    #[serde(rename = "HR")]
+    hr: i32,
+    #[serde(rename = "onesHR")]
+    ones_hr: i32,
+    #[serde(rename = "tensHR")]
+    tens_hr: i32,
+    #[serde(rename = "hundredsHR")]
+    hundreds_hr: i32,
+    #[serde(rename = "isHRConnected")]
+    is_hr_connected: bool,
+    #[serde(rename = "isHRActive")]
+    is_hr_active: bool,
+    // Set when the update came with new RR intervals, i.e. a beat was detected
+    #[serde(rename = "isHRBeat")]
+    is_hr_beat: bool,
+    // 0 to 1 between min_hr and max_hr
+    #[serde(rename = "HRPercent")]
+    hr_percent: f32,
+    // Same, but -1 to 1
+    #[serde(rename = "FullHRPercent")]
+    full_hr_percent: f32,
+}
+
+impl HrtoVrchatPayload {
+    fn new(hr_status: &HeartRateStatus, min_hr: u16, max_hr: u16) -> Self {
+        let hr = hr_status.heart_rate_bpm.min(255) as i32;
+        let range = max_hr.saturating_sub(min_hr).max(1) as f32;
+        let hr_percent =
+            ((hr_status.heart_rate_bpm as f32 - min_hr as f32) / range).clamp(0.0, 1.0);
+        let connected = hr_status.heart_rate_bpm != 0;
+        Self {
+            hr,
+            ones_hr: hr % 10,
+            tens_hr: hr / 10 % 10,
+            hundreds_hr: hr / 100 % 10,
+            is_hr_connected: connected,
+            is_hr_active: connected,
+            is_hr_beat: connected && !hr_status.rr_intervals.is_empty(),
+            hr_percent,
+            full_hr_percent: hr_percent * 2.0 - 1.0,
+        }
+    }
+}
+
+/// Serves HR to any websocket clients that connect, in HRtoVRChat_OSC's format.
+pub(super) async fn serve(
+    broadcast_tx: &BSender<AppUpdate>,
+    hrtovrchat_settings: HrtoVrchatSettings,
+    cancel_token: CancellationToken,
+) -> Result<(), AppError> {
+    let host_addr = SocketAddr::from((Ipv4Addr::LOCALHOST, hrtovrchat_settings.port));
+    let (min_hr, max_hr) = (hrtovrchat_settings.min_hr, hrtovrchat_settings.max_hr);
+    let payload = Arc::new(move |hr_status: &HeartRateStatus| {
+        let payload = HrtoVrchatPayload::new(hr_status, min_hr, max_hr);
+        Ok(serde_json::to_string(&payload)?)
+    });
+    push::serve(
+        broadcast_tx,
+        host_addr,
+        "HRtoVRChat",
+        payload,
+        None,
+        cancel_token,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn payload_digits_and_percent() {
+        let hr_status = HeartRateStatus {
+            heart_rate_bpm: 127,
+            rr_intervals: vec![Duration::from_millis(472)],
+            ..Default::default()
+        };
+        let payload = HrtoVrchatPayload::new(&hr_status, 60, 160);
+        assert_eq!(
+            (payload.ones_hr, payload.tens_hr, payload.hundreds_hr),
+            (7, 2, 1)
+        );
+        assert!(payload.is_hr_beat);
+        assert!((payload.hr_percent - 0.67).abs() < 0.001);
+        assert!((payload.full_hr_percent - 0.34).abs() < 0.001);
+    }
+    #[test]
+    fn disconnected_payload() {
+        let payload = HrtoVrchatPayload::new(&HeartRateStatus::default(), 0, 255);
+        assert_eq!(
+            serde_json::to_string(&payload).unwrap(),
+            r#"{"HR":0,"onesHR":0,"tensHR":0,"hundredsHR":0,"isHRConnected":false,"isHRActive":false,"isHRBeat":false,"HRPercent":0.0,"FullHRPercent":-1.0}"#
+        );
+    }
+}
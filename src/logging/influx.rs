@@ -0,0 +1,256 @@
+// Writes HR to InfluxDB v2 in line protocol, batched so it's one request every few seconds instead of per beat
+// https://docs.influxdata.com/influxdb/v2/write-data/developer-tools/api/
+
+use crate::app::AppUpdate;
+use crate::errors::AppError;
+use crate::heart_rate::{BatteryLevel, HeartRateStatus};
+use crate::settings::InfluxSettings;
+
+use http::{header, HeaderValue, StatusCode};
+use reqwest::{Client, Url};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver as BReceiver;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+// Kept through an outage, past this the oldest points are dropped
+const MAX_PENDING_LINES: usize = 50_000;
+
+/// Commas, spaces and equals signs need escaping in measurement names and tags.
+fn escape(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        if matches!(c, ',' | ' ' | '=') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+pub(super) struct InfluxLoggingActor {
+    measurement: String,
+    // Already escaped, with the leading comma
+    tags: String,
+    write_url: String,
+    flush_interval: Duration,
+    pending: Vec<String>,
+    client: Client,
+}
+
+impl InfluxLoggingActor {
+    pub(super) fn build(settings: InfluxSettings) -> Result<Self, AppError> {
+        if settings.org.is_empty() || settings.bucket.is_empty() {
+            return Err(AppError::InfluxSettingsMissing);
+        }
+        let url = if settings.url.contains("://") {
+            settings.url.trim_end_matches('/').to_owned()
+        } else {
+            format!("http://{}", settings.url.trim_end_matches('/'))
+        };
+        let write_url = Url::parse_with_params(
+            &format!("{url}/api/v2/write"),
+            [
+                ("org", settings.org.as_str()),
+                ("bucket", settings.bucket.as_str()),
+                ("precision", "ms"),
+            ],
+        )
+        .map_err(|e| AppError::InfluxUrl(e.to_string()))?
+        .to_string();
+        let tags = if settings.host_tag.is_empty() {
+            String::new()
+        } else {
+            format!(",host={}", escape(&settings.host_tag))
+        };
+
+        let mut headers = header::HeaderMap::new();
+        if !settings.token.is_empty() {
+            headers.insert(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Token {}", settings.token))?,
+            );
+        }
+        let client = Client::builder()
+            .default_headers(headers)
+            .timeout(Duration::from_secs(10))
+            .build()?;
+
+        Ok(Self {
+            measurement: escape(&settings.measurement),
+            tags,
+            write_url,
+            flush_interval: Duration::from_secs(settings.flush_interval_sec.max(1) as u64),
+            pending: Vec::new(),
+            client,
+        })
+    }
+
+    pub(super) async fn rx_loop(
+        &mut self,
+        broadcast_rx: &mut BReceiver<AppUpdate>,
+        cancel_token: CancellationToken,
+    ) -> Result<(), AppError> {
+        let mut flush = tokio::time::interval(self.flush_interval);
+        flush.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                heart_rate_status = broadcast_rx.recv() => {
+                    match heart_rate_status {
+                        Ok(AppUpdate::HeartRateStatus(data)) => self.handle_data(&data),
+                        Ok(_) => {}
+                        Err(RecvError::Closed) => {
+                            error!("InfluxDB Logging: Channel closed");
+                            return self.flush().await;
+                        }
+                        Err(RecvError::Lagged(count)) => {
+                            warn!("InfluxDB Logging: Lagged! Missed {count} messages");
+                        }
+                    }
+                }
+                _ = flush.tick() => self.flush().await?,
+                _ = cancel_token.cancelled() => {
+                    info!("InfluxDB thread shutting down");
+                    // Whatever's left, so the end of the session isn't lost
+                    return self.flush().await;
+                }
+            }
+        }
+    }
+
+    /// Queues one point for the BPM and battery, and one per RR interval.
+    ///
+    /// RR intervals that arrive together are spread back from the update's timestamp,
+    /// so each beat gets its own point instead of overwriting the last.
+    fn handle_data(&mut self, heart_rate_status: &HeartRateStatus) {
+        if heart_rate_status.heart_rate_bpm == 0 {
+            return;
+        }
+        let timestamp = heart_rate_status.timestamp.timestamp_millis();
+        let mut fields = format!("bpm={}i", heart_rate_status.heart_rate_bpm);
+        if let BatteryLevel::Level(level) = heart_rate_status.battery_level {
+            fields.push_str(&format!(",battery={level}i"));
+        }
+        self.push_line(&fields, timestamp);
+
+        let mut beat_at = timestamp;
+        let mut rr_lines = Vec::with_capacity(heart_rate_status.rr_intervals.len());
+        for rr in heart_rate_status.rr_intervals.iter().rev() {
+            rr_lines.push((format!("rr_ms={}i", rr.as_millis()), beat_at));
+            beat_at -= rr.as_millis() as i64;
+        }
+        for (fields, at) in rr_lines.into_iter().rev() {
+            self.push_line(&fields, at);
+        }
+    }
+
+    fn push_line(&mut self, fields: &str, timestamp_ms: i64) {
+        self.pending.push(format!(
+            "{}{} {fields} {timestamp_ms}",
+            self.measurement, self.tags
+        ));
+        if self.pending.len() > MAX_PENDING_LINES {
+            let overflow = self.pending.len() - MAX_PENDING_LINES;
+            self.pending.drain(..overflow);
+        }
+    }
+
+    async fn flush(&mut self) -> Result<(), AppError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let body = self.pending.join("\n");
+        // Just putting connection errors in the .log and retrying on the next flush,
+        // InfluxDB being down for a bit shouldn't stop the app
+        let response = match self.client.post(&self.write_url).body(body).send().await {
+            Ok(response) => response,
+            Err(e) => {
+                error!("Error writing to InfluxDB, will retry: {e}");
+                return Ok(());
+            }
+        };
+        let status = response.status();
+        match status {
+            status if status.is_success() => {
+                debug!("InfluxDB: Wrote {} lines", self.pending.len());
+                self.pending.clear();
+            }
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                return Err(AppError::InfluxUnauthorized);
+            }
+            status if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS => {
+                warn!("InfluxDB returned {status}, will retry");
+            }
+            status => {
+                // Retrying won't fix a bad bucket name or malformed line
+                let reason = response.text().await.unwrap_or_default();
+                error!(
+                    "InfluxDB rejected {} lines ({status}): {reason}",
+                    self.pending.len()
+                );
+                self.pending.clear();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Local};
+
+    fn actor() -> InfluxLoggingActor {
+        InfluxLoggingActor::build(InfluxSettings {
+            url: "localhost:8086/".into(),
+            org: "my org".into(),
+            bucket: "hr".into(),
+            measurement: "heart rate".into(),
+            host_tag: "desk,pc".into(),
+            flush_interval_sec: 5,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn write_url_and_lines() {
+        let mut actor = actor();
+        assert_eq!(
+            actor.write_url,
+            "http://localhost:8086/api/v2/write?org=my+org&bucket=hr&precision=ms"
+        );
+        let timestamp: DateTime<Local> = DateTime::parse_from_rfc3339("2024-08-01T20:00:00+00:00")
+            .unwrap()
+            .into();
+        actor.handle_data(&HeartRateStatus {
+            heart_rate_bpm: 72,
+            rr_intervals: vec![Duration::from_millis(810), Duration::from_millis(790)],
+            battery_level: BatteryLevel::Level(80),
+            timestamp,
+            ..Default::default()
+        });
+        let ms = timestamp.timestamp_millis();
+        assert_eq!(
+            actor.pending,
+            vec![
+                format!("heart\\ rate,host=desk\\,pc bpm=72i,battery=80i {ms}"),
+                format!("heart\\ rate,host=desk\\,pc rr_ms=810i {}", ms - 790),
+                format!("heart\\ rate,host=desk\\,pc rr_ms=790i {ms}"),
+            ]
+        );
+        actor.handle_data(&HeartRateStatus::default());
+        assert_eq!(actor.pending.len(), 3);
+    }
+
+    #[test]
+    fn needs_org_and_bucket() {
+        let result = InfluxLoggingActor::build(InfluxSettings {
+            url: "localhost:8086".into(),
+            bucket: "hr".into(),
+            ..Default::default()
+        });
+        assert!(matches!(result, Err(AppError::InfluxSettingsMissing)));
+    }
+}
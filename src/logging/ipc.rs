@@ -0,0 +1,184 @@
+// Local IPC output, newline-delimited JSON over a Unix domain socket (or a named pipe on Windows)
+// Same payload as the overlay websocket, just without any networking
+
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::{Receiver as BReceiver, Sender as BSender};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use super::overlay::OverlayPayload;
+use crate::app::AppUpdate;
+use crate::errors::AppError;
+use crate::heart_rate::HeartRateStatus;
+use crate::settings::IpcSettings;
+
+fn line(hr_status: &HeartRateStatus) -> Result<String, AppError> {
+    let mut line = serde_json::to_string(&OverlayPayload::from(hr_status))?;
+    line.push('\n');
+    Ok(line)
+}
+
+/// Writes a line per HR update until the client goes away.
+/// Clients only listen, anything they send is ignored.
+async fn client_loop<W: AsyncWrite + Unpin>(
+    mut client: W,
+    mut broadcast_rx: BReceiver<AppUpdate>,
+    cancel_token: CancellationToken,
+) -> Result<(), AppError> {
+    loop {
+        tokio::select! {
+            update = broadcast_rx.recv() => match update {
+                Ok(AppUpdate::HeartRateStatus(hr_status)) => {
+                    client.write_all(line(&hr_status)?.as_bytes()).await?;
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return Ok(()),
+            },
+            _ = cancel_token.cancelled() => {
+                client.shutdown().await?;
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn spawn_client<W: AsyncWrite + Unpin + Send + 'static>(
+    client: W,
+    broadcast_tx: &BSender<AppUpdate>,
+    cancel_token: &CancellationToken,
+) {
+    debug!("IPC client connected");
+    // Each client gets its own copy of the updates
+    let client_rx = broadcast_tx.subscribe();
+    let client_token = cancel_token.clone();
+    tokio::spawn(async move {
+        if let Err(e) = client_loop(client, client_rx, client_token).await {
+            debug!("IPC client error: {e}");
+        }
+        debug!("IPC client disconnected");
+    });
+}
+
+#[cfg(unix)]
+pub(super) async fn serve(
+    broadcast_tx: &BSender<AppUpdate>,
+    ipc_settings: IpcSettings,
+    cancel_token: CancellationToken,
+) -> Result<(), AppError> {
+    use tokio::net::UnixListener;
+
+    let path = std::path::Path::new(&ipc_settings.path);
+    // Left behind if we didn't get to shut down cleanly last time, binding fails while it's there
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    let listener = UnixListener::bind(path)?;
+    info!("IPC socket listening at {}", path.display());
+    let result = loop {
+        tokio::select! {
+            result = listener.accept() => match result {
+                Ok((client, _)) => spawn_client(client, broadcast_tx, &cancel_token),
+                Err(e) => warn!("Failed to accept IPC client: {e}"),
+            },
+            _ = cancel_token.cancelled() => {
+                info!("Shutting down IPC thread!");
+                break Ok(());
+            }
+        }
+    };
+    let _ = std::fs::remove_file(path);
+    result
+}
+
+#[cfg(windows)]
+pub(super) async fn serve(
+    broadcast_tx: &BSender<AppUpdate>,
+    ipc_settings: IpcSettings,
+    cancel_token: CancellationToken,
+) -> Result<(), AppError> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let path = ipc_settings.path;
+    // Errors if another instance already has the pipe
+    let mut server = ServerOptions::new()
+        .first_pipe_instance(true)
+        .create(&path)?;
+    info!("IPC pipe listening at {path}");
+    loop {
+        tokio::select! {
+            result = server.connect() => {
+                if let Err(e) = result {
+                    warn!("Failed to accept IPC client: {e}");
+                    continue;
+                }
+                // The next client needs a fresh instance to connect to
+                let client = std::mem::replace(&mut server, ServerOptions::new().create(&path)?);
+                spawn_client(client, broadcast_tx, &cancel_token);
+            }
+            _ = cancel_token.cancelled() => {
+                info!("Shutting down IPC thread!");
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+    use tokio::net::UnixStream;
+
+    #[tokio::test]
+    async fn streams_json_lines() {
+        let dir = std::env::temp_dir().join(format!("iron-heart-ipc-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.sock");
+        // A stale socket from a previous run gets replaced
+        std::fs::write(&path, "").unwrap();
+
+        let (broadcast_tx, _broadcast_rx) = tokio::sync::broadcast::channel(16);
+        let cancel_token = CancellationToken::new();
+        let server = tokio::spawn({
+            let broadcast_tx = broadcast_tx.clone();
+            let settings = IpcSettings {
+                enabled: true,
+                path: path.to_string_lossy().into_owned(),
+            };
+            let cancel_token = cancel_token.clone();
+            async move { serve(&broadcast_tx, settings, cancel_token).await }
+        });
+
+        let mut client = None;
+        for _ in 0..50 {
+            if let Ok(stream) = UnixStream::connect(&path).await {
+                client = Some(stream);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let mut lines = BufReader::new(client.expect("couldn't connect")).lines();
+        // Give the server a moment to subscribe the client
+        while broadcast_tx.receiver_count() < 2 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        broadcast_tx
+            .send(AppUpdate::HeartRateStatus(HeartRateStatus {
+                heart_rate_bpm: 72,
+                ..Default::default()
+            }))
+            .unwrap();
+        let line = lines.next_line().await.unwrap().unwrap();
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(json["bpm"], 72);
+        assert_eq!(json["connected"], true);
+
+        cancel_token.cancel();
+        server.await.unwrap().unwrap();
+        assert!(!path.exists());
+        let _ = std::fs::remove_dir(&dir);
+    }
+}
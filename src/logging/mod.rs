@@ -1,30 +1,68 @@
 use crate::app::{AppUpdate, ErrorPopup};
 use crate::broadcast;
 
-use crate::settings::{MiscSettings, PrometheusSettings};
+use crate::format::NumberFormat;
+use crate::settings::{
+    HrtoVrchatSettings, InfluxSettings, IpcSettings, MiscSettings, MqttOutputSettings, ObsSettings,
+    OverlaySettings, PrometheusExporterSettings, PrometheusSettings, StreamerbotSettings,
+    UdpOutputSettings, WebhookSettings,
+};
 
 use file::FileLoggingActor;
+use influx::InfluxLoggingActor;
 use prometheus::PrometheusLoggingActor;
+use raw_capture::RawCapture;
 use tokio::sync::broadcast::{Receiver as BReceiver, Sender as BSender};
+use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 use tracing::{error, info};
+use udp_output::UdpOutputActor;
+use webhook::WebhookActor;
 
+mod exporter;
 mod file;
+mod hrtovrchat;
+mod influx;
+mod ipc;
+mod mqtt;
+mod obs;
+pub mod overlay;
 mod prometheus;
+mod push;
+pub mod raw_capture;
+mod session_db;
+pub mod sessions;
+mod streamerbot;
+pub mod syslog;
+pub mod tcx;
+mod text_file;
+mod udp_output;
+mod webhook;
 
 pub async fn file_logging_thread(
     mut broadcast_rx: BReceiver<AppUpdate>,
     broadcast_tx: BSender<AppUpdate>,
     initial_activity: u8,
     misc_settings: MiscSettings,
+    number_format: NumberFormat,
+    raw_rx: Option<mpsc::Receiver<RawCapture>>,
     cancel_token: CancellationToken,
 ) {
-    if !misc_settings.log_sessions_to_csv && !misc_settings.write_bpm_to_file {
+    if !misc_settings.log_sessions_to_csv
+        && !misc_settings.log_sessions_to_sqlite
+        && !misc_settings.write_bpm_to_file
+    {
         info!("No file logging was enabled! Shutting down thread.");
         return;
     }
 
-    let mut logging = FileLoggingActor::new(initial_activity, misc_settings);
+    let mut logging = FileLoggingActor::new(
+        initial_activity,
+        misc_settings,
+        number_format,
+        raw_rx,
+        broadcast_tx.clone(),
+    );
 
     info!("Logging thread started!");
 
@@ -68,3 +106,175 @@ pub async fn prometheus_logging_thread(
         broadcast!(broadcast_tx, ErrorPopup::detailed(message, e));
     }
 }
+
+pub async fn influx_logging_thread(
+    mut broadcast_rx: BReceiver<AppUpdate>,
+    broadcast_tx: BSender<AppUpdate>,
+    influx_settings: InfluxSettings,
+    cancel_token: CancellationToken,
+) {
+    let mut logging = match InfluxLoggingActor::build(influx_settings) {
+        Ok(influx) => influx,
+        Err(e) => {
+            let message = "Failed to build InfluxDB sender";
+            broadcast!(broadcast_tx, ErrorPopup::detailed(message, e));
+            return;
+        }
+    };
+
+    info!("InfluxDB thread started!");
+
+    if let Err(e) = logging.rx_loop(&mut broadcast_rx, cancel_token).await {
+        error!("InfluxDB error: {e}");
+        let message = "InfluxDB error:";
+        broadcast!(broadcast_tx, ErrorPopup::detailed(message, e));
+    }
+}
+
+pub async fn webhook_thread(
+    mut broadcast_rx: BReceiver<AppUpdate>,
+    broadcast_tx: BSender<AppUpdate>,
+    webhook_settings: WebhookSettings,
+    cancel_token: CancellationToken,
+) {
+    let mut webhook = match WebhookActor::build(webhook_settings) {
+        Ok(webhook) => webhook,
+        Err(e) => {
+            let message = "Failed to build webhook sender";
+            broadcast!(broadcast_tx, ErrorPopup::detailed(message, e));
+            return;
+        }
+    };
+
+    info!("Webhook thread started!");
+
+    if let Err(e) = webhook.rx_loop(&mut broadcast_rx, cancel_token).await {
+        error!("Webhook error: {e}");
+        let message = "Webhook error:";
+        broadcast!(broadcast_tx, ErrorPopup::detailed(message, e));
+    }
+}
+
+pub async fn udp_output_thread(
+    mut broadcast_rx: BReceiver<AppUpdate>,
+    broadcast_tx: BSender<AppUpdate>,
+    udp_output_settings: UdpOutputSettings,
+    cancel_token: CancellationToken,
+) {
+    let result = async {
+        UdpOutputActor::build(&udp_output_settings)
+            .await?
+            .rx_loop(&mut broadcast_rx, cancel_token)
+            .await
+    }
+    .await;
+    if let Err(e) = result {
+        error!("UDP output error: {e}");
+        let message = "UDP output error:";
+        broadcast!(broadcast_tx, ErrorPopup::detailed(message, e));
+    }
+}
+
+pub async fn mqtt_output_thread(
+    mut broadcast_rx: BReceiver<AppUpdate>,
+    broadcast_tx: BSender<AppUpdate>,
+    output_settings: MqttOutputSettings,
+    cancel_token: CancellationToken,
+) {
+    if let Err(e) = mqtt::publish(
+        &mut broadcast_rx,
+        &broadcast_tx,
+        output_settings,
+        cancel_token,
+    )
+    .await
+    {
+        error!("MQTT output error: {e}");
+        let message = "MQTT output error:";
+        broadcast!(broadcast_tx, ErrorPopup::detailed(message, e));
+    }
+}
+
+pub async fn obs_thread(
+    mut broadcast_rx: BReceiver<AppUpdate>,
+    broadcast_tx: BSender<AppUpdate>,
+    obs_settings: ObsSettings,
+    cancel_token: CancellationToken,
+) {
+    if let Err(e) = obs::drive(&mut broadcast_rx, &broadcast_tx, obs_settings, cancel_token).await {
+        error!("OBS error: {e}");
+        let message = "OBS error:";
+        broadcast!(broadcast_tx, ErrorPopup::detailed(message, e));
+    }
+}
+
+pub async fn prometheus_exporter_thread(
+    broadcast_tx: BSender<AppUpdate>,
+    exporter_settings: PrometheusExporterSettings,
+    cancel_token: CancellationToken,
+) {
+    if let Err(e) = exporter::serve(&broadcast_tx, exporter_settings, cancel_token).await {
+        error!("Prometheus exporter error: {e}");
+        let message = "Prometheus exporter error:";
+        broadcast!(broadcast_tx, ErrorPopup::detailed(message, e));
+    }
+}
+
+pub async fn hrtovrchat_thread(
+    broadcast_tx: BSender<AppUpdate>,
+    hrtovrchat_settings: HrtoVrchatSettings,
+    cancel_token: CancellationToken,
+) {
+    if let Err(e) = hrtovrchat::serve(&broadcast_tx, hrtovrchat_settings, cancel_token).await {
+        error!("HRtoVRChat websocket error: {e}");
+        let message = "HRtoVRChat websocket error:";
+        broadcast!(broadcast_tx, ErrorPopup::detailed(message, e));
+    }
+}
+
+pub async fn streamerbot_thread(
+    mut broadcast_rx: BReceiver<AppUpdate>,
+    broadcast_tx: BSender<AppUpdate>,
+    streamerbot_settings: StreamerbotSettings,
+    cancel_token: CancellationToken,
+) {
+    if let Err(e) = streamerbot::drive(
+        &mut broadcast_rx,
+        &broadcast_tx,
+        streamerbot_settings,
+        cancel_token,
+    )
+    .await
+    {
+        error!("Streamer.bot error: {e}");
+        let message = "Streamer.bot error:";
+        broadcast!(broadcast_tx, ErrorPopup::detailed(message, e));
+    }
+}
+
+pub async fn overlay_thread(
+    broadcast_tx: BSender<AppUpdate>,
+    overlay_settings: OverlaySettings,
+    number_format: NumberFormat,
+    cancel_token: CancellationToken,
+) {
+    if let Err(e) =
+        overlay::serve(&broadcast_tx, overlay_settings, number_format, cancel_token).await
+    {
+        error!("Overlay websocket error: {e}");
+        let message = "Overlay websocket error:";
+        broadcast!(broadcast_tx, ErrorPopup::detailed(message, e));
+    }
+}
+
+pub async fn ipc_thread(
+    broadcast_tx: BSender<AppUpdate>,
+    ipc_settings: IpcSettings,
+    cancel_token: CancellationToken,
+) {
+    if let Err(e) = ipc::serve(&broadcast_tx, ipc_settings, cancel_token).await {
+        error!("IPC output error: {e}");
+        let message = "IPC output error:";
+        broadcast!(broadcast_tx, ErrorPopup::detailed(message, e));
+    }
+}
@@ -0,0 +1,313 @@
+// Publishes HR to an MQTT broker, with Home Assistant discovery so it shows up as sensors on its own
+// https://www.home-assistant.io/integrations/mqtt/#mqtt-discovery
+
+use crate::app::AppUpdate;
+use crate::errors::AppError;
+use crate::heart_rate::mqtt::{
+    connect_packet_with_will, decode_packets, packet, publish_packet, refusal_reason, Packet,
+    DISCONNECT, KEEP_ALIVE, PINGREQ,
+};
+use crate::heart_rate::{BatteryLevel, HeartRateStatus};
+use crate::journal::{self, Severity, Subsystem};
+use crate::reconnect::{self, ConnectError, ReconnectingClient};
+use crate::settings::{MqttOutputSettings, MqttSettings};
+
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::{Receiver as BReceiver, Sender as BSender};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+const ONLINE: &str = "online";
+const OFFLINE: &str = "offline";
+
+/// (key, name, unit, device class, icon) of each sensor, the key's also the end of its state topic
+const SENSORS: [(&str, &str, &str, Option<&str>, &str); 3] = [
+    ("bpm", "Heart Rate", "bpm", None, "mdi:heart-pulse"),
+    ("rr", "RR Interval", "ms", None, "mdi:timer-outline"),
+    (
+        "battery",
+        "HRM Battery",
+        "%",
+        Some("battery"),
+        "mdi:battery",
+    ),
+];
+
+struct MqttOutput {
+    settings: MqttOutputSettings,
+    // Only the battery is retained, so it's only sent when it changes
+    last_battery: Option<u8>,
+    buffer: Vec<u8>,
+}
+
+impl MqttOutput {
+    fn new(settings: MqttOutputSettings) -> Self {
+        Self {
+            settings,
+            last_battery: None,
+            buffer: Vec::new(),
+        }
+    }
+    fn topic(&self, key: &str) -> String {
+        format!("{}/{key}", self.settings.base_topic.trim_end_matches('/'))
+    }
+    /// Retained discovery configs, one per sensor.
+    fn discovery_packets(&self) -> Result<Vec<Vec<u8>>, AppError> {
+        // Topics can't have spaces or slashes in IDs, so the base topic is flattened into one
+        let node_id: String = self
+            .settings
+            .base_topic
+            .trim_end_matches('/')
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let mut packets = Vec::new();
+        for (key, name, unit, device_class, icon) in SENSORS {
+            let mut config = json!({
+                "name": name,
+                "unique_id": format!("{node_id}_{key}"),
+                "state_topic": self.topic(key),
+                "availability_topic": self.topic("status"),
+                "unit_of_measurement": unit,
+                "state_class": "measurement",
+                "icon": icon,
+                "device": {
+                    "identifiers": [node_id],
+                    "name": self.settings.device_name,
+                    "manufacturer": "null_iron_heart",
+                    "sw_version": env!("CARGO_PKG_VERSION"),
+                },
+            });
+            if let Some(device_class) = device_class {
+                config["device_class"] = json!(device_class);
+            }
+            let topic = format!(
+                "{}/sensor/{node_id}/{key}/config",
+                self.settings.discovery_prefix.trim_end_matches('/')
+            );
+            packets.push(publish_packet(
+                &topic,
+                serde_json::to_string(&config)?.as_bytes(),
+                true,
+            ));
+        }
+        Ok(packets)
+    }
+    fn state_packets(&mut self, hr_status: &HeartRateStatus) -> Vec<Vec<u8>> {
+        let mut packets = vec![publish_packet(
+            &self.topic("bpm"),
+            hr_status.heart_rate_bpm.to_string().as_bytes(),
+            false,
+        )];
+        if let Some(rr) = hr_status.rr_intervals.last() {
+            packets.push(publish_packet(
+                &self.topic("rr"),
+                rr.as_millis().to_string().as_bytes(),
+                false,
+            ));
+        }
+        if let BatteryLevel::Level(level) = hr_status.battery_level {
+            if self.last_battery != Some(level) {
+                self.last_battery = Some(level);
+                packets.push(publish_packet(
+                    &self.topic("battery"),
+                    level.to_string().as_bytes(),
+                    true,
+                ));
+            }
+        }
+        packets
+    }
+}
+
+impl ReconnectingClient for MqttOutput {
+    type Connection = TcpStream;
+    type Updates = BReceiver<AppUpdate>;
+    fn name(&self) -> &'static str {
+        "MQTT output"
+    }
+    fn subsystem(&self) -> Subsystem {
+        Subsystem::Mqtt
+    }
+    fn target(&self) -> String {
+        format!("{}:{}", self.settings.host, self.settings.port)
+    }
+    async fn connect(&mut self) -> Result<TcpStream, ConnectError> {
+        TcpStream::connect(self.target())
+            .await
+            .map_err(|e| ConnectError::Retry(e.to_string()))
+    }
+    async fn session(
+        &mut self,
+        mut stream: TcpStream,
+        broadcast_rx: &mut BReceiver<AppUpdate>,
+        _broadcast_tx: &BSender<AppUpdate>,
+        cancel_token: &CancellationToken,
+    ) -> Result<Option<String>, AppError> {
+        self.buffer.clear();
+        // Whatever the broker had retained might be from before we lost it
+        self.last_battery = None;
+        let broker = MqttSettings {
+            enabled: true,
+            host: self.settings.host.clone(),
+            port: self.settings.port,
+            topic: String::new(),
+            username: self.settings.username.clone(),
+            password: self.settings.password.clone(),
+            client_id: self.settings.client_id.clone(),
+        };
+        let status_topic = self.topic("status");
+        stream
+            .write_all(&connect_packet_with_will(
+                &broker,
+                Some((&status_topic, OFFLINE)),
+            ))
+            .await?;
+
+        let mut connected = false;
+        let mut ping = tokio::time::interval(KEEP_ALIVE / 2);
+        // First tick is immediate
+        ping.tick().await;
+        let mut read_buf = [0; 1024];
+        loop {
+            tokio::select! {
+                read = stream.read(&mut read_buf) => {
+                    match read {
+                        Ok(0) => return Ok(Some("MQTT broker closed the connection".into())),
+                        Ok(len) => self.buffer.extend(&read_buf[..len]),
+                        Err(e) => return Ok(Some(format!("Error receiving from MQTT broker: {e}"))),
+                    }
+                    let packets = match decode_packets(&mut self.buffer) {
+                        Ok(packets) => packets,
+                        Err(e) => return Ok(Some(e)),
+                    };
+                    for packet in packets {
+                        match packet {
+                            Packet::ConnAck { return_code: 0 } => {
+                                let message = format!("Publishing to MQTT broker at {}", self.target());
+                                info!("{message}");
+                                journal::record(Severity::Info, Subsystem::Mqtt, &message);
+                                if self.settings.discovery {
+                                    for packet in self.discovery_packets()? {
+                                        stream.write_all(&packet).await?;
+                                    }
+                                }
+                                stream
+                                    .write_all(&publish_packet(&status_topic, ONLINE.as_bytes(), true))
+                                    .await?;
+                                connected = true;
+                            }
+                            Packet::ConnAck {
+                                return_code: return_code @ (4 | 5),
+                            } => {
+                                return Err(AppError::MqttRefused(refusal_reason(return_code)));
+                            }
+                            Packet::ConnAck { return_code } => {
+                                return Ok(Some(format!(
+                                    "MQTT broker refused connection: {}",
+                                    refusal_reason(return_code)
+                                )));
+                            }
+                            other => debug!("MQTT output: Ignoring {other:?}"),
+                        }
+                    }
+                }
+                update = broadcast_rx.recv() => match update {
+                    Ok(AppUpdate::HeartRateStatus(hr_status)) if connected => {
+                        for packet in self.state_packets(&hr_status) {
+                            stream.write_all(&packet).await?;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(count)) => {
+                        warn!("MQTT output: Lagged! Missed {count} messages");
+                    }
+                    Err(RecvError::Closed) => return Ok(None),
+                },
+                _ = ping.tick() => {
+                    stream.write_all(&packet(PINGREQ, &[])).await?;
+                }
+                _ = cancel_token.cancelled() => {
+                    // A clean DISCONNECT means the broker won't send the will, so say it ourselves
+                    let _ = stream
+                        .write_all(&publish_packet(&status_topic, OFFLINE.as_bytes(), true))
+                        .await;
+                    let _ = stream.write_all(&packet(DISCONNECT, &[])).await;
+                    return Ok(None);
+                }
+            }
+        }
+    }
+}
+
+pub(super) async fn publish(
+    broadcast_rx: &mut BReceiver<AppUpdate>,
+    broadcast_tx: &BSender<AppUpdate>,
+    output_settings: MqttOutputSettings,
+    cancel_token: CancellationToken,
+) -> Result<(), AppError> {
+    let mut output = MqttOutput::new(output_settings);
+    reconnect::run(&mut output, broadcast_rx, broadcast_tx, &cancel_token).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn output() -> MqttOutput {
+        MqttOutput::new(MqttOutputSettings {
+            base_topic: "iron_heart/".into(),
+            discovery_prefix: "homeassistant".into(),
+            device_name: "Iron Heart".into(),
+            ..Default::default()
+        })
+    }
+    fn topic_and_payload(packet: &[u8]) -> (String, String) {
+        let mut buffer = packet.to_vec();
+        match decode_packets(&mut buffer).unwrap().remove(0) {
+            Packet::Publish { topic, payload, .. } => (topic, String::from_utf8(payload).unwrap()),
+            other => panic!("Not a PUBLISH: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn discovery_configs() {
+        let packets = output().discovery_packets().unwrap();
+        assert_eq!(packets.len(), 3);
+        let (topic, payload) = topic_and_payload(&packets[2]);
+        assert_eq!(topic, "homeassistant/sensor/iron_heart/battery/config");
+        let config: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        assert_eq!(config["state_topic"], "iron_heart/battery");
+        assert_eq!(config["availability_topic"], "iron_heart/status");
+        assert_eq!(config["device_class"], "battery");
+        assert_eq!(config["unique_id"], "iron_heart_battery");
+    }
+    #[test]
+    fn battery_only_when_changed() {
+        let mut output = output();
+        let hr_status = HeartRateStatus {
+            heart_rate_bpm: 72,
+            rr_intervals: vec![Duration::from_millis(833)],
+            battery_level: BatteryLevel::Level(80),
+            ..Default::default()
+        };
+        let published: Vec<_> = output
+            .state_packets(&hr_status)
+            .iter()
+            .map(|packet| topic_and_payload(packet))
+            .collect();
+        assert_eq!(
+            published,
+            vec![
+                ("iron_heart/bpm".into(), "72".into()),
+                ("iron_heart/rr".into(), "833".into()),
+                ("iron_heart/battery".into(), "80".into()),
+            ]
+        );
+        assert_eq!(output.state_packets(&hr_status).len(), 2);
+    }
+}
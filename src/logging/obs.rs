@@ -0,0 +1,387 @@
+// Drives OBS directly over obs-websocket (v5, built into OBS 28+), for streamers who'd rather not add a browser source
+// https://github.com/obsproject/obs-websocket/blob/master/docs/generated/protocol.md
+
+use crate::app::AppUpdate;
+use crate::errors::AppError;
+use crate::heart_rate::{BatteryLevel, HeartRateStatus};
+use crate::journal::Subsystem;
+use crate::reconnect::{self, ConnectError, ReconnectingClient, WsClient};
+use crate::settings::ObsSettings;
+
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::{Receiver as BReceiver, Sender as BSender};
+use tokio_util::sync::CancellationToken;
+use tokio_websockets::Message;
+use tracing::{debug, info, warn};
+
+// What OBS closes with when the password's wrong, no point retrying that
+const AUTHENTICATION_FAILED: u16 = 4009;
+
+// Opcodes
+const HELLO: u8 = 0;
+const IDENTIFY: u8 = 1;
+const IDENTIFIED: u8 = 2;
+const REQUEST: u8 = 6;
+const REQUEST_RESPONSE: u8 = 7;
+
+// Request IDs for the responses we care about
+const SCENE_REQUEST: &str = "scene";
+const SCENE_ITEM_REQUEST: &str = "scene_item";
+
+#[derive(Debug, Deserialize)]
+struct ObsMessage {
+    op: u8,
+    #[serde(default)]
+    d: Value,
+}
+
+/// The text with `{bpm}`, `{rr}` (latest, in ms) and `{battery}` filled in, blank if unknown.
+fn render(template: &str, hr_status: &HeartRateStatus) -> String {
+    let rr = hr_status
+        .rr_intervals
+        .last()
+        .map(|rr| rr.as_millis().to_string())
+        .unwrap_or_default();
+    let battery = match hr_status.battery_level {
+        BatteryLevel::Level(level) => level.to_string(),
+        _ => String::new(),
+    };
+    template
+        .replace("{bpm}", &hr_status.heart_rate_bpm.to_string())
+        .replace("{rr}", &rr)
+        .replace("{battery}", &battery)
+}
+
+// Standard alphabet with padding, unlike Fitbit's PKCE
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+            bits | (byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// base64(sha256(base64(sha256(password + salt)) + challenge)), Streamer.bot uses the same
+pub(super) fn auth_response(password: &str, salt: &str, challenge: &str) -> String {
+    let secret = base64(&Sha256::digest(format!("{password}{salt}").as_bytes()));
+    base64(&Sha256::digest(format!("{secret}{challenge}").as_bytes()))
+}
+
+fn request(request_type: &str, request_id: &str, request_data: Value) -> Message {
+    let request = json!({
+        "op": REQUEST,
+        "d": {
+            "requestType": request_type,
+            "requestId": request_id,
+            "requestData": request_data,
+        }
+    });
+    Message::text(request.to_string())
+}
+
+struct ObsOutput {
+    settings: ObsSettings,
+    // Only sent again when it changes
+    last_text: Option<String>,
+    // Whether there's a reading, which the visibility source follows
+    hr_connected: bool,
+    // Scene the visibility source is being looked up in
+    scene: String,
+    // Looked up once identified, if there's a source to toggle
+    scene_item: Option<(String, i64)>,
+}
+
+impl ObsOutput {
+    fn new(settings: ObsSettings) -> Self {
+        Self {
+            settings,
+            last_text: None,
+            hr_connected: false,
+            scene: String::new(),
+            scene_item: None,
+        }
+    }
+    /// Requests to send for an HR update, if anything changed.
+    fn handle_status(&mut self, hr_status: &HeartRateStatus) -> Vec<Message> {
+        let mut requests = Vec::new();
+        if !self.settings.text_source.is_empty() {
+            let text = render(&self.settings.template, hr_status);
+            if self.last_text.as_ref() != Some(&text) {
+                requests.push(request(
+                    "SetInputSettings",
+                    "text",
+                    json!({
+                        "inputName": self.settings.text_source,
+                        "inputSettings": { "text": text },
+                    }),
+                ));
+                self.last_text = Some(text);
+            }
+        }
+        let hr_connected = hr_status.heart_rate_bpm != 0;
+        if hr_connected != self.hr_connected {
+            self.hr_connected = hr_connected;
+            requests.extend(self.visibility_request());
+        }
+        requests
+    }
+    fn visibility_request(&self) -> Option<Message> {
+        let (scene, id) = self.scene_item.as_ref()?;
+        Some(request(
+            "SetSceneItemEnabled",
+            "visibility",
+            json!({
+                "sceneName": scene,
+                "sceneItemId": id,
+                "sceneItemEnabled": self.hr_connected,
+            }),
+        ))
+    }
+    fn scene_item_request(&mut self, scene: &str) -> Message {
+        self.scene = scene.to_owned();
+        request(
+            "GetSceneItemId",
+            SCENE_ITEM_REQUEST,
+            json!({ "sceneName": scene, "sourceName": self.settings.visibility_source }),
+        )
+    }
+    /// Replies to a message from OBS, if it needs any.
+    fn handle_message(&mut self, message: ObsMessage) -> Result<Vec<Message>, AppError> {
+        match message.op {
+            HELLO => {
+                let mut identify = json!({ "rpcVersion": 1, "eventSubscriptions": 0 });
+                if let Some(auth) = message.d.get("authentication") {
+                    if self.settings.password.is_empty() {
+                        return Err(AppError::ObsPasswordMissing);
+                    }
+                    let salt = auth["salt"].as_str().unwrap_or_default();
+                    let challenge = auth["challenge"].as_str().unwrap_or_default();
+                    identify["authentication"] =
+                        json!(auth_response(&self.settings.password, salt, challenge));
+                }
+                let identify = json!({ "op": IDENTIFY, "d": identify });
+                Ok(vec![Message::text(identify.to_string())])
+            }
+            IDENTIFIED => {
+                info!("OBS: Identified");
+                // Sent fresh, OBS may have been restarted with something else in it
+                self.last_text = None;
+                self.scene_item = None;
+                if self.settings.visibility_source.is_empty() {
+                    return Ok(Vec::new());
+                }
+                if self.settings.visibility_scene.is_empty() {
+                    Ok(vec![request(
+                        "GetCurrentProgramScene",
+                        SCENE_REQUEST,
+                        json!({}),
+                    )])
+                } else {
+                    let scene = self.settings.visibility_scene.clone();
+                    Ok(vec![self.scene_item_request(&scene)])
+                }
+            }
+            REQUEST_RESPONSE => {
+                let request_id = message.d["requestId"].as_str().unwrap_or_default();
+                let status = &message.d["requestStatus"];
+                if status["result"] != json!(true) {
+                    warn!(
+                        "OBS: Request {request_id} failed: {}",
+                        status["comment"].as_str().unwrap_or("no reason given")
+                    );
+                    return Ok(Vec::new());
+                }
+                let data = &message.d["responseData"];
+                match request_id {
+                    SCENE_REQUEST => {
+                        // Renamed in 5.3, older versions only have the first
+                        let scene = data["currentProgramSceneName"]
+                            .as_str()
+                            .or(data["sceneName"].as_str())
+                            .unwrap_or_default()
+                            .to_owned();
+                        Ok(vec![self.scene_item_request(&scene)])
+                    }
+                    SCENE_ITEM_REQUEST => {
+                        let Some(id) = data["sceneItemId"].as_i64() else {
+                            return Ok(Vec::new());
+                        };
+                        debug!(
+                            "OBS: {} is item {id} in {}",
+                            self.settings.visibility_source, self.scene
+                        );
+                        self.scene_item = Some((self.scene.clone(), id));
+                        Ok(self.visibility_request().into_iter().collect())
+                    }
+                    _ => Ok(Vec::new()),
+                }
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+}
+
+impl ReconnectingClient for ObsOutput {
+    type Connection = WsClient;
+    type Updates = BReceiver<AppUpdate>;
+    fn name(&self) -> &'static str {
+        "OBS"
+    }
+    fn subsystem(&self) -> Subsystem {
+        Subsystem::Obs
+    }
+    fn target(&self) -> String {
+        self.settings.url.clone()
+    }
+    async fn connect(&mut self) -> Result<WsClient, ConnectError> {
+        reconnect::connect_websocket(&self.settings.url).await
+    }
+    async fn session(
+        &mut self,
+        mut client: WsClient,
+        broadcast_rx: &mut BReceiver<AppUpdate>,
+        _broadcast_tx: &BSender<AppUpdate>,
+        cancel_token: &CancellationToken,
+    ) -> Result<Option<String>, AppError> {
+        let mut identified = false;
+        loop {
+            let replies = tokio::select! {
+                item = client.next() => match item {
+                    Some(Ok(msg)) if msg.is_close() => {
+                        if msg.as_close().is_some_and(|(code, _)| u16::from(code) == AUTHENTICATION_FAILED) {
+                            return Err(AppError::ObsUnauthorized);
+                        }
+                        return Ok(Some("OBS closed the connection".into()));
+                    }
+                    Some(Ok(msg)) => {
+                        let Some(message) = msg
+                            .as_text()
+                            .and_then(|text| serde_json::from_str::<ObsMessage>(text).ok())
+                        else {
+                            continue;
+                        };
+                        identified |= message.op == IDENTIFIED;
+                        self.handle_message(message)?
+                    }
+                    Some(Err(e)) => return Ok(Some(format!("Error receiving from OBS: {e}"))),
+                    None => return Ok(Some("OBS closed the connection".into())),
+                },
+                update = broadcast_rx.recv() => match update {
+                    Ok(AppUpdate::HeartRateStatus(hr_status)) if identified => {
+                        self.handle_status(&hr_status)
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(count)) => {
+                        warn!("OBS: Lagged! Missed {count} messages");
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return Ok(None),
+                },
+                _ = cancel_token.cancelled() => {
+                    let _ = client.close().await;
+                    return Ok(None);
+                }
+            };
+            for reply in replies {
+                client.send(reply).await?;
+            }
+        }
+    }
+}
+
+pub(super) async fn drive(
+    broadcast_rx: &mut BReceiver<AppUpdate>,
+    broadcast_tx: &BSender<AppUpdate>,
+    obs_settings: ObsSettings,
+    cancel_token: CancellationToken,
+) -> Result<(), AppError> {
+    let mut obs = ObsOutput::new(obs_settings);
+    reconnect::run(&mut obs, broadcast_rx, broadcast_tx, &cancel_token).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sent(messages: &[Message]) -> Vec<Value> {
+        messages
+            .iter()
+            .map(|msg| serde_json::from_str(msg.as_text().unwrap()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn auth_matches_docs() {
+        // Example from the obs-websocket protocol docs
+        assert_eq!(
+            auth_response(
+                "supersecretpassword",
+                "lM1GncleQOaCu9lT1yeUZhFYnqhsLLP1G5lAGo3ixaI=",
+                "+IxH4CnCiqpX1rM9scsNynZzbOe4KhDeYcTNS3PDaeY="
+            ),
+            "1Ct943GAT+6YQUUX47Ia/ncufilbe6+oD6lY+5kaCu4="
+        );
+        assert_eq!(base64(b"a"), "YQ==");
+        assert_eq!(base64(b"ab"), "YWI=");
+    }
+    #[test]
+    fn text_only_when_changed_and_visibility_follows() {
+        let mut obs = ObsOutput::new(ObsSettings {
+            text_source: "HR".into(),
+            template: "{bpm} BPM ({battery}%)".into(),
+            visibility_source: "Heart".into(),
+            visibility_scene: "Live".into(),
+            ..Default::default()
+        });
+        let identified = ObsMessage {
+            op: IDENTIFIED,
+            d: json!({}),
+        };
+        let lookup = sent(&obs.handle_message(identified).unwrap());
+        assert_eq!(lookup[0]["d"]["requestType"], "GetSceneItemId");
+        let found = ObsMessage {
+            op: REQUEST_RESPONSE,
+            d: json!({
+                "requestId": SCENE_ITEM_REQUEST,
+                "requestStatus": { "result": true, "code": 100 },
+                "responseData": { "sceneItemId": 7 },
+            }),
+        };
+        // Hidden until there's a reading
+        let hide = sent(&obs.handle_message(found).unwrap());
+        assert_eq!(hide[0]["d"]["requestData"]["sceneName"], "Live");
+        assert_eq!(hide[0]["d"]["requestData"]["sceneItemEnabled"], false);
+
+        let hr_status = HeartRateStatus {
+            heart_rate_bpm: 72,
+            battery_level: BatteryLevel::Level(50),
+            ..Default::default()
+        };
+        let requests = sent(&obs.handle_status(&hr_status));
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[0]["d"]["requestData"]["inputSettings"]["text"],
+            "72 BPM (50%)"
+        );
+        assert_eq!(requests[1]["d"]["requestData"]["sceneItemId"], 7);
+        assert_eq!(requests[1]["d"]["requestData"]["sceneItemEnabled"], true);
+        assert!(obs.handle_status(&hr_status).is_empty());
+
+        let requests = sent(&obs.handle_status(&HeartRateStatus::default()));
+        assert_eq!(requests[1]["d"]["requestData"]["sceneItemEnabled"], false);
+    }
+}
@@ -0,0 +1,422 @@
+use serde::Serialize;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::{Receiver as BReceiver, Sender as BSender};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use super::push::{self, Page};
+use crate::app::AppUpdate;
+use crate::errors::AppError;
+use crate::format::NumberFormat;
+use crate::heart_rate::{BatteryLevel, HeartRateStatus};
+use crate::settings::OverlaySettings;
+
+const PAGE_PATH: &str = "/overlay";
+const JSON_PATH: &str = "/json";
+// Big BPM number, beating heart and an optional graph, customized through its URL
+const BUILT_IN_PAGE: &str = include_str!("overlay.html");
+
+/// Everything in an HR update, for browser-source overlays to use as-is.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct OverlayPayload {
+    bpm: u16,
+    connected: bool,
+    rr_intervals_ms: Vec<u64>,
+    battery: Option<u8>,
+    twitch_up: bool,
+    twitch_down: bool,
+    stress_index: Option<f32>,
+    rssi: Option<i16>,
+    spo2: Option<f32>,
+    cadence: Option<u16>,
+    // RFC 3339, so `new Date(...)` takes it
+    timestamp: String,
+}
+
+impl From<&HeartRateStatus> for OverlayPayload {
+    fn from(hr_status: &HeartRateStatus) -> Self {
+        Self {
+            bpm: hr_status.heart_rate_bpm,
+            connected: hr_status.heart_rate_bpm != 0,
+            rr_intervals_ms: hr_status
+                .rr_intervals
+                .iter()
+                .map(|rr| rr.as_millis() as u64)
+                .collect(),
+            battery: match hr_status.battery_level {
+                BatteryLevel::Level(level) => Some(level),
+                _ => None,
+            },
+            twitch_up: hr_status.twitch_up,
+            twitch_down: hr_status.twitch_down,
+            stress_index: hr_status.stress_index,
+            rssi: hr_status.rssi,
+            spo2: hr_status.spo2,
+            cadence: hr_status.cadence,
+            timestamp: hr_status.timestamp.to_rfc3339(),
+        }
+    }
+}
+
+/// What the overlay pushes, the payload plus the BPM as `[format]` says to show it.
+#[derive(Debug, Serialize)]
+struct OverlayUpdate {
+    #[serde(flatten)]
+    payload: OverlayPayload,
+    // i.e. "72 bpm", empty while disconnected so pages can show their own placeholder
+    bpm_text: String,
+}
+
+impl OverlayUpdate {
+    fn new(hr_status: &HeartRateStatus, number_format: &NumberFormat) -> Self {
+        let bpm_text = match hr_status.heart_rate_bpm {
+            0 => String::new(),
+            bpm => number_format.bpm(bpm),
+        };
+        Self {
+            payload: OverlayPayload::from(hr_status),
+            bpm_text,
+        }
+    }
+}
+
+/// BPM stats since the source last connected (or a new session CSV was started),
+/// counting only readings while connected.
+#[derive(Debug, Default, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct SessionStats {
+    min_bpm: Option<u16>,
+    max_bpm: Option<u16>,
+    avg_bpm: Option<f32>,
+    #[serde(skip)]
+    total_bpm: u64,
+    #[serde(skip)]
+    readings: u64,
+}
+
+impl SessionStats {
+    fn add(&mut self, bpm: u16) {
+        self.min_bpm = Some(self.min_bpm.map_or(bpm, |min| min.min(bpm)));
+        self.max_bpm = Some(self.max_bpm.map_or(bpm, |max| max.max(bpm)));
+        self.total_bpm += bpm as u64;
+        self.readings += 1;
+        self.avg_bpm = Some(self.total_bpm as f32 / self.readings as f32);
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HeartRateSection {
+    bpm: u16,
+    bpm_text: String,
+    connected: bool,
+    zone: Option<u8>,
+    rr_intervals_ms: Vec<u64>,
+    twitch_up: bool,
+    twitch_down: bool,
+    stress_index: Option<f32>,
+    spo2: Option<f32>,
+    cadence: Option<u16>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct DeviceSection {
+    battery: Option<u8>,
+    rssi: Option<i16>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct PulsoidData {
+    heart_rate: u16,
+}
+
+/// Served at `/json` for overlay templates that poll a document rather than subscribe.
+///
+/// Laid out like gosumemory's (camelCase sections that templates pick fields out of),
+/// with Pulsoid's `measured_at`/`data.heart_rate` alongside for templates written against its API.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonDocument {
+    heart_rate: HeartRateSection,
+    device: DeviceSection,
+    session: SessionStats,
+    // Unix milliseconds, 0 until the first reading
+    #[serde(rename = "measured_at")]
+    measured_at: i64,
+    data: PulsoidData,
+    #[serde(skip)]
+    number_format: NumberFormat,
+}
+
+impl JsonDocument {
+    fn new(number_format: NumberFormat) -> Self {
+        Self {
+            heart_rate: HeartRateSection::default(),
+            device: DeviceSection::default(),
+            session: SessionStats::default(),
+            measured_at: 0,
+            data: PulsoidData::default(),
+            number_format,
+        }
+    }
+    fn update(&mut self, update: &AppUpdate) {
+        match update {
+            AppUpdate::HeartRateStatus(hr_status) => self.update_hr(hr_status),
+            AppUpdate::ZoneChanged(zone) => self.heart_rate.zone = Some(*zone),
+            AppUpdate::SessionLogging(Some(_)) => self.session = SessionStats::default(),
+            _ => {}
+        }
+    }
+    fn update_hr(&mut self, hr_status: &HeartRateStatus) {
+        let bpm = hr_status.heart_rate_bpm;
+        let connected = bpm != 0;
+        if connected {
+            // Starting over with each connection, rather than carrying on from the last one
+            if !self.heart_rate.connected {
+                self.session = SessionStats::default();
+            }
+            self.session.add(bpm);
+        }
+        let OverlayUpdate { payload, bpm_text } =
+            OverlayUpdate::new(hr_status, &self.number_format);
+        self.heart_rate = HeartRateSection {
+            bpm,
+            bpm_text,
+            connected,
+            zone: self.heart_rate.zone,
+            rr_intervals_ms: payload.rr_intervals_ms,
+            twitch_up: payload.twitch_up,
+            twitch_down: payload.twitch_down,
+            stress_index: payload.stress_index,
+            spo2: payload.spo2,
+            cadence: payload.cadence,
+        };
+        self.device = DeviceSection {
+            battery: payload.battery,
+            rssi: payload.rssi,
+        };
+        self.measured_at = hr_status.timestamp.timestamp_millis();
+        self.data.heart_rate = bpm;
+    }
+}
+
+/// Keeps the `/json` document up to date.
+async fn track(
+    document: Arc<Mutex<JsonDocument>>,
+    mut broadcast_rx: BReceiver<AppUpdate>,
+    cancel_token: CancellationToken,
+) {
+    loop {
+        tokio::select! {
+            update = broadcast_rx.recv() => match update {
+                Ok(update) => document
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .update(&update),
+                Err(RecvError::Lagged(count)) => warn!("Overlay: Lagged! Missed {count} messages"),
+                Err(RecvError::Closed) => return,
+            },
+            _ = cancel_token.cancelled() => return,
+        }
+    }
+}
+
+/// Pushes every HR update as JSON to any websocket clients that connect.
+pub(super) async fn serve(
+    broadcast_tx: &BSender<AppUpdate>,
+    overlay_settings: OverlaySettings,
+    number_format: NumberFormat,
+    cancel_token: CancellationToken,
+) -> Result<(), AppError> {
+    let ip = if overlay_settings.allow_lan {
+        Ipv4Addr::UNSPECIFIED
+    } else {
+        Ipv4Addr::LOCALHOST
+    };
+    let host_addr = SocketAddr::from((ip, overlay_settings.port));
+    let document = Arc::new(Mutex::new(JsonDocument::new(number_format.clone())));
+    let payload = Arc::new(move |hr_status: &HeartRateStatus| {
+        Ok(serde_json::to_string(&OverlayUpdate::new(
+            hr_status,
+            &number_format,
+        ))?)
+    });
+    info!("Overlay page at http://{host_addr}{PAGE_PATH}");
+    tokio::spawn(track(
+        document.clone(),
+        broadcast_tx.subscribe(),
+        cancel_token.clone(),
+    ));
+    let custom_page = overlay_settings.custom_page;
+    let pages = Arc::new(move |path: &str| match path {
+        PAGE_PATH => Some(Page {
+            content_type: "text/html; charset=utf-8",
+            body: page(&custom_page),
+        }),
+        JSON_PATH => Some(Page {
+            content_type: "application/json",
+            body: serde_json::to_string(&*document.lock().unwrap_or_else(PoisonError::into_inner))
+                .ok()?,
+        }),
+        _ => None,
+    });
+    push::serve(
+        broadcast_tx,
+        host_addr,
+        "Overlay",
+        payload,
+        Some(pages),
+        cancel_token,
+    )
+    .await
+}
+
+fn page(custom_page: &str) -> String {
+    if custom_page.is_empty() {
+        return BUILT_IN_PAGE.to_owned();
+    }
+    // Read on every request, so edits show up with a refresh of the browser source
+    std::fs::read_to_string(custom_page).unwrap_or_else(|e| {
+        warn!("Failed to read overlay page {custom_page}, serving the built-in one: {e}");
+        BUILT_IN_PAGE.to_owned()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::FormatSettings;
+    use futures_util::StreamExt;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use tokio_websockets::ClientBuilder;
+
+    #[test]
+    fn payload_from_status() {
+        let hr_status = HeartRateStatus {
+            heart_rate_bpm: 72,
+            rr_intervals: vec![Duration::from_millis(833)],
+            battery_level: BatteryLevel::Level(90),
+            ..Default::default()
+        };
+        let payload = OverlayPayload::from(&hr_status);
+        assert!(payload.connected);
+        assert_eq!(payload.rr_intervals_ms, vec![833]);
+        assert_eq!(payload.battery, Some(90));
+
+        let payload = OverlayPayload::from(&HeartRateStatus::default());
+        assert!(!payload.connected);
+        assert_eq!(payload.battery, None);
+    }
+    #[test]
+    fn json_document_tracks_session() {
+        let mut document = JsonDocument::new(NumberFormat::new(&FormatSettings {
+            bpm_suffix: " bpm".into(),
+            ..Default::default()
+        }));
+        let reading = |document: &mut JsonDocument, bpm| {
+            document.update(&AppUpdate::HeartRateStatus(HeartRateStatus {
+                heart_rate_bpm: bpm,
+                battery_level: BatteryLevel::Level(90),
+                ..Default::default()
+            }))
+        };
+        reading(&mut document, 80);
+        reading(&mut document, 100);
+        document.update(&AppUpdate::ZoneChanged(2));
+        let json = serde_json::to_value(&document).unwrap();
+        assert_eq!(json["heartRate"]["bpm"], 100);
+        assert_eq!(json["heartRate"]["bpmText"], "100 bpm");
+        assert_eq!(json["heartRate"]["zone"], 2);
+        assert_eq!(json["device"]["battery"], 90);
+        assert_eq!(json["data"]["heart_rate"], 100);
+        assert!(json["measured_at"].is_i64());
+        assert_eq!(
+            json["session"],
+            serde_json::json!({ "minBpm": 80, "maxBpm": 100, "avgBpm": 90.0 })
+        );
+
+        // Reconnecting starts the stats over
+        reading(&mut document, 0);
+        reading(&mut document, 120);
+        let json = serde_json::to_value(&document).unwrap();
+        assert_eq!(
+            json["session"],
+            serde_json::json!({ "minBpm": 120, "maxBpm": 120, "avgBpm": 120.0 })
+        );
+        // As does a new session CSV
+        document.update(&AppUpdate::SessionLogging(Some("nih.csv".into())));
+        reading(&mut document, 130);
+        assert_eq!(document.session.min_bpm, Some(130));
+    }
+    #[tokio::test]
+    async fn page_and_updates_on_one_port() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let overlay_settings = OverlaySettings {
+            enabled: true,
+            port,
+            ..Default::default()
+        };
+        let (broadcast_tx, _broadcast_rx) = tokio::sync::broadcast::channel(8);
+        let cancel_token = CancellationToken::new();
+        let server_tx = broadcast_tx.clone();
+        let server_cancel = cancel_token.clone();
+        let number_format = NumberFormat::new(&FormatSettings::default());
+        let server = tokio::spawn(async move {
+            serve(&server_tx, overlay_settings, number_format, server_cancel).await
+        });
+        // Let it bind
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let mut request = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        request
+            .write_all(b"GET /overlay?graph=1 HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        request.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        assert!(response.ends_with(BUILT_IN_PAGE));
+
+        let mut request = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        request
+            .write_all(b"GET /json HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        request.read_to_string(&mut response).await.unwrap();
+        assert!(
+            response.contains("Content-Type: application/json"),
+            "{response}"
+        );
+        assert!(
+            response.contains(r#""session":{"minBpm":null"#),
+            "{response}"
+        );
+
+        let uri = format!("ws://127.0.0.1:{port}/");
+        let builder = ClientBuilder::new().uri(&uri).unwrap();
+        let (mut client, _) = builder.connect().await.unwrap();
+        let hr_status = HeartRateStatus {
+            heart_rate_bpm: 88,
+            ..Default::default()
+        };
+        broadcast_tx
+            .send(AppUpdate::HeartRateStatus(hr_status))
+            .unwrap();
+        let message = client.next().await.unwrap().unwrap();
+        let message = message.as_text().unwrap();
+        assert!(message.contains(r#""bpm":88"#), "{message}");
+        assert!(message.contains(r#""bpm_text":"88""#), "{message}");
+
+        cancel_token.cancel();
+        server.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,160 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::{Receiver as BReceiver, Sender as BSender};
+use tokio_util::sync::CancellationToken;
+use tokio_websockets::{Message, ServerBuilder};
+use tracing::{debug, info, warn};
+
+use futures_util::{SinkExt, StreamExt};
+
+use crate::app::AppUpdate;
+use crate::errors::AppError;
+use crate::heart_rate::HeartRateStatus;
+
+/// Turns each update into the text sent to clients.
+pub(super) type PayloadFn = dyn Fn(&HeartRateStatus) -> Result<String, AppError> + Send + Sync;
+/// What to answer a plain HTTP GET of a path with, if there's a page there.
+pub(super) type PageFn = dyn Fn(&str) -> Option<Page> + Send + Sync;
+
+pub(super) struct Page {
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+const PEEK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Pushes every HR update to any websocket clients that connect, as whatever `payload` makes of it.
+/// Clients only listen, anything they send is ignored.
+///
+/// If `pages` is given, plain HTTP GETs on the same port are answered with what it returns.
+pub(super) async fn serve(
+    broadcast_tx: &BSender<AppUpdate>,
+    host_addr: SocketAddr,
+    name: &'static str,
+    payload: Arc<PayloadFn>,
+    pages: Option<Arc<PageFn>>,
+    cancel_token: CancellationToken,
+) -> Result<(), AppError> {
+    let listener = TcpListener::bind(host_addr).await?;
+    info!("{name} websocket listening on ws://{host_addr}");
+    loop {
+        tokio::select! {
+            result = listener.accept() => {
+                let (connection, peer) = match result {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        warn!("Failed to accept {name} client: {e}");
+                        continue;
+                    }
+                };
+                debug!("{name} client connected from {peer}");
+                // Each client gets its own copy of the updates
+                let client_rx = broadcast_tx.subscribe();
+                let client_token = cancel_token.clone();
+                let payload = payload.clone();
+                let pages = pages.clone();
+                tokio::spawn(async move {
+                    let result = match pages {
+                        Some(pages) => client_task(connection, client_rx, payload, &*pages, client_token).await,
+                        None => client_loop(connection, client_rx, payload, client_token).await,
+                    };
+                    if let Err(e) = result {
+                        debug!("{name} client {peer} error: {e}");
+                    }
+                    debug!("{name} client {peer} disconnected");
+                });
+            }
+            _ = cancel_token.cancelled() => {
+                info!("Shutting down {name} websocket thread!");
+                return Ok(());
+            }
+        }
+    }
+}
+
+async fn client_task(
+    mut connection: TcpStream,
+    broadcast_rx: BReceiver<AppUpdate>,
+    payload: Arc<PayloadFn>,
+    pages: &PageFn,
+    cancel_token: CancellationToken,
+) -> Result<(), AppError> {
+    let Some((path, head_len)) = plain_get(&connection).await? else {
+        return client_loop(connection, broadcast_rx, payload, cancel_token).await;
+    };
+    // Read what was peeked at, closing with it unread would reset the connection
+    let mut head = vec![0; head_len];
+    connection.read_exact(&mut head).await?;
+    let (status, content_type, body) = match pages(&path) {
+        Some(page) => ("200 OK", page.content_type, page.body),
+        None => ("404 Not Found", "text/plain", "Not Found".to_owned()),
+    };
+    // Allowing any origin, so overlays loaded from a file or another port can poll JSON pages
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    connection.write_all(response.as_bytes()).await?;
+    connection.shutdown().await?;
+    Ok(())
+}
+
+/// The path and head length of a plain HTTP GET, or None for a websocket handshake (or anything else).
+///
+/// Browsers send the whole request head at once, so peeking at it is enough to tell them apart,
+/// and the handshake still gets to read it all.
+async fn plain_get(connection: &TcpStream) -> Result<Option<(String, usize)>, AppError> {
+    let mut buf = [0; 4096];
+    let Ok(peeked) = tokio::time::timeout(PEEK_TIMEOUT, connection.peek(&mut buf)).await else {
+        return Ok(None);
+    };
+    let peeked = peeked?;
+    let Some(head_end) = buf[..peeked].windows(4).position(|w| w == b"\r\n\r\n") else {
+        return Ok(None);
+    };
+    let head = String::from_utf8_lossy(&buf[..head_end]);
+    let upgrade = head
+        .lines()
+        .any(|line| line.to_ascii_lowercase().starts_with("upgrade:"));
+    let mut parts = head.lines().next().unwrap_or_default().split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some("GET"), Some(target)) if !upgrade => {
+            let path = target.split('?').next().unwrap_or_default();
+            Ok(Some((path.trim_end_matches('/').to_owned(), head_end + 4)))
+        }
+        _ => Ok(None),
+    }
+}
+
+async fn client_loop(
+    connection: TcpStream,
+    mut broadcast_rx: BReceiver<AppUpdate>,
+    payload: Arc<PayloadFn>,
+    cancel_token: CancellationToken,
+) -> Result<(), AppError> {
+    let mut client = ServerBuilder::new().accept(connection).await?;
+    loop {
+        tokio::select! {
+            update = broadcast_rx.recv() => match update {
+                Ok(AppUpdate::HeartRateStatus(hr_status)) => {
+                    client.send(Message::text(payload(&hr_status)?)).await?;
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return Ok(()),
+            },
+            item = client.next() => match item {
+                Some(Ok(msg)) if !msg.is_close() => {}
+                Some(Err(e)) => return Err(e.into()),
+                _ => return Ok(()),
+            },
+            _ = cancel_token.cancelled() => {
+                client.close().await?;
+                return Ok(());
+            }
+        }
+    }
+}
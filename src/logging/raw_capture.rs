@@ -0,0 +1,133 @@
+use std::fmt::Write as _;
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+// Payloads are dropped past this, rather than holding up the HR source
+const QUEUE_SIZE: usize = 256;
+
+// Only set if `misc.raw_capture` (and session logging) is enabled
+static RAW_CAPTURE: OnceLock<mpsc::Sender<RawCapture>> = OnceLock::new();
+
+/// A payload exactly as it came from the HR source, before any parsing.
+#[derive(Debug)]
+pub enum RawPayload {
+    // Heart Rate Measurement notification
+    Ble(Vec<u8>),
+    Websocket(String),
+    // Message published to the MQTT source's topic
+    Mqtt(String),
+}
+
+#[derive(Debug)]
+pub struct RawCapture {
+    timestamp: DateTime<Local>,
+    payload: RawPayload,
+}
+
+/// One line of the `.raw.jsonl` file written next to the session CSV.
+#[derive(Debug, Serialize, PartialEq)]
+struct RawRecord<'a> {
+    timestamp: String,
+    source: &'static str,
+    // Space separated hex for BLE, the message as-is for websockets and MQTT
+    payload: std::borrow::Cow<'a, str>,
+}
+
+impl RawCapture {
+    pub(super) fn to_json_line(&self) -> Result<String, serde_json::Error> {
+        let (source, payload) = match &self.payload {
+            RawPayload::Ble(data) => {
+                let mut hex = String::with_capacity(data.len() * 3);
+                for byte in data {
+                    if !hex.is_empty() {
+                        hex.push(' ');
+                    }
+                    let _ = write!(hex, "{byte:02x}");
+                }
+                ("ble", hex.into())
+            }
+            RawPayload::Websocket(text) => ("websocket", text.as_str().into()),
+            RawPayload::Mqtt(text) => ("mqtt", text.as_str().into()),
+        };
+        let record = RawRecord {
+            timestamp: self.timestamp.to_rfc3339(),
+            source,
+            payload,
+        };
+        let mut line = serde_json::to_string(&record)?;
+        line.push('\n');
+        Ok(line)
+    }
+}
+
+/// Starts queueing payloads for the file logging thread to write out.
+pub fn init() -> Option<mpsc::Receiver<RawCapture>> {
+    let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+    RAW_CAPTURE.set(tx).ok()?;
+    Some(rx)
+}
+
+fn record(payload: RawPayload) {
+    if let Some(tx) = RAW_CAPTURE.get() {
+        let capture = RawCapture {
+            timestamp: Local::now(),
+            payload,
+        };
+        // Full or closed, either way there's nothing better to do with it
+        let _ = tx.try_send(capture);
+    }
+}
+
+/// Checked up front so nothing gets copied while capture is off.
+pub fn record_ble(data: &[u8]) {
+    if RAW_CAPTURE.get().is_some() {
+        record(RawPayload::Ble(data.to_vec()));
+    }
+}
+
+/// Same as `record_ble`, for websocket messages.
+pub fn record_websocket(message: &str) {
+    if RAW_CAPTURE.get().is_some() {
+        record(RawPayload::Websocket(message.to_owned()));
+    }
+}
+
+/// Same as `record_ble`, for MQTT payloads.
+pub fn record_mqtt(payload: &str) {
+    if RAW_CAPTURE.get().is_some() {
+        record(RawPayload::Mqtt(payload.to_owned()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_lines() {
+        let timestamp = DateTime::parse_from_rfc3339("2024-01-01T00:00:00+00:00")
+            .unwrap()
+            .with_timezone(&Local);
+        let ble = RawCapture {
+            timestamp,
+            payload: RawPayload::Ble(vec![0x10, 0x48, 0x00, 0x04]),
+        };
+        let line = ble.to_json_line().unwrap();
+        assert!(line.ends_with("\"source\":\"ble\",\"payload\":\"10 48 00 04\"}\n"));
+        let websocket = RawCapture {
+            timestamp,
+            payload: RawPayload::Websocket(r#"{"bpm":72}"#.into()),
+        };
+        let line = websocket.to_json_line().unwrap();
+        assert!(line.ends_with("\"source\":\"websocket\",\"payload\":\"{\\\"bpm\\\":72}\"}\n"));
+        let mqtt = RawCapture {
+            timestamp,
+            payload: RawPayload::Mqtt("72".into()),
+        };
+        let line = mqtt.to_json_line().unwrap();
+        assert!(line.ends_with("\"source\":\"mqtt\",\"payload\":\"72\"}\n"));
+    }
+}
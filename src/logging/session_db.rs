@@ -0,0 +1,371 @@
+// Sessions in a SQLite database, instead of a CSV per session
+// Only in builds with the "sqlite" feature, otherwise opening it just says so
+#![cfg_attr(not(feature = "sqlite"), allow(dead_code))]
+
+use chrono::{DateTime, Local, TimeZone};
+
+use super::sessions::{SessionCurve, SessionSummary};
+
+/// How a stored session's listed, same style as the CSV names.
+fn session_name(started_at_ms: i64) -> String {
+    Local
+        .timestamp_millis_opt(started_at_ms)
+        .single()
+        .map(|started_at| started_at.format("%Y-%m-%d_%H-%M-%S").to_string())
+        .unwrap_or_else(|| started_at_ms.to_string())
+}
+
+/// Points relative to the first sample, like `read_session` does for CSVs.
+fn curve(name: String, samples: &[(i64, u16)]) -> SessionCurve {
+    let points = match samples.first() {
+        Some(&(start, _)) => samples
+            .iter()
+            .map(|&(timestamp_ms, bpm)| ((timestamp_ms - start) as f64 / 1000.0, bpm as f64))
+            .collect(),
+        None => Vec::new(),
+    };
+    SessionCurve { name, points }
+}
+
+#[cfg(feature = "sqlite")]
+mod store {
+    use rusqlite::{params, Connection};
+    use std::path::Path;
+
+    use super::*;
+    use crate::errors::AppError;
+    use crate::heart_rate::{BatteryLevel, HeartRateStatus};
+
+    // Each one moves the schema up a version (kept in `PRAGMA user_version`), only ever append to this
+    const MIGRATIONS: &[&str] = &["
+        CREATE TABLE sessions (
+            id INTEGER PRIMARY KEY,
+            -- Unix milliseconds
+            started_at INTEGER NOT NULL,
+            -- NULL while it's being logged to, or if the app didn't get to close it
+            ended_at INTEGER
+        );
+        CREATE TABLE samples (
+            id INTEGER PRIMARY KEY,
+            session_id INTEGER NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+            timestamp INTEGER NOT NULL,
+            bpm INTEGER NOT NULL,
+            -- NULL if the device doesn't report it
+            battery INTEGER,
+            twitch_up INTEGER NOT NULL,
+            twitch_down INTEGER NOT NULL,
+            activity INTEGER NOT NULL,
+            source INTEGER NOT NULL
+        );
+        CREATE INDEX samples_by_session ON samples(session_id, timestamp);
+        CREATE TABLE rr_intervals (
+            sample_id INTEGER NOT NULL REFERENCES samples(id) ON DELETE CASCADE,
+            rr_ms INTEGER NOT NULL
+        );
+        CREATE INDEX rr_intervals_by_sample ON rr_intervals(sample_id);
+    "];
+
+    /// A stored session, as the session browser lists it.
+    pub struct StoredSession {
+        pub id: i64,
+        pub name: String,
+    }
+
+    pub struct SessionDb {
+        conn: Connection,
+    }
+
+    impl SessionDb {
+        pub fn open(path: &Path) -> Result<Self, AppError> {
+            Self::from_connection(Connection::open(path)?)
+        }
+        fn from_connection(mut conn: Connection) -> Result<Self, AppError> {
+            // Off by default in SQLite, deleting a session relies on it
+            conn.pragma_update(None, "foreign_keys", true)?;
+            migrate(&mut conn)?;
+            Ok(Self { conn })
+        }
+        pub fn start_session(&mut self, started_at: DateTime<Local>) -> Result<i64, AppError> {
+            self.conn.execute(
+                "INSERT INTO sessions (started_at) VALUES (?1)",
+                params![started_at.timestamp_millis()],
+            )?;
+            Ok(self.conn.last_insert_rowid())
+        }
+        pub fn end_session(&mut self, id: i64, ended_at: DateTime<Local>) -> Result<(), AppError> {
+            self.conn.execute(
+                "UPDATE sessions SET ended_at = ?2 WHERE id = ?1",
+                params![id, ended_at.timestamp_millis()],
+            )?;
+            Ok(())
+        }
+        pub fn add_sample(
+            &mut self,
+            session_id: i64,
+            hr_status: &HeartRateStatus,
+            activity: u8,
+        ) -> Result<(), AppError> {
+            let battery = match hr_status.battery_level {
+                BatteryLevel::Level(level) => Some(level),
+                _ => None,
+            };
+            let tx = self.conn.transaction()?;
+            tx.execute(
+                "INSERT INTO samples (session_id, timestamp, bpm, battery, twitch_up, twitch_down, activity, source)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    session_id,
+                    hr_status.timestamp.timestamp_millis(),
+                    hr_status.heart_rate_bpm,
+                    battery,
+                    hr_status.twitch_up,
+                    hr_status.twitch_down,
+                    activity,
+                    hr_status.source,
+                ],
+            )?;
+            let sample_id = tx.last_insert_rowid();
+            {
+                let mut insert_rr = tx.prepare_cached(
+                    "INSERT INTO rr_intervals (sample_id, rr_ms) VALUES (?1, ?2)",
+                )?;
+                for rr in &hr_status.rr_intervals {
+                    insert_rr.execute(params![sample_id, rr.as_millis() as u64])?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        }
+        /// Newest first.
+        pub fn list(&self) -> Result<Vec<StoredSession>, AppError> {
+            let mut statement = self
+                .conn
+                .prepare("SELECT id, started_at FROM sessions ORDER BY started_at DESC, id DESC")?;
+            let sessions = statement
+                .query_map([], |row| {
+                    Ok(StoredSession {
+                        id: row.get(0)?,
+                        name: session_name(row.get(1)?),
+                    })
+                })?
+                .collect::<Result<_, _>>()?;
+            Ok(sessions)
+        }
+        /// Every session with any samples, worked out by SQLite rather than loading each one.
+        pub fn summaries(&self) -> Result<Vec<(i64, SessionSummary)>, AppError> {
+            let mut statement = self.conn.prepare(
+                "SELECT session_id, MAX(timestamp) - MIN(timestamp), AVG(bpm), MAX(bpm)
+                 FROM samples GROUP BY session_id",
+            )?;
+            let summaries = statement
+                .query_map([], |row| {
+                    let duration_ms: i64 = row.get(1)?;
+                    Ok((
+                        row.get(0)?,
+                        SessionSummary {
+                            minutes: duration_ms as f64 / 60_000.0,
+                            average: row.get(2)?,
+                            peak: row.get(3)?,
+                        },
+                    ))
+                })?
+                .collect::<Result<_, _>>()?;
+            Ok(summaries)
+        }
+        pub fn load(&self, id: i64) -> Result<SessionCurve, AppError> {
+            let started_at: i64 = self.conn.query_row(
+                "SELECT started_at FROM sessions WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )?;
+            let mut statement = self.conn.prepare(
+                "SELECT timestamp, bpm FROM samples WHERE session_id = ?1 ORDER BY timestamp, id",
+            )?;
+            let samples: Vec<(i64, u16)> = statement
+                .query_map(params![id], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<Result<_, _>>()?;
+            Ok(curve(session_name(started_at), &samples))
+        }
+        /// Along with its samples and RR intervals.
+        pub fn delete(&mut self, id: i64) -> Result<(), AppError> {
+            self.conn
+                .execute("DELETE FROM sessions WHERE id = ?1", params![id])?;
+            Ok(())
+        }
+        /// Deletes all but the newest `keep` sessions.
+        pub fn prune(&mut self, keep: u32) -> Result<usize, AppError> {
+            let removed = self.conn.execute(
+                "DELETE FROM sessions WHERE id NOT IN
+                 (SELECT id FROM sessions ORDER BY started_at DESC, id DESC LIMIT ?1)",
+                params![keep],
+            )?;
+            Ok(removed)
+        }
+    }
+
+    fn migrate(conn: &mut Connection) -> Result<(), AppError> {
+        let version: u32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version as usize > MIGRATIONS.len() {
+            return Err(AppError::SessionDbTooNew(version));
+        }
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            let tx = conn.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", index as u32 + 1)?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::time::Duration;
+
+        fn sample(at: &DateTime<Local>, secs: i64, bpm: u16, rr_ms: &[u64]) -> HeartRateStatus {
+            HeartRateStatus {
+                heart_rate_bpm: bpm,
+                rr_intervals: rr_ms.iter().map(|&ms| Duration::from_millis(ms)).collect(),
+                timestamp: *at + chrono::Duration::seconds(secs),
+                ..Default::default()
+            }
+        }
+        fn count(db: &SessionDb, table: &str) -> i64 {
+            db.conn
+                .query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| {
+                    row.get(0)
+                })
+                .unwrap()
+        }
+
+        #[test]
+        fn migrates_once() {
+            let db = SessionDb::from_connection(Connection::open_in_memory().unwrap()).unwrap();
+            let version: u32 = db
+                .conn
+                .pragma_query_value(None, "user_version", |row| row.get(0))
+                .unwrap();
+            assert_eq!(version as usize, MIGRATIONS.len());
+            // Already up to date, so nothing's run again
+            let db = SessionDb::from_connection(db.conn).unwrap();
+            db.conn
+                .pragma_update(None, "user_version", MIGRATIONS.len() as u32 + 1)
+                .unwrap();
+            assert!(matches!(
+                SessionDb::from_connection(db.conn),
+                Err(AppError::SessionDbTooNew(_))
+            ));
+        }
+        #[test]
+        fn sessions_and_summaries() {
+            let mut db = SessionDb::from_connection(Connection::open_in_memory().unwrap()).unwrap();
+            let start = Local::now();
+            let first = db.start_session(start).unwrap();
+            db.add_sample(first, &sample(&start, 0, 80, &[750]), 0)
+                .unwrap();
+            db.add_sample(first, &sample(&start, 60, 120, &[500, 498]), 0)
+                .unwrap();
+            db.end_session(first, start + chrono::Duration::seconds(61))
+                .unwrap();
+            let second = db
+                .start_session(start + chrono::Duration::seconds(120))
+                .unwrap();
+
+            let sessions = db.list().unwrap();
+            assert_eq!(
+                sessions.iter().map(|s| s.id).collect::<Vec<_>>(),
+                [second, first]
+            );
+            let summaries = db.summaries().unwrap();
+            assert_eq!(summaries.len(), 1);
+            let (id, summary) = &summaries[0];
+            assert_eq!(*id, first);
+            assert_eq!(summary.minutes, 1.0);
+            assert_eq!(summary.average, 100.0);
+            assert_eq!(summary.peak, 120.0);
+            let curve = db.load(first).unwrap();
+            assert_eq!(curve.points, vec![(0.0, 80.0), (60.0, 120.0)]);
+            assert_eq!(count(&db, "rr_intervals"), 3);
+
+            // Samples and their RR intervals go with it
+            db.delete(first).unwrap();
+            assert_eq!(count(&db, "samples"), 0);
+            assert_eq!(count(&db, "rr_intervals"), 0);
+            assert!(db.load(first).is_err());
+        }
+        #[test]
+        fn prunes_oldest() {
+            let mut db = SessionDb::from_connection(Connection::open_in_memory().unwrap()).unwrap();
+            let start = Local::now();
+            let ids: Vec<i64> = (0..3)
+                .map(|i| {
+                    db.start_session(start + chrono::Duration::minutes(i))
+                        .unwrap()
+                })
+                .collect();
+            assert_eq!(db.prune(2).unwrap(), 1);
+            assert_eq!(
+                db.list().unwrap().iter().map(|s| s.id).collect::<Vec<_>>(),
+                [ids[2], ids[1]]
+            );
+        }
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+mod store {
+    use std::path::Path;
+
+    use super::*;
+    use crate::errors::AppError;
+    use crate::heart_rate::HeartRateStatus;
+
+    pub struct StoredSession {
+        pub id: i64,
+        pub name: String,
+    }
+
+    /// Can't be opened without the feature, so none of the rest is reachable.
+    pub enum SessionDb {}
+
+    impl SessionDb {
+        pub fn open(_path: &Path) -> Result<Self, AppError> {
+            Err(AppError::NoSqlite)
+        }
+        pub fn start_session(&mut self, _started_at: DateTime<Local>) -> Result<i64, AppError> {
+            match *self {}
+        }
+        pub fn end_session(
+            &mut self,
+            _id: i64,
+            _ended_at: DateTime<Local>,
+        ) -> Result<(), AppError> {
+            match *self {}
+        }
+        pub fn add_sample(
+            &mut self,
+            _session_id: i64,
+            _hr_status: &HeartRateStatus,
+            _activity: u8,
+        ) -> Result<(), AppError> {
+            match *self {}
+        }
+        pub fn list(&self) -> Result<Vec<StoredSession>, AppError> {
+            match *self {}
+        }
+        pub fn summaries(&self) -> Result<Vec<(i64, SessionSummary)>, AppError> {
+            match *self {}
+        }
+        pub fn load(&self, _id: i64) -> Result<SessionCurve, AppError> {
+            match *self {}
+        }
+        pub fn delete(&mut self, _id: i64) -> Result<(), AppError> {
+            match *self {}
+        }
+        pub fn prune(&mut self, _keep: u32) -> Result<usize, AppError> {
+            match *self {}
+        }
+    }
+}
+
+pub use store::SessionDb;
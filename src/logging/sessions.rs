@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDateTime;
+use serde::Deserialize;
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::warn;
+
+use crate::errors::AppError;
+use crate::settings::MiscSettings;
+
+use super::file::CSV_FILE_PREFIX;
+use super::session_db::SessionDb;
+
+// Both sessions get resampled to this many points before comparing
+const COMPARE_POINTS: usize = 200;
+
+#[allow(non_snake_case)]
+#[derive(Debug, Deserialize)]
+struct CsvRow {
+    Timestamp: String,
+    BPM: u16,
+}
+
+/// BPM over a logged session, as (seconds since the first row, BPM).
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionCurve {
+    pub name: String,
+    pub points: Vec<(f64, f64)>,
+}
+
+impl SessionCurve {
+    pub fn duration_secs(&self) -> f64 {
+        self.points
+            .last()
+            .map(|(secs, _)| *secs)
+            .unwrap_or_default()
+    }
+    pub fn average(&self) -> f64 {
+        if self.points.is_empty() {
+            return 0.0;
+        }
+        self.points.iter().map(|(_, bpm)| bpm).sum::<f64>() / self.points.len() as f64
+    }
+    pub fn peak(&self) -> f64 {
+        self.points.iter().map(|(_, bpm)| *bpm).fold(0.0, f64::max)
+    }
+    /// Same curve with time as a percentage (0-100) of the session's length.
+    pub fn normalized(&self) -> Vec<(f64, f64)> {
+        let duration = self.duration_secs();
+        if duration <= 0.0 {
+            return self.points.iter().map(|(_, bpm)| (0.0, *bpm)).collect();
+        }
+        self.points
+            .iter()
+            .map(|(secs, bpm)| (secs / duration * 100.0, *bpm))
+            .collect()
+    }
+    /// Linearly interpolated BPM at `progress` (0.0-1.0) through the session.
+    fn bpm_at(&self, progress: f64) -> Option<f64> {
+        let target = progress * self.duration_secs();
+        let after = self.points.iter().position(|(secs, _)| *secs >= target)?;
+        let (after_secs, after_bpm) = self.points[after];
+        let Some(&(before_secs, before_bpm)) = after.checked_sub(1).map(|i| &self.points[i]) else {
+            return Some(after_bpm);
+        };
+        if after_secs <= before_secs {
+            return Some(after_bpm);
+        }
+        let t = (target - before_secs) / (after_secs - before_secs);
+        Some(before_bpm + (after_bpm - before_bpm) * t)
+    }
+}
+
+/// Length, average and peak of a session, for the session browser's list.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SessionSummary {
+    pub minutes: f64,
+    pub average: f64,
+    pub peak: f64,
+}
+
+impl From<&SessionCurve> for SessionSummary {
+    fn from(curve: &SessionCurve) -> Self {
+        Self {
+            minutes: curve.duration_secs() / 60.0,
+            average: curve.average(),
+            peak: curve.peak(),
+        }
+    }
+}
+
+/// Where logged sessions are kept, the CSV folder or the SQLite database.
+#[derive(Debug, Clone)]
+pub enum SessionStore {
+    Csv(PathBuf),
+    Sqlite(PathBuf),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SessionId {
+    Csv(PathBuf),
+    Sqlite(i64),
+}
+
+/// A logged session, as the session browser lists it.
+#[derive(Debug, Clone)]
+pub struct SessionEntry {
+    pub id: SessionId,
+    pub name: String,
+    // What `AppUpdate::SessionLogging` calls it while it's being logged to
+    pub log_name: String,
+}
+
+/// What `AppUpdate::SessionLogging` calls a session being logged to the database.
+pub fn stored_session_log_name(id: i64) -> String {
+    format!("#{id}")
+}
+
+impl SessionStore {
+    pub fn new(misc_settings: &MiscSettings) -> Self {
+        if misc_settings.log_sessions_to_sqlite {
+            Self::Sqlite(PathBuf::from(&misc_settings.log_sessions_sqlite_path))
+        } else {
+            Self::Csv(PathBuf::from(&misc_settings.log_sessions_csv_path))
+        }
+    }
+    /// Newest first.
+    pub fn list(&self) -> Result<Vec<SessionEntry>, AppError> {
+        match self {
+            Self::Csv(folder) => Ok(list_sessions(folder)?
+                .into_iter()
+                .map(|path| SessionEntry {
+                    name: session_name(&path),
+                    log_name: path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                    id: SessionId::Csv(path),
+                })
+                .collect()),
+            Self::Sqlite(path) => Ok(SessionDb::open(path)?
+                .list()?
+                .into_iter()
+                .map(|session| SessionEntry {
+                    name: session.name,
+                    log_name: stored_session_log_name(session.id),
+                    id: SessionId::Sqlite(session.id),
+                })
+                .collect()),
+        }
+    }
+    pub fn load(&self, id: &SessionId) -> Result<SessionCurve, AppError> {
+        match (self, id) {
+            (Self::Sqlite(path), SessionId::Sqlite(id)) => SessionDb::open(path)?.load(*id),
+            (_, SessionId::Csv(path)) => load_session(path),
+            (Self::Csv(_), SessionId::Sqlite(_)) => Err(AppError::NoSqlite),
+        }
+    }
+    pub fn delete(&self, id: &SessionId) -> Result<(), AppError> {
+        match (self, id) {
+            (Self::Sqlite(path), SessionId::Sqlite(id)) => SessionDb::open(path)?.delete(*id),
+            (_, SessionId::Csv(path)) => delete_session(path),
+            (Self::Csv(_), SessionId::Sqlite(_)) => Err(AppError::NoSqlite),
+        }
+    }
+    /// Sends each session's summary as it's worked out (None if it couldn't be read or has no readings).
+    ///
+    /// Reads every CSV in full, so it's meant for a blocking task rather than the UI thread.
+    pub fn load_summaries(
+        &self,
+        ids: Vec<SessionId>,
+        summary_tx: UnboundedSender<(SessionId, Option<SessionSummary>)>,
+    ) {
+        if let Self::Sqlite(path) = self {
+            // One query for all of them, rather than loading each session
+            let summaries = SessionDb::open(path).and_then(|db| db.summaries());
+            let mut summaries: HashMap<i64, SessionSummary> = match summaries {
+                Ok(summaries) => summaries.into_iter().collect(),
+                Err(e) => {
+                    warn!("Couldn't summarize stored sessions: {e}");
+                    HashMap::new()
+                }
+            };
+            for id in ids {
+                let summary = match &id {
+                    SessionId::Sqlite(session) => summaries.remove(session),
+                    SessionId::Csv(_) => None,
+                };
+                if summary_tx.send((id, summary)).is_err() {
+                    return;
+                }
+            }
+            return;
+        }
+        for id in ids {
+            let summary = self
+                .load(&id)
+                .ok()
+                .filter(|curve| !curve.points.is_empty())
+                .map(|curve| SessionSummary::from(&curve));
+            // The browser's been closed
+            if summary_tx.send((id, summary)).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// How the second session differs from the first, point by point over normalized time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompareStats {
+    // Second minus first, so positive means the second session ran higher
+    pub mean_delta: f64,
+    pub mean_abs_delta: f64,
+    // Biggest difference either way, and where it was (percent through)
+    pub max_delta: f64,
+    pub max_delta_at: f64,
+}
+
+pub fn compare(first: &SessionCurve, second: &SessionCurve) -> Option<CompareStats> {
+    let deltas: Vec<(f64, f64)> = (0..COMPARE_POINTS)
+        .filter_map(|i| {
+            let progress = i as f64 / (COMPARE_POINTS - 1) as f64;
+            let delta = second.bpm_at(progress)? - first.bpm_at(progress)?;
+            Some((progress * 100.0, delta))
+        })
+        .collect();
+    if deltas.is_empty() {
+        return None;
+    }
+    let count = deltas.len() as f64;
+    let (max_delta_at, max_delta) = deltas
+        .iter()
+        .copied()
+        .max_by(|(_, a), (_, b)| a.abs().total_cmp(&b.abs()))?;
+    Some(CompareStats {
+        mean_delta: deltas.iter().map(|(_, delta)| delta).sum::<f64>() / count,
+        mean_abs_delta: deltas.iter().map(|(_, delta)| delta.abs()).sum::<f64>() / count,
+        max_delta,
+        max_delta_at,
+    })
+}
+
+/// Session CSVs in the log folder, newest first.
+pub fn list_sessions(folder: &Path) -> Result<Vec<PathBuf>, AppError> {
+    let mut sessions: Vec<PathBuf> = std::fs::read_dir(folder)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension().is_some_and(|ext| ext == "csv")
+                && path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with(CSV_FILE_PREFIX))
+        })
+        .collect();
+    // Names are timestamped, so this sorts by date
+    sessions.sort_unstable_by(|a, b| b.cmp(a));
+    Ok(sessions)
+}
+
+/// Deletes a session CSV, along with its raw capture if there is one.
+pub fn delete_session(path: &Path) -> Result<(), AppError> {
+    std::fs::remove_file(path)?;
+    let raw = path.with_extension("raw.jsonl");
+    if raw.exists() {
+        std::fs::remove_file(raw)?;
+    }
+    Ok(())
+}
+
+pub fn session_name(path: &Path) -> String {
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .map(|stem| stem.trim_start_matches(CSV_FILE_PREFIX).to_owned())
+        .unwrap_or_default()
+}
+
+pub fn load_session(path: &Path) -> Result<SessionCurve, AppError> {
+    let file = std::fs::File::open(path)?;
+    read_session(session_name(path), file)
+}
+
+/// (local time, BPM) of each row in a session CSV.
+pub(super) fn read_rows(reader: impl std::io::Read) -> Result<Vec<(NaiveDateTime, u16)>, AppError> {
+    let mut rows = Vec::new();
+    for row in csv::Reader::from_reader(reader).deserialize() {
+        let row: CsvRow = row?;
+        // Rows from an older/foreign format are skipped rather than failing the whole file
+        // Older logs don't have milliseconds
+        let Ok(timestamp) = NaiveDateTime::parse_from_str(&row.Timestamp, "%Y-%m-%d %H:%M:%S%.f")
+        else {
+            continue;
+        };
+        rows.push((timestamp, row.BPM));
+    }
+    Ok(rows)
+}
+
+fn read_session(name: String, reader: impl std::io::Read) -> Result<SessionCurve, AppError> {
+    let rows = read_rows(reader)?;
+    let points = match rows.first() {
+        Some(&(start, _)) => rows
+            .iter()
+            .map(|(timestamp, bpm)| {
+                let secs = (*timestamp - start).num_milliseconds() as f64 / 1000.0;
+                (secs, *bpm as f64)
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    Ok(SessionCurve { name, points })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn curve(points: &[(f64, f64)]) -> SessionCurve {
+        SessionCurve {
+            name: String::new(),
+            points: points.to_vec(),
+        }
+    }
+
+    #[test]
+    fn reads_session_csv() {
+        let csv = "Timestamp,BPM,RR,Battery,TwitchUp,TwitchDown,Activity\n\
+                   2024-01-01 10:00:00,80,750,90,0,0,0\n\
+                   2024-01-01 10:00:01,82,731,90,0,0,0\n\
+                   2024-01-01 10:01:00,120,500,90,1,0,0\n\
+                   2024-01-01 10:01:00.500,121,496,90,0,0,0\n";
+        let session = read_session("test".into(), csv.as_bytes()).unwrap();
+        assert_eq!(
+            session.points,
+            vec![(0.0, 80.0), (1.0, 82.0), (60.0, 120.0), (60.5, 121.0)]
+        );
+        assert_eq!(session.duration_secs(), 60.5);
+        assert_eq!(session.peak(), 121.0);
+    }
+    #[test]
+    fn compares_over_normalized_time() {
+        // Same shape, but the second one took twice as long and ran 10 BPM higher
+        let first = curve(&[(0.0, 80.0), (30.0, 140.0), (60.0, 100.0)]);
+        let second = curve(&[(0.0, 90.0), (60.0, 150.0), (120.0, 110.0)]);
+        let stats = compare(&first, &second).unwrap();
+        assert!((stats.mean_delta - 10.0).abs() < 0.001);
+        assert!((stats.mean_abs_delta - 10.0).abs() < 0.001);
+        assert_eq!(first.normalized()[1], (50.0, 140.0));
+        assert_eq!(second.normalized()[1], (50.0, 150.0));
+
+        let flat = curve(&[(0.0, 100.0), (60.0, 100.0)]);
+        let stats = compare(&flat, &first).unwrap();
+        assert!((stats.max_delta - 40.0).abs() < 1.0);
+        assert!((stats.max_delta_at - 50.0).abs() < 1.0);
+        assert_eq!(compare(&flat, &curve(&[])), None);
+    }
+}
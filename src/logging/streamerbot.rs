@@ -0,0 +1,327 @@
+// Runs a Streamer.bot action over its WebSocket server when the heart rate crosses a threshold (or changes zone),
+// so existing stream automation (sounds, chat messages, scene changes) can react to it
+// https://docs.streamer.bot/api/websocket
+
+use crate::app::AppUpdate;
+use crate::errors::AppError;
+use crate::heart_rate::{BatteryLevel, HeartRateStatus};
+use crate::journal::Subsystem;
+use crate::reconnect::{self, ConnectError, ReconnectingClient, WsClient};
+use crate::settings::StreamerbotSettings;
+
+use super::obs::auth_response;
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::{Receiver as BReceiver, Sender as BSender};
+use tokio_util::sync::CancellationToken;
+use tokio_websockets::Message;
+use tracing::{debug, info, warn};
+
+// Request IDs for the responses we care about
+const AUTH_REQUEST: &str = "auth";
+const ACTION_REQUEST: &str = "action";
+
+struct StreamerbotOutput {
+    settings: StreamerbotSettings,
+    // Latest nonzero BPM, None while disconnected so reconnecting doesn't count as crossing anything
+    last_bpm: Option<u16>,
+    last_status: HeartRateStatus,
+    zone: Option<u8>,
+    // Actions are only sent once Streamer.bot's happy with us
+    ready: bool,
+}
+
+impl StreamerbotOutput {
+    fn build(settings: StreamerbotSettings) -> Result<Self, AppError> {
+        if settings.action.is_empty() {
+            return Err(AppError::StreamerbotActionMissing);
+        }
+        Ok(Self {
+            settings,
+            last_bpm: None,
+            last_status: HeartRateStatus::default(),
+            zone: None,
+            ready: false,
+        })
+    }
+    /// A DoAction request, with the reading as arguments (`%bpm%` etc. in the action).
+    fn action(&self, event: &str, threshold: Option<u16>) -> Message {
+        let hr_status = &self.last_status;
+        let mut args = json!({
+            "event": event,
+            "bpm": hr_status.heart_rate_bpm,
+            "rr": hr_status.rr_intervals.last().map(|rr| rr.as_millis() as u64),
+            "battery": match hr_status.battery_level {
+                BatteryLevel::Level(level) => Some(level),
+                _ => None,
+            },
+            "zone": self.zone,
+        });
+        if let Some(threshold) = threshold {
+            args["threshold"] = json!(threshold);
+        }
+        let request = json!({
+            "request": "DoAction",
+            "id": ACTION_REQUEST,
+            "action": { "name": self.settings.action },
+            "args": args,
+        });
+        Message::text(request.to_string())
+    }
+    /// Actions for the thresholds this update crossed, lowest first going up and highest first going down.
+    fn handle_status(&mut self, hr_status: HeartRateStatus) -> Vec<Message> {
+        let bpm = hr_status.heart_rate_bpm;
+        self.last_status = hr_status;
+        let previous = std::mem::replace(&mut self.last_bpm, (bpm > 0).then_some(bpm));
+        let Some(previous) = previous.filter(|_| bpm > 0) else {
+            return Vec::new();
+        };
+        let mut thresholds = self.settings.thresholds.clone();
+        thresholds.sort_unstable();
+        if bpm < previous {
+            thresholds.reverse();
+        }
+        thresholds
+            .into_iter()
+            .filter_map(|threshold| {
+                if previous < threshold && bpm >= threshold {
+                    Some(self.action("above", Some(threshold)))
+                } else if previous >= threshold && bpm < threshold {
+                    Some(self.action("below", Some(threshold)))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+    fn handle_zone(&mut self, zone: u8) -> Vec<Message> {
+        let previous = self.zone.replace(zone);
+        // Not worth an action for the first reading
+        if self.settings.on_zone && previous.is_some() {
+            vec![self.action("zone", None)]
+        } else {
+            Vec::new()
+        }
+    }
+    /// Replies to a message from Streamer.bot, if it needs any.
+    fn handle_message(&mut self, message: Value) -> Result<Vec<Message>, AppError> {
+        if message["request"] == "Hello" {
+            let Some(auth) = message.get("authentication") else {
+                info!("Streamer.bot: Connected");
+                self.ready = true;
+                return Ok(Vec::new());
+            };
+            if self.settings.password.is_empty() {
+                return Err(AppError::StreamerbotPasswordMissing);
+            }
+            let salt = auth["salt"].as_str().unwrap_or_default();
+            let challenge = auth["challenge"].as_str().unwrap_or_default();
+            let request = json!({
+                "request": "Authenticate",
+                "id": AUTH_REQUEST,
+                "authentication": auth_response(&self.settings.password, salt, challenge),
+            });
+            return Ok(vec![Message::text(request.to_string())]);
+        }
+        let failed = message["status"] == "error";
+        match message["id"].as_str() {
+            Some(AUTH_REQUEST) if failed => Err(AppError::StreamerbotUnauthorized),
+            Some(AUTH_REQUEST) => {
+                info!("Streamer.bot: Authenticated");
+                self.ready = true;
+                Ok(Vec::new())
+            }
+            Some(ACTION_REQUEST) if failed => {
+                warn!(
+                    "Streamer.bot: Couldn't run action {}: {}",
+                    self.settings.action,
+                    message["error"].as_str().unwrap_or("no reason given")
+                );
+                Ok(Vec::new())
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+}
+
+impl ReconnectingClient for StreamerbotOutput {
+    type Connection = WsClient;
+    type Updates = BReceiver<AppUpdate>;
+    fn name(&self) -> &'static str {
+        "Streamer.bot"
+    }
+    fn subsystem(&self) -> Subsystem {
+        Subsystem::Streamerbot
+    }
+    fn target(&self) -> String {
+        self.settings.url.clone()
+    }
+    async fn connect(&mut self) -> Result<WsClient, ConnectError> {
+        self.ready = false;
+        reconnect::connect_websocket(&self.settings.url).await
+    }
+    async fn session(
+        &mut self,
+        mut client: WsClient,
+        broadcast_rx: &mut BReceiver<AppUpdate>,
+        _broadcast_tx: &BSender<AppUpdate>,
+        cancel_token: &CancellationToken,
+    ) -> Result<Option<String>, AppError> {
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(self.settings.interval_sec.max(1) as u64));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        // First tick is immediate
+        interval.tick().await;
+        loop {
+            let messages = tokio::select! {
+                item = client.next() => match item {
+                    Some(Ok(msg)) if msg.is_close() => {
+                        return Ok(Some("Streamer.bot closed the connection".into()));
+                    }
+                    Some(Ok(msg)) => {
+                        let Some(message) = msg
+                            .as_text()
+                            .and_then(|text| serde_json::from_str::<Value>(text).ok())
+                        else {
+                            continue;
+                        };
+                        debug!("Streamer.bot: {message}");
+                        self.handle_message(message)?
+                    }
+                    Some(Err(e)) => return Ok(Some(format!("Error receiving from Streamer.bot: {e}"))),
+                    None => return Ok(Some("Streamer.bot closed the connection".into())),
+                },
+                update = broadcast_rx.recv() => {
+                    let actions = match update {
+                        Ok(AppUpdate::HeartRateStatus(hr_status)) => self.handle_status(hr_status),
+                        Ok(AppUpdate::ZoneChanged(zone)) => self.handle_zone(zone),
+                        Ok(_) => continue,
+                        Err(RecvError::Lagged(count)) => {
+                            warn!("Streamer.bot: Lagged! Missed {count} messages");
+                            continue;
+                        }
+                        Err(RecvError::Closed) => return Ok(None),
+                    };
+                    if self.ready {
+                        actions
+                    } else {
+                        continue;
+                    }
+                }
+                // Nothing worth sending until there's a reading
+                _ = interval.tick(), if self.settings.interval_sec > 0 => {
+                    if self.ready && self.last_bpm.is_some() {
+                        vec![self.action("interval", None)]
+                    } else {
+                        continue;
+                    }
+                }
+                _ = cancel_token.cancelled() => {
+                    let _ = client.close().await;
+                    return Ok(None);
+                }
+            };
+            for message in messages {
+                client.send(message).await?;
+            }
+        }
+    }
+}
+
+pub(super) async fn drive(
+    broadcast_rx: &mut BReceiver<AppUpdate>,
+    broadcast_tx: &BSender<AppUpdate>,
+    streamerbot_settings: StreamerbotSettings,
+    cancel_token: CancellationToken,
+) -> Result<(), AppError> {
+    let mut streamerbot = StreamerbotOutput::build(streamerbot_settings)?;
+    reconnect::run(&mut streamerbot, broadcast_rx, broadcast_tx, &cancel_token).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sent(messages: &[Message]) -> Vec<Value> {
+        messages
+            .iter()
+            .map(|msg| serde_json::from_str(msg.as_text().unwrap()).unwrap())
+            .collect()
+    }
+
+    fn status(bpm: u16) -> HeartRateStatus {
+        HeartRateStatus {
+            heart_rate_bpm: bpm,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn actions_on_crossing_thresholds() {
+        let mut streamerbot = StreamerbotOutput::build(StreamerbotSettings {
+            action: "Heart Rate".into(),
+            thresholds: vec![150, 120],
+            ..Default::default()
+        })
+        .unwrap();
+        // First reading, nothing crossed
+        assert!(streamerbot.handle_status(status(130)).is_empty());
+        let actions = sent(&streamerbot.handle_status(status(160)));
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0]["request"], "DoAction");
+        assert_eq!(actions[0]["action"]["name"], "Heart Rate");
+        assert_eq!(actions[0]["args"]["event"], "above");
+        assert_eq!(actions[0]["args"]["threshold"], 150);
+        assert_eq!(actions[0]["args"]["bpm"], 160);
+
+        let actions = sent(&streamerbot.handle_status(status(100)));
+        let crossed: Vec<_> = actions
+            .iter()
+            .map(|action| {
+                (
+                    action["args"]["event"].clone(),
+                    action["args"]["threshold"].clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            crossed,
+            [(json!("below"), json!(150)), (json!("below"), json!(120))]
+        );
+        // Disconnecting and coming back doesn't count
+        assert!(streamerbot.handle_status(status(0)).is_empty());
+        assert!(streamerbot.handle_status(status(155)).is_empty());
+    }
+
+    #[test]
+    fn authenticates_when_asked() {
+        let settings = StreamerbotSettings {
+            action: "Heart Rate".into(),
+            password: "hunter2".into(),
+            ..Default::default()
+        };
+        let mut streamerbot = StreamerbotOutput::build(settings).unwrap();
+        let hello = json!({
+            "request": "Hello",
+            "authentication": { "salt": "salt", "challenge": "challenge" },
+        });
+        let replies = sent(&streamerbot.handle_message(hello).unwrap());
+        assert_eq!(replies[0]["request"], "Authenticate");
+        assert_eq!(
+            replies[0]["authentication"],
+            auth_response("hunter2", "salt", "challenge")
+        );
+        assert!(!streamerbot.ready);
+        streamerbot
+            .handle_message(json!({ "id": AUTH_REQUEST, "status": "ok" }))
+            .unwrap();
+        assert!(streamerbot.ready);
+        assert!(matches!(
+            streamerbot.handle_message(json!({ "id": AUTH_REQUEST, "status": "error" })),
+            Err(AppError::StreamerbotUnauthorized)
+        ));
+    }
+}
@@ -0,0 +1,227 @@
+use std::fmt::Write as _;
+use std::sync::OnceLock;
+
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::{info, warn, Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+use crate::journal::Severity;
+use crate::settings::{SyslogProtocol, SyslogSettings};
+
+const APP_NAME: &str = "null_iron_heart";
+// Messages are dropped past this, rather than holding up whoever's logging
+const QUEUE_SIZE: usize = 256;
+// "user-level messages"
+const FACILITY: u8 = 1;
+
+// Only set if `syslog.enabled`
+static SYSLOG: OnceLock<SyslogHandle> = OnceLock::new();
+
+struct SyslogHandle {
+    tx: mpsc::Sender<String>,
+    level: LevelFilter,
+    hostname: String,
+}
+
+impl SyslogHandle {
+    fn send(&self, severity: u8, msg_id: &str, message: &str) {
+        let line = format_message(
+            severity,
+            &chrono::Local::now().to_rfc3339(),
+            &self.hostname,
+            msg_id,
+            message,
+        );
+        // Full or closed, either way there's nothing better to do with it
+        let _ = self.tx.try_send(line);
+    }
+}
+
+/// RFC 5424 formatted line, without any transport framing.
+fn format_message(
+    severity: u8,
+    timestamp: &str,
+    hostname: &str,
+    msg_id: &str,
+    message: &str,
+) -> String {
+    let pri = FACILITY * 8 + severity;
+    let hostname = if hostname.is_empty() { "-" } else { hostname };
+    format!(
+        "<{pri}>1 {timestamp} {hostname} {APP_NAME} {} {msg_id} - {message}",
+        std::process::id()
+    )
+}
+
+fn level_to_severity(level: &Level) -> u8 {
+    match *level {
+        Level::ERROR => 3,
+        Level::WARN => 4,
+        Level::INFO => 6,
+        Level::DEBUG | Level::TRACE => 7,
+    }
+}
+
+impl From<Severity> for u8 {
+    fn from(severity: Severity) -> Self {
+        match severity {
+            Severity::Error => 3,
+            Severity::Warning => 4,
+            Severity::Info => 6,
+        }
+    }
+}
+
+/// Forwards app logs to the syslog endpoint, once `init` has been called.
+pub struct SyslogLayer;
+
+impl<S: Subscriber> Layer<S> for SyslogLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let Some(syslog) = SYSLOG.get() else {
+            return;
+        };
+        let metadata = event.metadata();
+        // Our own warnings would just feed back into the queue
+        if syslog.level < *metadata.level() || metadata.target() == module_path!() {
+            return;
+        }
+        let mut visitor = MessageVisitor(String::new());
+        event.record(&mut visitor);
+        let message = format!("{}: {}", metadata.target(), visitor.0);
+        syslog.send(level_to_severity(metadata.level()), "log", &message);
+    }
+}
+
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, "{value:?}");
+        } else {
+            let _ = write!(self.0, " {}={value:?}", field.name());
+        }
+    }
+}
+
+/// Sends a journal entry along as a session event, if syslog is enabled.
+pub fn forward_session_event(severity: Severity, subsystem: &str, message: &str) {
+    if let Some(syslog) = SYSLOG.get() {
+        syslog.send(severity.into(), subsystem, message);
+    }
+}
+
+/// Starts queueing logs for `syslog_thread` to send out.
+pub fn init(syslog_settings: &SyslogSettings) -> Option<mpsc::Receiver<String>> {
+    let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+    let handle = SyslogHandle {
+        tx,
+        level: syslog_settings.get_log_level(),
+        hostname: syslog_settings.hostname.replace(' ', "_"),
+    };
+    SYSLOG.set(handle).ok()?;
+    Some(rx)
+}
+
+pub async fn syslog_thread(
+    mut rx: mpsc::Receiver<String>,
+    syslog_settings: SyslogSettings,
+    cancel_token: CancellationToken,
+) {
+    info!(
+        "Syslog thread started, sending to {} over {:?}",
+        syslog_settings.address, syslog_settings.protocol
+    );
+    let mut sender = SyslogSender {
+        address: syslog_settings.address,
+        protocol: syslog_settings.protocol,
+        udp: None,
+        tcp: None,
+    };
+    loop {
+        tokio::select! {
+            Some(line) = rx.recv() => sender.send(&line).await,
+            _ = cancel_token.cancelled() => {
+                // Get the shutdown messages out
+                while let Ok(line) = rx.try_recv() {
+                    sender.send(&line).await;
+                }
+                break;
+            }
+        }
+    }
+}
+
+struct SyslogSender {
+    address: String,
+    protocol: SyslogProtocol,
+    udp: Option<UdpSocket>,
+    tcp: Option<TcpStream>,
+}
+
+impl SyslogSender {
+    /// Failed sends are dropped, the connection is retried on the next one.
+    async fn send(&mut self, line: &str) {
+        let result = match self.protocol {
+            SyslogProtocol::Udp => self.send_udp(line).await,
+            SyslogProtocol::Tcp => self.send_tcp(line).await,
+        };
+        if let Err(e) = result {
+            warn!("Failed to send to syslog: {e}");
+            self.udp = None;
+            self.tcp = None;
+        }
+    }
+    async fn send_udp(&mut self, line: &str) -> std::io::Result<()> {
+        if self.udp.is_none() {
+            let target = tokio::net::lookup_host(&self.address)
+                .await?
+                .next()
+                .ok_or(std::io::ErrorKind::AddrNotAvailable)?;
+            let bind_address = if target.is_ipv4() {
+                "0.0.0.0:0"
+            } else {
+                "[::]:0"
+            };
+            let socket = UdpSocket::bind(bind_address).await?;
+            socket.connect(target).await?;
+            self.udp = Some(socket);
+        }
+        if let Some(socket) = self.udp.as_ref() {
+            socket.send(line.as_bytes()).await?;
+        }
+        Ok(())
+    }
+    async fn send_tcp(&mut self, line: &str) -> std::io::Result<()> {
+        if self.tcp.is_none() {
+            self.tcp = Some(TcpStream::connect(&self.address).await?);
+        }
+        if let Some(stream) = self.tcp.as_mut() {
+            // Octet counting, RFC 6587
+            let framed = format!("{} {line}", line.len());
+            stream.write_all(framed.as_bytes()).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc5424_format() {
+        let line = format_message(4, "2024-01-01T00:00:00+00:00", "", "ble", "Stream closed");
+        let expected = format!(
+            "<12>1 2024-01-01T00:00:00+00:00 - null_iron_heart {} ble - Stream closed",
+            std::process::id()
+        );
+        assert_eq!(line, expected);
+    }
+}
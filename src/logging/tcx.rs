@@ -0,0 +1,178 @@
+// Converts a session CSV to Garmin's Training Center XML, which Strava, Garmin Connect, TrainingPeaks, etc accept
+// https://www8.garmin.com/xmlschemas/TrainingCenterDatabasev2.xsd
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+
+use super::sessions::read_rows;
+use crate::errors::AppError;
+
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.3fZ";
+
+struct Lap<'a> {
+    points: &'a [(DateTime<Utc>, u16)],
+}
+
+impl Lap<'_> {
+    fn start(&self) -> DateTime<Utc> {
+        self.points[0].0
+    }
+    fn total_secs(&self) -> f64 {
+        let end = self.points[self.points.len() - 1].0;
+        (end - self.start()).num_milliseconds() as f64 / 1000.0
+    }
+    fn average_bpm(&self) -> u16 {
+        let sum: u64 = self.points.iter().map(|(_, bpm)| *bpm as u64).sum();
+        (sum as f64 / self.points.len() as f64).round() as u16
+    }
+    fn max_bpm(&self) -> u16 {
+        self.points.iter().map(|(_, bpm)| *bpm).max().unwrap_or(0)
+    }
+}
+
+/// Splits into laps of `lap_minutes` each, or one lap for the whole session if 0.
+fn laps(points: &[(DateTime<Utc>, u16)], lap_minutes: u32) -> Vec<Lap<'_>> {
+    if lap_minutes == 0 {
+        return vec![Lap { points }];
+    }
+    let lap_length = chrono::Duration::minutes(lap_minutes as i64);
+    let mut laps = Vec::new();
+    let mut rest = points;
+    while let Some(&(start, _)) = rest.first() {
+        let len = rest
+            .iter()
+            .position(|(timestamp, _)| *timestamp - start >= lap_length)
+            .unwrap_or(rest.len());
+        let (lap, next) = rest.split_at(len);
+        laps.push(Lap { points: lap });
+        rest = next;
+    }
+    laps
+}
+
+fn to_tcx(points: &[(DateTime<Utc>, u16)], lap_minutes: u32) -> String {
+    let mut xml = String::new();
+    let _ = writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(
+        xml,
+        r#"<TrainingCenterDatabase xmlns="http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2">"#
+    );
+    let _ = writeln!(xml, "  <Activities>");
+    let _ = writeln!(xml, r#"    <Activity Sport="Other">"#);
+    let _ = writeln!(xml, "      <Id>{}</Id>", points[0].0.format(TIME_FORMAT));
+    for lap in laps(points, lap_minutes) {
+        let _ = writeln!(
+            xml,
+            r#"      <Lap StartTime="{}">"#,
+            lap.start().format(TIME_FORMAT)
+        );
+        let _ = writeln!(
+            xml,
+            "        <TotalTimeSeconds>{:.1}</TotalTimeSeconds>",
+            lap.total_secs()
+        );
+        let _ = writeln!(xml, "        <DistanceMeters>0</DistanceMeters>");
+        let _ = writeln!(xml, "        <Calories>0</Calories>");
+        let _ = writeln!(
+            xml,
+            "        <AverageHeartRateBpm><Value>{}</Value></AverageHeartRateBpm>",
+            lap.average_bpm()
+        );
+        let _ = writeln!(
+            xml,
+            "        <MaximumHeartRateBpm><Value>{}</Value></MaximumHeartRateBpm>",
+            lap.max_bpm()
+        );
+        let _ = writeln!(xml, "        <Intensity>Active</Intensity>");
+        let _ = writeln!(xml, "        <TriggerMethod>Manual</TriggerMethod>");
+        let _ = writeln!(xml, "        <Track>");
+        for (timestamp, bpm) in lap.points {
+            let _ = writeln!(
+                xml,
+                "          <Trackpoint><Time>{}</Time><HeartRateBpm><Value>{bpm}</Value></HeartRateBpm></Trackpoint>",
+                timestamp.format(TIME_FORMAT)
+            );
+        }
+        let _ = writeln!(xml, "        </Track>");
+        let _ = writeln!(xml, "      </Lap>");
+    }
+    let _ = writeln!(xml, "    </Activity>");
+    let _ = writeln!(xml, "  </Activities>");
+    let _ = writeln!(xml, "</TrainingCenterDatabase>");
+    xml
+}
+
+/// Session CSVs are in local time, TCX wants UTC.
+fn to_utc(timestamp: NaiveDateTime) -> Option<DateTime<Utc>> {
+    // Ambiguous around DST changes, either's close enough
+    Local
+        .from_local_datetime(&timestamp)
+        .earliest()
+        .map(|local| local.with_timezone(&Utc))
+}
+
+/// Writes `session` out as TCX next to it (or to `output`), returning where it went.
+pub fn export(
+    session: &Path,
+    output: Option<&Path>,
+    lap_minutes: u32,
+) -> Result<PathBuf, AppError> {
+    let file = std::fs::File::open(session).map_err(|e| AppError::ReadFile {
+        path: session.to_owned(),
+        source: e,
+    })?;
+    let points: Vec<(DateTime<Utc>, u16)> = read_rows(file)?
+        .into_iter()
+        .filter(|(_, bpm)| *bpm != 0)
+        .filter_map(|(timestamp, bpm)| Some((to_utc(timestamp)?, bpm)))
+        .collect();
+    if points.is_empty() {
+        return Err(AppError::EmptySession(session.to_owned()));
+    }
+    let output = output
+        .map(Path::to_owned)
+        .unwrap_or_else(|| session.with_extension("tcx"));
+    std::fs::write(&output, to_tcx(&points, lap_minutes)).map_err(|e| AppError::WriteFile {
+        path: output.clone(),
+        source: e,
+    })?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points() -> Vec<(DateTime<Utc>, u16)> {
+        let start = DateTime::parse_from_rfc3339("2024-08-01T20:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        [(0, 80), (30, 100), (60, 140), (90, 120), (120, 90)]
+            .into_iter()
+            .map(|(secs, bpm)| (start + chrono::Duration::seconds(secs), bpm))
+            .collect()
+    }
+
+    #[test]
+    fn single_lap_summary() {
+        let xml = to_tcx(&points(), 0);
+        assert_eq!(xml.matches("<Lap ").count(), 1);
+        assert!(xml.contains("<Id>2024-08-01T20:00:00.000Z</Id>"));
+        assert!(xml.contains("<TotalTimeSeconds>120.0</TotalTimeSeconds>"));
+        assert!(xml.contains("<AverageHeartRateBpm><Value>106</Value></AverageHeartRateBpm>"));
+        assert!(xml.contains("<MaximumHeartRateBpm><Value>140</Value></MaximumHeartRateBpm>"));
+        assert_eq!(xml.matches("<Trackpoint>").count(), 5);
+    }
+
+    #[test]
+    fn splits_laps() {
+        let points = points();
+        let laps = laps(&points, 1);
+        assert_eq!(laps.len(), 3);
+        assert_eq!(laps[0].points.len(), 2);
+        assert_eq!(laps[1].max_bpm(), 140);
+        assert_eq!(laps[2].average_bpm(), 90);
+    }
+}
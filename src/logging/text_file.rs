@@ -0,0 +1,170 @@
+use crate::errors::AppError;
+use crate::format::NumberFormat;
+use crate::heart_rate::{BatteryLevel, HeartRateStatus};
+use crate::settings::MiscSettings;
+
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::fs;
+use tracing::warn;
+
+/// `bpm_file_path`, filled in from `bpm_file_template` for OBS's "Read from file" text sources.
+///
+/// Written to a temporary file and renamed over the real one, so OBS never reads it half written.
+pub(super) struct TemplateFile {
+    path: PathBuf,
+    temp_path: PathBuf,
+    template: String,
+    number_format: NumberFormat,
+    // None to write on every update
+    pub(super) interval: Option<Duration>,
+    zone: Option<u8>,
+    last_rr: Duration,
+    // Filled in but not written yet, when on an interval
+    pending: Option<String>,
+    last_written: Option<String>,
+}
+
+impl TemplateFile {
+    pub(super) fn new(misc_settings: &MiscSettings, number_format: NumberFormat) -> Self {
+        let path = PathBuf::from(&misc_settings.bpm_file_path);
+        let mut temp_path = path.clone().into_os_string();
+        temp_path.push(".tmp");
+        let mut template = misc_settings.bpm_file_template.clone();
+        if misc_settings.write_rr_to_file {
+            template.push_str("\n{rr}");
+        }
+        let interval = (misc_settings.bpm_file_interval_ms > 0)
+            .then(|| Duration::from_millis(misc_settings.bpm_file_interval_ms as u64));
+        Self {
+            path,
+            temp_path: temp_path.into(),
+            template,
+            number_format,
+            interval,
+            zone: None,
+            last_rr: Duration::from_secs(0),
+            pending: None,
+            last_written: None,
+        }
+    }
+    /// Fills in `{bpm}`, `{rr}`, `{battery}` and `{zone}`.
+    fn fill(&self, heart_rate_status: &HeartRateStatus) -> String {
+        let battery = match heart_rate_status.battery_level {
+            BatteryLevel::Level(level) => level.to_string(),
+            _ => "?".to_owned(),
+        };
+        let zone = self
+            .zone
+            .map(|zone| zone.to_string())
+            .unwrap_or_else(|| "?".to_owned());
+        self.template
+            .replace(
+                "{bpm}",
+                &self.number_format.bpm(heart_rate_status.heart_rate_bpm),
+            )
+            .replace("{rr}", &self.number_format.rr_ms(self.last_rr))
+            .replace("{battery}", &battery)
+            .replace("{zone}", &zone)
+    }
+    pub(super) async fn update(
+        &mut self,
+        heart_rate_status: &HeartRateStatus,
+    ) -> Result<(), AppError> {
+        if let Some(rr) = heart_rate_status.rr_intervals.last() {
+            self.last_rr = *rr;
+        }
+        self.pending = Some(self.fill(heart_rate_status));
+        if self.interval.is_none() {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+    pub(super) fn set_zone(&mut self, zone: u8) {
+        self.zone = Some(zone);
+    }
+    pub(super) async fn flush(&mut self) -> Result<(), AppError> {
+        let Some(text) = self.pending.take() else {
+            return Ok(());
+        };
+        // OBS rereads the file when it changes, no need to touch it otherwise
+        if self.last_written.as_ref() == Some(&text) {
+            return Ok(());
+        }
+        fs::write(&self.temp_path, text.as_bytes())
+            .await
+            .map_err(|e| AppError::WriteFile {
+                path: self.temp_path.clone(),
+                source: e,
+            })?;
+        // Windows refuses the rename if something has the file open at that exact moment,
+        // so it's just tried again on the next update
+        if let Err(e) = fs::rename(&self.temp_path, &self.path).await {
+            warn!("Couldn't replace {}: {e}", self.path.display());
+            return Ok(());
+        }
+        self.last_written = Some(text);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::FormatSettings;
+
+    fn status(bpm: u16) -> HeartRateStatus {
+        HeartRateStatus {
+            heart_rate_bpm: bpm,
+            rr_intervals: vec![Duration::from_millis(810)],
+            battery_level: BatteryLevel::Level(64),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn fills_and_replaces() {
+        let path = std::env::temp_dir().join(format!("nih-bpm-{}.txt", std::process::id()));
+        let mut misc_settings = MiscSettings::default();
+        misc_settings.bpm_file_path = path.to_string_lossy().into_owned();
+        misc_settings.bpm_file_template = "{bpm} ❤ {battery}% z{zone}".into();
+        misc_settings.write_rr_to_file = true;
+        let number_format = NumberFormat::new(&FormatSettings {
+            decimal_separator: ".".into(),
+            bpm_suffix: " BPM".into(),
+            rr_suffix: " ms".into(),
+        });
+        let mut file = TemplateFile::new(&misc_settings, number_format);
+        file.update(&status(123)).await.unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).await.unwrap(),
+            "123 BPM ❤ 64% z?\n810 ms"
+        );
+        // Shorter than before, and nothing left over from it
+        file.set_zone(2);
+        file.update(&status(99)).await.unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).await.unwrap(),
+            "99 BPM ❤ 64% z2\n810 ms"
+        );
+        assert!(!file.temp_path.exists());
+        fs::remove_file(&path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn interval_waits_for_flush() {
+        let path = std::env::temp_dir().join(format!("nih-bpm-int-{}.txt", std::process::id()));
+        let mut misc_settings = MiscSettings::default();
+        misc_settings.bpm_file_path = path.to_string_lossy().into_owned();
+        misc_settings.bpm_file_template = "{bpm}".into();
+        misc_settings.bpm_file_interval_ms = 1000;
+        let number_format = NumberFormat::new(&FormatSettings::default());
+        let mut file = TemplateFile::new(&misc_settings, number_format);
+        file.update(&status(80)).await.unwrap();
+        file.update(&status(81)).await.unwrap();
+        assert!(!path.exists());
+        file.flush().await.unwrap();
+        assert_eq!(fs::read_to_string(&path).await.unwrap(), "81");
+        fs::remove_file(&path).await.unwrap();
+    }
+}
@@ -0,0 +1,180 @@
+// Sends each reading as a UDP datagram with the same JSON the UDP source takes, i.e. for game mods that listen for one
+// The target can be a broadcast address, to reach every listener on the LAN
+
+use serde::Serialize;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver as BReceiver;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+use crate::app::AppUpdate;
+use crate::errors::AppError;
+use crate::heart_rate::{BatteryLevel, HeartRateStatus};
+use crate::settings::UdpOutputSettings;
+
+/// `{"bpm": 72, "latest_rr_ms": 833, "battery": 90}`, the last two left out if there aren't any.
+#[derive(Debug, Serialize)]
+struct UdpPayload {
+    bpm: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latest_rr_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    battery: Option<u8>,
+}
+
+impl From<&HeartRateStatus> for UdpPayload {
+    fn from(hr_status: &HeartRateStatus) -> Self {
+        Self {
+            bpm: hr_status.heart_rate_bpm,
+            latest_rr_ms: hr_status
+                .rr_intervals
+                .last()
+                .map(|rr| rr.as_millis() as u64),
+            battery: match hr_status.battery_level {
+                BatteryLevel::Level(level) => Some(level),
+                _ => None,
+            },
+        }
+    }
+}
+
+pub(super) struct UdpOutputActor {
+    socket: UdpSocket,
+    // None sends every update as it comes in
+    interval: Option<Duration>,
+    // Latest one, resent each interval
+    last_payload: Option<Vec<u8>>,
+    send_failing: bool,
+}
+
+impl UdpOutputActor {
+    pub(super) async fn build(settings: &UdpOutputSettings) -> Result<Self, AppError> {
+        if settings.target.is_empty() {
+            return Err(AppError::UdpOutputTargetMissing);
+        }
+        let socket = UdpSocket::bind(("0.0.0.0", 0)).await?;
+        socket.set_broadcast(true)?;
+        socket.connect(&settings.target).await?;
+        info!("UDP output: Sending to {}", socket.peer_addr()?);
+        Ok(Self {
+            socket,
+            interval: (settings.interval_ms > 0)
+                .then(|| Duration::from_millis(settings.interval_ms as u64)),
+            last_payload: None,
+            send_failing: false,
+        })
+    }
+
+    async fn send(&mut self) {
+        let Some(payload) = self.last_payload.as_ref() else {
+            return;
+        };
+        // Just noting it in the .log, nothing may be listening yet
+        match self.socket.send(payload).await {
+            Ok(_) if self.send_failing => {
+                info!("UDP output: Sending again");
+                self.send_failing = false;
+            }
+            Ok(_) => {}
+            Err(e) if !self.send_failing => {
+                warn!("UDP output: Couldn't send: {e}");
+                self.send_failing = true;
+            }
+            Err(e) => debug!("UDP output: Couldn't send: {e}"),
+        }
+    }
+
+    pub(super) async fn rx_loop(
+        &mut self,
+        broadcast_rx: &mut BReceiver<AppUpdate>,
+        cancel_token: CancellationToken,
+    ) -> Result<(), AppError> {
+        // Unused when sending each update
+        let mut ticker = tokio::time::interval(self.interval.unwrap_or(Duration::from_secs(1)));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                update = broadcast_rx.recv() => match update {
+                    Ok(AppUpdate::HeartRateStatus(hr_status)) => {
+                        self.last_payload = Some(serde_json::to_vec(&UdpPayload::from(&hr_status))?);
+                        if self.interval.is_none() {
+                            self.send().await;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(count)) => warn!("UDP output: Lagged! Missed {count} messages"),
+                    Err(RecvError::Closed) => {
+                        error!("UDP output: Channel closed");
+                        return Ok(());
+                    }
+                },
+                _ = ticker.tick(), if self.interval.is_some() => self.send().await,
+                _ = cancel_token.cancelled() => {
+                    info!("Shutting down UDP output thread!");
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_matches_udp_source() {
+        let hr_status = HeartRateStatus {
+            heart_rate_bpm: 72,
+            rr_intervals: vec![Duration::from_millis(900), Duration::from_millis(833)],
+            battery_level: BatteryLevel::Level(90),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_string(&UdpPayload::from(&hr_status)).unwrap(),
+            r#"{"bpm":72,"latest_rr_ms":833,"battery":90}"#
+        );
+        let hr_status = HeartRateStatus {
+            heart_rate_bpm: 60,
+            battery_level: BatteryLevel::NotReported,
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_string(&UdpPayload::from(&hr_status)).unwrap(),
+            r#"{"bpm":60}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn sends_each_update() {
+        let listener = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let settings = UdpOutputSettings {
+            enabled: true,
+            target: listener.local_addr().unwrap().to_string(),
+            interval_ms: 0,
+        };
+        let mut actor = UdpOutputActor::build(&settings).await.unwrap();
+        let (broadcast_tx, mut broadcast_rx) = tokio::sync::broadcast::channel(16);
+        let cancel_token = CancellationToken::new();
+        let task = tokio::spawn({
+            let cancel_token = cancel_token.clone();
+            async move { actor.rx_loop(&mut broadcast_rx, cancel_token).await }
+        });
+        broadcast_tx
+            .send(AppUpdate::HeartRateStatus(HeartRateStatus {
+                heart_rate_bpm: 75,
+                ..Default::default()
+            }))
+            .unwrap();
+        let mut buf = [0; 64];
+        let len = tokio::time::timeout(Duration::from_secs(2), listener.recv(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&buf[..len], br#"{"bpm":75}"#);
+        cancel_token.cancel();
+        task.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,249 @@
+// POSTs a templated body to any URL on the events picked in `[webhook]`, i.e. for Discord/Slack webhooks or IFTTT
+// Unlike `[alerts]`, this is about the heart rate itself rather than the app's errors
+
+use crate::app::AppUpdate;
+use crate::errors::AppError;
+use crate::heart_rate::HeartRateStatus;
+use crate::settings::{WebhookEvent, WebhookSettings};
+
+use http::{header, HeaderName, HeaderValue};
+use reqwest::Client;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver as BReceiver;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+impl WebhookEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            WebhookEvent::Connected => "connected",
+            WebhookEvent::Disconnected => "disconnected",
+            WebhookEvent::Interval => "interval",
+            WebhookEvent::Zone => "zone",
+            WebhookEvent::TwitchUp => "twitch_up",
+            WebhookEvent::TwitchDown => "twitch_down",
+        }
+    }
+}
+
+pub(super) struct WebhookActor {
+    settings: WebhookSettings,
+    client: Client,
+    // Latest update, so interval and zone events have something to fill in
+    last_status: HeartRateStatus,
+    zone: Option<u8>,
+}
+
+impl WebhookActor {
+    pub(super) fn build(settings: WebhookSettings) -> Result<Self, AppError> {
+        if settings.url.is_empty() {
+            return Err(AppError::MissingWebhookUrl);
+        }
+        let mut headers = header::HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_str(&settings.content_type)?,
+        );
+        if !settings.header.is_empty() {
+            let (header_key, header_value) = settings
+                .header
+                .split_once(':')
+                .ok_or(AppError::MissingDelimiter)
+                .map(|(k, v)| (k.trim(), v.trim()))?;
+            headers.insert(
+                HeaderName::from_str(header_key)?,
+                HeaderValue::from_str(header_value)?,
+            );
+        }
+        let client = Client::builder()
+            .default_headers(headers)
+            .timeout(Duration::from_secs(10))
+            .build()?;
+        Ok(Self {
+            settings,
+            client,
+            last_status: HeartRateStatus::default(),
+            zone: None,
+        })
+    }
+
+    fn wants(&self, event: WebhookEvent) -> bool {
+        self.settings.events.contains(&event)
+    }
+
+    pub(super) async fn rx_loop(
+        &mut self,
+        broadcast_rx: &mut BReceiver<AppUpdate>,
+        cancel_token: CancellationToken,
+    ) -> Result<(), AppError> {
+        let interval_sec = self.settings.interval_sec.max(1) as u64;
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_sec));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        // First tick is immediate
+        interval.tick().await;
+        loop {
+            let events = tokio::select! {
+                update = broadcast_rx.recv() => match update {
+                    Ok(AppUpdate::HeartRateStatus(hr_status)) => self.handle_status(hr_status),
+                    Ok(AppUpdate::ZoneChanged(zone)) => self.handle_zone(zone),
+                    Ok(_) => continue,
+                    Err(RecvError::Closed) => {
+                        error!("Webhook: Channel closed");
+                        return Ok(());
+                    }
+                    Err(RecvError::Lagged(count)) => {
+                        warn!("Webhook: Lagged! Missed {count} messages");
+                        continue;
+                    }
+                },
+                _ = interval.tick() => {
+                    // Nothing worth sending until there's a reading
+                    if self.wants(WebhookEvent::Interval) && self.last_status.heart_rate_bpm != 0 {
+                        vec![WebhookEvent::Interval]
+                    } else {
+                        continue;
+                    }
+                }
+                _ = cancel_token.cancelled() => {
+                    info!("Webhook thread shutting down");
+                    return Ok(());
+                }
+            };
+            for event in events {
+                self.send(event);
+            }
+        }
+    }
+
+    /// Events this update sets off, out of the ones that are enabled.
+    fn handle_status(&mut self, hr_status: HeartRateStatus) -> Vec<WebhookEvent> {
+        let was_connected = self.last_status.heart_rate_bpm != 0;
+        let connected = hr_status.heart_rate_bpm != 0;
+        let mut events = Vec::new();
+        match (was_connected, connected) {
+            (false, true) => events.push(WebhookEvent::Connected),
+            (true, false) => events.push(WebhookEvent::Disconnected),
+            _ => {}
+        }
+        if hr_status.twitch_up {
+            events.push(WebhookEvent::TwitchUp);
+        }
+        if hr_status.twitch_down {
+            events.push(WebhookEvent::TwitchDown);
+        }
+        // Disconnected updates carry nothing else, so the battery and such are kept from the last reading
+        if connected {
+            self.last_status = hr_status;
+        } else {
+            self.last_status.heart_rate_bpm = 0;
+            self.last_status.timestamp = hr_status.timestamp;
+        }
+        events.retain(|event| self.wants(*event));
+        events
+    }
+
+    fn handle_zone(&mut self, zone: u8) -> Vec<WebhookEvent> {
+        self.zone = Some(zone);
+        if self.wants(WebhookEvent::Zone) {
+            vec![WebhookEvent::Zone]
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Fills in `{event}`, `{bpm}`, `{rr}` (ms), `{battery}`, `{zone}` and `{timestamp}`.
+    ///
+    /// Unknown values are 0, so a template stays valid JSON either way.
+    fn body(&self, event: WebhookEvent) -> String {
+        let status = &self.last_status;
+        let rr = status
+            .rr_intervals
+            .last()
+            .map(|rr| rr.as_millis())
+            .unwrap_or_default();
+        self.settings
+            .body
+            .replace("{event}", event.name())
+            .replace("{bpm}", &status.heart_rate_bpm.to_string())
+            .replace("{rr}", &rr.to_string())
+            .replace("{battery}", &u8::from(status.battery_level).to_string())
+            .replace("{zone}", &self.zone.unwrap_or_default().to_string())
+            .replace("{timestamp}", &status.timestamp.to_rfc3339())
+    }
+
+    fn send(&self, event: WebhookEvent) {
+        let request = self.client.post(&self.settings.url).body(self.body(event));
+        // Sent off on their own, so a slow server doesn't hold up the updates behind it
+        tokio::spawn(async move {
+            // Just putting these in the .log, a webhook failing once shouldn't stop the app
+            match request.send().await.and_then(|r| r.error_for_status()) {
+                Ok(_) => debug!("Webhook: Sent {}", event.name()),
+                Err(e) => error!("Webhook: Failed to send {}: {e}", event.name()),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn actor(events: Vec<WebhookEvent>) -> WebhookActor {
+        WebhookActor::build(WebhookSettings {
+            url: "http://localhost/hook".into(),
+            events,
+            body: r#"{"event":"{event}","bpm":{bpm},"zone":{zone}}"#.into(),
+            content_type: "application/json".into(),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+    fn status(bpm: u16) -> HeartRateStatus {
+        HeartRateStatus {
+            heart_rate_bpm: bpm,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn connection_and_twitch_events() {
+        let mut actor = actor(vec![
+            WebhookEvent::Connected,
+            WebhookEvent::Disconnected,
+            WebhookEvent::TwitchUp,
+        ]);
+        assert_eq!(
+            actor.handle_status(status(70)),
+            vec![WebhookEvent::Connected]
+        );
+        assert!(actor.handle_status(status(72)).is_empty());
+        let twitched = HeartRateStatus {
+            twitch_up: true,
+            twitch_down: true,
+            ..status(90)
+        };
+        // Twitch down wasn't picked
+        assert_eq!(actor.handle_status(twitched), vec![WebhookEvent::TwitchUp]);
+        assert_eq!(
+            actor.handle_status(status(0)),
+            vec![WebhookEvent::Disconnected]
+        );
+        assert_eq!(
+            actor.body(WebhookEvent::Disconnected),
+            r#"{"event":"disconnected","bpm":0,"zone":0}"#
+        );
+    }
+
+    #[test]
+    fn zone_body() {
+        let mut actor = actor(vec![WebhookEvent::Zone]);
+        assert!(actor.handle_status(status(150)).is_empty());
+        assert_eq!(actor.handle_zone(4), vec![WebhookEvent::Zone]);
+        assert_eq!(
+            actor.body(WebhookEvent::Zone),
+            r#"{"event":"zone","bpm":150,"zone":4}"#
+        );
+    }
+}
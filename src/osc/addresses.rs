@@ -15,6 +15,15 @@ pub(super) struct OscAddresses {
     pub rr_twitch_up: String,
     pub rr_twitch_down: String,
     pub activity: String,
+    pub ecg_waveform: String,
+    pub stress_index: String,
+    pub beat_envelope: String,
+    pub goal_progress: String,
+    pub zone: String,
+    pub spo2: String,
+    pub cadence: String,
+    pub instant_bpm: String,
+    pub health: String,
 }
 
 // Not sure if rosc has a function for this already
@@ -30,7 +39,7 @@ fn remove_trailing_char(s: &mut String, ch: char) {
     }
 }
 
-fn format_prefix(prefix: &str) -> Result<String, AppError> {
+pub(super) fn format_prefix(prefix: &str) -> Result<String, AppError> {
     let mut address = String::from("/");
     address.push_str(prefix);
     remove_double_slashes(&mut address);
@@ -42,7 +51,11 @@ fn format_prefix(prefix: &str) -> Result<String, AppError> {
     }
 }
 
-fn format_address(prefix: &str, param: &str, param_name: &str) -> Result<String, AppError> {
+pub(super) fn format_address(
+    prefix: &str,
+    param: &str,
+    param_name: &str,
+) -> Result<String, AppError> {
     // Don't allow empty/blank addresses
     if param.is_empty() || param == "/" {
         return Err(AppError::OscAddress(
@@ -87,6 +100,15 @@ impl OscAddresses {
             rr_twitch_up: format_address(&prefix, &osc_params.rr_twitch_up, "rr_twitch_up")?,
             rr_twitch_down: format_address(&prefix, &osc_params.rr_twitch_down, "rr_twitch_down")?,
             activity: format_address(&prefix, &osc_params.activity, "activity")?,
+            ecg_waveform: format_address(&prefix, &osc_params.ecg_waveform, "ecg_waveform")?,
+            stress_index: format_address(&prefix, &osc_params.stress_index, "stress_index")?,
+            beat_envelope: format_address(&prefix, &osc_params.beat_envelope, "beat_envelope")?,
+            goal_progress: format_address(&prefix, &osc_params.goal_progress, "goal_progress")?,
+            zone: format_address(&prefix, &osc_params.zone, "zone")?,
+            spo2: format_address(&prefix, &osc_params.spo2, "spo2")?,
+            cadence: format_address(&prefix, &osc_params.cadence, "cadence")?,
+            instant_bpm: format_address(&prefix, &osc_params.instant_bpm, "instant_bpm")?,
+            health: format_address(&prefix, &osc_params.health, "health")?,
         })
     }
 }
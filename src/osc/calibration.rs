@@ -0,0 +1,84 @@
+use tokio::time::{Duration, Instant};
+
+use super::hr::bpm_to_float;
+
+// Same range as the int param
+const MAX_BPM: f32 = 255.0;
+
+/// Slowly sweeps the float HR param across its whole range,
+/// so avatar creators can check their animations at every value.
+pub(super) struct CalibrationSweep {
+    started: Instant,
+    duration: Duration,
+    positive_float_bpm: bool,
+    last_logged_bpm: Option<u8>,
+}
+
+impl CalibrationSweep {
+    pub fn new(now: Instant, duration: Duration, positive_float_bpm: bool) -> Self {
+        Self {
+            started: now,
+            // Avoiding a divide by zero
+            duration: duration.max(Duration::from_secs(1)),
+            positive_float_bpm,
+            last_logged_bpm: None,
+        }
+    }
+    /// The BPM the sweep is at, and what it maps to on the float param.
+    ///
+    /// Returns None once the sweep is over.
+    pub fn value_at(&self, now: Instant) -> Option<(f32, f32)> {
+        let elapsed = now.saturating_duration_since(self.started);
+        if elapsed > self.duration {
+            return None;
+        }
+        let bpm = MAX_BPM * elapsed.as_secs_f32() / self.duration.as_secs_f32();
+        Some((bpm, bpm_to_float(bpm, self.positive_float_bpm)))
+    }
+    /// True when the sweep has moved onto a new whole BPM, to keep the log readable.
+    pub fn should_log(&mut self, bpm: f32) -> bool {
+        let whole_bpm = bpm as u8;
+        if self.last_logged_bpm == Some(whole_bpm) {
+            return false;
+        }
+        self.last_logged_bpm = Some(whole_bpm);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sweeps_full_range() {
+        let start = Instant::now();
+        let sweep = CalibrationSweep::new(start, Duration::from_secs(10), false);
+        assert_eq!(sweep.value_at(start), Some((0.0, -1.0)));
+        let (bpm, float) = sweep.value_at(start + Duration::from_secs(5)).unwrap();
+        assert!((bpm - 127.5).abs() < 0.01);
+        assert!(float.abs() < 0.01);
+        assert_eq!(
+            sweep.value_at(start + Duration::from_secs(10)),
+            Some((255.0, 1.0))
+        );
+        assert_eq!(sweep.value_at(start + Duration::from_secs(11)), None);
+    }
+    #[test]
+    fn positive_only() {
+        let start = Instant::now();
+        let sweep = CalibrationSweep::new(start, Duration::from_secs(10), true);
+        assert_eq!(sweep.value_at(start), Some((0.0, 0.0)));
+        assert_eq!(
+            sweep.value_at(start + Duration::from_secs(10)),
+            Some((255.0, 1.0))
+        );
+    }
+    #[test]
+    fn logs_each_bpm_once() {
+        let mut sweep = CalibrationSweep::new(Instant::now(), Duration::from_secs(10), false);
+        assert!(sweep.should_log(60.2));
+        assert!(!sweep.should_log(60.9));
+        assert!(sweep.should_log(61.0));
+    }
+}
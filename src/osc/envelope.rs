@@ -0,0 +1,87 @@
+use tokio::time::{Duration, Instant};
+
+// How often the envelope value gets updated while it's running
+pub(super) const ENVELOPE_STEP: Duration = Duration::from_millis(20);
+
+/// A simple attack/decay envelope that's started on each beat,
+/// ramping a float param from 0.0 up to 1.0 over `attack`, then back down to 0.0 over `decay`.
+#[derive(Debug)]
+pub struct BeatEnvelope {
+    attack: Duration,
+    decay: Duration,
+    started_at: Option<Instant>,
+}
+
+impl BeatEnvelope {
+    pub fn new(attack: Duration, decay: Duration) -> Self {
+        Self {
+            attack,
+            decay,
+            started_at: None,
+        }
+    }
+    pub fn start(&mut self, now: Instant) {
+        self.started_at = Some(now);
+    }
+    pub fn stop(&mut self) {
+        self.started_at = None;
+    }
+    pub fn is_running(&self) -> bool {
+        self.started_at.is_some()
+    }
+    /// Returns the value to send at `now`, or None if the envelope isn't running.
+    ///
+    /// The last value returned is always 0.0, after which the envelope stops itself.
+    pub fn next_value(&mut self, now: Instant) -> Option<f32> {
+        let elapsed = now.saturating_duration_since(self.started_at?);
+        if elapsed < self.attack {
+            Some(elapsed.as_secs_f32() / self.attack.as_secs_f32())
+        } else if elapsed < self.attack + self.decay {
+            let decaying = elapsed - self.attack;
+            Some(1.0 - decaying.as_secs_f32() / self.decay.as_secs_f32())
+        } else {
+            self.stop();
+            Some(0.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn envelope() -> BeatEnvelope {
+        BeatEnvelope::new(Duration::from_millis(100), Duration::from_millis(200))
+    }
+
+    #[test]
+    fn idle_until_started() {
+        let mut envelope = envelope();
+        assert!(!envelope.is_running());
+        assert_eq!(envelope.next_value(Instant::now()), None);
+    }
+    #[test]
+    fn attack_then_decay() {
+        let start = Instant::now();
+        let mut envelope = envelope();
+        envelope.start(start);
+        let at = |ms| start + Duration::from_millis(ms);
+        assert_eq!(envelope.next_value(at(0)), Some(0.0));
+        assert!((envelope.next_value(at(50)).unwrap() - 0.5).abs() < 0.001);
+        assert!((envelope.next_value(at(100)).unwrap() - 1.0).abs() < 0.001);
+        assert!((envelope.next_value(at(200)).unwrap() - 0.5).abs() < 0.001);
+        assert!(envelope.is_running());
+        // Always finishes on 0.0
+        assert_eq!(envelope.next_value(at(350)), Some(0.0));
+        assert!(!envelope.is_running());
+        assert_eq!(envelope.next_value(at(360)), None);
+    }
+    #[test]
+    fn zero_length_envelope() {
+        let start = Instant::now();
+        let mut envelope = BeatEnvelope::new(Duration::ZERO, Duration::ZERO);
+        envelope.start(start);
+        assert_eq!(envelope.next_value(start), Some(0.0));
+        assert!(!envelope.is_running());
+    }
+}
@@ -0,0 +1,541 @@
+use rosc::OscType;
+use std::collections::BTreeMap;
+
+use super::addresses::{format_address, format_prefix};
+use crate::errors::AppError;
+use crate::heart_rate::HeartRateStatus;
+
+/// A user defined param, sent alongside the built-in ones each update.
+#[derive(Debug)]
+pub(super) struct CustomParam {
+    pub address: String,
+    pub expr: Expr,
+}
+
+impl CustomParam {
+    /// Parses every `osc.custom_params` entry up front, so typos are caught at startup.
+    pub fn build_all(
+        prefix: &str,
+        params: &BTreeMap<String, String>,
+    ) -> Result<Vec<Self>, AppError> {
+        let prefix = format_prefix(prefix)?;
+        params
+            .iter()
+            .map(|(name, source)| {
+                let expr = Expr::parse(source).map_err(|reason| AppError::OscExpression {
+                    name: name.to_owned(),
+                    reason,
+                })?;
+                Ok(CustomParam {
+                    address: format_address(&prefix, name, name)?,
+                    expr,
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Value {
+    Int(i32),
+    Float(f32),
+    Bool(bool),
+}
+
+impl Value {
+    fn as_f32(self) -> f32 {
+        match self {
+            Value::Int(i) => i as f32,
+            Value::Float(f) => f,
+            Value::Bool(b) => b as i32 as f32,
+        }
+    }
+    fn as_i32(self) -> i32 {
+        match self {
+            Value::Int(i) => i,
+            Value::Float(f) => f as i32,
+            Value::Bool(b) => b as i32,
+        }
+    }
+    fn truthy(self) -> bool {
+        match self {
+            Value::Int(i) => i != 0,
+            Value::Float(f) => f != 0.0,
+            Value::Bool(b) => b,
+        }
+    }
+}
+
+impl From<Value> for OscType {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Int(i) => OscType::Int(i),
+            Value::Float(f) => OscType::Float(f),
+            Value::Bool(b) => OscType::Bool(b),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Var {
+    Bpm,
+    RrMs,
+    Battery,
+    StressIndex,
+    TwitchUp,
+    TwitchDown,
+    Connected,
+}
+
+impl Var {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "bpm" => Var::Bpm,
+            "rr" | "rr_ms" => Var::RrMs,
+            "battery" => Var::Battery,
+            "stress_index" => Var::StressIndex,
+            "twitch_up" => Var::TwitchUp,
+            "twitch_down" => Var::TwitchDown,
+            "connected" => Var::Connected,
+            _ => return None,
+        })
+    }
+    fn value(self, hr_status: &HeartRateStatus) -> Value {
+        match self {
+            Var::Bpm => Value::Int(hr_status.heart_rate_bpm as i32),
+            Var::RrMs => Value::Int(
+                hr_status
+                    .rr_intervals
+                    .last()
+                    .map(|rr| rr.as_millis() as i32)
+                    .unwrap_or_default(),
+            ),
+            Var::Battery => Value::Int(u8::from(hr_status.battery_level) as i32),
+            Var::StressIndex => Value::Float(hr_status.stress_index.unwrap_or_default()),
+            Var::TwitchUp => Value::Bool(hr_status.twitch_up),
+            Var::TwitchDown => Value::Bool(hr_status.twitch_down),
+            Var::Connected => Value::Bool(hr_status.heart_rate_bpm > 0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Func {
+    Min,
+    Max,
+    Clamp,
+    Abs,
+    Round,
+    Floor,
+    Ceil,
+    Int,
+    Float,
+    If,
+}
+
+impl Func {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "min" => Func::Min,
+            "max" => Func::Max,
+            "clamp" => Func::Clamp,
+            "abs" => Func::Abs,
+            "round" => Func::Round,
+            "floor" => Func::Floor,
+            "ceil" => Func::Ceil,
+            "int" => Func::Int,
+            "float" => Func::Float,
+            "if" => Func::If,
+            _ => return None,
+        })
+    }
+    fn arg_count(self) -> usize {
+        match self {
+            Func::Min | Func::Max => 2,
+            Func::Clamp | Func::If => 3,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Or,
+}
+
+impl BinOp {
+    fn precedence(self) -> u8 {
+        match self {
+            BinOp::Or => 1,
+            BinOp::And => 2,
+            BinOp::Eq | BinOp::Ne => 3,
+            BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => 4,
+            BinOp::Add | BinOp::Sub => 5,
+            BinOp::Mul | BinOp::Div | BinOp::Rem => 6,
+        }
+    }
+}
+
+/// A small expression language for deriving params from the current heart rate status.
+///
+/// Supports `+ - * / %`, comparisons, `&& || !`, parentheses, and a handful of functions
+/// (`min`, `max`, `clamp`, `abs`, `round`, `floor`, `ceil`, `int`, `float`, `if`).
+///
+/// Comparisons and logic result in a Bool, division always results in a Float,
+/// otherwise Ints stay Ints unless mixed with a Float.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Expr {
+    Literal(Value),
+    Var(Var),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Call(Func, Vec<Expr>),
+}
+
+impl Expr {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.expression(0)?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => Err(format!("Unexpected {token}")),
+        }
+    }
+
+    pub fn eval(&self, hr_status: &HeartRateStatus) -> Value {
+        match self {
+            Expr::Literal(value) => *value,
+            Expr::Var(var) => var.value(hr_status),
+            Expr::Neg(inner) => match inner.eval(hr_status) {
+                Value::Float(f) => Value::Float(-f),
+                other => Value::Int(other.as_i32().wrapping_neg()),
+            },
+            Expr::Not(inner) => Value::Bool(!inner.eval(hr_status).truthy()),
+            Expr::Binary(op, lhs, rhs) => binary(*op, lhs.eval(hr_status), rhs.eval(hr_status)),
+            Expr::Call(func, args) => {
+                let args: Vec<Value> = args.iter().map(|arg| arg.eval(hr_status)).collect();
+                call(*func, &args)
+            }
+        }
+    }
+}
+
+fn binary(op: BinOp, lhs: Value, rhs: Value) -> Value {
+    let both_int = !matches!(lhs, Value::Float(_)) && !matches!(rhs, Value::Float(_));
+    match op {
+        BinOp::And => Value::Bool(lhs.truthy() && rhs.truthy()),
+        BinOp::Or => Value::Bool(lhs.truthy() || rhs.truthy()),
+        BinOp::Lt => Value::Bool(lhs.as_f32() < rhs.as_f32()),
+        BinOp::Le => Value::Bool(lhs.as_f32() <= rhs.as_f32()),
+        BinOp::Gt => Value::Bool(lhs.as_f32() > rhs.as_f32()),
+        BinOp::Ge => Value::Bool(lhs.as_f32() >= rhs.as_f32()),
+        BinOp::Eq => Value::Bool(lhs.as_f32() == rhs.as_f32()),
+        BinOp::Ne => Value::Bool(lhs.as_f32() != rhs.as_f32()),
+        BinOp::Div => Value::Float(lhs.as_f32() / rhs.as_f32()),
+        BinOp::Add if both_int => Value::Int(lhs.as_i32().wrapping_add(rhs.as_i32())),
+        BinOp::Sub if both_int => Value::Int(lhs.as_i32().wrapping_sub(rhs.as_i32())),
+        BinOp::Mul if both_int => Value::Int(lhs.as_i32().wrapping_mul(rhs.as_i32())),
+        // Not worth stopping the world over a modulo by zero
+        BinOp::Rem if both_int => Value::Int(lhs.as_i32().checked_rem(rhs.as_i32()).unwrap_or(0)),
+        BinOp::Add => Value::Float(lhs.as_f32() + rhs.as_f32()),
+        BinOp::Sub => Value::Float(lhs.as_f32() - rhs.as_f32()),
+        BinOp::Mul => Value::Float(lhs.as_f32() * rhs.as_f32()),
+        BinOp::Rem => Value::Float(lhs.as_f32() % rhs.as_f32()),
+    }
+}
+
+fn call(func: Func, args: &[Value]) -> Value {
+    let pick = |take_lhs: bool, lhs: Value, rhs: Value| {
+        let both_int = !matches!(lhs, Value::Float(_)) && !matches!(rhs, Value::Float(_));
+        let picked = if take_lhs { lhs } else { rhs };
+        if both_int {
+            Value::Int(picked.as_i32())
+        } else {
+            Value::Float(picked.as_f32())
+        }
+    };
+    match func {
+        Func::Min => pick(args[0].as_f32() <= args[1].as_f32(), args[0], args[1]),
+        Func::Max => pick(args[0].as_f32() >= args[1].as_f32(), args[0], args[1]),
+        Func::Clamp => {
+            let low = call(Func::Max, &[args[0], args[1]]);
+            call(Func::Min, &[low, args[2]])
+        }
+        Func::Abs => match args[0] {
+            Value::Float(f) => Value::Float(f.abs()),
+            other => Value::Int(other.as_i32().wrapping_abs()),
+        },
+        Func::Round => Value::Int(args[0].as_f32().round() as i32),
+        Func::Floor => Value::Int(args[0].as_f32().floor() as i32),
+        Func::Ceil => Value::Int(args[0].as_f32().ceil() as i32),
+        Func::Int => Value::Int(args[0].as_i32()),
+        Func::Float => Value::Float(args[0].as_f32()),
+        Func::If => {
+            if args[0].truthy() {
+                args[1]
+            } else {
+                args[2]
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(Value),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+    Comma,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(Value::Int(i)) => write!(f, "'{i}'"),
+            Token::Number(value) => write!(f, "'{}'", value.as_f32()),
+            Token::Ident(name) => write!(f, "'{name}'"),
+            Token::Op(op) => write!(f, "'{op}'"),
+            Token::LParen => write!(f, "'('"),
+            Token::RParen => write!(f, "')'"),
+            Token::Comma => write!(f, "','"),
+        }
+    }
+}
+
+const OPERATORS: [&str; 15] = [
+    "&&", "||", "<=", ">=", "==", "!=", "<", ">", "+", "-", "*", "/", "%", "!", "=",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = source.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_ascii_digit() || c == '.' {
+            let len = rest
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(rest.len());
+            let number = &rest[..len];
+            let value = if number.contains('.') {
+                number.parse().map(Value::Float).ok()
+            } else {
+                number.parse().map(Value::Int).ok()
+            };
+            tokens.push(Token::Number(
+                value.ok_or_else(|| format!("Invalid number \"{number}\""))?,
+            ));
+            len
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..len].to_owned()));
+            len
+        } else if c == '(' {
+            tokens.push(Token::LParen);
+            1
+        } else if c == ')' {
+            tokens.push(Token::RParen);
+            1
+        } else if c == ',' {
+            tokens.push(Token::Comma);
+            1
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+            // Only there to give a better error than "unexpected character"
+            if *op == "=" {
+                return Err("Use \"==\" to compare values".to_owned());
+            }
+            tokens.push(Token::Op(op));
+            op.len()
+        } else {
+            return Err(format!("Unexpected character '{c}'"));
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(format!("Expected {expected}, found {token}")),
+            None => Err(format!("Expected {expected}, found end of expression")),
+        }
+    }
+    fn peek_binary_op(&self) -> Option<BinOp> {
+        let Some(Token::Op(op)) = self.peek() else {
+            return None;
+        };
+        Some(match *op {
+            "+" => BinOp::Add,
+            "-" => BinOp::Sub,
+            "*" => BinOp::Mul,
+            "/" => BinOp::Div,
+            "%" => BinOp::Rem,
+            "<" => BinOp::Lt,
+            "<=" => BinOp::Le,
+            ">" => BinOp::Gt,
+            ">=" => BinOp::Ge,
+            "==" => BinOp::Eq,
+            "!=" => BinOp::Ne,
+            "&&" => BinOp::And,
+            "||" => BinOp::Or,
+            _ => return None,
+        })
+    }
+    // Precedence climbing, all binary ops are left associative
+    fn expression(&mut self, min_precedence: u8) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        while let Some(op) = self.peek_binary_op() {
+            if op.precedence() <= min_precedence {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.expression(op.precedence())?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Op("-")) => Ok(Expr::Neg(Box::new(self.unary()?))),
+            Some(Token::Op("!")) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(Token::Number(value)) => Ok(Expr::Literal(value)),
+            Some(Token::LParen) => {
+                let expr = self.expression(0)?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" => Ok(Expr::Literal(Value::Bool(true))),
+                "false" => Ok(Expr::Literal(Value::Bool(false))),
+                _ if self.peek() == Some(&Token::LParen) => self.call(&name),
+                _ => Var::from_name(&name)
+                    .map(Expr::Var)
+                    .ok_or_else(|| format!("Unknown variable \"{name}\"")),
+            },
+            Some(token) => Err(format!("Unexpected {token}")),
+            None => Err("Unexpected end of expression".to_owned()),
+        }
+    }
+    fn call(&mut self, name: &str) -> Result<Expr, String> {
+        let func = Func::from_name(name).ok_or_else(|| format!("Unknown function \"{name}\""))?;
+        self.expect(Token::LParen)?;
+        let mut args = Vec::new();
+        if self.peek() != Some(&Token::RParen) {
+            loop {
+                args.push(self.expression(0)?);
+                if self.peek() != Some(&Token::Comma) {
+                    break;
+                }
+                self.pos += 1;
+            }
+        }
+        self.expect(Token::RParen)?;
+        if args.len() != func.arg_count() {
+            return Err(format!(
+                "\"{name}\" takes {} argument(s), got {}",
+                func.arg_count(),
+                args.len()
+            ));
+        }
+        Ok(Expr::Call(func, args))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn eval(source: &str, bpm: u16) -> Value {
+        let hr_status = HeartRateStatus {
+            heart_rate_bpm: bpm,
+            rr_intervals: vec![Duration::from_millis(750)],
+            ..Default::default()
+        };
+        Expr::parse(source).unwrap().eval(&hr_status)
+    }
+
+    #[test]
+    fn examples_from_docs() {
+        assert_eq!(eval("bpm / 2", 150), Value::Float(75.0));
+        assert_eq!(eval("bpm > 150", 151), Value::Bool(true));
+        assert_eq!(eval("bpm > 150", 150), Value::Bool(false));
+    }
+    #[test]
+    fn precedence_and_types() {
+        assert_eq!(eval("1 + 2 * 3", 0), Value::Int(7));
+        assert_eq!(eval("(1 + 2) * 3", 0), Value::Int(9));
+        assert_eq!(eval("10 - 2 - 3", 0), Value::Int(5));
+        assert_eq!(eval("-bpm + 1.5", 2), Value::Float(-0.5));
+        assert_eq!(eval("bpm % 7", 100), Value::Int(2));
+        assert_eq!(eval("bpm % 0", 100), Value::Int(0));
+        assert_eq!(
+            eval("bpm >= 60 && bpm < 100 || !connected", 80),
+            Value::Bool(true)
+        );
+    }
+    #[test]
+    fn functions() {
+        assert_eq!(eval("clamp(bpm, 60, 180)", 200), Value::Int(180));
+        assert_eq!(eval("min(bpm, 0.5)", 200), Value::Float(0.5));
+        assert_eq!(eval("round(rr / 100)", 0), Value::Int(8));
+        assert_eq!(eval("if(bpm > 100, 1, 0)", 120), Value::Int(1));
+        assert_eq!(eval("float(bpm)", 3), Value::Float(3.0));
+    }
+    #[test]
+    fn parse_errors() {
+        assert!(Expr::parse("").is_err());
+        assert!(Expr::parse("bpm +").is_err());
+        assert!(Expr::parse("(bpm").is_err());
+        assert!(Expr::parse("bpm bpm").is_err());
+        assert!(Expr::parse("bmp * 2").unwrap_err().contains("bmp"));
+        assert!(Expr::parse("nope(bpm)").unwrap_err().contains("nope"));
+        assert!(Expr::parse("min(bpm)").is_err());
+        assert!(Expr::parse("bpm = 2").unwrap_err().contains("=="));
+        assert!(Expr::parse("bpm # 2").is_err());
+    }
+    #[test]
+    fn custom_param_addresses() {
+        let mut params = BTreeMap::new();
+        params.insert("HRHalf".to_owned(), "bpm / 2".to_owned());
+        let built = CustomParam::build_all("/avatar/parameters/", &params).unwrap();
+        assert_eq!(built[0].address, "/avatar/parameters/HRHalf");
+
+        params.insert("Broken".to_owned(), "bpm +".to_owned());
+        assert!(matches!(
+            CustomParam::build_all("/avatar/parameters/", &params),
+            Err(AppError::OscExpression { name, .. }) if name == "Broken"
+        ));
+    }
+}
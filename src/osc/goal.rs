@@ -0,0 +1,83 @@
+use tokio::time::{Duration, Instant};
+
+// Gaps longer than this between updates (i.e. a disconnection) aren't counted
+const MAX_UPDATE_GAP: Duration = Duration::from_secs(5);
+
+/// Tracks time spent towards `osc.session_goal_min`,
+/// only counting updates where the BPM is within the goal's range.
+pub(super) struct SessionGoal {
+    target: Duration,
+    min_bpm: u16,
+    max_bpm: u16,
+    accumulated: Duration,
+    last_update: Option<Instant>,
+}
+
+impl SessionGoal {
+    pub fn new(target: Duration, min_bpm: u16, max_bpm: u16) -> Self {
+        Self {
+            target,
+            min_bpm,
+            max_bpm,
+            accumulated: Duration::ZERO,
+            last_update: None,
+        }
+    }
+    /// Counts the time since the last update if it was in range,
+    /// and returns progress from 0.0 to 1.0.
+    pub fn update(&mut self, bpm: u16, now: Instant) -> f32 {
+        let in_range = bpm > 0 && (self.min_bpm..=self.max_bpm).contains(&bpm);
+        if let Some(last_update) = self.last_update {
+            let gap = now.saturating_duration_since(last_update);
+            if in_range && gap <= MAX_UPDATE_GAP {
+                self.accumulated += gap;
+            }
+        }
+        self.last_update = in_range.then_some(now);
+        self.progress()
+    }
+    pub fn progress(&self) -> f32 {
+        if self.target.is_zero() {
+            return 1.0;
+        }
+        (self.accumulated.as_secs_f32() / self.target.as_secs_f32()).min(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEC: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn counts_time_in_range() {
+        let mut goal = SessionGoal::new(SEC * 10, 100, 150);
+        let start = Instant::now();
+        assert_eq!(goal.update(120, start), 0.0);
+        assert_eq!(goal.update(120, start + SEC * 2), 0.2);
+        // Out of range, the time leading up to it isn't counted
+        assert_eq!(goal.update(90, start + SEC * 3), 0.2);
+        assert_eq!(goal.update(120, start + SEC * 4), 0.2);
+        assert_eq!(goal.update(130, start + SEC * 5), 0.3);
+    }
+    #[test]
+    fn skips_disconnections() {
+        let mut goal = SessionGoal::new(SEC * 10, 0, 255);
+        let start = Instant::now();
+        goal.update(120, start);
+        goal.update(120, start + SEC);
+        // Long gap without updates
+        assert_eq!(goal.update(120, start + SEC * 60), 0.1);
+        assert_eq!(goal.update(0, start + SEC * 61), 0.1);
+    }
+    #[test]
+    fn caps_at_one() {
+        let mut goal = SessionGoal::new(SEC * 2, 0, 255);
+        let start = Instant::now();
+        for i in 0..10 {
+            goal.update(80, start + SEC * i);
+        }
+        assert_eq!(goal.progress(), 1.0);
+    }
+}
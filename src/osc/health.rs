@@ -0,0 +1,90 @@
+use crate::heart_rate::ble::BleReconnect;
+
+/// Sent as the `health` Int param, so avatars can tell the bridge itself
+/// having trouble apart from there simply being no heart rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum BridgeHealth {
+    // Sent on shutdown, so it's left behind when the app isn't running
+    Offline = 0,
+    Ok = 1,
+    // Source is fine, but not reading anything (i.e. strap taken off)
+    NoHeartRate = 2,
+    // Connection to the HR source was lost and is being retried
+    SourceLost = 3,
+    // Sends are failing, anything received might be stale
+    Degraded = 4,
+}
+
+/// Keeps track of what the OSC actor has seen to work out the `BridgeHealth`.
+#[derive(Debug, Default)]
+pub(super) struct HealthTracker {
+    heart_rate: bool,
+    source_lost: bool,
+    degraded: bool,
+    last_sent: Option<BridgeHealth>,
+}
+
+impl HealthTracker {
+    pub fn current(&self) -> BridgeHealth {
+        if self.degraded {
+            BridgeHealth::Degraded
+        } else if self.source_lost {
+            BridgeHealth::SourceLost
+        } else if !self.heart_rate {
+            BridgeHealth::NoHeartRate
+        } else {
+            BridgeHealth::Ok
+        }
+    }
+    pub fn set_bpm(&mut self, bpm: u16) {
+        self.heart_rate = bpm > 0;
+        // Data coming in means we're connected, no matter what we were told last
+        if self.heart_rate {
+            self.source_lost = false;
+        }
+    }
+    pub fn set_reconnect(&mut self, status: BleReconnect) {
+        self.source_lost = status != BleReconnect::Connected;
+    }
+    pub fn set_degraded(&mut self, degraded: bool) {
+        self.degraded = degraded;
+    }
+    /// Returns the health if it's changed since it was last sent.
+    pub fn changed(&mut self) -> Option<BridgeHealth> {
+        let current = self.current();
+        (self.last_sent != Some(current)).then(|| {
+            self.last_sent = Some(current);
+            current
+        })
+    }
+    /// Makes sure the next `changed` sends it, i.e. after switching presets.
+    pub fn force_resend(&mut self) {
+        self.last_sent = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn priority_and_changes() {
+        let mut health = HealthTracker::default();
+        assert_eq!(health.changed(), Some(BridgeHealth::NoHeartRate));
+        health.set_bpm(70);
+        assert_eq!(health.changed(), Some(BridgeHealth::Ok));
+        assert_eq!(health.changed(), None);
+        health.set_reconnect(BleReconnect::Retrying {
+            attempt: 1,
+            delay: Duration::from_secs(2),
+        });
+        health.set_bpm(0);
+        assert_eq!(health.changed(), Some(BridgeHealth::SourceLost));
+        health.set_degraded(true);
+        assert_eq!(health.changed(), Some(BridgeHealth::Degraded));
+        health.set_degraded(false);
+        health.set_bpm(72);
+        assert_eq!(health.changed(), Some(BridgeHealth::Ok));
+    }
+}
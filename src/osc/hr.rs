@@ -1,88 +1,120 @@
 use crate::heart_rate::HeartRateStatus;
 use rand::Rng;
-use rosc::encoder;
-use rosc::{OscBundle, OscMessage, OscPacket, OscType};
+use rosc::{OscBundle, OscMessage, OscPacket, OscTime, OscType};
 use std::f32;
+use std::time::Duration;
+use tokio::time::Instant;
 
 use super::addresses::OscAddresses;
-use super::OSC_NOW;
-
-use std::net::{SocketAddrV4, UdpSocket};
+use super::expr::CustomParam;
+use super::sender::OscSender;
 
 use crate::errors::AppError;
+use crate::settings::{BeatStyle, RrDelivery};
 
-pub(super) fn send_raw_hr_status(
-    hr_status: &HeartRateStatus,
-    hiding_disconnect: bool,
-    delay_sending_connected: bool,
-    positive_float_bpm: bool,
+// Params left as None aren't sent, nor is an empty bundle
+pub(super) fn send_raw_beat_params(
+    pulse_edge: Option<bool>,
+    toggle_beat: Option<bool>,
     osc_addresses: &OscAddresses,
-    socket: &UdpSocket,
-    target_addr: SocketAddrV4,
+    sender: &OscSender,
 ) -> Result<(), AppError> {
-    let bundle = form_bpm_bundle(
-        hr_status,
-        hiding_disconnect,
-        delay_sending_connected,
-        positive_float_bpm,
-        osc_addresses,
-    );
-    let msg_buf = encoder::encode(&OscPacket::Bundle(bundle))?;
-    socket.send_to(&msg_buf, target_addr)?;
-    Ok(())
+    let mut bundle = sender.bundle();
+
+    if let Some(pulse_edge) = pulse_edge {
+        let pulse_msg = OscMessage {
+            addr: osc_addresses.beat_pulse.clone(),
+            args: vec![OscType::Bool(pulse_edge)],
+        };
+        bundle.content.push(OscPacket::Message(pulse_msg));
+    }
+
+    if let Some(toggle_beat) = toggle_beat {
+        let toggle_msg = OscMessage {
+            addr: osc_addresses.beat_toggle.clone(),
+            args: vec![OscType::Bool(toggle_beat)],
+        };
+        bundle.content.push(OscPacket::Message(toggle_msg));
+    }
+
+    sender.send(bundle)
 }
 
-pub(super) fn send_raw_beat_params(
-    pulse_edge: bool,
-    toggle_beat: bool,
+pub(super) fn send_raw_activity_param(
+    new_index: u8,
     osc_addresses: &OscAddresses,
-    socket: &UdpSocket,
-    target_addr: SocketAddrV4,
+    sender: &OscSender,
 ) -> Result<(), AppError> {
-    let mut bundle = OscBundle {
-        timetag: OSC_NOW,
-        content: vec![],
-    };
+    send_raw_int_param(&osc_addresses.activity, new_index as i32, sender)
+}
 
-    let pulse_msg = OscMessage {
-        addr: osc_addresses.beat_pulse.clone(),
-        args: vec![OscType::Bool(pulse_edge)],
-    };
+pub(super) fn send_raw_int_param(
+    address: &str,
+    value: i32,
+    sender: &OscSender,
+) -> Result<(), AppError> {
+    let mut bundle = sender.bundle();
 
-    let toggle_msg = OscMessage {
-        addr: osc_addresses.beat_toggle.clone(),
-        args: vec![OscType::Bool(toggle_beat)],
+    let int_msg = OscMessage {
+        addr: address.to_owned(),
+        args: vec![OscType::Int(value)],
     };
 
-    bundle.content.push(OscPacket::Message(pulse_msg));
-    bundle.content.push(OscPacket::Message(toggle_msg));
+    bundle.content.push(OscPacket::Message(int_msg));
 
-    let msg_buf = encoder::encode(&OscPacket::Bundle(bundle))?;
-    socket.send_to(&msg_buf, target_addr)?;
-    Ok(())
+    sender.send(bundle)
 }
 
-pub(super) fn send_raw_activity_param(
-    new_index: u8,
-    osc_addresses: &OscAddresses,
-    socket: &UdpSocket,
-    target_addr: SocketAddrV4,
+pub(super) fn send_raw_float_param(
+    address: &str,
+    value: f32,
+    sender: &OscSender,
 ) -> Result<(), AppError> {
-    let mut bundle = OscBundle {
-        timetag: OSC_NOW,
-        content: vec![],
-    };
+    let mut bundle = sender.bundle();
 
-    let activity_msg = OscMessage {
-        addr: osc_addresses.activity.clone(),
-        args: vec![OscType::Int(new_index as i32)],
+    let float_msg = OscMessage {
+        addr: address.to_owned(),
+        args: vec![OscType::Float(value)],
     };
 
-    bundle.content.push(OscPacket::Message(activity_msg));
+    bundle.content.push(OscPacket::Message(float_msg));
+
+    sender.send(bundle)
+}
+
+pub(super) fn send_raw_custom_params(
+    custom_params: &[CustomParam],
+    hr_status: &HeartRateStatus,
+    sender: &OscSender,
+) -> Result<(), AppError> {
+    let mut bundle = sender.bundle();
+
+    for param in custom_params {
+        let msg = OscMessage {
+            addr: param.address.clone(),
+            args: vec![param.expr.eval(hr_status).into()],
+        };
+        bundle.content.push(OscPacket::Message(msg));
+    }
+
+    sender.send(bundle)
+}
 
-    let msg_buf = encoder::encode(&OscPacket::Bundle(bundle))?;
-    socket.send_to(&msg_buf, target_addr)?;
-    Ok(())
+/// Which of the beat bools get sent on an edge, given the chosen style.
+///
+/// Returns: (pulse, toggle)
+pub(super) fn beat_params_for_style(
+    beat_style: BeatStyle,
+    pulse_edge: bool,
+    toggle_edge: bool,
+) -> (Option<bool>, Option<bool>) {
+    match beat_style {
+        BeatStyle::PulseAndToggle => (Some(pulse_edge), Some(toggle_edge)),
+        BeatStyle::Pulse => (Some(pulse_edge), None),
+        // Toggle only changes on the rising edge, no need to repeat it
+        BeatStyle::Toggle => (None, pulse_edge.then_some(toggle_edge)),
+        BeatStyle::Envelope => (None, None),
+    }
 }
 
 pub(super) fn make_mimic_data(hr_status: &HeartRateStatus) -> HeartRateStatus {
@@ -101,10 +133,12 @@ pub(super) fn form_bpm_bundle(
     hiding_disconnect: bool,
     delay_sending_connected: bool,
     positive_float_bpm: bool,
+    rr_delivery: RrDelivery,
     osc_addresses: &OscAddresses,
+    timetag: OscTime,
 ) -> OscBundle {
     let mut bundle = OscBundle {
-        timetag: OSC_NOW,
+        timetag,
         content: vec![],
     };
 
@@ -113,11 +147,7 @@ pub(super) fn form_bpm_bundle(
         args: vec![OscType::Int(hr_status.heart_rate_bpm as i32)],
     };
 
-    let hr_float = if positive_float_bpm {
-        hr_status.heart_rate_bpm as f32 / 255.0
-    } else {
-        (hr_status.heart_rate_bpm as f32 / 255.0) * 2.0 - 1.0
-    };
+    let hr_float = bpm_to_float(hr_status.heart_rate_bpm as f32, positive_float_bpm);
     let bpm_float_msg = OscMessage {
         addr: osc_addresses.bpm_float.clone(),
         args: vec![OscType::Float(hr_float)],
@@ -157,12 +187,16 @@ pub(super) fn form_bpm_bundle(
             args: vec![OscType::Int(0)],
         };
         bundle.content.push(OscPacket::Message(rr_msg));
-    } else if let Some(&latest_rr) = hr_status.rr_intervals.last() {
-        let rr_msg = OscMessage {
-            addr: osc_addresses.latest_rr.clone(),
-            args: vec![OscType::Int((latest_rr.as_secs_f32() * 1000.0) as i32)],
-        };
-        bundle.content.push(OscPacket::Message(rr_msg));
+    } else {
+        bundle.content.extend(
+            form_rr_messages(
+                &hr_status.rr_intervals,
+                rr_delivery,
+                &osc_addresses.latest_rr,
+            )
+            .into_iter()
+            .map(OscPacket::Message),
+        );
     }
 
     let twitch_up_msg = OscMessage {
@@ -188,3 +222,168 @@ pub(super) fn form_bpm_bundle(
 
     bundle
 }
+
+/// Maps 0-255 BPM onto the float param's range, -1.0 to 1.0 (or 0.0 to 1.0 if `positive_float_bpm`)
+pub(super) fn bpm_to_float(bpm: f32, positive_float_bpm: bool) -> f32 {
+    if positive_float_bpm {
+        bpm / 255.0
+    } else {
+        (bpm / 255.0) * 2.0 - 1.0
+    }
+}
+
+fn rr_to_osc(rr: &Duration) -> OscType {
+    OscType::Int((rr.as_secs_f32() * 1000.0) as i32)
+}
+
+/// Devices can send multiple RR intervals per notification,
+/// by default only the latest is sent, but HRV tools would want all of them.
+pub(super) fn form_rr_messages(
+    rr_intervals: &[Duration],
+    rr_delivery: RrDelivery,
+    address: &str,
+) -> Vec<OscMessage> {
+    let message = |args| OscMessage {
+        addr: address.to_owned(),
+        args,
+    };
+    match rr_delivery {
+        // Already averaged down by `RrMeanWindow`
+        RrDelivery::Latest | RrDelivery::Mean => rr_intervals
+            .last()
+            .map(|rr| message(vec![rr_to_osc(rr)]))
+            .into_iter()
+            .collect(),
+        RrDelivery::Each => rr_intervals
+            .iter()
+            .map(|rr| message(vec![rr_to_osc(rr)]))
+            .collect(),
+        RrDelivery::MultiArg if rr_intervals.is_empty() => Vec::new(),
+        RrDelivery::MultiArg => vec![message(rr_intervals.iter().map(rr_to_osc).collect())],
+    }
+}
+
+/// How one OSC target wants its RR intervals.
+pub(super) struct RrTarget {
+    pub delivery: RrDelivery,
+    // Only present if `delivery` is Mean
+    pub mean_window: Option<RrMeanWindow>,
+}
+
+impl RrTarget {
+    pub fn new(delivery: RrDelivery, mean_window_ms: u16) -> Self {
+        let mean_window = (delivery == RrDelivery::Mean)
+            .then(|| RrMeanWindow::new(Duration::from_millis(mean_window_ms as u64)));
+        Self {
+            delivery,
+            mean_window,
+        }
+    }
+}
+
+/// Collects RR intervals for `RrDelivery::Mean`, handing out their average once per window.
+pub(super) struct RrMeanWindow {
+    window: Duration,
+    pending: Vec<Duration>,
+    started: Option<Instant>,
+}
+
+impl RrMeanWindow {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            pending: Vec::new(),
+            started: None,
+        }
+    }
+    /// Returns the mean once the window's passed, and starts a new one.
+    pub fn push(&mut self, rr_intervals: &[Duration], now: Instant) -> Option<Duration> {
+        self.pending.extend_from_slice(rr_intervals);
+        let started = *self.started.get_or_insert(now);
+        if self.pending.is_empty() || now.saturating_duration_since(started) < self.window {
+            return None;
+        }
+        let mean = self.pending.iter().sum::<Duration>() / self.pending.len() as u32;
+        self.pending.clear();
+        self.started = Some(now);
+        Some(mean)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDR: &str = "/avatar/parameters/RRInterval";
+
+    fn rr_args(messages: &[OscMessage]) -> Vec<Vec<OscType>> {
+        messages.iter().map(|msg| msg.args.clone()).collect()
+    }
+
+    #[test]
+    fn rr_latest_only() {
+        let rr = [Duration::from_millis(800), Duration::from_millis(750)];
+        let messages = form_rr_messages(&rr, RrDelivery::Latest, ADDR);
+        assert_eq!(rr_args(&messages), vec![vec![OscType::Int(750)]]);
+    }
+    #[test]
+    fn rr_each_interval() {
+        let rr = [Duration::from_millis(800), Duration::from_millis(750)];
+        let messages = form_rr_messages(&rr, RrDelivery::Each, ADDR);
+        assert_eq!(
+            rr_args(&messages),
+            vec![vec![OscType::Int(800)], vec![OscType::Int(750)]]
+        );
+        assert!(messages.iter().all(|msg| msg.addr == ADDR));
+    }
+    #[test]
+    fn rr_multi_arg() {
+        let rr = [Duration::from_millis(800), Duration::from_millis(750)];
+        let messages = form_rr_messages(&rr, RrDelivery::MultiArg, ADDR);
+        assert_eq!(
+            rr_args(&messages),
+            vec![vec![OscType::Int(800), OscType::Int(750)]]
+        );
+    }
+    #[test]
+    fn beat_style_params() {
+        use BeatStyle::*;
+        assert_eq!(
+            beat_params_for_style(PulseAndToggle, true, false),
+            (Some(true), Some(false))
+        );
+        assert_eq!(
+            beat_params_for_style(Pulse, false, true),
+            (Some(false), None)
+        );
+        assert_eq!(
+            beat_params_for_style(Toggle, true, true),
+            (None, Some(true))
+        );
+        assert_eq!(beat_params_for_style(Toggle, false, true), (None, None));
+        assert_eq!(beat_params_for_style(Envelope, true, true), (None, None));
+    }
+    #[test]
+    fn rr_mean_window() {
+        let ms = Duration::from_millis;
+        let mut window = RrMeanWindow::new(ms(1000));
+        let start = Instant::now();
+        assert_eq!(window.push(&[ms(800)], start), None);
+        assert_eq!(window.push(&[ms(700), ms(900)], start + ms(500)), None);
+        assert_eq!(window.push(&[], start + ms(1000)), Some(ms(800)));
+        // Nothing came in during the next window
+        assert_eq!(window.push(&[], start + ms(2000)), None);
+        assert_eq!(window.push(&[ms(600)], start + ms(2100)), Some(ms(600)));
+    }
+    #[test]
+    fn rr_empty() {
+        for delivery in [
+            RrDelivery::Latest,
+            RrDelivery::Each,
+            RrDelivery::MultiArg,
+            RrDelivery::Mean,
+        ] {
+            assert!(form_rr_messages(&[], delivery, ADDR).is_empty());
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use chrono::Local;
+use rosc::decoder::{decode_udp, MTU};
+use std::net::SocketAddr;
+use tokio::net::UdpSocket;
+
+use super::sender::describe_packets;
+use crate::errors::AppError;
+
+/// Prints any OSC traffic arriving at `addr` until Ctrl+C,
+/// for checking what (if anything) is making it to a port.
+pub async fn listen(addr: SocketAddr) -> Result<(), AppError> {
+    let socket = UdpSocket::bind(addr)
+        .await
+        .map_err(|e| AppError::OscBind { addr, source: e })?;
+    println!(
+        "Listening for OSC on {}, press Ctrl+C to stop",
+        socket.local_addr()?
+    );
+    let mut buf = [0u8; MTU];
+    loop {
+        let (size, from) = tokio::select! {
+            received = socket.recv_from(&mut buf) => received?,
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        let time = Local::now().format("%H:%M:%S%.3f");
+        match decode_udp(&buf[..size]) {
+            Ok((_, packet)) => {
+                for line in describe_packets(&[packet]) {
+                    println!("[{time}] {from} {line}");
+                }
+            }
+            Err(e) => println!("[{time}] {from} Couldn't decode {size} bytes: {e}"),
+        }
+    }
+    Ok(())
+}
@@ -1,22 +1,54 @@
 use addresses::OscAddresses;
-use hr::{make_mimic_data, send_raw_activity_param, send_raw_beat_params, send_raw_hr_status};
+use hr::{
+    beat_params_for_style, form_bpm_bundle, make_mimic_data, send_raw_activity_param,
+    send_raw_beat_params, send_raw_custom_params, send_raw_float_param, send_raw_int_param,
+    RrTarget,
+};
 use rosc::OscTime;
-use std::net::{SocketAddrV4, UdpSocket};
-use std::str::FromStr;
+use std::net::{SocketAddr, UdpSocket};
 use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::broadcast::{Receiver as BReceiver, Sender as BSender};
 use tokio::time::{self, interval, Duration, Instant, Interval};
 use tokio_util::sync::CancellationToken;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 
 use crate::app::{AppUpdate, ErrorPopup};
 use crate::broadcast;
 use crate::errors::AppError;
-use crate::heart_rate::{rr_from_bpm, HeartRateStatus};
-use crate::settings::OscSettings;
+use crate::heart_rate::{bpm_from_rr, rr_from_bpm, HeartRateStatus};
+use crate::journal::{self, Severity, Subsystem};
+use crate::settings::{BeatStyle, OscAddrConf, OscSettings};
+use calibration::CalibrationSweep;
+use envelope::ENVELOPE_STEP;
+use expr::CustomParam;
+use goal::SessionGoal;
+use health::{BridgeHealth, HealthTracker};
+use oscquery::OscQuery;
+use send_health::SendHealth;
+use sender::OscSender;
+use std::collections::BTreeMap;
+use waveform::EcgSweep;
 
 mod addresses;
+mod calibration;
+mod envelope;
+mod expr;
+mod goal;
+mod health;
 mod hr;
+mod listen;
+mod oscquery;
+mod send_health;
+mod sender;
+mod target;
+mod waveform;
+
+pub use envelope::BeatEnvelope;
+pub use listen::listen;
+pub use sender::{new_tap_log, OscTapLog};
+
+// How often the float param is updated during a calibration sweep
+const CALIBRATION_STEP: Duration = Duration::from_millis(50);
 
 const OSC_NOW: OscTime = OscTime {
     seconds: 0,
@@ -25,12 +57,20 @@ const OSC_NOW: OscTime = OscTime {
 
 struct OscActor {
     // I/O and current data
-    target_addr: SocketAddrV4,
+    broadcast_tx: BSender<AppUpdate>,
     hr_status: HeartRateStatus,
     //
     osc_settings: OscSettings,
-    socket: UdpSocket,
+    sender: OscSender,
     osc_addresses: OscAddresses,
+    // User defined params from `osc.custom_params`
+    custom_params: Vec<CustomParam>,
+    // `osc.presets`, already merged with `osc.addresses` and checked
+    presets: BTreeMap<String, OscAddrConf>,
+    // Backs off the beat params when the target keeps being unreachable
+    send_health: SendHealth,
+    // What gets reported through the `health` param
+    bridge_health: HealthTracker,
     // Used to delay the connected bool by one update "cycle",
     // as otherwise a value of "0" can sneak in on the display.
     delay_sending_connected: bool,
@@ -44,80 +84,457 @@ struct OscActor {
     beat_pulse: Duration,
     pulse_edge: bool,
     toggle_edge: bool,
+    // Only present if the ECG waveform param is enabled,
+    // ticks through the samples during each beat pulse
+    ecg_sweep: Option<EcgSweep>,
+    waveform_ticker: Interval,
+    // Only present if `beat_style` is Envelope
+    beat_envelope: Option<BeatEnvelope>,
+    envelope_ticker: Interval,
     disconnected_at: Option<Instant>,
     disconnect_update_interval: Interval,
+    // If `target_ip` is a hostname, it's looked up again every so often
+    // in case its IP changes (i.e. a headset on DHCP)
+    resolve_target: bool,
+    resolve_ticker: Interval,
     // Used when BLE connection is lost, but we don't want to
     // hide the BPM display in VRChat, we'll just bounce around
     // the last known actual value until we reconnect or time out.
     max_hide_disconnection: Duration,
+    // Only present if `session_goal_min` is set
+    session_goal: Option<SessionGoal>,
+    // The main target first, then `osc.extra_targets`, in the sender's order
+    rr_targets: Vec<RrTarget>,
+    // Only present while sweeping the float HR param for calibration,
+    // live data is held back until it's done
+    calibration: Option<CalibrationSweep>,
+    calibration_ticker: Interval,
     // TODO send with bpm
     activity: Option<u8>,
+    // Last zone from the App's zone engine
+    zone: Option<u8>,
 }
 
 impl OscActor {
-    fn build(initial_activity: Option<u8>, osc_settings: OscSettings) -> Result<Self, AppError> {
+    fn build(
+        broadcast_tx: BSender<AppUpdate>,
+        initial_activity: Option<u8>,
+        osc_settings: OscSettings,
+        tap_log: Option<OscTapLog>,
+    ) -> Result<Self, AppError> {
         let osc_addresses = OscAddresses::build(&osc_settings.addresses)?;
+        let custom_params =
+            CustomParam::build_all(&osc_settings.addresses.prefix, &osc_settings.custom_params)?;
+        let presets = osc_settings
+            .presets
+            .iter()
+            .map(|(name, preset)| {
+                let conf = osc_settings.addresses.with_overrides(&preset.addresses)?;
+                // Catching bad addresses now, rather than when switching to it
+                OscAddresses::build(&conf)?;
+                CustomParam::build_all(&conf.prefix, &osc_settings.custom_params)?;
+                Ok((name.clone(), conf))
+            })
+            .collect::<Result<_, AppError>>()?;
 
-        let host_addr = SocketAddrV4::from_str(&format!("{}:{}", osc_settings.host_ip, 0))?;
+        let host_ip = target::parse_ip(&osc_settings.host_ip).ok_or_else(|| {
+            AppError::OscResolve(osc_settings.host_ip.clone(), "Not an IP".into())
+        })?;
 
-        let target_addr =
-            SocketAddrV4::from_str(&format!("{}:{}", osc_settings.target_ip, osc_settings.port))?;
+        let candidates = target::resolve(&osc_settings.target_ip, osc_settings.port)?;
+        let target_addr = target::pick_target(&candidates, host_ip).ok_or_else(|| {
+            AppError::OscResolve(
+                osc_settings.target_ip.clone(),
+                format!("No address reachable from {host_ip}"),
+            )
+        })?;
+        if target::is_hostname(&osc_settings.target_ip) {
+            info!(
+                "OSC target {} resolved to {target_addr}",
+                osc_settings.target_ip
+            );
+        }
+
+        let host_addr = SocketAddr::new(
+            target::bind_ip_for(host_ip, target_addr),
+            osc_settings.host_port,
+        );
+        let socket = UdpSocket::bind(host_addr).map_err(|e| AppError::OscBind {
+            addr: host_addr,
+            source: e,
+        })?;
 
-        let socket = UdpSocket::bind(host_addr)?;
+        info!("OSC socket bound to {}", socket.local_addr()?);
+        if osc_settings.dry_run {
+            warn!("OSC dry-run mode is enabled, nothing will be sent to {target_addr}");
+        }
+        let mut rr_targets = vec![RrTarget::new(
+            osc_settings.rr_delivery,
+            osc_settings.rr_mean_window_ms,
+        )];
+        let mut extra_targets = Vec::new();
+        for extra in &osc_settings.extra_targets {
+            let ip = target::parse_ip(&extra.ip)
+                .ok_or_else(|| AppError::OscResolve(extra.ip.clone(), "Not an IP".into()))?;
+            extra_targets.push(SocketAddr::new(ip, extra.port));
+            let mean_window_ms = match extra.rr_mean_window_ms {
+                0 => osc_settings.rr_mean_window_ms,
+                ms => ms,
+            };
+            rr_targets.push(RrTarget::new(extra.rr_delivery, mean_window_ms));
+        }
+        let sender = OscSender::new(socket, target_addr, extra_targets, &osc_settings, tap_log);
 
         let beat_pulse_duration = Duration::from_millis(osc_settings.pulse_length_ms as u64);
         let positive_float_bpm = osc_settings.only_positive_float_bpm;
 
         let disconnect_update_interval = time::interval(Duration::from_secs(6));
 
+        // Only hostnames need re-resolving
+        let resolve_interval = Duration::from_secs(osc_settings.target_resolve_interval_sec as u64);
+        let resolve_target =
+            target::is_hostname(&osc_settings.target_ip) && !resolve_interval.is_zero();
+        let mut resolve_ticker = interval(resolve_interval.max(Duration::from_secs(1)));
+        resolve_ticker.reset();
+
+        let beat_envelope = (osc_settings.beat_style == BeatStyle::Envelope).then(|| {
+            BeatEnvelope::new(
+                Duration::from_millis(osc_settings.envelope_attack_ms as u64),
+                Duration::from_millis(osc_settings.envelope_decay_ms as u64),
+            )
+        });
+
+        let ecg_sweep = osc_settings.ecg_waveform_enabled.then(|| {
+            EcgSweep::new(
+                osc_settings.ecg_waveform_samples,
+                osc_settings.ecg_waveform_amplitude,
+            )
+        });
+
         let max_hide_disconnection =
             Duration::from_secs(osc_settings.max_hide_disconnection_sec as u64);
 
+        let session_goal = (osc_settings.session_goal_min > 0).then(|| {
+            SessionGoal::new(
+                Duration::from_secs(osc_settings.session_goal_min as u64 * 60),
+                osc_settings.session_goal_min_bpm,
+                osc_settings.session_goal_max_bpm,
+            )
+        });
+
         Ok(OscActor {
-            target_addr,
+            broadcast_tx,
             delay_sending_connected: true,
             positive_float_bpm,
             use_real_rr: false,
             latest_rr: Duration::from_secs(1),
-            socket,
+            sender,
             osc_settings,
             osc_addresses,
+            custom_params,
+            presets,
+            send_health: SendHealth::default(),
+            bridge_health: HealthTracker::default(),
             hr_status: HeartRateStatus::default(),
             heart_beat_ticker: interval(Duration::from_secs(1)),
             beat_pulse: beat_pulse_duration,
             pulse_edge: false,
             toggle_edge: false,
+            ecg_sweep,
+            waveform_ticker: interval(Duration::from_secs(1)),
+            beat_envelope,
+            envelope_ticker: interval(ENVELOPE_STEP),
             disconnected_at: None,
             disconnect_update_interval,
+            resolve_target,
+            resolve_ticker,
             max_hide_disconnection,
+            session_goal,
+            rr_targets,
+            calibration: None,
+            calibration_ticker: interval(CALIBRATION_STEP),
             activity: initial_activity,
+            zone: None,
         })
     }
+    // Advertises ourselves, and points the sender at VRChat if it can be found
+    async fn start_oscquery(&mut self) -> Result<OscQuery, AppError> {
+        let oscquery = OscQuery::start(self.sender.local_addr()?.port()).await?;
+        match oscquery.find_vrchat().await {
+            Ok(Some(target_addr)) => {
+                info!("OSCQuery: Found VRChat at {target_addr}");
+                self.sender.set_target(target_addr.into());
+                // Don't want the configured target coming back
+                self.resolve_target = false;
+            }
+            Ok(None) => warn!("OSCQuery: VRChat not found, using configured target"),
+            Err(e) => warn!("OSCQuery: Failed to look for VRChat, using configured target. {e}"),
+        }
+        Ok(oscquery)
+    }
     // Hides display on avatar and sets value to 0
     // Used on startup, disconnect, and shutdown
     fn init_params(&mut self) -> Result<(), AppError> {
         self.delay_sending_connected = true;
         self.toggle_edge = false;
-        send_raw_hr_status(
+        self.send_hr_status(&HeartRateStatus::default(), false, false, false)?;
+        let result = send_raw_custom_params(
+            &self.custom_params,
             &HeartRateStatus::default(),
-            false,
-            false,
-            self.positive_float_bpm,
-            &self.osc_addresses,
-            &self.socket,
-            self.target_addr,
-        )?;
-        send_raw_beat_params(
-            false,
-            false,
+            &self.sender,
+        );
+        self.track_send(result)?;
+        let (pulse, toggle) = beat_params_for_style(self.osc_settings.beat_style, false, false);
+        let result = send_raw_beat_params(
+            pulse,
+            // Toggle param is normally only sent on the rising edge
+            toggle.or((self.osc_settings.beat_style == BeatStyle::Toggle).then_some(false)),
             &self.osc_addresses,
-            &self.socket,
-            self.target_addr,
-        )?;
-        send_raw_activity_param(0, &self.osc_addresses, &self.socket, self.target_addr)?;
+            &self.sender,
+        );
+        self.track_send(result)?;
+        let result = send_raw_activity_param(0, &self.osc_addresses, &self.sender);
+        self.track_send(result)?;
+        if let Some(envelope) = self.beat_envelope.as_mut() {
+            envelope.stop();
+            let result = send_raw_float_param(&self.osc_addresses.beat_envelope, 0.0, &self.sender);
+            self.track_send(result)?;
+        }
+        if let Some(sweep) = self.ecg_sweep.as_mut() {
+            sweep.stop();
+            let result = send_raw_float_param(&self.osc_addresses.ecg_waveform, 0.0, &self.sender);
+            self.track_send(result)?;
+        }
+        Ok(())
+    }
+    /// Sends the HR bundle to each target, with RR intervals the way that target wants them.
+    ///
+    /// Only live data is `averaged` into mean windows, one-off resends and mimic data aren't.
+    fn send_hr_status(
+        &mut self,
+        hr_status: &HeartRateStatus,
+        hiding_disconnect: bool,
+        delay_sending_connected: bool,
+        averaged: bool,
+    ) -> Result<(), AppError> {
+        for index in 0..self.rr_targets.len() {
+            let target = &mut self.rr_targets[index];
+            // The averaged interval only goes out over OSC, the latest is still used for beats
+            let averaged_status;
+            let sent_status = match target.mean_window.as_mut() {
+                Some(window) if averaged => {
+                    averaged_status = HeartRateStatus {
+                        rr_intervals: window
+                            .push(&hr_status.rr_intervals, Instant::now())
+                            .into_iter()
+                            .collect(),
+                        ..hr_status.clone()
+                    };
+                    &averaged_status
+                }
+                _ => hr_status,
+            };
+            let bundle = form_bpm_bundle(
+                sent_status,
+                hiding_disconnect,
+                delay_sending_connected,
+                self.positive_float_bpm,
+                target.delivery,
+                &self.osc_addresses,
+                self.sender.timetag(),
+            );
+            let result = self.sender.send_to(index, bundle);
+            self.track_send(result)?;
+        }
+        Ok(())
+    }
+    // Send errors (like the target being unreachable) aren't fatal,
+    // they're counted to decide if we should hold back on the beat params for a bit.
+    // Anything else (like encoding errors) still is.
+    fn track_send(&mut self, result: Result<(), AppError>) -> Result<(), AppError> {
+        match result {
+            Ok(()) => {
+                if self.send_health.record_success() {
+                    info!("OSC: Sends succeeding again, resuming beat params");
+                    self.bridge_health.set_degraded(false);
+                    journal::record(Severity::Info, Subsystem::Osc, "Sends succeeding again");
+                    broadcast!(self.broadcast_tx, AppUpdate::OscDegraded(false));
+                }
+                Ok(())
+            }
+            Err(AppError::Io(e)) => {
+                debug!("OSC: Send failed: {e}");
+                if self.send_health.record_error(Instant::now()) {
+                    warn!("OSC: Sends repeatedly failing ({e}), backing off beat params");
+                    self.bridge_health.set_degraded(true);
+                    journal::record(
+                        Severity::Warning,
+                        Subsystem::Osc,
+                        &format!("Sends repeatedly failing: {e}"),
+                    );
+                    broadcast!(self.broadcast_tx, AppUpdate::OscDegraded(true));
+                }
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+    fn set_paused(&mut self, paused: bool) -> Result<(), AppError> {
+        if paused {
+            info!("OSC: Pausing output");
+            journal::record(Severity::Info, Subsystem::Osc, "Output paused");
+            // Leave the avatar showing as disconnected
+            self.init_params()?;
+            self.sender.set_paused(true);
+        } else {
+            info!("OSC: Resuming output");
+            journal::record(Severity::Info, Subsystem::Osc, "Output resumed");
+            self.sender.set_paused(false);
+            // Hold back the connected bool for a cycle, same as a fresh connection
+            self.delay_sending_connected = true;
+            self.resend_selections()?;
+        }
+        Ok(())
+    }
+    // Only sent when it changes
+    fn update_bridge_health(&mut self) -> Result<(), AppError> {
+        let Some(health) = self.bridge_health.changed() else {
+            return Ok(());
+        };
+        debug!("OSC: Bridge health is now {health:?}");
+        self.send_bridge_health(health)
+    }
+    fn send_bridge_health(&mut self, health: BridgeHealth) -> Result<(), AppError> {
+        let result = send_raw_int_param(&self.osc_addresses.health, health as i32, &self.sender);
+        self.track_send(result)
+    }
+    // Activity, zone, and health are only sent when they change
+    fn resend_selections(&mut self) -> Result<(), AppError> {
+        self.bridge_health.force_resend();
+        self.update_bridge_health()?;
+        if let Some(index) = self.activity {
+            let result = send_raw_activity_param(index, &self.osc_addresses, &self.sender);
+            self.track_send(result)?;
+        }
+        if let Some(zone) = self.zone {
+            let result = send_raw_int_param(&self.osc_addresses.zone, zone as i32, &self.sender);
+            self.track_send(result)?;
+        }
+        Ok(())
+    }
+    fn switch_preset(&mut self, preset: Option<String>) -> Result<(), AppError> {
+        let conf = match preset.as_ref() {
+            Some(name) => match self.presets.get(name) {
+                Some(conf) => conf,
+                None => {
+                    warn!("OSC: No preset named {name}");
+                    return Ok(());
+                }
+            },
+            None => &self.osc_settings.addresses,
+        };
+        self.osc_addresses = OscAddresses::build(conf)?;
+        self.custom_params =
+            CustomParam::build_all(&conf.prefix, &self.osc_settings.custom_params)?;
+        let name = preset.as_deref().unwrap_or("default");
+        info!("OSC: Switched to preset {name}");
+        journal::record(
+            Severity::Info,
+            Subsystem::Osc,
+            &format!("Switched to preset {name}"),
+        );
+        // Bringing the new avatar up to speed
+        self.init_params()?;
+        self.resend_selections()?;
+        if self.hr_status.heart_rate_bpm > 0 && self.calibration.is_none() {
+            let hr_status = self.hr_status.clone();
+            self.send_hr_status(&hr_status, false, false, false)?;
+            let result = send_raw_custom_params(&self.custom_params, &self.hr_status, &self.sender);
+            self.track_send(result)?;
+            self.delay_sending_connected = false;
+        }
         Ok(())
     }
+    fn set_calibrating(&mut self, calibrating: bool) -> Result<(), AppError> {
+        if calibrating == self.calibration.is_some() {
+            return Ok(());
+        }
+        if calibrating {
+            let duration = Duration::from_secs(self.osc_settings.calibration_sweep_sec as u64);
+            info!(
+                "OSC: Starting float HR calibration sweep over {} seconds",
+                duration.as_secs()
+            );
+            self.calibration = Some(CalibrationSweep::new(
+                Instant::now(),
+                duration,
+                self.positive_float_bpm,
+            ));
+            // Not resetting, so the sweep starts right away
+            self.calibration_ticker = time::interval(CALIBRATION_STEP);
+            return Ok(());
+        }
+        info!("OSC: Calibration sweep finished, restoring live data");
+        self.calibration = None;
+        if self.hr_status.heart_rate_bpm > 0 {
+            let hr_status = self.hr_status.clone();
+            self.send_hr_status(&hr_status, false, self.delay_sending_connected, false)
+        } else {
+            self.init_params()
+        }
+    }
+    fn calibration_tick(&mut self) -> Result<(), AppError> {
+        let Some(sweep) = self.calibration.as_mut() else {
+            return Ok(());
+        };
+        let Some((bpm, value)) = sweep.value_at(Instant::now()) else {
+            self.set_calibrating(false)?;
+            broadcast!(self.broadcast_tx, AppUpdate::OscCalibrating(false));
+            return Ok(());
+        };
+        if sweep.should_log(bpm) {
+            info!(
+                "OSC calibration: {} BPM -> {} = {value:.4}",
+                bpm as u8, self.osc_addresses.bpm_float
+            );
+        }
+        let result = send_raw_float_param(&self.osc_addresses.bpm_float, value, &self.sender);
+        self.track_send(result)
+    }
+    // Failures here only get logged, the last known address is still worth trying
+    async fn re_resolve_target(&mut self) {
+        let host = &self.osc_settings.target_ip;
+        let candidates = match target::lookup(host, self.osc_settings.port).await {
+            Ok(candidates) => candidates,
+            Err(e) => {
+                warn!("OSC: {e}");
+                return;
+            }
+        };
+        let Ok(local_addr) = self.sender.local_addr() else {
+            return;
+        };
+        match target::pick_target(&candidates, local_addr.ip()) {
+            Some(target_addr) if target_addr != self.sender.target() => {
+                info!("OSC: {host} now resolves to {target_addr}");
+                journal::record(
+                    Severity::Info,
+                    Subsystem::Osc,
+                    &format!("{host} now resolves to {target_addr}"),
+                );
+                self.sender.set_target(target_addr);
+            }
+            Some(_) => {}
+            None => warn!("OSC: {host} has no address reachable from {local_addr}"),
+        }
+    }
     fn handle_data(&mut self, data: HeartRateStatus) -> Result<(), AppError> {
+        if let Some(goal) = self.session_goal.as_mut() {
+            let progress = goal.update(data.heart_rate_bpm, Instant::now());
+            let result =
+                send_raw_float_param(&self.osc_addresses.goal_progress, progress, &self.sender);
+            self.track_send(result)?;
+        }
         // Fresh BPM data!
         if data.heart_rate_bpm > 0 {
             self.hr_status = data;
@@ -143,6 +560,10 @@ impl OscActor {
             return Ok(());
         }
 
+        if self.calibration.is_some() {
+            return Ok(());
+        }
+
         // Param that goes true when we're sending mimic data
         let hiding_ble_disconnection = if let Some(dc_timestamp) = self.disconnected_at {
             (dc_timestamp.elapsed() < self.max_hide_disconnection)
@@ -151,15 +572,48 @@ impl OscActor {
             false
         };
 
-        send_raw_hr_status(
-            &self.hr_status,
+        let hr_status = self.hr_status.clone();
+        self.send_hr_status(
+            &hr_status,
             hiding_ble_disconnection,
             self.delay_sending_connected,
-            self.positive_float_bpm,
-            &self.osc_addresses,
-            &self.socket,
-            self.target_addr,
+            true,
         )?;
+        let result = send_raw_custom_params(&self.custom_params, &self.hr_status, &self.sender);
+        self.track_send(result)?;
+        if self.osc_settings.send_stress_index {
+            if let Some(stress_index) = self.hr_status.stress_index {
+                let value = (stress_index / self.osc_settings.stress_index_max).clamp(0.0, 1.0);
+                let result =
+                    send_raw_float_param(&self.osc_addresses.stress_index, value, &self.sender);
+                self.track_send(result)?;
+            }
+        }
+        if let Some(spo2) = self.hr_status.spo2 {
+            let value = (spo2 / 100.0).clamp(0.0, 1.0);
+            let result = send_raw_float_param(&self.osc_addresses.spo2, value, &self.sender);
+            self.track_send(result)?;
+        }
+        if self.osc_settings.send_instant_bpm {
+            // Only on updates with a new beat, the param holds the last one in between
+            if let Some(bpm) = self
+                .hr_status
+                .rr_intervals
+                .last()
+                .copied()
+                .and_then(bpm_from_rr)
+            {
+                let value = bpm.round().clamp(0.0, 255.0) as i32;
+                let result =
+                    send_raw_int_param(&self.osc_addresses.instant_bpm, value, &self.sender);
+                self.track_send(result)?;
+            }
+        }
+        if let Some(cadence) = self.hr_status.cadence {
+            let result =
+                send_raw_int_param(&self.osc_addresses.cadence, cadence as i32, &self.sender);
+            self.track_send(result)?;
+        }
         // Check after sending, otherwise it's pointless
         if self.delay_sending_connected && (self.hr_status.heart_rate_bpm > 0) {
             self.delay_sending_connected = false;
@@ -177,6 +631,18 @@ impl OscActor {
                 self.toggle_edge = !self.toggle_edge;
                 self.heart_beat_ticker = time::interval(self.beat_pulse);
                 self.heart_beat_ticker.reset();
+                if let Some(sweep) = self.ecg_sweep.as_mut() {
+                    sweep.start();
+                    // Spreading the samples so the last one lands on the falling edge
+                    let step = self.beat_pulse / (sweep.sample_count() - 1) as u32;
+                    // Not resetting, so the first sample gets sent right away
+                    self.waveform_ticker = time::interval(step.max(Duration::from_millis(1)));
+                }
+                if let Some(envelope) = self.beat_envelope.as_mut() {
+                    envelope.start(Instant::now());
+                    // Not resetting, so the envelope starts right away
+                    self.envelope_ticker = time::interval(ENVELOPE_STEP);
+                }
             } else {
                 // Falling edge
                 self.pulse_edge = false;
@@ -184,13 +650,41 @@ impl OscActor {
                 self.heart_beat_ticker = time::interval(new_interval);
                 self.heart_beat_ticker.reset();
             }
-            send_raw_beat_params(
-                self.pulse_edge,
-                self.toggle_edge,
-                &self.osc_addresses,
-                &self.socket,
-                self.target_addr,
-            )?;
+            // Still flip/flopping the edges while degraded, just not sending them
+            if self.send_health.beats_allowed(Instant::now()) {
+                let (pulse, toggle) = beat_params_for_style(
+                    self.osc_settings.beat_style,
+                    self.pulse_edge,
+                    self.toggle_edge,
+                );
+                let result = send_raw_beat_params(pulse, toggle, &self.osc_addresses, &self.sender);
+                self.track_send(result)?;
+            }
+        }
+        Ok(())
+    }
+    fn envelope_tick(&mut self) -> Result<(), AppError> {
+        let Some(value) = self
+            .beat_envelope
+            .as_mut()
+            .and_then(|envelope| envelope.next_value(Instant::now()))
+        else {
+            return Ok(());
+        };
+        if self.send_health.is_degraded() {
+            return Ok(());
+        }
+        let result = send_raw_float_param(&self.osc_addresses.beat_envelope, value, &self.sender);
+        self.track_send(result)
+    }
+    fn waveform_tick(&mut self) -> Result<(), AppError> {
+        if let Some(value) = self.ecg_sweep.as_mut().and_then(EcgSweep::next_value) {
+            if self.send_health.is_degraded() {
+                return Ok(());
+            }
+            let result =
+                send_raw_float_param(&self.osc_addresses.ecg_waveform, value, &self.sender);
+            self.track_send(result)?;
         }
         Ok(())
     }
@@ -199,17 +693,18 @@ impl OscActor {
             let hiding_ble_disconnection = (dc_timestamp.elapsed() < self.max_hide_disconnection)
                 && (self.hr_status.heart_rate_bpm > 0);
 
-            if hiding_ble_disconnection {
+            if self.calibration.is_some() {
+                // Picked back up once the sweep is done
+            } else if hiding_ble_disconnection {
                 let mimic = make_mimic_data(&self.hr_status);
-                send_raw_hr_status(
+                self.send_hr_status(
                     &mimic,
                     hiding_ble_disconnection,
                     self.delay_sending_connected,
-                    self.positive_float_bpm,
-                    &self.osc_addresses,
-                    &self.socket,
-                    self.target_addr,
+                    false,
                 )?;
+                let result = send_raw_custom_params(&self.custom_params, &mimic, &self.sender);
+                self.track_send(result)?;
             } else {
                 // Alright, we're really disconnected now
                 self.hr_status = HeartRateStatus::default();
@@ -228,20 +723,53 @@ impl OscActor {
         loop {
             let heart_beat = self.heart_beat_ticker.tick();
             let mimic = self.disconnect_update_interval.tick();
+            let waveform_running = self.ecg_sweep.as_ref().is_some_and(EcgSweep::is_running);
+            let waveform = self.waveform_ticker.tick();
+            let envelope_running = self
+                .beat_envelope
+                .as_ref()
+                .is_some_and(BeatEnvelope::is_running);
+            let envelope = self.envelope_ticker.tick();
+            let resolve_target = self.resolve_target;
+            let resolve = self.resolve_ticker.tick();
+            let calibrating = self.calibration.is_some();
+            let calibration = self.calibration_ticker.tick();
             tokio::select! {
                 hr_data = broadcast_rx.recv() => {
                     match hr_data {
                         Ok(AppUpdate::HeartRateStatus(data)) => {
+                            self.bridge_health.set_bpm(data.heart_rate_bpm);
                             self.handle_data(data)?;
+                            self.update_bridge_health()?;
+                        },
+                        Ok(AppUpdate::BleReconnect(status)) => {
+                            self.bridge_health.set_reconnect(status);
+                            self.update_bridge_health()?;
                         },
                         Ok(AppUpdate::ActivitySelected(index)) => {
                             self.activity = Some(index);
-                            send_raw_activity_param(index, &self.osc_addresses, &self.socket, self.target_addr)?;
+                            let result = send_raw_activity_param(index, &self.osc_addresses, &self.sender);
+                            self.track_send(result)?;
+                        },
+                        Ok(AppUpdate::OscPaused(paused)) => {
+                            self.set_paused(paused)?;
+                        },
+                        Ok(AppUpdate::ZoneChanged(zone)) => {
+                            self.zone = Some(zone);
+                            let result = send_raw_int_param(&self.osc_addresses.zone, zone as i32, &self.sender);
+                            self.track_send(result)?;
+                        },
+                        Ok(AppUpdate::OscCalibrating(calibrating)) => {
+                            self.set_calibrating(calibrating)?;
+                        },
+                        Ok(AppUpdate::OscPreset(preset)) => {
+                            self.switch_preset(preset)?;
                         },
                         Ok(_) => {},
                         Err(RecvError::Closed) => {
                             error!("OSC: Channel closed");
                             self.init_params()?;
+                            self.send_bridge_health(BridgeHealth::Offline)?;
                             break;
                         },
                         Err(RecvError::Lagged(count)) => {
@@ -253,13 +781,31 @@ impl OscActor {
                 _ = heart_beat => {
                     self.heart_beat()?;
                 }
+                // Sweeping the ECG waveform param during a beat pulse
+                _ = waveform, if waveform_running => {
+                    self.waveform_tick()?;
+                }
+                // Ramping the beat envelope param up and down
+                _ = envelope, if envelope_running => {
+                    self.envelope_tick()?;
+                }
                 // Sending mimic data when we're disconnected
+                // (Also picks up send health changes)
                 _ = mimic => {
                     self.mimic_tick()?;
+                    self.update_bridge_health()?;
+                }
+                // Sweeping the float HR param
+                _ = calibration, if calibrating => {
+                    self.calibration_tick()?;
+                }
+                _ = resolve, if resolve_target => {
+                    self.re_resolve_target().await;
                 }
                 _ = cancel_token.cancelled() => {
                     info!("Shutting down OSC thread!");
                     self.init_params()?;
+                    self.send_bridge_health(BridgeHealth::Offline)?;
                     break;
                 }
             }
@@ -273,9 +819,15 @@ pub async fn osc_thread(
     broadcast_tx: BSender<AppUpdate>,
     initial_activity: Option<u8>,
     osc_settings: OscSettings,
+    tap_log: Option<OscTapLog>,
     cancel_token: CancellationToken,
 ) {
-    let mut osc = match OscActor::build(initial_activity, osc_settings) {
+    let mut osc = match OscActor::build(
+        broadcast_tx.clone(),
+        initial_activity,
+        osc_settings,
+        tap_log,
+    ) {
         Ok(osc) => osc,
         Err(e) => {
             error!("Failed to set up OSC. {e}");
@@ -285,6 +837,19 @@ pub async fn osc_thread(
         }
     };
 
+    // Not worth failing over, the configured target is still there to fall back on
+    let oscquery = if osc.osc_settings.oscquery {
+        match osc.start_oscquery().await {
+            Ok(oscquery) => Some(oscquery),
+            Err(e) => {
+                warn!("OSCQuery: Failed to start, using configured target. {e}");
+                None
+            }
+        }
+    } else {
+        None
+    };
+
     // TODO?
     // Maybe option for twitches to be a toggle and/or pulse?
     // Current implementation is a weird mix of both, but is simple to implement
@@ -294,4 +859,8 @@ pub async fn osc_thread(
         let message = "OSC Error";
         broadcast!(broadcast_tx, ErrorPopup::detailed(message, e));
     }
+
+    if let Some(oscquery) = oscquery {
+        oscquery.stop();
+    }
 }
@@ -0,0 +1,180 @@
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use rand::Rng;
+use serde_json::json;
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio::time::{timeout, Duration, Instant};
+use tracing::{debug, info, warn};
+
+use crate::errors::AppError;
+
+const OSC_SERVICE: &str = "_osc._udp.local.";
+const OSCJSON_SERVICE: &str = "_oscjson._tcp.local.";
+const VRCHAT_INSTANCE_PREFIX: &str = "VRChat-Client-";
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_REQUEST_HEAD: usize = 4 * 1024;
+
+/// Advertises the app over OSCQuery (mDNS + a small HTTP server describing it),
+/// and looks up where VRChat is actually listening instead of assuming port 9000.
+///
+/// The instance name gets a random suffix, so multiple copies (or other tools) don't collide.
+pub(super) struct OscQuery {
+    daemon: ServiceDaemon,
+    name: String,
+    service_names: Vec<String>,
+    http_server: JoinHandle<()>,
+}
+
+impl OscQuery {
+    pub async fn start(osc_port: u16) -> Result<Self, AppError> {
+        let name = format!("IronHeart-{:04X}", rand::thread_rng().gen::<u16>());
+
+        let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+        let http_port = listener.local_addr()?.port();
+        let host_info = json!({
+            "NAME": name,
+            "OSC_IP": Ipv4Addr::LOCALHOST.to_string(),
+            "OSC_PORT": osc_port,
+            "OSC_TRANSPORT": "UDP",
+            "EXTENSIONS": {
+                "ACCESS": true,
+                "VALUE": true,
+            },
+        })
+        .to_string();
+        // We only send, so there's nothing to list
+        let root_node = json!({
+            "FULL_PATH": "/",
+            "ACCESS": 0,
+            "CONTENTS": {},
+        })
+        .to_string();
+        let http_server = tokio::spawn(serve_http(listener, host_info, root_node));
+
+        let daemon = ServiceDaemon::new()?;
+        let host_name = format!("{name}.local.");
+        let properties: HashMap<String, String> = HashMap::new();
+        let mut service_names = Vec::new();
+        for (service, port) in [(OSC_SERVICE, osc_port), (OSCJSON_SERVICE, http_port)] {
+            let service_info =
+                ServiceInfo::new(service, &name, &host_name, "", port, properties.clone())?
+                    .enable_addr_auto();
+            service_names.push(service_info.get_fullname().to_owned());
+            daemon.register(service_info)?;
+        }
+
+        info!("OSCQuery: Advertising as {name} (OSC port {osc_port}, HTTP port {http_port})");
+
+        Ok(Self {
+            daemon,
+            name,
+            service_names,
+            http_server,
+        })
+    }
+    /// Browses for a VRChat client's OSC receiver, giving up after a few seconds.
+    pub async fn find_vrchat(&self) -> Result<Option<SocketAddrV4>, AppError> {
+        let receiver = self.daemon.browse(OSC_SERVICE)?;
+        let deadline = Instant::now() + DISCOVERY_TIMEOUT;
+        let mut found = None;
+        while let Ok(Ok(event)) = timeout(
+            deadline.saturating_duration_since(Instant::now()),
+            receiver.recv_async(),
+        )
+        .await
+        {
+            let ServiceEvent::ServiceResolved(service_info) = event else {
+                continue;
+            };
+            let fullname = service_info.get_fullname();
+            debug!("OSCQuery: Found {fullname}");
+            if !fullname.starts_with(VRCHAT_INSTANCE_PREFIX) {
+                continue;
+            }
+            let addresses = service_info.get_addresses_v4();
+            // Same machine is the usual case
+            let ip = addresses
+                .iter()
+                .find(|ip| ip.is_loopback())
+                .or_else(|| addresses.iter().next());
+            if let Some(ip) = ip {
+                found = Some(SocketAddrV4::new(**ip, service_info.get_port()));
+                break;
+            }
+        }
+        if let Err(e) = self.daemon.stop_browse(OSC_SERVICE) {
+            debug!("OSCQuery: Failed to stop browsing: {e}");
+        }
+        Ok(found)
+    }
+    pub fn stop(self) {
+        for service_name in &self.service_names {
+            if let Err(e) = self.daemon.unregister(service_name) {
+                warn!("OSCQuery: Failed to unregister {service_name}: {e}");
+            }
+        }
+        if let Err(e) = self.daemon.shutdown() {
+            warn!("OSCQuery: Failed to shut down mDNS daemon: {e}");
+        }
+        self.http_server.abort();
+        info!("OSCQuery: Stopped advertising {}", self.name);
+    }
+}
+
+async fn serve_http(listener: TcpListener, host_info: String, root_node: String) {
+    loop {
+        let connection = match listener.accept().await {
+            Ok((connection, _)) => connection,
+            Err(e) => {
+                warn!("OSCQuery: Failed to accept HTTP connection: {e}");
+                continue;
+            }
+        };
+        let host_info = host_info.clone();
+        let root_node = root_node.clone();
+        tokio::spawn(async move {
+            if let Err(e) = respond(connection, &host_info, &root_node).await {
+                debug!("OSCQuery: HTTP request failed: {e}");
+            }
+        });
+    }
+}
+
+async fn respond(
+    mut connection: TcpStream,
+    host_info: &str,
+    root_node: &str,
+) -> Result<(), AppError> {
+    let mut head = Vec::with_capacity(512);
+    let mut buf = [0; 512];
+    let read_head = async {
+        while !head.windows(4).any(|w| w == b"\r\n\r\n") && head.len() < MAX_REQUEST_HEAD {
+            let read = connection.read(&mut buf).await?;
+            if read == 0 {
+                break;
+            }
+            head.extend_from_slice(&buf[..read]);
+        }
+        Ok::<(), AppError>(())
+    };
+    if let Ok(result) = timeout(Duration::from_secs(5), read_head).await {
+        result?;
+    }
+    let head = String::from_utf8_lossy(&head);
+    let target = head.split_whitespace().nth(1).unwrap_or_default();
+    let body = if target.contains("HOST_INFO") {
+        host_info
+    } else {
+        root_node
+    };
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    connection.write_all(response.as_bytes()).await?;
+    connection.shutdown().await?;
+    Ok(())
+}
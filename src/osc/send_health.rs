@@ -0,0 +1,131 @@
+use tokio::time::{Duration, Instant};
+
+use crate::backoff::Backoff;
+
+// How many sends in a row need to fail before we start holding back
+const ERROR_THRESHOLD: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Tracks OSC send failures so the high-frequency beat params can back off
+/// when the target is unreachable, instead of failing every few hundred ms.
+///
+/// The low-rate BPM bundles keep going regardless, and any successful send ends degraded mode.
+#[derive(Debug)]
+pub(super) struct SendHealth {
+    consecutive_errors: u32,
+    backoff: Backoff,
+    // Some while degraded, beats are held back until this passes
+    suppressed_until: Option<Instant>,
+}
+
+impl Default for SendHealth {
+    fn default() -> Self {
+        Self {
+            consecutive_errors: 0,
+            backoff: Backoff::new(INITIAL_BACKOFF, MAX_BACKOFF, None),
+            suppressed_until: None,
+        }
+    }
+}
+
+impl SendHealth {
+    pub fn is_degraded(&self) -> bool {
+        self.suppressed_until.is_some()
+    }
+    /// Beat params are allowed when healthy, or as a probe once the current backoff has passed.
+    pub fn beats_allowed(&self, now: Instant) -> bool {
+        !matches!(self.suppressed_until, Some(until) if now < until)
+    }
+    /// Returns true if this success ended degraded mode.
+    pub fn record_success(&mut self) -> bool {
+        let was_degraded = self.is_degraded();
+        *self = Self::default();
+        was_degraded
+    }
+    /// Returns true if this error started degraded mode.
+    pub fn record_error(&mut self, now: Instant) -> bool {
+        self.consecutive_errors = self.consecutive_errors.saturating_add(1);
+        if self.consecutive_errors < ERROR_THRESHOLD {
+            return false;
+        }
+        let started = !self.is_degraded();
+        // Only lengthen the backoff if the probe after the last one failed,
+        // not for every low-rate bundle that fails in the meantime
+        if started || self.beats_allowed(now) {
+            // Never gives up without a window
+            let delay = self
+                .backoff
+                .next_delay(now.into_std())
+                .unwrap_or(MAX_BACKOFF);
+            self.suppressed_until = Some(now + delay);
+        }
+        started
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn degrades_after_threshold() {
+        let now = Instant::now();
+        let mut health = SendHealth::default();
+        for _ in 1..ERROR_THRESHOLD {
+            assert!(!health.record_error(now));
+            assert!(health.beats_allowed(now));
+        }
+        assert!(health.record_error(now));
+        assert!(health.is_degraded());
+        assert!(!health.beats_allowed(now));
+        assert!(health.beats_allowed(now + INITIAL_BACKOFF));
+    }
+    #[test]
+    fn success_resets() {
+        let now = Instant::now();
+        let mut health = SendHealth::default();
+        for _ in 0..ERROR_THRESHOLD - 1 {
+            health.record_error(now);
+        }
+        assert!(!health.record_success());
+        // Counter was reset, so this shouldn't degrade
+        assert!(!health.record_error(now));
+        for _ in 0..ERROR_THRESHOLD {
+            health.record_error(now);
+        }
+        assert!(health.is_degraded());
+        assert!(health.record_success());
+        assert!(!health.is_degraded());
+        assert!(health.beats_allowed(now));
+    }
+    #[test]
+    fn backoff_grows_only_on_failed_probes() {
+        let now = Instant::now();
+        let mut health = SendHealth::default();
+        for _ in 0..ERROR_THRESHOLD {
+            health.record_error(now);
+        }
+        // Errors during the backoff don't extend it
+        health.record_error(now + Duration::from_secs(1));
+        assert!(health.beats_allowed(now + INITIAL_BACKOFF));
+        // Failed probe doubles it
+        let probe = now + INITIAL_BACKOFF;
+        health.record_error(probe);
+        assert!(!health.beats_allowed(probe + INITIAL_BACKOFF));
+        assert!(health.beats_allowed(probe + INITIAL_BACKOFF * 2));
+    }
+    #[test]
+    fn backoff_is_capped() {
+        let mut now = Instant::now();
+        let mut health = SendHealth::default();
+        for _ in 0..ERROR_THRESHOLD {
+            health.record_error(now);
+        }
+        for _ in 0..20 {
+            now += MAX_BACKOFF;
+            health.record_error(now);
+        }
+        assert!(health.beats_allowed(now + MAX_BACKOFF));
+    }
+}
@@ -0,0 +1,285 @@
+use rosc::encoder;
+use rosc::{OscBundle, OscPacket, OscTime, OscType};
+use std::collections::VecDeque;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tracing::info;
+
+use super::OSC_NOW;
+use crate::errors::AppError;
+use crate::settings::OscSettings;
+
+// How many lines the TUI pane keeps around
+const TAP_LOG_LENGTH: usize = 100;
+
+/// Most recent OSC messages (or would-be messages in dry-run mode), newest last.
+pub type OscTapLog = Arc<Mutex<VecDeque<String>>>;
+
+pub fn new_tap_log() -> OscTapLog {
+    Arc::new(Mutex::new(VecDeque::with_capacity(TAP_LOG_LENGTH)))
+}
+
+/// Owns the socket and everything needed to get a bundle out the door,
+/// including the optional debug tap and dry-run mode.
+pub(super) struct OscSender {
+    socket: UdpSocket,
+    target_addr: SocketAddr,
+    // From `osc.extra_targets`, never re-resolved or pointed elsewhere by OSCQuery
+    extra_targets: Vec<SocketAddr>,
+    real_timetags: bool,
+    timetag_delay: Duration,
+    debug_tap: bool,
+    dry_run: bool,
+    bools_as_ints: bool,
+    // Set by the user to temporarily stop sending anything
+    paused: bool,
+    tap_log: Option<OscTapLog>,
+}
+
+impl OscSender {
+    pub fn new(
+        socket: UdpSocket,
+        target_addr: SocketAddr,
+        extra_targets: Vec<SocketAddr>,
+        osc_settings: &OscSettings,
+        tap_log: Option<OscTapLog>,
+    ) -> Self {
+        Self {
+            socket,
+            target_addr,
+            extra_targets,
+            real_timetags: osc_settings.real_timetags,
+            timetag_delay: Duration::from_millis(osc_settings.timetag_delay_ms as u64),
+            debug_tap: osc_settings.debug_tap,
+            dry_run: osc_settings.dry_run,
+            bools_as_ints: osc_settings.bools_as_ints,
+            paused: false,
+            tap_log,
+        }
+    }
+    // Bundles are normally tagged as "immediately",
+    // but some downstream tools can schedule on real timetags
+    pub fn timetag(&self) -> OscTime {
+        if !self.real_timetags {
+            return OSC_NOW;
+        }
+        // Only fails for times before the UNIX epoch
+        OscTime::try_from(SystemTime::now() + self.timetag_delay).unwrap_or(OSC_NOW)
+    }
+    /// An empty bundle with the current timetag.
+    pub fn bundle(&self) -> OscBundle {
+        OscBundle {
+            timetag: self.timetag(),
+            content: vec![],
+        }
+    }
+    pub fn local_addr(&self) -> Result<SocketAddr, AppError> {
+        Ok(self.socket.local_addr()?)
+    }
+    pub fn target(&self) -> SocketAddr {
+        self.target_addr
+    }
+    pub fn set_target(&mut self, target_addr: SocketAddr) {
+        self.target_addr = target_addr;
+    }
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+    /// The main target and then the extra ones, the order `send_to` counts them in.
+    pub fn targets(&self) -> impl Iterator<Item = SocketAddr> + '_ {
+        std::iter::once(self.target_addr).chain(self.extra_targets.iter().copied())
+    }
+    /// Sends the bundle to every target (unless it's empty, we're paused, or in dry-run mode),
+    /// writing each message to the debug tap first if it's enabled.
+    pub fn send(&self, bundle: OscBundle) -> Result<(), AppError> {
+        let Some(msg_buf) = self.encode(bundle, true)? else {
+            return Ok(());
+        };
+        // One unreachable target shouldn't hold back the rest
+        let mut result = Ok(());
+        for target in self.targets() {
+            if let Err(e) = self.socket.send_to(&msg_buf, target) {
+                result = Err(e.into());
+            }
+        }
+        result
+    }
+    /// Same as `send`, but only to one target, 0 being the main one.
+    ///
+    /// Only the main target's bundles go to the debug tap, the others are near repeats of it.
+    pub fn send_to(&self, index: usize, bundle: OscBundle) -> Result<(), AppError> {
+        let Some(target) = self.targets().nth(index) else {
+            return Ok(());
+        };
+        let Some(msg_buf) = self.encode(bundle, index == 0)? else {
+            return Ok(());
+        };
+        self.socket.send_to(&msg_buf, target)?;
+        Ok(())
+    }
+    // None if there's nothing that should actually go out
+    fn encode(&self, mut bundle: OscBundle, tap: bool) -> Result<Option<Vec<u8>>, AppError> {
+        if bundle.content.is_empty() || self.paused {
+            return Ok(None);
+        }
+        if self.bools_as_ints {
+            bools_to_ints(&mut bundle.content);
+        }
+        if tap && (self.debug_tap || self.dry_run) {
+            self.tap(&bundle);
+        }
+        let msg_buf = encoder::encode(&OscPacket::Bundle(bundle))?;
+        Ok((!self.dry_run).then_some(msg_buf))
+    }
+    fn tap(&self, bundle: &OscBundle) {
+        let prefix = if self.dry_run { "(dry run) " } else { "" };
+        let lines: Vec<String> = describe_packets(&bundle.content)
+            .into_iter()
+            .map(|line| format!("{prefix}{line}"))
+            .collect();
+        for line in &lines {
+            info!("OSC tap: {line}");
+        }
+        let Some(tap_log) = &self.tap_log else {
+            return;
+        };
+        let Ok(mut tap_log) = tap_log.lock() else {
+            return;
+        };
+        for line in lines {
+            if tap_log.len() == TAP_LOG_LENGTH {
+                tap_log.pop_front();
+            }
+            tap_log.push_back(line);
+        }
+    }
+}
+
+/// Swaps every Bool argument for an Int 0/1, for consumers that mishandle OSC booleans.
+fn bools_to_ints(packets: &mut [OscPacket]) {
+    for packet in packets {
+        match packet {
+            OscPacket::Message(msg) => {
+                for arg in &mut msg.args {
+                    if let OscType::Bool(value) = *arg {
+                        *arg = OscType::Int(value as i32);
+                    }
+                }
+            }
+            OscPacket::Bundle(bundle) => bools_to_ints(&mut bundle.content),
+        }
+    }
+}
+
+/// One line per message, i.e. `/avatar/parameters/HR int:123`
+pub(super) fn describe_packets(packets: &[OscPacket]) -> Vec<String> {
+    let mut lines = Vec::new();
+    for packet in packets {
+        match packet {
+            OscPacket::Message(msg) => {
+                let args: Vec<String> = msg.args.iter().map(describe_arg).collect();
+                lines.push(format!("{} {}", msg.addr, args.join(" ")));
+            }
+            OscPacket::Bundle(bundle) => lines.extend(describe_packets(&bundle.content)),
+        }
+    }
+    lines
+}
+
+fn describe_arg(arg: &OscType) -> String {
+    match arg {
+        OscType::Int(value) => format!("int:{value}"),
+        OscType::Float(value) => format!("float:{value:.3}"),
+        OscType::Bool(value) => format!("bool:{value}"),
+        OscType::String(value) => format!("string:{value:?}"),
+        other => format!("{other:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rosc::OscMessage;
+
+    #[test]
+    fn describes_nested_messages() {
+        let message = |addr: &str, args| {
+            OscPacket::Message(OscMessage {
+                addr: addr.to_owned(),
+                args,
+            })
+        };
+        let packets = vec![
+            message("/a", vec![OscType::Int(123)]),
+            OscPacket::Bundle(OscBundle {
+                timetag: OSC_NOW,
+                content: vec![message(
+                    "/b",
+                    vec![OscType::Float(0.5), OscType::Bool(true)],
+                )],
+            }),
+        ];
+        assert_eq!(
+            describe_packets(&packets),
+            vec!["/a int:123", "/b float:0.500 bool:true"]
+        );
+    }
+    #[test]
+    fn bools_become_ints() {
+        let mut packets = vec![OscPacket::Bundle(OscBundle {
+            timetag: OSC_NOW,
+            content: vec![OscPacket::Message(OscMessage {
+                addr: "/a".to_owned(),
+                args: vec![OscType::Bool(true), OscType::Bool(false), OscType::Int(7)],
+            })],
+        })];
+        bools_to_ints(&mut packets);
+        assert_eq!(describe_packets(&packets), vec!["/a int:1 int:0 int:7"]);
+    }
+    #[test]
+    fn sends_to_one_or_every_target() {
+        let listeners: Vec<UdpSocket> = (0..2)
+            .map(|_| UdpSocket::bind("127.0.0.1:0").unwrap())
+            .collect();
+        for listener in &listeners {
+            listener.set_nonblocking(true).unwrap();
+        }
+        let addrs: Vec<SocketAddr> = listeners.iter().map(|l| l.local_addr().unwrap()).collect();
+        let sender = OscSender::new(
+            UdpSocket::bind("127.0.0.1:0").unwrap(),
+            addrs[0],
+            vec![addrs[1]],
+            &OscSettings::default(),
+            None,
+        );
+        let bundle = |addr: &str| OscBundle {
+            timetag: OSC_NOW,
+            content: vec![OscPacket::Message(OscMessage {
+                addr: addr.to_owned(),
+                args: vec![OscType::Int(1)],
+            })],
+        };
+        let encoded = |addr: &str| encoder::encode(&OscPacket::Bundle(bundle(addr))).unwrap();
+        // Whatever's waiting on each
+        let received = |listener: &UdpSocket| {
+            let mut buf = [0; 1024];
+            let mut packets = Vec::new();
+            while let Ok(len) = listener.recv(&mut buf) {
+                packets.push(buf[..len].to_vec());
+            }
+            packets
+        };
+
+        sender.send(bundle("/all")).unwrap();
+        sender.send_to(1, bundle("/extra")).unwrap();
+        // Past the last target, nothing to send to
+        sender.send_to(2, bundle("/nowhere")).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(received(&listeners[0]), vec![encoded("/all")]);
+        assert_eq!(
+            received(&listeners[1]),
+            vec![encoded("/all"), encoded("/extra")]
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+
+use crate::errors::AppError;
+
+// IPv6 addresses might be written with brackets like in a URL
+fn strip_brackets(host: &str) -> &str {
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+
+/// Parses an IP, accepting IPv6 with or without brackets.
+pub(super) fn parse_ip(host: &str) -> Option<IpAddr> {
+    strip_brackets(host).parse().ok()
+}
+
+/// True if `target_ip` is a hostname (i.e. `quest-headset.local`) that needs resolving,
+/// rather than an IP literal.
+pub(super) fn is_hostname(host: &str) -> bool {
+    parse_ip(host).is_none()
+}
+
+/// Blocking lookup, used once at startup.
+pub(super) fn resolve(host: &str, port: u16) -> Result<Vec<SocketAddr>, AppError> {
+    let addresses = (strip_brackets(host), port)
+        .to_socket_addrs()
+        .map_err(|e| AppError::OscResolve(host.to_owned(), e.to_string()))?;
+    Ok(addresses.collect())
+}
+
+/// Non-blocking lookup, used while running to pick up DHCP/network changes.
+pub(super) async fn lookup(host: &str, port: u16) -> Result<Vec<SocketAddr>, AppError> {
+    let addresses = tokio::net::lookup_host((strip_brackets(host), port))
+        .await
+        .map_err(|e| AppError::OscResolve(host.to_owned(), e.to_string()))?;
+    Ok(addresses.collect())
+}
+
+/// Picks an address that a socket bound to `host_ip` can send to.
+///
+/// An unspecified `host_ip` (0.0.0.0 or ::) can be swapped to the other family,
+/// anything more specific needs a target of the same family.
+pub(super) fn pick_target(candidates: &[SocketAddr], host_ip: IpAddr) -> Option<SocketAddr> {
+    candidates
+        .iter()
+        .find(|addr| addr.is_ipv4() == host_ip.is_ipv4())
+        .or_else(|| {
+            if host_ip.is_unspecified() {
+                candidates.first()
+            } else {
+                None
+            }
+        })
+        .copied()
+}
+
+/// The IP to bind to so the socket can reach `target`.
+pub(super) fn bind_ip_for(host_ip: IpAddr, target: SocketAddr) -> IpAddr {
+    match (host_ip, target) {
+        (IpAddr::V4(ip), SocketAddr::V6(_)) if ip.is_unspecified() => {
+            IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+        }
+        (IpAddr::V6(ip), SocketAddr::V4(_)) if ip.is_unspecified() => {
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+        }
+        _ => host_ip,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn hostnames_and_literals() {
+        assert!(!is_hostname("127.0.0.1"));
+        assert!(!is_hostname("::1"));
+        assert!(!is_hostname("[fe80::1]"));
+        assert!(is_hostname("quest-headset.local"));
+        assert!(is_hostname("localhost"));
+    }
+    #[test]
+    fn resolves_literals() {
+        assert_eq!(resolve("[::1]", 9000).unwrap(), vec![addr("[::1]:9000")]);
+        assert_eq!(
+            resolve("127.0.0.1", 9000).unwrap(),
+            vec![addr("127.0.0.1:9000")]
+        );
+    }
+    #[test]
+    fn prefers_same_family() {
+        let candidates = [addr("[fe80::2]:9000"), addr("192.168.1.2:9000")];
+        let unspecified_v4 = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+        assert_eq!(
+            pick_target(&candidates, unspecified_v4),
+            Some(addr("192.168.1.2:9000"))
+        );
+        let unspecified_v6 = IpAddr::V6(Ipv6Addr::UNSPECIFIED);
+        assert_eq!(
+            pick_target(&candidates, unspecified_v6),
+            Some(addr("[fe80::2]:9000"))
+        );
+    }
+    #[test]
+    fn only_unspecified_switches_family() {
+        let candidates = [addr("[fe80::2]:9000")];
+        let unspecified_v4 = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+        let target = pick_target(&candidates, unspecified_v4).unwrap();
+        assert_eq!(
+            bind_ip_for(unspecified_v4, target),
+            IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+        );
+        let specific_v4 = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 3));
+        assert_eq!(pick_target(&candidates, specific_v4), None);
+    }
+}
@@ -0,0 +1,113 @@
+// Rough P-QRS-T shape made out of gaussian bumps,
+// tuned by eye rather than from any real ECG data.
+// (center, height, width), center/width are in fractions of a beat pulse
+const ECG_WAVES: [(f32, f32, f32); 5] = [
+    // P
+    (0.15, 0.12, 0.045),
+    // Q
+    (0.33, -0.12, 0.015),
+    // R
+    (0.40, 1.0, 0.02),
+    // S
+    (0.47, -0.25, 0.015),
+    // T
+    (0.72, 0.3, 0.07),
+];
+
+/// Value of the ECG-like waveform at `t`, where 0.0 is the start of the beat pulse and 1.0 is the end.
+///
+/// Output is roughly within -0.25 to 1.0, peaking at the R wave.
+pub(super) fn ecg_sample(t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    ECG_WAVES
+        .iter()
+        .map(|(center, height, width)| {
+            let offset = (t - center) / width;
+            height * (-0.5 * offset * offset).exp()
+        })
+        .sum()
+}
+
+/// Steps through `sample_count` evenly spaced samples of the waveform, one per tick.
+#[derive(Debug)]
+pub(super) struct EcgSweep {
+    sample_count: u16,
+    amplitude: f32,
+    step: Option<u16>,
+}
+
+impl EcgSweep {
+    pub fn new(sample_count: u16, amplitude: f32) -> Self {
+        Self {
+            // Need at least a start and an end
+            sample_count: sample_count.max(2),
+            amplitude,
+            step: None,
+        }
+    }
+    pub fn sample_count(&self) -> u16 {
+        self.sample_count
+    }
+    pub fn start(&mut self) {
+        self.step = Some(0);
+    }
+    pub fn stop(&mut self) {
+        self.step = None;
+    }
+    pub fn is_running(&self) -> bool {
+        self.step.is_some()
+    }
+    /// Returns the next value to send, or None once the sweep is finished.
+    pub fn next_value(&mut self) -> Option<f32> {
+        let step = self.step?;
+        if step >= self.sample_count {
+            self.step = None;
+            return None;
+        }
+        self.step = Some(step + 1);
+        let t = step as f32 / (self.sample_count - 1) as f32;
+        Some(ecg_sample(t) * self.amplitude)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ecg_peaks_at_r_wave() {
+        let r_peak = ecg_sample(0.40);
+        assert!((r_peak - 1.0).abs() < 0.05);
+        for i in 0..=100 {
+            assert!(ecg_sample(i as f32 / 100.0) <= r_peak);
+        }
+    }
+    #[test]
+    fn ecg_rests_at_edges() {
+        assert!(ecg_sample(0.0).abs() < 0.01);
+        assert!(ecg_sample(1.0).abs() < 0.01);
+        // Out of range gets clamped
+        assert_eq!(ecg_sample(-1.0), ecg_sample(0.0));
+        assert_eq!(ecg_sample(2.0), ecg_sample(1.0));
+    }
+    #[test]
+    fn sweep_emits_sample_count_values() {
+        let mut sweep = EcgSweep::new(8, 0.5);
+        assert!(sweep.next_value().is_none());
+        sweep.start();
+        let values: Vec<f32> = std::iter::from_fn(|| sweep.next_value()).collect();
+        assert_eq!(values.len(), 8);
+        assert!(!sweep.is_running());
+        let max = values.iter().cloned().fold(f32::MIN, f32::max);
+        assert!(max <= 0.5);
+    }
+    #[test]
+    fn sweep_minimum_samples() {
+        let mut sweep = EcgSweep::new(0, 1.0);
+        assert_eq!(sweep.sample_count(), 2);
+        sweep.start();
+        assert!(sweep.next_value().is_some());
+        assert!(sweep.next_value().is_some());
+        assert!(sweep.next_value().is_none());
+    }
+}
@@ -0,0 +1,374 @@
+// Pairing with devices that won't send HR until they're bonded, answering their PIN prompts from the TUI
+// btleplug can't pair on its own, so on Linux this registers our own BlueZ agent over D-Bus
+// Everywhere else (or without the "pairing_agent" feature) the user's pointed to their OS instead
+#![cfg_attr(
+    not(all(feature = "pairing_agent", target_os = "linux")),
+    allow(dead_code)
+)]
+
+use tokio::sync::{mpsc, oneshot};
+use tracing::debug;
+use tui_input::Input;
+
+use crate::app::{App, SubState};
+
+pub mod tui;
+
+#[cfg(all(feature = "pairing_agent", target_os = "linux"))]
+pub use agent::pair;
+
+// Passkeys are shown and entered as (up to) 6 digits
+const MAX_PASSKEY: u32 = 999_999;
+// Legacy PIN codes, per the BlueZ Agent API
+const MAX_PIN_CODE_LEN: usize = 16;
+
+pub type PairingTx = mpsc::Sender<PairingRequest>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PairingKind {
+    // Up to 16 characters, for older devices
+    PinCode,
+    // A number from 0 to 999999
+    Passkey,
+}
+
+impl PairingKind {
+    /// Checks what the user typed, giving back what to send BlueZ if it's usable.
+    pub fn validate(self, input: &str) -> Result<String, &'static str> {
+        let input = input.trim();
+        match self {
+            PairingKind::PinCode if input.is_empty() || input.len() > MAX_PIN_CODE_LEN => {
+                Err("PIN must be 1 to 16 characters")
+            }
+            PairingKind::PinCode => Ok(input.to_owned()),
+            PairingKind::Passkey => match input.parse::<u32>() {
+                Ok(passkey) if passkey <= MAX_PASSKEY => Ok(passkey.to_string()),
+                _ => Err("Passkey must be up to 6 digits"),
+            },
+        }
+    }
+}
+
+/// A device asked for a PIN/passkey mid-pairing, the answer (None if the user backed out) goes back through `reply`.
+#[derive(Debug)]
+pub struct PairingRequest {
+    pub device: String,
+    pub kind: PairingKind,
+    pub reply: oneshot::Sender<Option<String>>,
+}
+
+/// The TUI side of pairing, holding whatever request is being answered right now.
+pub struct PairingPrompt {
+    request_tx: PairingTx,
+    pub request_rx: mpsc::Receiver<PairingRequest>,
+    pending: Option<PairingRequest>,
+    pub input: Input,
+    // Shown under the input after a bad Enter
+    invalid: Option<&'static str>,
+    // Restored once the prompt closes, usually the connecting popup
+    previous_sub_state: SubState,
+}
+
+impl PairingPrompt {
+    pub fn new() -> Self {
+        let (request_tx, request_rx) = mpsc::channel(4);
+        Self {
+            request_tx,
+            request_rx,
+            pending: None,
+            input: Input::default(),
+            invalid: None,
+            previous_sub_state: SubState::None,
+        }
+    }
+    /// For the BLE threads, so they can ask through here when a device wants a PIN.
+    pub fn request_tx(&self) -> PairingTx {
+        self.request_tx.clone()
+    }
+}
+
+impl App {
+    pub fn pairing_prompt_open(&mut self, request: PairingRequest) {
+        if self.pairing.pending.is_some() {
+            // Dropping the reply rejects it, that device tries again on its next connect
+            debug!("Already pairing, rejecting request from {}", request.device);
+            return;
+        }
+        self.pairing.input.reset();
+        self.pairing.invalid = None;
+        self.pairing.pending = Some(request);
+        self.pairing.previous_sub_state =
+            std::mem::replace(&mut self.sub_state, SubState::PairingPrompt);
+    }
+    pub fn pairing_enter_pressed(&mut self) {
+        let Some(pending) = self.pairing.pending.as_ref() else {
+            return;
+        };
+        match pending.kind.validate(self.pairing.input.value()) {
+            Ok(answer) => self.pairing_prompt_close(Some(answer)),
+            Err(e) => self.pairing.invalid = Some(e),
+        }
+    }
+    pub fn pairing_esc_pressed(&mut self) {
+        self.pairing_prompt_close(None);
+    }
+    /// Closes the prompt if BlueZ gave up on the request (cancelled, timed out) while it was open.
+    pub fn pairing_tick(&mut self) {
+        if self
+            .pairing
+            .pending
+            .as_ref()
+            .is_some_and(|pending| pending.reply.is_closed())
+        {
+            self.pairing_prompt_close(None);
+        }
+    }
+    fn pairing_prompt_close(&mut self, answer: Option<String>) {
+        if let Some(pending) = self.pairing.pending.take() {
+            let _ = pending.reply.send(answer);
+        }
+        self.pairing.input.reset();
+        if self.sub_state == SubState::PairingPrompt {
+            self.sub_state = std::mem::take(&mut self.pairing.previous_sub_state);
+        }
+    }
+}
+
+#[cfg(all(feature = "pairing_agent", target_os = "linux"))]
+mod agent {
+    use std::time::Duration;
+
+    use dbus::channel::MatchingReceiver;
+    use dbus::message::MatchRule;
+    use dbus::nonblock::Proxy;
+    use dbus::{MethodErr, Path};
+    use dbus_crossroads::Crossroads;
+    use tokio::sync::oneshot;
+    use tokio_util::sync::CancellationToken;
+    use tracing::info;
+
+    use super::{PairingKind, PairingRequest, PairingTx};
+    use crate::errors::AppError;
+
+    const AGENT_PATH: &str = "/io/github/nullstalgia/iron_heart/agent";
+    const AGENT_IFACE: &str = "org.bluez.Agent1";
+    const AGENT_MANAGER: &str = "org.bluez.AgentManager1";
+    const DEVICE_IFACE: &str = "org.bluez.Device1";
+    // Lets us show a passkey, or take one typed in
+    const CAPABILITY: &str = "KeyboardDisplay";
+
+    const DBUS_TIMEOUT: Duration = Duration::from_secs(10);
+    // Long enough for someone to go find the PIN, BlueZ gives up on the agent after a minute anyway
+    const PAIR_TIMEOUT: Duration = Duration::from_secs(90);
+
+    fn rejected() -> MethodErr {
+        ("org.bluez.Error.Rejected", "Rejected").into()
+    }
+    fn canceled() -> MethodErr {
+        ("org.bluez.Error.Canceled", "Canceled").into()
+    }
+
+    /// Pairs with `device_id` (btleplug's id, i.e. "hci0/dev_AA_BB_CC_DD_EE_FF") through our own agent.
+    ///
+    /// Since the agent's registered on the same connection that calls Pair, BlueZ asks it instead
+    /// of the desktop's, without us having to become the default agent.
+    pub async fn pair(
+        device_id: &str,
+        device_name: &str,
+        requests: PairingTx,
+        cancel_token: &CancellationToken,
+    ) -> Result<(), AppError> {
+        let (resource, conn) = dbus_tokio::connection::new_system_sync()?;
+        // Handles everything coming in and out, only finishes if the connection's lost
+        let mut connection = tokio::spawn(resource);
+        // Drops any request still waiting on the TUI once we're done
+        let done = CancellationToken::new();
+        let result = async {
+            let mut cr = objects(device_name.to_owned(), requests, done.clone());
+            cr.set_async_support(Some((
+                conn.clone(),
+                Box::new(|future| {
+                    tokio::spawn(future);
+                }),
+            )));
+            conn.start_receive(
+                MatchRule::new_method_call(),
+                Box::new(move |msg, conn| {
+                    let _ = cr.handle_message(msg, conn);
+                    true
+                }),
+            );
+
+            let manager = Proxy::new("org.bluez", "/org/bluez", DBUS_TIMEOUT, conn.clone());
+            manager
+                .method_call::<(), _, _, _>(
+                    AGENT_MANAGER,
+                    "RegisterAgent",
+                    (Path::from(AGENT_PATH), CAPABILITY),
+                )
+                .await?;
+            let device = Proxy::new(
+                "org.bluez",
+                format!("/org/bluez/{device_id}"),
+                PAIR_TIMEOUT,
+                conn.clone(),
+            );
+            info!("Pairing with {device_name}");
+            let paired = tokio::select! {
+                paired = device.method_call::<(), _, _, _>(DEVICE_IFACE, "Pair", ()) => paired.map_err(AppError::from),
+                lost = &mut connection => {
+                    let reason = match lost {
+                        Ok(e) => e.to_string(),
+                        Err(e) => e.to_string(),
+                    };
+                    Err(dbus::Error::new_failed(&format!("Lost connection to D-Bus: {reason}")).into())
+                }
+                // Shutting down, the connect loop sees the token right after
+                _ = cancel_token.cancelled() => {
+                    let _ = device.method_call::<(), _, _, _>(DEVICE_IFACE, "CancelPairing", ()).await;
+                    Ok(())
+                }
+            };
+            let _ = manager
+                .method_call::<(), _, _, _>(AGENT_MANAGER, "UnregisterAgent", (Path::from(AGENT_PATH),))
+                .await;
+            paired
+        }
+        .await;
+        done.cancel();
+        connection.abort();
+        result
+    }
+
+    /// Asks the TUI, giving up if BlueZ cancels or pairing finishes first.
+    async fn ask(
+        requests: PairingTx,
+        device: String,
+        kind: PairingKind,
+        done: CancellationToken,
+    ) -> Result<String, MethodErr> {
+        let (reply, answer) = oneshot::channel();
+        requests
+            .send(PairingRequest {
+                device,
+                kind,
+                reply,
+            })
+            .await
+            .map_err(|_| rejected())?;
+        tokio::select! {
+            answer = answer => answer.ok().flatten().ok_or_else(canceled),
+            _ = done.cancelled() => Err(canceled()),
+        }
+    }
+
+    /// The Agent1 object BlueZ calls back into while pairing.
+    ///
+    /// We're only ever asked about the device we're pairing ourselves, so confirmations are accepted as-is.
+    fn objects(device_name: String, requests: PairingTx, done: CancellationToken) -> Crossroads {
+        let mut cr = Crossroads::new();
+        let agent = cr.register(AGENT_IFACE, |b| {
+            let (name, tx, token) = (device_name.clone(), requests.clone(), done.clone());
+            b.method_with_cr_async(
+                "RequestPinCode",
+                ("device",),
+                ("pincode",),
+                move |mut ctx, _, (_,): (Path<'static>,)| {
+                    let answer = ask(
+                        tx.clone(),
+                        name.clone(),
+                        PairingKind::PinCode,
+                        token.clone(),
+                    );
+                    async move { ctx.reply(answer.await.map(|pin_code| (pin_code,))) }
+                },
+            );
+            let (name, tx, token) = (device_name.clone(), requests.clone(), done.clone());
+            b.method_with_cr_async(
+                "RequestPasskey",
+                ("device",),
+                ("passkey",),
+                move |mut ctx, _, (_,): (Path<'static>,)| {
+                    let answer = ask(
+                        tx.clone(),
+                        name.clone(),
+                        PairingKind::Passkey,
+                        token.clone(),
+                    );
+                    async move {
+                        let passkey = answer
+                            .await
+                            .and_then(|passkey| passkey.parse::<u32>().map_err(|_| rejected()));
+                        ctx.reply(passkey.map(|passkey| (passkey,)))
+                    }
+                },
+            );
+            let name = device_name.clone();
+            b.method(
+                "DisplayPasskey",
+                ("device", "passkey", "entered"),
+                (),
+                move |_, _: &mut (), (_, passkey, _): (Path<'static>, u32, u16)| {
+                    info!("Pairing with {name}, passkey: {passkey:06}");
+                    Ok(())
+                },
+            );
+            let name = device_name.clone();
+            b.method(
+                "DisplayPinCode",
+                ("device", "pincode"),
+                (),
+                move |_, _: &mut (), (_, pin_code): (Path<'static>, String)| {
+                    info!("Pairing with {name}, PIN: {pin_code}");
+                    Ok(())
+                },
+            );
+            b.method(
+                "RequestConfirmation",
+                ("device", "passkey"),
+                (),
+                |_, _: &mut (), _: (Path<'static>, u32)| Ok(()),
+            );
+            b.method(
+                "RequestAuthorization",
+                ("device",),
+                (),
+                |_, _: &mut (), _: (Path<'static>,)| Ok(()),
+            );
+            b.method(
+                "AuthorizeService",
+                ("device", "uuid"),
+                (),
+                |_, _: &mut (), _: (Path<'static>, String)| Ok(()),
+            );
+            let token = done.clone();
+            b.method("Cancel", (), (), move |_, _: &mut (), ()| {
+                token.cancel();
+                Ok(())
+            });
+            b.method("Release", (), (), |_, _: &mut (), ()| Ok(()));
+        });
+        cr.insert(AGENT_PATH, &[agent], ());
+        cr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_answers() {
+        assert_eq!(
+            PairingKind::Passkey.validate(" 012345 "),
+            Ok("12345".into())
+        );
+        assert_eq!(PairingKind::Passkey.validate("999999"), Ok("999999".into()));
+        assert!(PairingKind::Passkey.validate("1000000").is_err());
+        assert!(PairingKind::Passkey.validate("12a4").is_err());
+        assert!(PairingKind::Passkey.validate("").is_err());
+        assert_eq!(PairingKind::PinCode.validate("0000"), Ok("0000".into()));
+        assert!(PairingKind::PinCode.validate("").is_err());
+        assert!(PairingKind::PinCode.validate(&"1".repeat(17)).is_err());
+    }
+}
@@ -0,0 +1,50 @@
+use ratatui::{
+    layout::{Alignment, Constraint, Layout},
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+
+use ratatui::prelude::*;
+
+use super::PairingKind;
+use crate::{app::App, utils::centered_rect};
+
+pub fn render_pairing_prompt(app: &mut App, f: &mut Frame) {
+    let Some(pending) = app.pairing.pending.as_ref() else {
+        return;
+    };
+    let mut area = centered_rect(40, 25, f.area());
+    area.height = area.height.min(5);
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_style(Style::new().green())
+        .title(format!("Pairing with {}", pending.device))
+        .title_bottom("[esc → cancel]")
+        .title_alignment(Alignment::Center);
+
+    f.render_widget(Clear, area);
+    f.render_widget(&block, area);
+
+    let vertical = Layout::vertical([Constraint::Max(1), Constraint::Max(1), Constraint::Fill(1)]);
+    let inner_area = block.inner(area);
+    let [label_area, input_area, invalid_area] = vertical.areas(inner_area);
+
+    let label = match pending.kind {
+        PairingKind::PinCode => "Enter the device's PIN:",
+        PairingKind::Passkey => "Enter the device's passkey:",
+    };
+    f.render_widget(Paragraph::new(label).centered(), label_area);
+    if let Some(invalid) = app.pairing.invalid {
+        f.render_widget(Paragraph::new(invalid.red()).centered(), invalid_area);
+    }
+
+    let width = input_area.width.max(1) - 1; // So the cursor doesn't bleed off the edge
+    let scroll = app.pairing.input.visual_scroll(width as usize);
+    let input = Paragraph::new(app.pairing.input.value()).scroll((0, scroll as u16));
+    f.render_widget(input, input_area);
+    f.set_cursor_position((
+        // Put cursor past the end of the input text
+        input_area.x + ((app.pairing.input.visual_cursor()).max(scroll) - scroll) as u16,
+        input_area.y,
+    ));
+}
@@ -0,0 +1,208 @@
+use btleplug::api::{Central, CentralEvent, CentralState, Manager as _, Peripheral, ScanFilter};
+use btleplug::platform::{Adapter, Manager};
+use futures::StreamExt;
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::net::UdpSocket;
+use tokio::time::{timeout, Duration, Instant};
+use tracing::{debug, info};
+
+use crate::settings::{BLESettings, OscSettings, Settings};
+
+// Long enough for most straps to advertise a few times
+const SAVED_DEVICE_SCAN: Duration = Duration::from_secs(3);
+// Port unreachable replies come back almost instantly over loopback
+const OSC_REPLY_WAIT: Duration = Duration::from_millis(300);
+// "#bundle", with an "immediately" timetag and no content, so it's harmless if something is listening
+const EMPTY_BUNDLE: &[u8] = b"#bundle\0\0\0\0\0\0\0\0\x01";
+
+/// Something found at startup that would otherwise turn into a vague error later on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PreflightIssue {
+    NoBluetoothAdapter,
+    BluetoothOff,
+    SavedDeviceNotFound,
+    OscTargetUnresolved(String),
+    VrchatNotDetected(SocketAddr),
+}
+
+impl PreflightIssue {
+    /// What's wrong, and what the user can do about it.
+    pub fn message(&self) -> String {
+        match self {
+            PreflightIssue::NoBluetoothAdapter => {
+                "No Bluetooth adapter found. Plug one in (or enable it in Device Manager), then Retry."
+                    .into()
+            }
+            PreflightIssue::BluetoothOff => "Bluetooth is turned off. Enable Bluetooth, then Retry.".into(),
+            PreflightIssue::SavedDeviceNotFound => {
+                "None of your saved devices are advertising. Make sure it's on and being worn.".into()
+            }
+            PreflightIssue::OscTargetUnresolved(host) => {
+                format!("Couldn't find OSC target \"{host}\". Check osc.target_ip.")
+            }
+            PreflightIssue::VrchatNotDetected(target) => {
+                format!("VRChat not detected (nothing listening on {target}). Is it running with OSC enabled?")
+            }
+        }
+    }
+}
+
+/// Runs the checks relevant to the configured HR source and OSC settings.
+pub async fn run(settings: &Settings) -> Vec<PreflightIssue> {
+    let mut issues = Vec::new();
+    let uses_ble = !settings.dummy.enabled && !settings.websocket.enabled;
+    if uses_ble {
+        if let Err(issue) = check_bluetooth(&settings.ble).await {
+            issues.push(issue);
+        }
+    }
+    if let Err(issue) = check_osc_target(&settings.osc).await {
+        issues.push(issue);
+    }
+    info!("Pre-flight checks done, {} issue(s)", issues.len());
+    issues
+}
+
+async fn check_bluetooth(ble_settings: &BLESettings) -> Result<(), PreflightIssue> {
+    let adapter = first_adapter()
+        .await
+        .ok_or(PreflightIssue::NoBluetoothAdapter)?;
+    // Not every platform can tell us, so only a definite "off" counts
+    if let Ok(CentralState::PoweredOff) = adapter.adapter_state().await {
+        return Err(PreflightIssue::BluetoothOff);
+    }
+    if ble_settings.saved_devices.is_empty() {
+        return Ok(());
+    }
+    if saw_saved_device(&adapter, ble_settings).await {
+        Ok(())
+    } else {
+        Err(PreflightIssue::SavedDeviceNotFound)
+    }
+}
+
+async fn first_adapter() -> Option<Adapter> {
+    let manager = Manager::new().await.ok()?;
+    manager.adapters().await.ok()?.into_iter().next()
+}
+
+// Stops as soon as one shows up
+async fn saw_saved_device(adapter: &Adapter, ble_settings: &BLESettings) -> bool {
+    let Ok(mut events) = adapter.events().await else {
+        return false;
+    };
+    if adapter.start_scan(ScanFilter::default()).await.is_err() {
+        return false;
+    }
+    let deadline = Instant::now() + SAVED_DEVICE_SCAN;
+    let mut found = false;
+    while let Ok(Some(event)) = timeout(
+        deadline.saturating_duration_since(Instant::now()),
+        events.next(),
+    )
+    .await
+    {
+        let (CentralEvent::DeviceDiscovered(id) | CentralEvent::DeviceUpdated(id)) = event else {
+            continue;
+        };
+        let Ok(peripheral) = adapter.peripheral(&id).await else {
+            continue;
+        };
+        let Ok(Some(properties)) = peripheral.properties().await else {
+            continue;
+        };
+        let name = properties.local_name.unwrap_or_default();
+        // Same as `DeviceInfo::get_id()`
+        let address = if cfg!(target_os = "macos") {
+            id.to_string()
+        } else {
+            properties.address.to_string()
+        };
+        if ble_settings.saved_priority(&name, &address).is_some() {
+            debug!("Pre-flight: Saw saved device {name}");
+            found = true;
+            break;
+        }
+    }
+    if let Err(e) = adapter.stop_scan().await {
+        debug!("Pre-flight: Failed to stop scan: {e}");
+    }
+    found
+}
+
+/// Only local targets are checked for a listener, remote hosts don't reliably
+/// reply when nothing's listening.
+async fn check_osc_target(osc_settings: &OscSettings) -> Result<(), PreflightIssue> {
+    // OSCQuery looks up the real port itself
+    if !osc_settings.enabled || osc_settings.oscquery {
+        return Ok(());
+    }
+    let host = osc_settings
+        .target_ip
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let unresolved = || PreflightIssue::OscTargetUnresolved(osc_settings.target_ip.clone());
+    let target = tokio::net::lookup_host((host, osc_settings.port))
+        .await
+        .map_err(|_| unresolved())?
+        .next()
+        .ok_or_else(unresolved)?;
+    if !target.ip().is_loopback() {
+        return Ok(());
+    }
+    match target_listening(target).await {
+        Some(false) => Err(PreflightIssue::VrchatNotDetected(target)),
+        _ => Ok(()),
+    }
+}
+
+/// Sends an empty bundle and waits for a "port unreachable" reply.
+///
+/// Returns None if it couldn't tell.
+async fn target_listening(target: SocketAddr) -> Option<bool> {
+    let bind_ip = match target.ip() {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+    };
+    let socket = UdpSocket::bind((bind_ip, 0)).await.ok()?;
+    socket.connect(target).await.ok()?;
+    socket.send(EMPTY_BUNDLE).await.ok()?;
+    let mut buf = [0; 64];
+    match timeout(OSC_REPLY_WAIT, socket.recv(&mut buf)).await {
+        // Windows reports it as a reset
+        Ok(Err(e))
+            if matches!(
+                e.kind(),
+                ErrorKind::ConnectionRefused | ErrorKind::ConnectionReset
+            ) =>
+        {
+            Some(false)
+        }
+        Ok(_) => Some(true),
+        // No reply, but no complaints either
+        Err(_) => Some(true),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn detects_closed_local_port() {
+        // Grab a free port, then close it so nothing's listening
+        let port = {
+            let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+            socket.local_addr().unwrap().port()
+        };
+        let target = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port);
+        assert_eq!(target_listening(target).await, Some(false));
+    }
+    #[tokio::test]
+    async fn detects_open_local_port() {
+        let listener = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let target = listener.local_addr().unwrap();
+        assert_eq!(target_listening(target).await, Some(true));
+    }
+}
@@ -0,0 +1,128 @@
+// Keeping a connection to another app or service (OBS, Streamer.bot, Intiface, Twitch chat, MQTT brokers, remote HR services)
+// Connecting, backing off between attempts and reporting lost connections is the same for all of them,
+// each client only says how to connect and what to do while connected
+
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::broadcast::Sender as BSender;
+use tokio_util::sync::CancellationToken;
+use tokio_websockets::{ClientBuilder, MaybeTlsStream, WebSocketStream};
+use tracing::{info, warn};
+
+use crate::app::{AppUpdate, ErrorPopup};
+use crate::backoff::Backoff;
+use crate::broadcast;
+use crate::errors::AppError;
+use crate::journal::{self, Severity, Subsystem};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// Doubles with each failed attempt, back to the start once connected
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+pub type WsClient = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Why a connection attempt didn't pan out.
+pub enum ConnectError {
+    /// Worth trying again later, with why.
+    Retry(String),
+    /// No point retrying until the user changes something (bad URL, credentials rejected).
+    Fatal(AppError),
+}
+
+impl From<AppError> for ConnectError {
+    fn from(e: AppError) -> Self {
+        Self::Fatal(e)
+    }
+}
+
+/// Connects to a WebSocket server, for clients that don't need anything special.
+pub async fn connect_websocket(uri: &str) -> Result<WsClient, ConnectError> {
+    let builder = ClientBuilder::new()
+        .uri(uri)
+        .map_err(|e| ConnectError::Fatal(e.into()))?;
+    builder
+        .connect()
+        .await
+        .map(|(client, _)| client)
+        .map_err(|e| ConnectError::Retry(e.to_string()))
+}
+
+pub trait ReconnectingClient {
+    type Connection;
+    /// What the session listens to besides the connection, the app's updates for outputs, `()` for sources.
+    type Updates;
+    /// For the log and popups, i.e. "OBS".
+    fn name(&self) -> &'static str;
+    fn subsystem(&self) -> Subsystem;
+    /// Where it's connecting to, for the log, so no tokens or keys.
+    fn target(&self) -> String;
+    async fn connect(&mut self) -> Result<Self::Connection, ConnectError>;
+    /// Ok with why once the connection's done for (None if cancelled), Err if there's no point reconnecting.
+    async fn session(
+        &mut self,
+        connection: Self::Connection,
+        updates: &mut Self::Updates,
+        broadcast_tx: &BSender<AppUpdate>,
+        cancel_token: &CancellationToken,
+    ) -> Result<Option<String>, AppError>;
+    /// After losing the connection, i.e. so sources don't leave their last reading hanging.
+    fn on_lost(&mut self, _broadcast_tx: &BSender<AppUpdate>) {}
+}
+
+/// Keeps the client connected until cancelled, or until it hits something retrying won't fix.
+pub async fn run<C: ReconnectingClient>(
+    client: &mut C,
+    updates: &mut C::Updates,
+    broadcast_tx: &BSender<AppUpdate>,
+    cancel_token: &CancellationToken,
+) -> Result<(), AppError> {
+    let name = client.name();
+    let mut backoff = Backoff::new(RECONNECT_DELAY, MAX_RECONNECT_DELAY, None);
+    loop {
+        info!("{name}: Connecting to {}", client.target());
+        let connect = tokio::select! {
+            connect = tokio::time::timeout(CONNECT_TIMEOUT, client.connect()) => connect,
+            _ = cancel_token.cancelled() => return Ok(()),
+        };
+        let reason = match connect {
+            Ok(Ok(connection)) => {
+                backoff.reset();
+                journal::record(
+                    Severity::Info,
+                    client.subsystem(),
+                    &format!("Connected to {}", client.target()),
+                );
+                match client
+                    .session(connection, updates, broadcast_tx, cancel_token)
+                    .await?
+                {
+                    Some(reason) => reason,
+                    None => {
+                        info!("Shutting down {name} thread!");
+                        return Ok(());
+                    }
+                }
+            }
+            Ok(Err(ConnectError::Retry(e))) => format!("Couldn't connect to {name}: {e}"),
+            Ok(Err(ConnectError::Fatal(e))) => return Err(e),
+            Err(_) => format!("Timed out connecting to {name}"),
+        };
+        warn!("{name}: {reason}");
+        journal::record(
+            Severity::Warning,
+            client.subsystem(),
+            &format!("Connection lost: {reason}"),
+        );
+        client.on_lost(broadcast_tx);
+        broadcast!(broadcast_tx, ErrorPopup::Intermittent(reason));
+        // Only None with a window, which we don't have
+        let delay = backoff
+            .next_delay(std::time::Instant::now())
+            .unwrap_or(MAX_RECONNECT_DELAY);
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = cancel_token.cancelled() => return Ok(()),
+        }
+    }
+}
@@ -1,12 +1,15 @@
-use config::{Config, File as ConfigFile};
+use config::{Config, File as ConfigFile, Map};
 use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::Write;
 use std::path::PathBuf;
 use std::str::FromStr;
 use tracing::{info, level_filters::LevelFilter};
 
+use crate::alerts::AlertKind;
 use crate::errors::AppError;
+use crate::sources::HrSource;
 
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct MiscSettings {
@@ -14,8 +17,28 @@ pub struct MiscSettings {
     pub write_bpm_to_file: bool,
     pub write_rr_to_file: bool,
     pub bpm_file_path: String,
+    // {bpm}, {rr}, {battery} and {zone} are filled in
+    pub bpm_file_template: String,
+    // 0 to write on every update
+    pub bpm_file_interval_ms: u32,
     pub log_sessions_to_csv: bool,
     pub log_sessions_csv_path: String,
+    // Starts a new CSV after this long, 0 for one per run
+    pub log_sessions_rotate_minutes: u32,
+    // Oldest CSVs (or stored sessions) past this many are deleted, 0 to keep them all
+    pub log_sessions_keep: u32,
+    // Sessions go into a SQLite database instead, needs the `sqlite` feature
+    pub log_sessions_to_sqlite: bool,
+    pub log_sessions_sqlite_path: String,
+    // Saves the unparsed payloads from the HR source next to the session CSV
+    pub raw_capture: bool,
+    pub journal_enabled: bool,
+    pub journal_path: String,
+    pub preflight_checks: bool,
+    // Keeps the system awake while HR is coming in
+    pub inhibit_sleep: bool,
+    // For the low_battery alert and the on_low_battery hook, 0 to disable
+    pub low_battery_percent: u8,
     pub vrcx_shortcut_prompt: bool,
 }
 
@@ -28,14 +51,239 @@ pub struct TuiSettings {
     pub chart_rr_clamp_high: bool,
     pub chart_rr_clamp_low: bool,
     pub charts_combine: bool,
+    // Hides MAC addresses, IPs, and URLs in the TUI
+    pub stream_safe: bool,
+    pub startup_banner: String,
+    pub remember_layout: bool,
+    pub show_unnamed_devices: bool,
+    // Limits chart updates for slow terminals (SSH/mosh), see `ChartThrottle`
+    pub chart_throttle: ChartThrottle,
+    pub chart_throttle_interval_ms: u32,
+    // 0 to keep every point
+    pub chart_throttle_max_points: u16,
+    pub layout: LayoutPreset,
+    pub theme: Theme,
 }
 
-#[derive(Debug, Deserialize, Serialize, Default)]
+/// What the heart rate screen is made up of, cycled with `m`
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LayoutPreset {
+    /// Status table with the charts below
+    #[default]
+    Full,
+    /// Just the status table, for a small window
+    Compact,
+    /// Just the charts, i.e. for a window capture
+    Charts,
+}
+
+impl LayoutPreset {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Full => Self::Compact,
+            Self::Compact => Self::Charts,
+            Self::Charts => Self::Full,
+        }
+    }
+}
+
+/// Colors for the charts, cycled with `g`
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Theme {
+    #[default]
+    Default,
+    /// Brighter lines, for washed out terminals or captures
+    HighContrast,
+    Monochrome,
+}
+
+impl Theme {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Default => Self::HighContrast,
+            Self::HighContrast => Self::Monochrome,
+            Self::Monochrome => Self::Default,
+        }
+    }
+}
+
+/// When chart updates get rate limited and thinned out
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChartThrottle {
+    /// Once drawing to the terminal starts lagging
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct BLESettings {
     pub never_ask_to_save: bool,
+    // Highest priority first
+    pub saved_devices: Vec<SavedDevice>,
+    // How long to wait for a higher priority device before settling for a lower one
+    pub saved_device_wait_sec: u16,
+    // Gives up on a connection attempt (connecting through subscribing) after this long
+    pub connect_timeout_sec: u16,
+    // Only read to migrate older configs into `saved_devices`
+    #[serde(default, skip_serializing)]
     pub saved_name: String,
+    #[serde(default, skip_serializing)]
     pub saved_address: String,
     pub rr_ignore_after_empty: u16,
+    pub reconnect_initial_delay_sec: u16,
+    pub reconnect_max_delay_sec: u16,
+    pub reconnect_window_sec: u32,
+    // In dBm, 0 disables the warning
+    pub rssi_warning_threshold: i16,
+    // Battery is also updated live if the device sends notifications for it
+    pub battery_poll_sec: u16,
+    // Re-subscribes (then reconnects) if connected but no HR arrives for this long
+    pub notification_watchdog_sec: u16,
+    // Never connects, reads HR from the chosen device's advertisements instead
+    pub advertisement_only: bool,
+    // Scan list filters, saved devices are always connected to
+    pub scan_hr_service_only: bool,
+    // Regex, empty to allow any name
+    pub scan_name_filter: String,
+    // Connected to alongside the main device (e.g. a watch as backup for a chest strap)
+    // Leave both empty to disable
+    pub secondary_device: SavedDevice,
+    pub aggregate_policy: AggregatePolicy,
+    // How long a device can go without sending HR before the other takes over
+    pub aggregate_gap_sec: u16,
+    // Appends every raw HR notification to `raw_dump_path`, for debugging parsing issues
+    pub raw_dump_enabled: bool,
+    pub raw_dump_path: String,
+    // Streams raw ECG from Polar devices (i.e. H10) for local beat detection
+    pub polar_ecg_enabled: bool,
+    // Empty to not log the ECG
+    pub polar_ecg_log_path: String,
+    // Also subscribes to cycling/running cadence, if the device has it
+    pub cadence_enabled: bool,
+    pub hooks: HookSettings,
+    pub quirks: Vec<DeviceQuirk>,
+}
+
+/// How HR from the main and secondary device gets combined
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AggregatePolicy {
+    /// Main device only, falling back to the secondary during gaps
+    #[default]
+    PreferPrimary,
+    /// Mean of both while they're both sending
+    Average,
+}
+
+/// Shell commands run on device events, with `HR_EVENT`, `HR_DEVICE_NAME`,
+/// `HR_DEVICE_ADDRESS`, and `HR_BATTERY` set. Empty to disable
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct HookSettings {
+    pub on_connect: String,
+    pub on_disconnect: String,
+    pub on_low_battery: String,
+}
+
+/// Overrides for devices that don't quite follow the Heart Rate Service spec,
+/// matched by name or address like `SavedDevice`
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct DeviceQuirk {
+    pub name: String,
+    pub address: String,
+    // UUID (or 16-bit short form, i.e. "fff4") of a characteristic that sends
+    // Heart Rate Measurement formatted data. Empty for the standard one
+    pub hr_characteristic: String,
+    pub rr_unit: RrUnit,
+}
+
+impl DeviceQuirk {
+    fn matches(&self, name: &str, address: &str) -> bool {
+        (!self.name.is_empty() && self.name == name)
+            || (!self.address.is_empty() && self.address == address)
+    }
+}
+
+/// What the RR intervals in a Heart Rate Measurement are counted in
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RrUnit {
+    /// 1/1024ths of a second, as per the spec
+    #[default]
+    Standard,
+    /// Some budget devices send plain milliseconds instead
+    Milliseconds,
+}
+
+/// A device that gets connected to automatically when found
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct SavedDevice {
+    pub name: String,
+    pub address: String,
+}
+
+impl SavedDevice {
+    fn matches(&self, name: &str, address: &str) -> bool {
+        (!self.name.is_empty() && self.name == name)
+            || (!self.address.is_empty() && self.address == address)
+    }
+}
+
+impl BLESettings {
+    pub fn has_secondary_device(&self) -> bool {
+        !self.secondary_device.name.is_empty() || !self.secondary_device.address.is_empty()
+    }
+    pub fn is_secondary_device(&self, name: &str, address: &str) -> bool {
+        self.secondary_device.matches(name, address)
+    }
+    pub fn quirk_for(&self, name: &str, address: &str) -> Option<&DeviceQuirk> {
+        self.quirks
+            .iter()
+            .find(|quirk| quirk.matches(name, address))
+    }
+    /// Where the device is in `saved_devices` (0 being the highest priority), if it's in there.
+    pub fn saved_priority(&self, name: &str, address: &str) -> Option<usize> {
+        self.saved_devices
+            .iter()
+            .position(|saved| saved.matches(name, address))
+    }
+    /// Updates the matching saved device in case its name or address changed,
+    /// or adds it with the lowest priority.
+    ///
+    /// Returns true if anything changed.
+    pub fn remember_device(&mut self, name: &str, address: &str) -> bool {
+        let device = SavedDevice {
+            name: name.to_owned(),
+            address: address.to_owned(),
+        };
+        match self.saved_priority(name, address) {
+            Some(index) if self.saved_devices[index] == device => false,
+            Some(index) => {
+                self.saved_devices[index] = device;
+                true
+            }
+            None => {
+                self.saved_devices.push(device);
+                true
+            }
+        }
+    }
+    // Older versions only had room for a single saved device
+    fn migrate_legacy_saved_device(&mut self) {
+        let name = std::mem::take(&mut self.saved_name);
+        let address = std::mem::take(&mut self.saved_address);
+        if name.is_empty() && address.is_empty() {
+            return;
+        }
+        if self.saved_priority(&name, &address).is_none() {
+            self.saved_devices.insert(0, SavedDevice { name, address });
+        }
+    }
 }
 
 // TODO Async get for osc settings due to oscquery
@@ -46,14 +294,100 @@ pub struct BLESettings {
 pub struct OscSettings {
     pub enabled: bool,
     pub host_ip: String,
+    pub host_port: u16,
     pub target_ip: String,
     pub port: u16,
+    pub target_resolve_interval_sec: u16,
+    pub beat_style: BeatStyle,
     pub pulse_length_ms: u16,
+    pub envelope_attack_ms: u16,
+    pub envelope_decay_ms: u16,
     pub only_positive_float_bpm: bool,
+    pub calibration_sweep_sec: u16,
     pub hide_disconnections: bool,
     pub max_hide_disconnection_sec: u16,
     pub twitch_rr_threshold_ms: u16,
+    pub ecg_waveform_enabled: bool,
+    pub ecg_waveform_samples: u16,
+    pub ecg_waveform_amplitude: f32,
+    pub real_timetags: bool,
+    pub timetag_delay_ms: u16,
+    pub rr_delivery: RrDelivery,
+    pub rr_mean_window_ms: u16,
+    pub send_stress_index: bool,
+    pub stress_index_max: f32,
+    pub send_instant_bpm: bool,
+    pub bools_as_ints: bool,
+    pub session_goal_min: u16,
+    pub session_goal_min_bpm: u16,
+    pub session_goal_max_bpm: u16,
+    pub debug_tap: bool,
+    pub dry_run: bool,
+    pub oscquery: bool,
     pub addresses: OscAddrConf,
+    // Param name -> expression, see `osc::expr`
+    pub custom_params: BTreeMap<String, String>,
+    // Alternate addresses that can be switched to while running
+    pub presets: BTreeMap<String, OscPreset>,
+    // Other apps that get the same params as target_ip, each with their own RR delivery
+    pub extra_targets: Vec<OscTarget>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct OscTarget {
+    pub ip: String,
+    pub port: u16,
+    #[serde(default)]
+    pub rr_delivery: RrDelivery,
+    // 0 for the same window as osc.rr_mean_window_ms
+    #[serde(default)]
+    pub rr_mean_window_ms: u16,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct OscPreset {
+    // A single digit that switches straight to this preset, empty for none
+    #[serde(default)]
+    pub key: String,
+    // Same names as [osc.addresses], anything left out is taken from there
+    #[serde(default)]
+    pub addresses: BTreeMap<String, String>,
+}
+
+impl OscPreset {
+    pub fn bound_to(&self, key: char) -> bool {
+        self.key.chars().eq([key])
+    }
+}
+
+/// Which param(s) get driven by each heart beat
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BeatStyle {
+    /// Both `beat_pulse` and `beat_toggle`
+    #[default]
+    PulseAndToggle,
+    /// `beat_pulse` is true for `pulse_length_ms`
+    Pulse,
+    /// `beat_toggle` flips on each beat
+    Toggle,
+    /// `beat_envelope` float ramps up over `envelope_attack_ms`, then down over `envelope_decay_ms`
+    Envelope,
+}
+
+/// How RR intervals get sent when a device reports more than one at a time
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RrDelivery {
+    /// Only the most recent interval
+    #[default]
+    Latest,
+    /// A separate message for each interval
+    Each,
+    /// One message with an Int argument per interval
+    MultiArg,
+    /// The average of the intervals received over `rr_mean_window_ms`, sent once per window
+    Mean,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
@@ -71,17 +405,77 @@ pub struct OscAddrConf {
     pub rr_twitch_up: String,
     pub rr_twitch_down: String,
     pub activity: String,
+    pub ecg_waveform: String,
+    pub stress_index: String,
+    pub beat_envelope: String,
+    pub goal_progress: String,
+    pub zone: String,
+    pub spo2: String,
+    pub cadence: String,
+    pub instant_bpm: String,
+    pub health: String,
     // TODO Session Max/Min/Avg Params?
 }
 
+impl OscAddrConf {
+    /// Replaces the named addresses, for `osc.presets`.
+    pub fn with_overrides(&self, overrides: &BTreeMap<String, String>) -> Result<Self, AppError> {
+        let mut conf = serde_json::to_value(self)?;
+        for (name, address) in overrides {
+            match conf.get_mut(name) {
+                Some(field) => *field = address.as_str().into(),
+                None => return Err(AppError::OscAddress(name.clone(), address.clone())),
+            }
+        }
+        Ok(serde_json::from_value(conf)?)
+    }
+}
+
+/// Shape of the dummy source's BPM over time
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DummyMode {
+    /// Straight up and down between low_bpm and high_bpm
+    #[default]
+    Triangle,
+    /// Same range and timing as Triangle, but eased
+    Sine,
+    /// Wanders around between low_bpm and high_bpm, drifting back towards the middle
+    RandomWalk,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct DummySettings {
     // When enabled, BLE and Websockets are disabled
     pub enabled: bool,
+    pub mode: DummyMode,
     pub low_bpm: u16,
     pub high_bpm: u16,
     pub bpm_speed: f32,
     pub loops_before_dc: u16,
+    // Random +/- on each RR interval, like a real heart's variability
+    pub rr_jitter_ms: u16,
+    // Chance (0-1) of an RR interval being a missed/extra beat, for testing filters
+    pub artifact_chance: f32,
+}
+
+/// What to do with websocket readings outside of min_bpm/max_bpm
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutOfRangePolicy {
+    #[default]
+    Drop,
+    /// Pinned to the nearest bound
+    Clamp,
+}
+
+/// Where to find readings in incoming JSON, as JSON pointers (i.e. "/data/heartRate").
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct JsonMapping {
+    // Empty ones fall back to the usual names
+    pub bpm: String,
+    pub rr: String,
+    pub battery: String,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
@@ -89,6 +483,161 @@ pub struct WebSocketSettings {
     // Note: BLE is disabled if websockets are enabled
     pub enabled: bool,
     pub port: u16,
+    // Plain HTTP GET endpoint on the same port, for phone automations
+    pub simple_ingest_enabled: bool,
+    pub simple_ingest_token: String,
+    // Pretend to be OBS, for HeartRateOnStream
+    pub heart_rate_on_stream: bool,
+    // Shared secret websocket clients have to give, none if empty
+    pub auth_token: String,
+    // PEM certificate and PKCS#8 key, serves wss:// instead of ws:// if both are set
+    pub tls_cert_path: String,
+    pub tls_key_path: String,
+    pub json_mapping: JsonMapping,
+    // Announce the server over mDNS, for companion apps to find
+    pub advertise: bool,
+    // Per client, anything over is dropped. 0 for no limit
+    pub max_messages_per_sec: u32,
+    // Plausible BPM range, 0 always gets through since it means no reading
+    pub min_bpm: u16,
+    pub max_bpm: u16,
+    pub out_of_range: OutOfRangePolicy,
+    // Connect out to this ws:// URL instead of listening, if set
+    pub client_url: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct UdpSettings {
+    // BLE is disabled if enabled
+    pub enabled: bool,
+    pub port: u16,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct AntSettings {
+    // Needs a build with the `ant` feature, BLE is disabled if enabled
+    pub enabled: bool,
+    // Pairs with a specific strap, 0 takes the first one found
+    pub device_number: u16,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct SerialSettings {
+    // Needs a build with the `serial` feature, BLE is disabled if enabled
+    pub enabled: bool,
+    // i.e. "COM3" or "/dev/ttyUSB0"
+    pub port: String,
+    pub baud: u32,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct MqttSettings {
+    // BLE is disabled if enabled
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    // Wildcards work, any matching message is taken as a reading
+    pub topic: String,
+    // Both optional
+    pub username: String,
+    pub password: String,
+    pub client_id: String,
+}
+
+/// Publishes HR to an MQTT broker, separate from the MQTT source above
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct MqttOutputSettings {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    // Both optional
+    pub username: String,
+    pub password: String,
+    // Has to differ from the source's if both are connected to the same broker
+    pub client_id: String,
+    // bpm, rr, battery, and status (online/offline) go under this
+    pub base_topic: String,
+    // Home Assistant MQTT discovery
+    pub discovery: bool,
+    pub discovery_prefix: String,
+    pub device_name: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct HttpPollSettings {
+    // BLE is disabled if enabled
+    pub enabled: bool,
+    pub url: String,
+    pub interval_ms: u64,
+    // JSON pointers (RFC 6901) into the response, RR and battery are skipped if empty
+    pub bpm_pointer: String,
+    pub rr_pointer: String,
+    pub battery_pointer: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct PulsoidSettings {
+    // BLE is disabled if enabled
+    pub enabled: bool,
+    // Needs the data:heart_rate:read scope
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct HypeRateSettings {
+    // BLE is disabled if enabled
+    pub enabled: bool,
+    // Developer API key, from HypeRate
+    pub api_key: String,
+    // The ID shown in the HypeRate app
+    pub session_id: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct StromnoSettings {
+    // BLE is disabled if enabled
+    pub enabled: bool,
+    // From the widget link, the whole link works too
+    pub widget_id: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct FitbitSettings {
+    // BLE is disabled if enabled
+    pub enabled: bool,
+    // From a "Personal" app registered at dev.fitbit.com
+    pub client_id: String,
+    // Fitbit only allows 150 requests an hour, so at least 30
+    pub poll_interval_secs: u16,
+    // The app's redirect URL has to be http://localhost:<port>/
+    pub redirect_port: u16,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct FailoverSettings {
+    // Runs the fallbacks alongside the main source, using whichever's first with a recent reading
+    pub enabled: bool,
+    // In priority order, after the main source
+    pub fallbacks: Vec<HrSource>,
+    // How long a source can go without a reading before the next one takes over
+    pub stale_after_sec: u16,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct HrvSettings {
+    // How many RR intervals the Stress Index is calculated over
+    pub stress_index_window: u16,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct ZoneSettings {
+    pub enabled: bool,
+    // Lowest BPM of zone 1 and up, anything below the first is zone 0
+    pub boundaries: Vec<u16>,
+    // Zone only changes once the BPM is this far past a boundary...
+    pub hysteresis_bpm: u16,
+    // ...or has stayed past it for this long
+    pub hysteresis_hold_sec: f32,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
@@ -97,6 +646,209 @@ pub struct ActivitiesSettings {
     pub remember_last: bool,
 }
 
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct SyslogSettings {
+    pub enabled: bool,
+    // host:port
+    pub address: String,
+    pub protocol: SyslogProtocol,
+    log_level: String,
+    // Empty sends the nil value ("-")
+    pub hostname: String,
+}
+
+impl SyslogSettings {
+    pub fn get_log_level(&self) -> LevelFilter {
+        LevelFilter::from_str(&self.log_level).unwrap_or(LevelFilter::INFO)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyslogProtocol {
+    #[default]
+    Udp,
+    /// Uses octet counting framing
+    Tcp,
+}
+
+/// Switches a fan on and off with the heart rate zone
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct FanSettings {
+    pub enabled: bool,
+    pub backend: FanBackend,
+    // Turns on at or above this zone...
+    pub on_zone: u8,
+    // ...and off at or below this one
+    pub off_zone: u8,
+    pub min_switch_sec: u16,
+    pub turn_off_on_exit: bool,
+    pub ha_url: String,
+    pub ha_token: String,
+    pub ha_entity_id: String,
+    // IP or host, port 9999 is assumed if not given
+    pub kasa_address: String,
+}
+
+/// Chat bot that answers a command with the current BPM, and can announce milestones
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct TwitchSettings {
+    pub enabled: bool,
+    // Account the bot chats as, and an OAuth token for it with chat:read and chat:edit
+    pub username: String,
+    pub oauth_token: String,
+    pub channel: String,
+    pub command: String,
+    pub reply: String,
+    pub command_cooldown_sec: u32,
+    pub announce_session_max: bool,
+    // New maxes under this aren't worth announcing
+    pub session_max_above: u16,
+    pub session_max_message: String,
+    pub announce_zones: bool,
+    pub zone_message: String,
+    // Shared between all announcements, so chat isn't flooded
+    pub announcement_cooldown_sec: u32,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FanBackend {
+    #[default]
+    HomeAssistant,
+    /// TP-Link Kasa plugs, over their local protocol
+    Kasa,
+}
+
+/// What sets how strong each beat is, for outputs that pulse with them
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BeatStrength {
+    #[default]
+    Bpm,
+    Zone,
+}
+
+/// Pulses toys connected to Intiface Central on each heartbeat, over the Buttplug protocol
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct HapticsSettings {
+    pub enabled: bool,
+    pub url: String,
+    pub intensity_from: BeatStrength,
+    // BPM range that's scaled onto min_intensity..max_intensity
+    pub min_bpm: u16,
+    pub max_bpm: u16,
+    pub min_intensity: f32,
+    pub max_intensity: f32,
+    // Zone 0 and up, the last is used for any zones past it
+    pub zone_intensities: Vec<f32>,
+    // How long each beat's pulse lasts
+    pub pulse_ms: u16,
+}
+
+/// Plays a heartbeat sound on each beat, louder with the BPM or zone
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct AudioSettings {
+    pub enabled: bool,
+    // WAV file played on each beat, empty for the built-in one
+    pub sound_path: String,
+    pub volume_from: BeatStrength,
+    // BPM range that's scaled onto min_volume..max_volume
+    pub min_bpm: u16,
+    pub max_bpm: u16,
+    pub min_volume: f32,
+    pub max_volume: f32,
+    // Zone 0 and up, the last is used for any zones past it
+    pub zone_volumes: Vec<f32>,
+}
+
+/// Advertises as a BLE heart rate monitor through BlueZ, for treadmills, bike computers, Zwift, etc
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct GattServerSettings {
+    pub enabled: bool,
+    // BlueZ adapter name, i.e. "hci0"
+    pub adapter: String,
+    // Shown to devices scanning for heart rate monitors
+    pub local_name: String,
+}
+
+/// Pulses a WLED strip on each heartbeat over its realtime UDP protocol, colored by HR zone
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct WledSettings {
+    pub enabled: bool,
+    // IP or host, port 21324 is assumed if not given
+    pub address: String,
+    pub led_count: u16,
+    // Hex colors for zone 0 and up, the last is used for any zones past it
+    pub palette: Vec<String>,
+    // Brightness between beats, 0.0 to 1.0
+    pub idle_brightness: f32,
+    pub attack_ms: u16,
+    pub decay_ms: u16,
+}
+
+/// Websocket that sends HR out in the same shape as HRtoVRChat_OSC, for overlays built around it
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct HrtoVrchatSettings {
+    pub enabled: bool,
+    pub port: u16,
+    // Range that HRPercent and FullHRPercent are scaled between
+    pub min_hr: u16,
+    pub max_hr: u16,
+}
+
+/// Updates a text source in OBS over obs-websocket, and shows/hides another with the HR connection
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct ObsSettings {
+    pub enabled: bool,
+    pub url: String,
+    // Only needed if authentication is on in OBS
+    pub password: String,
+    // Name of a Text (GDI+/FreeType 2) source, empty to leave text alone
+    pub text_source: String,
+    // {bpm}, {rr} and {battery} are filled in
+    pub template: String,
+    // Shown while there's a reading, hidden while not. Empty to leave alone
+    pub visibility_source: String,
+    // Scene the visibility source is in, empty for whichever's live
+    pub visibility_scene: String,
+}
+
+/// Runs a Streamer.bot action over its WebSocket server when the heart rate crosses a threshold
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct StreamerbotSettings {
+    pub enabled: bool,
+    pub url: String,
+    // Only needed if authentication is on in Streamer.bot
+    pub password: String,
+    // Name of the action to run
+    pub action: String,
+    // BPMs that run the action when crossed, either way
+    pub thresholds: Vec<u16>,
+    // Also on heart rate zone changes, needs [zones] enabled
+    pub on_zone: bool,
+    // Also every interval_sec while there's a reading, 0 turns it off
+    pub interval_sec: u32,
+}
+
+/// Websocket that pushes every HR update as JSON, for browser-source overlays
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct OverlaySettings {
+    pub enabled: bool,
+    pub port: u16,
+    // Listen on all interfaces instead of just localhost
+    pub allow_lan: bool,
+    // HTML file served at /overlay in place of the built-in page
+    pub custom_page: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct IpcSettings {
+    pub enabled: bool,
+    // Unix domain socket path, or named pipe name on Windows
+    pub path: String,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct PrometheusSettings {
     pub enabled: bool,
@@ -105,6 +857,31 @@ pub struct PrometheusSettings {
     // Unused for now, maybe someone'll ask for it.
     // pub batch_size: usize,
     pub metrics: PrometheusMetrics,
+    pub exporter: PrometheusExporterSettings,
+}
+
+/// Batched writes to InfluxDB v2, one point per update plus one per RR interval
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct InfluxSettings {
+    pub enabled: bool,
+    pub url: String,
+    pub org: String,
+    pub bucket: String,
+    // API token with write access to the bucket
+    pub token: String,
+    pub measurement: String,
+    // Added as a `host` tag if set, to tell machines apart in a shared bucket
+    pub host_tag: String,
+    pub flush_interval_sec: u16,
+}
+
+/// `/metrics` endpoint for Prometheus to scrape, separate from the push logging above
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct PrometheusExporterSettings {
+    pub enabled: bool,
+    pub port: u16,
+    // Listen on all interfaces instead of just localhost
+    pub allow_lan: bool,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
@@ -117,6 +894,99 @@ pub struct PrometheusMetrics {
     pub activity: String,
 }
 
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct AlertSettings {
+    pub enabled: bool,
+    pub xsoverlay_port: u16,
+    pub webhook_url: String,
+    pub routes: AlertRoutes,
+    pub rules: AlertRules,
+}
+
+/// Heart rate limits that raise alerts, 0 turns one off.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct AlertRules {
+    pub high_bpm: u16,
+    pub low_bpm: u16,
+    // How long the heart rate has to stay past high_bpm/low_bpm
+    pub sustain_sec: f32,
+}
+
+/// Which sinks each kind of alert gets sent to.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct AlertRoutes {
+    pub error: Vec<AlertSink>,
+    pub warning: Vec<AlertSink>,
+    pub notification: Vec<AlertSink>,
+    pub connected: Vec<AlertSink>,
+    pub disconnected: Vec<AlertSink>,
+    pub zone: Vec<AlertSink>,
+    pub high_heart_rate: Vec<AlertSink>,
+    pub low_heart_rate: Vec<AlertSink>,
+    pub low_battery: Vec<AlertSink>,
+}
+
+impl AlertRoutes {
+    pub fn sinks_for(&self, kind: AlertKind) -> &[AlertSink] {
+        match kind {
+            AlertKind::Error => &self.error,
+            AlertKind::Warning => &self.warning,
+            AlertKind::Notification => &self.notification,
+            AlertKind::Connected => &self.connected,
+            AlertKind::Disconnected => &self.disconnected,
+            AlertKind::Zone => &self.zone,
+            AlertKind::HighHeartRate => &self.high_heart_rate,
+            AlertKind::LowHeartRate => &self.low_heart_rate,
+            AlertKind::LowBattery => &self.low_battery,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertSink {
+    Popup,
+    Toast,
+    Xsoverlay,
+    Webhook,
+    Sound,
+}
+
+/// Sends readings as the same JSON the UDP source takes, for game mods listening for it
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct UdpOutputSettings {
+    pub enabled: bool,
+    // "host:port", can be a broadcast address
+    pub target: String,
+    // 0 sends each update as it comes in
+    pub interval_ms: u32,
+}
+
+/// POSTs a templated body on the picked events, for chat webhooks and home servers
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct WebhookSettings {
+    pub enabled: bool,
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    // For the "interval" event
+    pub interval_sec: u32,
+    pub body: String,
+    pub content_type: String,
+    // Optional "Key: Value", i.e. for an Authorization header
+    pub header: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    Connected,
+    Disconnected,
+    Interval,
+    Zone,
+    TwitchUp,
+    TwitchDown,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct AutoUpdateSettings {
     pub update_check_prompt: bool,
@@ -124,54 +994,143 @@ pub struct AutoUpdateSettings {
     pub version_skipped: String,
 }
 
-#[derive(Debug, Deserialize, Serialize, Default)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct FormatSettings {
+    // Only the first character is used
+    pub decimal_separator: String,
+    pub bpm_suffix: String,
+    pub rr_suffix: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
 pub struct Settings {
     pub osc: OscSettings,
     pub ble: BLESettings,
     pub websocket: WebSocketSettings,
+    pub udp: UdpSettings,
+    pub ant: AntSettings,
+    pub serial: SerialSettings,
+    pub mqtt: MqttSettings,
+    pub mqtt_output: MqttOutputSettings,
+    pub http_poll: HttpPollSettings,
+    pub pulsoid: PulsoidSettings,
+    pub hyperate: HypeRateSettings,
+    pub stromno: StromnoSettings,
+    pub fitbit: FitbitSettings,
+    pub failover: FailoverSettings,
     pub misc: MiscSettings,
     pub dummy: DummySettings,
     pub tui: TuiSettings,
     pub updates: AutoUpdateSettings,
     pub activities: ActivitiesSettings,
     pub prometheus: PrometheusSettings,
+    pub influxdb: InfluxSettings,
+    pub hrtovrchat: HrtoVrchatSettings,
+    pub obs: ObsSettings,
+    pub streamerbot: StreamerbotSettings,
+    pub overlay: OverlaySettings,
+    pub ipc: IpcSettings,
+    pub syslog: SyslogSettings,
+    pub alerts: AlertSettings,
+    pub webhook: WebhookSettings,
+    pub udp_output: UdpOutputSettings,
+    pub hrv: HrvSettings,
+    pub zones: ZoneSettings,
+    pub fan: FanSettings,
+    pub twitch: TwitchSettings,
+    pub haptics: HapticsSettings,
+    pub wled: WledSettings,
+    pub audio: AudioSettings,
+    pub gatt_server: GattServerSettings,
+    pub format: FormatSettings,
 }
 
 impl Settings {
+    /// The first source that's enabled, Bluetooth if none are.
+    pub fn hr_source(&self) -> HrSource {
+        let enabled = [
+            (self.dummy.enabled, HrSource::Dummy),
+            (self.websocket.enabled, HrSource::WebSocket),
+            (self.udp.enabled, HrSource::Udp),
+            (self.ant.enabled, HrSource::Ant),
+            (self.serial.enabled, HrSource::Serial),
+            (self.mqtt.enabled, HrSource::Mqtt),
+            (self.http_poll.enabled, HrSource::HttpPoll),
+            (self.pulsoid.enabled, HrSource::Pulsoid),
+            (self.hyperate.enabled, HrSource::HypeRate),
+            (self.stromno.enabled, HrSource::Stromno),
+            (self.fitbit.enabled, HrSource::Fitbit),
+        ];
+        enabled
+            .into_iter()
+            .find_map(|(enabled, source)| enabled.then_some(source))
+            .unwrap_or(HrSource::Ble)
+    }
     #[allow(clippy::needless_late_init)]
     pub fn load(config_path: PathBuf, required: bool) -> Result<Self, AppError> {
         let default_log_level;
         let default_session_log_path;
+        let default_session_db_path;
         let default_bpm_txt_path;
+        let default_ipc_path = if cfg!(windows) {
+            r"\\.\pipe\iron-heart"
+        } else {
+            "iron-heart.sock"
+        };
 
         if !cfg!(debug_assertions) {
             // Release build default params
             default_log_level = "debug";
             default_session_log_path = "session_logs";
+            default_session_db_path = "sessions.db";
             default_bpm_txt_path = "bpm.txt"
         } else {
             // Debug build default params
             default_log_level = "debug";
             // (assuming it's in target/debug/)
             default_session_log_path = "../../session_logs";
+            default_session_db_path = "../../sessions.db";
             default_bpm_txt_path = "../../bpm.txt"
         };
 
         // TODO: New way of doing defaults
         // Either use serde's defaults and skip the extra config crate entirely (doesn't look like it supports serde defaults?)
         // or switch to something more sane like figment or confique
-        let settings = Config::builder()
+        let mut settings: Settings = Config::builder()
             // Start off by merging in the "default" configuration file
             .add_source(ConfigFile::from(config_path).required(required))
             .set_default("osc.enabled", true)?
             .set_default("osc.host_ip", "0.0.0.0")?
+            .set_default("osc.host_port", 0)?
             .set_default("osc.target_ip", "127.0.0.1")?
+            .set_default("osc.target_resolve_interval_sec", 60)?
             .set_default("osc.port", 9000)?
+            .set_default("osc.beat_style", "pulse_and_toggle")?
             .set_default("osc.pulse_length_ms", 100)?
+            .set_default("osc.envelope_attack_ms", 30)?
+            .set_default("osc.envelope_decay_ms", 250)?
             .set_default("osc.only_positive_float_bpm", false)?
+            .set_default("osc.calibration_sweep_sec", 30)?
             .set_default("osc.hide_disconnections", false)?
             .set_default("osc.max_hide_disconnection_sec", 60)?
             .set_default("osc.twitch_rr_threshold_ms", 50)?
+            .set_default("osc.ecg_waveform_enabled", false)?
+            .set_default("osc.ecg_waveform_samples", 12)?
+            .set_default("osc.ecg_waveform_amplitude", 1.0)?
+            .set_default("osc.real_timetags", false)?
+            .set_default("osc.timetag_delay_ms", 0)?
+            .set_default("osc.rr_delivery", "latest")?
+            .set_default("osc.rr_mean_window_ms", 1000)?
+            .set_default("osc.send_stress_index", false)?
+            .set_default("osc.stress_index_max", 1000.0)?
+            .set_default("osc.send_instant_bpm", false)?
+            .set_default("osc.bools_as_ints", false)?
+            .set_default("osc.session_goal_min", 0)?
+            .set_default("osc.session_goal_min_bpm", 0)?
+            .set_default("osc.session_goal_max_bpm", 255)?
+            .set_default("osc.debug_tap", false)?
+            .set_default("osc.dry_run", false)?
+            .set_default("osc.oscquery", false)?
             .set_default("osc.addresses.prefix", "/avatar/parameters/")?
             .set_default("osc.addresses.hrm_connected", "isHRConnected")?
             .set_default("osc.addresses.hiding_disconnect", "isHRReconnecting")?
@@ -185,18 +1144,124 @@ impl Settings {
             .set_default("osc.addresses.rr_twitch_up", "HRTwitchUp")?
             .set_default("osc.addresses.rr_twitch_down", "HRTwitchDown")?
             .set_default("osc.addresses.activity", "HRActivity")?
+            .set_default("osc.addresses.ecg_waveform", "HRWaveform")?
+            .set_default("osc.addresses.stress_index", "HRStressIndex")?
+            .set_default("osc.addresses.beat_envelope", "HRBeatEnvelope")?
+            .set_default("osc.addresses.goal_progress", "HRGoalProgress")?
+            .set_default("osc.addresses.zone", "HRZone")?
+            .set_default("osc.addresses.spo2", "HRSpO2")?
+            .set_default("osc.addresses.cadence", "HRCadence")?
+            .set_default("osc.addresses.instant_bpm", "HRInstant")?
+            .set_default("osc.addresses.health", "HRBridgeHealth")?
+            .set_default("osc.custom_params", Map::<String, String>::new())?
+            .set_default("osc.presets", Map::<String, String>::new())?
+            .set_default("osc.extra_targets", Vec::<String>::new())?
             .set_default("ble.never_ask_to_save", false)?
-            .set_default("ble.saved_address", "")?
-            .set_default("ble.saved_name", "")?
+            .set_default("ble.saved_devices", Vec::<String>::new())?
+            .set_default("ble.quirks", Vec::<String>::new())?
+            .set_default("ble.saved_device_wait_sec", 5)?
+            .set_default("ble.connect_timeout_sec", 30)?
             .set_default("ble.rr_ignore_after_empty", 0)?
+            .set_default("ble.reconnect_initial_delay_sec", 2)?
+            .set_default("ble.reconnect_max_delay_sec", 60)?
+            .set_default("ble.reconnect_window_sec", 600)?
+            .set_default("ble.rssi_warning_threshold", 0)?
+            .set_default("ble.battery_poll_sec", 300)?
+            .set_default("ble.notification_watchdog_sec", 10)?
+            .set_default("ble.advertisement_only", false)?
+            .set_default("ble.scan_hr_service_only", true)?
+            .set_default("ble.scan_name_filter", "")?
+            .set_default("ble.secondary_device.name", "")?
+            .set_default("ble.secondary_device.address", "")?
+            .set_default("ble.hooks.on_connect", "")?
+            .set_default("ble.hooks.on_disconnect", "")?
+            .set_default("ble.hooks.on_low_battery", "")?
+            .set_default("ble.aggregate_policy", "prefer_primary")?
+            .set_default("ble.aggregate_gap_sec", 5)?
+            .set_default("ble.raw_dump_enabled", false)?
+            .set_default("ble.raw_dump_path", "hrm_dump.txt")?
+            .set_default("ble.polar_ecg_enabled", false)?
+            .set_default("ble.polar_ecg_log_path", "")?
+            .set_default("ble.cadence_enabled", false)?
             .set_default("websocket.enabled", false)?
             .set_default("websocket.port", 5566)?
+            .set_default("websocket.simple_ingest_enabled", false)?
+            .set_default("websocket.simple_ingest_token", "")?
+            .set_default("websocket.heart_rate_on_stream", false)?
+            .set_default("websocket.auth_token", "")?
+            .set_default("websocket.tls_cert_path", "")?
+            .set_default("websocket.tls_key_path", "")?
+            .set_default("websocket.advertise", false)?
+            .set_default("websocket.max_messages_per_sec", 20)?
+            .set_default("websocket.min_bpm", 20)?
+            .set_default("websocket.max_bpm", 250)?
+            .set_default("websocket.out_of_range", "drop")?
+            .set_default("websocket.json_mapping.bpm", "")?
+            .set_default("websocket.json_mapping.rr", "")?
+            .set_default("websocket.json_mapping.battery", "")?
+            .set_default("websocket.client_url", "")?
+            .set_default("udp.enabled", false)?
+            .set_default("udp.port", 5567)?
+            .set_default("ant.enabled", false)?
+            .set_default("ant.device_number", 0)?
+            .set_default("serial.enabled", false)?
+            .set_default("serial.port", "")?
+            .set_default("serial.baud", 115200)?
+            .set_default("mqtt.enabled", false)?
+            .set_default("mqtt.host", "localhost")?
+            .set_default("mqtt.port", 1883)?
+            .set_default("mqtt.topic", "")?
+            .set_default("mqtt.username", "")?
+            .set_default("mqtt.password", "")?
+            .set_default("mqtt.client_id", "iron-heart")?
+            .set_default("mqtt_output.enabled", false)?
+            .set_default("mqtt_output.host", "localhost")?
+            .set_default("mqtt_output.port", 1883)?
+            .set_default("mqtt_output.username", "")?
+            .set_default("mqtt_output.password", "")?
+            .set_default("mqtt_output.client_id", "iron-heart-output")?
+            .set_default("mqtt_output.base_topic", "iron_heart")?
+            .set_default("mqtt_output.discovery", true)?
+            .set_default("mqtt_output.discovery_prefix", "homeassistant")?
+            .set_default("mqtt_output.device_name", "Heart Rate Monitor")?
+            .set_default("http_poll.enabled", false)?
+            .set_default("http_poll.url", "")?
+            .set_default("http_poll.interval_ms", 1000)?
+            .set_default("http_poll.bpm_pointer", "/bpm")?
+            .set_default("http_poll.rr_pointer", "")?
+            .set_default("http_poll.battery_pointer", "")?
+            .set_default("pulsoid.enabled", false)?
+            .set_default("pulsoid.token", "")?
+            .set_default("hyperate.enabled", false)?
+            .set_default("hyperate.api_key", "")?
+            .set_default("hyperate.session_id", "")?
+            .set_default("stromno.enabled", false)?
+            .set_default("stromno.widget_id", "")?
+            .set_default("fitbit.enabled", false)?
+            .set_default("fitbit.client_id", "")?
+            .set_default("fitbit.poll_interval_secs", 60)?
+            .set_default("fitbit.redirect_port", 8189)?
+            .set_default("failover.enabled", false)?
+            .set_default("failover.fallbacks", Vec::<String>::new())?
+            .set_default("failover.stale_after_sec", 5)?
             .set_default("misc.log_level", default_log_level)?
             .set_default("misc.write_bpm_to_file", false)?
             .set_default("misc.write_rr_to_file", false)?
             .set_default("misc.bpm_file_path", default_bpm_txt_path)?
+            .set_default("misc.bpm_file_template", "{bpm}")?
+            .set_default("misc.bpm_file_interval_ms", 0)?
             .set_default("misc.log_sessions_to_csv", false)?
             .set_default("misc.log_sessions_csv_path", default_session_log_path)?
+            .set_default("misc.log_sessions_rotate_minutes", 0)?
+            .set_default("misc.log_sessions_keep", 0)?
+            .set_default("misc.log_sessions_to_sqlite", false)?
+            .set_default("misc.log_sessions_sqlite_path", default_session_db_path)?
+            .set_default("misc.raw_capture", false)?
+            .set_default("misc.journal_enabled", false)?
+            .set_default("misc.journal_path", "journal.jsonl")?
+            .set_default("misc.preflight_checks", true)?
+            .set_default("misc.inhibit_sleep", false)?
+            .set_default("misc.low_battery_percent", 15)?
             .set_default("misc.vrcx_shortcut_prompt", true)?
             .set_default("updates.update_check_prompt", true)?
             .set_default("updates.allow_checking_for_updates", false)?
@@ -208,11 +1273,23 @@ impl Settings {
             .set_default("tui.chart_rr_clamp_high", true)?
             .set_default("tui.chart_rr_clamp_low", false)?
             .set_default("tui.charts_combine", true)?
+            .set_default("tui.stream_safe", false)?
+            .set_default("tui.startup_banner", "")?
+            .set_default("tui.remember_layout", true)?
+            .set_default("tui.show_unnamed_devices", true)?
+            .set_default("tui.chart_throttle", "auto")?
+            .set_default("tui.chart_throttle_interval_ms", 1000)?
+            .set_default("tui.chart_throttle_max_points", 40)?
+            .set_default("tui.layout", "full")?
+            .set_default("tui.theme", "default")?
             .set_default("dummy.enabled", false)?
+            .set_default("dummy.mode", "triangle")?
             .set_default("dummy.low_bpm", 50)?
             .set_default("dummy.high_bpm", 120)?
             .set_default("dummy.bpm_speed", 1.5)?
             .set_default("dummy.loops_before_dc", 2)?
+            .set_default("dummy.rr_jitter_ms", 20)?
+            .set_default("dummy.artifact_chance", 0.01)?
             .set_default("activities.enabled", false)?
             .set_default("activities.remember_last", true)?
             .set_default("prometheus.enabled", false)?
@@ -224,10 +1301,147 @@ impl Settings {
             .set_default("prometheus.metrics.twitch_up", "heart_rate_twitch_up")?
             .set_default("prometheus.metrics.twitch_down", "heart_rate_twitch_down")?
             .set_default("prometheus.metrics.activity", "heart_rate_activity")?
+            .set_default("prometheus.exporter.enabled", false)?
+            .set_default("prometheus.exporter.port", 5569)?
+            .set_default("prometheus.exporter.allow_lan", false)?
+            .set_default("influxdb.enabled", false)?
+            .set_default("influxdb.url", "http://localhost:8086")?
+            .set_default("influxdb.org", "")?
+            .set_default("influxdb.bucket", "")?
+            .set_default("influxdb.token", "")?
+            .set_default("influxdb.measurement", "heart_rate")?
+            .set_default("influxdb.host_tag", "")?
+            .set_default("influxdb.flush_interval_sec", 5)?
             // .set_default("prometheus.batch_size", 30)?
+            .set_default("hrtovrchat.enabled", false)?
+            .set_default("hrtovrchat.port", 5567)?
+            .set_default("hrtovrchat.min_hr", 0)?
+            .set_default("hrtovrchat.max_hr", 255)?
+            .set_default("obs.enabled", false)?
+            .set_default("obs.url", "ws://127.0.0.1:4455")?
+            .set_default("obs.password", "")?
+            .set_default("obs.text_source", "")?
+            .set_default("obs.template", "{bpm} BPM")?
+            .set_default("obs.visibility_source", "")?
+            .set_default("obs.visibility_scene", "")?
+            .set_default("streamerbot.enabled", false)?
+            .set_default("streamerbot.url", "ws://127.0.0.1:8080/")?
+            .set_default("streamerbot.password", "")?
+            .set_default("streamerbot.action", "")?
+            .set_default("streamerbot.thresholds", Vec::<u16>::new())?
+            .set_default("streamerbot.on_zone", false)?
+            .set_default("streamerbot.interval_sec", 0)?
+            .set_default("overlay.enabled", false)?
+            .set_default("overlay.port", 5568)?
+            .set_default("overlay.allow_lan", false)?
+            .set_default("overlay.custom_page", "")?
+            .set_default("ipc.enabled", false)?
+            .set_default("ipc.path", default_ipc_path)?
+            .set_default("syslog.enabled", false)?
+            .set_default("syslog.address", "127.0.0.1:514")?
+            .set_default("syslog.protocol", "udp")?
+            .set_default("syslog.log_level", "info")?
+            .set_default("syslog.hostname", "")?
+            .set_default("hrv.stress_index_window", 120)?
+            .set_default("format.decimal_separator", ".")?
+            .set_default("format.bpm_suffix", "")?
+            .set_default("format.rr_suffix", "")?
+            .set_default("zones.enabled", false)?
+            .set_default("zones.boundaries", vec![100, 120, 140, 160, 180])?
+            .set_default("zones.hysteresis_bpm", 3)?
+            .set_default("zones.hysteresis_hold_sec", 5.0)?
+            .set_default("fan.enabled", false)?
+            .set_default("fan.backend", "home_assistant")?
+            .set_default("fan.on_zone", 3)?
+            .set_default("fan.off_zone", 2)?
+            .set_default("fan.min_switch_sec", 30)?
+            .set_default("fan.turn_off_on_exit", true)?
+            .set_default("fan.ha_url", "http://homeassistant.local:8123")?
+            .set_default("fan.ha_token", "")?
+            .set_default("fan.ha_entity_id", "")?
+            .set_default("fan.kasa_address", "")?
+            .set_default("twitch.enabled", false)?
+            .set_default("twitch.username", "")?
+            .set_default("twitch.oauth_token", "")?
+            .set_default("twitch.channel", "")?
+            .set_default("twitch.command", "!hr")?
+            .set_default("twitch.reply", "Current heart rate: {bpm} BPM")?
+            .set_default("twitch.command_cooldown_sec", 10)?
+            .set_default("twitch.announce_session_max", false)?
+            .set_default("twitch.session_max_above", 140)?
+            .set_default(
+                "twitch.session_max_message",
+                "New heart rate high for this stream: {max} BPM!",
+            )?
+            .set_default("twitch.announce_zones", false)?
+            .set_default("twitch.zone_message", "Heart rate zone {zone} ({bpm} BPM)")?
+            .set_default("twitch.announcement_cooldown_sec", 60)?
+            .set_default("haptics.enabled", false)?
+            .set_default("haptics.url", "ws://127.0.0.1:12345")?
+            .set_default("haptics.intensity_from", "bpm")?
+            .set_default("haptics.min_bpm", 60)?
+            .set_default("haptics.max_bpm", 180)?
+            .set_default("haptics.min_intensity", 0.2)?
+            .set_default("haptics.max_intensity", 1.0)?
+            .set_default(
+                "haptics.zone_intensities",
+                vec![0.2, 0.35, 0.5, 0.65, 0.8, 1.0],
+            )?
+            .set_default("haptics.pulse_ms", 100)?
+            .set_default("wled.enabled", false)?
+            .set_default("wled.address", "")?
+            .set_default("wled.led_count", 30)?
+            .set_default(
+                "wled.palette",
+                vec!["808080", "0000ff", "00ff00", "ffff00", "ff8000", "ff0000"],
+            )?
+            .set_default("wled.idle_brightness", 0.1)?
+            .set_default("wled.attack_ms", 30)?
+            .set_default("wled.decay_ms", 300)?
+            .set_default("audio.enabled", false)?
+            .set_default("audio.sound_path", "")?
+            .set_default("audio.volume_from", "bpm")?
+            .set_default("audio.min_bpm", 60)?
+            .set_default("audio.max_bpm", 180)?
+            .set_default("audio.min_volume", 0.3)?
+            .set_default("audio.max_volume", 1.0)?
+            .set_default("audio.zone_volumes", vec![0.3, 0.45, 0.6, 0.75, 0.9, 1.0])?
+            .set_default("gatt_server.enabled", false)?
+            .set_default("gatt_server.adapter", "hci0")?
+            .set_default("gatt_server.local_name", "Iron Heart")?
+            .set_default("alerts.enabled", false)?
+            .set_default("alerts.xsoverlay_port", 42069)?
+            .set_default("alerts.webhook_url", "")?
+            .set_default("alerts.routes.error", vec!["popup"])?
+            .set_default("alerts.routes.warning", vec!["popup"])?
+            .set_default("alerts.routes.notification", vec!["popup"])?
+            .set_default("alerts.routes.connected", Vec::<String>::new())?
+            .set_default("alerts.routes.disconnected", vec!["popup"])?
+            .set_default("alerts.routes.zone", Vec::<String>::new())?
+            .set_default("alerts.routes.high_heart_rate", vec!["toast"])?
+            .set_default("alerts.routes.low_heart_rate", vec!["toast"])?
+            .set_default("alerts.routes.low_battery", vec!["toast"])?
+            .set_default("alerts.rules.high_bpm", 0)?
+            .set_default("alerts.rules.low_bpm", 0)?
+            .set_default("alerts.rules.sustain_sec", 10.0)?
+            .set_default("webhook.enabled", false)?
+            .set_default("webhook.url", "")?
+            .set_default("webhook.events", vec!["connected", "disconnected"])?
+            .set_default("webhook.interval_sec", 60)?
+            .set_default(
+                "webhook.body",
+                r#"{"event":"{event}","bpm":{bpm},"zone":{zone},"timestamp":"{timestamp}"}"#,
+            )?
+            .set_default("webhook.content_type", "application/json")?
+            .set_default("webhook.header", "")?
+            .set_default("udp_output.enabled", false)?
+            .set_default("udp_output.target", "")?
+            .set_default("udp_output.interval_ms", 1000)?
             .build()?
             .try_deserialize()?;
 
+        settings.ble.migrate_legacy_saved_device();
+
         Ok(settings)
     }
 
@@ -266,3 +1480,77 @@ impl Settings {
         LevelFilter::from_str(&self.misc.log_level).unwrap_or(LevelFilter::INFO)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved(name: &str, address: &str) -> SavedDevice {
+        SavedDevice {
+            name: name.to_owned(),
+            address: address.to_owned(),
+        }
+    }
+
+    #[test]
+    fn preset_overrides() {
+        let base = OscAddrConf {
+            prefix: "/avatar/parameters".into(),
+            bpm_int: "HR".into(),
+            bpm_float: "floatHR".into(),
+            ..Default::default()
+        };
+        let overrides = BTreeMap::from([("bpm_float".to_owned(), "HRf".to_owned())]);
+        let conf = base.with_overrides(&overrides).unwrap();
+        assert_eq!(conf.bpm_float, "HRf");
+        assert_eq!(conf.bpm_int, "HR");
+        assert_eq!(conf.prefix, "/avatar/parameters");
+        let typo = BTreeMap::from([("bpm_flaot".to_owned(), "HRf".to_owned())]);
+        assert!(base.with_overrides(&typo).is_err());
+    }
+    #[test]
+    fn priority_by_name_or_address() {
+        let ble = BLESettings {
+            saved_devices: vec![saved("Polar H10", "AA"), saved("Armband", "BB")],
+            ..Default::default()
+        };
+        assert_eq!(ble.saved_priority("Polar H10", "CC"), Some(0));
+        assert_eq!(ble.saved_priority("Renamed", "BB"), Some(1));
+        assert_eq!(ble.saved_priority("Other", "CC"), None);
+        // Blank entries don't match everything
+        let blank = BLESettings {
+            saved_devices: vec![saved("", "AA")],
+            ..Default::default()
+        };
+        assert_eq!(blank.saved_priority("", "CC"), None);
+    }
+    #[test]
+    fn remember_updates_or_appends() {
+        let mut ble = BLESettings {
+            saved_devices: vec![saved("Polar H10", "AA")],
+            ..Default::default()
+        };
+        assert!(!ble.remember_device("Polar H10", "AA"));
+        assert!(ble.remember_device("Polar H10", "AB"));
+        assert!(ble.remember_device("Armband", "BB"));
+        assert_eq!(
+            ble.saved_devices,
+            vec![saved("Polar H10", "AB"), saved("Armband", "BB")]
+        );
+    }
+    #[test]
+    fn migrates_single_saved_device() {
+        let mut ble = BLESettings {
+            saved_name: "Polar H10".into(),
+            saved_address: "AA".into(),
+            saved_devices: vec![saved("Armband", "BB")],
+            ..Default::default()
+        };
+        ble.migrate_legacy_saved_device();
+        assert_eq!(
+            ble.saved_devices,
+            vec![saved("Polar H10", "AA"), saved("Armband", "BB")]
+        );
+        assert!(ble.saved_name.is_empty());
+    }
+}
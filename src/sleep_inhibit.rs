@@ -0,0 +1,214 @@
+use std::time::{Duration, Instant};
+
+use tracing::info;
+
+// HR can drop out for a bit (reconnecting, strap shifting), so don't let go right away
+const RELEASE_AFTER: Duration = Duration::from_secs(120);
+
+/// Keeps the system from sleeping while heart rate is coming in, if `misc.inhibit_sleep` is on.
+pub struct SleepInhibit {
+    enabled: bool,
+    lock: Option<platform::WakeLock>,
+    last_active: Option<Instant>,
+}
+
+impl SleepInhibit {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            lock: None,
+            last_active: None,
+        }
+    }
+    pub fn hr_update(&mut self, bpm: u16, now: Instant) {
+        if !self.enabled {
+            return;
+        }
+        if bpm > 0 {
+            self.last_active = Some(now);
+            if self.lock.is_none() {
+                self.lock = platform::WakeLock::acquire();
+                if self.lock.is_some() {
+                    info!("Inhibiting sleep while the session is active");
+                } else {
+                    // Already warned, no point trying on every update
+                    self.enabled = false;
+                }
+            }
+        } else {
+            self.tick(now);
+        }
+    }
+    /// Lets go once HR has been gone for a while.
+    pub fn tick(&mut self, now: Instant) {
+        if self.lock.is_some() && inactive(self.last_active, now) {
+            info!("No heart rate for a while, allowing sleep again");
+            self.lock = None;
+        }
+    }
+}
+
+fn inactive(last_active: Option<Instant>, now: Instant) -> bool {
+    last_active.is_none_or(|last| now.saturating_duration_since(last) >= RELEASE_AFTER)
+}
+
+// Released by dropping it
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::process::{Child, Command, Stdio};
+    use tracing::{debug, warn};
+
+    pub struct WakeLock(Child);
+
+    impl WakeLock {
+        /// Holds a logind inhibitor lock for as long as the child's alive.
+        ///
+        /// The command it runs exits along with us, like `caffeinate -w` on macOS,
+        /// so a crash (or anything else that skips `Drop`) can't leave sleep blocked.
+        pub fn acquire() -> Option<Self> {
+            let pid = std::process::id().to_string();
+            let child = Command::new("systemd-inhibit")
+                .args([
+                    "--what=idle:sleep",
+                    "--who=iron-heart",
+                    "--why=Heart rate session active",
+                    "--mode=block",
+                    "tail",
+                    "--pid",
+                    &pid,
+                    "-f",
+                    "/dev/null",
+                ])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn();
+            match child {
+                Ok(child) => Some(Self(child)),
+                Err(e) => {
+                    warn!("Couldn't inhibit sleep (is systemd-inhibit installed?): {e}");
+                    None
+                }
+            }
+        }
+    }
+
+    impl Drop for WakeLock {
+        fn drop(&mut self) {
+            if let Err(e) = self.0.kill().and_then(|_| self.0.wait()) {
+                debug!("Failed to stop systemd-inhibit: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::process::{Child, Command, Stdio};
+    use tracing::{debug, warn};
+
+    pub struct WakeLock(Child);
+
+    impl WakeLock {
+        /// `caffeinate` holds an idle sleep assertion until it's killed (or we exit).
+        pub fn acquire() -> Option<Self> {
+            let child = Command::new("caffeinate")
+                .args(["-i", "-w", &std::process::id().to_string()])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn();
+            match child {
+                Ok(child) => Some(Self(child)),
+                Err(e) => {
+                    warn!("Couldn't inhibit sleep: {e}");
+                    None
+                }
+            }
+        }
+    }
+
+    impl Drop for WakeLock {
+        fn drop(&mut self) {
+            if let Err(e) = self.0.kill().and_then(|_| self.0.wait()) {
+                debug!("Failed to stop caffeinate: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::sync::mpsc;
+    use tracing::warn;
+
+    const ES_CONTINUOUS: u32 = 0x8000_0000;
+    const ES_SYSTEM_REQUIRED: u32 = 0x0000_0001;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetThreadExecutionState(flags: u32) -> u32;
+    }
+
+    // Dropping the sender wakes the thread back up to clear it
+    pub struct WakeLock(#[allow(dead_code)] mpsc::Sender<()>);
+
+    impl WakeLock {
+        /// The execution state belongs to the thread that set it, and the
+        /// runtime moves tasks between threads, so it gets one of its own.
+        pub fn acquire() -> Option<Self> {
+            let (tx, rx) = mpsc::channel::<()>();
+            let (ready_tx, ready_rx) = mpsc::channel();
+            let spawned = std::thread::Builder::new()
+                .name("sleep-inhibit".into())
+                .spawn(move || {
+                    // SAFETY: Only takes flags, returns 0 on failure
+                    let previous =
+                        unsafe { SetThreadExecutionState(ES_CONTINUOUS | ES_SYSTEM_REQUIRED) };
+                    let _ = ready_tx.send(previous != 0);
+                    // Blocks until the lock is dropped
+                    let _ = rx.recv();
+                    // SAFETY: Same as above
+                    unsafe { SetThreadExecutionState(ES_CONTINUOUS) };
+                });
+            if let Err(e) = spawned {
+                warn!("Couldn't inhibit sleep: {e}");
+                return None;
+            }
+            match ready_rx.recv() {
+                Ok(true) => Some(Self(tx)),
+                _ => {
+                    warn!("Couldn't inhibit sleep: SetThreadExecutionState failed");
+                    None
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod platform {
+    use tracing::warn;
+
+    pub struct WakeLock;
+
+    impl WakeLock {
+        pub fn acquire() -> Option<Self> {
+            warn!("Sleep inhibition isn't supported on this platform");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn releases_after_grace_period() {
+        let start = Instant::now();
+        assert!(inactive(None, start));
+        assert!(!inactive(Some(start), start + Duration::from_secs(60)));
+        assert!(inactive(Some(start), start + RELEASE_AFTER));
+    }
+}
@@ -0,0 +1,233 @@
+use ratatui::widgets::TableState;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::Sender as BSender;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::app::{AppUpdate, AppView};
+use crate::heart_rate::twitcher::TwitchThreshold;
+use crate::heart_rate::{
+    ant::AntSource, dummy::DummySource, fitbit::FitbitSource, http_poll::HttpPollSource,
+    hyperate::HypeRateSource, mqtt::MqttSource, pulsoid::PulsoidSource, serial::SerialSource,
+    stdin::StdinSource, stromno::StromnoSource, udp::UdpSource, websocket::WebSocketSource,
+};
+use crate::settings::Settings;
+
+/// What a source can report besides BPM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    pub rr_intervals: bool,
+    pub battery: bool,
+}
+
+impl Capabilities {
+    pub const BPM_ONLY: Capabilities = Capabilities {
+        rr_intervals: false,
+        battery: false,
+    };
+    // Only if the sender/device actually sends them, but they're passed along
+    pub const FULL: Capabilities = Capabilities {
+        rr_intervals: true,
+        battery: true,
+    };
+
+    /// Short summary for the source picker.
+    pub fn describe(&self) -> &'static str {
+        match (self.rr_intervals, self.battery) {
+            (true, true) => "BPM, RR, battery",
+            (true, false) => "BPM, RR",
+            (false, true) => "BPM, battery",
+            (false, false) => "BPM only",
+        }
+    }
+}
+
+/// Everything a source thread gets handed when it's started.
+pub struct SourceContext {
+    pub settings: Settings,
+    // Might lead to the failover manager rather than the app, sources can't tell the difference
+    pub broadcast_tx: BSender<AppUpdate>,
+    // Not left as Duration as it's used to check an abs difference
+    pub rr_twitch_threshold: TwitchThreshold,
+    pub stress_index_window: usize,
+    pub cancel_token: CancellationToken,
+}
+
+/// A heart rate source that runs on its own once spawned, sending `HeartRateStatus`
+/// (and any errors) over the broadcast channel until it's cancelled.
+///
+/// Bluetooth is the exception, it needs the app for picking and saving devices.
+/// New sources implement this and get a variant + `HrSource::driver` arm, `app` doesn't need touching.
+pub trait HeartRateSource: Send {
+    fn source(&self) -> HrSource;
+    fn capabilities(&self) -> Capabilities;
+    /// Shown until the first reading comes in.
+    fn waiting_view(&self) -> AppView {
+        AppView::WaitingForRemote
+    }
+    fn spawn(self: Box<Self>, context: SourceContext) -> JoinHandle<()>;
+}
+
+/// Where heart rate data is coming from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HrSource {
+    Ble,
+    #[serde(rename = "websocket")]
+    WebSocket,
+    Udp,
+    Dummy,
+    // Only works in builds with the `ant` feature, explains itself otherwise
+    Ant,
+    // Same as ANT+, needs the `serial` feature
+    Serial,
+    Mqtt,
+    HttpPoll,
+    Pulsoid,
+    #[serde(rename = "hyperate")]
+    HypeRate,
+    Stromno,
+    Fitbit,
+    // Only from `--stdin`, since there's nothing to read if it wasn't piped in at launch
+    Stdin,
+}
+
+impl HrSource {
+    pub const ALL: [HrSource; 12] = [
+        HrSource::Ble,
+        HrSource::WebSocket,
+        HrSource::Udp,
+        HrSource::Dummy,
+        HrSource::Ant,
+        HrSource::Serial,
+        HrSource::Mqtt,
+        HrSource::HttpPoll,
+        HrSource::Pulsoid,
+        HrSource::HypeRate,
+        HrSource::Stromno,
+        HrSource::Fitbit,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HrSource::Ble => "Bluetooth",
+            HrSource::WebSocket => "WebSocket",
+            HrSource::Udp => "UDP",
+            HrSource::Dummy => "Dummy",
+            HrSource::Ant => "ANT+",
+            HrSource::Serial => "Serial",
+            HrSource::Mqtt => "MQTT",
+            HrSource::HttpPoll => "HTTP Polling",
+            HrSource::Pulsoid => "Pulsoid",
+            HrSource::HypeRate => "HypeRate",
+            HrSource::Stromno => "Stromno",
+            HrSource::Fitbit => "Fitbit",
+            HrSource::Stdin => "stdin",
+        }
+    }
+
+    /// The source's default setup, None for Bluetooth which the app runs itself.
+    pub fn driver(&self) -> Option<Box<dyn HeartRateSource>> {
+        let driver: Box<dyn HeartRateSource> = match self {
+            HrSource::Ble => return None,
+            HrSource::WebSocket => Box::new(WebSocketSource::default()),
+            HrSource::Udp => Box::new(UdpSource),
+            HrSource::Dummy => Box::new(DummySource::default()),
+            HrSource::Ant => Box::new(AntSource),
+            HrSource::Serial => Box::new(SerialSource),
+            HrSource::Mqtt => Box::new(MqttSource),
+            HrSource::HttpPoll => Box::new(HttpPollSource),
+            HrSource::Pulsoid => Box::new(PulsoidSource),
+            HrSource::HypeRate => Box::new(HypeRateSource),
+            HrSource::Stromno => Box::new(StromnoSource),
+            HrSource::Fitbit => Box::new(FitbitSource),
+            HrSource::Stdin => Box::new(StdinSource),
+        };
+        Some(driver)
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        match self.driver() {
+            Some(driver) => driver.capabilities(),
+            None => Capabilities::FULL,
+        }
+    }
+}
+
+/// Keeps track of the running HR source, giving it a cancel token of its own
+/// so it can be swapped for another without stopping OSC, logging, etc.
+pub struct SourceManager {
+    current: Option<HrSource>,
+    parent: CancellationToken,
+    cancel: CancellationToken,
+    // For the source picker
+    pub table_state: TableState,
+}
+
+impl SourceManager {
+    pub fn new(parent: CancellationToken) -> Self {
+        Self {
+            current: None,
+            cancel: parent.child_token(),
+            parent,
+            table_state: TableState::default(),
+        }
+    }
+    pub fn current(&self) -> Option<HrSource> {
+        self.current
+    }
+    /// Stops whatever source was running, returning the token for `source` to run under.
+    pub fn start(&mut self, source: HrSource) -> CancellationToken {
+        self.cancel.cancel();
+        self.cancel = self.parent.child_token();
+        self.current = Some(source);
+        self.cancel.clone()
+    }
+    /// Token of the running source, for anything it spawns later (i.e. BLE's notification threads).
+    pub fn token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+    /// Points the picker at the running source.
+    pub fn select_current(&mut self) {
+        let index = self
+            .current
+            .and_then(|current| HrSource::ALL.iter().position(|&s| s == current));
+        self.table_state.select(Some(index.unwrap_or(0)));
+    }
+    pub fn selected(&self) -> Option<HrSource> {
+        self.table_state
+            .selected()
+            .and_then(|index| HrSource::ALL.get(index).copied())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swapping_cancels_only_the_old_source() {
+        let parent = CancellationToken::new();
+        let mut sources = SourceManager::new(parent.clone());
+        let ble = sources.start(HrSource::Ble);
+        let ble_child = sources.token().child_token();
+        let dummy = sources.start(HrSource::Dummy);
+        assert!(ble.is_cancelled());
+        assert!(ble_child.is_cancelled());
+        assert!(!dummy.is_cancelled());
+        assert!(!parent.is_cancelled());
+        assert_eq!(sources.current(), Some(HrSource::Dummy));
+
+        parent.cancel();
+        assert!(dummy.is_cancelled());
+    }
+    #[test]
+    fn drivers_match_their_source() {
+        for source in HrSource::ALL.into_iter().chain([HrSource::Stdin]) {
+            match source.driver() {
+                Some(driver) => assert_eq!(driver.source(), source),
+                None => assert_eq!(source, HrSource::Ble),
+            }
+        }
+    }
+}
@@ -3,6 +3,9 @@ use std::collections::HashMap;
 use btleplug::api::CharPropFlags;
 use uuid::Uuid;
 
+// Shown for devices that don't advertise a name
+pub const UNKNOWN_NAME: &str = "Unknown";
+
 /// A struct to hold the information of a Bluetooth device.
 #[derive(Debug, Clone, Default)]
 #[allow(dead_code)]
@@ -36,7 +39,7 @@ impl DeviceInfo {
     ) -> Self {
         Self {
             id,
-            name: name.unwrap_or_else(|| "Unknown".to_string()),
+            name: name.unwrap_or_else(|| UNKNOWN_NAME.to_string()),
             tx_power: tx_power.map_or_else(|| "n/a".to_string(), |tx| tx.to_string()),
             address,
             rssi: rssi.map_or_else(|| "n/a".to_string(), |rssi| rssi.to_string()),
@@ -48,6 +51,10 @@ impl DeviceInfo {
         }
     }
 
+    pub fn is_unnamed(&self) -> bool {
+        self.name == UNKNOWN_NAME
+    }
+
     pub fn get_id(&self) -> String {
         // Returns the `uuid` or `address` of the device if MacOS or Linux.
         if cfg!(target_os = "macos") {
@@ -1,4 +1,5 @@
 use crate::app::App;
+use crate::errors::AppError;
 use crate::event::EventHandler;
 use crate::panic_handler::initialize_panic_handler;
 use crate::ui;
@@ -7,7 +8,9 @@ use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
 use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
 use ratatui::backend::Backend;
 use ratatui::Terminal;
-use std::io;
+use std::io::{self, IsTerminal, Write};
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
 
 /// Representation of a terminal user interface.
 ///
@@ -31,6 +34,19 @@ impl<B: Backend> Tui<B> {
     ///
     /// It enables the raw mode and sets terminal properties.
     pub fn init(&mut self) -> AppResult<()> {
+        // Launched from a desktop shortcut/service, or output is being piped somewhere
+        if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+            return Err(AppError::NoTerminal.into());
+        }
+        // Don't leave the terminal in raw mode if we're about to fall back to headless
+        if let Err(e) = self.setup() {
+            let _ = Self::reset();
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn setup(&mut self) -> AppResult<()> {
         terminal::enable_raw_mode()?;
         crossterm::execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture)?;
 
@@ -78,3 +94,39 @@ impl<B: Backend> Tui<B> {
         Ok(())
     }
 }
+
+impl<B: Backend + Write> Tui<B> {
+    /// Rings the terminal bell for the "sound" alert sink, most terminals play their own alert sound.
+    ///
+    /// Goes through the backend after a draw, rather than straight to stdout from elsewhere,
+    /// so it can't end up in the middle of one.
+    pub fn ring_bell(&mut self) -> AppResult<()> {
+        let backend = self.terminal.backend_mut();
+        backend.write_all(b"\x07")?;
+        Write::flush(backend)?;
+        Ok(())
+    }
+}
+
+/// Cancelled if the controlling terminal goes away, i.e. the terminal window gets closed.
+///
+/// Only detectable on Unix (via SIGHUP), elsewhere this never gets cancelled.
+pub fn terminal_hangup() -> CancellationToken {
+    let hangup = CancellationToken::new();
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::hangup()) {
+            Ok(mut stream) => {
+                let hangup = hangup.clone();
+                tokio::spawn(async move {
+                    if stream.recv().await.is_some() {
+                        hangup.cancel();
+                    }
+                });
+            }
+            Err(e) => warn!("Couldn't listen for terminal hangups: {e}"),
+        }
+    }
+    hangup
+}
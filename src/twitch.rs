@@ -0,0 +1,347 @@
+// Twitch chat bot, over Twitch's IRC (through its websocket, so it's TLS without another dependency)
+// https://dev.twitch.tv/docs/chat/irc/
+
+use futures_util::{SinkExt, StreamExt};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::{Receiver as BReceiver, Sender as BSender};
+use tokio_util::sync::CancellationToken;
+use tokio_websockets::Message;
+use tracing::{debug, error, info, warn};
+
+use crate::app::{AppUpdate, ErrorPopup};
+use crate::broadcast;
+use crate::errors::AppError;
+use crate::journal::{self, Severity, Subsystem};
+use crate::reconnect::{self, ConnectError, ReconnectingClient, WsClient};
+use crate::settings::TwitchSettings;
+
+const TWITCH_IRC_URL: &str = "wss://irc-ws.chat.twitch.tv:443";
+const NO_READING_REPLY: &str = "No heart rate right now";
+
+/// Fills in `{bpm}`, `{max}` (this session's highest) and `{zone}`.
+fn fill(template: &str, bpm: u16, max: u16, zone: Option<u8>) -> String {
+    let zone = zone.map(|zone| zone.to_string()).unwrap_or_default();
+    template
+        .replace("{bpm}", &bpm.to_string())
+        .replace("{max}", &max.to_string())
+        .replace("{zone}", &zone)
+}
+
+/// The command and its trailing parameter (i.e. the chat message) of an IRC line, tags and prefix skipped.
+fn parse_line(line: &str) -> Option<(&str, &str)> {
+    let mut rest = line.trim_end();
+    if rest.starts_with('@') {
+        rest = rest.split_once(' ')?.1;
+    }
+    if rest.starts_with(':') {
+        rest = rest.split_once(' ')?.1;
+    }
+    let (command, params) = rest.split_once(' ').unwrap_or((rest, ""));
+    let trailing = params
+        .split_once(':')
+        .map(|(_, trailing)| trailing)
+        .unwrap_or(params);
+    Some((command, trailing))
+}
+
+struct TwitchBot {
+    settings: TwitchSettings,
+    // Lowercase with the #, as IRC wants it
+    channel: String,
+    bpm: u16,
+    session_max: u16,
+    zone: Option<u8>,
+    last_reply: Option<Instant>,
+    last_announcement: Option<Instant>,
+}
+
+impl TwitchBot {
+    fn new(settings: TwitchSettings) -> Self {
+        let channel = format!(
+            "#{}",
+            settings
+                .channel
+                .trim()
+                .trim_start_matches('#')
+                .to_lowercase()
+        );
+        Self {
+            settings,
+            channel,
+            bpm: 0,
+            session_max: 0,
+            zone: None,
+            last_reply: None,
+            last_announcement: None,
+        }
+    }
+    fn login_lines(&self) -> Vec<String> {
+        let token = self.settings.oauth_token.trim();
+        let token = token.strip_prefix("oauth:").unwrap_or(token);
+        vec![
+            format!("PASS oauth:{token}"),
+            format!("NICK {}", self.settings.username.trim().to_lowercase()),
+            format!("JOIN {}", self.channel),
+        ]
+    }
+    fn privmsg(&self, text: &str) -> String {
+        format!("PRIVMSG {} :{text}", self.channel)
+    }
+    fn cooled_down(last: Option<Instant>, cooldown_sec: u32, now: Instant) -> bool {
+        last.is_none_or(|at| {
+            now.saturating_duration_since(at) >= Duration::from_secs(cooldown_sec as u64)
+        })
+    }
+    /// Lines to send back for a line from Twitch.
+    fn handle_line(&mut self, line: &str, now: Instant) -> Result<Vec<String>, AppError> {
+        let Some((command, trailing)) = parse_line(line) else {
+            return Ok(Vec::new());
+        };
+        match command {
+            "PING" => Ok(vec![format!("PONG :{trailing}")]),
+            "NOTICE" if trailing.contains("authentication failed") => {
+                Err(AppError::TwitchUnauthorized)
+            }
+            "NOTICE" if trailing.contains("Improperly formatted auth") => {
+                Err(AppError::TwitchUnauthorized)
+            }
+            "PRIVMSG" => {
+                let asked = trailing
+                    .split_whitespace()
+                    .next()
+                    .is_some_and(|word| word.eq_ignore_ascii_case(&self.settings.command));
+                if !asked
+                    || !Self::cooled_down(self.last_reply, self.settings.command_cooldown_sec, now)
+                {
+                    return Ok(Vec::new());
+                }
+                self.last_reply = Some(now);
+                let reply = if self.bpm == 0 {
+                    NO_READING_REPLY.to_owned()
+                } else {
+                    fill(&self.settings.reply, self.bpm, self.session_max, self.zone)
+                };
+                Ok(vec![self.privmsg(&reply)])
+            }
+            "001" => {
+                info!("Twitch: Logged in, joining {}", self.channel);
+                Ok(Vec::new())
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+    fn announce(&mut self, template: &str, now: Instant) -> Option<String> {
+        if !Self::cooled_down(
+            self.last_announcement,
+            self.settings.announcement_cooldown_sec,
+            now,
+        ) {
+            return None;
+        }
+        self.last_announcement = Some(now);
+        Some(self.privmsg(&fill(template, self.bpm, self.session_max, self.zone)))
+    }
+    /// A new session max to announce, if it is one.
+    fn handle_bpm(&mut self, bpm: u16, now: Instant) -> Option<String> {
+        self.bpm = bpm;
+        if bpm <= self.session_max {
+            return None;
+        }
+        self.session_max = bpm;
+        if !self.settings.announce_session_max || bpm < self.settings.session_max_above {
+            return None;
+        }
+        let template = self.settings.session_max_message.clone();
+        self.announce(&template, now)
+    }
+    fn handle_zone(&mut self, zone: u8, now: Instant) -> Option<String> {
+        self.zone = Some(zone);
+        if !self.settings.announce_zones {
+            return None;
+        }
+        let template = self.settings.zone_message.clone();
+        self.announce(&template, now)
+    }
+    fn validate(&self) -> Result<(), AppError> {
+        if self.settings.username.is_empty()
+            || self.settings.oauth_token.is_empty()
+            || self.channel == "#"
+        {
+            return Err(AppError::TwitchSettingsMissing);
+        }
+        Ok(())
+    }
+}
+
+impl ReconnectingClient for TwitchBot {
+    type Connection = WsClient;
+    type Updates = BReceiver<AppUpdate>;
+    fn name(&self) -> &'static str {
+        "Twitch chat"
+    }
+    fn subsystem(&self) -> Subsystem {
+        Subsystem::Twitch
+    }
+    fn target(&self) -> String {
+        self.channel.clone()
+    }
+    async fn connect(&mut self) -> Result<WsClient, ConnectError> {
+        reconnect::connect_websocket(TWITCH_IRC_URL).await
+    }
+    async fn session(
+        &mut self,
+        mut client: WsClient,
+        broadcast_rx: &mut BReceiver<AppUpdate>,
+        _broadcast_tx: &BSender<AppUpdate>,
+        cancel_token: &CancellationToken,
+    ) -> Result<Option<String>, AppError> {
+        for line in self.login_lines() {
+            client.send(Message::text(line)).await?;
+        }
+        loop {
+            let outgoing = tokio::select! {
+                item = client.next() => match item {
+                    Some(Ok(msg)) if msg.is_close() => {
+                        return Ok(Some("Twitch closed the connection".into()));
+                    }
+                    Some(Ok(msg)) => {
+                        let mut outgoing = Vec::new();
+                        // One message can hold several lines
+                        for line in msg.as_text().unwrap_or_default().lines() {
+                            debug!("Twitch: < {line}");
+                            outgoing.extend(self.handle_line(line, Instant::now())?);
+                        }
+                        outgoing
+                    }
+                    Some(Err(e)) => return Ok(Some(format!("Error receiving from Twitch: {e}"))),
+                    None => return Ok(Some("Twitch closed the connection".into())),
+                },
+                update = broadcast_rx.recv() => match update {
+                    Ok(AppUpdate::HeartRateStatus(hr_status)) => {
+                        self.handle_bpm(hr_status.heart_rate_bpm, Instant::now()).into_iter().collect()
+                    }
+                    Ok(AppUpdate::ZoneChanged(zone)) => {
+                        self.handle_zone(zone, Instant::now()).into_iter().collect()
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(count)) => {
+                        warn!("Twitch: Lagged! Missed {count} messages");
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return Ok(None),
+                },
+                _ = cancel_token.cancelled() => {
+                    let _ = client.close().await;
+                    return Ok(None);
+                }
+            };
+            for line in outgoing {
+                client.send(Message::text(line)).await?;
+            }
+        }
+    }
+}
+
+/// Answers a chat command with the current BPM, and announces new session maxes and zone changes.
+pub async fn twitch_thread(
+    mut broadcast_rx: BReceiver<AppUpdate>,
+    broadcast_tx: BSender<AppUpdate>,
+    twitch_settings: TwitchSettings,
+    cancel_token: CancellationToken,
+) {
+    let mut bot = TwitchBot::new(twitch_settings);
+    let result = async {
+        bot.validate()?;
+        reconnect::run(&mut bot, &mut broadcast_rx, &broadcast_tx, &cancel_token).await
+    };
+    if let Err(e) = result.await {
+        error!("Twitch error: {e}");
+        journal::record(Severity::Error, Subsystem::Twitch, &e.to_string());
+        broadcast!(broadcast_tx, ErrorPopup::detailed("Twitch error", e));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bot() -> TwitchBot {
+        TwitchBot::new(TwitchSettings {
+            username: "HeartBot".into(),
+            oauth_token: "oauth:abc".into(),
+            channel: "#Streamer".into(),
+            command: "!hr".into(),
+            reply: "{bpm} BPM, max {max}".into(),
+            command_cooldown_sec: 10,
+            announce_session_max: true,
+            session_max_above: 100,
+            session_max_message: "New max: {max}".into(),
+            announce_zones: true,
+            zone_message: "Zone {zone}".into(),
+            announcement_cooldown_sec: 60,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn login_and_ping() {
+        let mut bot = bot();
+        assert_eq!(
+            bot.login_lines(),
+            vec!["PASS oauth:abc", "NICK heartbot", "JOIN #streamer"]
+        );
+        let now = Instant::now();
+        assert_eq!(
+            bot.handle_line("PING :tmi.twitch.tv", now).unwrap(),
+            vec!["PONG :tmi.twitch.tv"]
+        );
+        assert!(matches!(
+            bot.handle_line(":tmi.twitch.tv NOTICE * :Login authentication failed", now),
+            Err(AppError::TwitchUnauthorized)
+        ));
+    }
+    #[test]
+    fn command_with_cooldown() {
+        let mut bot = bot();
+        let now = Instant::now();
+        let line =
+            "@badges=;color= :viewer!viewer@viewer.tmi.twitch.tv PRIVMSG #streamer :!HR please";
+        assert_eq!(
+            bot.handle_line(line, now).unwrap(),
+            vec![format!("PRIVMSG #streamer :{NO_READING_REPLY}")]
+        );
+        bot.handle_bpm(90, now);
+        assert!(bot.handle_line(line, now).unwrap().is_empty());
+        let later = now + Duration::from_secs(10);
+        assert_eq!(
+            bot.handle_line(line, later).unwrap(),
+            vec!["PRIVMSG #streamer :90 BPM, max 90"]
+        );
+        let chatter = ":viewer!viewer@viewer.tmi.twitch.tv PRIVMSG #streamer :what's !hr";
+        assert!(bot
+            .handle_line(chatter, later + Duration::from_secs(10))
+            .unwrap()
+            .is_empty());
+    }
+    #[test]
+    fn announcements() {
+        let mut bot = bot();
+        let now = Instant::now();
+        // Below session_max_above
+        assert_eq!(bot.handle_bpm(95, now), None);
+        assert_eq!(
+            bot.handle_bpm(120, now).as_deref(),
+            Some("PRIVMSG #streamer :New max: 120")
+        );
+        // Cooling down, but still tracked
+        assert_eq!(bot.handle_bpm(130, now), None);
+        assert_eq!(bot.handle_zone(3, now), None);
+        let later = now + Duration::from_secs(60);
+        assert_eq!(
+            bot.handle_zone(4, later).as_deref(),
+            Some("PRIVMSG #streamer :Zone 4")
+        );
+        assert_eq!(bot.session_max, 130);
+    }
+}
@@ -1,5 +1,6 @@
 use ratatui::{
-    layout::Alignment,
+    layout::{Alignment, Rect},
+    style::{Modifier, Style},
     widgets::{Block, Borders, Clear, Paragraph, TableState},
     Frame,
 };
@@ -7,6 +8,7 @@ use ratatui::{
 use crate::{
     activities::tui::{render_activity_name_entry, render_activity_selection},
     app::{App, AppView, SubState},
+    pairing::tui::render_pairing_prompt,
     updates::tui::{update_allow_check_prompt, update_downloading_ui, update_found_prompt},
     widgets::prompts::{connecting_popup, render_error_popup},
 };
@@ -18,13 +20,18 @@ use crate::updates::tui::restart_app_prompt;
 use crate::vrcx::tui::vrcx_prompt;
 
 use crate::structs::DeviceInfo;
-use crate::utils::centered_rect;
+use crate::utils::{centered_rect, redact};
 use crate::widgets::action_bar::action_bar;
 use crate::widgets::detail_table::detail_table;
+use crate::widgets::device_information::render_device_information;
 use crate::widgets::device_table::device_table;
 use crate::widgets::heart_rate_display::heart_rate_display;
 use crate::widgets::inspect_overlay::inspect_overlay;
-use crate::widgets::prompts::save_prompt;
+use crate::widgets::osc_tap::osc_tap_overlay;
+use crate::widgets::prompts::{preflight_prompt, save_prompt};
+use crate::widgets::session_compare::render_session_compare;
+use crate::widgets::source_selection::render_source_selection;
+use crate::widgets::twitch_tuning::render_twitch_tuning;
 
 use ratatui::layout::{Constraint, Direction, Layout};
 
@@ -58,7 +65,11 @@ pub fn render(app: &mut App, f: &mut Frame) {
     match app.view {
         AppView::BleDeviceSelection => {
             // Draw the device table
-            let device_table = device_table(app.table_state.selected(), &app.discovered_devices);
+            let device_table = device_table(
+                app.table_state.selected(),
+                &app.discovered_devices,
+                app.hide_sensitive(),
+            );
             f.render_stateful_widget(device_table, chunks[0], &mut app.table_state);
 
             // Draw the detail table
@@ -69,6 +80,7 @@ pub fn render(app: &mut App, f: &mut Frame) {
             let info_table: ratatui::widgets::Table<'_> = action_bar(
                 app.ble_scan_paused.load(Ordering::SeqCst),
                 app.sub_state == SubState::ConnectingForCharacteristics,
+                app.ui_state.show_unnamed_devices,
                 &app.frame_count,
             );
             f.render_widget(info_table, chunks[2]);
@@ -80,7 +92,11 @@ pub fn render(app: &mut App, f: &mut Frame) {
             // TODO Move out to a function
             let area = centered_rect(60, 60, f.area());
             let mut text = "Waiting for websocket connection...".to_string();
-            if let Some(ref url) = app.websocket_url {
+            let client_url = &app.settings.websocket.client_url;
+            if !client_url.is_empty() {
+                let client_url = redact(app.hide_sensitive(), client_url.clone());
+                text.push_str(&format!("\nConnecting to: {}", client_url));
+            } else if let Some(ref url) = app.websocket_url {
                 let connection_info = if url.starts_with("0.0.0.0") {
                     local_ip_address::local_ip()
                         .map(|local_ip| format!("{}{}", local_ip, &url[7..]))
@@ -88,7 +104,20 @@ pub fn render(app: &mut App, f: &mut Frame) {
                 } else {
                     url.clone()
                 };
+                let connection_info = redact(app.hide_sensitive(), connection_info);
+                let scheme = if app.settings.websocket.tls_cert_path.is_empty() {
+                    "ws"
+                } else {
+                    "wss"
+                };
+                let connection_info = format!("{scheme}://{connection_info}");
                 text.push_str(&format!("\nConnect to: {}", connection_info));
+                if let Some(ref name) = app.websocket_mdns_name {
+                    text.push_str(&format!("\nAdvertised as: {name}"));
+                }
+            }
+            if let Some(clients) = app.websocket_clients.filter(|clients| *clients > 0) {
+                text.push_str(&format!("\nClients connected: {clients}"));
             }
             let connecting_block = Paragraph::new(text)
                 .alignment(Alignment::Center)
@@ -96,6 +125,60 @@ pub fn render(app: &mut App, f: &mut Frame) {
             f.render_widget(Clear, area);
             f.render_widget(connecting_block, area);
         }
+        AppView::WaitingForUdp => {
+            let area = centered_rect(60, 60, f.area());
+            let mut text = "Waiting for UDP heart rate...".to_string();
+            if let Ok(local_ip) = local_ip_address::local_ip() {
+                let address = format!("{local_ip}:{}", app.settings.udp.port);
+                text.push_str(&format!(
+                    "\nSend to: {}",
+                    redact(app.hide_sensitive(), address)
+                ));
+            }
+            let waiting_block = Paragraph::new(text)
+                .alignment(Alignment::Center)
+                .block(Block::default().borders(Borders::ALL));
+            f.render_widget(Clear, area);
+            f.render_widget(waiting_block, area);
+        }
+        AppView::WaitingForAnt => {
+            let area = centered_rect(60, 60, f.area());
+            let searching_block = Paragraph::new("Searching for ANT+ heart rate strap...")
+                .alignment(Alignment::Center)
+                .block(Block::default().borders(Borders::ALL));
+            f.render_widget(Clear, area);
+            f.render_widget(searching_block, area);
+        }
+        AppView::WaitingForSerial => {
+            let area = centered_rect(60, 60, f.area());
+            let text = format!("Waiting for heart rate on {}...", app.settings.serial.port);
+            let waiting_block = Paragraph::new(text)
+                .alignment(Alignment::Center)
+                .block(Block::default().borders(Borders::ALL));
+            f.render_widget(Clear, area);
+            f.render_widget(waiting_block, area);
+        }
+        AppView::WaitingForRemote => {
+            let area = centered_rect(60, 60, f.area());
+            let source = app.sources.current().map_or("remote source", |s| s.name());
+            let waiting_block = Paragraph::new(format!("Waiting for heart rate from {source}..."))
+                .alignment(Alignment::Center)
+                .block(Block::default().borders(Borders::ALL));
+            f.render_widget(Clear, area);
+            f.render_widget(waiting_block, area);
+        }
+    }
+
+    if app.view != AppView::HeartRateView && !app.settings.tui.startup_banner.is_empty() {
+        // Sits in the top margin
+        let banner_area = Rect {
+            height: 1,
+            ..f.area()
+        };
+        let banner = Paragraph::new(app.settings.tui.startup_banner.as_str())
+            .alignment(Alignment::Center)
+            .style(Style::default().add_modifier(Modifier::BOLD));
+        f.render_widget(banner, banner_area);
     }
 
     // Overlays/substates
@@ -119,8 +202,9 @@ pub fn render(app: &mut App, f: &mut Frame) {
             let area = centered_rect(50, 50, f.area());
             let connecting_block = connecting_popup(
                 &selected_device.name,
-                &selected_device.get_id(),
+                &redact(app.hide_sensitive(), selected_device.get_id()),
                 app.quick_connect_ui,
+                app.connect_stage,
             );
             f.render_widget(Clear, area);
             f.render_widget(connecting_block, area);
@@ -149,9 +233,36 @@ pub fn render(app: &mut App, f: &mut Frame) {
         SubState::LaunchUpdatePrompt => {
             restart_app_prompt(app, f);
         }
+        SubState::PreflightPrompt => {
+            preflight_prompt(app, f);
+        }
+        SubState::TwitchTuning => {
+            render_twitch_tuning(app, f);
+        }
+        SubState::SessionCompare => {
+            render_session_compare(app, f);
+        }
+        SubState::DeviceInformation => {
+            render_device_information(app, f);
+        }
+        SubState::SourceSelection => {
+            render_source_selection(app, f);
+        }
+        SubState::PairingPrompt => {
+            render_pairing_prompt(app, f);
+        }
         SubState::None | SubState::ConnectingForCharacteristics => {}
     }
 
+    if app.ui_state.show_osc_tap {
+        if let Some(tap_log) = &app.osc_tap_log {
+            let area = centered_rect(80, 60, f.area());
+            let overlay = osc_tap_overlay(tap_log, area.height);
+            f.render_widget(Clear, area);
+            f.render_widget(overlay, area);
+        }
+    }
+
     // Draw the error overlay if the string is not empty
     render_error_popup(app, f);
 }
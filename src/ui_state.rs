@@ -0,0 +1,145 @@
+use serde_derive::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tracing::{debug, warn};
+
+use crate::errors::AppError;
+use crate::settings::{LayoutPreset, Theme, TuiSettings};
+
+const UI_STATE_TOML_PATH: &str = "ui_state.toml";
+
+/// Bits of the TUI that can be changed at runtime, and are remembered across restarts
+/// if `tui.remember_layout` is enabled.
+///
+/// Starts off from the values in the config, and the file only overrides them once something's been toggled.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct UiState {
+    pub chart_bpm_enabled: bool,
+    pub chart_rr_enabled: bool,
+    pub charts_combine: bool,
+    pub show_osc_tap: bool,
+    pub show_unnamed_devices: bool,
+    pub layout: LayoutPreset,
+    pub theme: Theme,
+    pub screen: Screen,
+}
+
+/// Panels on the heart rate view that come back open after a restart.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Screen {
+    #[default]
+    HeartRate,
+    DeviceInformation,
+    SessionCompare,
+}
+
+// Missing fields in the file fall back to the config's values
+#[derive(Debug, Deserialize, Default)]
+struct UiStateFile {
+    chart_bpm_enabled: Option<bool>,
+    chart_rr_enabled: Option<bool>,
+    charts_combine: Option<bool>,
+    show_osc_tap: Option<bool>,
+    show_unnamed_devices: Option<bool>,
+    layout: Option<LayoutPreset>,
+    theme: Option<Theme>,
+    screen: Option<Screen>,
+}
+
+impl UiState {
+    pub fn from_settings(tui_settings: &TuiSettings) -> Self {
+        Self {
+            chart_bpm_enabled: tui_settings.chart_bpm_enabled,
+            chart_rr_enabled: tui_settings.chart_rr_enabled,
+            charts_combine: tui_settings.charts_combine,
+            show_osc_tap: false,
+            show_unnamed_devices: tui_settings.show_unnamed_devices,
+            layout: tui_settings.layout,
+            theme: tui_settings.theme,
+            screen: Screen::default(),
+        }
+    }
+    /// Applies the saved state on top of the config's, if there is one.
+    pub fn load(tui_settings: &TuiSettings) -> Result<Self, AppError> {
+        let defaults = Self::from_settings(tui_settings);
+        let file_path = PathBuf::from(UI_STATE_TOML_PATH);
+        if !tui_settings.remember_layout || !file_path.exists() {
+            return Ok(defaults);
+        }
+        let buffer = std::fs::read_to_string(&file_path)?;
+        Ok(defaults.merged(toml::from_str(&buffer)?))
+    }
+    fn merged(self, file: UiStateFile) -> Self {
+        Self {
+            chart_bpm_enabled: file.chart_bpm_enabled.unwrap_or(self.chart_bpm_enabled),
+            chart_rr_enabled: file.chart_rr_enabled.unwrap_or(self.chart_rr_enabled),
+            charts_combine: file.charts_combine.unwrap_or(self.charts_combine),
+            show_osc_tap: file.show_osc_tap.unwrap_or(self.show_osc_tap),
+            show_unnamed_devices: file
+                .show_unnamed_devices
+                .unwrap_or(self.show_unnamed_devices),
+            layout: file.layout.unwrap_or(self.layout),
+            theme: file.theme.unwrap_or(self.theme),
+            screen: file.screen.unwrap_or(self.screen),
+        }
+    }
+    pub fn save(&self) -> Result<(), AppError> {
+        let file_path = PathBuf::from(UI_STATE_TOML_PATH);
+        std::fs::write(file_path, toml::to_string(self)?)?;
+        debug!("Saved UI state: {self:?}");
+        Ok(())
+    }
+    /// Saves if enabled, only logging on failure since it's not worth interrupting the user over.
+    pub fn persist(&self, tui_settings: &TuiSettings) {
+        if !tui_settings.remember_layout {
+            return;
+        }
+        if let Err(e) = self.save() {
+            warn!("Failed to save UI state: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults() -> UiState {
+        UiState {
+            chart_bpm_enabled: true,
+            chart_rr_enabled: true,
+            charts_combine: true,
+            show_osc_tap: false,
+            show_unnamed_devices: true,
+            layout: LayoutPreset::Full,
+            theme: Theme::Default,
+            screen: Screen::HeartRate,
+        }
+    }
+
+    #[test]
+    fn saved_values_override_config() {
+        let file: UiStateFile = toml::from_str("chart_rr_enabled = false").unwrap();
+        let state = defaults().merged(file);
+        assert_eq!(
+            state,
+            UiState {
+                chart_rr_enabled: false,
+                ..defaults()
+            }
+        );
+    }
+    #[test]
+    fn round_trip() {
+        let state = UiState {
+            charts_combine: false,
+            show_osc_tap: true,
+            layout: LayoutPreset::Compact,
+            theme: Theme::Monochrome,
+            screen: Screen::SessionCompare,
+            ..defaults()
+        };
+        let file: UiStateFile = toml::from_str(&toml::to_string(&state).unwrap()).unwrap();
+        assert_eq!(defaults().merged(file), state);
+    }
+}
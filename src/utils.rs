@@ -35,6 +35,17 @@ pub fn extract_manufacturer_data(manufacturer_data: &HashMap<u16, Vec<u8>>) -> M
     }
 }
 
+pub const HIDDEN_PLACEHOLDER: &str = "[hidden]";
+
+/// Swaps out the given info with a placeholder when in stream-safe mode.
+pub fn redact(hide: bool, sensitive: String) -> String {
+    if hide {
+        HIDDEN_PLACEHOLDER.to_string()
+    } else {
+        sensitive
+    }
+}
+
 /// Returns a `Rect` with the provided percentage of the parent `Rect` and centered.
 pub fn centered_rect(percent_x: u16, percent_y: u16, size: Rect) -> Rect {
     let popup_size = Rect {
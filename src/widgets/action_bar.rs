@@ -12,6 +12,7 @@ pub const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
 pub fn action_bar(
     scan_paused: bool,
     is_loading_characteristics: bool,
+    show_unnamed: bool,
     frame_count: &usize,
 ) -> Table<'static> {
     let index_slow = (frame_count / 2) % SPINNER.len();
@@ -30,6 +31,11 @@ pub fn action_bar(
         } else {
             text!["[c → load characteristics]".to_string()]
         },
+        if show_unnamed {
+            text!["[u → hide unnamed]"]
+        } else {
+            text!["[u → show unnamed]"]
+        },
         text![cargo_crate_version!()].right_aligned(),
     ])
     .style(Style::default().fg(Color::DarkGray))];
@@ -41,6 +47,7 @@ pub fn action_bar(
             Constraint::Length(20),
             Constraint::Length(17),
             Constraint::Length(30),
+            Constraint::Length(18),
             Constraint::Fill(1),
         ],
     )
@@ -0,0 +1,42 @@
+use ratatui::{
+    layout::Constraint,
+    style::{Color, Style},
+    widgets::{Block, Borders, Clear, Row, Table},
+    Frame,
+};
+
+use crate::app::App;
+use crate::heart_rate::device_information::DeviceInformation;
+use crate::utils::{centered_rect, redact};
+
+/// Popup with what the connected device reported about itself.
+pub fn render_device_information(app: &App, f: &mut Frame) {
+    let Some(device_information) = app.device_information.as_ref() else {
+        return;
+    };
+    let mut area = centered_rect(50, 40, f.area());
+    // Six rows, plus the borders
+    area.height = area.height.min(8);
+    let rows: Vec<Row> = device_information
+        .rows()
+        .into_iter()
+        .map(|(name, value)| {
+            let value = match value {
+                Some(value) if DeviceInformation::IDENTIFYING_ROWS.contains(&name) => {
+                    redact(app.hide_sensitive(), value.to_owned())
+                }
+                Some(value) => value.to_owned(),
+                None => "N/A".to_owned(),
+            };
+            Row::new([name.to_owned(), value])
+        })
+        .collect();
+    let table = Table::new(rows, [Constraint::Length(14), Constraint::Fill(1)]).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Device Information (esc to close)")
+            .border_style(Style::default().fg(Color::Yellow)),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(table, area);
+}
@@ -4,10 +4,13 @@ use ratatui::{
     widgets::{Block, Borders, Row, Table},
 };
 
-use crate::{structs::DeviceInfo, utils::extract_manufacturer_data};
+use crate::{
+    structs::DeviceInfo,
+    utils::{extract_manufacturer_data, redact},
+};
 
 /// Creates a table with the detected BTLE devices.
-pub fn device_table(selected: Option<usize>, devices: &[DeviceInfo]) -> Table {
+pub fn device_table(selected: Option<usize>, devices: &[DeviceInfo], hide_ids: bool) -> Table {
     let selected_style = Style::default().add_modifier(Modifier::REVERSED);
     let rows: Vec<Row> = devices
         .iter()
@@ -20,7 +23,7 @@ pub fn device_table(selected: Option<usize>, devices: &[DeviceInfo]) -> Table {
             };
             Row::new(vec![
                 device.name.clone(),
-                device.get_id(),
+                redact(hide_ids, device.get_id()),
                 extract_manufacturer_data(&device.manufacturer_data).company_code,
                 device.rssi.clone(),
             ])
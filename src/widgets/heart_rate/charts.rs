@@ -11,6 +11,8 @@ use ratatui_macros::{line, span};
 
 use crate::{
     app::App,
+    format::NumberFormat,
+    settings::Theme,
     widgets::heart_rate_display::{CHART_BPM_MAX_ELEMENTS, CHART_RR_MAX_ELEMENTS},
 };
 
@@ -20,6 +22,47 @@ pub enum ChartType {
     Combined,
 }
 
+struct ChartColors {
+    bpm: Color,
+    bpm_label: Color,
+    rr: Color,
+    rr_label: Color,
+    axis: Color,
+    title: Color,
+}
+
+impl ChartColors {
+    fn new(theme: Theme) -> Self {
+        match theme {
+            Theme::Default => Self {
+                bpm: Color::Red,
+                bpm_label: Color::LightRed,
+                rr: Color::Blue,
+                rr_label: Color::LightBlue,
+                axis: Color::Gray,
+                title: Color::Cyan,
+            },
+            Theme::HighContrast => Self {
+                bpm: Color::LightRed,
+                bpm_label: Color::LightRed,
+                rr: Color::LightCyan,
+                rr_label: Color::LightCyan,
+                axis: Color::White,
+                title: Color::LightYellow,
+            },
+            // Told apart by the dots being brighter/dimmer
+            Theme::Monochrome => Self {
+                bpm: Color::White,
+                bpm_label: Color::White,
+                rr: Color::DarkGray,
+                rr_label: Color::Gray,
+                axis: Color::Gray,
+                title: Color::White,
+            },
+        }
+    }
+}
+
 fn legend_rect(width: u16, height: u16, graph_area: Rect) -> Rect {
     let popup_size = Rect {
         width: width + 2,
@@ -33,19 +76,23 @@ fn legend_rect(width: u16, height: u16, graph_area: Rect) -> Rect {
     }
 }
 
-fn bpm_rr_legend(chart_type: &ChartType, graph_area: Rect) -> (Paragraph, Rect) {
+fn bpm_rr_legend(
+    chart_type: &ChartType,
+    colors: &ChartColors,
+    graph_area: Rect,
+) -> (Paragraph<'static>, Rect) {
     let text = match chart_type {
         ChartType::Combined => {
             vec![
-                line![span!(Color::Red; "BPM")],
-                line![span!(Color::Blue; "(RR)")],
+                line![span!(colors.bpm; "BPM")],
+                line![span!(colors.rr; "(RR)")],
             ]
         }
         ChartType::Bpm => {
-            vec![line![span!(Color::Red; "BPM")]]
+            vec![line![span!(colors.bpm; "BPM")]]
         }
         ChartType::Rr => {
-            vec![line![span!(Color::Blue; "(RR)")]]
+            vec![line![span!(colors.rr; "(RR)")]]
         }
     };
     let max_line_length = text
@@ -63,10 +110,17 @@ fn bpm_rr_legend(chart_type: &ChartType, graph_area: Rect) -> (Paragraph, Rect)
     (legend_block, legend_area)
 }
 
-fn styled_label(bpm: f64, rr: f64, chart_type: &ChartType, allow_space: bool) -> Line {
-    let bpm_label_style = (Color::LightRed, Modifier::BOLD);
-    let rr_label_style = (Color::LightBlue, Modifier::BOLD);
-    let rr = format!("({:.1})", rr);
+fn styled_label(
+    bpm: f64,
+    rr: f64,
+    chart_type: &ChartType,
+    allow_space: bool,
+    number_format: &NumberFormat,
+    colors: &ChartColors,
+) -> Line<'static> {
+    let bpm_label_style = (colors.bpm_label, Modifier::BOLD);
+    let rr_label_style = (colors.rr_label, Modifier::BOLD);
+    let rr = format!("({})", number_format.decimal(rr, 1));
     // Not a fan of this, need to ask Ratatui peeps
     let spaces = if allow_space && bpm <= 99.0 {
         "  "
@@ -93,6 +147,7 @@ fn styled_label(bpm: f64, rr: f64, chart_type: &ChartType, allow_space: bool) ->
 
 pub fn render_combined_chart(f: &mut Frame, area: Rect, app: &App, mut chart_type: ChartType) {
     let mut datasets = Vec::new();
+    let colors = ChartColors::new(app.ui_state.theme);
 
     let rr_bounds = [app.chart_low_rr, app.chart_high_rr];
     let mid_rr = app.chart_mid_rr;
@@ -110,7 +165,7 @@ pub fn render_combined_chart(f: &mut Frame, area: Rect, app: &App, mut chart_typ
                 .name("(RR)")
                 .graph_type(GraphType::Line)
                 .marker(symbols::Marker::Dot)
-                .style(Style::default().fg(Color::Blue))
+                .style(Style::default().fg(colors.rr))
                 .data(&app.rr_dataset),
         );
     }
@@ -121,7 +176,7 @@ pub fn render_combined_chart(f: &mut Frame, area: Rect, app: &App, mut chart_typ
                 .name("BPM")
                 .graph_type(GraphType::Line)
                 .marker(symbols::Marker::Dot)
-                .style(Style::default().fg(Color::Red))
+                .style(Style::default().fg(colors.bpm))
                 .data(&app.bpm_dataset),
         );
     }
@@ -129,9 +184,30 @@ pub fn render_combined_chart(f: &mut Frame, area: Rect, app: &App, mut chart_typ
     let allow_space = bpm_bounds[0] <= 99.0 && bpm_bounds[1] >= 100.0;
 
     let labels = vec![
-        styled_label(bpm_bounds[0], rr_bounds[0], &chart_type, allow_space),
-        styled_label(mid_bpm, mid_rr, &chart_type, allow_space),
-        styled_label(bpm_bounds[1], rr_bounds[1], &chart_type, allow_space),
+        styled_label(
+            bpm_bounds[0],
+            rr_bounds[0],
+            &chart_type,
+            allow_space,
+            &app.number_format,
+            &colors,
+        ),
+        styled_label(
+            mid_bpm,
+            mid_rr,
+            &chart_type,
+            allow_space,
+            &app.number_format,
+            &colors,
+        ),
+        styled_label(
+            bpm_bounds[1],
+            rr_bounds[1],
+            &chart_type,
+            allow_space,
+            &app.number_format,
+            &colors,
+        ),
     ];
 
     let y_bounds = match chart_type {
@@ -146,15 +222,15 @@ pub fn render_combined_chart(f: &mut Frame, area: Rect, app: &App, mut chart_typ
     };
 
     let chart = Chart::new(datasets)
-        .block(Block::bordered().title("Histogram".cyan().bold()))
+        .block(Block::bordered().title("Histogram".fg(colors.title).bold()))
         .x_axis(
             Axis::default()
-                .style(Style::default().fg(Color::Gray))
+                .style(Style::default().fg(colors.axis))
                 .bounds([0.0, x_bound_top as f64]),
         )
         .y_axis(
             Axis::default()
-                .style(Style::default().fg(Color::Gray))
+                .style(Style::default().fg(colors.axis))
                 .labels(labels)
                 .bounds(y_bounds),
         )
@@ -162,7 +238,7 @@ pub fn render_combined_chart(f: &mut Frame, area: Rect, app: &App, mut chart_typ
     f.render_widget(chart, area);
     // Temporarily making our own legend while we wait for Ratatui issue #1290 (https://github.com/ratatui-org/ratatui/issues/1290)
     // to allow us to change order of legend elements
-    let (legend, legend_area) = bpm_rr_legend(&chart_type, area);
+    let (legend, legend_area) = bpm_rr_legend(&chart_type, &colors, area);
     f.render_widget(Clear, legend_area);
     f.render_widget(legend, legend_area);
 }
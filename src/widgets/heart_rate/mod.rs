@@ -1,2 +1,3 @@
 pub mod charts;
+pub mod refresh;
 pub mod tables;
@@ -0,0 +1,158 @@
+use std::time::{Duration, Instant};
+
+use tracing::info;
+
+use crate::settings::{ChartThrottle, TuiSettings};
+
+// Draws slower than this (on average) mean the terminal's struggling to keep up, i.e. over SSH
+const SLOW_DRAW: Duration = Duration::from_millis(30);
+// And need to get back under this before charts go back to full rate
+const FAST_DRAW: Duration = Duration::from_millis(10);
+// Weight of the latest draw in the running average
+const DRAW_SMOOTHING: f64 = 0.1;
+
+/// Decides how often the chart datasets get rebuilt, and how many points they keep.
+///
+/// The table is redrawn as usual, but unchanged charts don't send anything
+/// to the terminal, so holding them back is what saves the bandwidth.
+#[derive(Debug)]
+pub struct ChartRefresh {
+    mode: ChartThrottle,
+    min_interval: Duration,
+    max_points: usize,
+    // Only used in `Auto`
+    average_draw: Duration,
+    slow_terminal: bool,
+    last_update: Option<Instant>,
+    // New data came in while held back
+    pending: bool,
+}
+
+impl ChartRefresh {
+    pub fn new(tui_settings: &TuiSettings) -> Self {
+        Self {
+            mode: tui_settings.chart_throttle,
+            min_interval: Duration::from_millis(tui_settings.chart_throttle_interval_ms as u64),
+            max_points: tui_settings.chart_throttle_max_points as usize,
+            average_draw: Duration::ZERO,
+            slow_terminal: false,
+            last_update: None,
+            pending: false,
+        }
+    }
+    pub fn throttled(&self) -> bool {
+        match self.mode {
+            ChartThrottle::Auto => self.slow_terminal,
+            ChartThrottle::Always => true,
+            ChartThrottle::Never => false,
+        }
+    }
+    /// Keeps track of how long the terminal's been taking to draw.
+    pub fn record_draw(&mut self, elapsed: Duration) {
+        if self.mode != ChartThrottle::Auto {
+            return;
+        }
+        self.average_draw = self
+            .average_draw
+            .mul_f64(1.0 - DRAW_SMOOTHING)
+            .saturating_add(elapsed.mul_f64(DRAW_SMOOTHING));
+        if !self.slow_terminal && self.average_draw > SLOW_DRAW {
+            info!(
+                "Drawing is slow ({}ms), throttling charts",
+                self.average_draw.as_millis()
+            );
+            self.slow_terminal = true;
+        } else if self.slow_terminal && self.average_draw < FAST_DRAW {
+            info!("Drawing is fast again, charts back to full rate");
+            self.slow_terminal = false;
+        }
+    }
+    /// Called with new data, returns true if the datasets should be rebuilt now.
+    pub fn should_update(&mut self, now: Instant) -> bool {
+        self.pending = true;
+        self.due(now)
+    }
+    /// Called every tick, to catch up on data that was held back.
+    pub fn pending_due(&mut self, now: Instant) -> bool {
+        self.pending && self.due(now)
+    }
+    fn due(&mut self, now: Instant) -> bool {
+        let due = !self.throttled()
+            || self
+                .last_update
+                .is_none_or(|last| now.saturating_duration_since(last) >= self.min_interval);
+        if due {
+            self.last_update = Some(now);
+            self.pending = false;
+        }
+        due
+    }
+    /// Thins out a dataset to at most `max_points` while throttled, always keeping the newest.
+    pub fn decimate(&self, dataset: Vec<(f64, f64)>) -> Vec<(f64, f64)> {
+        if !self.throttled() || self.max_points == 0 || dataset.len() <= self.max_points {
+            return dataset;
+        }
+        let stride = dataset.len().div_ceil(self.max_points);
+        // Newest is first, since the histories are reversed for the charts
+        dataset.into_iter().step_by(stride).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chart_refresh(mode: ChartThrottle) -> ChartRefresh {
+        ChartRefresh::new(&TuiSettings {
+            chart_throttle: mode,
+            chart_throttle_interval_ms: 1000,
+            chart_throttle_max_points: 40,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn holds_back_updates_while_throttled() {
+        let mut refresh = chart_refresh(ChartThrottle::Always);
+        let start = Instant::now();
+        assert!(refresh.should_update(start));
+        assert!(!refresh.should_update(start + Duration::from_millis(300)));
+        assert!(!refresh.pending_due(start + Duration::from_millis(600)));
+        assert!(refresh.pending_due(start + Duration::from_millis(1000)));
+        // Nothing new came in since
+        assert!(!refresh.pending_due(start + Duration::from_millis(3000)));
+
+        let mut refresh = chart_refresh(ChartThrottle::Never);
+        assert!(refresh.should_update(start));
+        assert!(refresh.should_update(start));
+    }
+    #[test]
+    fn auto_throttles_slow_terminals() {
+        let mut refresh = chart_refresh(ChartThrottle::Auto);
+        assert!(!refresh.throttled());
+        for _ in 0..50 {
+            refresh.record_draw(Duration::from_millis(80));
+        }
+        assert!(refresh.throttled());
+        // Somewhere in between isn't enough to go back
+        for _ in 0..50 {
+            refresh.record_draw(Duration::from_millis(20));
+        }
+        assert!(refresh.throttled());
+        for _ in 0..50 {
+            refresh.record_draw(Duration::from_millis(2));
+        }
+        assert!(!refresh.throttled());
+    }
+    #[test]
+    fn decimation_keeps_newest() {
+        let refresh = chart_refresh(ChartThrottle::Always);
+        let dataset: Vec<(f64, f64)> = (0..120).map(|i| (i as f64, 60.0)).collect();
+        let decimated = refresh.decimate(dataset);
+        assert_eq!(decimated.len(), 40);
+        assert_eq!(decimated[0].0, 0.0);
+        assert_eq!(decimated[1].0, 3.0);
+        let short: Vec<(f64, f64)> = (0..10).map(|i| (i as f64, 60.0)).collect();
+        assert_eq!(refresh.decimate(short.clone()), short);
+    }
+}
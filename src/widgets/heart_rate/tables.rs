@@ -1,11 +1,20 @@
+use std::time::Duration;
+
 use ratatui::{
-    layout::{Constraint, Rect},
+    layout::{Alignment, Constraint, Rect},
     style::{Color, Modifier, Style},
-    widgets::{Block, Borders, Cell, Row, Table},
+    widgets::{
+        block::{Position, Title},
+        Block, Borders, Cell, Row, Table,
+    },
     Frame,
 };
 
-use crate::{app::App, heart_rate::BatteryLevel};
+use crate::{
+    app::App,
+    heart_rate::{ble::BleReconnect, bpm_from_rr, BatteryLevel},
+    zones::zone_color,
+};
 
 use ratatui_macros::{line, span};
 
@@ -39,19 +48,26 @@ pub fn render_table(f: &mut Frame, area: Rect, app: &App) {
         }),
     };
 
+    let bpm_style = match app.current_zone() {
+        Some(zone) => Style::default().fg(zone_color(zone)),
+        None => Style::default(),
+    };
+
     let time_format = if app.settings.tui.session_stats_use_12hr {
         "%-I:%M %p"
     } else {
         "%H:%M"
     };
 
+    let number_format = &app.number_format;
     let rr_string = format!(
-        "{:.3?}",
+        "[{}]",
         heart_rate_status
             .rr_intervals
             .iter()
-            .map(|rr| rr.as_secs_f32())
-            .collect::<Vec<f32>>()
+            .map(|rr| number_format.decimal(rr.as_secs_f64(), 3))
+            .collect::<Vec<String>>()
+            .join(number_format.list_separator())
     );
 
     let high_string = format!(
@@ -67,7 +83,7 @@ pub fn render_table(f: &mut Frame, area: Rect, app: &App) {
     );
 
     let mut content = vec![
-        Cell::from(heart_rate_status.heart_rate_bpm.to_string()),
+        Cell::from(number_format.bpm(heart_rate_status.heart_rate_bpm)).style(bpm_style),
         Cell::from(rr_string),
         Cell::from(battery_string).style(battery_style),
         Cell::from(high_string),
@@ -82,6 +98,69 @@ pub fn render_table(f: &mut Frame, area: Rect, app: &App) {
         Constraint::Length(20),
     ];
 
+    // Beat-to-beat, since straps tend to smooth out the BPM they report
+    if let Some(instant_bpm) = app
+        .rr_history
+        .back()
+        .and_then(|rr| bpm_from_rr(Duration::from_secs_f64(*rr)))
+    {
+        headers.push(line!["Beat BPM"]);
+        content.push(Cell::from(number_format.bpm(format!("{instant_bpm:.0}"))));
+        constraints.push(Constraint::Length(10));
+    }
+
+    if let Some(rssi) = heart_rate_status.rssi {
+        let rssi_style = Style::default().fg(match rssi {
+            -70.. => Color::Green,
+            -85..=-71 => Color::Yellow,
+            _ => Color::Red,
+        });
+        headers.push(line!["Signal"]);
+        content.push(Cell::from(format!("{rssi} dBm")).style(rssi_style));
+        constraints.push(Constraint::Length(10));
+    }
+
+    if let Some(spo2) = heart_rate_status.spo2 {
+        let spo2_style = Style::default().fg(match spo2 {
+            95.0.. => Color::Green,
+            90.0..95.0 => Color::Yellow,
+            _ => Color::Red,
+        });
+        headers.push(line!["SpO2"]);
+        content.push(Cell::from(format!("{spo2:.0}%")).style(spo2_style));
+        constraints.push(Constraint::Length(10));
+    }
+
+    if let Some(cadence) = heart_rate_status.cadence {
+        headers.push(line!["Cadence"]);
+        content.push(Cell::from(format!("{cadence}/min")));
+        constraints.push(Constraint::Length(10));
+    }
+
+    if let Some(stress_index) = heart_rate_status.stress_index {
+        headers.push(line!["Stress Index"]);
+        content.push(Cell::from(format!("{stress_index:.0}")));
+        constraints.push(Constraint::Length(15));
+    }
+
+    if let Some(active) = app.failover_active {
+        // Standing out while a fallback's in charge
+        let style = if app.sources.current() == Some(active) {
+            Style::default()
+        } else {
+            Style::default().fg(Color::Yellow)
+        };
+        headers.push(line!["Source"]);
+        content.push(Cell::from(active.name()).style(style));
+        constraints.push(Constraint::Length(14));
+    }
+
+    if let Some(clients) = app.websocket_clients {
+        headers.push(line!["Clients"]);
+        content.push(Cell::from(clients.to_string()));
+        constraints.push(Constraint::Length(8));
+    }
+
     if app.settings.activities.enabled {
         headers.push(line![span!(Modifier::UNDERLINED; "A"), span!("ctivity")]);
         let activity = app.activities.selected();
@@ -93,13 +172,50 @@ pub fn render_table(f: &mut Frame, area: Rect, app: &App) {
     rows.push(Row::new(headers).style(Style::default().add_modifier(Modifier::BOLD)));
     rows.push(Row::new(content));
 
+    let mut block = Block::default()
+        .borders(Borders::ALL)
+        .title("Most Recent Data")
+        .border_style(Style::default().fg(Color::Yellow));
+
+    if let Some(BleReconnect::Retrying { attempt, delay }) = app.ble_reconnect {
+        block = block.title(
+            Title::from(span!(Color::Yellow; "Reconnecting (attempt {attempt}, waiting {}s)", delay.as_secs()))
+                .alignment(Alignment::Left),
+        );
+    }
+    if app.osc_calibrating {
+        block = block.title(
+            Title::from(span!(Color::Cyan; "Calibrating float HR (f to stop)"))
+                .alignment(Alignment::Right),
+        );
+    } else if app.osc_paused {
+        block = block.title(
+            Title::from(span!(Color::Cyan; "OSC paused (p to resume)")).alignment(Alignment::Right),
+        );
+    } else if app.osc_degraded {
+        block = block.title(
+            Title::from(span!(Color::Red; "OSC target unreachable, beats paused"))
+                .alignment(Alignment::Right),
+        );
+    }
+
+    if let Some(session_log) = app.session_log.as_ref() {
+        block = block.title(
+            Title::from(span!(Color::Red; "● REC {session_log}"))
+                .alignment(Alignment::Left)
+                .position(Position::Bottom),
+        );
+    }
+
+    if let Some(preset) = app.osc_preset.as_ref() {
+        block = block.title(
+            Title::from(span!(Color::Cyan; "Preset: {preset} (v to cycle)"))
+                .alignment(Alignment::Center),
+        );
+    }
+
     let table = Table::new(rows.to_vec(), constraints)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title("Most Recent Data")
-                .border_style(Style::default().fg(Color::Yellow)),
-        )
+        .block(block)
         .highlight_style(Style::default().add_modifier(Modifier::BOLD));
 
     f.render_widget(table, area);
@@ -5,6 +5,7 @@ use ratatui::{
 
 use crate::{
     app::App,
+    settings::LayoutPreset,
     widgets::heart_rate::{charts::render_combined_chart, charts::ChartType, tables::render_table},
 };
 
@@ -17,19 +18,30 @@ pub const CHART_RR_VERT_MARGIN: f64 = 0.1;
 
 pub fn heart_rate_display(app: &App, frame: &mut Frame) {
     let area = frame.area();
+    let layout = app.ui_state.layout;
+    if layout == LayoutPreset::Compact {
+        render_table(frame, area, app);
+        return;
+    }
 
     let vertical = Layout::vertical([Constraint::Min(4), Constraint::Percentage(100)]);
     let horizontal_shared = Layout::horizontal([Constraint::Percentage(100)]);
     let horizontal_split =
         Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]);
     let [status_area, bottom] = vertical.areas(area);
+    let bottom = match layout {
+        LayoutPreset::Charts => area,
+        _ => bottom,
+    };
     let [bpm_history, rr_history] = horizontal_split.areas(bottom);
     let [shared_chart] = horizontal_shared.areas(bottom);
 
-    render_table(frame, status_area, app);
-    let bpm_chart = app.settings.tui.chart_bpm_enabled;
-    let rr_chart = app.settings.tui.chart_rr_enabled;
-    let combined = app.settings.tui.charts_combine;
+    if layout == LayoutPreset::Full {
+        render_table(frame, status_area, app);
+    }
+    let bpm_chart = app.ui_state.chart_bpm_enabled;
+    let rr_chart = app.ui_state.chart_rr_enabled;
+    let combined = app.ui_state.charts_combine;
 
     if combined && bpm_chart && rr_chart {
         render_combined_chart(frame, shared_chart, app, ChartType::Combined);
@@ -1,7 +1,12 @@
 pub mod action_bar;
 pub mod detail_table;
+pub mod device_information;
 pub mod device_table;
 pub mod heart_rate;
 pub mod heart_rate_display;
 pub mod inspect_overlay;
+pub mod osc_tap;
 pub mod prompts;
+pub mod session_compare;
+pub mod source_selection;
+pub mod twitch_tuning;
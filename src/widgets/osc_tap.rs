@@ -0,0 +1,28 @@
+use ratatui::{
+    style::{Color, Style},
+    text::Line,
+    widgets::{Block, Borders, Paragraph},
+};
+
+use crate::osc::OscTapLog;
+
+/// Provides an overlay with the most recent OSC messages, newest at the bottom.
+pub fn osc_tap_overlay(tap_log: &OscTapLog, height: u16) -> Paragraph<'static> {
+    // Borders take up two rows
+    let visible_rows = height.saturating_sub(2) as usize;
+    let lines: Vec<Line> = match tap_log.lock() {
+        Ok(tap_log) => tap_log
+            .iter()
+            .skip(tap_log.len().saturating_sub(visible_rows))
+            .map(|line| Line::from(line.clone()))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+
+    Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("OSC Tap (o to close)")
+            .border_style(Style::default().fg(Color::Yellow)),
+    )
+}
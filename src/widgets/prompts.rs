@@ -10,6 +10,7 @@ use ratatui_macros::{row, span};
 
 use crate::{
     app::{App, ErrorPopup},
+    heart_rate::ble::ConnectStage,
     utils::centered_rect,
 };
 
@@ -46,10 +47,56 @@ pub fn save_prompt() -> Table<'static> {
     option_table
 }
 
+#[derive(Debug, Eq, PartialEq, FromPrimitive)]
+#[repr(u8)]
+pub enum PreflightPromptChoice {
+    Retry,
+    #[num_enum(default)]
+    Continue,
+    Quit,
+}
+
+/// Lists what the pre-flight checks found, with options to retry, carry on, or quit.
+pub fn preflight_prompt(app: &mut App, f: &mut Frame) {
+    let area = centered_rect(65, 50, f.area());
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("Pre-flight Check")
+        .border_style(Style::default().fg(ratatui::style::Color::Yellow));
+
+    let inner_area = block.inner(area);
+    let [issues_area, options_area] =
+        Layout::vertical([Constraint::Fill(1), Constraint::Length(3)]).areas(inner_area);
+
+    let mut lines: Vec<Line> = app
+        .preflight_issues
+        .iter()
+        .map(|issue| Line::raw(format!("- {}", issue.message())))
+        .collect();
+    if app.preflight_running {
+        lines.push(Line::raw(""));
+        lines.push(Line::raw("Checking again..."));
+    }
+    let issues = Paragraph::new(Text::from(lines)).wrap(Wrap { trim: true });
+
+    let selected_style = Style::default().add_modifier(Modifier::REVERSED);
+    let rows: Vec<Row> = vec![row!["Retry"], row!["Continue anyway"], row!["Quit"]];
+    let options = Table::new(rows, [Constraint::Percentage(100)])
+        .highlight_style(selected_style)
+        .highlight_symbol(">> ");
+
+    f.render_widget(Clear, area);
+    f.render_widget(block, area);
+    f.render_widget(issues, issues_area);
+    f.render_stateful_widget(options, options_area, &mut app.prompt_state);
+}
+
 pub fn connecting_popup<'a>(
     device_name: &str,
     device_mac: &str,
     quick_connect_ui: bool,
+    stage: Option<ConnectStage>,
 ) -> Paragraph<'a> {
     let mut name = device_name;
     let mut border_style = Style::default();
@@ -62,13 +109,23 @@ pub fn connecting_popup<'a>(
         }
     }
 
-    Paragraph::new(format!("Connecting to:\n{}\n({})", name, device_mac))
-        .alignment(Alignment::Center)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .border_style(border_style),
-        )
+    let stage = match stage {
+        Some(ConnectStage::Scanning) => "Scanning for device...",
+        Some(ConnectStage::Connecting) | None => "Connecting...",
+        Some(ConnectStage::Subscribing) => "Subscribing to notifications...",
+        Some(ConnectStage::Streaming) => "Waiting for data...",
+    };
+
+    Paragraph::new(format!(
+        "Connecting to:\n{}\n({})\n\n{}\n[esc → cancel]",
+        name, device_mac, stage
+    ))
+    .alignment(Alignment::Center)
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(border_style),
+    )
 }
 
 pub fn render_error_popup(app: &App, f: &mut Frame) {
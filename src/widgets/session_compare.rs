@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+
+use ratatui::{
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style, Stylize},
+    symbols,
+    text::Line,
+    widgets::{Axis, Block, Chart, Clear, Dataset, GraphType, Paragraph, Row, Table, TableState},
+    Frame,
+};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use tracing::warn;
+
+use crate::app::App;
+use crate::format::NumberFormat;
+use crate::logging::sessions::{
+    compare, CompareStats, SessionCurve, SessionEntry, SessionId, SessionStore, SessionSummary,
+};
+use crate::ui::table_state_scroll;
+use crate::utils::centered_rect;
+
+/// Browsing logged sessions, picking two to overlay, or deleting them.
+pub struct SessionCompare {
+    store: SessionStore,
+    sessions: Vec<SessionEntry>,
+    // Filled in by a background task as they're worked out, missing while still loading
+    // and None if it couldn't be read
+    summaries: HashMap<SessionId, Option<SessionSummary>>,
+    summary_rx: UnboundedReceiver<(SessionId, Option<SessionSummary>)>,
+    // What `AppUpdate::SessionLogging` called the session being logged to right now, which can't be deleted
+    active: Option<String>,
+    // Waiting on a second press to delete this one
+    pending_delete: Option<usize>,
+    pub table_state: TableState,
+    // Up to two, in the order they were picked
+    picked: Vec<usize>,
+    comparison: Option<Comparison>,
+    error: Option<String>,
+}
+
+struct Comparison {
+    first: SessionCurve,
+    second: SessionCurve,
+    stats: Option<CompareStats>,
+}
+
+impl SessionCompare {
+    pub fn new(store: SessionStore, active: Option<String>) -> Self {
+        let (sessions, error) = match store.list() {
+            Ok(sessions) => (sessions, None),
+            Err(e) => (Vec::new(), Some(e.to_string())),
+        };
+        // Every CSV gets read in full for these, so the list shows up right away and fills in
+        let (summary_tx, summary_rx) = mpsc::unbounded_channel();
+        let ids = sessions.iter().map(|session| session.id.clone()).collect();
+        let summary_store = store.clone();
+        tokio::task::spawn_blocking(move || summary_store.load_summaries(ids, summary_tx));
+        let mut table_state = TableState::default();
+        if !sessions.is_empty() {
+            table_state.select(Some(0));
+        }
+        Self {
+            store,
+            sessions,
+            summaries: HashMap::new(),
+            summary_rx,
+            active,
+            pending_delete: None,
+            table_state,
+            picked: Vec::new(),
+            comparison: None,
+            error,
+        }
+    }
+    /// Picks up any summaries the background task has finished.
+    fn receive_summaries(&mut self) {
+        while let Ok((id, summary)) = self.summary_rx.try_recv() {
+            self.summaries.insert(id, summary);
+        }
+    }
+    pub fn showing_comparison(&self) -> bool {
+        self.comparison.is_some()
+    }
+    pub fn scroll(&mut self, up: bool) {
+        if self.comparison.is_none() {
+            self.pending_delete = None;
+            table_state_scroll(up, &mut self.table_state, self.sessions.len());
+        }
+    }
+    /// Deletes the highlighted session on the second press.
+    pub fn delete_selected(&mut self) {
+        let Some(selected) = self.table_state.selected() else {
+            return;
+        };
+        if self.comparison.is_some() || selected >= self.sessions.len() {
+            return;
+        }
+        if self.active.as_ref() == Some(&self.sessions[selected].log_name) {
+            self.error = Some("That session is still being logged to".into());
+            return;
+        }
+        if self.pending_delete != Some(selected) {
+            self.pending_delete = Some(selected);
+            return;
+        }
+        self.pending_delete = None;
+        if let Err(e) = self.store.delete(&self.sessions[selected].id) {
+            warn!("Couldn't delete session: {e}");
+            self.error = Some(e.to_string());
+            return;
+        }
+        let session = self.sessions.remove(selected);
+        self.summaries.remove(&session.id);
+        // Picks after the deleted one shift down with it
+        self.picked.retain(|&i| i != selected);
+        for picked in self.picked.iter_mut().filter(|i| **i > selected) {
+            *picked -= 1;
+        }
+        let selected = (!self.sessions.is_empty()).then(|| selected.min(self.sessions.len() - 1));
+        self.table_state.select(selected);
+        self.error = None;
+    }
+    /// Picks (or un-picks) the highlighted session, comparing once there's two.
+    pub fn toggle_selected(&mut self) {
+        self.pending_delete = None;
+        let Some(selected) = self.table_state.selected() else {
+            return;
+        };
+        if let Some(index) = self.picked.iter().position(|&i| i == selected) {
+            self.picked.remove(index);
+            return;
+        }
+        self.picked.push(selected);
+        if self.picked.len() == 2 {
+            self.load_comparison();
+        }
+    }
+    /// Back to the list from a comparison.
+    pub fn back(&mut self) {
+        self.comparison = None;
+        self.picked.clear();
+    }
+    fn load_comparison(&mut self) {
+        let load = |index: usize| self.store.load(&self.sessions[index].id);
+        match (load(self.picked[0]), load(self.picked[1])) {
+            (Ok(first), Ok(second)) => {
+                let stats = compare(&first, &second);
+                self.comparison = Some(Comparison {
+                    first,
+                    second,
+                    stats,
+                });
+                self.error = None;
+            }
+            (Err(e), _) | (_, Err(e)) => {
+                warn!("Couldn't load session for comparison: {e}");
+                self.error = Some(e.to_string());
+                self.picked.clear();
+            }
+        }
+    }
+}
+
+pub fn render_session_compare(app: &mut App, f: &mut Frame) {
+    let Some(compare) = app.session_compare.as_mut() else {
+        return;
+    };
+    compare.receive_summaries();
+    let area = centered_rect(90, 80, f.area());
+    f.render_widget(Clear, area);
+
+    let block = Block::bordered().title("Session Comparison".cyan().bold());
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    match compare.comparison.as_ref() {
+        Some(comparison) => render_comparison(comparison, &app.number_format, f, inner),
+        None => render_session_list(compare, f, inner),
+    }
+}
+
+fn render_session_list(compare: &mut SessionCompare, f: &mut Frame, area: ratatui::layout::Rect) {
+    let [info_area, table_area] =
+        Layout::vertical([Constraint::Length(2), Constraint::Fill(1)]).areas(area);
+    let mut info = vec![Line::from(
+        "[up/down → move] [enter → pick two to compare] [d → delete] [esc → close]".dark_gray(),
+    )];
+    if let Some(pending) = compare.pending_delete {
+        let name = &compare.sessions[pending].name;
+        info.push(Line::from(
+            format!("Press d again to delete {name}").yellow(),
+        ));
+    } else if let Some(error) = compare.error.as_ref() {
+        info.push(Line::from(error.as_str().red()));
+    } else if compare.sessions.is_empty() {
+        info.push(Line::from(
+            "No logged sessions found, see log_sessions_to_csv and log_sessions_to_sqlite",
+        ));
+    }
+    f.render_widget(Paragraph::new(info), info_area);
+
+    let rows: Vec<Row> = compare
+        .sessions
+        .iter()
+        .enumerate()
+        .map(|(i, session)| {
+            let mark = match compare.picked.iter().position(|&picked| picked == i) {
+                Some(0) => "[1] ",
+                Some(_) => "[2] ",
+                None => "    ",
+            };
+            let summary = match compare.summaries.get(&session.id) {
+                Some(Some(summary)) => [
+                    format!("{:.0} min", summary.minutes),
+                    format!("avg {:.0}", summary.average),
+                    format!("peak {:.0}", summary.peak),
+                ],
+                Some(None) => ["no readings".into(), String::new(), String::new()],
+                None => ["loading...".into(), String::new(), String::new()],
+            };
+            let [minutes, average, peak] = summary;
+            Row::new([format!("{mark}{}", session.name), minutes, average, peak])
+        })
+        .collect();
+    let widths = [
+        Constraint::Fill(1),
+        Constraint::Length(12),
+        Constraint::Length(10),
+        Constraint::Length(10),
+    ];
+    let table = Table::new(rows, widths)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol(">> ");
+    f.render_stateful_widget(table, table_area, &mut compare.table_state);
+}
+
+fn render_comparison(
+    comparison: &Comparison,
+    number_format: &NumberFormat,
+    f: &mut Frame,
+    area: ratatui::layout::Rect,
+) {
+    let [info_area, chart_area] =
+        Layout::vertical([Constraint::Length(4), Constraint::Fill(1)]).areas(area);
+
+    let describe = |curve: &SessionCurve| {
+        format!(
+            "{} ({:.0} min): avg {:.0}, peak {:.0}",
+            curve.name,
+            curve.duration_secs() / 60.0,
+            curve.average(),
+            curve.peak()
+        )
+    };
+    let delta = match comparison.stats {
+        Some(stats) => {
+            let signed = |value: f64| {
+                let sign = if value >= 0.0 { "+" } else { "" };
+                format!("{sign}{}", number_format.decimal(value, 1))
+            };
+            format!(
+                "Delta (2 - 1): mean {} BPM, mean absolute {} BPM, largest {:+.0} BPM at {:.0}% through",
+                signed(stats.mean_delta),
+                number_format.decimal(stats.mean_abs_delta, 1),
+                stats.max_delta,
+                stats.max_delta_at
+            )
+        }
+        None => "Not enough data in both sessions to compare".into(),
+    };
+    let info = vec![
+        Line::from(format!("1: {}", describe(&comparison.first)).light_blue()),
+        Line::from(format!("2: {}", describe(&comparison.second)).light_magenta()),
+        Line::from(delta),
+        Line::from("Time is normalized to each session's length [esc → back]".dark_gray()),
+    ];
+    f.render_widget(Paragraph::new(info), info_area);
+
+    let first = comparison.first.normalized();
+    let second = comparison.second.normalized();
+    let (low, high) = first
+        .iter()
+        .chain(&second)
+        .fold((f64::MAX, f64::MIN), |(low, high), (_, bpm)| {
+            (low.min(*bpm), high.max(*bpm))
+        });
+    if low > high {
+        return;
+    }
+    let (low, high) = ((low - 5.0).max(0.0), high + 5.0);
+
+    let datasets = vec![
+        Dataset::default()
+            .name("1")
+            .graph_type(GraphType::Line)
+            .marker(symbols::Marker::Braille)
+            .style(Style::default().fg(Color::LightBlue))
+            .data(&first),
+        Dataset::default()
+            .name("2")
+            .graph_type(GraphType::Line)
+            .marker(symbols::Marker::Braille)
+            .style(Style::default().fg(Color::LightMagenta))
+            .data(&second),
+    ];
+    let chart = Chart::new(datasets)
+        .x_axis(
+            Axis::default()
+                .style(Style::default().fg(Color::Gray))
+                .labels(vec![Line::from("0%"), Line::from("100%")])
+                .bounds([0.0, 100.0]),
+        )
+        .y_axis(
+            Axis::default()
+                .style(Style::default().fg(Color::Gray))
+                .labels(vec![
+                    Line::from(format!("{low:.0}")),
+                    Line::from(format!("{high:.0}")),
+                ])
+                .bounds([low, high]),
+        );
+    f.render_widget(chart, chart_area);
+}
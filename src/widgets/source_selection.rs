@@ -0,0 +1,42 @@
+use ratatui::{
+    layout::Constraint,
+    style::{Color, Modifier, Style},
+    widgets::{Block, Borders, Clear, Row, Table},
+    Frame,
+};
+
+use crate::app::App;
+use crate::sources::HrSource;
+use crate::utils::centered_rect;
+
+/// Popup for switching the HR source while running.
+pub fn render_source_selection(app: &mut App, f: &mut Frame) {
+    let mut area = centered_rect(50, 30, f.area());
+    // One row per source, plus the borders
+    area.height = area.height.min(HrSource::ALL.len() as u16 + 2);
+    let current = app.sources.current();
+    let rows: Vec<Row> = HrSource::ALL
+        .iter()
+        .map(|&source| {
+            let mark = if Some(source) == current {
+                " (current)"
+            } else {
+                ""
+            };
+            let capabilities = source.capabilities().describe();
+            Row::new([format!("{}{mark}", source.name()), capabilities.to_owned()])
+        })
+        .collect();
+    let constraints = [Constraint::Fill(1), Constraint::Length(16)];
+    let table = Table::new(rows, constraints)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("HR Source (enter to switch, esc to close)")
+                .border_style(Style::default().fg(Color::Yellow)),
+        )
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol(">> ");
+    f.render_widget(Clear, area);
+    f.render_stateful_widget(table, area, &mut app.sources.table_state);
+}
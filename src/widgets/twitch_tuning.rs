@@ -0,0 +1,162 @@
+use std::time::Duration;
+
+use ratatui::{
+    layout::{Constraint, Layout},
+    style::{Color, Style, Stylize},
+    symbols,
+    text::Line,
+    widgets::{Axis, Block, Chart, Clear, Dataset, GraphType, Paragraph},
+    Frame,
+};
+
+use crate::app::App;
+use crate::heart_rate::twitcher::{replay, TwitchSample};
+use crate::utils::centered_rect;
+
+// Up/Down change the threshold by this much
+pub const TWITCH_TUNING_STEP_MS: u16 = 5;
+const TWITCH_TUNING_MAX_MS: u16 = 1000;
+
+/// Candidate threshold being tried against a snapshot of recent updates.
+pub struct TwitchTuning {
+    pub threshold_ms: u16,
+    samples: Vec<TwitchSample>,
+    // (twitch_up, twitch_down) per sample, for the current threshold
+    results: Vec<(bool, bool)>,
+}
+
+impl TwitchTuning {
+    pub fn new(threshold_ms: u16, samples: Vec<TwitchSample>) -> Self {
+        let mut tuning = Self {
+            threshold_ms,
+            samples,
+            results: Vec::new(),
+        };
+        tuning.rerun();
+        tuning
+    }
+    pub fn raise(&mut self) {
+        self.threshold_ms = self
+            .threshold_ms
+            .saturating_add(TWITCH_TUNING_STEP_MS)
+            .min(TWITCH_TUNING_MAX_MS);
+        self.rerun();
+    }
+    pub fn lower(&mut self) {
+        self.threshold_ms = self.threshold_ms.saturating_sub(TWITCH_TUNING_STEP_MS);
+        self.rerun();
+    }
+    fn rerun(&mut self) {
+        let threshold = Duration::from_millis(self.threshold_ms as u64).as_secs_f32();
+        self.results = replay(threshold, &self.samples);
+    }
+    fn counts(&self) -> (usize, usize) {
+        self.results
+            .iter()
+            .fold((0, 0), |(up, down), (twitch_up, twitch_down)| {
+                (up + *twitch_up as usize, down + *twitch_down as usize)
+            })
+    }
+}
+
+pub fn render_twitch_tuning(app: &App, f: &mut Frame) {
+    let Some(tuning) = app.twitch_tuning.as_ref() else {
+        return;
+    };
+    let area = centered_rect(90, 80, f.area());
+    f.render_widget(Clear, area);
+
+    let block = Block::bordered().title("Twitch Threshold Tuning".cyan().bold());
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+    let [info_area, chart_area] =
+        Layout::vertical([Constraint::Length(3), Constraint::Fill(1)]).areas(inner);
+
+    let (up, down) = tuning.counts();
+    let saved = app.settings.osc.twitch_rr_threshold_ms;
+    let info = vec![
+        Line::from(format!(
+            "Threshold: {}ms (saved: {saved}ms) | Over the last {} updates: {up} up, {down} down",
+            tuning.threshold_ms,
+            tuning.samples.len()
+        )),
+        Line::from(vec![
+            "Up".light_green(),
+            " = RR got longer (BPM lowered), ".into(),
+            "Down".light_magenta(),
+            " = RR got shorter (BPM raised)".into(),
+        ]),
+        Line::from("[up/down → ±5ms] [enter → save and apply] [esc → cancel]".dark_gray()),
+    ];
+    f.render_widget(Paragraph::new(info), info_area);
+
+    if tuning.samples.is_empty() {
+        f.render_widget(
+            Paragraph::new("No heart rate data yet, come back once there's been some!"),
+            chart_area,
+        );
+        return;
+    }
+
+    let rr_ms: Vec<(f64, f64)> = tuning
+        .samples
+        .iter()
+        .enumerate()
+        .map(|(i, sample)| (i as f64, sample.latest_rr().as_secs_f64() * 1000.0))
+        .collect();
+    let marked = |pick: fn(&(bool, bool)) -> bool| -> Vec<(f64, f64)> {
+        rr_ms
+            .iter()
+            .zip(&tuning.results)
+            .filter(|(_, result)| pick(result))
+            .map(|(point, _)| *point)
+            .collect()
+    };
+    let ups = marked(|(up, _)| *up);
+    let downs = marked(|(_, down)| *down);
+
+    let (low, high) = rr_ms
+        .iter()
+        .fold((f64::MAX, f64::MIN), |(low, high), (_, rr)| {
+            (low.min(*rr), high.max(*rr))
+        });
+    // Keeps a flat line off the borders
+    let (low, high) = ((low - 20.0).max(0.0), high + 20.0);
+
+    let datasets = vec![
+        Dataset::default()
+            .name("RR (ms)")
+            .graph_type(GraphType::Line)
+            .marker(symbols::Marker::Braille)
+            .style(Style::default().fg(Color::Blue))
+            .data(&rr_ms),
+        Dataset::default()
+            .name("Up")
+            .graph_type(GraphType::Scatter)
+            .marker(symbols::Marker::Dot)
+            .style(Style::default().fg(Color::LightGreen))
+            .data(&ups),
+        Dataset::default()
+            .name("Down")
+            .graph_type(GraphType::Scatter)
+            .marker(symbols::Marker::Dot)
+            .style(Style::default().fg(Color::LightMagenta))
+            .data(&downs),
+    ];
+    let chart = Chart::new(datasets)
+        .x_axis(
+            Axis::default()
+                .style(Style::default().fg(Color::Gray))
+                .bounds([0.0, rr_ms.len().saturating_sub(1) as f64]),
+        )
+        .y_axis(
+            Axis::default()
+                .style(Style::default().fg(Color::Gray))
+                .labels(vec![
+                    Line::from(format!("{low:.0}")),
+                    Line::from(format!("{high:.0}")),
+                ])
+                .bounds([low, high]),
+        );
+    f.render_widget(chart, chart_area);
+}
@@ -0,0 +1,244 @@
+// Pulses a WLED strip on each heartbeat over its realtime UDP protocol (DNRGB)
+// https://kno.wled.ge/interfaces/udp-realtime/
+
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::{Receiver as BReceiver, Sender as BSender};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+use crate::app::{AppUpdate, ErrorPopup};
+use crate::broadcast;
+use crate::errors::AppError;
+use crate::heart_rate::beat_timer::BeatTimer;
+use crate::osc::BeatEnvelope;
+use crate::settings::WledSettings;
+
+const WLED_PORT: u16 = 21324;
+const DNRGB: u8 = 4;
+// Seconds WLED waits after the last packet before going back to its own effect
+const REALTIME_TIMEOUT_SEC: u8 = 2;
+// Most LEDs that fit in one packet, longer strips are split up
+const LEDS_PER_PACKET: usize = 489;
+const FRAME_STEP: Duration = Duration::from_millis(25);
+// Keeps WLED in realtime mode between beats
+const IDLE_REFRESH: Duration = Duration::from_secs(1);
+
+/// "ff8000" or "#ff8000" to RGB.
+fn parse_color(hex: &str) -> Result<[u8; 3], AppError> {
+    let digits = hex.trim().trim_start_matches('#');
+    let invalid = || AppError::WledColor(hex.to_owned());
+    if digits.len() != 6 || !digits.is_ascii() {
+        return Err(invalid());
+    }
+    let mut rgb = [0; 3];
+    for (i, channel) in rgb.iter_mut().enumerate() {
+        *channel = u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(rgb)
+}
+
+struct WledOutput {
+    led_count: usize,
+    // At least one color
+    palette: Vec<[u8; 3]>,
+    idle_brightness: f32,
+    zone: Option<u8>,
+    beats: BeatTimer,
+    envelope: BeatEnvelope,
+}
+
+impl WledOutput {
+    fn build(settings: &WledSettings) -> Result<Self, AppError> {
+        let mut palette = settings
+            .palette
+            .iter()
+            .map(|hex| parse_color(hex))
+            .collect::<Result<Vec<_>, _>>()?;
+        if palette.is_empty() {
+            palette.push([255, 0, 0]);
+        }
+        Ok(Self {
+            led_count: settings.led_count.max(1) as usize,
+            palette,
+            idle_brightness: settings.idle_brightness.clamp(0.0, 1.0),
+            zone: None,
+            beats: BeatTimer::default(),
+            envelope: BeatEnvelope::new(
+                Duration::from_millis(settings.attack_ms as u64),
+                Duration::from_millis(settings.decay_ms as u64),
+            ),
+        })
+    }
+    fn color(&self) -> [u8; 3] {
+        let zone = self.zone.unwrap_or_default() as usize;
+        self.palette[zone.min(self.palette.len() - 1)]
+    }
+    /// Packets to light the whole strip with the zone's color at `envelope_value` (0.0 to 1.0) of the way to full.
+    fn frame(&self, envelope_value: f32) -> Vec<Vec<u8>> {
+        let brightness = self.idle_brightness + (1.0 - self.idle_brightness) * envelope_value;
+        let rgb = self
+            .color()
+            .map(|channel| (channel as f32 * brightness).round() as u8);
+        (0..self.led_count)
+            .step_by(LEDS_PER_PACKET)
+            .map(|start| {
+                let leds = LEDS_PER_PACKET.min(self.led_count - start);
+                let mut packet = Vec::with_capacity(4 + leds * 3);
+                packet.extend([DNRGB, REALTIME_TIMEOUT_SEC]);
+                packet.extend((start as u16).to_be_bytes());
+                for _ in 0..leds {
+                    packet.extend(rgb);
+                }
+                packet
+            })
+            .collect()
+    }
+    async fn rx_loop(
+        &mut self,
+        socket: &UdpSocket,
+        broadcast_rx: &mut BReceiver<AppUpdate>,
+        cancel_token: CancellationToken,
+    ) -> Result<(), AppError> {
+        let mut frame_ticker = tokio::time::interval(FRAME_STEP);
+        let mut idle_ticker = tokio::time::interval(IDLE_REFRESH);
+        let mut send_failing = false;
+        loop {
+            let next_beat = self.beats.next_beat();
+            let pulsing = self.envelope.is_running();
+            let frame = tokio::select! {
+                update = broadcast_rx.recv() => match update {
+                    Ok(AppUpdate::HeartRateStatus(hr_status)) => {
+                        self.beats.update(&hr_status, Instant::now());
+                        continue;
+                    }
+                    Ok(AppUpdate::ZoneChanged(zone)) => {
+                        self.zone = Some(zone);
+                        continue;
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(count)) => {
+                        warn!("WLED: Lagged! Missed {count} messages");
+                        continue;
+                    }
+                    Err(RecvError::Closed) => {
+                        error!("WLED: Channel closed");
+                        return Ok(());
+                    }
+                },
+                _ = tokio::time::sleep_until(next_beat.unwrap_or_else(Instant::now)), if next_beat.is_some() => {
+                    let now = Instant::now();
+                    if self.beats.beat(now) {
+                        self.envelope.start(now);
+                        // Not resetting, so the first frame goes out right away
+                        frame_ticker = tokio::time::interval(FRAME_STEP);
+                    }
+                    continue;
+                }
+                _ = frame_ticker.tick(), if pulsing => {
+                    let Some(value) = self.envelope.next_value(Instant::now()) else {
+                        continue;
+                    };
+                    self.frame(value)
+                }
+                // Once the readings stop, so does this, and WLED goes back to its own effect
+                _ = idle_ticker.tick(), if !pulsing && self.beats.bpm() > 0 => self.frame(0.0),
+                _ = cancel_token.cancelled() => {
+                    info!("Shutting down WLED thread!");
+                    return Ok(());
+                }
+            };
+            for packet in frame {
+                // Just noting it in the .log, the strip may just be unplugged for a bit
+                match socket.send(&packet).await {
+                    Ok(_) if send_failing => {
+                        info!("WLED: Sending again");
+                        send_failing = false;
+                    }
+                    Ok(_) => {}
+                    Err(e) if !send_failing => {
+                        warn!("WLED: Couldn't send: {e}");
+                        send_failing = true;
+                    }
+                    Err(e) => debug!("WLED: Couldn't send: {e}"),
+                }
+            }
+        }
+    }
+}
+
+/// Pulses a WLED strip with each heartbeat, in the current zone's color.
+pub async fn wled_thread(
+    mut broadcast_rx: BReceiver<AppUpdate>,
+    broadcast_tx: BSender<AppUpdate>,
+    wled_settings: WledSettings,
+    cancel_token: CancellationToken,
+) {
+    let result = async {
+        if wled_settings.address.is_empty() {
+            return Err(AppError::WledAddressMissing);
+        }
+        let mut output = WledOutput::build(&wled_settings)?;
+        let socket = UdpSocket::bind(("0.0.0.0", 0)).await?;
+        if wled_settings.address.contains(':') {
+            socket.connect(&wled_settings.address).await?;
+        } else {
+            socket
+                .connect((wled_settings.address.as_str(), WLED_PORT))
+                .await?;
+        }
+        info!("WLED: Sending to {}", socket.peer_addr()?);
+        output
+            .rx_loop(&socket, &mut broadcast_rx, cancel_token)
+            .await
+    }
+    .await;
+    if let Err(e) = result {
+        error!("WLED error: {e}");
+        broadcast!(broadcast_tx, ErrorPopup::detailed("WLED error", e));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(led_count: u16) -> WledOutput {
+        WledOutput::build(&WledSettings {
+            led_count,
+            palette: vec!["#000080".into(), "ff8000".into()],
+            idle_brightness: 0.5,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(parse_color("#FF8000").unwrap(), [255, 128, 0]);
+        assert!(matches!(parse_color("ff80"), Err(AppError::WledColor(_))));
+        assert!(matches!(parse_color("gg8000"), Err(AppError::WledColor(_))));
+    }
+    #[test]
+    fn frames_follow_zone_and_envelope() {
+        let mut wled = output(3);
+        assert_eq!(
+            wled.frame(0.0),
+            vec![vec![4, 2, 0, 0, 0, 0, 64, 0, 0, 64, 0, 0, 64]]
+        );
+        // Past the end of the palette
+        wled.zone = Some(4);
+        assert_eq!(&wled.frame(1.0)[0][4..7], &[255, 128, 0]);
+    }
+    #[test]
+    fn long_strips_are_split() {
+        let packets = output(1000).frame(1.0);
+        assert_eq!(packets.len(), 3);
+        assert_eq!(packets[0].len(), 4 + 489 * 3);
+        // Starting LED, big endian
+        assert_eq!(&packets[1][2..4], &[1, 233]);
+        assert_eq!(packets[2].len(), 4 + 22 * 3);
+    }
+}
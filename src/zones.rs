@@ -0,0 +1,168 @@
+use ratatui::style::Color;
+use std::time::{Duration, Instant};
+
+use crate::settings::ZoneSettings;
+
+// Zone 0 (below the first boundary) and up
+const ZONE_COLORS: [Color; 6] = [
+    Color::Gray,
+    Color::Blue,
+    Color::Green,
+    Color::Yellow,
+    Color::LightRed,
+    Color::Red,
+];
+
+/// Works out which heart rate zone we're in from `zones.boundaries`.
+///
+/// To keep the zone from flapping while hovering around a boundary,
+/// it only changes once the BPM is `hysteresis_bpm` past it,
+/// or has stayed past it for `hysteresis_hold_sec`.
+#[derive(Debug)]
+pub struct ZoneEngine {
+    // Lower bound of zones 1 and up, sorted
+    boundaries: Vec<u16>,
+    hysteresis_bpm: u16,
+    hold: Duration,
+    current: Option<u8>,
+    // Zone we've been hovering in without crossing by the margin, and since when
+    pending: Option<(u8, Instant)>,
+}
+
+impl ZoneEngine {
+    pub fn new(zone_settings: &ZoneSettings) -> Self {
+        let mut boundaries = zone_settings.boundaries.clone();
+        boundaries.sort_unstable();
+        boundaries.dedup();
+        Self {
+            boundaries,
+            hysteresis_bpm: zone_settings.hysteresis_bpm,
+            hold: Duration::from_secs_f32(zone_settings.hysteresis_hold_sec.max(0.0)),
+            current: None,
+            pending: None,
+        }
+    }
+    /// The zone for a BPM, ignoring hysteresis.
+    fn zone_for(&self, bpm: u16) -> u8 {
+        self.boundaries
+            .iter()
+            .take_while(|&&boundary| bpm >= boundary)
+            .count() as u8
+    }
+    pub fn current(&self) -> Option<u8> {
+        self.current
+    }
+    /// Returns the new zone if it changed.
+    ///
+    /// 0 BPM (disconnected) is ignored, the last zone is kept.
+    pub fn update(&mut self, bpm: u16, now: Instant) -> Option<u8> {
+        if bpm == 0 {
+            return None;
+        }
+        let raw_zone = self.zone_for(bpm);
+        let Some(current) = self.current else {
+            self.current = Some(raw_zone);
+            return self.current;
+        };
+        if raw_zone == current {
+            self.pending = None;
+            return None;
+        }
+        // Crossed by more than the margin?
+        let with_margin = if raw_zone > current {
+            self.zone_for(bpm.saturating_sub(self.hysteresis_bpm))
+        } else {
+            self.zone_for(bpm.saturating_add(self.hysteresis_bpm))
+        };
+        if with_margin != current {
+            return self.change_to(with_margin);
+        }
+        // Otherwise we need to stay past the boundary for a bit
+        match self.pending {
+            Some((zone, since)) if zone == raw_zone => {
+                if now.saturating_duration_since(since) >= self.hold {
+                    return self.change_to(raw_zone);
+                }
+            }
+            _ => self.pending = Some((raw_zone, now)),
+        }
+        None
+    }
+    fn change_to(&mut self, zone: u8) -> Option<u8> {
+        self.pending = None;
+        self.current = Some(zone);
+        self.current
+    }
+}
+
+pub fn zone_color(zone: u8) -> Color {
+    ZONE_COLORS[(zone as usize).min(ZONE_COLORS.len() - 1)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEC: Duration = Duration::from_secs(1);
+
+    fn engine() -> ZoneEngine {
+        ZoneEngine::new(&ZoneSettings {
+            enabled: true,
+            boundaries: vec![140, 100, 120],
+            hysteresis_bpm: 3,
+            hysteresis_hold_sec: 5.0,
+        })
+    }
+
+    #[test]
+    fn raw_zones() {
+        let engine = engine();
+        assert_eq!(engine.zone_for(60), 0);
+        assert_eq!(engine.zone_for(100), 1);
+        assert_eq!(engine.zone_for(139), 2);
+        assert_eq!(engine.zone_for(200), 3);
+    }
+    #[test]
+    fn first_reading_sets_zone() {
+        let mut engine = engine();
+        let now = Instant::now();
+        assert_eq!(engine.update(0, now), None);
+        assert_eq!(engine.update(101, now), Some(1));
+        assert_eq!(engine.update(105, now), None);
+    }
+    #[test]
+    fn hovering_doesnt_flap() {
+        let mut engine = engine();
+        let start = Instant::now();
+        engine.update(118, start);
+        for i in 0..4 {
+            let bpm = if i % 2 == 0 { 121 } else { 119 };
+            assert_eq!(engine.update(bpm, start + SEC * i), None);
+        }
+        assert_eq!(engine.current(), Some(1));
+    }
+    #[test]
+    fn crossing_by_margin() {
+        let mut engine = engine();
+        let start = Instant::now();
+        engine.update(115, start);
+        assert_eq!(engine.update(123, start), Some(2));
+        // Dropping back under needs the margin too
+        assert_eq!(engine.update(118, start), None);
+        assert_eq!(engine.update(116, start), Some(1));
+    }
+    #[test]
+    fn holding_past_boundary() {
+        let mut engine = engine();
+        let start = Instant::now();
+        engine.update(115, start);
+        assert_eq!(engine.update(121, start), None);
+        assert_eq!(engine.update(121, start + SEC * 4), None);
+        assert_eq!(engine.update(122, start + SEC * 5), Some(2));
+    }
+    #[test]
+    fn colors_saturate() {
+        assert_eq!(zone_color(0), Color::Gray);
+        assert_eq!(zone_color(200), Color::Red);
+    }
+}
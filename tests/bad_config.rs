@@ -18,6 +18,8 @@ async fn misspelled_bool() {
         no_save: true,
         subcommands: None,
         skip_prompts: true,
+        dummy: false,
+        stdin: false,
     };
 
     iron_heart::run_headless(arg_config, parent_token)
@@ -39,6 +41,8 @@ async fn missing_end_quote() {
         no_save: true,
         subcommands: None,
         skip_prompts: true,
+        dummy: false,
+        stdin: false,
     };
 
     iron_heart::run_headless(arg_config, parent_token)